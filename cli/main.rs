@@ -1,16 +1,50 @@
 use clap::{Parser, Subcommand};
 use lao_orchestrator_core::{
+    ab::{self, AbSpec},
+    api, artifacts,
+    audit::{self, AuditKind, AuditQuery},
+    breakpoints::{self, Breakpoints, PauseHandler, PausedStep, Resume},
+    checkpoint, crash,
     cross_platform::PathUtils,
-    load_workflow_yaml,
+    daemon, data_dir, encryption,
+    env_vars::{self, TemplateEnv},
+    evaluation, exec_plugin, exporter, extends,
+    golden::{self, CaseOutcome},
+    importer::{self, ImportFormat},
+    input_form, load_workflow_yaml,
+    logging::{self, LogLevel, StderrSink},
+    memo, metadata, metrics, mock, mqtt, ollama,
+    orchestrator::Orchestrator,
+    params,
+    pipeline::{self, BatchReport, BatchSpec, Resource},
+    plan::ExecutionPlan,
     plugin_dev_tools::{PluginDevTools, PluginTemplate},
+    plugin_fuzz,
     plugin_manager::PluginManager,
-    plugins::PluginRegistry,
-    run_workflow_yaml,
-    scheduler::WorkflowScheduler,
-    workflow_state::WorkflowSchedule,
+    plugin_policy::{self, PluginPolicy},
+    plugins::{self, PluginRegistry},
+    presets,
+    prompts::{self, Version},
+    redaction::{self, RedactionConfig},
+    replay::Replay,
+    rpc,
+    run_class::{self, RunClass},
+    run_diff::{self, DiffLine, StepChange},
+    run_labels::{self, RunLabels, RunQuery},
+    run_logs::{RunLogConfig, RunLogSink},
+    run_workflow_with_registry,
+    scheduler,
+    secrets::{self, SecretStore},
+    service,
+    state_manager::WorkflowStateManager,
+    triggers::{self, Trigger, TriggerOn, TriggerStore},
+    usage, webhook,
+    workflow_state::WorkflowStatus,
+    workflow_validation::{Severity, ValidationService},
 };
-use lao_plugin_api::PluginInput;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Deserialize)]
 struct PromptPair {
@@ -35,6 +69,13 @@ fn strip_code_fences(s: &str) -> String {
 #[command(name = "lao")]
 #[command(about = "Local AI Orchestrator CLI", long_about = None)]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Keep runs, caches, logs and secrets here instead of the user's data directory (or set LAO_DATA_DIR)"
+    )]
+    data_dir: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -46,9 +87,54 @@ enum Commands {
         path: String,
         #[arg(long)]
         dry_run: bool,
+        #[arg(long, help = "Run in this process even if a daemon is running")]
+        local: bool,
+        #[arg(long, help = "Yield to interactive runs between steps")]
+        background: bool,
+        #[arg(
+            long,
+            value_name = "FIXTURES",
+            help = "Replace every plugin with a mock answering from this fixtures file"
+        )]
+        mock: Option<String>,
+        #[arg(
+            long = "label",
+            value_name = "KEY=VALUE",
+            help = "Label the run for `lao history`, e.g. project=thesis (repeatable)"
+        )]
+        labels: Vec<String>,
+        #[arg(
+            long = "break",
+            value_name = "STEP",
+            help = "Pause before this step to check or replace its input, e.g. step3 (repeatable)"
+        )]
+        breaks: Vec<String>,
+        #[arg(
+            long = "param",
+            value_name = "NAME=VALUE",
+            help = "Run input, ${NAME} in steps and ${{ params.NAME }} anywhere in the workflow (repeatable)"
+        )]
+        params: Vec<String>,
     },
     /// Validate a workflow YAML file (type & plugin availability)
-    Validate { path: String },
+    Validate {
+        path: String,
+        #[arg(long, help = "Re-validate whenever the file changes")]
+        watch: bool,
+        #[arg(long, help = "Report unknown fields as warnings instead of errors")]
+        lenient: bool,
+    },
+    /// Print a workflow with the workflows it extends merged in
+    Resolve { path: String },
+    /// Run golden-file workflow tests against mock plugins
+    Test {
+        /// Directory of test workflows
+        #[arg(default_value = "tests/workflows")]
+        dir: String,
+        /// Write the actual results as the new expected files
+        #[arg(long)]
+        update: bool,
+    },
     /// List available plugins
     PluginList,
     /// Scaffold a new workflow YAML template
@@ -82,25 +168,34 @@ enum Commands {
     ListWorkflows,
     /// View a workflow YAML file by name (from workflows/ directory)
     ViewWorkflow { name: String },
+    /// Describe a workflow: its metadata, the plugins it needs and its steps
+    Show { path: String },
     /// Delete a workflow YAML file by name (from workflows/ directory)
     DeleteWorkflow { name: String },
     /// Explain a plugin's capabilities, schemas, and usage examples
     ExplainPlugin { name: String },
-    /// Schedule a workflow to run at specified intervals
+    /// Run a workflow on a schedule from the daemon
+    #[command(args_conflicts_with_subcommands = true)]
     Schedule {
-        workflow_path: String,
-        #[arg(
-            long,
-            help = "Cron-like expression (e.g., 'interval:60' for every 60 minutes)"
-        )]
-        cron: String,
+        #[command(subcommand)]
+        action: Option<ScheduleCommands>,
+        workflow_path: Option<String>,
+        #[arg(long, help = "Same as `schedule add <workflow> <cron>`")]
+        cron: Option<String>,
         #[arg(long, help = "Maximum number of times to run (optional)")]
         max_runs: Option<u32>,
     },
-    /// Unschedule a workflow
+    /// Remove a schedule or watch (same as `schedule rm`)
     Unschedule { workflow_id: String },
-    /// List all scheduled workflows
+    /// List the schedules (same as `schedule list`)
     ListScheduled,
+    /// Run a workflow from the daemon for each new file in a directory
+    Watch {
+        #[command(subcommand)]
+        action: WatchCommands,
+    },
+    /// Check the Ollama server that LLM plugins use
+    OllamaStatus,
     /// Show workflow execution history and state
     Status {
         #[arg(help = "Workflow ID (optional - shows all if not specified)")]
@@ -116,15 +211,475 @@ enum Commands {
         max_age_hours: u64,
     },
     /// Run the workflow scheduler daemon
+    #[command(args_conflicts_with_subcommands = true)]
     Daemon {
-        #[arg(long, default_value = "60", help = "Check interval in seconds")]
+        #[command(subcommand)]
+        action: Option<DaemonCommands>,
+        #[arg(
+            long,
+            default_value = "10",
+            help = "Seconds between checks of schedules and watched directories"
+        )]
         interval: u64,
+        #[arg(
+            long,
+            help = "Serve Prometheus metrics at /metrics on this address (e.g. 127.0.0.1:9464)"
+        )]
+        metrics_addr: Option<String>,
+        #[arg(
+            long,
+            help = "Trigger workflows with POST /hooks/<name> on this address (e.g. 127.0.0.1:8787)"
+        )]
+        webhook_addr: Option<String>,
+        #[arg(
+            long,
+            help = "Run workflows with an `mqtt:` topic on messages from this broker (e.g. 127.0.0.1:1883)"
+        )]
+        mqtt_broker: Option<String>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Run workflows on global hotkeys from this file (default hotkeys.yaml in the data directory, or LAO_HOTKEYS; needs --features hotkeys)"
+        )]
+        hotkeys: Option<Option<String>>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Show a tray icon with the favorite workflows from this file (default tray.yaml in the data directory, or LAO_TRAY; needs --features tray)"
+        )]
+        tray: Option<Option<String>>,
+        #[arg(
+            long,
+            default_value = "workflows",
+            help = "Directory webhook and MQTT workflows are resolved in"
+        )]
+        workflows_dir: String,
+        #[arg(
+            long,
+            default_value = "127.0.0.1:0",
+            help = "Address of the local API `lao run` delegates to (port 0 picks a free one)"
+        )]
+        api_addr: String,
+    },
+    /// Serve an HTTP API to list plugins, manage workflows, start runs and follow them
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8740", help = "Address to listen on")]
+        addr: String,
+        #[arg(
+            long,
+            default_value = "workflows",
+            help = "Directory the API's workflows are read from and saved to"
+        )]
+        workflows_dir: String,
+        #[arg(
+            long,
+            help = "Token clients must send (default LAO_SERVE_TOKEN, else a random one is printed)"
+        )]
+        token: Option<String>,
+    },
+    /// Answer JSON-RPC requests from an editor on stdin and stdout
+    Rpc,
+    /// Show the audit log of plugin installs/loads, permission changes, secret accesses and external steps
+    Audit {
+        #[arg(
+            long,
+            help = "Only this event kind (plugin_install, plugin_uninstall, plugin_load, permission_grant, permission_revoke, secret_access, external_step)"
+        )]
+        kind: Option<String>,
+        #[arg(
+            long,
+            help = "Only events whose subject (plugin or secret) contains this"
+        )]
+        subject: Option<String>,
+        #[arg(long, help = "Only events newer than this, e.g. 30m, 24h, 7d")]
+        since: Option<String>,
+        #[arg(
+            long,
+            default_value = "50",
+            help = "Show at most this many (newest) events"
+        )]
+        limit: usize,
+        #[arg(long, help = "Print entries as JSON lines")]
+        json: bool,
+        #[arg(long, help = "Check the hash chain instead of listing events")]
+        verify: bool,
+    },
+    /// Run a single plugin once and print its output (input from --input or stdin)
+    Exec {
+        #[arg(required_unless_present = "preset")]
+        plugin: Option<String>,
+        #[arg(
+            long,
+            help = "Plugin input (default: read from stdin, or asked for field by field at a terminal)"
+        )]
+        input: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "plugin",
+            help = "Run a saved node preset: its plugin, with the input in its template"
+        )]
+        preset: Option<String>,
+    },
+    /// Export a workflow in another form
+    Export {
+        path: String,
+        #[arg(
+            long,
+            default_value = "script",
+            help = "Export format: script (POSIX shell script of `lao exec` calls)"
+        )]
+        format: String,
+        #[arg(long, help = "Output file path (default: stdout)")]
+        output: Option<String>,
+    },
+    /// Convert an n8n workflow or LangChain chain export (JSON) into a LAO workflow
+    Import {
+        path: String,
+        #[arg(long, help = "Output file path (default: workflows/<input name>.yaml)")]
+        output: Option<String>,
+        #[arg(long, help = "Source format: n8n or langchain (default: detect)")]
+        format: Option<String>,
+    },
+    /// List past runs, newest first, by workflow, status, label or tag
+    History {
+        #[arg(long, help = "Only runs of this workflow")]
+        workflow: Option<String>,
+        #[arg(long, help = "Only runs with this status: success, error or cancelled")]
+        status: Option<String>,
+        #[arg(
+            long = "label",
+            value_name = "KEY=VALUE",
+            help = "Only runs with this label (repeatable)"
+        )]
+        labels: Vec<String>,
+        #[arg(
+            long = "tag",
+            help = "Only runs with this tag, e.g. schedule, webhook or background (repeatable)"
+        )]
+        tags: Vec<String>,
+        #[arg(
+            long,
+            help = "Only runs whose id, workflow, labels or tags contain this"
+        )]
+        search: Option<String>,
+        #[arg(long, help = "Only runs newer than this, e.g. 30m, 24h, 7d")]
+        since: Option<String>,
+        #[arg(
+            long,
+            default_value = "20",
+            help = "Show at most this many (newest) runs"
+        )]
+        limit: usize,
+        #[arg(long, help = "Print runs as JSON lines")]
+        json: bool,
+    },
+    /// Compare two runs: steps added or removed, changed outputs and durations
+    DiffRuns {
+        /// Earlier run id
+        a: String,
+        /// Later run id
+        b: String,
+        #[arg(long, help = "Print the diff as JSON")]
+        json: bool,
+    },
+    /// Go through a recorded run step by step, or re-execute one of its steps
+    Replay {
+        /// Run id, as listed by `lao history`
+        run_id: String,
+        #[arg(long, help = "Re-execute this step and compare its output, e.g. step3")]
+        step: Option<String>,
+        #[arg(
+            long,
+            requires = "step",
+            help = "New input for the step; ${stepN} is the recorded output of stepN"
+        )]
+        input: Option<String>,
+        #[arg(
+            long,
+            value_name = "FILE",
+            requires = "step",
+            conflicts_with = "input",
+            help = "Read the new input from a file"
+        )]
+        input_file: Option<String>,
+        #[arg(
+            long,
+            requires = "step",
+            conflicts_with_all = ["input", "input_file"],
+            help = "Edit the recorded input before re-executing"
+        )]
+        edit: bool,
+        #[arg(long, help = "Print the steps or the result as JSON")]
+        json: bool,
+    },
+    /// Run a failed, cancelled or interrupted run again, reusing the
+    /// outputs of the steps that succeeded
+    Resume {
+        /// Run id, as listed by `lao history`
+        run_id: String,
+    },
+    /// Show token counts and estimated compute time and energy per workflow and plugin
+    Usage {
+        #[arg(
+            long,
+            default_value = "7d",
+            help = "Only runs newer than this, e.g. 24h, 7d, 4w"
+        )]
+        since: String,
+        #[arg(long, help = "Print the summary as JSON")]
+        json: bool,
     },
     /// Plugin management commands
     Plugin {
         #[command(subcommand)]
         command: PluginCommands,
     },
+    /// Inspect crash reports written to the crash directory
+    Report {
+        #[command(subcommand)]
+        command: ReportCommands,
+    },
+    /// Manage at-rest encryption of run logs, states, caches and artifacts
+    Encryption {
+        #[command(subcommand)]
+        command: EncryptionCommands,
+    },
+    /// Manage the encrypted secrets steps read as ${{ secrets.NAME }}
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommands,
+    },
+    /// Inspect and clear step outputs memoized across workflows
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Inspect judge scores of steps with an `evaluate:` block
+    Eval {
+        #[command(subcommand)]
+        command: EvalCommands,
+    },
+    /// Run a workflow once per input, overlapping CPU and GPU steps of
+    /// different inputs
+    Batch {
+        path: String,
+        /// Inputs, one run each, e.g. recordings/*.wav
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Run input each item is given as, ${file} by default
+        #[arg(long, default_value = "file")]
+        input_name: String,
+        /// Runs in flight at once (default: one per lane)
+        #[arg(long, default_value_t = 0)]
+        depth: usize,
+        #[arg(
+            long,
+            value_name = "FIXTURES",
+            help = "Replace every plugin with a mock answering from this fixtures file"
+        )]
+        mock: Option<String>,
+        #[arg(long, help = "Print the report, lane spans included, as JSON")]
+        json: bool,
+    },
+    /// Compare prompts for one step: run the workflow with each and compare
+    /// judge scores and durations
+    Ab {
+        path: String,
+        /// Step id (step2) or display label whose prompt varies
+        #[arg(long)]
+        step: String,
+        /// Prompt files (prompts/a.md, prompts/a.md@v2) or inline prompts
+        #[arg(long, num_args = 2.., required = true)]
+        variants: Vec<String>,
+        /// Runs per variant
+        #[arg(short = 'n', long, default_value_t = 3)]
+        runs: usize,
+        #[arg(long, help = "Judge plugin, instead of the step's evaluate: judge")]
+        judge: Option<String>,
+        #[arg(
+            long,
+            value_name = "FIXTURES",
+            help = "Replace every plugin with a mock answering from this fixtures file"
+        )]
+        mock: Option<String>,
+        #[arg(long, help = "Print the comparison as JSON")]
+        json: bool,
+    },
+    /// List and show saved node presets (presets/, or LAO_PRESET_DIR)
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommands,
+    },
+    /// List, edit and compare versioned prompt files (prompts/, or LAO_PROMPT_DIR)
+    Prompts {
+        #[command(subcommand)]
+        command: PromptCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum PromptCommands {
+    /// List the prompt files and their saved versions
+    List,
+    /// Edit a prompt in $EDITOR and save the result as its next version
+    Edit { name: String },
+    /// Line diff between two versions of a prompt
+    Diff {
+        name: String,
+        /// Version to compare from: v1, latest or working
+        #[arg(default_value = "latest")]
+        from: String,
+        /// Version to compare to
+        #[arg(default_value = "working")]
+        to: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PresetCommands {
+    /// List the saved presets
+    List,
+    /// Print a preset as YAML
+    Show { name: String },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Show memoized entries and their size per plugin
+    Stats {
+        #[arg(long, help = "Print the stats as JSON")]
+        json: bool,
+    },
+    /// List memoized entries, newest first
+    List {
+        /// Only this plugin's entries
+        #[arg(long)]
+        plugin: Option<String>,
+        #[arg(long, default_value_t = 50, help = "Show at most this many entries")]
+        limit: usize,
+        #[arg(long, help = "Print the entries as JSON")]
+        json: bool,
+    },
+    /// Print a memoized output
+    Show {
+        /// Entry key, or the start of one
+        key: String,
+    },
+    /// Remove old memoized outputs, or the oldest ones past a size limit
+    Prune {
+        #[arg(long, help = "Remove entries older than this, e.g. 30d, 12w")]
+        older_than: Option<String>,
+        #[arg(
+            long,
+            value_name = "MB",
+            help = "Then remove the oldest until the rest take at most this much"
+        )]
+        max_size: Option<f64>,
+    },
+    /// Remove memoized outputs
+    Clear {
+        /// Only remove this plugin's outputs
+        #[arg(long)]
+        plugin: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum EvalCommands {
+    /// Rank the models and prompts of evaluated steps by mean score
+    Rank {
+        /// Only this workflow's steps
+        #[arg(long)]
+        workflow: Option<String>,
+        /// Only steps with this id
+        #[arg(long)]
+        step: Option<String>,
+        #[arg(long, help = "Print the ranking as JSON")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommands {
+    /// Register the daemon as a user service that starts at login
+    Install {
+        /// Arguments for `lao daemon`, after `--` (e.g. -- --webhook-addr 127.0.0.1:8787)
+        #[arg(last = true)]
+        args: Vec<String>,
+        #[arg(long, help = "Register it without starting it now")]
+        no_start: bool,
+        #[arg(long, help = "Print the service file and commands instead")]
+        dry_run: bool,
+    },
+    /// Stop the daemon service and unregister it
+    Uninstall,
+    /// Show whether the daemon service is running
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommands {
+    /// Run a workflow on a schedule, e.g. "0 9 * * *" for 9:00 every day
+    Add {
+        workflow: String,
+        #[arg(
+            help = "Cron expression (minute hour day month weekday), @hourly, @daily, @weekly or interval:MINUTES"
+        )]
+        schedule: String,
+        #[arg(long, help = "Stop after this many runs")]
+        max_runs: Option<u32>,
+    },
+    /// List the schedules with their next run
+    List,
+    /// Remove a schedule
+    Rm { id: String },
+}
+
+#[derive(Subcommand)]
+enum WatchCommands {
+    /// Run a workflow with each new file in a directory as ${file}
+    Add {
+        workflow: String,
+        dir: String,
+        #[arg(long, help = "Only files whose name matches this glob, e.g. \"*.wav\"")]
+        pattern: Option<String>,
+    },
+    /// List the watches
+    List,
+    /// Remove a watch
+    Rm { id: String },
+}
+
+#[derive(Subcommand)]
+enum EncryptionCommands {
+    /// Write a new random key; point LAO_ENCRYPTION_KEYFILE at it
+    Keygen { path: String },
+}
+
+#[derive(Subcommand)]
+enum SecretCommands {
+    /// Store a secret, replacing its old value
+    Set {
+        name: String,
+        #[arg(
+            help = "The value; read from stdin when left out, which keeps it out of shell history"
+        )]
+        value: Option<String>,
+    },
+    /// List the stored secrets' names
+    List,
+    /// Remove a secret
+    Rm { name: String },
+}
+
+#[derive(Subcommand)]
+enum ReportCommands {
+    /// Print the most recent crash report
+    LastCrash {
+        /// Write the report and its run's logs, redacted, to this .tar.gz instead
+        #[arg(long)]
+        bundle: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -225,6 +780,20 @@ enum PluginCommands {
         #[arg(long)]
         output: Option<String>,
     },
+    /// Fuzz a plugin library at the C ABI and print a conformance report
+    Fuzz {
+        /// Path to the plugin's shared library
+        library: String,
+        /// Seconds each case may run before it counts as hung
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run one fuzz case in isolation (started by `plugin fuzz`)
+    #[command(hide = true)]
+    FuzzProbe,
     /// Refresh marketplace cache
     RefreshMarketplace,
     /// Register event hooks for plugins
@@ -242,40 +811,216 @@ enum PluginCommands {
 
 fn main() {
     let cli = Cli::parse();
+    // Probe processes of `lao plugin fuzz` run one case and exit before any setup
+    if let Commands::Plugin {
+        command: PluginCommands::FuzzProbe,
+    } = cli.command
+    {
+        let code = if plugin_fuzz::probe_from_env().is_some() {
+            0
+        } else {
+            2
+        };
+        std::process::exit(code);
+    }
+    // Runs, caches, logs and secrets are kept apart for each OS user
+    if let Some(dir) = &cli.data_dir {
+        data_dir::init(dir);
+    }
+    if let Err(e) = data_dir::prepare() {
+        eprintln!("[ERROR] Failed to set up the data directory: {}", e);
+        std::process::exit(1);
+    }
+    // Panics (and native crashes with LAO_NATIVE_CRASH=1) leave a report under crashes/
+    crash::install(crash::default_dir());
+    // Secrets and personal data are redacted from every log event (see redaction.yaml)
+    let redaction_path = redaction::default_path();
+    if let Err(e) = RedactionConfig::load(&redaction_path).and_then(redaction::init) {
+        eprintln!("[ERROR] Failed to load redaction rules: {}", e);
+        std::process::exit(1);
+    }
+    // Engine log events go to stderr so stdout stays clean for results; LAO_LOG sets the level
+    logging::add_sink(StderrSink {
+        level: LogLevel::from_env(),
+    });
+    // Every run also keeps its own JSONL log under runs/<id>/ for later inspection
+    logging::add_sink(RunLogSink::new(RunLogConfig::from_env()));
+    // Plugin loads, permission changes and external steps go to the append-only audit log
+    audit::init(audit::default_path());
+    usage::init(usage::default_path());
+    // Scores of steps with an `evaluate:` judge, for `lao eval rank`
+    evaluation::init(evaluation::default_path());
+    // Binary step outputs are kept, with lineage, in the artifact store
+    artifacts::init(artifacts::default_dir());
+    // Step results of each run are kept next to its log for `lao diff-runs`
+    run_diff::init(run_diff::default_dir());
+    // and a checkpoint of each step as it finishes, for `lao resume`
+    checkpoint::init(run_diff::default_dir());
+    // Which plugins workflows may call, and how far each plugin is trusted
+    if let Err(e) = PluginPolicy::load(&plugin_policy::default_path()).and_then(plugin_policy::init)
+    {
+        eprintln!("[ERROR] Failed to load plugin policy: {}", e);
+        std::process::exit(1);
+    }
+    // The project's .env and the user's secrets.env feed ${{ env.NAME }} in
+    // workflows, limited by LAO_ENV_ALLOW
+    let dotenv = [env_vars::default_path(), env_vars::user_path()];
+    if let Err(e) = TemplateEnv::load_all(&dotenv).and_then(env_vars::init) {
+        eprintln!("[ERROR] Failed to load .env: {}", e);
+        std::process::exit(1);
+    }
+    // Interactive runs publish a lease so a daemon's background runs yield to them
+    run_class::init(run_class::default_lease_dir());
+    // LAO_ENCRYPTION_KEYFILE / LAO_ENCRYPTION_PASSPHRASE encrypt run data at rest
+    if let Err(e) = encryption::init_from_env() {
+        eprintln!("[ERROR] Failed to set up encryption: {}", e);
+        std::process::exit(1);
+    }
     match cli.command {
-        Commands::Run { path, dry_run } => {
+        Commands::Run {
+            path,
+            dry_run,
+            local,
+            background,
+            mock,
+            labels,
+            breaks,
+            params,
+        } => {
+            let inputs = match parse_params(&params) {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let labels = match parse_labels(&labels) {
+                Ok(labels) => RunLabels {
+                    labels: labels.into_iter().collect(),
+                    ..Default::default()
+                }
+                .with_tag("cli"),
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            // Mocked runs never touch real plugins, so they also skip the daemon
+            let mock_registry = match mock.as_deref().map(|fixtures| {
+                let fixtures = mock::load_fixtures(std::path::Path::new(fixtures))?;
+                load_workflow_yaml(&path).map(|w| Arc::new(mock::mock_registry(&w, &fixtures)))
+            }) {
+                Some(Err(e)) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+                Some(Ok(registry)) => Some(registry),
+                None => None,
+            };
+            let registry = mock_registry
+                .clone()
+                .unwrap_or_else(|| plugins::shared().snapshot());
             if dry_run {
-                match load_workflow_yaml(&path) {
-                    Ok(workflow) => {
-                        let plugin_dir = PathUtils::plugin_dir();
-                        let plugin_registry = PluginRegistry::dynamic_registry(
-                            plugin_dir.to_str().unwrap_or("plugins"),
-                        );
-                        println!("[DRY RUN] Workflow: {}", workflow.workflow);
-                        for (i, step) in workflow.steps.iter().enumerate() {
-                            let plugin = plugin_registry.plugins.get(&step.run);
-                            println!("Step {}: {}", i + 1, step.run);
-                            match plugin {
-                                Some(_p) => {
-                                    println!("  [OK] Plugin '{}' loaded.", step.run);
-                                }
-                                None => {
-                                    println!("  [ERROR] Plugin '{}' not found!", step.run);
-                                }
-                            }
+                let plan =
+                    match params::with_values(&inputs, || ExecutionPlan::load(&path, &registry)) {
+                        Ok(plan) => plan,
+                        Err(e) => {
+                            eprintln!("[DRY RUN] Failed to load workflow: {}", e);
+                            std::process::exit(1);
                         }
+                    };
+                println!("[DRY RUN] Workflow: {}", plan.workflow.workflow);
+                for step in plan.in_order() {
+                    let cached = if step.cache_hit { " (cached)" } else { "" };
+                    println!(
+                        "Step {} [group {}]: {}{}",
+                        step.index + 1,
+                        step.group + 1,
+                        step.step.run,
+                        cached
+                    );
+                    if let Some(mode) = step.step.disabled_mode() {
+                        println!("  [SKIP] Disabled ({}).", mode.as_str());
+                        continue;
                     }
-                    Err(e) => {
-                        eprintln!("[DRY RUN] Failed to load workflow: {}", e);
-                        std::process::exit(1);
+                    match &step.io_types {
+                        Some((input, output)) => println!(
+                            "  [OK] Plugin '{}' loaded ({:?} -> {:?}).",
+                            step.step.run, input, output
+                        ),
+                        None => println!("  [ERROR] Plugin '{}' not found!", step.step.run),
                     }
                 }
+                for warning in &plan.warnings {
+                    println!("[WARN] {}", warning);
+                }
+                for error in &plan.errors {
+                    println!("[ERROR] {}", error);
+                }
+                if !plan.is_valid() {
+                    std::process::exit(1);
+                }
             } else {
-                match run_workflow_yaml(&path) {
+                let class = if background {
+                    RunClass::Background
+                } else {
+                    RunClass::Interactive
+                };
+                // The daemon can't ask at a breakpoint, so paused runs stay here
+                let pauses = !breaks.is_empty()
+                    || load_workflow_yaml(&path)
+                        .is_ok_and(|w| w.steps.iter().any(|s| s.breakpoint == Some(true)));
+                let breakpoints = breaks
+                    .into_iter()
+                    .fold(Breakpoints::new(TerminalPause), Breakpoints::at);
+                let result = run_class::with_class(class, || {
+                    run_labels::with_labels(labels, || {
+                        // A running daemon already has plugins loaded and caches warm
+                        let delegated = if local || pauses || mock_registry.is_some() {
+                            None
+                        } else {
+                            daemon::try_run(&daemon::default_path(), &path, inputs.clone())
+                        };
+                        if delegated.is_some() {
+                            eprintln!("Ran on the LAO daemon (use --local to run in this process)");
+                        }
+                        if mock_registry.is_some() {
+                            eprintln!("Running with mock plugins");
+                        }
+                        delegated.unwrap_or_else(|| {
+                            breakpoints::with_breakpoints(breakpoints, || {
+                                run_workflow_with_registry(&path, inputs, &registry)
+                            })
+                        })
+                    })
+                });
+                match result {
                     Ok(results) => {
                         println!("Workflow executed successfully. Step outputs:");
                         for (i, output) in results.iter().enumerate() {
-                            println!("Step {}: {:?}", i + 1, output);
+                            match output.validation.as_deref() {
+                                Some("skipped") => println!(
+                                    "Step {}: {} {}",
+                                    i + 1,
+                                    output.step_id,
+                                    output.output.as_deref().unwrap_or("skipped")
+                                ),
+                                _ => println!(
+                                    "Step {}: {}",
+                                    i + 1,
+                                    secrets::hide(&format!("{:?}", output))
+                                ),
+                            }
+                        }
+                        let shared = results
+                            .iter()
+                            .filter(|log| log.validation.as_deref() == Some("dedup"))
+                            .count();
+                        if shared > 0 {
+                            println!(
+                                "{} duplicate step(s) reused the output of an identical call",
+                                shared
+                            );
                         }
                     }
                     Err(e) => {
@@ -285,33 +1030,161 @@ fn main() {
                 }
             }
         }
-        Commands::Validate { path } => match load_workflow_yaml(&path) {
-            Ok(workflow) => {
-                let plugin_dir = PathUtils::plugin_dir();
-                let plugin_registry =
-                    PluginRegistry::dynamic_registry(plugin_dir.to_str().unwrap_or("plugins"));
-                let dag = match lao_orchestrator_core::build_dag(&workflow.steps) {
-                    Ok(d) => d,
-                    Err(e) => {
-                        eprintln!("[ERROR] Failed to build DAG: {}", e);
-                        std::process::exit(1);
-                    }
-                };
-                let errors = lao_orchestrator_core::validate_workflow_types(&dag, &plugin_registry);
-                if errors.is_empty() {
-                    println!("Validation passed: all steps and plugins available.");
-                } else {
-                    for (step, msg) in errors {
-                        println!("Step {}: {}", step, msg);
-                    }
+        Commands::Resume { run_id } => {
+            let dir = run_diff::default_dir();
+            let checkpoint = match checkpoint::load(&dir, &run_id) {
+                Ok(checkpoint) => checkpoint,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
                     std::process::exit(1);
                 }
+            };
+            println!(
+                "Resuming run {} of {} ({}): {} step(s) succeeded",
+                run_id,
+                checkpoint.workflow,
+                checkpoint.path,
+                checkpoint.succeeded()
+            );
+            if let Some(step) = checkpoint.first_failure() {
+                println!(
+                    "{} failed: {}",
+                    step.step_id,
+                    step.error.as_deref().unwrap_or("")
+                );
             }
-            Err(e) => {
-                eprintln!("Failed to load workflow: {}", e);
-                std::process::exit(1);
+            let registry = plugins::shared().snapshot();
+            match checkpoint::resume(&dir, &run_id, &registry) {
+                Ok(results) => {
+                    for (i, output) in results.iter().enumerate() {
+                        match output.validation.as_deref() {
+                            Some("resumed") => {
+                                println!(
+                                    "Step {}: {} reused from run {}",
+                                    i + 1,
+                                    output.step_id,
+                                    run_id
+                                )
+                            }
+                            Some("skipped") => println!(
+                                "Step {}: {} {}",
+                                i + 1,
+                                output.step_id,
+                                output.output.as_deref().unwrap_or("skipped")
+                            ),
+                            _ => println!("Step {}: {:?}", i + 1, output),
+                        }
+                    }
+                    if let Some(failed) = results.iter().find(|log| log.error.is_some()) {
+                        let next = checkpoint::latest(&dir, &checkpoint.path)
+                            .map_or(run_id.clone(), |next| next.run_id);
+                        eprintln!(
+                            "{} failed again; once it's fixed: lao resume {}",
+                            failed.step_id, next
+                        );
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to resume run {}: {}", run_id, e);
+                    std::process::exit(1);
+                }
             }
-        },
+        }
+        Commands::Resolve { path } => {
+            let merged = extends::load(std::path::Path::new(&path)).and_then(|merged| {
+                // Checked as a workflow so mistakes in overrides show here
+                serde_yaml::from_value::<lao_orchestrator_core::Workflow>(merged.clone())
+                    .map_err(|e| e.to_string())?;
+                serde_yaml::to_string(&merged).map_err(|e| e.to_string())
+            });
+            match merged {
+                Ok(yaml) => print!("{}", yaml),
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Validate {
+            path,
+            watch,
+            lenient,
+        } => {
+            let mut service = if lenient {
+                ValidationService::lenient()
+            } else {
+                ValidationService::new()
+            };
+            if watch {
+                watch_workflow(&path, &mut service);
+            }
+            let registry = plugins::shared().snapshot();
+            match service.update_file(std::path::Path::new(&path), &registry) {
+                Ok(diagnostics) if diagnostics.is_empty() => {
+                    println!("Validation passed: all steps and plugins available.");
+                }
+                Ok(diagnostics) => {
+                    for d in diagnostics.iter() {
+                        println!("{}:{}", path, d);
+                    }
+                    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to load workflow: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Test { dir, update } => {
+            let cases = match golden::discover(std::path::Path::new(&dir)) {
+                Ok(cases) => cases,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            // Steps with a cache_key must not hit outputs cached by real runs
+            let cache_dir = std::env::temp_dir().join(format!("lao_test_{}", uuid::Uuid::new_v4()));
+            std::env::set_var("LAO_CACHE_DIR", &cache_dir);
+            let mut failed = 0;
+            for case in &cases {
+                match golden::check(case, update) {
+                    CaseOutcome::Passed => println!("PASS    {}", case.name),
+                    CaseOutcome::Updated => println!("UPDATED {}", case.name),
+                    CaseOutcome::Missing => {
+                        failed += 1;
+                        println!(
+                            "MISSING {} (run with --update to record {})",
+                            case.name,
+                            case.expected_path().display()
+                        );
+                    }
+                    CaseOutcome::Mismatch(diff) => {
+                        failed += 1;
+                        println!("FAIL    {}", case.name);
+                        for line in diff.lines() {
+                            println!("        {}", line);
+                        }
+                    }
+                    CaseOutcome::Error(e) => {
+                        failed += 1;
+                        println!("ERROR   {}: {}", case.name, e);
+                    }
+                }
+            }
+            std::fs::remove_dir_all(&cache_dir).ok();
+            println!(
+                "{} of {} workflow tests passed",
+                cases.len() - failed,
+                cases.len()
+            );
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
         Commands::PluginList => {
             let plugin_dir = PathUtils::plugin_dir();
             let plugin_registry =
@@ -356,25 +1229,30 @@ fn main() {
                 }
             };
             // SAFETY: FFI call to plugin, must ensure input is valid and plugin is trusted.
-            use std::ffi::CString;
-            let c_prompt = match CString::new(prompt.clone()) {
-                Ok(c) => c,
-                Err(_) => {
-                    eprintln!("Failed to create CString from prompt");
+            let yaml = match dispatcher.call(&prompt) {
+                Ok(yaml) => yaml,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
                     std::process::exit(1);
                 }
             };
-            let input = PluginInput {
-                text: c_prompt.into_raw(),
-            };
-            let output_obj = unsafe { ((*dispatcher.vtable).run)(&input) };
-            let c_str = unsafe { std::ffi::CStr::from_ptr(output_obj.text) };
-            let yaml = c_str.to_string_lossy().to_string();
-            unsafe { ((*dispatcher.vtable).free_output)(output_obj) };
             println!("Generated workflow:\n{}", yaml);
             let clean_yaml = strip_code_fences(&yaml);
             match serde_yaml::from_str::<lao_orchestrator_core::Workflow>(&clean_yaml) {
-                Ok(_workflow) => {
+                Ok(mut workflow) => {
+                    // Generated workflows may not reach restricted plugins and are
+                    // saved pinned to the plugins they were generated against
+                    if let Err(e) = plugin_policy::admit_generated(&mut workflow, &registry) {
+                        eprintln!("[ERROR] Generated workflow refused: {}", e);
+                        std::process::exit(1);
+                    }
+                    let clean_yaml = match serde_yaml::to_string(&workflow) {
+                        Ok(yaml) => yaml,
+                        Err(e) => {
+                            eprintln!("[ERROR] Failed to serialize workflow: {}", e);
+                            std::process::exit(1);
+                        }
+                    };
                     let out_path = output
                         .unwrap_or_else(|| "workflows/generated_from_prompt.yaml".to_string());
                     if let Some(parent) = std::path::Path::new(&out_path).parent() {
@@ -433,22 +1311,14 @@ fn main() {
             };
             let mut failures = 0;
             for (i, pair) in prompt_pairs.iter().enumerate() {
-                use std::ffi::CString;
-                let c_prompt = match CString::new(pair.prompt.clone()) {
-                    Ok(c) => c,
-                    Err(_) => {
-                        eprintln!("Failed to create CString from prompt");
+                let generated = match dispatcher.call(&pair.prompt) {
+                    Ok(generated) => generated,
+                    Err(e) => {
+                        eprintln!("[ERROR] {}", e);
                         failures += 1;
                         continue;
                     }
                 };
-                let input = PluginInput {
-                    text: c_prompt.into_raw(),
-                };
-                let output_obj = unsafe { ((*dispatcher.vtable).run)(&input) };
-                let c_str = unsafe { std::ffi::CStr::from_ptr(output_obj.text) };
-                let generated = c_str.to_string_lossy().to_string();
-                unsafe { ((*dispatcher.vtable).free_output)(output_obj) };
                 let expected = normalize_yaml(&pair.workflow);
                 let actual = normalize_yaml(&generated);
                 let pass = expected == actual;
@@ -478,29 +1348,28 @@ fn main() {
         }
         Commands::ListWorkflows => {
             let dir = std::path::Path::new("workflows");
-            match std::fs::read_dir(dir) {
-                Ok(entries) => {
-                    println!("Available workflows:");
-                    let mut found = false;
-                    for entry in entries.flatten() {
-                        let path = entry.path();
-                        if let Some(ext) = path.extension() {
-                            if ext == "yaml" || ext == "yml" {
-                                match path.file_name() {
-                                    Some(name) => println!("- {}", name.to_string_lossy()),
-                                    None => println!("- [unknown file name]"),
-                                }
-                                found = true;
-                            }
-                        }
-                    }
-                    if !found {
-                        println!("[INFO] No workflow YAML files found in workflows/ directory.");
+            if let Err(e) = std::fs::read_dir(dir) {
+                eprintln!("[ERROR] Failed to read workflows directory: {}", e);
+                std::process::exit(1);
+            }
+            let entries = metadata::gallery(dir);
+            if entries.is_empty() {
+                println!("[INFO] No workflow YAML files found in workflows/ directory.");
+            } else {
+                println!("Available workflows:");
+            }
+            for entry in entries {
+                let file = entry
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                match (&entry.error, &entry.description) {
+                    (Some(e), _) => println!("- {}  [invalid: {}]", file, e),
+                    (None, Some(description)) => {
+                        println!("- {}  {}: {}", file, entry.title, description)
                     }
-                }
-                Err(e) => {
-                    eprintln!("[ERROR] Failed to read workflows directory: {}", e);
-                    std::process::exit(1);
+                    (None, None) => println!("- {}  {}", file, entry.title),
                 }
             }
         }
@@ -516,6 +1385,42 @@ fn main() {
                 }
             }
         }
+        Commands::Show { path } => {
+            let workflow = match load_workflow_yaml(&path) {
+                Ok(workflow) => workflow,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to load {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            };
+            let meta = workflow.metadata.clone().unwrap_or_default();
+            println!("{}", workflow.title());
+            if let Some(description) = &meta.description {
+                println!("{}", description.trim_end());
+            }
+            if let Some(author) = &meta.author {
+                println!("Author: {}", author);
+            }
+            let registry = plugins::shared().snapshot();
+            println!("Requires:");
+            for plugin in workflow.required_plugins() {
+                if registry.get(&plugin).is_some() {
+                    println!("  ✓ {}", plugin);
+                } else {
+                    println!("  ✗ {} (not installed)", plugin);
+                }
+            }
+            println!("Steps:");
+            for (i, step) in workflow.steps.iter().enumerate() {
+                println!("  step{}  {}", i + 1, step.run);
+            }
+            if !meta.examples.is_empty() {
+                println!("Examples:");
+                for example in &meta.examples {
+                    println!("  {}", example);
+                }
+            }
+        }
         Commands::DeleteWorkflow { name } => {
             let path = format!("workflows/{}.yaml", name);
             match std::fs::remove_file(&path) {
@@ -564,210 +1469,1969 @@ fn main() {
                     serde_yaml::to_string(input).unwrap_or_default().trim()
                 );
             }
-            if let Some(output) = manifest.get("output") {
+            if let Some(output) = manifest.get("output") {
+                println!(
+                    "Output Schema: {}",
+                    serde_yaml::to_string(output).unwrap_or_default().trim()
+                );
+            }
+            if let Some(examples) = manifest.get("example_prompts") {
+                println!("Example Prompts:");
+                if let Some(arr) = examples.as_sequence() {
+                    for ex in arr {
+                        println!("  - {:?}", ex);
+                    }
+                } else {
+                    println!("  - {:?}", examples);
+                }
+            }
+        }
+        Commands::Schedule {
+            action,
+            workflow_path,
+            cron,
+            max_runs,
+        } => {
+            let action = match (action, workflow_path, cron) {
+                (Some(action), _, _) => action,
+                (None, Some(workflow), Some(schedule)) => ScheduleCommands::Add {
+                    workflow,
+                    schedule,
+                    max_runs,
+                },
+                (None, None, None) => ScheduleCommands::List,
+                (None, _, _) => {
+                    eprintln!("[ERROR] Give a workflow and --cron, or use `lao schedule add`");
+                    std::process::exit(1);
+                }
+            };
+            schedule_command(action);
+        }
+        Commands::Unschedule { workflow_id } => {
+            schedule_command(ScheduleCommands::Rm { id: workflow_id })
+        }
+        Commands::ListScheduled => schedule_command(ScheduleCommands::List),
+        Commands::Watch { action } => watch_command(action),
+        Commands::OllamaStatus => {
+            let client = match ollama::shared() {
+                Ok(client) => client,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let config = client.config();
+            println!("Ollama: {}", config.base_url);
+            println!(
+                "Auth: {}",
+                if config.api_key.is_some() {
+                    "bearer token"
+                } else {
+                    "none"
+                }
+            );
+            println!(
+                "Timeout: {}s, retries: {}",
+                config.timeout.as_secs(),
+                config.retries
+            );
+            match client.health() {
+                Ok(version) => println!("✓ Reachable, version {}", version),
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Status { workflow_id } => {
+            let states = match WorkflowStateManager::new(scheduler::default_dir()) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to read workflow states: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            if let Some(id) = workflow_id {
+                match states.load_state(&id) {
+                    Ok(Some(state)) => {
+                        println!("Workflow: {} ({})", state.workflow_name, state.workflow_id);
+                        println!("Status: {:?}", state.status);
+                        println!("Created: {:?}", state.created_at);
+                        if let Some(started) = state.started_at {
+                            println!("Started: {:?}", started);
+                        }
+                        if let Some(completed) = state.completed_at {
+                            println!("Completed: {:?}", completed);
+                        }
+                        println!(
+                            "Progress: {}/{} steps",
+                            state.current_step, state.total_steps
+                        );
+                        if let Some(error) = &state.error_message {
+                            println!("Error: {}", error);
+                        }
+                    }
+                    Ok(None) => println!("Workflow not found: {}", id),
+                    Err(e) => eprintln!("[ERROR] Failed to load workflow state: {}", e),
+                }
+            } else {
+                println!("Data directory: {}", data_dir::root().display());
+                let store = TriggerStore::open_default();
+                match store.load() {
+                    Ok(all) => println!(
+                        "Triggers: {} schedules and {} watches",
+                        all.iter()
+                            .filter(|t| matches!(t.on, TriggerOn::Schedule { .. }))
+                            .count(),
+                        all.iter()
+                            .filter(|t| matches!(t.on, TriggerOn::Watch { .. }))
+                            .count()
+                    ),
+                    Err(e) => eprintln!("[WARN] {}", e),
+                }
+                let states: Vec<_> = states
+                    .list_states()
+                    .into_iter()
+                    .filter(|state| !matches!(state.status, WorkflowStatus::Scheduled))
+                    .collect();
+                if states.is_empty() {
+                    println!("No workflow states found.");
+                } else {
+                    println!("All workflow states:");
+                    for state in states {
+                        println!(
+                            "  {} - {} ({:?})",
+                            state.workflow_id, state.workflow_name, state.status
+                        );
+                    }
+                }
+                warn_legacy_schedules();
+            }
+        }
+        Commands::Cleanup { max_age_hours } => {
+            let mut states = match WorkflowStateManager::new(scheduler::default_dir()) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to read workflow states: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            match states.cleanup_old_states(max_age_hours) {
+                Ok(count) => println!("✓ Cleaned up {} old workflow states", count),
+                Err(e) => eprintln!("[ERROR] Failed to cleanup states: {}", e),
+            }
+        }
+        Commands::Daemon {
+            action: Some(action),
+            ..
+        } => daemon_service(action),
+        Commands::Daemon {
+            action: None,
+            interval,
+            metrics_addr,
+            webhook_addr,
+            mqtt_broker,
+            hotkeys,
+            tray,
+            workflows_dir,
+            api_addr,
+        } => {
+            println!("Starting LAO workflow scheduler daemon...");
+            println!("Check interval: {} seconds", interval);
+
+            // Load plugins now so the first delegated run starts warm
+            plugins::shared();
+            let info_path = daemon::default_path();
+            match daemon::serve(api_addr.as_str(), &info_path) {
+                Ok(addr) => println!(
+                    "Serving the local run API at {} (published in {})",
+                    addr,
+                    info_path.display()
+                ),
+                Err(e) => {
+                    eprintln!(
+                        "[ERROR] Failed to start the local run API on {}: {}",
+                        api_addr, e
+                    );
+                    std::process::exit(1);
+                }
+            }
+
+            if let Some(addr) = metrics_addr {
+                match metrics::serve(addr.as_str()) {
+                    Ok(_) => println!("Serving metrics at http://{}/metrics", addr),
+                    Err(e) => {
+                        eprintln!("[ERROR] Failed to start metrics server on {}: {}", addr, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            if let Some(addr) = webhook_addr {
+                match webhook::serve(addr.as_str(), &workflows_dir) {
+                    Ok(_) => println!(
+                        "Serving webhooks at http://{}/hooks/<name> for {}",
+                        addr, workflows_dir
+                    ),
+                    Err(e) => {
+                        eprintln!("[ERROR] Failed to start webhook server on {}: {}", addr, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            if let Some(broker) = mqtt_broker {
+                let topics: Vec<String> = mqtt::subscriptions(std::path::Path::new(&workflows_dir))
+                    .into_iter()
+                    .map(|s| s.topic)
+                    .collect();
+                if topics.is_empty() {
+                    println!(
+                        "No workflows in {} have an mqtt: topic yet; checking again every few seconds",
+                        workflows_dir
+                    );
+                } else {
+                    println!("Subscribing at {} to {}", broker, topics.join(", "));
+                }
+                mqtt::serve(&broker, &workflows_dir);
+            }
+
+            if let Some(file) = hotkeys {
+                let file = file
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(lao_orchestrator_core::hotkeys::default_path);
+                serve_hotkeys(&file);
+            }
+
+            if let Some(file) = tray {
+                let file = file
+                    .map(std::path::PathBuf::from)
+                    .unwrap_or_else(lao_orchestrator_core::tray::default_path);
+                serve_tray(&file);
+            }
+
+            let store = TriggerStore::open_default();
+            match store.load() {
+                Ok(all) => println!(
+                    "Triggers: {} schedules and {} watches in {}",
+                    all.iter()
+                        .filter(|t| matches!(t.on, TriggerOn::Schedule { .. }))
+                        .count(),
+                    all.iter()
+                        .filter(|t| matches!(t.on, TriggerOn::Watch { .. }))
+                        .count(),
+                    store.path().display()
+                ),
+                Err(e) => eprintln!("[WARN] {}", e),
+            }
+            warn_legacy_schedules();
+            triggers::serve(store, std::time::Duration::from_secs(interval));
+            loop {
+                std::thread::park();
+            }
+        }
+        Commands::Serve {
+            addr,
+            workflows_dir,
+            token,
+        } => {
+            // Load plugins now so the first run starts warm
+            plugins::shared();
+            let token = token
+                .or_else(|| std::env::var("LAO_SERVE_TOKEN").ok())
+                .unwrap_or_else(api::generate_token);
+            match api::serve(addr.as_str(), &workflows_dir, &token, Orchestrator::new()) {
+                Ok(bound) => {
+                    println!(
+                        "Serving the LAO API at http://{} for {}",
+                        bound, workflows_dir
+                    );
+                    println!("Token: {}", token);
+                }
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to start the API on {}: {}", addr, e);
+                    std::process::exit(1);
+                }
+            }
+            loop {
+                std::thread::park();
+            }
+        }
+        Commands::Rpc => {
+            // stdout carries the protocol; everything else goes to stderr
+            let stdin = std::io::stdin();
+            if let Err(e) = rpc::serve(stdin.lock(), std::io::stdout(), Orchestrator::new()) {
+                eprintln!("[ERROR] rpc: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Audit {
+            kind,
+            subject,
+            since,
+            limit,
+            json,
+            verify,
+        } => {
+            let path = audit::default_path();
+            if verify {
+                match audit::verify(&path) {
+                    Ok(count) => println!("✓ Audit log intact: {} entries", count),
+                    Err(e) => {
+                        eprintln!(
+                            "[ERROR] Audit log {} failed verification: {}",
+                            path.display(),
+                            e
+                        );
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            let kind = match kind.as_deref().map(|k| (k, AuditKind::parse(k))) {
+                Some((k, None)) => {
+                    eprintln!("[ERROR] Unknown audit event kind: {}", k);
+                    std::process::exit(1);
+                }
+                Some((_, kind)) => kind,
+                None => None,
+            };
+            let since = match since.as_deref().map(parse_age) {
+                Some(Ok(age)) => Some(chrono::Utc::now() - age),
+                Some(Err(e)) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+                None => None,
+            };
+            let query = AuditQuery {
+                kind,
+                subject,
+                since,
+                limit: Some(limit),
+            };
+            match audit::query(&path, &query) {
+                Ok(entries) if entries.is_empty() => println!("No audit events found."),
+                Ok(entries) => {
+                    for entry in entries {
+                        if json {
+                            println!("{}", serde_json::to_string(&entry).unwrap_or_default());
+                        } else {
+                            println!("{}", entry.render());
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to read audit log {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Replay {
+            run_id,
+            step,
+            input,
+            input_file,
+            edit,
+            json,
+        } => {
+            let replay = match Replay::load(&run_diff::default_dir(), &run_id) {
+                Ok(replay) => replay,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let Some(step_id) = step else {
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(replay.steps()).unwrap_or_default()
+                    );
+                    return;
+                }
+                let run = &replay.run;
+                println!(
+                    "{}  {}  {}  {}ms  {}",
+                    run.run_id,
+                    run.workflow,
+                    run.status,
+                    run.duration_ms,
+                    run.finished.format("%Y-%m-%d %H:%M:%S")
+                );
+                for step in replay.steps() {
+                    println!();
+                    println!(
+                        "{}  {}  {}  {}ms",
+                        step.step_id, step.runner, step.status, step.duration_ms
+                    );
+                    let fields = [
+                        ("input", &step.input),
+                        ("output", &step.output),
+                        ("error", &step.error),
+                    ];
+                    for (name, text) in fields {
+                        if let Some(text) = text {
+                            println!("  {}:", name);
+                            for line in text.lines() {
+                                println!("    {}", line);
+                            }
+                        }
+                    }
+                }
+                return;
+            };
+            let input = match (input, input_file, edit) {
+                (Some(input), _, _) => Ok(Some(input)),
+                (_, Some(file), _) => std::fs::read_to_string(&file)
+                    .map(Some)
+                    .map_err(|e| format!("{}: {}", file, e)),
+                (_, _, true) => replay
+                    .step(&step_id)
+                    .and_then(|step| edit_input(step.input.as_deref().unwrap_or_default()))
+                    .map(Some),
+                _ => Ok(None),
+            };
+            let registry = plugins::shared().snapshot();
+            let rerun = input.and_then(|input| replay.rerun(&step_id, input.as_deref(), &registry));
+            let rerun = match rerun {
+                Ok(rerun) => rerun,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&rerun).unwrap_or_default()
+                );
+            } else {
+                let with = if rerun.input_changed {
+                    "a new input"
+                } else {
+                    "the recorded input"
+                };
+                println!(
+                    "Re-ran {} ({}) with {} in {}ms",
+                    rerun.step_id, rerun.runner, with, rerun.duration_ms
+                );
+                if let Some(error) = &rerun.error {
+                    println!("  error: {}", error);
+                } else if rerun.diff.iter().all(|l| matches!(l, DiffLine::Same(_))) {
+                    println!("  Same output as recorded");
+                } else {
+                    for line in &rerun.diff {
+                        match line {
+                            DiffLine::Same(text) => println!("     {}", text),
+                            DiffLine::Added(text) => println!("    +{}", text),
+                            DiffLine::Removed(text) => println!("    -{}", text),
+                        }
+                    }
+                }
+            }
+            if rerun.error.is_some() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Exec {
+            plugin,
+            input,
+            preset,
+        } => {
+            let preset = match preset.map(|name| presets::load(&presets::default_dir(), &name)) {
+                Some(Ok(preset)) => Some(preset),
+                Some(Err(e)) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+                None => None,
+            };
+            let registry = plugins::shared().snapshot();
+            // At a terminal, a plugin with an input schema is asked for field by field
+            let form = match (&input, &preset, &plugin) {
+                (None, None, Some(plugin))
+                    if std::io::IsTerminal::is_terminal(&std::io::stdin()) =>
+                {
+                    registry
+                        .get(plugin)
+                        .and_then(|instance| instance.info.input_schema.as_deref())
+                        .and_then(|schema| serde_json::from_str::<serde_json::Value>(schema).ok())
+                }
+                _ => None,
+            };
+            let input = match (input, form) {
+                (Some(input), _) => input,
+                (None, Some(schema)) => {
+                    match input_form::prompt(&schema, std::io::stdin().lock(), std::io::stderr()) {
+                        Ok(serde_json::Value::String(text)) => text,
+                        Ok(value) => value.to_string(),
+                        Err(e) => {
+                            eprintln!("[ERROR] {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                (None, None) => {
+                    let mut buffer = String::new();
+                    if let Err(e) =
+                        std::io::Read::read_to_string(&mut std::io::stdin(), &mut buffer)
+                    {
+                        eprintln!("[ERROR] Failed to read input from stdin: {}", e);
+                        std::process::exit(1);
+                    }
+                    buffer
+                }
+            };
+            let (plugin, input) = match &preset {
+                Some(preset) => (
+                    preset.run().unwrap_or_default().to_string(),
+                    preset.input(&input),
+                ),
+                None => (plugin.unwrap_or_default(), input),
+            };
+            let Some(instance) = registry.get(&plugin) else {
+                eprintln!("[ERROR] Plugin '{}' not found", plugin);
+                std::process::exit(1);
+            };
+            match exec_plugin(instance, &input) {
+                Ok(output) => print!("{}", output),
+                Err(e) => {
+                    eprintln!("[ERROR] {} failed: {}", plugin, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Export {
+            path,
+            format,
+            output,
+        } => {
+            if format != "script" {
+                eprintln!(
+                    "[ERROR] Unknown export format: {} (supported: script)",
+                    format
+                );
+                std::process::exit(1);
+            }
+            let plan = match ExecutionPlan::load(&path, &plugins::shared().snapshot()) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to load workflow: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let script = match exporter::to_shell_script(&plan) {
+                Ok(script) => script,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to export workflow: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            match output {
+                Some(output) => {
+                    if let Err(e) = std::fs::write(&output, script) {
+                        eprintln!("[ERROR] Failed to write {}: {}", output, e);
+                        std::process::exit(1);
+                    }
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        let _ = std::fs::set_permissions(
+                            &output,
+                            std::fs::Permissions::from_mode(0o755),
+                        );
+                    }
+                    println!("✓ Wrote script to {}", output);
+                }
+                None => print!("{}", script),
+            }
+        }
+        Commands::Import {
+            path,
+            output,
+            format,
+        } => {
+            let format = match format.as_deref().map(|f| (f, ImportFormat::parse(f))) {
+                Some((f, None)) => {
+                    eprintln!(
+                        "[ERROR] Unknown import format: {} (expected n8n or langchain)",
+                        f
+                    );
+                    std::process::exit(1);
+                }
+                Some((_, format)) => format,
+                None => None,
+            };
+            let json = match std::fs::read_to_string(&path) {
+                Ok(json) => json,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to read {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            };
+            let result = match importer::import_workflow(&json, format) {
+                Ok(result) => result,
+                Err(e) => {
+                    eprintln!("[ERROR] Import failed: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let output = output.unwrap_or_else(|| {
+                let stem = std::path::Path::new(&path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "imported".to_string());
+                format!("workflows/{}.yaml", stem)
+            });
+            let yaml = match result.to_yaml() {
+                Ok(yaml) => yaml,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to serialize workflow: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(parent) = std::path::Path::new(&output).parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            if let Err(e) = std::fs::write(&output, yaml) {
+                eprintln!("[ERROR] Failed to write workflow file {}: {}", output, e);
+                std::process::exit(1);
+            }
+
+            let registry = plugins::shared().snapshot();
+            let report = &result.report;
+            println!(
+                "✓ Imported '{}' to {} ({} steps)",
+                result.workflow.workflow,
+                output,
+                result.workflow.steps.len()
+            );
+            for node in &report.mapped {
+                let missing = if registry.get(&node.plugin).is_none() {
+                    " [plugin not installed]"
+                } else {
+                    ""
+                };
+                println!(
+                    "  {} {} ({}) -> {}{}",
+                    node.step_id, node.source, node.node_type, node.plugin, missing
+                );
+            }
+            if !report.triggers.is_empty() {
+                println!(
+                    "Skipped trigger nodes (use `lao schedule` instead): {}",
+                    report.triggers.join(", ")
+                );
+            }
+            if !report.unmapped.is_empty() {
+                println!("Unmapped nodes to replace by hand:");
+                for node in &report.unmapped {
+                    println!(
+                        "  {} {} ({}): {}",
+                        node.step_id.as_deref().unwrap_or("-"),
+                        node.source,
+                        node.node_type,
+                        node.reason
+                    );
+                }
+            }
+        }
+        Commands::Usage { since, json } => {
+            let age = match parse_age(&since) {
+                Ok(age) => age,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let path = usage::default_path();
+            let history = match usage::read_history(&path) {
+                Ok(history) => history,
+                Err(e) => {
+                    eprintln!(
+                        "[ERROR] Failed to read usage history {}: {}",
+                        path.display(),
+                        e
+                    );
+                    std::process::exit(1);
+                }
+            };
+            let summary = usage::summarize(&history, Some(chrono::Utc::now() - age));
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&summary).unwrap_or_default()
+                );
+                return;
+            }
+            if summary.runs == 0 {
+                println!("No runs recorded in the last {}.", since);
+                return;
+            }
+
+            println!(
+                "Usage over the last {}: {} runs, {} tokens, {:.1} min plugin time, ~{:.1} Wh at {} W",
+                since,
+                summary.runs,
+                summary.total_tokens(),
+                summary.plugin_seconds() / 60.0,
+                summary.energy_wh,
+                usage::power_watts()
+            );
+            println!();
+            println!(
+                "{:<32} {:>6} {:>12} {:>10} {:>10}",
+                "WORKFLOW", "RUNS", "TOKENS", "MINUTES", "WH"
+            );
+            for (name, totals) in summary.workflows_by_time() {
+                println!(
+                    "{:<32} {:>6} {:>12} {:>10.1} {:>10.2}",
+                    name,
+                    totals.runs,
+                    totals.tokens,
+                    totals.seconds / 60.0,
+                    totals.energy_wh
+                );
+            }
+            println!();
+            println!(
+                "{:<32} {:>6} {:>12} {:>12} {:>10}",
+                "PLUGIN", "CALLS", "PROMPT TOK", "OUTPUT TOK", "MINUTES"
+            );
+            for (name, totals) in &summary.plugins {
+                println!(
+                    "{:<32} {:>6} {:>12} {:>12} {:>10.1}",
+                    name,
+                    totals.calls,
+                    totals.prompt_tokens,
+                    totals.completion_tokens,
+                    totals.seconds / 60.0
+                );
+            }
+        }
+        Commands::History {
+            workflow,
+            status,
+            labels,
+            tags,
+            search,
+            since,
+            limit,
+            json,
+        } => {
+            let since = match since.as_deref().map(parse_age) {
+                Some(Ok(age)) => Some(chrono::Utc::now() - age),
+                Some(Err(e)) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+                None => None,
+            };
+            let labels = match parse_labels(&labels) {
+                Ok(labels) => labels,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let query = RunQuery {
+                workflow,
+                status,
+                labels,
+                tags,
+                text: search,
+                since,
+                limit: Some(limit),
+            };
+            let runs = run_labels::query(&run_diff::default_dir(), &query);
+            if runs.is_empty() {
+                println!("No runs found.");
+            }
+            for run in runs {
+                if json {
+                    let mut value = serde_json::to_value(&run).unwrap_or_default();
+                    if let Some(object) = value.as_object_mut() {
+                        object.remove("steps");
+                    }
+                    println!("{}", value);
+                } else {
+                    println!(
+                        "{}  {}  {}  {}  {}ms  {}",
+                        run.finished.format("%Y-%m-%d %H:%M:%S"),
+                        run.run_id,
+                        run.workflow,
+                        run.status,
+                        run.duration_ms,
+                        run.labels.render()
+                    );
+                }
+            }
+        }
+        Commands::DiffRuns { a, b, json } => {
+            let diff = match run_diff::diff_runs(&run_diff::default_dir(), &a, &b) {
+                Ok(diff) => diff,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&diff).unwrap_or_default()
+                );
+                return;
+            }
+            for run in [&diff.a, &diff.b] {
+                println!(
+                    "{}  {}  {}  {}ms  {}",
+                    run.run_id,
+                    run.workflow,
+                    run.status,
+                    run.duration_ms,
+                    run.finished.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+            println!("Duration: {:+}ms", diff.duration_delta_ms);
+            println!();
+            for step in &diff.steps {
+                let (marker, status) = match (step.change, &step.status) {
+                    (StepChange::Added, (_, Some(b))) => ('+', b.clone()),
+                    (StepChange::Removed, (Some(a), _)) => ('-', a.clone()),
+                    (StepChange::Changed, (Some(a), Some(b))) if a != b => {
+                        ('~', format!("{} -> {}", a, b))
+                    }
+                    (StepChange::Changed, (_, Some(b))) => ('~', b.clone()),
+                    (_, (_, status)) => (' ', status.clone().unwrap_or_default()),
+                };
+                println!(
+                    "{} {:<24} {:<24} {:<20} {:+}ms",
+                    marker, step.step_id, step.runner, status, step.duration_delta_ms
+                );
+                if let Some((error_a, error_b)) = &step.error {
+                    println!("      error: {:?} -> {:?}", error_a, error_b);
+                }
+                if let Some((prompt_a, prompt_b)) = &step.prompt {
+                    let name = |p: &Option<String>| p.clone().unwrap_or_else(|| "none".to_string());
+                    println!("      prompt: {} -> {}", name(prompt_a), name(prompt_b));
+                }
+                for line in &step.output {
+                    match line {
+                        DiffLine::Same(_) => {}
+                        DiffLine::Added(text) => println!("      +{}", text),
+                        DiffLine::Removed(text) => println!("      -{}", text),
+                    }
+                }
+            }
+        }
+        Commands::Cache {
+            command: CacheCommands::Stats { json },
+        } => {
+            let stats = memo::MemoStore::open_default().stats();
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&stats).unwrap_or_default()
+                );
+                return;
+            }
+            if stats.is_empty() {
+                println!("No memoized outputs.");
+                return;
+            }
+            println!(
+                "{:<32} {:>8} {:>12}  LAST WRITTEN",
+                "PLUGIN", "ENTRIES", "KB"
+            );
+            for plugin in &stats {
+                println!(
+                    "{:<32} {:>8} {:>12.1}  {}",
+                    plugin.plugin,
+                    plugin.entries,
+                    plugin.bytes as f64 / 1024.0,
+                    plugin
+                        .newest
+                        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default()
+                );
+            }
+        }
+        Commands::Cache {
+            command:
+                CacheCommands::List {
+                    plugin,
+                    limit,
+                    json,
+                },
+        } => {
+            let mut entries = memo::MemoStore::open_default().list(plugin.as_deref());
+            let total = entries.len();
+            entries.truncate(limit);
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&entries).unwrap_or_default()
+                );
+                return;
+            }
+            if entries.is_empty() {
+                println!("No memoized outputs.");
+                return;
+            }
+            println!(
+                "{:<16} {:<28} {:<10} {:>10}  WRITTEN",
+                "KEY", "PLUGIN", "VERSION", "KB"
+            );
+            for entry in &entries {
+                println!(
+                    "{:<16} {:<28} {:<10} {:>10.1}  {}",
+                    &entry.key[..entry.key.len().min(16)],
+                    entry.plugin,
+                    entry.version,
+                    entry.bytes as f64 / 1024.0,
+                    entry.created.format("%Y-%m-%d %H:%M")
+                );
+            }
+            if total > entries.len() {
+                println!("... {} more (use --limit)", total - entries.len());
+            }
+        }
+        Commands::Cache {
+            command: CacheCommands::Show { key },
+        } => match memo::MemoStore::open_default().show(&key) {
+            Ok((entry, output)) => {
+                println!("Key:     {}", entry.key);
+                println!("Plugin:  {} {}", entry.plugin, entry.version);
+                println!("Written: {}", entry.created.format("%Y-%m-%d %H:%M:%S UTC"));
+                println!();
+                println!("{}", output);
+            }
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Cache {
+            command:
+                CacheCommands::Prune {
+                    older_than,
+                    max_size,
+                },
+        } => {
+            if older_than.is_none() && max_size.is_none() {
+                eprintln!("[ERROR] Give --older-than, --max-size or both");
+                std::process::exit(1);
+            }
+            let before = match older_than.as_deref().map(parse_age).transpose() {
+                Ok(age) => age.map(|age| chrono::Utc::now() - age),
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let max_bytes = max_size.map(|mb| (mb * 1024.0 * 1024.0) as u64);
+            match memo::MemoStore::open_default().prune(before, max_bytes) {
+                Ok(removed) => println!("✓ Removed {} memoized outputs", removed),
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to prune memoized outputs: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Cache {
+            command: CacheCommands::Clear { plugin },
+        } => match memo::MemoStore::open_default().clear(plugin.as_deref()) {
+            Ok(removed) => println!("✓ Removed {} memoized outputs", removed),
+            Err(e) => {
+                eprintln!("[ERROR] Failed to clear memoized outputs: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Eval {
+            command:
+                EvalCommands::Rank {
+                    workflow,
+                    step,
+                    json,
+                },
+        } => {
+            let history = match evaluation::read_history(&evaluation::default_path()) {
+                Ok(history) => history,
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to read evaluation log: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let ranked = evaluation::rank(&history, workflow.as_deref(), step.as_deref());
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&ranked).unwrap_or_default()
+                );
+                return;
+            }
+            if ranked.is_empty() {
+                println!("No evaluated steps.");
+                return;
+            }
+            println!(
+                "{:<24} {:<20} {:<16} {:<8} {:>5} {:>6} {:>6} {:>6}  PROMPT",
+                "WORKFLOW/STEP", "PLUGIN", "MODEL", "VARIANT", "RUNS", "MEAN", "MIN", "MAX"
+            );
+            for variant in &ranked {
+                println!(
+                    "{:<24} {:<20} {:<16} {:<8} {:>5} {:>6.2} {:>6.1} {:>6.1}  {}",
+                    format!("{}/{}", variant.workflow, variant.step_id),
+                    variant.plugin,
+                    variant.model.as_deref().unwrap_or("-"),
+                    variant.prompt,
+                    variant.runs,
+                    variant.mean,
+                    variant.min,
+                    variant.max,
+                    variant.prompt_preview
+                );
+            }
+        }
+        Commands::Batch {
+            path,
+            inputs,
+            input_name,
+            depth,
+            mock,
+            json,
+        } => {
+            let registry = match mock.as_deref().map(|fixtures| {
+                let fixtures = mock::load_fixtures(std::path::Path::new(fixtures))?;
+                load_workflow_yaml(&path).map(|w| Arc::new(mock::mock_registry(&w, &fixtures)))
+            }) {
+                Some(Ok(registry)) => registry,
+                Some(Err(e)) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+                None => plugins::shared().snapshot(),
+            };
+            let spec = BatchSpec {
+                path,
+                inputs,
+                input_name,
+                depth,
+            };
+            let total = spec.inputs.len();
+            let labels = run_labels::current().with_tag("cli");
+            let report = run_labels::with_labels(labels, || {
+                pipeline::run_batch(&spec, &registry, &|index, item| match &item.error {
+                    Some(e) => eprintln!("[{}/{}] {} failed: {}", index + 1, total, item.input, e),
+                    None => eprintln!(
+                        "[{}/{}] {} done in {}ms",
+                        index + 1,
+                        total,
+                        item.input,
+                        item.duration_ms
+                    ),
+                })
+            });
+            let report = match report {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).unwrap_or_default()
+                );
+            } else {
+                print_batch_timeline(&report);
+            }
+            if report.items.iter().any(|item| item.error.is_some()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Ab {
+            path,
+            step,
+            variants,
+            runs,
+            judge,
+            mock,
+            json,
+        } => {
+            let registry = match mock.as_deref().map(|fixtures| {
+                let fixtures = mock::load_fixtures(std::path::Path::new(fixtures))?;
+                let workflow = load_workflow_yaml(&path)?;
+                let mut registry = mock::mock_registry(&workflow, &fixtures);
+                // Judges are plugins of the run too
+                let judges = judge.iter().chain(
+                    workflow
+                        .steps
+                        .iter()
+                        .filter_map(|s| s.evaluate.as_ref().map(|e| &e.judge)),
+                );
+                for name in judges {
+                    if registry.get(name).is_none() {
+                        let spec = fixtures.get(name).cloned().unwrap_or_default();
+                        registry.register_plugin(mock::mock_plugin(name, spec));
+                    }
+                }
+                Ok::<_, String>(registry)
+            }) {
+                Some(Ok(registry)) => Arc::new(registry),
+                Some(Err(e)) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+                None => plugins::shared().snapshot(),
+            };
+            let spec = AbSpec {
+                step,
+                variants,
+                runs,
+                judge,
+            };
+            let labels = run_labels::current().with_tag("cli");
+            let report = run_labels::with_labels(labels, || {
+                ab::run(&path, &spec, &registry, &mut |variant, run| {
+                    eprintln!("[ab] {} run {}/{}", variant, run, spec.runs)
+                })
+            });
+            let report = match report {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).unwrap_or_default()
+                );
+                return;
+            }
+            println!(
+                "{} {} — {} runs per variant, judge: {}",
+                report.workflow,
+                report.step_id,
+                spec.runs,
+                report.judge.as_deref().unwrap_or("none")
+            );
+            println!();
+            let best = report.best().map(|v| v.variant.clone());
+            println!(
+                "  {:<40} {:>6} {:>7} {:>13} {:>10}",
+                "VARIANT", "FAILED", "SCORE", "RANGE", "MEAN MS"
+            );
+            for variant in &report.variants {
+                let marker = if best.as_ref() == Some(&variant.variant) {
+                    '*'
+                } else {
+                    ' '
+                };
+                let (score, range) = match variant.mean_score() {
+                    Some(mean) => {
+                        let min = variant.scores.iter().copied().fold(f64::INFINITY, f64::min);
+                        let max = variant
+                            .scores
+                            .iter()
+                            .copied()
+                            .fold(f64::NEG_INFINITY, f64::max);
+                        (format!("{:.2}", mean), format!("{:.1}–{:.1}", min, max))
+                    }
+                    None => ("-".to_string(), "-".to_string()),
+                };
+                println!(
+                    "{} {:<40} {:>6} {:>7} {:>13} {:>10}",
+                    marker,
+                    variant.variant,
+                    variant.failures,
+                    score,
+                    range,
+                    variant.mean_ms()
+                );
+                if variant.criteria.len() > 1 {
+                    let criteria: Vec<String> = variant
+                        .criteria
+                        .iter()
+                        .map(|(name, score)| format!("{} {:.2}", name, score))
+                        .collect();
+                    println!("  {:<40} {}", "", criteria.join(", "));
+                }
+                if let Some(error) = &variant.error {
+                    println!("  {:<40} first failure: {}", "", error);
+                }
+            }
+            if let Some(best) = best {
+                let by = if report.judge.is_some() {
+                    "score"
+                } else {
+                    "duration"
+                };
+                println!();
+                println!("Best by {}: {}", by, best);
+            }
+        }
+        Commands::Preset {
+            command: PresetCommands::List,
+        } => {
+            let dir = presets::default_dir();
+            let presets = presets::list(&dir);
+            if presets.is_empty() {
+                println!("No presets in {}.", dir.display());
+                return;
+            }
+            println!("{:<24} {:<24} DESCRIPTION", "NAME", "PLUGIN");
+            for preset in &presets {
+                println!(
+                    "{:<24} {:<24} {}",
+                    preset.name,
+                    preset.run().unwrap_or_default(),
+                    preset.description.as_deref().unwrap_or_default()
+                );
+            }
+        }
+        Commands::Preset {
+            command: PresetCommands::Show { name },
+        } => match presets::load(&presets::default_dir(), &name) {
+            Ok(preset) => {
+                if let Some(description) = &preset.description {
+                    println!("# {}", description);
+                }
+                print!(
+                    "{}",
+                    serde_yaml::to_string(&preset.fields).unwrap_or_default()
+                );
+            }
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Prompts {
+            command: PromptCommands::List,
+        } => {
+            let dir = prompts::default_dir();
+            let files = prompts::list(&dir);
+            if files.is_empty() {
+                println!("No prompts in {}.", dir.display());
+                return;
+            }
+            println!("{:<32} {:<10} {:<8} STATE", "NAME", "VERSIONS", "LATEST");
+            for file in &files {
+                let latest = file
+                    .versions
+                    .last()
+                    .map_or("-".to_string(), |v| format!("v{}", v));
                 println!(
-                    "Output Schema: {}",
-                    serde_yaml::to_string(output).unwrap_or_default().trim()
+                    "{:<32} {:<10} {:<8} {}",
+                    file.name,
+                    file.versions.len(),
+                    latest,
+                    if file.edited { "edited" } else { "saved" }
                 );
             }
-            if let Some(examples) = manifest.get("example_prompts") {
-                println!("Example Prompts:");
-                if let Some(arr) = examples.as_sequence() {
-                    for ex in arr {
-                        println!("  - {:?}", ex);
-                    }
-                } else {
-                    println!("  - {:?}", examples);
+        }
+        Commands::Prompts {
+            command: PromptCommands::Edit { name },
+        } => {
+            let result = prompts::find(&prompts::default_dir(), &name).and_then(|path| {
+                let text = std::fs::read_to_string(&path).unwrap_or_default();
+                let edited = edit_input(&text)?;
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)
+                        .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+                }
+                std::fs::write(&path, edited)
+                    .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+                Ok((path.clone(), prompts::save_version(&path)?))
+            });
+            match result {
+                Ok((path, Some(version))) => println!("Saved {} as v{}", path.display(), version),
+                Ok((path, None)) => println!("{} is unchanged", path.display()),
+                Err(e) => {
+                    eprintln!("[ERROR] {}", e);
+                    std::process::exit(1);
                 }
             }
         }
-        Commands::Schedule {
-            workflow_path,
-            cron,
-            max_runs,
+        Commands::Prompts {
+            command: PromptCommands::Diff { name, from, to },
         } => {
-            let workflow_id = format!(
-                "scheduled_{}",
-                &uuid::Uuid::new_v4().to_string().replace("-", "")[..8]
-            );
-
-            // Validate workflow exists
-            if !std::path::Path::new(&workflow_path).exists() {
-                eprintln!("[ERROR] Workflow file not found: {}", workflow_path);
-                std::process::exit(1);
-            }
-
-            let schedule = WorkflowSchedule {
-                cron_expression: Some(cron.clone()),
-                next_run: None,
-                enabled: true,
-                max_runs,
-                run_count: 0,
-            };
-
-            let mut scheduler = match WorkflowScheduler::new("workflow_states") {
-                Ok(s) => s,
+            let result = prompts::find(&prompts::default_dir(), &name).and_then(|path| {
+                let (a, a_version) = prompts::read(&path, Version::parse(&from)?)?;
+                let (b, b_version) = prompts::read(&path, Version::parse(&to)?)?;
+                Ok((a, a_version, b, b_version))
+            });
+            let (a, a_version, b, b_version) = match result {
+                Ok(texts) => texts,
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to initialize scheduler: {}", e);
+                    eprintln!("[ERROR] {}", e);
                     std::process::exit(1);
                 }
             };
-
-            match scheduler.schedule_workflow(workflow_id.clone(), workflow_path.clone(), schedule)
-            {
-                Ok(_) => {
-                    println!(
-                        "✓ Scheduled workflow '{}' with ID: {}",
-                        workflow_path, workflow_id
-                    );
-                    println!("  Cron expression: {}", cron);
-                    if let Some(max) = max_runs {
-                        println!("  Max runs: {}", max);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("[ERROR] Failed to schedule workflow: {}", e);
-                    std::process::exit(1);
+            let label =
+                |v: Option<u32>| v.map_or("working copy".to_string(), |v| format!("v{}", v));
+            println!("--- {} {}", name, label(a_version));
+            println!("+++ {} {}", name, label(b_version));
+            for line in run_diff::line_diff(&a, &b) {
+                match line {
+                    DiffLine::Same(text) => println!(" {}", text),
+                    DiffLine::Added(text) => println!("+{}", text),
+                    DiffLine::Removed(text) => println!("-{}", text),
                 }
             }
         }
-        Commands::Unschedule { workflow_id } => {
-            let mut scheduler = match WorkflowScheduler::new("workflow_states") {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("[ERROR] Failed to initialize scheduler: {}", e);
+        Commands::Plugin { command } => {
+            handle_plugin_command(command);
+        }
+        Commands::Encryption {
+            command: EncryptionCommands::Keygen { path },
+        } => match encryption::generate_keyfile(std::path::Path::new(&path)) {
+            Ok(()) => {
+                println!("✓ Wrote key to {}", path);
+                println!("Set LAO_ENCRYPTION_KEYFILE={} to encrypt run data", path);
+                println!("Keep a backup: data encrypted with it can't be read without it");
+            }
+            Err(e) => {
+                eprintln!("[ERROR] Failed to write key: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Secret {
+            command: SecretCommands::Set { name, value },
+        } => {
+            let value = value.unwrap_or_else(|| {
+                use std::io::IsTerminal;
+                if std::io::stdin().is_terminal() {
+                    eprint!("Value for {}: ", name);
+                }
+                let mut line = String::new();
+                if let Err(e) = std::io::stdin().read_line(&mut line) {
+                    eprintln!("[ERROR] Failed to read the value: {}", e);
                     std::process::exit(1);
                 }
-            };
-
-            match scheduler.unschedule_workflow(&workflow_id) {
-                Ok(_) => println!("✓ Unscheduled workflow: {}", workflow_id),
+                line.trim_end_matches(['\r', '\n']).to_string()
+            });
+            if value.is_empty() {
+                eprintln!("[ERROR] The value of {} is empty", name);
+                std::process::exit(1);
+            }
+            match SecretStore::open_default().set(&name, &value) {
+                Ok(()) => println!(
+                    "✓ Stored {}; steps read it as ${{{{ secrets.{} }}}}",
+                    name, name
+                ),
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to unschedule workflow: {}", e);
+                    eprintln!("[ERROR] Failed to store {}: {}", name, e);
                     std::process::exit(1);
                 }
             }
         }
-        Commands::ListScheduled => {
-            let scheduler = match WorkflowScheduler::new("workflow_states") {
-                Ok(s) => s,
+        Commands::Secret {
+            command: SecretCommands::List,
+        } => match SecretStore::open_default().load() {
+            Ok(secrets) if secrets.is_empty() => println!("No secrets stored."),
+            Ok(secrets) => {
+                for name in secrets.keys() {
+                    println!("{}", name);
+                }
+            }
+            Err(e) => {
+                eprintln!("[ERROR] Failed to read secrets: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Secret {
+            command: SecretCommands::Rm { name },
+        } => match SecretStore::open_default().remove(&name) {
+            Ok(true) => println!("✓ Removed {}", name),
+            Ok(false) => {
+                eprintln!("[ERROR] No secret named {}", name);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("[ERROR] Failed to remove {}: {}", name, e);
+                std::process::exit(1);
+            }
+        },
+        Commands::Report {
+            command: ReportCommands::LastCrash { bundle },
+        } => {
+            let dir = crash::default_dir();
+            let (path, report) = match crash::last_crash(&dir) {
+                Ok(Some(found)) => found,
+                Ok(None) => {
+                    println!("No crash reports in {}.", dir.display());
+                    return;
+                }
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to initialize scheduler: {}", e);
+                    eprintln!(
+                        "[ERROR] Failed to read crash reports in {}: {}",
+                        dir.display(),
+                        e
+                    );
                     std::process::exit(1);
                 }
             };
-
-            let scheduled = scheduler.list_scheduled_workflows();
-            if scheduled.is_empty() {
-                println!("No scheduled workflows found.");
-            } else {
-                println!("Scheduled workflows:");
-                for (id, workflow) in scheduled {
-                    println!("  {} - {}", id, workflow.workflow_path);
-                    println!("    Schedule: {:?}", workflow.schedule.cron_expression);
-                    println!("    Next run: {:?}", workflow.next_run);
-                    println!("    Enabled: {}", workflow.schedule.enabled);
-                    if let Some(last) = workflow.last_run {
-                        println!("    Last run: {:?}", last);
+            match bundle {
+                Some(output) => match bundle_crash(&path, &report, &output) {
+                    Ok(()) => println!("✓ Wrote crash bundle to {}", output),
+                    Err(e) => {
+                        eprintln!("[ERROR] Failed to write crash bundle: {}", e);
+                        std::process::exit(1);
                     }
-                    println!("    Run count: {}", workflow.schedule.run_count);
-                    println!();
+                },
+                None => {
+                    println!("Report: {}\n", path.display());
+                    print!("{}", report.render());
                 }
             }
         }
-        Commands::Status { workflow_id } => {
-            let scheduler = match WorkflowScheduler::new("workflow_states") {
-                Ok(s) => s,
+    }
+}
+
+// The report plus the crashed run's log files, all passed through crash::redact
+fn bundle_crash(
+    report_path: &std::path::Path,
+    report: &crash::CrashReport,
+    output: &str,
+) -> std::io::Result<()> {
+    let file = std::fs::File::create(output)?;
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    let mut add = |name: &str, content: String| -> std::io::Result<()> {
+        let content = crash::redact(&content);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o600);
+        header.set_cksum();
+        archive.append_data(&mut header, name, content.as_bytes())
+    };
+
+    let report_name = report_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "crash.json".to_string());
+    add(&report_name, std::fs::read_to_string(report_path)?)?;
+    add("crash.txt", report.render())?;
+
+    // Run ids are directory names; anything path-like is left out
+    let run_id = report.context.run_id.as_deref();
+    if let Some(run_id) = run_id.filter(|id| !id.contains(['/', '\\', '.'])) {
+        let run_dir = RunLogConfig::from_env().run_dir(run_id);
+        if let Ok(entries) = std::fs::read_dir(&run_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with("run.log") {
+                    let log = std::fs::read_to_string(entry.path())?
+                        .lines()
+                        .filter_map(|line| encryption::open_line(line).ok())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    add(&format!("run/{}", name), log)?;
+                }
+            }
+        }
+    }
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+// Print diagnostics for `path` each time its contents change, until interrupted
+fn schedule_command(action: ScheduleCommands) {
+    match action {
+        ScheduleCommands::Add {
+            workflow,
+            schedule,
+            max_runs,
+        } => {
+            let path = triggered_workflow(&workflow);
+            let trigger = match Trigger::schedule(&path, &schedule, max_runs) {
+                Ok(trigger) => trigger,
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to initialize scheduler: {}", e);
+                    eprintln!("[ERROR] {}", e);
                     std::process::exit(1);
                 }
             };
-
-            if let Some(id) = workflow_id {
-                match scheduler.get_workflow_history(&id) {
-                    Ok(Some(state)) => {
-                        println!("Workflow: {} ({})", state.workflow_name, state.workflow_id);
-                        println!("Status: {:?}", state.status);
-                        println!("Created: {:?}", state.created_at);
-                        if let Some(started) = state.started_at {
-                            println!("Started: {:?}", started);
-                        }
-                        if let Some(completed) = state.completed_at {
-                            println!("Completed: {:?}", completed);
-                        }
-                        println!(
-                            "Progress: {}/{} steps",
-                            state.current_step, state.total_steps
-                        );
-                        if let Some(error) = &state.error_message {
-                            println!("Error: {}", error);
-                        }
-                    }
-                    Ok(None) => println!("Workflow not found: {}", id),
-                    Err(e) => eprintln!("[ERROR] Failed to load workflow state: {}", e),
-                }
-            } else {
-                let states = scheduler.list_workflow_states();
-                if states.is_empty() {
-                    println!("No workflow states found.");
-                } else {
-                    println!("All workflow states:");
-                    for state in states {
-                        println!(
-                            "  {} - {} ({:?})",
-                            state.workflow_id, state.workflow_name, state.status
-                        );
-                    }
-                }
+            add_trigger(&trigger);
+            println!("✓ Scheduled {} with ID: {}", workflow, trigger.id);
+            if let Some(next) = trigger.next_run() {
+                println!(
+                    "  Next run: {}",
+                    next.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                );
+            }
+            if let Some(max) = max_runs {
+                println!("  Max runs: {}", max);
             }
         }
-        Commands::Cleanup { max_age_hours } => {
-            let mut scheduler = match WorkflowScheduler::new("workflow_states") {
-                Ok(s) => s,
+        ScheduleCommands::List => list_triggers(true),
+        ScheduleCommands::Rm { id } => remove_trigger(&id),
+    }
+}
+
+fn watch_command(action: WatchCommands) {
+    match action {
+        WatchCommands::Add {
+            workflow,
+            dir,
+            pattern,
+        } => {
+            let path = triggered_workflow(&workflow);
+            let dir = PathUtils::normalize_str(&dir);
+            let dir = std::fs::canonicalize(&dir).unwrap_or_else(|_| dir.into());
+            let trigger = match Trigger::watch(&path, &dir, pattern.as_deref()) {
+                Ok(trigger) => trigger,
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to initialize scheduler: {}", e);
+                    eprintln!("[ERROR] {}", e);
                     std::process::exit(1);
                 }
             };
+            add_trigger(&trigger);
+            println!(
+                "✓ Watching {} for {} with ID: {}",
+                dir.display(),
+                workflow,
+                trigger.id
+            );
+            println!("  Each new file runs the workflow with its path as ${{file}}");
+        }
+        WatchCommands::List => list_triggers(false),
+        WatchCommands::Rm { id } => remove_trigger(&id),
+    }
+}
 
-            match scheduler.cleanup_old_states(max_age_hours) {
-                Ok(count) => println!("✓ Cleaned up {} old workflow states", count),
-                Err(e) => eprintln!("[ERROR] Failed to cleanup states: {}", e),
-            }
+// The absolute path of a valid workflow, for the daemon to find it from anywhere
+fn triggered_workflow(path: &str) -> String {
+    if !std::path::Path::new(path).exists() {
+        eprintln!("[ERROR] Workflow file not found: {}", path);
+        std::process::exit(1);
+    }
+    match ExecutionPlan::load(path, &plugins::shared().snapshot()) {
+        Ok(plan) if !plan.is_valid() => {
+            eprintln!("[ERROR] Workflow is invalid: {}", plan.error_summary());
+            std::process::exit(1);
         }
-        Commands::Daemon { interval } => {
-            println!("Starting LAO workflow scheduler daemon...");
-            println!("Check interval: {} seconds", interval);
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(1);
+        }
+    }
+    std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
 
-            let mut scheduler = match WorkflowScheduler::new("workflow_states") {
-                Ok(s) => s,
+fn add_trigger(trigger: &Trigger) {
+    if let Err(e) = TriggerStore::open_default().add(trigger.clone()) {
+        eprintln!("[ERROR] Failed to save the trigger: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn remove_trigger(id: &str) {
+    let removed = TriggerStore::open_default().remove(id).and_then(|removed| {
+        if removed {
+            return Ok(true);
+        }
+        scheduler::remove_legacy_schedule(scheduler::default_dir(), id)
+            .map_err(|e| format!("Failed to remove the old schedule: {}", e))
+    });
+    match removed {
+        Ok(true) => println!("✓ Removed {}", id),
+        Ok(false) => {
+            eprintln!("[ERROR] No schedule or watch with ID {}", id);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn list_triggers(schedules: bool) {
+    let triggers = match TriggerStore::open_default().load() {
+        Ok(triggers) => triggers,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(1);
+        }
+    };
+    let triggers: Vec<&Trigger> = triggers
+        .iter()
+        .filter(|t| matches!(t.on, TriggerOn::Schedule { .. }) == schedules)
+        .collect();
+    let (kind, empty) = if schedules {
+        ("Schedules", "No scheduled workflows found.")
+    } else {
+        ("Watches", "No watched directories found.")
+    };
+    if schedules {
+        warn_legacy_schedules();
+    }
+    if triggers.is_empty() {
+        println!("{}", empty);
+        return;
+    }
+    let time = |t: chrono::DateTime<chrono::Utc>| {
+        t.with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    };
+    println!("{}:", kind);
+    for trigger in triggers {
+        println!("  {} - {}", trigger.id, trigger.workflow);
+        println!("    On: {}", trigger.describe());
+        if let Some(next) = trigger.next_run() {
+            println!("    Next run: {}", time(next));
+        }
+        if let Some(last) = trigger.last_run {
+            println!("    Last run: {}", time(last));
+        }
+        match trigger.max_runs {
+            Some(max) => println!("    Run count: {}/{}", trigger.runs, max),
+            None => println!("    Run count: {}", trigger.runs),
+        }
+        println!();
+    }
+}
+
+// Schedules made before they moved to triggers don't run, and can't be moved
+// over as the workflow they ran wasn't kept
+fn warn_legacy_schedules() {
+    let legacy = match scheduler::legacy_schedules(scheduler::default_dir()) {
+        Ok(legacy) => legacy,
+        Err(e) => {
+            eprintln!("[WARN] Failed to read workflow states: {}", e);
+            return;
+        }
+    };
+    if legacy.is_empty() {
+        return;
+    }
+    eprintln!("[WARN] These schedules from an older version of LAO no longer run. Add them again with `lao schedule add <workflow> <schedule>`, then remove the old ones with `lao schedule rm <id>`:");
+    for schedule in legacy {
+        eprintln!("  {} - {}", schedule.id, schedule.cron_expression);
+    }
+}
+
+fn daemon_service(action: DaemonCommands) {
+    let manager = match service::Manager::current() {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(1);
+        }
+    };
+    match action {
+        DaemonCommands::Install {
+            args,
+            no_start,
+            dry_run,
+        } => {
+            let spec = match service::ServiceSpec::current(args) {
+                Ok(spec) => spec,
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to initialize scheduler: {}", e);
+                    eprintln!("[ERROR] {}", e);
                     std::process::exit(1);
                 }
             };
+            if dry_run {
+                let unit = manager
+                    .unit_path()
+                    .unwrap_or_else(|_| std::path::PathBuf::from("<unit>"));
+                println!("# {}", unit.display());
+                print!("{}", manager.render(&spec));
+                println!();
+                for command in manager.install_commands(&unit, !no_start) {
+                    println!("$ {}", command.join(" "));
+                }
+                return;
+            }
+            match service::install(manager, &spec, !no_start) {
+                Ok(unit) => {
+                    println!(
+                        "✓ Installed the daemon as a {} service: {}",
+                        manager.name(),
+                        unit.display()
+                    );
+                    println!("  Runs in:  {}", spec.working_dir.display());
+                    println!("  Output:   {}", spec.stdout_log().display());
+                    println!("  Errors:   {}", spec.stderr_log().display());
+                    if no_start {
+                        println!("It starts at the next login.");
+                    }
+                    if manager == service::Manager::Systemd {
+                        println!("To keep it running while you're logged out: loginctl enable-linger $USER");
+                    }
+                }
+                Err(e) => {
+                    eprintln!("[ERROR] Failed to install the daemon service: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        DaemonCommands::Uninstall => match service::uninstall(manager) {
+            Ok(failed) => {
+                for e in failed {
+                    eprintln!("[WARN] {}", e);
+                }
+                println!("✓ Removed the daemon's {} service", manager.name());
+            }
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                std::process::exit(1);
+            }
+        },
+        DaemonCommands::Status => match service::status(manager) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
 
-            loop {
-                let due_workflows = scheduler.get_due_workflows();
-                if !due_workflows.is_empty() {
-                    println!("Found {} due workflows", due_workflows.len());
-                    for workflow_id in due_workflows {
-                        // In a real implementation, you'd execute the workflow here
-                        println!("Would execute workflow: {}", workflow_id);
-                        let _ = scheduler.update_workflow_run(&workflow_id);
+fn watch_workflow(path: &str, service: &mut ValidationService) -> ! {
+    println!("Watching {} (Ctrl+C to stop)", path);
+    let mut last = None;
+    let mut failed = None;
+    loop {
+        // Picks up plugin reloads as well as edits to the file
+        let registry = plugins::shared().snapshot();
+        match service.update_file(std::path::Path::new(path), &registry) {
+            Ok(diagnostics) => {
+                failed = None;
+                if !last.as_ref().is_some_and(|l| Arc::ptr_eq(l, &diagnostics)) {
+                    println!("[{}]", chrono::Local::now().format("%H:%M:%S"));
+                    if diagnostics.is_empty() {
+                        println!("Validation passed: all steps and plugins available.");
+                    }
+                    for d in diagnostics.iter() {
+                        println!("{}:{}", path, d);
                     }
+                    last = Some(diagnostics);
+                }
+            }
+            Err(e) => {
+                if failed.as_ref() != Some(&e) {
+                    eprintln!("Failed to load workflow: {}", e);
+                    failed = Some(e);
+                    last = None;
                 }
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+}
+
+// Asks on the terminal how a run paused at a breakpoint goes on
+struct TerminalPause;
 
-                std::thread::sleep(std::time::Duration::from_secs(interval));
+impl PauseHandler for TerminalPause {
+    fn paused(&self, step: &PausedStep) -> Resume {
+        use std::io::{BufRead, Write};
+        eprintln!("[PAUSED] Before {} ({}). Input:", step.step_id, step.plugin);
+        eprintln!("{}", step.input);
+        let stdin = std::io::stdin();
+        loop {
+            eprint!("[c]ontinue, [e]dit input, [a]bort? ");
+            std::io::stderr().flush().ok();
+            let mut answer = String::new();
+            // Nobody at the terminal: go on as if there was no breakpoint
+            if stdin.lock().read_line(&mut answer).unwrap_or(0) == 0 {
+                return Resume::Continue;
+            }
+            match answer.trim() {
+                "" | "c" | "continue" => return Resume::Continue,
+                "a" | "abort" => return Resume::Abort,
+                "e" | "edit" => match edit_input(&step.input) {
+                    Ok(input) => return Resume::Replace(input),
+                    Err(e) => eprintln!("[ERROR] {}", e),
+                },
+                _ => {}
             }
         }
-        Commands::Plugin { command } => {
-            handle_plugin_command(command);
+    }
+}
+
+// The new input from $VISUAL or $EDITOR, or typed up to a line with only "."
+// Width of the lane timeline in characters
+const TIMELINE_WIDTH: usize = 60;
+
+/// One row per resource lane, each column a slice of the batch's wall time
+/// marked with the item that held the lane (1-9, then a-z)
+fn print_batch_timeline(report: &BatchReport) {
+    let marker = |item: usize| {
+        char::from_digit((item as u32 + 1) % 36, 36)
+            .filter(|_| item < 35)
+            .unwrap_or('#')
+    };
+    let wall = report.wall_ms.max(1);
+    println!(
+        "Timeline ({}ms, one column = {}ms):",
+        wall,
+        wall.div_ceil(TIMELINE_WIDTH as u64)
+    );
+    for resource in Resource::ALL {
+        let row: String = (0..TIMELINE_WIDTH)
+            .map(|column| {
+                let at = wall * column as u64 / TIMELINE_WIDTH as u64;
+                report
+                    .spans
+                    .iter()
+                    .find(|s| s.resource == resource && s.start_ms <= at && at < s.end_ms)
+                    .map_or('·', |s| marker(s.item))
+            })
+            .collect();
+        let busy = report.busy_ms.get(&resource).copied().unwrap_or_default();
+        println!("  {}  |{}|  busy {}ms", resource.as_str(), row, busy);
+    }
+    println!();
+    for (index, item) in report.items.iter().enumerate() {
+        println!(
+            "  {} {:<40} {:<8} {}ms",
+            marker(index),
+            item.input,
+            item.status,
+            item.duration_ms
+        );
+    }
+    let steps: u64 = report.busy_ms.values().sum();
+    println!();
+    println!(
+        "Overlap: {}ms ({:.0}% of the wall time both CPU and GPU were busy)",
+        report.overlap_ms,
+        report.overlap_ratio() * 100.0
+    );
+    println!(
+        "Steps ran for {}ms in {}ms of wall time",
+        steps, report.wall_ms
+    );
+}
+
+fn edit_input(input: &str) -> Result<String, String> {
+    use std::io::BufRead;
+    if let Some(editor) = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+    {
+        let file = std::env::temp_dir().join(format!("lao-input-{}.txt", std::process::id()));
+        std::fs::write(&file, input).map_err(|e| e.to_string())?;
+        let status = std::process::Command::new(&editor)
+            .arg(&file)
+            .status()
+            .map_err(|e| format!("{}: {}", editor, e))?;
+        let edited = std::fs::read_to_string(&file).map_err(|e| e.to_string());
+        std::fs::remove_file(&file).ok();
+        if !status.success() {
+            return Err(format!("{} exited with {}", editor, status));
+        }
+        return edited;
+    }
+    eprintln!("Type the new input, then a line with only \".\":");
+    let mut lines = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line == "." {
+            break;
+        }
+        lines.push(line);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn parse_labels(labels: &[String]) -> Result<Vec<(String, String)>, String> {
+    labels.iter().map(|l| run_labels::parse_label(l)).collect()
+}
+
+fn parse_params(params: &[String]) -> Result<HashMap<String, String>, String> {
+    params
+        .iter()
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) if !name.trim().is_empty() => {
+                Ok((name.trim().to_string(), value.to_string()))
+            }
+            _ => Err(format!("Invalid param '{}', expected NAME=VALUE", param)),
+        })
+        .collect()
+}
+
+#[cfg(feature = "hotkeys")]
+fn serve_hotkeys(file: &std::path::Path) {
+    match lao_orchestrator_core::hotkeys::serve(file) {
+        Ok(_) => println!("Listening for the global hotkeys in {}", file.display()),
+        Err(e) => {
+            eprintln!(
+                "[ERROR] Failed to register hotkeys from {}: {}",
+                file.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "hotkeys"))]
+fn serve_hotkeys(_file: &std::path::Path) {
+    eprintln!(
+        "[ERROR] This build has no hotkey support; rebuild with `cargo build --features hotkeys`"
+    );
+    std::process::exit(1);
+}
+
+#[cfg(feature = "tray")]
+fn serve_tray(file: &std::path::Path) {
+    match lao_orchestrator_core::tray::serve(file, &run_diff::default_dir()) {
+        Ok(()) => println!("Showing the tray icon"),
+        Err(e) => {
+            eprintln!("[ERROR] Failed to show the tray icon: {}", e);
+            std::process::exit(1);
         }
     }
 }
 
+#[cfg(not(feature = "tray"))]
+fn serve_tray(_file: &std::path::Path) {
+    eprintln!("[ERROR] This build has no tray support; rebuild with `cargo build --features tray`");
+    std::process::exit(1);
+}
+
+// Ages like "90s", "30m", "24h" or "7d"
+fn parse_age(value: &str) -> Result<chrono::Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}', expected e.g. 24h or 7d", value))?;
+    match unit {
+        "s" => Ok(chrono::Duration::seconds(number)),
+        "m" => Ok(chrono::Duration::minutes(number)),
+        "h" | "" => Ok(chrono::Duration::hours(number)),
+        "d" => Ok(chrono::Duration::days(number)),
+        "w" => Ok(chrono::Duration::weeks(number)),
+        _ => Err(format!(
+            "Invalid duration unit '{}', expected s, m, h, d or w",
+            unit
+        )),
+    }
+}
+
 fn handle_plugin_command(command: PluginCommands) {
     match command {
         PluginCommands::List => match PluginManager::new("plugins/") {
@@ -1027,6 +3691,34 @@ fn handle_plugin_command(command: PluginCommands) {
                 }
             }
         }
+        PluginCommands::Fuzz {
+            library,
+            timeout,
+            json,
+        } => {
+            let exe = match std::env::current_exe() {
+                Ok(exe) => exe,
+                Err(e) => {
+                    eprintln!("[ERROR] Cannot locate the lao executable: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let mut prober = plugin_fuzz::Prober::new(exe, &["plugin", "fuzz-probe"]);
+            prober.timeout = std::time::Duration::from_secs(timeout);
+            let report = plugin_fuzz::fuzz_plugin(std::path::Path::new(&library), &prober);
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).unwrap_or_default()
+                );
+            } else {
+                print!("{}", report.render());
+            }
+            if !report.passed() {
+                std::process::exit(1);
+            }
+        }
+        PluginCommands::FuzzProbe => {}
         PluginCommands::RefreshMarketplace => match PluginManager::new("plugins/") {
             Ok(mut manager) => {
                 let rt = tokio::runtime::Runtime::new().unwrap();
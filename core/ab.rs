@@ -0,0 +1,342 @@
+// A/B comparison of prompts for one step, `lao ab`.
+use crate::evaluation::EvaluateSpec;
+use crate::plugins::PluginRegistry;
+use crate::{prompts, run_labels, Workflow, WorkflowStep};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+pub const VARIANT_LABEL: &str = "ab_variant";
+
+#[derive(Debug, Clone)]
+pub struct AbSpec {
+    /// Step id (`step2`) or display label of the step whose prompt varies
+    pub step: String,
+    /// Prompt files or inline prompts
+    pub variants: Vec<String>,
+    /// Runs per variant
+    pub runs: usize,
+    /// Judge plugin, instead of the step's `evaluate:` judge
+    pub judge: Option<String>,
+}
+
+/// Results of one variant's runs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantReport {
+    pub variant: String,
+    pub runs: usize,
+    /// Runs where the workflow or the step failed
+    pub failures: usize,
+    /// Mean judge score of each scored run
+    pub scores: Vec<f64>,
+    /// Mean per criterion over the scored runs
+    pub criteria: BTreeMap<String, f64>,
+    pub durations_ms: Vec<u64>,
+    /// The first failure, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl VariantReport {
+    pub fn mean_score(&self) -> Option<f64> {
+        (!self.scores.is_empty())
+            .then(|| self.scores.iter().sum::<f64>() / self.scores.len() as f64)
+    }
+
+    pub fn mean_ms(&self) -> u64 {
+        self.durations_ms.iter().sum::<u64>() / self.durations_ms.len().max(1) as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AbReport {
+    pub workflow: String,
+    pub step_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judge: Option<String>,
+    /// In the order given
+    pub variants: Vec<VariantReport>,
+}
+
+impl AbReport {
+    /// The variant with the best mean score, or the fastest when unjudged
+    pub fn best(&self) -> Option<&VariantReport> {
+        let succeeded = self.variants.iter().filter(|v| v.failures < v.runs);
+        match self.judge {
+            Some(_) => succeeded
+                .filter_map(|v| v.mean_score().map(|score| (score, v)))
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, v)| v),
+            None => succeeded.min_by_key(|v| v.mean_ms()),
+        }
+    }
+}
+
+/// Index of the step `step` names, by id or display label
+fn find_step(workflow: &Workflow, step: &str) -> Result<usize, String> {
+    workflow
+        .steps
+        .iter()
+        .enumerate()
+        .position(|(index, s)| {
+            s.id_at(index) == step
+                || s.display
+                    .as_ref()
+                    .and_then(|d| d.label.as_deref())
+                    .is_some_and(|label| label.eq_ignore_ascii_case(step))
+        })
+        .ok_or_else(|| format!("No step '{}' in {}", step, workflow.workflow))
+}
+
+/// Give `step` the prompt of `variant`
+fn set_prompt(step: &mut WorkflowStep, variant: &str) -> Result<(), String> {
+    let text = if prompts::is_reference(variant) {
+        step.prompt_file = Some(variant.to_string());
+        prompts::resolve(variant)?
+    } else {
+        step.prompt_file = None;
+        variant.to_string()
+    };
+    if !step.params.is_mapping() {
+        step.params = serde_yaml::Value::Mapping(Default::default());
+    }
+    if let Some(params) = step.params.as_mapping_mut() {
+        params.insert("prompt".into(), text.into());
+    }
+    Ok(())
+}
+
+/// Run the workflow at `path` `spec.runs` times per variant against
+/// `registry`; `progress` is called before each run with the variant and the
+/// run's number
+pub fn run(
+    path: &str,
+    spec: &AbSpec,
+    registry: &PluginRegistry,
+    progress: &mut dyn FnMut(&str, usize),
+) -> Result<AbReport, String> {
+    if spec.variants.len() < 2 {
+        return Err("Give at least two variants to compare".to_string());
+    }
+    if spec.runs == 0 {
+        return Err("Runs per variant must be at least 1".to_string());
+    }
+    let workflow = crate::load_workflow_yaml(path)?;
+    let index = find_step(&workflow, &spec.step)?;
+    let step_id = workflow.steps[index].id_at(index);
+    let own = workflow.steps[index].evaluate.clone();
+    let evaluate = match &spec.judge {
+        Some(judge) => Some(EvaluateSpec {
+            judge: judge.clone(),
+            criteria: own.map(|e| e.criteria).unwrap_or_default(),
+        }),
+        None => own,
+    };
+    if let Some(problem) = evaluate.as_ref().and_then(|e| e.problem(registry)) {
+        return Err(problem);
+    }
+
+    let dir = std::env::temp_dir().join(format!("lao_ab_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let result = spec
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(number, variant)| {
+            let mut variant_workflow = workflow.clone();
+            let step = &mut variant_workflow.steps[index];
+            set_prompt(step, variant)?;
+            // Scored here instead, and asked again every run
+            step.evaluate = None;
+            step.memoize = Some(false);
+            step.cache_key = None;
+            let file = dir.join(format!("variant{}.yaml", number + 1));
+            let yaml = serde_yaml::to_string(&variant_workflow).map_err(|e| e.to_string())?;
+            std::fs::write(&file, yaml)
+                .map_err(|e| format!("Cannot write {}: {}", file.display(), e))?;
+            let labels = run_labels::current()
+                .with_label(VARIANT_LABEL, variant.as_str())
+                .with_tag("ab");
+
+            let mut report = VariantReport {
+                variant: variant.clone(),
+                runs: spec.runs,
+                failures: 0,
+                scores: Vec::new(),
+                criteria: BTreeMap::new(),
+                durations_ms: Vec::new(),
+                error: None,
+            };
+            let mut criteria: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+            for run in 1..=spec.runs {
+                progress(variant, run);
+                let start = Instant::now();
+                let logs = run_labels::with_labels(labels.clone(), || {
+                    crate::run_workflow_with_registry(
+                        &file.to_string_lossy(),
+                        HashMap::new(),
+                        registry,
+                    )
+                });
+                report.durations_ms.push(start.elapsed().as_millis() as u64);
+                let output = logs.and_then(|logs| {
+                    let log = logs.into_iter().find(|log| log.step_id == step_id);
+                    match log {
+                        Some(log) => match (log.output, log.error) {
+                            (_, Some(error)) => Err(format!("{}: {}", step_id, error)),
+                            (output, None) => Ok(output.unwrap_or_default()),
+                        },
+                        None => Err(format!("{} did not run", step_id)),
+                    }
+                });
+                let scored = output.and_then(|output| match &evaluate {
+                    Some(evaluate) => {
+                        let mut call = |name: &str, input: &str| {
+                            let plugin = registry
+                                .get(name)
+                                .ok_or_else(|| format!("Plugin '{}' not found", name))?;
+                            crate::exec_plugin(plugin, input)
+                        };
+                        evaluate.score(&output, &mut call).map(Some)
+                    }
+                    None => Ok(None),
+                });
+                match scored {
+                    Ok(Some(scores)) => {
+                        let mean = scores.values().sum::<f64>() / scores.len().max(1) as f64;
+                        report.scores.push(mean);
+                        for (name, score) in scores {
+                            criteria.entry(name).or_default().push(score);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        report.failures += 1;
+                        report.error.get_or_insert(e);
+                    }
+                }
+            }
+            report.criteria = criteria
+                .into_iter()
+                .map(|(name, scores)| (name, scores.iter().sum::<f64>() / scores.len() as f64))
+                .collect();
+            Ok(report)
+        })
+        .collect::<Result<Vec<_>, String>>();
+    std::fs::remove_dir_all(&dir).ok();
+
+    Ok(AbReport {
+        workflow: workflow.workflow.clone(),
+        step_id,
+        judge: evaluate.map(|e| e.judge),
+        variants: result?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_plugin, mock_registry, CannedResponse, MockFixtures, MockSpec};
+
+    struct Ab {
+        dir: std::path::PathBuf,
+        path: String,
+        registry: PluginRegistry,
+        spec: AbSpec,
+    }
+
+    impl Ab {
+        // A terse prompt file against an inline detailed one, judged 5 and 8
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("lao_ab_test_{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let prompt = dir.join("terse.md");
+            std::fs::write(&prompt, "Terse: ${step1}").unwrap();
+            let path = dir.join("ab.yaml");
+            std::fs::write(
+                &path,
+                "workflow: ab\nsteps:\n  - run: EchoPlugin\n    input: hi\n  - run: OllamaPlugin\n    prompt: \"Detailed: ${step1}\"\n    display:\n      label: Summarize\n    evaluate:\n      judge: Judge\n",
+            )
+            .unwrap();
+            let path = path.to_str().unwrap().to_string();
+            let workflow = crate::load_workflow_yaml(&path).unwrap();
+            let mut registry = mock_registry(&workflow, &MockFixtures::default());
+            registry.register_plugin(mock_plugin(
+                "Judge",
+                MockSpec {
+                    responses: vec![CannedResponse {
+                        input_contains: Some("Detailed".to_string()),
+                        output: Some("8".to_string()),
+                        error: None,
+                    }],
+                    output: Some("5".to_string()),
+                    ..Default::default()
+                },
+            ));
+            let spec = AbSpec {
+                step: "summarize".to_string(),
+                variants: vec![
+                    prompt.to_str().unwrap().to_string(),
+                    "Detailed: ${step1}".to_string(),
+                ],
+                runs: 2,
+                judge: None,
+            };
+            Self {
+                dir,
+                path,
+                registry,
+                spec,
+            }
+        }
+
+        fn run(&self, spec: &AbSpec) -> Result<AbReport, String> {
+            run(&self.path, spec, &self.registry, &mut |_, _| {})
+        }
+    }
+
+    impl Drop for Ab {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_each_variant_runs_the_given_number_of_times() {
+        let ab = Ab::new();
+        let mut calls = Vec::new();
+        let report = run(&ab.path, &ab.spec, &ab.registry, &mut |variant, run| {
+            calls.push((variant.to_string(), run))
+        })
+        .unwrap();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(report.step_id, "step2");
+        assert_eq!(report.variants[0].durations_ms.len(), 2);
+    }
+
+    #[test]
+    fn test_variants_are_scored_by_the_steps_judge() {
+        let ab = Ab::new();
+        let report = ab.run(&ab.spec).unwrap();
+        assert_eq!(report.judge.as_deref(), Some("Judge"));
+        assert_eq!(report.variants[0].scores, [5.0, 5.0]);
+        assert_eq!(report.variants[1].mean_score(), Some(8.0));
+        assert_eq!(report.variants[1].criteria["overall"], 8.0);
+        assert_eq!(report.best().unwrap().variant, "Detailed: ${step1}");
+    }
+
+    #[test]
+    fn test_unknown_judge_or_step_is_an_error() {
+        let ab = Ab::new();
+        let missing = AbSpec {
+            judge: Some("Nobody".to_string()),
+            ..ab.spec.clone()
+        };
+        assert!(ab.run(&missing).is_err());
+        let unknown = AbSpec {
+            step: "step9".to_string(),
+            ..ab.spec.clone()
+        };
+        assert!(ab.run(&unknown).is_err());
+    }
+}
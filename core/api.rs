@@ -0,0 +1,586 @@
+// Headless HTTP API of `lao serve`.
+// Endpoints and auth are listed in docs/cli.md (HTTP API).
+use crate::cancel::CancelToken;
+use crate::channel::Overflow;
+use crate::logging::{self, LogEvent};
+use crate::orchestrator::{Orchestrator, OrchestratorError, RunEvent, RunHandle};
+use crate::plan::ExecutionPlan;
+use crate::run_labels;
+use crate::webhook::{read_request, write_response, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Finished runs kept for `GET /runs`; the oldest are forgotten first
+pub const MAX_RUNS: usize = 100;
+// Log events an event stream buffers for a slow client
+const EVENT_STREAM_CAPACITY: usize = 1024;
+const EVENT_POLL: Duration = Duration::from_millis(200);
+const EVENT_KEEPALIVE: Duration = Duration::from_secs(15);
+
+struct ApiRun {
+    run_id: String,
+    workflow: String,
+    started: DateTime<Utc>,
+    events: Vec<RunEvent>,
+    // None while the run is going
+    status: Option<String>,
+    error: Option<String>,
+    cancel: CancelToken,
+}
+
+struct Server {
+    workflows_dir: PathBuf,
+    token: String,
+    orchestrator: Orchestrator,
+    runs: Mutex<Vec<ApiRun>>,
+}
+
+#[derive(Deserialize)]
+struct StartRequest {
+    workflow: String,
+    #[serde(default)]
+    params: HashMap<String, String>,
+}
+
+/// Serve the API for workflows in `workflows_dir` on a background thread.
+/// Returns the bound address, useful when `addr` asked for port 0.
+pub fn serve<A: ToSocketAddrs>(
+    addr: A,
+    workflows_dir: impl Into<PathBuf>,
+    token: &str,
+    orchestrator: Orchestrator,
+) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let server = Arc::new(Server {
+        workflows_dir: workflows_dir.into(),
+        token: token.to_string(),
+        orchestrator,
+        runs: Mutex::new(Vec::new()),
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let server = server.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &server) {
+                    log::warn!("api request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+/// A token for `lao serve` when none is given
+pub fn generate_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn handle_connection(mut stream: TcpStream, server: &Arc<Server>) -> std::io::Result<()> {
+    let request = match read_request(&stream)? {
+        Ok(request) => request,
+        Err(rejected) => return write_response(&mut stream, &rejected),
+    };
+    let (path, query) = request
+        .path
+        .split_once('?')
+        .unwrap_or((request.path.as_str(), ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let response = match (request.method.as_str(), segments.as_slice()) {
+        _ if !authorized(&request, query, &server.token) => {
+            HttpResponse::text(401, "missing or wrong token\n")
+        }
+        ("GET", ["plugins"]) => list_plugins(server),
+        ("GET", ["workflows"]) => list_workflows(server),
+        ("GET", ["workflows", name]) => match workflow_path(server, name) {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(yaml) => HttpResponse {
+                    status: 200,
+                    content_type: "application/yaml".to_string(),
+                    body: yaml,
+                },
+                Err(_) => HttpResponse::text(404, format!("no workflow named {}\n", name)),
+            },
+            None => HttpResponse::text(404, "not found\n"),
+        },
+        ("POST", ["workflows", name]) => save_workflow(server, name, &request.body),
+        ("POST", ["validate"]) => {
+            let (errors, warnings) = check(server, &request.body);
+            HttpResponse::json(
+                200,
+                json!({ "valid": errors.is_empty(), "errors": errors, "warnings": warnings }),
+            )
+        }
+        ("POST", ["runs"]) => match serde_json::from_slice::<StartRequest>(&request.body) {
+            Ok(start) => start_run(server, start),
+            Err(e) => HttpResponse::text(400, format!("invalid run request: {}\n", e)),
+        },
+        ("GET", ["runs"]) => list_runs(server),
+        ("GET", ["runs", id]) => with_run(server, id, |run| HttpResponse::json(200, describe(run)))
+            .unwrap_or_else(|missing| missing),
+        ("GET", ["runs", id, "events"]) => {
+            if with_run(server, id, |_| ()).is_err() {
+                HttpResponse::text(404, format!("no run {}\n", id))
+            } else {
+                let resume = request
+                    .headers
+                    .get("last-event-id")
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .map_or(0, |last| last + 1);
+                return stream_events(stream, server, id, resume);
+            }
+        }
+        ("GET", ["runs", id, "steps", step, "output"]) => step_output(server, id, step),
+        ("POST", ["runs", id, "cancel"]) => with_run(server, id, |run| {
+            run.cancel.cancel();
+            HttpResponse::json(202, json!({ "run_id": run.run_id }))
+        })
+        .unwrap_or_else(|missing| missing),
+        (_, ["plugins"] | ["workflows"] | ["workflows", _] | ["validate"] | ["runs"]) => {
+            HttpResponse::text(405, "method not allowed\n")
+        }
+        _ => HttpResponse::text(404, "not found\n"),
+    };
+    write_response(&mut stream, &response)
+}
+
+fn authorized(request: &HttpRequest, query: &str, token: &str) -> bool {
+    let header = request
+        .headers
+        .get("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let param = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="));
+    header.or(param).is_some_and(|t| t == token)
+}
+
+// A plain file stem, as for webhooks
+fn workflow_path(server: &Server, name: &str) -> Option<PathBuf> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    valid.then(|| server.workflows_dir.join(format!("{}.yaml", name)))
+}
+
+fn list_plugins(server: &Server) -> HttpResponse {
+    let registry = server.orchestrator.registry();
+    let mut plugins = registry.list_plugins();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    HttpResponse::json(200, json!(plugins))
+}
+
+fn list_workflows(server: &Server) -> HttpResponse {
+    let mut names: Vec<String> = fs::read_dir(&server.workflows_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yaml"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        // Only names requests can use
+        .filter(|name| workflow_path(server, name).is_some())
+        .collect();
+    names.sort();
+    HttpResponse::json(200, json!(names))
+}
+
+// The plan's errors and warnings for the YAML in `body`
+fn check(server: &Server, body: &[u8]) -> (Vec<String>, Vec<String>) {
+    let yaml = String::from_utf8_lossy(body);
+    match crate::parse_workflow_yaml(&yaml, &server.workflows_dir) {
+        Ok(workflow) => {
+            let plan = ExecutionPlan::compile(workflow, &server.orchestrator.registry());
+            (
+                plan.errors.iter().map(|e| e.to_string()).collect(),
+                plan.warnings.iter().map(|w| w.to_string()).collect(),
+            )
+        }
+        Err(e) => (vec![e], Vec::new()),
+    }
+}
+
+fn save_workflow(server: &Server, name: &str, body: &[u8]) -> HttpResponse {
+    let Some(path) = workflow_path(server, name) else {
+        return HttpResponse::text(404, "not found\n");
+    };
+    let (errors, warnings) = check(server, body);
+    if !errors.is_empty() {
+        return HttpResponse::json(422, json!({ "errors": errors, "warnings": warnings }));
+    }
+    let existed = path.exists();
+    if let Err(e) = fs::create_dir_all(&server.workflows_dir).and_then(|_| fs::write(&path, body)) {
+        return HttpResponse::text(500, format!("cannot save {}: {}\n", path.display(), e));
+    }
+    logging::info("workflow saved through the API")
+        .field("path", path.display().to_string())
+        .emit();
+    HttpResponse::json(
+        if existed { 200 } else { 201 },
+        json!({ "name": name, "warnings": warnings }),
+    )
+}
+
+fn start_run(server: &Arc<Server>, start: StartRequest) -> HttpResponse {
+    let Some(path) = workflow_path(server, &start.workflow).filter(|p| p.exists()) else {
+        return HttpResponse::text(404, format!("no workflow named {}\n", start.workflow));
+    };
+    crate::metrics::global().record_trigger("api");
+    let handle = run_labels::with_trigger("api", || {
+        server
+            .orchestrator
+            .start(&path.to_string_lossy(), start.params)
+    });
+    let (started, run_id) = mpsc::channel();
+    let follower = server.clone();
+    thread::spawn(move || follow(&follower, handle, start.workflow, started));
+    match run_id.recv() {
+        Ok(Ok(run_id)) => HttpResponse::json(201, json!({ "run_id": run_id })),
+        // Nothing ran: the workflow or its params are at fault
+        Ok(Err(OrchestratorError::Invalid(errors))) => {
+            HttpResponse::json(422, json!({ "errors": errors }))
+        }
+        Ok(Err(e)) => HttpResponse::json(422, json!({ "errors": [e.to_string()] })),
+        Err(_) => HttpResponse::text(500, "run thread stopped\n"),
+    }
+}
+
+// Records the run's events until it ends; `started` gets its id, or why it never started
+fn follow(
+    server: &Server,
+    handle: RunHandle,
+    workflow: String,
+    started: mpsc::Sender<Result<String, OrchestratorError>>,
+) {
+    let mut run_id = None;
+    while let Ok(event) = handle.events().recv() {
+        if let RunEvent::RunStarted { run_id: id, .. } = &event {
+            let mut runs = server.runs.lock().unwrap_or_else(|e| e.into_inner());
+            runs.push(ApiRun {
+                run_id: id.clone(),
+                workflow: workflow.clone(),
+                started: Utc::now(),
+                events: Vec::new(),
+                status: None,
+                error: None,
+                cancel: handle.cancel_token(),
+            });
+            forget_old(&mut runs);
+            run_id = Some(id.clone());
+            let _ = started.send(Ok(id.clone()));
+        }
+        if let Some(id) = &run_id {
+            update(server, id, |run| run.events.push(event));
+        }
+    }
+    let result = handle.wait();
+    let Some(id) = run_id else {
+        let _ = started.send(Err(result.err().unwrap_or_else(|| {
+            OrchestratorError::Run("the run ended before it started".to_string())
+        })));
+        return;
+    };
+    update(server, &id, |run| {
+        run.status = Some(match &result {
+            Ok(outcome) => outcome.status.clone(),
+            Err(OrchestratorError::Cancelled) => "cancelled".to_string(),
+            Err(_) => "error".to_string(),
+        });
+        run.error = result.as_ref().err().map(|e| e.to_string());
+    });
+}
+
+fn forget_old(runs: &mut Vec<ApiRun>) {
+    while runs.iter().filter(|r| r.status.is_some()).count() > MAX_RUNS {
+        if let Some(oldest) = runs.iter().position(|r| r.status.is_some()) {
+            runs.remove(oldest);
+        }
+    }
+}
+
+fn update(server: &Server, run_id: &str, f: impl FnOnce(&mut ApiRun)) {
+    let mut runs = server.runs.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(run) = runs.iter_mut().find(|r| r.run_id == run_id) {
+        f(run);
+    }
+}
+
+fn with_run<T>(
+    server: &Server,
+    run_id: &str,
+    f: impl FnOnce(&ApiRun) -> T,
+) -> Result<T, HttpResponse> {
+    let runs = server.runs.lock().unwrap_or_else(|e| e.into_inner());
+    runs.iter()
+        .find(|r| r.run_id == run_id)
+        .map(f)
+        .ok_or_else(|| HttpResponse::text(404, format!("no run {}\n", run_id)))
+}
+
+fn list_runs(server: &Server) -> HttpResponse {
+    let runs = server.runs.lock().unwrap_or_else(|e| e.into_inner());
+    let summaries: Vec<serde_json::Value> = runs
+        .iter()
+        .rev()
+        .map(|run| {
+            json!({
+                "run_id": run.run_id,
+                "workflow": run.workflow,
+                "started": run.started,
+                "status": run.status.as_deref().unwrap_or("running"),
+            })
+        })
+        .collect();
+    HttpResponse::json(200, json!(summaries))
+}
+
+fn describe(run: &ApiRun) -> serde_json::Value {
+    let steps: Vec<serde_json::Value> = run
+        .events
+        .iter()
+        .filter(|e| matches!(e, RunEvent::StepFinished { .. }))
+        .map(|e| redacted(e, &run.run_id))
+        .collect();
+    json!({
+        "run_id": run.run_id,
+        "workflow": run.workflow,
+        "started": run.started,
+        "status": run.status.as_deref().unwrap_or("running"),
+        "error": run.error,
+        "steps": steps,
+    })
+}
+
+// The event as JSON, with its output and error redacted
+fn redacted(event: &RunEvent, run_id: &str) -> serde_json::Value {
+    let mut value = serde_json::to_value(event).unwrap_or_default();
+    for key in ["output", "error"] {
+        if let Some(text) = value.get(key).and_then(|v| v.as_str()) {
+            value[key] = json!(crate::redaction::redact(text, Some(run_id)));
+        }
+    }
+    value
+}
+
+fn step_output(server: &Server, run_id: &str, step: &str) -> HttpResponse {
+    with_run(server, run_id, |run| {
+        let output = run.events.iter().rev().find_map(|e| match e {
+            RunEvent::StepFinished {
+                step_id, output, ..
+            } if step_id == step => Some(output.clone()),
+            _ => None,
+        });
+        match output {
+            Some(Some(output)) => {
+                HttpResponse::text(200, crate::redaction::redact(&output, Some(run_id)))
+            }
+            Some(None) => HttpResponse::text(404, format!("{} has no output\n", step)),
+            None => HttpResponse::text(404, format!("{} has not finished\n", step)),
+        }
+    })
+    .unwrap_or_else(|missing| missing)
+}
+
+// Server-sent events until the run finishes or the client goes away
+fn stream_events(
+    mut stream: TcpStream,
+    server: &Server,
+    run_id: &str,
+    mut sent: usize,
+) -> std::io::Result<()> {
+    let logs = logging::subscribe_with(EVENT_STREAM_CAPACITY, Overflow::DropOldest);
+    stream.set_write_timeout(Some(EVENT_KEEPALIVE))?;
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    )?;
+    let mut last_write = Instant::now();
+    loop {
+        let mut chunk = String::new();
+        let log = match logs.recv_timeout(EVENT_POLL) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        for event in log.into_iter().chain(logs.try_iter()) {
+            if event.run_id.as_deref() == Some(run_id) {
+                push_log(&mut chunk, &event);
+            }
+        }
+        let (events, finished) = {
+            let runs = server.runs.lock().unwrap_or_else(|e| e.into_inner());
+            match runs.iter().find(|r| r.run_id == run_id) {
+                Some(run) => (
+                    run.events.get(sent..).unwrap_or_default().to_vec(),
+                    run.status.is_some(),
+                ),
+                // Forgotten while followed
+                None => (Vec::new(), true),
+            }
+        };
+        for event in &events {
+            let data = redacted(event, run_id);
+            let kind = data["event"].as_str().unwrap_or("event").to_string();
+            chunk.push_str(&format!(
+                "id: {}\nevent: {}\ndata: {}\n\n",
+                sent, kind, data
+            ));
+            sent += 1;
+        }
+        if chunk.is_empty() && last_write.elapsed() >= EVENT_KEEPALIVE {
+            chunk.push_str(": keepalive\n\n");
+        }
+        if !chunk.is_empty() {
+            // A failed write means the client went away; dropping `logs` unsubscribes
+            if stream.write_all(chunk.as_bytes()).is_err() {
+                return Ok(());
+            }
+            last_write = Instant::now();
+        }
+        if finished {
+            return stream.flush();
+        }
+    }
+}
+
+fn push_log(chunk: &mut String, event: &LogEvent) {
+    chunk.push_str(&format!("event: log\ndata: {}\n\n", event.to_json_line()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_plugin, MockSpec};
+    use crate::plugins::PluginRegistry;
+    use std::io::{BufRead, BufReader, Read};
+
+    fn call(addr: SocketAddr, method: &str, path: &str, token: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            token,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    /// Serves an API with EchoPlugin and token `t0k` from a fresh directory.
+    fn server() -> (SocketAddr, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_api_{}", uuid::Uuid::new_v4()));
+        let mut registry = PluginRegistry::new();
+        registry.register_plugin(mock_plugin("EchoPlugin", MockSpec::default()));
+        let addr = serve(
+            "127.0.0.1:0",
+            &dir,
+            "t0k",
+            Orchestrator::with_registry(registry),
+        )
+        .unwrap();
+        (addr, dir)
+    }
+
+    const ECHO: &str = "workflow: echo\nparams:\n  topic: ~\nsteps:\n  - run: EchoPlugin\n    input: \"${{ params.topic }}\"\n";
+
+    #[test]
+    fn test_requests_need_the_token() {
+        let (addr, dir) = server();
+        assert_eq!(call(addr, "GET", "/plugins", "wrong", "").0, 401);
+        let (status, plugins) = call(addr, "GET", "/plugins?token=t0k", "", "");
+        assert_eq!(status, 200);
+        assert!(plugins.contains("EchoPlugin"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_only_valid_workflows_are_stored() {
+        let (addr, dir) = server();
+        let bad = "workflow: bad\nsteps:\n  - run: MissingPlugin\n";
+        assert_eq!(call(addr, "POST", "/workflows/bad", "t0k", bad).0, 422);
+        let (_, checked) = call(addr, "POST", "/validate", "t0k", bad);
+        assert!(checked.contains("\"valid\":false"));
+        assert_eq!(call(addr, "POST", "/workflows/echo", "t0k", ECHO).0, 201);
+        assert_eq!(call(addr, "GET", "/workflows", "t0k", "").1, "[\"echo\"]");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_missing_param_fails_the_run_before_it_starts() {
+        let (addr, dir) = server();
+        call(addr, "POST", "/workflows/echo", "t0k", ECHO);
+        let (status, _) = call(addr, "POST", "/runs", "t0k", r#"{"workflow":"echo"}"#);
+        assert_eq!(status, 422);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_api_runs_workflows_and_streams_events() {
+        let (addr, dir) = server();
+        call(addr, "POST", "/workflows/echo", "t0k", ECHO);
+        let (status, started) = call(
+            addr,
+            "POST",
+            "/runs",
+            "t0k",
+            r#"{"workflow":"echo","params":{"topic":"rust"}}"#,
+        );
+        assert_eq!(status, 201);
+        let run_id = serde_json::from_str::<serde_json::Value>(&started).unwrap()["run_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET /runs/{}/events HTTP/1.1\r\nAuthorization: Bearer t0k\r\n\r\n",
+            run_id
+        )
+        .unwrap();
+        let kinds: Vec<String> = BufReader::new(stream)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| line.strip_prefix("event: ").map(str::to_string))
+            .filter(|kind| kind != "log")
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "run_started",
+                "step_started",
+                "step_finished",
+                "run_finished"
+            ]
+        );
+
+        let (status, output) = call(
+            addr,
+            "GET",
+            &format!("/runs/{}/steps/step1/output", run_id),
+            "t0k",
+            "",
+        );
+        assert_eq!((status, output.as_str()), (200, "EchoPlugin(rust)"));
+        let (_, run) = call(addr, "GET", &format!("/runs/{}", run_id), "t0k", "");
+        assert!(run.contains("\"status\":\"success\""));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,278 @@
+// Artifact store for binary step outputs; steps pass on the artifact's path.
+use crate::audit::sha256_hex;
+use chrono::{DateTime, Utc};
+use lao_plugin_api::{ArtifactRef, PluginOutputType};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Where an artifact came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lineage {
+    pub run_id: String,
+    pub step_id: String,
+    pub plugin: String,
+    /// Ids of the artifacts the producing step consumed
+    #[serde(default)]
+    pub parents: Vec<String>,
+    /// Path the plugin originally wrote
+    pub source_path: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactRecord {
+    pub artifact: ArtifactRef,
+    pub lineage: Lineage,
+}
+
+pub struct ArtifactStore {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl ArtifactStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Copy the file at `source` into the store and record its lineage
+    pub fn capture(
+        &self,
+        source: &Path,
+        kind: PluginOutputType,
+        lineage: Lineage,
+    ) -> std::io::Result<ArtifactRef> {
+        let bytes = fs::read(source)?;
+        let sha256 = sha256_hex(&bytes);
+        let extension = source
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let objects = self.dir.join("objects");
+        fs::create_dir_all(&objects)?;
+        let encrypted = crate::encryption::enabled();
+        let stored = if encrypted {
+            objects.join(format!("{}{}.enc", sha256, extension))
+        } else {
+            objects.join(format!("{}{}", sha256, extension))
+        };
+        if !stored.exists() {
+            crate::encryption::write(&stored, &bytes)?;
+        }
+        let path = if encrypted { source } else { stored.as_path() };
+
+        let artifact = ArtifactRef {
+            id: uuid::Uuid::new_v4().to_string(),
+            path: path.to_string_lossy().to_string(),
+            mime: guess_mime(source, &kind).to_string(),
+            kind,
+            size: bytes.len() as u64,
+            sha256,
+        };
+        let record = ArtifactRecord {
+            artifact: artifact.clone(),
+            lineage,
+        };
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.index_path())?;
+        writeln!(
+            index,
+            "{}",
+            crate::encryption::seal_line(&serde_json::to_string(&record)?)?
+        )?;
+        Ok(artifact)
+    }
+
+    pub fn records(&self) -> std::io::Result<Vec<ArtifactRecord>> {
+        let content = match fs::read_to_string(self.index_path()) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| crate::encryption::open_line(line).ok())
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect())
+    }
+
+    /// The artifact with `id` followed by everything it was derived from, nearest first
+    pub fn lineage(&self, id: &str) -> std::io::Result<Vec<ArtifactRecord>> {
+        let records = self.records()?;
+        let mut chain = Vec::new();
+        let mut pending = vec![id.to_string()];
+        while let Some(next) = pending.pop() {
+            if chain.iter().any(|r: &ArtifactRecord| r.artifact.id == next) {
+                continue;
+            }
+            if let Some(record) = records.iter().find(|r| r.artifact.id == next) {
+                pending.extend(record.lineage.parents.iter().rev().cloned());
+                chain.push(record.clone());
+            }
+        }
+        Ok(chain)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join("index.jsonl")
+    }
+}
+
+static STORE: OnceLock<ArtifactStore> = OnceLock::new();
+
+/// Start capturing binary outputs into `dir`; later calls are ignored
+pub fn init(dir: impl Into<PathBuf>) {
+    let _ = STORE.set(ArtifactStore::new(dir));
+}
+
+pub fn store() -> Option<&'static ArtifactStore> {
+    STORE.get()
+}
+
+/// LAO_ARTIFACT_DIR, defaulting to artifacts/ in the data directory
+pub fn default_dir() -> PathBuf {
+    std::env::var("LAO_ARTIFACT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("artifacts"))
+}
+
+/// Capture a step's output when its plugin declares a binary output type and
+/// the output names an existing file. Returns None (leaving the output as
+/// text) when the store isn't initialized or the output isn't a file.
+pub fn capture_output(
+    output: &str,
+    kind: &PluginOutputType,
+    lineage: Lineage,
+) -> Option<ArtifactRef> {
+    let store = STORE.get()?;
+    if !kind.is_artifact() {
+        return None;
+    }
+    let source = Path::new(output.trim());
+    if !source.is_file() {
+        return None;
+    }
+    match store.capture(source, kind.clone(), lineage) {
+        Ok(artifact) => Some(artifact),
+        Err(e) => {
+            crate::logging::warn("failed to capture artifact")
+                .field("path", source.display().to_string())
+                .field("error", e.to_string())
+                .emit();
+            None
+        }
+    }
+}
+
+fn guess_mime(path: &Path, kind: &PluginOutputType) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "json" => "application/json",
+        "txt" | "md" => "text/plain",
+        _ => match kind {
+            PluginOutputType::Audio => "audio/*",
+            PluginOutputType::Image => "image/*",
+            PluginOutputType::Video => "video/*",
+            _ => "application/octet-stream",
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lineage(step_id: &str, parents: Vec<String>, source: &Path) -> Lineage {
+        Lineage {
+            run_id: "run-1".to_string(),
+            step_id: step_id.to_string(),
+            plugin: "TestPlugin".to_string(),
+            parents,
+            source_path: source.display().to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_capture_stores_files_by_hash() {
+        let dir = std::env::temp_dir().join(format!("lao_artifacts_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("meeting.wav");
+        fs::write(&recording, b"RIFF....WAVE").unwrap();
+
+        let store = ArtifactStore::new(dir.join("store"));
+        let audio = store
+            .capture(
+                &recording,
+                PluginOutputType::Audio,
+                lineage("step1", vec![], &recording),
+            )
+            .unwrap();
+        assert_eq!(audio.mime, "audio/wav");
+        assert_eq!(audio.size, 12);
+        assert!(audio.path.ends_with(&format!("{}.wav", audio.sha256)));
+        assert!(Path::new(&audio.path).is_file());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_capture_tracks_lineage() {
+        let dir = std::env::temp_dir().join(format!("lao_artifacts_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("meeting.wav");
+        fs::write(&recording, b"RIFF....WAVE").unwrap();
+        let spectrogram = dir.join("meeting.png");
+        fs::write(&spectrogram, b"\x89PNG").unwrap();
+
+        let store = ArtifactStore::new(dir.join("store"));
+        let audio = store
+            .capture(
+                &recording,
+                PluginOutputType::Audio,
+                lineage("step1", vec![], &recording),
+            )
+            .unwrap();
+        let image = store
+            .capture(
+                &spectrogram,
+                PluginOutputType::Image,
+                lineage("step2", vec![audio.id.clone()], &spectrogram),
+            )
+            .unwrap();
+        let chain = store.lineage(&image.id).unwrap();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain[0].artifact, image);
+        assert_eq!(chain[1].artifact.id, audio.id);
+        assert_eq!(chain[1].lineage.step_id, "step1");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
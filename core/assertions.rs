@@ -0,0 +1,365 @@
+// Output assertions: quality gates checked against each output of a step.
+// Assertion kinds and the `on_assert_fail` policies are in docs/workflows.md
+// (Output Assertions).
+use crate::plugins::PluginRegistry;
+use crate::WorkflowStep;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_CRITERIA: &str = "overall quality and correctness";
+
+/// One `assert:` entry of a step
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputAssertion {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub non_empty: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Length bounds in characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// The output must be JSON matching this schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judge: Option<JudgeSpec>,
+    /// Error to report instead of the default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// An LLM plugin asked to score the output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JudgeSpec {
+    pub plugin: String,
+    /// What the score is about; "overall quality and correctness" by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub criteria: Option<String>,
+    /// Lowest passing score, out of 10
+    pub min_score: f64,
+}
+
+/// What a step does when its output fails an assertion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssertAction {
+    #[default]
+    Retry,
+    Fallback,
+    Fail,
+}
+
+impl OutputAssertion {
+    /// Why the assertion can't be checked with `registry`, if it can't
+    pub fn problem(&self, registry: &PluginRegistry) -> Option<String> {
+        let checks = Self {
+            message: None,
+            ..self.clone()
+        };
+        if checks == Self::default() {
+            return Some(
+                "assertion has no check (non_empty, regex, min_length, max_length, json_schema or judge)"
+                    .to_string(),
+            );
+        }
+        if let Some(Err(e)) = self.regex.as_deref().map(Regex::new) {
+            return Some(format!("invalid assertion regex: {}", e));
+        }
+        if let (Some(min), Some(max)) = (self.min_length, self.max_length) {
+            if min > max {
+                return Some(format!(
+                    "assertion min_length {} is above max_length {}",
+                    min, max
+                ));
+            }
+        }
+        let judge = self.judge.as_ref()?;
+        if registry.get(&judge.plugin).is_none() {
+            return Some(format!("judge plugin '{}' not found", judge.plugin));
+        }
+        None
+    }
+
+    /// Check `output`; `judge` calls a plugin with a prompt
+    pub fn check(
+        &self,
+        output: &str,
+        judge: &mut dyn FnMut(&str, &str) -> Result<String, String>,
+    ) -> Result<(), String> {
+        match (self.failure(output, judge), &self.message) {
+            (None, _) => Ok(()),
+            (Some(_), Some(message)) => Err(message.clone()),
+            (Some(failure), None) => Err(failure),
+        }
+    }
+
+    fn failure(
+        &self,
+        output: &str,
+        judge: &mut dyn FnMut(&str, &str) -> Result<String, String>,
+    ) -> Option<String> {
+        if self.non_empty == Some(true) && output.trim().is_empty() {
+            return Some("output is empty".to_string());
+        }
+        let length = output.chars().count();
+        if let Some(min) = self.min_length.filter(|&min| length < min) {
+            return Some(format!(
+                "output is {} characters long, less than {}",
+                length, min
+            ));
+        }
+        if let Some(max) = self.max_length.filter(|&max| length > max) {
+            return Some(format!(
+                "output is {} characters long, more than {}",
+                length, max
+            ));
+        }
+        if let Some(regex) = &self.regex {
+            if !Regex::new(regex).is_ok_and(|re| re.is_match(output)) {
+                return Some(format!("output does not match /{}/", regex));
+            }
+        }
+        if let Some(schema) = &self.json_schema {
+            let json = match serde_json::from_str::<Value>(strip_code_fence(output)) {
+                Ok(json) => json,
+                Err(e) => return Some(format!("output is not JSON: {}", e)),
+            };
+            if let Err(e) = check_schema(&json, schema, "$") {
+                return Some(format!("output does not match the schema: {}", e));
+            }
+        }
+        let spec = self.judge.as_ref()?;
+        match judge_score(judge, &spec.plugin, spec.criteria.as_deref(), output) {
+            Ok(score) if score >= spec.min_score => None,
+            Ok(score) => Some(format!(
+                "judge '{}' scored {}, below {}",
+                spec.plugin, score, spec.min_score
+            )),
+            Err(e) => Some(e),
+        }
+    }
+}
+
+/// Score `output` from 0 to 10 for `criteria` by asking the `plugin` judge
+/// through `call`; also used by step evaluations (see `evaluation`)
+pub fn judge_score(
+    call: &mut dyn FnMut(&str, &str) -> Result<String, String>,
+    plugin: &str,
+    criteria: Option<&str>,
+    output: &str,
+) -> Result<f64, String> {
+    let prompt = format!(
+        "Rate the following output from 0 to 10 for {}. Reply with the number only.\n\nOutput:\n{}",
+        criteria.unwrap_or(DEFAULT_CRITERIA),
+        output
+    );
+    let reply = call(plugin, &prompt).map_err(|e| format!("judge '{}' failed: {}", plugin, e))?;
+    parse_score(&reply).ok_or_else(|| format!("judge '{}' gave no score: {}", plugin, reply.trim()))
+}
+
+/// What `step` does on a failed assertion
+pub fn action(step: &WorkflowStep) -> AssertAction {
+    step.on_assert_fail.unwrap_or(if step.fallback.is_some() {
+        AssertAction::Fallback
+    } else {
+        AssertAction::Retry
+    })
+}
+
+/// Check every assertion; failures are joined with "; "
+pub fn check_output(
+    assertions: &[OutputAssertion],
+    output: &str,
+    judge: &mut dyn FnMut(&str, &str) -> Result<String, String>,
+) -> Result<(), String> {
+    let failures: Vec<String> = assertions
+        .iter()
+        .filter_map(|a| a.check(output, judge).err())
+        .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("; "))
+    }
+}
+
+// Models often wrap JSON in a ```json fence
+pub(crate) fn strip_code_fence(output: &str) -> &str {
+    let trimmed = output.trim();
+    trimmed
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| inner.strip_prefix("json").unwrap_or(inner).trim())
+        .unwrap_or(trimmed)
+}
+
+fn parse_score(reply: &str) -> Option<f64> {
+    let re = Regex::new(r"-?\d+(?:\.\d+)?").ok()?;
+    re.find(reply)?.as_str().parse().ok()
+}
+
+fn type_matches(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+/// Check `value` against the supported subset of JSON Schema; `at` is its path
+pub fn check_schema(value: &Value, schema: &Value, at: &str) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !names.is_empty() && !names.iter().any(|name| type_matches(value, name)) {
+            return Err(format!("{} should be {}", at, names.join(" or ")));
+        }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Err(format!("{} is not one of the allowed values", at));
+        }
+    }
+    if let Some(text) = value.as_str() {
+        let length = text.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+            if length < min {
+                return Err(format!("{} is shorter than {} characters", at, min));
+            }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+            if length > max {
+                return Err(format!("{} is longer than {} characters", at, max));
+            }
+        }
+    }
+    if let Some(number) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if number < min {
+                return Err(format!("{} is below {}", at, min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if number > max {
+                return Err(format!("{} is above {}", at, max));
+            }
+        }
+    }
+    if let Some(object) = value.as_object() {
+        for name in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(name) {
+                return Err(format!("{} is missing '{}'", at, name));
+            }
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (name, item) in object {
+            match properties.and_then(|p| p.get(name)) {
+                Some(property) => check_schema(item, property, &format!("{}.{}", at, name))?,
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    return Err(format!("{} has unexpected '{}'", at, name));
+                }
+                None => {}
+            }
+        }
+    }
+    if let (Some(items), Some(schema)) = (value.as_array(), schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check_schema(item, schema, &format!("{}[{}]", at, i))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assertions() -> Vec<OutputAssertion> {
+        serde_yaml::from_str(
+            r#"
+- non_empty: true
+  max_length: 200
+- json_schema:
+    type: object
+    required: [title, tags]
+    properties:
+      title: { type: string, minLength: 3 }
+      tags: { type: array, items: { type: string } }
+- judge: { plugin: Judge, criteria: faithfulness, min_score: 7 }
+"#,
+        )
+        .unwrap()
+    }
+
+    /// Scores outputs about the quarterly report 8 and anything else 4.
+    fn judge(plugin: &str, prompt: &str) -> Result<String, String> {
+        assert_eq!(plugin, "Judge");
+        assert!(prompt.contains("faithfulness"));
+        Ok(if prompt.contains("Quarterly") {
+            "Score: 8/10"
+        } else {
+            "4"
+        }
+        .to_string())
+    }
+
+    #[test]
+    fn test_good_output_passes() {
+        let good = "```json\n{\"title\": \"Quarterly report\", \"tags\": [\"finance\"]}\n```";
+        check_output(&assertions(), good, &mut judge).unwrap();
+    }
+
+    #[test]
+    fn test_every_failed_assertion_is_reported() {
+        let error =
+            check_output(&assertions(), r#"{"title": "Q3", "tags": [1]}"#, &mut judge).unwrap_err();
+        assert_eq!(
+            error,
+            "output does not match the schema: $.tags[0] should be string; judge 'Judge' scored 4, below 7"
+        );
+    }
+
+    #[test]
+    fn test_blank_output_is_empty() {
+        let mut judged = false;
+        let error = check_output(&assertions()[..1], " ", &mut |_: &str, _: &str| {
+            judged = true;
+            Ok(String::new())
+        })
+        .unwrap_err();
+        assert_eq!(error, "output is empty");
+        assert!(!judged);
+    }
+
+    #[test]
+    fn test_assertion_problems() {
+        let assertions = assertions();
+        let registry = PluginRegistry::default();
+        assert!(assertions[2]
+            .problem(&registry)
+            .unwrap()
+            .contains("'Judge' not found"));
+        assert!(OutputAssertion::default().problem(&registry).is_some());
+        assert!(assertions[1].problem(&registry).is_none());
+    }
+}
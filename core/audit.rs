@@ -0,0 +1,420 @@
+// Append-only audit log for security-relevant events, hash-chained so
+// edits and deletions show up in `lao audit --verify`.
+// The events recorded are listed in docs/observability.md (Audit log).
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// prev_hash of the first entry in a log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    PluginInstall,
+    PluginUninstall,
+    PluginLoad,
+    PermissionGrant,
+    PermissionRevoke,
+    SecretAccess,
+    ExternalStep,
+}
+
+impl AuditKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "plugin_install" => Some(AuditKind::PluginInstall),
+            "plugin_uninstall" => Some(AuditKind::PluginUninstall),
+            "plugin_load" => Some(AuditKind::PluginLoad),
+            "permission_grant" => Some(AuditKind::PermissionGrant),
+            "permission_revoke" => Some(AuditKind::PermissionRevoke),
+            "secret_access" => Some(AuditKind::SecretAccess),
+            "external_step" => Some(AuditKind::ExternalStep),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::PluginInstall => "plugin_install",
+            AuditKind::PluginUninstall => "plugin_uninstall",
+            AuditKind::PluginLoad => "plugin_load",
+            AuditKind::PermissionGrant => "permission_grant",
+            AuditKind::PermissionRevoke => "permission_revoke",
+            AuditKind::SecretAccess => "secret_access",
+            AuditKind::ExternalStep => "external_step",
+        }
+    }
+}
+
+impl fmt::Display for AuditKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub kind: AuditKind,
+    /// Plugin or secret name the event is about
+    pub subject: String,
+    /// OS user running LAO
+    pub actor: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, serde_json::Value>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    pub fn new(kind: AuditKind, subject: impl Into<String>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            kind,
+            subject: subject.into(),
+            actor: current_user(),
+            run_id: None,
+            details: BTreeMap::new(),
+            prev_hash: String::new(),
+            hash: String::new(),
+        }
+    }
+
+    pub fn run(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn detail_str(&self, key: &str) -> Option<&str> {
+        self.details.get(key).and_then(|v| v.as_str())
+    }
+
+    /// Append to the process-wide audit log, if one was set up with `init`
+    pub fn record(self) {
+        if let Some(log) = AUDIT_LOG.get() {
+            if let Err(e) = log.append(self) {
+                crate::logging::warn("failed to write audit log")
+                    .field("path", log.path.display().to_string())
+                    .field("error", e.to_string())
+                    .emit();
+            }
+        }
+    }
+
+    /// Hash over every field but `hash` itself, chained through `prev_hash`
+    pub fn compute_hash(&self) -> String {
+        let mut unhashed = self.clone();
+        unhashed.hash = String::new();
+        let json = serde_json::to_string(&unhashed).unwrap_or_default();
+        sha256_hex(json.as_bytes())
+    }
+
+    /// One line for terminals: time, kind, subject, actor, then details
+    pub fn render(&self) -> String {
+        let mut line = format!(
+            "{} {:<17} {} by {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.kind.as_str(),
+            self.subject,
+            self.actor
+        );
+        if let Some(run_id) = &self.run_id {
+            line.push_str(&format!(" run={}", run_id));
+        }
+        for (key, value) in &self.details {
+            match value {
+                serde_json::Value::String(s) => line.push_str(&format!(" {}={}", key, s)),
+                other => line.push_str(&format!(" {}={}", key, other)),
+            }
+        }
+        line
+    }
+}
+
+pub fn event(kind: AuditKind, subject: impl Into<String>) -> AuditEntry {
+    AuditEntry::new(kind, subject)
+}
+
+/// Record that a secret was read; `accessor` is the plugin or step that asked for it.
+/// Only the name is logged, never the value.
+pub fn secret_access(name: &str, accessor: &str) {
+    event(AuditKind::SecretAccess, name)
+        .detail("accessor", accessor)
+        .record();
+}
+
+// Plugin tags that mean a call can be seen outside the process
+const NETWORK_TAGS: &[&str] = &["web", "http", "network", "api", "cloud", "mail", "email", "imap"];
+const FILE_WRITE_TAGS: &[&str] = &["files", "archive", "recording", "screenshot", "clipboard"];
+
+/// "network" or "file_write" when a plugin's tags say its calls reach the
+/// network or write files, which makes its steps worth auditing
+pub fn external_effect(tags: &[String]) -> Option<&'static str> {
+    let has = |list: &[&str]| tags.iter().any(|t| list.contains(&t.to_lowercase().as_str()));
+    if has(NETWORK_TAGS) {
+        Some("network")
+    } else if has(FILE_WRITE_TAGS) {
+        Some("file_write")
+    } else {
+        None
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    let digest = Sha256::digest(bytes);
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of a file's contents, e.g. a plugin library
+pub fn file_sha256(path: &Path) -> std::io::Result<String> {
+    Ok(sha256_hex(&fs::read(path)?))
+}
+
+fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    // Serialises appends so concurrent records keep the chain linear
+    lock: Mutex<()>,
+}
+
+static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+
+/// Start recording to `path` for the rest of the process; later calls are ignored
+pub fn init(path: impl Into<PathBuf>) {
+    let _ = AUDIT_LOG.set(AuditLog::new(path));
+}
+
+/// Whether `init` was called; lets callers skip expensive details such as file hashes
+pub fn enabled() -> bool {
+    AUDIT_LOG.get().is_some()
+}
+
+/// LAO_AUDIT_LOG, defaulting to audit/audit.log in the data directory
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_AUDIT_LOG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("audit").join("audit.log"))
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Link the entry to the last one on disk, hash it and append it
+    pub fn append(&self, mut entry: AuditEntry) -> std::io::Result<AuditEntry> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        // Read from disk rather than caching, so CLI and daemon processes share one chain
+        entry.prev_hash = last_hash(&self.path)?.unwrap_or_else(|| GENESIS_HASH.to_string());
+        entry.hash = entry.compute_hash();
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(entry)
+    }
+}
+
+// Hash of the last entry, reading only the tail of the file
+fn last_hash(path: &Path) -> std::io::Result<Option<String>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    let mut window = 4096u64;
+    loop {
+        let start = len.saturating_sub(window);
+        file.seek(SeekFrom::Start(start))?;
+        let mut tail = String::new();
+        file.read_to_string(&mut tail)?;
+        let mut lines = tail.lines().filter(|l| !l.trim().is_empty());
+        // A partial first line is only trusted once it is also the last line
+        let last = lines.next_back();
+        if start == 0 || last.is_some_and(|l| tail.trim_end().len() > l.len()) {
+            return Ok(last
+                .and_then(|l| serde_json::from_str::<AuditEntry>(l).ok())
+                .map(|e| e.hash));
+        }
+        window *= 4;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub kind: Option<AuditKind>,
+    /// Case-insensitive substring of the subject
+    pub subject: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Newest entries to return
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.kind.is_none_or(|kind| entry.kind == kind)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.subject.as_ref().is_none_or(|subject| {
+                entry
+                    .subject
+                    .to_lowercase()
+                    .contains(&subject.to_lowercase())
+            })
+    }
+}
+
+/// Entries matching the query, oldest first
+pub fn query(path: &Path, query: &AuditQuery) -> std::io::Result<Vec<AuditEntry>> {
+    let mut entries: Vec<AuditEntry> = read_entries(path)?
+        .into_iter()
+        .filter(|entry| query.matches(entry))
+        .collect();
+    if let Some(limit) = query.limit {
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+    }
+    Ok(entries)
+}
+
+pub fn read_entries(path: &Path) -> std::io::Result<Vec<AuditEntry>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+        .collect())
+}
+
+/// Check every line parses, hashes to its recorded hash and links to the
+/// previous entry. Returns the number of entries, or the first broken line.
+pub fn verify(path: &Path) -> Result<usize, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut prev = GENESIS_HASH.to_string();
+    let mut count = 0;
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_no = index + 1;
+        let entry: AuditEntry = serde_json::from_str(line)
+            .map_err(|e| format!("line {}: not an audit entry: {}", line_no, e))?;
+        if entry.prev_hash != prev {
+            return Err(format!(
+                "line {}: chain broken, an entry before it was changed or removed",
+                line_no
+            ));
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(format!("line {}: entry was modified", line_no));
+        }
+        prev = entry.hash;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log() -> AuditLog {
+        AuditLog::new(
+            std::env::temp_dir()
+                .join(format!("lao-audit-{}", uuid::Uuid::new_v4()))
+                .join("audit.log"),
+        )
+    }
+
+    #[test]
+    fn test_append_chains_and_verifies() {
+        let log = temp_log();
+        let first = log
+            .append(event(AuditKind::PluginLoad, "EchoPlugin").detail("sha256", "abc"))
+            .unwrap();
+        let second = log
+            .append(event(AuditKind::ExternalStep, "FeedPlugin").run("run-1"))
+            .unwrap();
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        assert_eq!(second.prev_hash, first.hash);
+        assert_eq!(verify(log.path()), Ok(2));
+
+        let found = query(
+            log.path(),
+            &AuditQuery {
+                kind: Some(AuditKind::ExternalStep),
+                ..AuditQuery::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].run_id.as_deref(), Some("run-1"));
+
+        // Tampering with the first entry breaks verification
+        let content = fs::read_to_string(log.path()).unwrap();
+        fs::write(log.path(), content.replacen("EchoPlugin", "EvilPlugin", 1)).unwrap();
+        assert!(verify(log.path()).unwrap_err().starts_with("line 1"));
+
+        fs::remove_dir_all(log.path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_last_hash_reads_long_files() {
+        let log = temp_log();
+        let mut last = None;
+        for i in 0..100 {
+            last = Some(
+                log.append(event(AuditKind::SecretAccess, format!("TOKEN_{}", i)))
+                    .unwrap(),
+            );
+        }
+        assert_eq!(last_hash(log.path()).unwrap(), last.map(|e| e.hash));
+        assert_eq!(verify(log.path()), Ok(100));
+        assert_eq!(AuditKind::parse("secret-access"), Some(AuditKind::SecretAccess));
+        assert_eq!(
+            external_effect(&["feed".to_string(), "web".to_string()]),
+            Some("network")
+        );
+        assert_eq!(external_effect(&["echo".to_string()]), None);
+
+        fs::remove_dir_all(log.path().parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,474 @@
+// Backend targets for LLM steps: which server, and which model, the host
+// sends a step's Ollama requests to (see docs/workflows.md).
+use crate::ollama::{self, OllamaClient, OllamaConfig, OllamaResponse};
+use crate::prefix_cache;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Default registry file, in the data directory; overridable with `LAO_BACKENDS`
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_BACKENDS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("backends.yaml"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Ollama,
+    Llamacpp,
+}
+
+impl BackendKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackendKind::Ollama => "ollama",
+            BackendKind::Llamacpp => "llamacpp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackendSpec {
+    pub kind: BackendKind,
+    pub url: String,
+    /// Model used instead of the one the plugin asks for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Environment variable holding the bearer token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// Slots of a llama.cpp server (its `--parallel`), see `prefix_cache`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slots: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BackendRegistry {
+    #[serde(default)]
+    pub backends: BTreeMap<String, BackendSpec>,
+    /// Models workflows refer to as `${{ tiers.<name> }}` (see `tiers`)
+    #[serde(default)]
+    pub tiers: BTreeMap<String, String>,
+}
+
+impl BackendRegistry {
+    /// The registry at `path`; empty if there is none
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        serde_yaml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// The entry a step's `backend` names; `None` for the default server
+    pub fn resolve(&self, target: &str) -> Result<Option<(&str, &BackendSpec)>, String> {
+        let (kind, name) = match target.split_once('@') {
+            Some((kind, name)) => (Some(kind.trim()), name.trim()),
+            None => (None, target.trim()),
+        };
+        if kind.is_none()
+            && name == BackendKind::Ollama.as_str()
+            && !self.backends.contains_key(name)
+        {
+            return Ok(None);
+        }
+        let Some((name, spec)) = self.backends.get_key_value(name) else {
+            let known: Vec<&str> = self.backends.keys().map(String::as_str).collect();
+            return Err(format!(
+                "backend '{}' is not in the backend registry (known: {})",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            ));
+        };
+        match kind {
+            Some(kind) if kind != spec.kind.as_str() => Err(format!(
+                "backend '{}' is {}, not {}",
+                name,
+                spec.kind.as_str(),
+                kind
+            )),
+            _ => Ok(Some((name.as_str(), spec))),
+        }
+    }
+}
+
+static REGISTRY: OnceLock<Result<BackendRegistry, String>> = OnceLock::new();
+
+/// The registry of this process, read from `default_path` on first use
+pub fn registry() -> Result<&'static BackendRegistry, String> {
+    REGISTRY
+        .get_or_init(|| BackendRegistry::load(&default_path()))
+        .as_ref()
+        .map_err(|e| e.clone())
+}
+
+/// A registry entry with its connection
+pub struct Backend {
+    pub name: String,
+    pub spec: BackendSpec,
+    client: OllamaClient,
+}
+
+impl Backend {
+    pub fn new(name: &str, spec: &BackendSpec) -> Result<Self, String> {
+        let defaults = OllamaConfig::from_env();
+        let api_key = spec
+            .api_key_env
+            .as_ref()
+            .and_then(|var| std::env::var(var).ok());
+        let client = OllamaClient::new(OllamaConfig {
+            base_url: spec.url.clone(),
+            api_key,
+            ..defaults
+        })?;
+        Ok(Self {
+            name: name.to_string(),
+            spec: spec.clone(),
+            client,
+        })
+    }
+
+    /// Send an Ollama API request to this backend. `on_body` gets the reply
+    /// as it arrives, see `OllamaClient::request_streaming`.
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+        on_body: &mut dyn FnMut(&[u8]),
+    ) -> Result<OllamaResponse, String> {
+        let mut body: Option<Value> = match body {
+            Some(text) => Some(
+                serde_json::from_str(text).map_err(|e| format!("invalid request body: {}", e))?,
+            ),
+            None => None,
+        };
+        if let (Some(model), Some(body)) = (&self.spec.model, body.as_mut()) {
+            body["model"] = json!(model);
+        }
+        if let Some(body) = body.as_mut() {
+            let slots = self.spec.slots.unwrap_or(1);
+            hint(&self.name, self.spec.kind, slots, path, body);
+        }
+        match self.spec.kind {
+            BackendKind::Ollama => {
+                let body = body.map(|b| b.to_string());
+                self.client
+                    .request_streaming(method, path, body.as_deref(), on_body)
+            }
+            BackendKind::Llamacpp if path == "/api/generate" => {
+                let request = to_llamacpp(body.as_ref().unwrap_or(&Value::Null));
+                let response =
+                    self.client
+                        .request("POST", "/completion", Some(&request.to_string()))?;
+                if !response.is_success() {
+                    return Ok(response);
+                }
+                let reply: Value = serde_json::from_str(&response.body)
+                    .map_err(|e| format!("invalid llama.cpp reply: {}", e))?;
+                let model = body.as_ref().map_or(Value::Null, |b| b["model"].clone());
+                Ok(OllamaResponse {
+                    status: response.status,
+                    body: from_llamacpp(&reply, model).to_string(),
+                })
+            }
+            BackendKind::Llamacpp => Err(format!(
+                "llama.cpp backend '{}' only serves /api/generate, not {}",
+                self.name, path
+            )),
+        }
+    }
+}
+
+// Ollama /api/generate request as a llama.cpp /completion request
+fn to_llamacpp(generate: &Value) -> Value {
+    let prompt = generate["prompt"].as_str().unwrap_or_default();
+    let prompt = match generate["system"].as_str() {
+        Some(system) => format!("{}\n\n{}", system, prompt),
+        None => prompt.to_string(),
+    };
+    let mut request = json!({ "prompt": prompt, "stream": false });
+    let options = &generate["options"];
+    for (from, to) in [
+        ("temperature", "temperature"),
+        ("top_p", "top_p"),
+        ("top_k", "top_k"),
+        ("seed", "seed"),
+        ("stop", "stop"),
+        ("num_predict", "n_predict"),
+    ] {
+        if !options[from].is_null() {
+            request[to] = options[from].clone();
+        }
+    }
+    if generate["format"] == "json" {
+        request["json_schema"] = json!({});
+    }
+    for hint in ["cache_prompt", "id_slot"] {
+        if !generate[hint].is_null() {
+            request[hint] = generate[hint].clone();
+        }
+    }
+    request
+}
+
+// Prefix caching hints for the request (see `prefix_cache`)
+fn hint(server: &str, kind: BackendKind, slots: usize, path: &str, body: &mut Value) {
+    if let Some(shared) = prefix_cache::global().hint(server, kind, slots, path, body) {
+        log::debug!(
+            "{} request to {} shares its first {} chars with an earlier one",
+            path,
+            server,
+            shared
+        );
+    }
+}
+
+// llama.cpp /completion reply in the shape of Ollama's /api/generate reply
+fn from_llamacpp(reply: &Value, model: Value) -> Value {
+    json!({
+        "model": model,
+        "response": reply["content"].as_str().unwrap_or_default(),
+        "done": true,
+        "prompt_eval_count": reply["tokens_evaluated"].as_u64().unwrap_or(0),
+        "eval_count": reply["tokens_predicted"].as_u64().unwrap_or(0),
+    })
+}
+
+// Connections are kept per backend, like the default server's
+fn connect(name: &str, spec: &BackendSpec) -> Result<Arc<Backend>, String> {
+    static BACKENDS: OnceLock<Mutex<HashMap<String, Arc<Backend>>>> = OnceLock::new();
+    let mut backends = BACKENDS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(backend) = backends.get(name).filter(|b| b.spec == *spec) {
+        return Ok(backend.clone());
+    }
+    let backend = Arc::new(Backend::new(name, spec)?);
+    backends.insert(name.to_string(), backend.clone());
+    Ok(backend)
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Result<Arc<Backend>, String>>> = const { RefCell::new(None) };
+    // The `model` param of the current step
+    static MODEL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// While alive, LLM requests plugins make on this thread go to `target`,
+/// asking for `model` when the step names one
+pub struct StepScope {
+    previous: Option<Result<Arc<Backend>, String>>,
+    previous_model: Option<String>,
+}
+
+impl StepScope {
+    pub fn enter(target: Option<&str>, model: Option<&str>) -> Self {
+        Self::enter_with(target, model, registry())
+    }
+
+    pub fn enter_with(
+        target: Option<&str>,
+        model: Option<&str>,
+        registry: Result<&BackendRegistry, String>,
+    ) -> Self {
+        let backend = target.and_then(|target| {
+            registry
+                .and_then(|registry| registry.resolve(target))
+                .and_then(|resolved| resolved.map(|(name, spec)| connect(name, spec)).transpose())
+                .transpose()
+        });
+        Self {
+            previous: CURRENT.with(|current| current.replace(backend)),
+            previous_model: MODEL.with(|current| current.replace(model.map(str::to_string))),
+        }
+    }
+}
+
+impl Drop for StepScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        let previous_model = self.previous_model.take();
+        MODEL.with(|current| *current.borrow_mut() = previous_model);
+    }
+}
+
+/// Send a plugin's Ollama API request to the current step's backend, or to
+/// the default server
+pub(crate) fn request(
+    method: &str,
+    path: &str,
+    body: Option<&str>,
+    on_body: &mut dyn FnMut(&[u8]),
+) -> Result<OllamaResponse, String> {
+    let model = MODEL.with(|current| current.borrow().clone());
+    let with_model = match (model, body) {
+        (Some(model), Some(text)) => {
+            let mut body: Value =
+                serde_json::from_str(text).map_err(|e| format!("invalid request body: {}", e))?;
+            body["model"] = json!(model);
+            Some(body.to_string())
+        }
+        _ => None,
+    };
+    let body = with_model.as_deref().or(body);
+    let backend = CURRENT.with(|current| current.borrow().clone());
+    match backend {
+        Some(Ok(backend)) => backend.request(method, path, body, on_body),
+        Some(Err(e)) => Err(e),
+        None => {
+            let hinted = body
+                .and_then(|text| serde_json::from_str::<Value>(text).ok())
+                .map(|mut body| {
+                    hint("ollama", BackendKind::Ollama, 1, path, &mut body);
+                    body.to_string()
+                });
+            let body = hinted.as_deref().or(body);
+            ollama::shared()
+                .and_then(|client| client.request_streaming(method, path, body, on_body))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread::JoinHandle;
+
+    // A server answering one request with `reply`; joining it gives the
+    // request line and body it got
+    fn serve_once(reply: &'static str) -> (SocketAddr, JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut request_line, mut length) = (String::new(), 0);
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line.trim_end().is_empty() {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                reply.len(),
+                reply
+            )
+            .unwrap();
+            (request_line, String::from_utf8(body).unwrap())
+        });
+        (addr, server)
+    }
+
+    fn two_backends(tiny: &str) -> BackendRegistry {
+        serde_yaml::from_str(&format!(
+            "backends:\n  gpu-box:\n    kind: ollama\n    url: http://gpu-box.lan:11434\n  tiny:\n    kind: llamacpp\n    url: http://{}\n    model: qwen2.5-0.5b\n",
+            tiny
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_backend_references() {
+        let registry = two_backends("127.0.0.1:8080");
+        assert!(registry.resolve("ollama").unwrap().is_none());
+        assert_eq!(
+            registry.resolve("ollama@gpu-box").unwrap().unwrap().0,
+            "gpu-box"
+        );
+        assert_eq!(
+            registry.resolve("ollama@tiny").unwrap_err(),
+            "backend 'tiny' is llamacpp, not ollama"
+        );
+        assert!(registry
+            .resolve("cloud")
+            .unwrap_err()
+            .contains("known: gpu-box, tiny"));
+    }
+
+    #[test]
+    fn test_steps_reach_their_backends() {
+        // A llama.cpp server answering one completion
+        let (addr, server) =
+            serve_once(r#"{"content":"urgent","tokens_evaluated":12,"tokens_predicted":3}"#);
+        let registry = two_backends(&addr.to_string());
+        let reply = {
+            let _scope = StepScope::enter_with(Some("llamacpp@tiny"), None, Ok(&registry));
+            request(
+                "POST",
+                "/api/generate",
+                Some(r#"{"model":"llama3","prompt":"Classify: server down","format":"json","options":{"temperature":0}}"#),
+                &mut |_| {},
+            )
+            .unwrap()
+        };
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with("POST /completion "));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["prompt"], "Classify: server down");
+        assert_eq!(body["temperature"], 0);
+        assert_eq!(body["json_schema"], json!({}));
+        let reply: Value = serde_json::from_str(&reply.body).unwrap();
+        assert_eq!(reply["response"], "urgent");
+        assert_eq!(reply["model"], "qwen2.5-0.5b");
+        assert_eq!(reply["eval_count"], 3);
+    }
+
+    #[test]
+    fn test_unknown_backend_fails_the_request() {
+        let registry = two_backends("127.0.0.1:8080");
+        let _scope = StepScope::enter_with(Some("cloud"), None, Ok(&registry));
+        assert!(request("POST", "/api/generate", Some("{}"), &mut |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_step_model_replaces_the_plugins() {
+        let (addr, server) = serve_once(r#"{"model":"phi3:mini","response":"ok","done":true}"#);
+        let registry: BackendRegistry = serde_yaml::from_str(&format!(
+            "backends:\n  local:\n    kind: ollama\n    url: http://{}\n",
+            addr
+        ))
+        .unwrap();
+        {
+            let _scope = StepScope::enter_with(Some("local"), Some("phi3:mini"), Ok(&registry));
+            request(
+                "POST",
+                "/api/generate",
+                Some(r#"{"model":"llama2","prompt":"hi","stream":false}"#),
+                &mut |_| {},
+            )
+            .unwrap();
+        }
+        let (request_line, body) = server.join().unwrap();
+        assert!(request_line.starts_with("POST /api/generate "));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["model"], "phi3:mini");
+        assert_eq!(body["prompt"], "hi");
+        assert!(MODEL.with(|current| current.borrow().is_none()));
+    }
+}
@@ -0,0 +1,561 @@
+// Branches: steps that run only when earlier steps went a certain way.
+// The `when` expression syntax and the `if_failed`/`if_succeeded` handlers
+// are described in docs/workflows.md (Branches).
+use crate::assertions::strip_code_fence;
+use crate::plan::{ExecutionPlan, PlannedStep};
+use crate::{StepLog, WorkflowStep};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Literal(String),
+    Step(String, Field),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    /// The output, or the value at a path into it when it is JSON
+    Output(Vec<String>),
+    Status,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Value(Operand),
+    Compare(Operand, Op, Operand),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '\'' || c == '"' {
+            chars.next();
+            let mut literal = String::new();
+            loop {
+                match chars.next() {
+                    Some(end) if end == c => break,
+                    Some('\\') => literal.extend(chars.next()),
+                    Some(other) => literal.push(other),
+                    None => return Err(format!("unclosed string {}{}", c, literal)),
+                }
+            }
+            tokens.push(Token::Text(literal));
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(match word.as_str() {
+                "contains" => Token::Op(Op::Contains),
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                _ => Token::Word(word),
+            });
+            continue;
+        }
+        chars.next();
+        let next = chars.peek().copied();
+        let (token, pair) = match (c, next) {
+            ('=', Some('=')) => (Token::Op(Op::Eq), true),
+            ('!', Some('=')) => (Token::Op(Op::Ne), true),
+            ('<', Some('=')) => (Token::Op(Op::Le), true),
+            ('>', Some('=')) => (Token::Op(Op::Ge), true),
+            ('&', Some('&')) => (Token::And, true),
+            ('|', Some('|')) => (Token::Or, true),
+            ('<', _) => (Token::Op(Op::Lt), false),
+            ('>', _) => (Token::Op(Op::Gt), false),
+            ('!', _) => (Token::Not, false),
+            ('(', _) => (Token::Open, false),
+            (')', _) => (Token::Close, false),
+            _ => return Err(format!("unexpected '{}'", c)),
+        };
+        if pair {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.at += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.at += 1;
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.at += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.at += 1;
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            _ => {
+                let left = self.operand()?;
+                match self.peek() {
+                    Some(Token::Op(op)) => {
+                        let op = *op;
+                        self.at += 1;
+                        Ok(Expr::Compare(left, op, self.operand()?))
+                    }
+                    _ => Ok(Expr::Value(left)),
+                }
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        match self.next() {
+            Some(Token::Text(text)) => Ok(Operand::Literal(text)),
+            Some(Token::Word(word)) => {
+                if let Some(path) = word.strip_prefix("steps.") {
+                    if path.split('.').any(str::is_empty) {
+                        return Err(format!("'{}' has an empty part", word));
+                    }
+                    let mut parts = path.split('.');
+                    let id = parts.next().unwrap_or_default();
+                    let field = match parts.next() {
+                        Some("output") => Field::Output(parts.map(str::to_string).collect()),
+                        Some(field @ ("status" | "error")) if parts.next().is_some() => {
+                            return Err(format!("steps.{}.{} has no fields", id, field))
+                        }
+                        Some("status") => Field::Status,
+                        Some("error") => Field::Error,
+                        Some(other) => {
+                            return Err(format!(
+                                "unknown field '{}' of steps.{} (output, status or error)",
+                                other, id
+                            ))
+                        }
+                        None => {
+                            return Err(format!(
+                                "'{}' needs a field: steps.<id>.output, status or error",
+                                word
+                            ))
+                        }
+                    };
+                    Ok(Operand::Step(id.to_string(), field))
+                } else if word.parse::<f64>().is_ok() || word == "true" || word == "false" {
+                    Ok(Operand::Literal(word))
+                } else {
+                    Err(format!(
+                        "'{}' is neither steps.<id>.<field> nor a quoted string",
+                        word
+                    ))
+                }
+            }
+            Some(other) => Err(format!("expected a value, found {:?}", other)),
+            None => Err("expression ends early".to_string()),
+        }
+    }
+}
+
+/// Parse a `when` expression, with or without `${{ }}` around it
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let trimmed = text.trim();
+    let inner = trimmed
+        .strip_prefix('$')
+        .unwrap_or(trimmed)
+        .strip_prefix("{{")
+        .and_then(|rest| rest.strip_suffix("}}"))
+        .unwrap_or(trimmed);
+    let mut parser = Parser {
+        tokens: tokenize(inner)?,
+        at: 0,
+    };
+    let expr = parser.or()?;
+    match parser.next() {
+        None => Ok(expr),
+        Some(extra) => Err(format!("unexpected {:?}", extra)),
+    }
+}
+
+// The value at `path` in JSON `output`: strings as they are, other values as
+// JSON, and nothing when the output isn't JSON or the path leads nowhere
+fn json_at(output: &str, path: &[String]) -> String {
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(strip_code_fence(output)) else {
+        return String::new();
+    };
+    for key in path {
+        let next = match &mut value {
+            serde_json::Value::Array(items) => key
+                .parse::<usize>()
+                .ok()
+                .filter(|&i| i < items.len())
+                .map(|i| items.swap_remove(i)),
+            serde_json::Value::Object(fields) => fields.remove(key),
+            _ => None,
+        };
+        match next {
+            Some(next) => value = next,
+            None => return String::new(),
+        }
+    }
+    match value {
+        serde_json::Value::String(text) => text,
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// How a step that has run went, as `steps.<id>.status` reads it
+pub fn status(log: &StepLog) -> &'static str {
+    if log.error.is_some() {
+        "error"
+    } else if matches!(log.validation.as_deref(), Some("skipped" | "disabled")) {
+        "skipped"
+    } else {
+        "success"
+    }
+}
+
+impl Expr {
+    /// Ids of the steps the expression reads
+    pub fn steps(&self) -> Vec<&str> {
+        fn ids(operand: &Operand) -> Vec<&str> {
+            match operand {
+                Operand::Step(id, _) => vec![id.as_str()],
+                Operand::Literal(_) => Vec::new(),
+            }
+        }
+        match self {
+            Expr::Value(a) => ids(a),
+            Expr::Compare(a, _, b) => [ids(a), ids(b)].concat(),
+            Expr::Not(e) => e.steps(),
+            Expr::And(a, b) | Expr::Or(a, b) => [a.steps(), b.steps()].concat(),
+        }
+    }
+
+    /// Whether the expression holds for the steps in `logs`
+    pub fn holds(&self, logs: &[StepLog]) -> bool {
+        let value = |operand: &Operand| match operand {
+            Operand::Literal(text) => text.clone(),
+            Operand::Step(id, field) => {
+                let Some(log) = logs.iter().rev().find(|l| l.step_id == *id) else {
+                    return match field {
+                        Field::Status => "pending".to_string(),
+                        _ => String::new(),
+                    };
+                };
+                match field {
+                    Field::Output(path) => {
+                        let output = log.output.clone().unwrap_or_default();
+                        if path.is_empty() {
+                            output
+                        } else {
+                            json_at(&output, path)
+                        }
+                    }
+                    Field::Status => status(log).to_string(),
+                    Field::Error => log.error.clone().unwrap_or_default(),
+                }
+            }
+        };
+        match self {
+            Expr::Value(a) => {
+                let a = value(a);
+                !matches!(a.trim(), "" | "false" | "0")
+            }
+            Expr::Compare(a, op, b) => {
+                let (a, b) = (value(a), value(b));
+                let (a, b) = (a.trim(), b.trim());
+                let numbers = || Some((a.parse::<f64>().ok()?, b.parse::<f64>().ok()?));
+                match op {
+                    Op::Eq => a == b,
+                    Op::Ne => a != b,
+                    Op::Contains => a.contains(b),
+                    Op::Lt => numbers().is_some_and(|(a, b)| a < b),
+                    Op::Le => numbers().is_some_and(|(a, b)| a <= b),
+                    Op::Gt => numbers().is_some_and(|(a, b)| a > b),
+                    Op::Ge => numbers().is_some_and(|(a, b)| a >= b),
+                }
+            }
+            Expr::Not(e) => !e.holds(logs),
+            Expr::And(a, b) => a.holds(logs) && b.holds(logs),
+            Expr::Or(a, b) => a.holds(logs) || b.holds(logs),
+        }
+    }
+}
+
+/// Problems with the branches of step `id`; `position` is each step's place
+/// in execution order
+pub fn problems(id: &str, step: &WorkflowStep, position: &HashMap<&str, usize>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let here = position.get(id).copied().unwrap_or_default();
+    if let Some(when) = &step.when {
+        match parse(when) {
+            Err(e) => problems.push(format!("when: {}", e)),
+            Ok(expr) => {
+                for other in expr.steps() {
+                    match position.get(other) {
+                        None => problems.push(format!("when: '{}' does not name a step", other)),
+                        Some(&at) if at >= here => problems.push(format!(
+                            "when: reads {}, which doesn't run before this step",
+                            other
+                        )),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    let handlers = [
+        ("if_failed", &step.on_failure),
+        ("if_succeeded", &step.on_success),
+    ];
+    for (key, targets) in handlers {
+        for target in targets.iter().flatten() {
+            match position.get(target.as_str()) {
+                None => problems.push(format!("{}: '{}' does not name a step", key, target)),
+                Some(&at) if at <= here => problems.push(format!(
+                    "{}: {} must come after this step to handle it",
+                    key, target
+                )),
+                _ => {}
+            }
+        }
+    }
+    problems
+}
+
+/// Why `planned` is skipped given the steps that ran before it, if it is
+pub fn skip_reason(
+    plan: &ExecutionPlan,
+    planned: &PlannedStep,
+    logs: &[StepLog],
+) -> Option<String> {
+    let id = planned.id.as_str();
+    let ran = |other: &str| logs.iter().rev().find(|l| l.step_id == other);
+    if let Some(input) = &planned.step.input_from {
+        if ran(input).is_some_and(|log| log.validation.as_deref() == Some("skipped")) {
+            return Some(format!("its input step {} was skipped", input));
+        }
+    }
+    // Handlers run if any step naming them went the way they handle
+    let mut untriggered = Vec::new();
+    let mut triggered = false;
+    for source in &plan.steps {
+        let (outcome, verb) = if source.step.on_failure.iter().flatten().any(|t| t == id) {
+            ("error", "fail")
+        } else if source.step.on_success.iter().flatten().any(|t| t == id) {
+            ("success", "succeed")
+        } else {
+            continue;
+        };
+        untriggered.push(format!("{} didn't {}", source.id, verb));
+        triggered |= ran(source.id.as_str()).is_some_and(|log| status(log) == outcome);
+    }
+    if !untriggered.is_empty() && !triggered {
+        return Some(untriggered.join(" and "));
+    }
+    let when = planned.step.when.as_deref()?;
+    match parse(when) {
+        Ok(expr) if expr.holds(logs) => None,
+        Ok(_) => Some(format!("when: {} doesn't hold", when.trim())),
+        Err(e) => Some(format!("when: {}", e)),
+    }
+}
+
+/// Reads a step id or a list of them
+pub(crate) fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(
+        Option::<OneOrMany>::deserialize(deserializer)?.map(|ids| match ids {
+            OneOrMany::One(id) => vec![id],
+            OneOrMany::Many(ids) => ids,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: &str, output: Option<&str>, error: Option<&str>) -> StepLog {
+        StepLog {
+            step: 0,
+            step_id: id.to_string(),
+            runner: "EchoPlugin".to_string(),
+            input: serde_yaml::Value::Null,
+            output: output.map(str::to_string),
+            error: error.map(str::to_string),
+            artifact: None,
+            attempt: 1,
+            input_type: None,
+            output_type: None,
+            validation: None,
+        }
+    }
+
+    fn holds(text: &str) -> bool {
+        let logs = [
+            log("step1", Some("error: model not found"), None),
+            log("step2", None, Some("timed out")),
+            log("step3", Some("42"), None),
+        ];
+        parse(text).unwrap().holds(&logs)
+    }
+
+    #[test]
+    fn test_when_reads_outputs_and_statuses() {
+        assert!(holds("{{steps.step1.output contains 'error'}}"));
+        assert!(holds(
+            "${{ steps.step2.status == 'error' && steps.step3.output > 40 }}"
+        ));
+        assert!(!holds(
+            "steps.step1.status != 'success' or (steps.step3.output < 10)"
+        ));
+    }
+
+    #[test]
+    fn test_when_treats_steps_not_run_as_pending() {
+        assert!(holds(
+            "not steps.step2.output || steps.step4.status == \"pending\""
+        ));
+    }
+
+    #[test]
+    fn test_when_reads_fields_of_json_outputs() {
+        let logs = [log(
+            "classify",
+            Some("```json\n{\"label\": \"urgent\", \"scores\": [0.9, 0.1], \"meta\": {\"lang\": \"en\"}}\n```"),
+            None,
+        )];
+        let holds = |text: &str| parse(text).unwrap().holds(&logs);
+        assert!(holds("steps.classify.output.label == \"urgent\""));
+        assert!(holds("steps.classify.output.scores.0 > 0.5"));
+        assert!(holds("steps.classify.output.meta.lang == 'en'"));
+        assert!(holds("steps.classify.output.meta contains 'lang'"));
+        assert!(!holds("steps.classify.output.missing"));
+        assert!(!holds("steps.classify.output.scores.7"));
+    }
+
+    #[test]
+    fn test_json_paths_of_text_outputs_are_empty() {
+        let logs = [log("step1", Some("urgent"), None)];
+        assert!(parse("steps.step1.output.label == ''")
+            .unwrap()
+            .holds(&logs));
+        assert!(parse("steps.step1.output == 'urgent'")
+            .unwrap()
+            .holds(&logs));
+    }
+
+    #[test]
+    fn test_when_lists_the_steps_it_reads() {
+        assert_eq!(
+            parse("steps.step1.output == 'a' && steps.step3.error")
+                .unwrap()
+                .steps(),
+            ["step1", "step3"]
+        );
+    }
+
+    #[test]
+    fn test_malformed_when_is_an_error() {
+        assert!(parse("steps.step1 == 'a'").is_err());
+        assert!(parse("steps.step1.output contains").is_err());
+        assert!(parse("agenda == 'a'").is_err());
+        assert!(parse("steps.step1.status.code == 'a'").is_err());
+        assert!(parse("steps.step1.output..label").is_err());
+    }
+
+    #[test]
+    fn test_branches_must_point_at_the_right_steps() {
+        let step: WorkflowStep = serde_yaml::from_str(
+            "run: EchoPlugin\nwhen: steps.step3.output == 'x'\nif_failed: step1\n",
+        )
+        .unwrap();
+        assert_eq!(step.on_failure, Some(vec!["step1".to_string()]));
+        let position = HashMap::from([("step1", 0), ("step2", 1), ("step3", 2)]);
+        assert_eq!(
+            problems("step2", &step, &position),
+            [
+                "when: reads step3, which doesn't run before this step",
+                "if_failed: step1 must come after this step to handle it"
+            ]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// Breakpoints: pausing a run before a step to look at, or replace, its input.
+// `breakpoint` and `lao run --break` are in docs/workflows.md (Breakpoints).
+use crate::logging;
+use crate::WorkflowStep;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A step waiting at a breakpoint
+#[derive(Debug, Clone)]
+pub struct PausedStep {
+    pub run_id: String,
+    pub step_id: String,
+    pub plugin: String,
+    /// The input the plugin would be given
+    pub input: String,
+}
+
+/// How a paused run goes on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    /// Run the step with this input instead
+    Replace(String),
+    /// Stop the run, as if it was cancelled
+    Abort,
+}
+
+pub trait PauseHandler: Send + Sync {
+    /// Called on the run's thread, which waits for the answer
+    fn paused(&self, step: &PausedStep) -> Resume;
+}
+
+/// Steps to pause before, and who resumes them
+#[derive(Clone)]
+pub struct Breakpoints {
+    pub steps: HashSet<String>,
+    handler: Arc<dyn PauseHandler>,
+}
+
+impl Breakpoints {
+    /// Pauses at steps with `breakpoint: true`, and at the ones added with `at`
+    pub fn new(handler: impl PauseHandler + 'static) -> Self {
+        Self {
+            steps: HashSet::new(),
+            handler: Arc::new(handler),
+        }
+    }
+
+    pub fn at(mut self, step_id: impl Into<String>) -> Self {
+        self.steps.insert(step_id.into());
+        self
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Breakpoints>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with runs on this thread pausing at `breakpoints`
+pub fn with_breakpoints<T>(breakpoints: Breakpoints, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|c| c.replace(Some(breakpoints)));
+    let result = f();
+    CURRENT.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+/// Whether `step` has a breakpoint and a handler to pause it
+pub(crate) fn will_pause(step_id: &str, step: &WorkflowStep) -> bool {
+    CURRENT.with(|c| {
+        c.borrow()
+            .as_ref()
+            .is_some_and(|b| step.breakpoint == Some(true) || b.steps.contains(step_id))
+    })
+}
+
+/// Pause before `step` if it has a breakpoint. A replaced input goes into
+/// `input` and the `input` param; false if the run is to stop.
+pub(crate) fn pause(
+    run_id: &str,
+    step_id: &str,
+    step: &WorkflowStep,
+    params: &mut serde_yaml::Value,
+    input: &mut String,
+) -> bool {
+    let current = CURRENT.with(|c| c.borrow().clone());
+    let wanted = step.breakpoint == Some(true)
+        || current.as_ref().is_some_and(|b| b.steps.contains(step_id));
+    if !wanted {
+        return true;
+    }
+    let Some(breakpoints) = current else {
+        logging::info("breakpoint passed, nothing to resume the run")
+            .run(run_id)
+            .step(step_id)
+            .emit();
+        return true;
+    };
+    logging::info("run paused").run(run_id).step(step_id).emit();
+    let paused = PausedStep {
+        run_id: run_id.to_string(),
+        step_id: step_id.to_string(),
+        plugin: step.run.clone(),
+        input: input.clone(),
+    };
+    let resume = breakpoints.handler.paused(&paused);
+    logging::info("run resumed")
+        .run(run_id)
+        .step(step_id)
+        .field("input_replaced", matches!(resume, Resume::Replace(_)))
+        .field("aborted", resume == Resume::Abort)
+        .emit();
+    match resume {
+        Resume::Continue => true,
+        Resume::Replace(replacement) => {
+            crate::replace_input(params, input, replacement);
+            true
+        }
+        Resume::Abort => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    struct Surgeon(Mutex<Vec<PausedStep>>, Resume);
+
+    impl PauseHandler for Arc<Surgeon> {
+        fn paused(&self, step: &PausedStep) -> Resume {
+            self.0.lock().unwrap().push(step.clone());
+            self.1.clone()
+        }
+    }
+
+    /// Writes a workflow with a breakpoint on its second step.
+    fn paused_workflow() -> (std::path::PathBuf, String, crate::plugins::PluginRegistry) {
+        let dir = std::env::temp_dir().join(format!("lao_breakpoints_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("paused.yaml");
+        std::fs::write(
+            &path,
+            "workflow: paused\nsteps:\n  - run: EchoPlugin\n    input: draft\n  - run: EchoPlugin\n    input_from: step1\n    breakpoint: true\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap().to_string();
+        let workflow = crate::load_workflow_yaml(&path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        (dir, path, registry)
+    }
+
+    #[test]
+    fn test_breakpoint_without_a_handler_is_passed() {
+        let (dir, path, registry) = paused_workflow();
+        let logs = crate::run_workflow_with_registry(&path, HashMap::new(), &registry).unwrap();
+        assert_eq!(
+            logs[1].output.as_deref(),
+            Some("EchoPlugin(EchoPlugin(draft))")
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_breakpoint_replaces_input() {
+        let (dir, path, registry) = paused_workflow();
+        let surgeon = Arc::new(Surgeon(
+            Mutex::new(Vec::new()),
+            Resume::Replace("better prompt".to_string()),
+        ));
+        let logs = with_breakpoints(Breakpoints::new(surgeon.clone()), || {
+            crate::run_workflow_with_registry(&path, HashMap::new(), &registry).unwrap()
+        });
+        assert_eq!(logs[1].output.as_deref(), Some("EchoPlugin(better prompt)"));
+        assert_eq!(logs[1].input["input"], "better prompt");
+        let paused = surgeon.0.lock().unwrap().clone();
+        assert_eq!(paused.len(), 1);
+        assert_eq!(paused[0].step_id, "step2");
+        assert_eq!(paused[0].input, "EchoPlugin(draft)");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_breakpoint_aborts_the_run() {
+        let (dir, path, registry) = paused_workflow();
+        let abort = Arc::new(Surgeon(Mutex::new(Vec::new()), Resume::Abort));
+        let result = with_breakpoints(Breakpoints::new(abort.clone()).at("step1"), || {
+            crate::run_workflow_with_registry(&path, HashMap::new(), &registry)
+        });
+        assert_eq!(result.unwrap_err(), crate::cancel::CANCELLED);
+        assert_eq!(abort.0.lock().unwrap()[0].step_id, "step1");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
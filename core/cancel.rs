@@ -0,0 +1,216 @@
+// Cancelling runs, and stopping steps that take too long. Stopping is
+// cooperative: plugins run in the host's process.
+// `timeout` and cancelling are described in docs/workflows.md (Timeouts and
+// Stopping Runs).
+use lao_plugin_api::HostStop;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Error returned by a run that was cancelled
+pub const CANCELLED: &str = "Run cancelled";
+
+// How often a running step's watchdog looks at its token
+pub(crate) const POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+    static STEP: RefCell<Option<Arc<Stop>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with runs on this thread stopped by `token`
+pub fn with_token<T>(token: CancelToken, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|c| c.replace(Some(token)));
+    let result = f();
+    CURRENT.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+/// Whether the run on this thread has been cancelled
+pub fn requested() -> bool {
+    CURRENT.with(|c| c.borrow().as_ref().is_some_and(CancelToken::is_cancelled))
+}
+
+type Abort = Box<dyn FnOnce() + Send>;
+
+static NEXT_ABORT: AtomicU64 = AtomicU64::new(0);
+
+// Why a step was stopped, and what to undo when it is
+#[derive(Default)]
+struct Stop {
+    reason: Mutex<Option<String>>,
+    aborts: Mutex<Vec<(u64, Abort)>>,
+}
+
+impl Stop {
+    fn reason(&self) -> Option<String> {
+        self.reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn trigger(&self, reason: String) {
+        self.reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert(reason);
+        let aborts = std::mem::take(&mut *self.aborts.lock().unwrap_or_else(|e| e.into_inner()));
+        for (_, abort) in aborts {
+            abort();
+        }
+    }
+}
+
+/// While alive, the step on this thread is stopped when the run's token is
+/// cancelled or `timeout` has passed
+pub struct StepScope {
+    stop: Arc<Stop>,
+    previous: Option<Arc<Stop>>,
+    done: Option<mpsc::Sender<()>>,
+    watchdog: Option<JoinHandle<()>>,
+}
+
+impl StepScope {
+    pub fn enter(timeout: Option<Duration>) -> Self {
+        let stop = Arc::new(Stop::default());
+        let token = CURRENT.with(|c| c.borrow().clone());
+        if token.as_ref().is_some_and(CancelToken::is_cancelled) {
+            stop.trigger(CANCELLED.to_string());
+        }
+        let (done, finished) = mpsc::channel::<()>();
+        let watchdog = (token.is_some() || timeout.is_some()).then(|| {
+            let stop = stop.clone();
+            let start = Instant::now();
+            thread::spawn(move || loop {
+                // Ends as soon as the scope is dropped
+                if finished.recv_timeout(POLL) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+                if token.as_ref().is_some_and(CancelToken::is_cancelled) {
+                    return stop.trigger(CANCELLED.to_string());
+                }
+                if let Some(timeout) = timeout.filter(|t| start.elapsed() >= *t) {
+                    return stop.trigger(format!("timed out after {:?}", timeout));
+                }
+            })
+        });
+        let previous = STEP.with(|s| s.replace(Some(stop.clone())));
+        Self {
+            stop,
+            previous,
+            done: Some(done),
+            watchdog,
+        }
+    }
+
+    /// Why the step was stopped, if it was
+    pub fn stopped(&self) -> Option<String> {
+        self.stop.reason()
+    }
+}
+
+impl Drop for StepScope {
+    fn drop(&mut self) {
+        self.done.take();
+        if let Some(watchdog) = self.watchdog.take() {
+            let _ = watchdog.join();
+        }
+        STEP.with(|s| *s.borrow_mut() = self.previous.take());
+    }
+}
+
+/// Why the step running on this thread was stopped, if it was
+pub fn stopped() -> Option<String> {
+    STEP.with(|s| s.borrow().as_ref().and_then(|stop| stop.reason()))
+}
+
+/// Removes its abort again when dropped
+pub struct AbortGuard(Option<(Arc<Stop>, u64)>);
+
+impl Drop for AbortGuard {
+    fn drop(&mut self) {
+        if let Some((stop, id)) = self.0.take() {
+            let mut aborts = stop.aborts.lock().unwrap_or_else(|e| e.into_inner());
+            aborts.retain(|(other, _)| *other != id);
+        }
+    }
+}
+
+/// Call `abort` from another thread if the step on this thread is stopped
+/// while the guard lives, e.g. to shut down a socket it is blocked reading;
+/// at once if it already was
+pub fn on_stop(abort: impl FnOnce() + Send + 'static) -> AbortGuard {
+    let Some(stop) = STEP.with(|s| s.borrow().clone()) else {
+        return AbortGuard(None);
+    };
+    // Holding the list while looking, so a stop can't come in between
+    let mut aborts = stop.aborts.lock().unwrap_or_else(|e| e.into_inner());
+    if stop.reason().is_some() {
+        drop(aborts);
+        abort();
+        return AbortGuard(None);
+    }
+    let id = NEXT_ABORT.fetch_add(1, Ordering::Relaxed);
+    aborts.push((id, Box::new(abort)));
+    drop(aborts);
+    AbortGuard(Some((stop, id)))
+}
+
+/// Handed to plugins that export `plugin_host_stop`
+pub static HOST_STOP: HostStop = HostStop {
+    stopped: host_stopped,
+};
+
+unsafe extern "C" fn host_stopped() -> bool {
+    stopped().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_steps_stop_on_timeout_and_cancel() {
+        let step = StepScope::enter(Some(Duration::from_millis(100)));
+        let (aborted, was_aborted) = mpsc::channel();
+        let _guard = on_stop(move || aborted.send(()).unwrap());
+        was_aborted.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(step.stopped().as_deref(), Some("timed out after 100ms"));
+        assert!(stopped().is_some());
+        drop(step);
+        assert_eq!(stopped(), None);
+
+        let token = CancelToken::new();
+        let cancelled = with_token(token.clone(), || {
+            let step = StepScope::enter(None);
+            token.cancel();
+            while step.stopped().is_none() {
+                thread::sleep(POLL);
+            }
+            unsafe { lao_plugin_api::accept_host_stop(&HOST_STOP) };
+            (step.stopped(), lao_plugin_api::stop_requested())
+        });
+        assert_eq!(cancelled, (Some(CANCELLED.to_string()), true));
+    }
+}
@@ -0,0 +1,269 @@
+// Bounded channels between the executor and slower consumers, so a stalled
+// reader costs dropped events rather than a blocked workflow.
+use std::collections::VecDeque;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Discard the oldest queued item to make room; readers see recent events
+    DropOldest,
+    /// Discard the item being sent; readers see a complete prefix
+    DropNewest,
+    /// Wait up to the given time for room, then discard the item being sent
+    Block(Duration),
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    dropped: u64,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// A channel holding at most `capacity` items (at least one)
+pub fn bounded<T>(capacity: usize, overflow: Overflow) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            items: VecDeque::new(),
+            dropped: 0,
+            senders: 1,
+            receiver_alive: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        capacity: capacity.max(1),
+        overflow,
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Queue `item`, applying the overflow policy if the channel is full.
+    /// Fails only once the receiver is gone; an item dropped for lack of room
+    /// still counts as sent.
+    pub fn send(&self, item: T) -> Result<(), SendError<T>> {
+        let shared = &self.shared;
+        let mut queue = shared.lock();
+        if !queue.receiver_alive {
+            return Err(SendError(item));
+        }
+        if queue.items.len() >= shared.capacity {
+            match shared.overflow {
+                Overflow::DropOldest => {
+                    queue.items.pop_front();
+                    queue.dropped += 1;
+                }
+                Overflow::DropNewest => {
+                    queue.dropped += 1;
+                    return Ok(());
+                }
+                Overflow::Block(timeout) => {
+                    let deadline = Instant::now() + timeout;
+                    while queue.items.len() >= shared.capacity && queue.receiver_alive {
+                        let left = deadline.saturating_duration_since(Instant::now());
+                        if left.is_zero() {
+                            break;
+                        }
+                        queue = shared
+                            .not_full
+                            .wait_timeout(queue, left)
+                            .unwrap_or_else(|e| e.into_inner())
+                            .0;
+                    }
+                    if !queue.receiver_alive {
+                        return Err(SendError(item));
+                    }
+                    if queue.items.len() >= shared.capacity {
+                        queue.dropped += 1;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        queue.items.push_back(item);
+        shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// False once the receiver has been dropped
+    pub fn is_connected(&self) -> bool {
+        self.shared.lock().receiver_alive
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.lock().senders -= 1;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut queue = self.shared.lock();
+        match queue.items.pop_front() {
+            Some(item) => {
+                self.shared.not_full.notify_one();
+                Ok(item)
+            }
+            None if queue.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut queue = self.shared.lock();
+        loop {
+            if let Some(item) = queue.items.pop_front() {
+                self.shared.not_full.notify_one();
+                return Ok(item);
+            }
+            if queue.senders == 0 {
+                return Err(RecvError);
+            }
+            queue = self
+                .shared
+                .not_empty
+                .wait(queue)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.lock();
+        loop {
+            if let Some(item) = queue.items.pop_front() {
+                self.shared.not_full.notify_one();
+                return Ok(item);
+            }
+            if queue.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            queue = self
+                .shared
+                .not_empty
+                .wait_timeout(queue, left)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Items queued right now, without waiting
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.try_recv().ok())
+    }
+
+    /// Items dropped since the last call, per the overflow policy
+    pub fn take_dropped(&self) -> u64 {
+        std::mem::take(&mut self.shared.lock().dropped)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock();
+        queue.receiver_alive = false;
+        queue.items.clear();
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_drop_oldest_keeps_the_newest_and_counts_drops() {
+        let (sender, receiver) = bounded(2, Overflow::DropOldest);
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(receiver.take_dropped(), 3);
+        assert_eq!(receiver.take_dropped(), 0);
+    }
+
+    #[test]
+    fn test_drop_newest_keeps_the_oldest() {
+        let (sender, receiver) = bounded(2, Overflow::DropNewest);
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_full_channel_never_blocks_the_sender_for_long() {
+        let (sender, receiver) = bounded(1, Overflow::Block(Duration::from_millis(20)));
+        sender.send(1).unwrap();
+        let started = Instant::now();
+        sender.send(2).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(receiver.take_dropped(), 1);
+    }
+
+    #[test]
+    fn test_reader_freeing_room_lets_a_blocked_send_through() {
+        let (sender, receiver) = bounded(1, Overflow::Block(Duration::from_secs(5)));
+        sender.send(1).unwrap();
+        let reader = thread::spawn(move || {
+            let first = receiver.recv().unwrap();
+            (first, receiver.recv().unwrap())
+        });
+        sender.send(2).unwrap();
+        drop(sender);
+        assert_eq!(reader.join().unwrap(), (1, 2));
+    }
+
+    #[test]
+    fn test_send_fails_once_the_receiver_is_gone() {
+        let (sender, receiver) = bounded(4, Overflow::DropOldest);
+        drop(receiver);
+        assert!(!sender.is_connected());
+        assert!(sender.send(1).is_err());
+    }
+}
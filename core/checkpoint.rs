@@ -0,0 +1,409 @@
+// Checkpoints of runs in progress, and resuming a run that failed.
+// `lao resume` is described in docs/workflows.md (Resuming Failed Runs).
+use crate::hooks::{ExecutionHook, RunContext, RunEnd, StepContext, StepFinish};
+use crate::plugins::PluginRegistry;
+use crate::{encryption, secrets, StepLog};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub run_id: String,
+    pub workflow: String,
+    /// Path the workflow was loaded from
+    pub path: String,
+    pub inputs: HashMap<String, String>,
+    /// `running` until the run ends, then its status
+    pub status: String,
+    pub updated: DateTime<Utc>,
+    /// The run this one resumed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resumed_from: Option<String>,
+    pub steps: Vec<CheckpointStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointStep {
+    pub step_id: String,
+    pub runner: String,
+    /// `success`, `error` or `skipped`
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<String>,
+    /// Hash of the plugin and params the step ran with
+    pub call: String,
+}
+
+impl Checkpoint {
+    /// A run that didn't complete: it failed, was cancelled or never ended
+    pub fn is_resumable(&self) -> bool {
+        self.status != "success"
+    }
+
+    /// The recorded output of `step_id` if it succeeded with the same call
+    pub fn reusable(
+        &self,
+        step_id: &str,
+        runner: &str,
+        params: &serde_yaml::Value,
+    ) -> Option<&str> {
+        self.steps
+            .iter()
+            .find(|s| s.step_id == step_id)
+            .filter(|s| s.status == "success" && s.artifact.is_none())
+            .filter(|s| s.call == call_hash(runner, params))
+            .and_then(|s| s.output.as_deref())
+    }
+
+    /// Steps that succeeded
+    pub fn succeeded(&self) -> usize {
+        self.steps.iter().filter(|s| s.status == "success").count()
+    }
+
+    /// The first step that failed
+    pub fn first_failure(&self) -> Option<&CheckpointStep> {
+        self.steps.iter().find(|s| s.status == "error")
+    }
+}
+
+fn call_hash(runner: &str, params: &serde_yaml::Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(runner.as_bytes());
+    hasher.update([0]);
+    hasher.update(serde_json::to_string(params).unwrap_or_default().as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn step_of(log: &StepLog) -> CheckpointStep {
+    let status = if log.error.is_some() {
+        "error"
+    } else if log.validation.as_deref() == Some("skipped") {
+        "skipped"
+    } else {
+        "success"
+    };
+    CheckpointStep {
+        step_id: log.step_id.clone(),
+        runner: log.runner.clone(),
+        status: status.to_string(),
+        // A step whose output holds a secret runs again on resume
+        output: log.output.clone().filter(|o| !secrets::reveals(o)),
+        error: log.error.as_deref().map(secrets::hide),
+        artifact: log.artifact.as_ref().map(|a| a.id.clone()),
+        call: call_hash(&log.runner, &log.input),
+    }
+}
+
+static CHECKPOINT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Start writing checkpoints under `dir`; later calls are ignored
+pub fn init(dir: impl Into<PathBuf>) {
+    let _ = CHECKPOINT_DIR.set(dir.into());
+}
+
+pub fn save(dir: &Path, checkpoint: &Checkpoint) -> Result<(), String> {
+    let run_dir = dir.join(&checkpoint.run_id);
+    fs::create_dir_all(&run_dir)
+        .map_err(|e| format!("Cannot create {}: {}", run_dir.display(), e))?;
+    let json = serde_json::to_string(checkpoint).map_err(|e| e.to_string())?;
+    let path = run_dir.join(CHECKPOINT_FILE);
+    encryption::write(&path, json).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+pub fn load(dir: &Path, run_id: &str) -> Result<Checkpoint, String> {
+    if run_id.is_empty() || run_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid run id '{}'", run_id));
+    }
+    let path = dir.join(run_id).join(CHECKPOINT_FILE);
+    let text = encryption::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("No checkpoint for run {}", run_id),
+        _ => format!("Cannot read {}: {}", path.display(), e),
+    })?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+/// The most recent resumable run of the workflow at `path` that no later
+/// run resumed
+pub fn latest(dir: &Path, path: &str) -> Option<Checkpoint> {
+    let same = |other: &str| {
+        other == path
+            || fs::canonicalize(other)
+                .ok()
+                .is_some_and(|p| fs::canonicalize(path).is_ok_and(|q| p == q))
+    };
+    let checkpoints: Vec<Checkpoint> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| load(dir, &entry.file_name().to_string_lossy()).ok())
+        .filter(|c| same(&c.path))
+        .collect();
+    let resumed: Vec<&str> = checkpoints
+        .iter()
+        .filter_map(|c| c.resumed_from.as_deref())
+        .collect();
+    checkpoints
+        .iter()
+        .filter(|c| c.is_resumable() && !resumed.contains(&c.run_id.as_str()))
+        .max_by_key(|c| c.updated)
+        .cloned()
+}
+
+/// Checkpoints each run as it goes; a built-in execution hook
+#[derive(Default)]
+pub struct CheckpointHook {
+    runs: Mutex<HashMap<String, Checkpoint>>,
+}
+
+impl CheckpointHook {
+    fn update(&self, run_id: &str, f: impl FnOnce(&mut Checkpoint)) {
+        let Some(dir) = CHECKPOINT_DIR.get() else {
+            return;
+        };
+        let mut runs = self.runs.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(checkpoint) = runs.get_mut(run_id) else {
+            return;
+        };
+        f(checkpoint);
+        checkpoint.updated = Utc::now();
+        if let Err(e) = save(dir, checkpoint) {
+            crate::logging::warn("saving run checkpoint failed")
+                .run(run_id)
+                .field("error", e)
+                .emit();
+        }
+    }
+}
+
+impl ExecutionHook for CheckpointHook {
+    fn on_run_start(&self, run: &RunContext) {
+        if CHECKPOINT_DIR.get().is_none() {
+            return;
+        }
+        let checkpoint = Checkpoint {
+            run_id: run.run_id.to_string(),
+            workflow: run.workflow.workflow.clone(),
+            path: run.path.to_string(),
+            inputs: run.inputs.clone(),
+            status: "running".to_string(),
+            updated: Utc::now(),
+            resumed_from: RESUMING.with(|r| r.borrow().as_ref().map(|c| c.run_id.clone())),
+            steps: Vec::new(),
+        };
+        self.runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(run.run_id.to_string(), checkpoint);
+        self.update(run.run_id, |_| {});
+    }
+
+    fn on_step_finish(&self, run: &RunContext, _step: &StepContext, finish: &StepFinish) {
+        self.update(run.run_id, |checkpoint| {
+            checkpoint.steps.push(step_of(finish.log))
+        });
+    }
+
+    fn on_run_end(&self, run: &RunContext, end: &RunEnd) {
+        self.update(run.run_id, |checkpoint| {
+            checkpoint.status = end.status.to_string()
+        });
+        self.runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(run.run_id);
+    }
+}
+
+thread_local! {
+    static RESUMING: RefCell<Option<Arc<Checkpoint>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with runs on this thread reusing the steps `checkpoint` completed
+pub fn with_resume<T>(checkpoint: Checkpoint, f: impl FnOnce() -> T) -> T {
+    let previous = RESUMING.with(|r| r.replace(Some(Arc::new(checkpoint))));
+    let result = f();
+    RESUMING.with(|r| *r.borrow_mut() = previous);
+    result
+}
+
+/// The output to reuse for a step of the run being resumed, and that run's id
+pub(crate) fn resumed(
+    step_id: &str,
+    runner: &str,
+    params: &serde_yaml::Value,
+) -> Option<(String, String)> {
+    RESUMING.with(|r| {
+        let checkpoint = r.borrow().clone()?;
+        let output = checkpoint.reusable(step_id, runner, params)?.to_string();
+        Some((output, checkpoint.run_id.clone()))
+    })
+}
+
+/// Resume run `run_id` checkpointed under `dir` against `registry`
+pub fn resume(dir: &Path, run_id: &str, registry: &PluginRegistry) -> Result<Vec<StepLog>, String> {
+    let checkpoint = load(dir, run_id)?;
+    if !checkpoint.is_resumable() {
+        return Err(format!(
+            "Run {} succeeded; there is nothing to resume",
+            run_id
+        ));
+    }
+    let (path, inputs) = (checkpoint.path.clone(), checkpoint.inputs.clone());
+    with_resume(checkpoint, || {
+        crate::run_workflow_with_registry(&path, inputs, registry)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::PluginInstance;
+    use lao_plugin_api::PluginInfo;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Checkpoints go to one directory per process, shared by these tests
+    fn runs() -> &'static Path {
+        static RUNS: OnceLock<PathBuf> = OnceLock::new();
+        RUNS.get_or_init(|| {
+            let runs = std::env::temp_dir()
+                .join(format!("lao_checkpoint_{}", uuid::Uuid::new_v4()))
+                .join("runs");
+            init(&runs);
+            runs
+        })
+    }
+
+    fn info(name: &str) -> PluginInfo {
+        PluginInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            author: String::new(),
+            dependencies: Vec::new(),
+            tags: Vec::new(),
+            capabilities: Vec::new(),
+            input_schema: None,
+            output_schema: None,
+        }
+    }
+
+    fn find(matches: impl Fn(&Checkpoint) -> bool) -> Checkpoint {
+        fs::read_dir(runs())
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter_map(|e| load(runs(), &e.file_name().to_string_lossy()).ok())
+            .find(|c| matches(c))
+            .unwrap()
+    }
+
+    struct FailedRun {
+        dir: PathBuf,
+        path: String,
+        registry: PluginRegistry,
+        transcribed: Arc<AtomicUsize>,
+        checkpoint: Checkpoint,
+    }
+
+    impl FailedRun {
+        // Summarize fails until the run is fixed
+        fn new() -> Self {
+            runs();
+            let dir = std::env::temp_dir().join(format!("lao_checkpoint_{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("resume.yaml");
+            fs::write(
+                &path,
+                "workflow: resume\nsteps:\n  - run: Transcribe\n    input: ${file}\n  - run: Summarize\n    input_from: step1\n",
+            )
+            .unwrap();
+            let transcribed = Arc::new(AtomicUsize::new(0));
+            let calls = transcribed.clone();
+            let marker = dir.join("fixed");
+            let mut registry = PluginRegistry::new();
+            registry.register_plugin(PluginInstance::native(info("Transcribe"), move |input| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(format!("text of {}", input))
+            }));
+            registry.register_plugin(PluginInstance::native(info("Summarize"), move |input| {
+                if marker.exists() {
+                    Ok(format!("summary of {}", input))
+                } else {
+                    Err("model not loaded".to_string())
+                }
+            }));
+
+            let path = path.to_string_lossy().into_owned();
+            let inputs = HashMap::from([("file".to_string(), "a.wav".to_string())]);
+            let logs = crate::run_workflow_with_registry(&path, inputs, &registry).unwrap();
+            assert!(logs[1].error.is_some());
+            let checkpoint = find(|c| c.path == path);
+            Self {
+                dir,
+                path,
+                registry,
+                transcribed,
+                checkpoint,
+            }
+        }
+
+        fn fix(&self) {
+            fs::write(self.dir.join("fixed"), "").unwrap();
+        }
+    }
+
+    impl Drop for FailedRun {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_failed_run_leaves_a_checkpoint() {
+        let run = FailedRun::new();
+        assert_eq!(run.checkpoint.status, "error");
+        assert_eq!(run.checkpoint.succeeded(), 1);
+        assert_eq!(run.checkpoint.first_failure().unwrap().step_id, "step2");
+        assert_eq!(
+            latest(runs(), &run.path).unwrap().run_id,
+            run.checkpoint.run_id
+        );
+    }
+
+    #[test]
+    fn test_resume_reuses_steps_that_succeeded() {
+        let run = FailedRun::new();
+        run.fix();
+        let logs = resume(runs(), &run.checkpoint.run_id, &run.registry).unwrap();
+        assert_eq!(run.transcribed.load(Ordering::SeqCst), 1);
+        assert_eq!(logs[0].validation.as_deref(), Some("resumed"));
+        assert_eq!(logs[1].output.as_deref(), Some("summary of text of a.wav"));
+    }
+
+    #[test]
+    fn test_completed_run_has_nothing_to_resume() {
+        let run = FailedRun::new();
+        run.fix();
+        resume(runs(), &run.checkpoint.run_id, &run.registry).unwrap();
+        assert!(latest(runs(), &run.path).is_none());
+        let resumed = find(|c| c.resumed_from.as_deref() == Some(run.checkpoint.run_id.as_str()));
+        assert_eq!(resumed.status, "success");
+        assert!(resume(runs(), &resumed.run_id, &run.registry).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+// Keeping step inputs within the model's context window.
+use crate::logging;
+use crate::map_reduce::{self, MapReduceSpec};
+use crate::plugins::PluginInstance;
+use crate::WorkflowStep;
+use serde::{Deserialize, Serialize};
+
+const CHARS_PER_TOKEN: u64 = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextPolicy {
+    #[default]
+    Fail,
+    /// Drop the start of the input, keeping the end
+    TruncateHead,
+    /// Drop the end of the input, keeping the start
+    TruncateTail,
+    /// Run as a map-reduce step with chunks that fit
+    Chunk,
+}
+
+impl ContextPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContextPolicy::Fail => "fail",
+            ContextPolicy::TruncateHead => "truncate_head",
+            ContextPolicy::TruncateTail => "truncate_tail",
+            ContextPolicy::Chunk => "chunk",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContextSpec {
+    /// Window in tokens; what the plugin reports by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    /// Tokens of the window kept free for the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<ContextPolicy>,
+}
+
+impl ContextSpec {
+    /// What is wrong with the settings, if anything
+    pub fn problem(&self) -> Option<String> {
+        match (self.max_tokens, self.reserve) {
+            (Some(0), _) => Some("context max_tokens must be at least 1".to_string()),
+            (Some(max), Some(reserve)) if reserve >= max => {
+                Some("context reserve must be smaller than max_tokens".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Estimated token count of `text`
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Apply the step's policy if `input` is over its window: a cut input goes
+/// into `input` and the `input` param, and `chunk` returns the map-reduce
+/// settings to run with. An error when the step is to fail.
+pub(crate) fn fit(
+    run_id: &str,
+    step_id: &str,
+    step: &WorkflowStep,
+    plugin: &PluginInstance,
+    params: &mut serde_yaml::Value,
+    input: &mut String,
+) -> Result<Option<MapReduceSpec>, String> {
+    if map_reduce::spec(step).is_some() {
+        return Ok(None);
+    }
+    let spec = step.context.clone().unwrap_or_default();
+    let Some(window) = spec.max_tokens.or_else(|| plugin.context_window()) else {
+        return Ok(None);
+    };
+    let budget = window.saturating_sub(spec.reserve.unwrap_or(0)).max(1);
+    let tokens = estimate_tokens(input);
+    if tokens <= budget {
+        return Ok(None);
+    }
+    let policy = spec.policy.unwrap_or_default();
+    logging::warn("input over the context window")
+        .run(run_id)
+        .step(step_id)
+        .field("runner", step.run.clone())
+        .field("tokens", tokens)
+        .field("budget", budget)
+        .field("policy", policy.as_str())
+        .emit();
+    let keep = (budget * CHARS_PER_TOKEN) as usize;
+    let chars = input.chars().count();
+    match policy {
+        ContextPolicy::Fail => Err(format!(
+            "input is about {} tokens, over the {} the context window leaves; set context.policy to truncate_head, truncate_tail or chunk",
+            tokens, budget
+        )),
+        ContextPolicy::TruncateHead => {
+            let kept = input.chars().skip(chars - keep).collect();
+            crate::replace_input(params, input, kept);
+            Ok(None)
+        }
+        ContextPolicy::TruncateTail => {
+            let kept = input.chars().take(keep).collect();
+            crate::replace_input(params, input, kept);
+            Ok(None)
+        }
+        ContextPolicy::Chunk => Ok(Some(MapReduceSpec {
+            chunk_size: Some(keep),
+            overlap: Some(keep / 20),
+            ..Default::default()
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::collections::HashMap;
+
+    fn run(policy: &str) -> Result<Vec<crate::StepLog>, String> {
+        let dir = std::env::temp_dir().join(format!("lao_context_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("window.yaml");
+        std::fs::write(
+            &path,
+            format!(
+                "workflow: window\nsteps:\n  - run: EchoPlugin\n    input: \"aaaa bbbb cccc dddd\"\n    context:\n      max_tokens: 4\n      policy: {}\n",
+                policy
+            ),
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let workflow = crate::load_workflow_yaml(path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        let result = crate::run_workflow_with_registry(path, HashMap::new(), &registry);
+        std::fs::remove_dir_all(&dir).ok();
+        result
+    }
+
+    #[test]
+    fn test_context_policies() {
+        assert_eq!(estimate_tokens("aaaa bbbb"), 3);
+
+        let logs = run("fail").unwrap();
+        let error = logs[0].error.as_deref().unwrap();
+        assert!(error.contains("about 5 tokens"), "{}", error);
+
+        let logs = run("truncate_tail").unwrap();
+        assert_eq!(
+            logs[0].output.as_deref(),
+            Some("EchoPlugin(aaaa bbbb cccc d)")
+        );
+        assert_eq!(logs[0].input["input"], "aaaa bbbb cccc d");
+
+        let logs = run("truncate_head").unwrap();
+        assert_eq!(
+            logs[0].output.as_deref(),
+            Some("EchoPlugin(a bbbb cccc dddd)")
+        );
+
+        // Cut at the last space before 16 characters, then combined
+        let logs = run("chunk").unwrap();
+        let output = logs[0].output.as_deref().unwrap();
+        assert!(output.contains("EchoPlugin(aaaa bbbb cccc "), "{}", output);
+        assert!(output.contains("dddd)"), "{}", output);
+    }
+}
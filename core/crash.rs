@@ -0,0 +1,429 @@
+// Crash reports for panics and, optionally, native crashes inside plugins.
+// Where reports go is described in docs/observability.md (Crash Reports).
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CrashContext {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// "panic" or "native"
+    pub kind: String,
+    /// Missing for native crashes; use the file's modification time
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub thread: Option<String>,
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(default)]
+    pub backtrace: Option<String>,
+    #[serde(default)]
+    pub context: CrashContext,
+    pub lao_version: String,
+    pub os: String,
+    pub arch: String,
+    #[serde(default)]
+    pub command_line: Vec<String>,
+}
+
+impl CrashReport {
+    fn new(kind: &str, context: CrashContext) -> Self {
+        Self {
+            kind: kind.to_string(),
+            timestamp: None,
+            message: String::new(),
+            location: None,
+            thread: None,
+            signal: None,
+            backtrace: None,
+            context,
+            lao_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            command_line: std::env::args().map(|arg| redact(&arg)).collect(),
+        }
+    }
+
+    /// Plain-text form for `lao report last-crash`
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let when = self
+            .timestamp
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| "unknown".to_string());
+        out.push_str(&format!("{} crash at {}\n", self.kind, when));
+        if let Some(signal) = self.signal {
+            out.push_str(&format!("signal:   {}\n", signal));
+        }
+        if !self.message.is_empty() {
+            out.push_str(&format!("message:  {}\n", self.message));
+        }
+        if let Some(location) = &self.location {
+            out.push_str(&format!("location: {}\n", location));
+        }
+        if let Some(thread) = &self.thread {
+            out.push_str(&format!("thread:   {}\n", thread));
+        }
+        let context = &self.context;
+        for (label, value) in [
+            ("workflow", &context.workflow),
+            ("run", &context.run_id),
+            ("step", &context.step_id),
+            ("plugin", &context.plugin),
+        ] {
+            if let Some(value) = value {
+                out.push_str(&format!("{:<9} {}\n", format!("{}:", label), value));
+            }
+        }
+        out.push_str(&format!(
+            "lao:      {} ({}/{})\n",
+            self.lao_version, self.os, self.arch
+        ));
+        out.push_str(&format!("command:  {}\n", self.command_line.join(" ")));
+        if let Some(backtrace) = &self.backtrace {
+            out.push_str("\nbacktrace:\n");
+            out.push_str(backtrace);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    run_id: None,
+    workflow: None,
+    step_id: None,
+    plugin: None,
+});
+static CRASH_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// LAO_CRASH_DIR, defaulting to crashes/ in the data directory
+pub fn default_dir() -> PathBuf {
+    std::env::var("LAO_CRASH_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("crashes"))
+}
+
+/// Install the panic hook (and native handlers when LAO_NATIVE_CRASH=1).
+/// The previous hook still runs, so panics print as usual.
+pub fn install(dir: impl Into<PathBuf>) {
+    if CRASH_DIR.set(dir.into()).is_err() {
+        return;
+    }
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let mut report = CrashReport::new("panic", current_context());
+        report.timestamp = Some(Utc::now());
+        report.message = redact(&panic_message(info));
+        report.location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+        report.thread = std::thread::current().name().map(str::to_string);
+        report.backtrace = Some(redact(
+            &std::backtrace::Backtrace::force_capture().to_string(),
+        ));
+        if let Some(path) = write_report(&report) {
+            eprintln!(
+                "[crash] report written to {}; run `lao report last-crash` to view it",
+                path.display()
+            );
+        }
+        previous(info);
+    }));
+
+    #[cfg(unix)]
+    if std::env::var("LAO_NATIVE_CRASH").is_ok_and(|v| v == "1" || v == "true") {
+        native::install();
+    }
+}
+
+fn panic_message(info: &std::panic::PanicHookInfo<'_>) -> String {
+    if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panic with non-string payload".to_string()
+    }
+}
+
+fn write_report(report: &CrashReport) -> Option<PathBuf> {
+    let dir = CRASH_DIR.get()?;
+    fs::create_dir_all(dir).ok()?;
+    let path = dir.join(format!(
+        "crash-{}-{}.json",
+        Utc::now().format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    ));
+    let json = serde_json::to_string_pretty(report).ok()?;
+    fs::write(&path, json).ok()?;
+    Some(path)
+}
+
+pub fn current_context() -> CrashContext {
+    CONTEXT.lock().map(|c| c.clone()).unwrap_or_default()
+}
+
+fn update_context(f: impl FnOnce(&mut CrashContext)) {
+    let Ok(mut context) = CONTEXT.lock() else {
+        return;
+    };
+    f(&mut context);
+    #[cfg(unix)]
+    native::prepare(&context);
+}
+
+/// Marks a run as active until dropped
+pub struct RunScope(());
+
+impl RunScope {
+    pub fn enter(run_id: &str, workflow: &str) -> Self {
+        update_context(|c| {
+            *c = CrashContext {
+                run_id: Some(run_id.to_string()),
+                workflow: Some(workflow.to_string()),
+                step_id: None,
+                plugin: None,
+            }
+        });
+        RunScope(())
+    }
+
+    /// The step and plugin that a crash from here on would be blamed on
+    pub fn step(&self, step_id: &str, plugin: &str) {
+        update_context(|c| {
+            c.step_id = Some(step_id.to_string());
+            c.plugin = Some(plugin.to_string());
+        });
+    }
+}
+
+impl Drop for RunScope {
+    fn drop(&mut self) {
+        update_context(|c| *c = CrashContext::default());
+    }
+}
+
+/// Hide values following secret-looking keys (`token=...`, `"password": "..."`,
+/// `Bearer ...`) and replace the home directory with `~`
+pub fn redact(text: &str) -> String {
+    let out = crate::redaction::redact_keyed_secrets(text);
+    match home_dir() {
+        Some(home) if home.len() > 1 => out.replace(&home, "~"),
+        _ => out,
+    }
+}
+
+fn home_dir() -> Option<String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+}
+
+/// The newest report in `dir` and its path
+pub fn last_crash(dir: &Path) -> std::io::Result<Option<(PathBuf, CrashReport)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let newest = entries
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().starts_with("crash-"))
+        .filter_map(|e| Some((e.metadata().and_then(|m| m.modified()).ok()?, e.path())))
+        .max_by_key(|(modified, _)| *modified);
+    let Some((modified, path)) = newest else {
+        return Ok(None);
+    };
+    let content = fs::read_to_string(&path)?;
+    let mut report: CrashReport = serde_json::from_str(&content)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if report.timestamp.is_none() {
+        report.timestamp = Some(DateTime::<Utc>::from(modified));
+    }
+    Ok(Some((path, report)))
+}
+
+#[cfg(unix)]
+mod native {
+    use super::{CrashContext, CrashReport, CRASH_DIR};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    const BUFFER_SIZE: usize = 8192;
+    const SIGNALS: &[libc::c_int] = &[
+        libc::SIGSEGV,
+        libc::SIGBUS,
+        libc::SIGILL,
+        libc::SIGFPE,
+        libc::SIGABRT,
+    ];
+
+    // Pre-rendered report up to `"signal":`, and the NUL-terminated path to write it to.
+    // Only written from `prepare` (under the context lock) and read by the handler.
+    static mut REPORT: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
+    static REPORT_LEN: AtomicUsize = AtomicUsize::new(0);
+    static mut PATH: [u8; 1024] = [0; 1024];
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+    pub(super) fn install() {
+        let Some(dir) = CRASH_DIR.get() else {
+            return;
+        };
+        if std::fs::create_dir_all(dir).is_err() {
+            return;
+        }
+        let path = dir.join(format!("crash-native-{}.json", std::process::id()));
+        let bytes = path.to_string_lossy().into_owned().into_bytes();
+        if bytes.len() >= 1024 {
+            return;
+        }
+        unsafe {
+            let target = &mut *std::ptr::addr_of_mut!(PATH);
+            target[..bytes.len()].copy_from_slice(&bytes);
+            target[bytes.len()] = 0;
+        }
+        INSTALLED.store(true, Ordering::SeqCst);
+        prepare(&super::current_context());
+
+        for &signal in SIGNALS {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handler as *const () as usize;
+                action.sa_flags = libc::SA_RESETHAND;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, std::ptr::null_mut());
+            }
+        }
+    }
+
+    pub(super) fn prepare(context: &CrashContext) {
+        if !INSTALLED.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut report = CrashReport::new("native", context.clone());
+        report.message = "native crash (signal)".to_string();
+        let Ok(json) = serde_json::to_string(&report) else {
+            return;
+        };
+        // Drop the closing brace so the handler can append the signal number
+        let prefix = format!("{},\"signal\":", &json[..json.len() - 1]);
+        if prefix.len() > BUFFER_SIZE {
+            return;
+        }
+        REPORT_LEN.store(0, Ordering::SeqCst);
+        unsafe {
+            let target = &mut *std::ptr::addr_of_mut!(REPORT);
+            target[..prefix.len()].copy_from_slice(prefix.as_bytes());
+        }
+        REPORT_LEN.store(prefix.len(), Ordering::SeqCst);
+    }
+
+    // Only async-signal-safe calls: open, write, close, raise
+    extern "C" fn handler(signal: libc::c_int) {
+        unsafe {
+            let len = REPORT_LEN.load(Ordering::SeqCst);
+            let path = std::ptr::addr_of!(PATH) as *const libc::c_char;
+            let fd = libc::open(
+                path,
+                libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+                0o600,
+            );
+            if fd >= 0 && len > 0 {
+                libc::write(fd, std::ptr::addr_of!(REPORT) as *const libc::c_void, len);
+                let mut digits = [0u8; 12];
+                let mut n = signal.unsigned_abs();
+                let mut start = digits.len() - 2;
+                digits[digits.len() - 2] = b'}';
+                digits[digits.len() - 1] = b'\n';
+                loop {
+                    start -= 1;
+                    digits[start] = b'0' + (n % 10) as u8;
+                    n /= 10;
+                    if n == 0 {
+                        break;
+                    }
+                }
+                libc::write(
+                    fd,
+                    digits[start..].as_ptr() as *const libc::c_void,
+                    digits.len() - start,
+                );
+            }
+            if fd >= 0 {
+                libc::close(fd);
+            }
+            // SA_RESETHAND restored the default action; re-raise to crash as usual
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_secrets() {
+        let redacted = redact(
+            "calling api_key=sk-123 with {\"password\": \"hunter2\"} and Authorization: Bearer abc.def",
+        );
+        assert!(!redacted.contains("sk-123"));
+        assert!(!redacted.contains("hunter2"));
+        assert!(!redacted.contains("abc.def"));
+        assert!(redacted.contains("api_key=[REDACTED]"));
+        assert_eq!(redact("no secrets here"), "no secrets here");
+    }
+
+    #[test]
+    fn test_last_crash_reads_newest_report() {
+        let dir = std::env::temp_dir().join(format!("lao-crash-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        assert!(last_crash(&dir).unwrap().is_none());
+
+        let scope = RunScope::enter("run-1", "summarize");
+        scope.step("step2", "OllamaPlugin");
+        let mut report = CrashReport::new("panic", current_context());
+        drop(scope);
+        report.message = "index out of bounds".to_string();
+        fs::write(
+            dir.join("crash-20260101-000000-1.json"),
+            serde_json::to_string(&report).unwrap(),
+        )
+        .unwrap();
+        // Native reports are written without a timestamp
+        let native = "{\"kind\":\"native\",\"lao_version\":\"0.1.20\",\"os\":\"linux\",\"arch\":\"x86_64\",\"signal\":11}\n";
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(dir.join("crash-native-2.json"), native).unwrap();
+
+        let (_, last) = last_crash(&dir).unwrap().unwrap();
+        assert_eq!(last.kind, "native");
+        assert_eq!(last.signal, Some(11));
+        assert!(last.timestamp.is_some());
+
+        fs::remove_file(dir.join("crash-native-2.json")).unwrap();
+        let (_, last) = last_crash(&dir).unwrap().unwrap();
+        assert_eq!(last.context.step_id.as_deref(), Some("step2"));
+        assert_eq!(last.context.plugin.as_deref(), Some("OllamaPlugin"));
+        assert!(last.render().contains("plugin:   OllamaPlugin"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,7 +2,7 @@
 // Provides platform detection, path handling, and OS-specific functionality
 
 use std::env;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
 
 /// Platform detection utilities
 pub struct Platform;
@@ -70,6 +70,29 @@ impl Platform {
         }
     }
 
+    /// File name a plugin crate builds to on this platform, e.g.
+    /// `libecho_plugin.so`, `libecho_plugin.dylib` or `echo_plugin.dll`
+    pub fn shared_lib_name(stem: &str) -> String {
+        format!(
+            "{}{}.{}",
+            Self::shared_lib_prefix(),
+            stem,
+            Self::shared_lib_extension()
+        )
+    }
+
+    /// Crate name behind a shared library file built on any platform, so
+    /// `libecho_plugin.so` and `echo_plugin.dll` both give `echo_plugin`
+    pub fn shared_lib_stem(path: &Path) -> Option<&str> {
+        let ext = path.extension()?.to_str()?;
+        let stem = path.file_stem()?.to_str()?;
+        match ext {
+            "dll" => Some(stem),
+            "so" | "dylib" => Some(stem.strip_prefix("lib").unwrap_or(stem)),
+            _ => None,
+        }
+    }
+
     /// Get the executable extension for current platform
     pub fn exe_extension() -> &'static str {
         match Self::os() {
@@ -118,9 +141,47 @@ impl Platform {
 pub struct PathUtils;
 
 impl PathUtils {
-    /// Normalize a path for the current platform
+    /// Normalize a path for the current platform: expand a leading `~`, use
+    /// this platform's separator and resolve `.` and `..` lexically, without
+    /// touching the filesystem
     pub fn normalize(path: &Path) -> PathBuf {
-        path.to_path_buf()
+        PathBuf::from(Self::normalize_str(&path.to_string_lossy()))
+    }
+
+    /// `normalize` for a path written in a workflow or manifest, which may
+    /// have been written on another OS. URLs are returned unchanged.
+    pub fn normalize_str(path: &str) -> String {
+        if path.is_empty() || path.contains("://") {
+            return path.to_string();
+        }
+        let foreign = if cfg!(windows) { '/' } else { '\\' };
+        let mut text = path.replace(foreign, MAIN_SEPARATOR_STR);
+        if text == "~" || text.starts_with(&format!("~{}", MAIN_SEPARATOR)) {
+            if let Some(home) = Platform::home_dir() {
+                text = format!("{}{}", home.display(), &text[1..]);
+            }
+        }
+
+        let mut normalized = PathBuf::new();
+        for component in Path::new(&text).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => match normalized.components().next_back() {
+                    Some(Component::Normal(_)) => {
+                        normalized.pop();
+                    }
+                    // `..` above the root is the root
+                    Some(Component::RootDir | Component::Prefix(_)) => {}
+                    _ => normalized.push(".."),
+                },
+                other => normalized.push(other.as_os_str()),
+            }
+        }
+        if normalized.as_os_str().is_empty() {
+            ".".to_string()
+        } else {
+            normalized.to_string_lossy().into_owned()
+        }
     }
 
     /// Join paths in a cross-platform way
@@ -131,8 +192,8 @@ impl PathUtils {
     /// Get the LAO plugin directory
     pub fn plugin_dir() -> PathBuf {
         // Try environment variable first
-        if let Ok(plugin_dir) = env::var("LAO_PLUGIN_DIR") {
-            return PathBuf::from(plugin_dir);
+        if let Some(dir) = Self::plugin_dirs_from_env().into_iter().next() {
+            return dir;
         }
 
         // Get current directory
@@ -155,6 +216,42 @@ impl PathUtils {
         }
     }
 
+    fn plugin_dirs_from_env() -> Vec<PathBuf> {
+        env::var_os("LAO_PLUGIN_DIR")
+            .map(|dirs| {
+                env::split_paths(&dirs)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .map(|dir| Self::normalize(&dir))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Per-user plugin directory: `~/.local/share/lao/plugins` on Linux,
+    /// `~/Library/Application Support/lao/plugins` on macOS and
+    /// `%LOCALAPPDATA%\lao\plugins` on Windows
+    pub fn user_plugin_dir() -> Option<PathBuf> {
+        Platform::data_dir().map(|dir| dir.join("lao").join("plugins"))
+    }
+
+    /// Every directory plugins are loaded from, in priority order.
+    /// `LAO_PLUGIN_DIR` may list several directories separated the way PATH
+    /// is on this platform; otherwise the project plugin directory comes
+    /// first, then the per-user one.
+    pub fn plugin_dirs() -> Vec<PathBuf> {
+        let dirs = Self::plugin_dirs_from_env();
+        if !dirs.is_empty() {
+            return dirs;
+        }
+        let mut dirs = vec![Self::plugin_dir()];
+        if let Some(user_dir) = Self::user_plugin_dir() {
+            if !dirs.contains(&user_dir) {
+                dirs.push(user_dir);
+            }
+        }
+        dirs
+    }
+
     /// Get the LAO cache directory
     pub fn cache_dir() -> PathBuf {
         // Try environment variable first
@@ -210,12 +307,9 @@ impl EnvUtils {
 
     /// Add a directory to PATH (for current process)
     pub fn add_to_path(dir: &Path) -> Result<(), String> {
-        let current_path = env::var("PATH").unwrap_or_default();
-        let new_path = if current_path.is_empty() {
-            dir.to_string_lossy().to_string()
-        } else {
-            format!("{}:{}", dir.to_string_lossy(), current_path)
-        };
+        let current_path = env::var_os("PATH").unwrap_or_default();
+        let dirs = std::iter::once(dir.to_path_buf()).chain(env::split_paths(&current_path));
+        let new_path = env::join_paths(dirs).map_err(|e| e.to_string())?;
 
         env::set_var("PATH", new_path);
         Ok(())
@@ -254,4 +348,55 @@ mod tests {
         let cache_dir = PathUtils::cache_dir();
         assert!(!cache_dir.to_string_lossy().is_empty());
     }
+
+    #[test]
+    fn test_plugin_library_names() {
+        let name = Platform::shared_lib_name("echo_plugin");
+        assert!(Platform::is_shared_lib_file(Path::new(&name)));
+        assert_eq!(
+            Platform::shared_lib_stem(Path::new(&name)),
+            Some("echo_plugin")
+        );
+        for built in [
+            "libecho_plugin.so",
+            "libecho_plugin.dylib",
+            "echo_plugin.dll",
+        ] {
+            assert_eq!(
+                Platform::shared_lib_stem(Path::new(built)),
+                Some("echo_plugin")
+            );
+        }
+        assert_eq!(
+            Platform::shared_lib_stem(Path::new("echo_plugin.yaml")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_normalize_portable_paths() {
+        let sep = MAIN_SEPARATOR_STR;
+        let expected = ["data", "audio", "a.wav"].join(sep);
+        assert_eq!(PathUtils::normalize_str("data/audio/a.wav"), expected);
+        assert_eq!(PathUtils::normalize_str("data\\audio\\a.wav"), expected);
+        assert_eq!(
+            PathUtils::normalize_str("./data/tmp/../audio/a.wav"),
+            expected
+        );
+        assert_eq!(
+            PathUtils::normalize_str("../a.wav"),
+            format!("..{}a.wav", sep)
+        );
+        assert_eq!(PathUtils::normalize_str("a/.."), ".");
+        assert_eq!(
+            PathUtils::normalize_str("https://example.com/a.wav"),
+            "https://example.com/a.wav"
+        );
+        if let Some(home) = Platform::home_dir() {
+            assert_eq!(
+                PathUtils::normalize(Path::new("~/a.wav")),
+                home.join("a.wav")
+            );
+        }
+    }
 }
@@ -0,0 +1,470 @@
+// Local run API of `lao daemon`, which `lao run` delegates runs to when the
+// daemon has the same working directory, data directory and environment.
+use crate::cancel::{self, CancelToken};
+use crate::channel::Overflow;
+use crate::logging::{self, LogEvent, LogLevel};
+use crate::run_class::{self, RunClass};
+use crate::run_labels::{self, RunLabels};
+use crate::webhook::{read_request, write_response, HttpResponse};
+use crate::{run_workflow_yaml_with_inputs, StepLog};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+// A daemon that can't accept a connection this fast isn't worth waiting for
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(300);
+// Events an event stream client may fall behind by before losing the oldest
+const EVENT_STREAM_CAPACITY: usize = 1024;
+// An empty line this often tells a quiet stream from a closed one
+const EVENT_KEEPALIVE: Duration = Duration::from_secs(15);
+
+static TRIGGERS_PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Hold back (or let through again) the runs triggers start in this process
+pub fn set_triggers_paused(paused: bool) {
+    TRIGGERS_PAUSED.store(paused, Ordering::Relaxed);
+    crate::logging::info(if paused {
+        "triggers paused"
+    } else {
+        "triggers resumed"
+    })
+    .emit();
+}
+
+pub fn triggers_paused() -> bool {
+    TRIGGERS_PAUSED.load(Ordering::Relaxed)
+}
+
+/// What a running daemon publishes for clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    pub addr: String,
+    pub pid: u32,
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunRequest {
+    pub path: String,
+    #[serde(default)]
+    pub inputs: HashMap<String, String>,
+    #[serde(default)]
+    pub class: RunClass,
+    #[serde(default)]
+    pub labels: RunLabels,
+    #[serde(default)]
+    pub context: RunContext,
+}
+
+/// What a run depends on besides its workflow; the daemon only takes runs
+/// whose context matches its own
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunContext {
+    pub cwd: PathBuf,
+    pub data_dir: PathBuf,
+    pub env: String,
+}
+
+impl RunContext {
+    pub fn current() -> Self {
+        Self {
+            cwd: std::env::current_dir().unwrap_or_default(),
+            data_dir: crate::data_dir::root(),
+            env: crate::env_vars::fingerprint(),
+        }
+    }
+
+    // Why a run from `other` would go differently here
+    fn mismatch(&self, other: &RunContext) -> Option<String> {
+        if self.cwd != other.cwd {
+            Some(format!("daemon runs in {}", self.cwd.display()))
+        } else if self.data_dir != other.data_dir {
+            Some(format!(
+                "daemon uses data directory {}",
+                self.data_dir.display()
+            ))
+        } else if self.env != other.env {
+            Some("daemon sees other environment variables".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunResponse {
+    Logs(Vec<StepLog>),
+    Error(String),
+}
+
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_DAEMON_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("daemon").join("daemon.json"))
+}
+
+/// Serve the run API on a background thread and publish it at `info_path`.
+/// Returns the bound address, useful when `addr` asked for port 0.
+pub fn serve<A: ToSocketAddrs>(addr: A, info_path: &Path) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let info = DaemonInfo {
+        addr: local_addr.to_string(),
+        pid: std::process::id(),
+        token: uuid::Uuid::new_v4().simple().to_string(),
+    };
+    write_info(info_path, &info)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let token = info.token.clone();
+            thread::spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                let mut request = RequestSummary::default();
+                if let Err(e) = handle_connection(stream, &token, &mut request) {
+                    let mut event = logging::warn("daemon request failed")
+                        .field("peer", peer)
+                        .field("method", request.method)
+                        .field("path", request.path)
+                        .field("error", e.to_string());
+                    if let Some((workflow, class)) = request.run {
+                        event = event
+                            .field("workflow", workflow)
+                            .field("class", class.as_str());
+                    }
+                    event.emit();
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+// Only the daemon's user may read the token
+fn write_info(path: &Path, info: &DaemonInfo) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(info).map_err(std::io::Error::other)?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(json.as_bytes())
+}
+
+// The request a connection made, as far as it got, for the warning when it
+// fails
+#[derive(Default)]
+struct RequestSummary {
+    method: String,
+    path: String,
+    /// Workflow path and class of a run request
+    run: Option<(String, RunClass)>,
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    token: &str,
+    summary: &mut RequestSummary,
+) -> std::io::Result<()> {
+    let response = match read_request(&stream)? {
+        Err(rejected) => rejected,
+        Ok(request) => {
+            summary.method = request.method.clone();
+            summary.path = request.path.clone();
+            let authorized = request
+                .headers
+                .get("authorization")
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .is_some_and(|t| t == token);
+            match (request.method.as_str(), request.path.as_str()) {
+                _ if !authorized => HttpResponse::text(401, "missing or wrong token\n"),
+                ("GET", "/events") => return stream_events(stream),
+                ("POST", "/runs") => match serde_json::from_slice::<RunRequest>(&request.body) {
+                    Ok(run) => {
+                        summary.run = Some((run.path.clone(), run.class));
+                        let response = run_request(run, &stream);
+                        write_response(&mut stream, &response)?;
+                        // Also ends the disconnect watch of the run
+                        return stream.shutdown(Shutdown::Both);
+                    }
+                    Err(e) => HttpResponse::text(400, format!("invalid run request: {}\n", e)),
+                },
+                (_, "/runs") => HttpResponse::text(405, "use POST\n"),
+                _ => HttpResponse::text(404, "not found\n"),
+            }
+        }
+    };
+    write_response(&mut stream, &response)
+}
+
+fn run_request(run: RunRequest, stream: &TcpStream) -> HttpResponse {
+    if let Some(reason) = RunContext::current().mismatch(&run.context) {
+        return HttpResponse::text(409, format!("{}\n", reason));
+    }
+    crate::metrics::global().record_trigger("cli");
+    crate::logging::info("daemon running workflow for the CLI")
+        .field("path", run.path.clone())
+        .field("class", run.class.as_str())
+        .emit();
+    // The client sends nothing more, so a read only returns once it is gone
+    let token = CancelToken::new();
+    if let Ok(mut watched) = stream.try_clone() {
+        let token = token.clone();
+        thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            if matches!(watched.read(&mut byte), Ok(0) | Err(_)) {
+                token.cancel();
+            }
+        });
+    }
+    let result = cancel::with_token(token, || {
+        run_class::with_class(run.class, || {
+            run_labels::with_labels(run.labels, || {
+                run_workflow_yaml_with_inputs(&run.path, run.inputs)
+            })
+        })
+    });
+    let response = match result {
+        Ok(logs) => RunResponse::Logs(logs),
+        Err(e) => RunResponse::Error(e),
+    };
+    HttpResponse::json(
+        200,
+        serde_json::to_value(&response).unwrap_or(serde_json::Value::Null),
+    )
+}
+
+// Runs until the client disconnects
+fn stream_events(mut stream: TcpStream) -> std::io::Result<()> {
+    let events = logging::subscribe_with(EVENT_STREAM_CAPACITY, Overflow::DropOldest);
+    stream.set_write_timeout(Some(EVENT_KEEPALIVE))?;
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    )?;
+    loop {
+        let event = match events.recv_timeout(EVENT_KEEPALIVE) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        let dropped = events.take_dropped();
+        let mut lines = String::new();
+        if dropped > 0 {
+            let notice = LogEvent::new(LogLevel::Warn, "events dropped for a slow reader")
+                .field("dropped", dropped);
+            lines.push_str(&notice.to_json_line());
+            lines.push('\n');
+        }
+        if let Some(event) = event {
+            lines.push_str(&event.to_json_line());
+        }
+        lines.push('\n');
+        // A failed write means the client went away; dropping `events` unsubscribes
+        if stream.write_all(lines.as_bytes()).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// The daemon published at `info_path`, if the file exists
+pub fn discover(info_path: &Path) -> Option<DaemonInfo> {
+    let text = fs::read_to_string(info_path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Run `path` on the daemon published at `info_path`. `None` means no daemon
+/// could be reached and the caller should run the workflow itself. The run
+/// keeps the calling thread's class and labels.
+pub fn try_run(
+    info_path: &Path,
+    path: &str,
+    inputs: HashMap<String, String>,
+) -> Option<Result<Vec<StepLog>, String>> {
+    let info = discover(info_path)?;
+    let addr: SocketAddr = info.addr.parse().ok()?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).ok()?;
+
+    // The daemon has its own working directory
+    let path = fs::canonicalize(path).ok()?.to_string_lossy().to_string();
+    let body = serde_json::to_string(&RunRequest {
+        path,
+        inputs,
+        class: run_class::current(),
+        labels: run_labels::current(),
+        context: RunContext::current(),
+    })
+    .ok()?;
+    match send_run(&mut stream, &info, &body) {
+        Err(Declined(reason)) => {
+            crate::logging::info("daemon declined the run; running it here")
+                .field("reason", reason)
+                .emit();
+            None
+        }
+        Ok(result) => Some(result),
+    }
+}
+
+// The daemon's answer when the run would go differently there
+struct Declined(String);
+
+fn send_run(
+    stream: &mut TcpStream,
+    info: &DaemonInfo,
+    body: &str,
+) -> Result<Result<Vec<StepLog>, String>, Declined> {
+    let io = |e: std::io::Error| format!("daemon at {} stopped responding: {}", info.addr, e);
+    let sent = write!(
+        stream,
+        "POST /runs HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        info.addr,
+        info.token,
+        body.len(),
+        body
+    )
+    .map_err(io);
+    let mut reply = String::new();
+    if let Err(e) = sent.and_then(|_| stream.read_to_string(&mut reply).map_err(io)) {
+        return Ok(Err(e));
+    }
+
+    let (head, body) = reply.split_once("\r\n\r\n").unwrap_or((&reply, ""));
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if status == "409" {
+        return Err(Declined(body.trim().to_string()));
+    }
+    if status != "200" {
+        return Ok(Err(format!(
+            "daemon at {} refused the run ({}): {}",
+            info.addr,
+            status,
+            body.trim()
+        )));
+    }
+    Ok(match serde_json::from_str(body) {
+        Ok(RunResponse::Logs(logs)) => Ok(logs),
+        Ok(RunResponse::Error(e)) => Err(e),
+        Err(e) => Err(format!("unreadable reply from daemon: {}", e)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_is_delegated_only_to_a_live_daemon() {
+        let dir = std::env::temp_dir().join(format!("lao_daemon_{}", uuid::Uuid::new_v4()));
+        let info_path = dir.join("daemon.json");
+        let workflow = dir.join("missing_plugin.yaml");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            &workflow,
+            "workflow: remote\nsteps:\n  - run: NoSuchPlugin\n",
+        )
+        .unwrap();
+        let workflow = workflow.to_string_lossy().to_string();
+
+        // No daemon published yet
+        assert!(try_run(&info_path, &workflow, HashMap::new()).is_none());
+
+        serve("127.0.0.1:0", &info_path).unwrap();
+        let result = try_run(&info_path, &workflow, HashMap::new()).unwrap();
+        assert!(result.unwrap_err().contains("NoSuchPlugin"));
+
+        // A wrong token is rejected rather than silently run locally
+        let mut info = discover(&info_path).unwrap();
+        info.token = "wrong".to_string();
+        write_info(&info_path, &info).unwrap();
+        let result = try_run(&info_path, &workflow, HashMap::new()).unwrap();
+        assert!(result.unwrap_err().contains("401"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_runs_from_another_context_are_declined() {
+        let dir = std::env::temp_dir().join(format!("lao_daemon_{}", uuid::Uuid::new_v4()));
+        let info_path = dir.join("daemon.json");
+        serve("127.0.0.1:0", &info_path).unwrap();
+        let info = discover(&info_path).unwrap();
+
+        let mut context = RunContext::current();
+        context.cwd = dir.join("elsewhere");
+        let body = serde_json::to_string(&RunRequest {
+            path: "flow.yaml".to_string(),
+            inputs: HashMap::new(),
+            class: RunClass::default(),
+            labels: RunLabels::default(),
+            context,
+        })
+        .unwrap();
+        let mut stream = TcpStream::connect(&info.addr).unwrap();
+        match send_run(&mut stream, &info, &body) {
+            Err(Declined(reason)) => assert!(reason.contains("daemon runs in")),
+            Ok(result) => panic!("run was not declined: {:?}", result),
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_event_stream_delivers_log_events() {
+        use std::io::{BufRead, BufReader};
+
+        let dir = std::env::temp_dir().join(format!("lao_daemon_{}", uuid::Uuid::new_v4()));
+        let info_path = dir.join("daemon.json");
+        serve("127.0.0.1:0", &info_path).unwrap();
+        let info = discover(&info_path).unwrap();
+
+        let mut stream = TcpStream::connect(&info.addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(
+            stream,
+            "GET /events HTTP/1.1\r\nAuthorization: Bearer {}\r\n\r\n",
+            info.token
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 200"));
+        // The client is subscribed once the headers arrive
+        while line.trim() != "" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        logging::info("streamed")
+            .field("test", "event_stream")
+            .emit();
+        let event = reader
+            .lines()
+            .map(|l| l.unwrap())
+            .filter(|l| !l.is_empty())
+            .map(|l| serde_json::from_str::<LogEvent>(&l).unwrap())
+            .find(|e| e.field_str("test") == Some("event_stream"))
+            .unwrap();
+        assert_eq!(event.message, "streamed");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
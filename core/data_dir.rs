@@ -0,0 +1,104 @@
+// Where LAO keeps each OS user's state and config (`--data-dir`,
+// LAO_DATA_DIR); project files stay in the working directory.
+// What lives where is listed in docs/cli.md (Data directory).
+use crate::cross_platform::Platform;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `dir` as the data directory of this process; later calls are ignored
+pub fn init(dir: impl Into<PathBuf>) {
+    let _ = ROOT.set(dir.into());
+}
+
+/// The data directory: `init`'s, LAO_DATA_DIR, the user's platform data
+/// directory, or the working directory when there is no home
+pub fn root() -> PathBuf {
+    resolve(
+        ROOT.get().cloned(),
+        std::env::var_os("LAO_DATA_DIR").map(PathBuf::from),
+        Platform::data_dir(),
+    )
+}
+
+fn resolve(
+    initialized: Option<PathBuf>,
+    from_env: Option<PathBuf>,
+    platform: Option<PathBuf>,
+) -> PathBuf {
+    initialized
+        .or(from_env)
+        .or_else(|| platform.map(|dir| dir.join("lao")))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// `relative` inside the data directory
+pub fn path(relative: impl AsRef<Path>) -> PathBuf {
+    root().join(relative)
+}
+
+/// Create the data directory if needed, readable only by this user
+pub fn prepare() -> Result<PathBuf, String> {
+    let root = root();
+    create_private(&root)?;
+    Ok(root)
+}
+
+fn create_private(dir: &Path) -> Result<(), String> {
+    if dir.exists() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    restrict(dir)
+}
+
+#[cfg(unix)]
+fn restrict(dir: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+        .map_err(|e| format!("Cannot restrict {}: {}", dir.display(), e))
+}
+
+// Directories under the user's profile are already private on Windows
+#[cfg(not(unix))]
+fn restrict(_dir: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_is_per_user_unless_overridden() {
+        let platform = Some(PathBuf::from("/home/alice/.local/share"));
+        let service = Some(PathBuf::from("/var/lib/lao"));
+        assert_eq!(
+            resolve(None, None, platform.clone()),
+            PathBuf::from("/home/alice/.local/share/lao")
+        );
+        assert_eq!(
+            resolve(None, service.clone(), platform.clone()),
+            PathBuf::from("/var/lib/lao")
+        );
+        assert_eq!(
+            resolve(Some(PathBuf::from("data")), service, platform),
+            PathBuf::from("data")
+        );
+        assert_eq!(resolve(None, None, None), PathBuf::from("."));
+
+        let dir = std::env::temp_dir().join(format!("lao_data_{}", uuid::Uuid::new_v4()));
+        create_private(&dir.join("lao")).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.join("lao"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,199 @@
+// Step deduplication within a run: memoizable steps making the same call
+// share the first one's output. LAO_DEDUP=off disables it.
+use lao_plugin_api::ArtifactRef;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// What makes two steps' plugin calls the same
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Call {
+    plugin: String,
+    backend: Option<String>,
+    params: String,
+    input: String,
+}
+
+impl Call {
+    /// `params` are the step's params; their `input` is taken from `input`,
+    /// the rendered text the plugin gets
+    pub fn new(
+        plugin: &str,
+        backend: Option<&str>,
+        params: &serde_yaml::Value,
+        input: &str,
+    ) -> Self {
+        let mut params = params.clone();
+        if let Some(mapping) = params.as_mapping_mut() {
+            mapping.remove("input");
+        }
+        Self {
+            plugin: plugin.to_string(),
+            backend: backend.map(str::to_string),
+            params: serde_json::to_string(&params).unwrap_or_default(),
+            input: input.to_string(),
+        }
+    }
+}
+
+/// Output of the step that first made a call
+#[derive(Debug, Clone)]
+pub struct SharedOutput {
+    pub step_id: String,
+    pub output: String,
+    pub artifact: Option<ArtifactRef>,
+    took: Duration,
+}
+
+/// Calls made so far in one run
+#[derive(Debug, Default)]
+pub struct RunDedup {
+    enabled: bool,
+    seen: HashMap<Call, SharedOutput>,
+    /// Steps answered with another step's output
+    pub shared: usize,
+    /// Plugin time those steps would have taken
+    pub saved: Duration,
+}
+
+impl RunDedup {
+    pub fn new() -> Self {
+        Self {
+            enabled: enabled_from_env(),
+            ..Default::default()
+        }
+    }
+
+    /// Output of an earlier step that made `call`
+    pub fn lookup(&mut self, call: &Call) -> Option<SharedOutput> {
+        if !self.enabled {
+            return None;
+        }
+        let shared = self.seen.get(call)?;
+        self.shared += 1;
+        self.saved += shared.took;
+        Some(shared.clone())
+    }
+
+    /// Remember a successful call; `took` is the plugin time it cost
+    pub fn record(
+        &mut self,
+        call: &Call,
+        step_id: &str,
+        output: &str,
+        artifact: Option<ArtifactRef>,
+        took: Duration,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        self.seen
+            .entry(call.clone())
+            .or_insert_with(|| SharedOutput {
+                step_id: step_id.to_string(),
+                output: output.to_string(),
+                artifact,
+                took,
+            });
+    }
+}
+
+fn enabled_from_env() -> bool {
+    !matches!(
+        std::env::var("LAO_DEDUP").as_deref(),
+        Ok("0" | "off" | "false" | "no")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dedup() -> RunDedup {
+        RunDedup {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn call(plugin: &str, input: &str) -> Call {
+        Call::new(plugin, None, &serde_yaml::Value::Null, input)
+    }
+
+    fn params(yaml: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_duplicate_shares_the_first_output() {
+        let mut dedup = dedup();
+        let boilerplate = call("Summarizer", "boilerplate");
+        assert!(dedup.lookup(&boilerplate).is_none());
+        dedup.record(
+            &boilerplate,
+            "chunk1",
+            "legal notice",
+            None,
+            Duration::from_millis(40),
+        );
+        let shared = dedup.lookup(&boilerplate).unwrap();
+        assert_eq!(shared.step_id, "chunk1");
+        assert_eq!(shared.output, "legal notice");
+        assert_eq!(dedup.shared, 1);
+        assert_eq!(dedup.saved, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_later_record_keeps_the_first_output() {
+        let mut dedup = dedup();
+        let boilerplate = call("Summarizer", "boilerplate");
+        dedup.record(&boilerplate, "chunk1", "legal notice", None, Duration::ZERO);
+        dedup.record(&boilerplate, "chunk3", "other", None, Duration::ZERO);
+        assert_eq!(dedup.lookup(&boilerplate).unwrap().step_id, "chunk1");
+    }
+
+    #[test]
+    fn test_other_plugin_or_input_is_not_a_duplicate() {
+        let mut dedup = dedup();
+        dedup.record(
+            &call("Summarizer", "boilerplate"),
+            "chunk1",
+            "legal notice",
+            None,
+            Duration::ZERO,
+        );
+        assert!(dedup.lookup(&call("Classifier", "boilerplate")).is_none());
+        assert!(dedup.lookup(&call("Summarizer", "chunk two")).is_none());
+        assert!(dedup.lookup(&call("Summarizer", "boilerplate ")).is_none());
+    }
+
+    #[test]
+    fn test_other_backend_or_params_are_not_duplicates() {
+        let mut dedup = dedup();
+        let short = params("{input: '${{ doc }}', style: short}");
+        dedup.record(
+            &Call::new("Summarizer", Some("ollama@gpu-box"), &short, "text"),
+            "a",
+            "summary",
+            None,
+            Duration::ZERO,
+        );
+        let other_backend = Call::new("Summarizer", None, &short, "text");
+        let bullets = params("{input: text, style: bullets}");
+        let other_params = Call::new("Summarizer", Some("ollama@gpu-box"), &bullets, "text");
+        assert!(dedup.lookup(&other_backend).is_none());
+        assert!(dedup.lookup(&other_params).is_none());
+
+        // The `input` param is compared as rendered, not as written
+        let rendered = params("{input: text, style: short}");
+        let same = Call::new("Summarizer", Some("ollama@gpu-box"), &rendered, "text");
+        assert!(dedup.lookup(&same).is_some());
+    }
+
+    #[test]
+    fn test_disabled_never_shares() {
+        let mut dedup = RunDedup::default();
+        let boilerplate = call("Summarizer", "boilerplate");
+        dedup.record(&boilerplate, "chunk1", "legal notice", None, Duration::ZERO);
+        assert!(dedup.lookup(&boilerplate).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+// How a step is drawn in the UI graph.
+use serde::{Deserialize, Serialize};
+
+/// Colors of the named roles
+pub const ROLES: &[(&str, [u8; 3])] = &[
+    ("ingest", [0x00, 0x89, 0x7b]),
+    ("transform", [0xf5, 0x7c, 0x00]),
+    ("llm", [0x5e, 0x35, 0xb1]),
+    ("output", [0x1e, 0x88, 0xe5]),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepDisplay {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// A role from `ROLES` or `#rrggbb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+impl StepDisplay {
+    pub fn is_empty(&self) -> bool {
+        self.label.is_none() && self.icon.is_none() && self.color.is_none()
+    }
+
+    /// The color as RGB, if one is set and valid
+    pub fn rgb(&self) -> Option<[u8; 3]> {
+        self.color.as_deref().and_then(|c| parse_color(c).ok())
+    }
+
+    /// What is wrong with the settings, if anything
+    pub fn problem(&self) -> Option<String> {
+        self.color
+            .as_deref()
+            .and_then(|c| parse_color(c).err())
+            .map(|e| format!("display color {}", e))
+    }
+
+    /// The label, or `step_id` when there is none, after the icon if any
+    pub fn title(&self, step_id: &str) -> String {
+        let label = self.label.as_deref().unwrap_or(step_id);
+        match self.icon.as_deref() {
+            Some(icon) => format!("{} {}", icon, label),
+            None => label.to_string(),
+        }
+    }
+}
+
+/// A role name or `#rrggbb` as RGB
+pub fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let color = color.trim();
+    if let Some(&(_, rgb)) = ROLES
+        .iter()
+        .find(|(role, _)| role.eq_ignore_ascii_case(color))
+    {
+        return Ok(rgb);
+    }
+    let hex = color
+        .strip_prefix('#')
+        .filter(|h| h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| {
+            let roles: Vec<&str> = ROLES.iter().map(|(role, _)| *role).collect();
+            format!(
+                "'{}' is neither #rrggbb nor a role ({})",
+                color,
+                roles.join(", ")
+            )
+        })?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+/// `rgb` as `#rrggbb`
+pub fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_colors_and_title() {
+        assert_eq!(parse_color("LLM").unwrap(), [0x5e, 0x35, 0xb1]);
+        assert_eq!(parse_color("#FF8000").unwrap(), [255, 128, 0]);
+        assert_eq!(hex([255, 128, 0]), "#ff8000");
+        assert!(parse_color("#ff80").is_err());
+        assert!(parse_color("teal").unwrap_err().contains("ingest"));
+
+        let display: StepDisplay =
+            serde_yaml::from_str("label: Draft summary\nicon: \"🧠\"\ncolor: nope\n").unwrap();
+        assert_eq!(display.title("step3"), "🧠 Draft summary");
+        assert_eq!(display.rgb(), None);
+        assert!(display.problem().unwrap().starts_with("display color"));
+        assert_eq!(StepDisplay::default().title("step3"), "step3");
+        assert!(StepDisplay::default().is_empty());
+    }
+}
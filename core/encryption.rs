@@ -0,0 +1,287 @@
+// Optional at-rest encryption for run logs, workflow states, the step cache,
+// usage history and artifacts.
+// Keys and `lao encryption keygen` are described in docs/observability.md
+// (Encryption at Rest).
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+/// Header of every sealed file or line
+pub const MAGIC: &[u8; 5] = b"LAOE1";
+/// Marks a sealed line in a JSONL log
+pub const LINE_PREFIX: &str = "laoe1:";
+
+const KDF_KEYFILE: u8 = 0;
+const KDF_PASSPHRASE: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+enum KeySource {
+    Keyfile([u8; 32]),
+    Passphrase {
+        passphrase: String,
+        // Salt used for everything this process writes
+        salt: [u8; SALT_LEN],
+        // Keys derived so far, by salt; Argon2 is deliberately slow
+        derived: Mutex<HashMap<[u8; SALT_LEN], [u8; 32]>>,
+    },
+}
+
+pub struct Cipher {
+    source: KeySource,
+}
+
+impl Cipher {
+    pub fn from_key(key: [u8; 32]) -> Self {
+        Self {
+            source: KeySource::Keyfile(key),
+        }
+    }
+
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            source: KeySource::Passphrase {
+                passphrase: passphrase.to_string(),
+                salt,
+                derived: Mutex::new(HashMap::new()),
+            },
+        }
+    }
+
+    /// Read a keyfile written by `generate_keyfile`
+    pub fn from_keyfile(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read keyfile {}: {}", path.display(), e))?;
+        let key = decode_hex(content.trim())
+            .filter(|bytes| bytes.len() == 32)
+            .ok_or_else(|| format!("keyfile {} must hold 64 hex characters", path.display()))?;
+        let mut buffer = [0u8; 32];
+        buffer.copy_from_slice(&key);
+        Ok(Self::from_key(buffer))
+    }
+
+    fn key_for(&self, kdf: u8, salt: &[u8; SALT_LEN]) -> Result<[u8; 32], String> {
+        match (&self.source, kdf) {
+            (KeySource::Keyfile(key), KDF_KEYFILE) => Ok(*key),
+            (
+                KeySource::Passphrase {
+                    passphrase,
+                    derived,
+                    ..
+                },
+                KDF_PASSPHRASE,
+            ) => {
+                let mut derived = derived.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(key) = derived.get(salt) {
+                    return Ok(*key);
+                }
+                let mut key = [0u8; 32];
+                argon2::Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                    .map_err(|e| format!("key derivation failed: {}", e))?;
+                derived.insert(*salt, key);
+                Ok(key)
+            }
+            (KeySource::Keyfile(_), _) => {
+                Err("data was encrypted with a passphrase, but a keyfile is configured".into())
+            }
+            (KeySource::Passphrase { .. }, _) => {
+                Err("data was encrypted with a keyfile, but a passphrase is configured".into())
+            }
+        }
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let (kdf, salt) = match &self.source {
+            KeySource::Keyfile(_) => (KDF_KEYFILE, [0u8; SALT_LEN]),
+            KeySource::Passphrase { salt, .. } => (KDF_PASSPHRASE, *salt),
+        };
+        let key = self.key_for(kdf, &salt)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| "encryption failed".to_string())?;
+
+        let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.push(kdf);
+        sealed.extend_from_slice(&salt);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < HEADER_LEN || !is_sealed(sealed) {
+            return Err("not an encrypted LAO file".to_string());
+        }
+        let kdf = sealed[MAGIC.len()];
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&sealed[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN]);
+        let nonce = XNonce::from_slice(&sealed[HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+        let key = self.key_for(kdf, &salt)?;
+        XChaCha20Poly1305::new(&key.into())
+            .decrypt(nonce, &sealed[HEADER_LEN..])
+            .map_err(|_| "decryption failed: wrong key or corrupted data".to_string())
+    }
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+static CIPHER: OnceLock<Cipher> = OnceLock::new();
+
+/// Enable encryption from LAO_ENCRYPTION_KEYFILE or LAO_ENCRYPTION_PASSPHRASE.
+/// Returns whether encryption is on; later calls are ignored.
+pub fn init_from_env() -> Result<bool, String> {
+    if CIPHER.get().is_some() {
+        return Ok(true);
+    }
+    let cipher = if let Ok(path) = std::env::var("LAO_ENCRYPTION_KEYFILE") {
+        Cipher::from_keyfile(Path::new(&path))?
+    } else if let Ok(passphrase) = std::env::var("LAO_ENCRYPTION_PASSPHRASE") {
+        if passphrase.is_empty() {
+            return Err("LAO_ENCRYPTION_PASSPHRASE is empty".to_string());
+        }
+        Cipher::from_passphrase(&passphrase)
+    } else {
+        return Ok(false);
+    };
+    let _ = CIPHER.set(cipher);
+    Ok(true)
+}
+
+pub fn enabled() -> bool {
+    CIPHER.get().is_some()
+}
+
+/// Seal `plaintext` if encryption is on, otherwise return it unchanged
+pub fn seal(plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+    match CIPHER.get() {
+        Some(cipher) => cipher.seal(plaintext).map_err(invalid_data),
+        None => Ok(plaintext.to_vec()),
+    }
+}
+
+/// Decrypt sealed data; plaintext passes through
+pub fn open(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    if !is_sealed(data) {
+        return Ok(data.to_vec());
+    }
+    match CIPHER.get() {
+        Some(cipher) => cipher.open(data).map_err(invalid_data),
+        None => Err(invalid_data(
+            "data is encrypted; set LAO_ENCRYPTION_KEYFILE or LAO_ENCRYPTION_PASSPHRASE",
+        )),
+    }
+}
+
+/// One line of a JSONL log, sealed if encryption is on
+pub fn seal_line(line: &str) -> std::io::Result<String> {
+    if !enabled() {
+        return Ok(line.to_string());
+    }
+    Ok(format!("{}{}", LINE_PREFIX, STANDARD.encode(seal(line.as_bytes())?)))
+}
+
+/// Inverse of `seal_line`; plaintext lines pass through
+pub fn open_line(line: &str) -> std::io::Result<String> {
+    let Some(encoded) = line.strip_prefix(LINE_PREFIX) else {
+        return Ok(line.to_string());
+    };
+    let sealed = STANDARD.decode(encoded.trim()).map_err(invalid_data)?;
+    String::from_utf8(open(&sealed)?).map_err(invalid_data)
+}
+
+/// `fs::write`, sealing the contents if encryption is on
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    fs::write(path, seal(contents.as_ref())?)
+}
+
+/// `fs::read_to_string` for files written with `write`
+pub fn read_to_string(path: impl AsRef<Path>) -> std::io::Result<String> {
+    String::from_utf8(open(&fs::read(path)?)?).map_err(invalid_data)
+}
+
+/// Write a new random key as hex, readable only by the owner on Unix
+pub fn generate_keyfile(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    fs::write(path, format!("{}\n", hex))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn invalid_data(e: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = Cipher::from_key([7u8; 32]);
+        let sealed = cipher.seal(b"meeting transcript").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(7).any(|w| w == b"meeting"));
+        assert_eq!(cipher.open(&sealed).unwrap(), b"meeting transcript");
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.open(&tampered).is_err());
+        assert!(Cipher::from_key([8u8; 32]).open(&sealed).is_err());
+
+        let passphrase = Cipher::from_passphrase("correct horse");
+        let sealed = passphrase.seal(b"summary").unwrap();
+        assert_eq!(passphrase.open(&sealed).unwrap(), b"summary");
+        // Another process with the same passphrase has its own write salt
+        assert_eq!(
+            Cipher::from_passphrase("correct horse").open(&sealed).unwrap(),
+            b"summary"
+        );
+        assert!(cipher.open(&sealed).is_err());
+
+        let dir = std::env::temp_dir().join(format!("lao_keyfile_{}", uuid::Uuid::new_v4()));
+        let keyfile = dir.join("key");
+        generate_keyfile(&keyfile).unwrap();
+        let from_file = Cipher::from_keyfile(&keyfile).unwrap();
+        assert_eq!(from_file.open(&from_file.seal(b"x").unwrap()).unwrap(), b"x");
+        assert!(generate_keyfile(&keyfile).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,330 @@
+// `${{ env.NAME }}` in workflow YAML, filled in from the environment, the
+// project's .env and the user's secrets.
+// Where values come from is described in docs/workflows.md (Environment
+// Variables).
+use regex::{Captures, Regex};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Variables templates may read without being listed
+pub const DEFAULT_ALLOWED: &[&str] = &["HOME", "USER", "USERNAME", "HOSTNAME", "TMPDIR"];
+
+/// Variables available to `${{ env.NAME }}`
+#[derive(Debug, Clone, Default)]
+pub struct TemplateEnv {
+    dotenv: HashMap<String, String>,
+    allow: Vec<String>,
+}
+
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_DOTENV")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(".env"))
+}
+
+/// The user's secrets, secrets.env in the data directory
+pub fn user_path() -> PathBuf {
+    crate::data_dir::path("secrets.env")
+}
+
+static ENV: OnceLock<TemplateEnv> = OnceLock::new();
+
+/// Use `env` for workflow templates; later calls are ignored
+pub fn init(env: TemplateEnv) -> Result<(), String> {
+    let _ = ENV.set(env);
+    Ok(())
+}
+
+fn global() -> &'static TemplateEnv {
+    ENV.get_or_init(|| TemplateEnv::from_dotenv(HashMap::new()))
+}
+
+impl TemplateEnv {
+    /// Variables of the .env file at `path`, if there is one
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Self::load_all(&[path.to_path_buf()])
+    }
+
+    /// Variables of the .env files at `paths` that exist, the first file
+    /// defining a name winning
+    pub fn load_all(paths: &[PathBuf]) -> Result<Self, String> {
+        let mut dotenv = HashMap::new();
+        for path in paths.iter().rev() {
+            match fs::read_to_string(path) {
+                Ok(text) => dotenv
+                    .extend(parse_dotenv(&text).map_err(|e| format!("{}: {}", path.display(), e))?),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+            }
+        }
+        Ok(Self::from_dotenv(dotenv))
+    }
+
+    pub fn from_dotenv(dotenv: HashMap<String, String>) -> Self {
+        let listed = std::env::var("LAO_ENV_ALLOW")
+            .ok()
+            .or_else(|| dotenv.get("LAO_ENV_ALLOW").cloned())
+            .unwrap_or_default();
+        Self::with_allowlist(dotenv, &listed)
+    }
+
+    // `listed` is LAO_ENV_ALLOW's value
+    fn with_allowlist(dotenv: HashMap<String, String>, listed: &str) -> Self {
+        let allow = DEFAULT_ALLOWED
+            .iter()
+            .map(|name| name.to_string())
+            .chain(
+                listed
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from),
+            )
+            .collect();
+        Self { dotenv, allow }
+    }
+
+    fn allowed(&self, name: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|entry| match entry.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => entry == name,
+            })
+    }
+
+    /// Value of `name`, looking in the process environment through `process`
+    fn resolve(
+        &self,
+        name: &str,
+        process: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<String, String> {
+        if !self.allowed(name) {
+            return Err(format!(
+                "env.{} is not available to workflows; add it to LAO_ENV_ALLOW",
+                name
+            ));
+        }
+        process(name)
+            .or_else(|| self.dotenv.get(name).cloned())
+            .ok_or_else(|| format!("env.{} is not set", name))
+    }
+
+    /// Replace `${{ env.NAME }}` in every string of `value`
+    pub fn expand<T: Serialize + DeserializeOwned>(&self, value: T) -> Result<T, String> {
+        self.expand_with(value, &|name| std::env::var(name).ok())
+    }
+
+    fn expand_with<T: Serialize + DeserializeOwned>(
+        &self,
+        value: T,
+        process: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<T, String> {
+        let mut tree = serde_yaml::to_value(&value).map_err(|e| e.to_string())?;
+        if !self.expand_tree(&mut tree, process)? {
+            return Ok(value);
+        }
+        serde_yaml::from_value(tree).map_err(|e| e.to_string())
+    }
+
+    // True when anything was replaced
+    fn expand_tree(
+        &self,
+        value: &mut serde_yaml::Value,
+        process: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<bool, String> {
+        use serde_yaml::Value;
+        match value {
+            Value::String(text) if text.contains("${{") => {
+                let mut error = None;
+                let expanded = reference().replace_all(text, |caps: &Captures| {
+                    match self.resolve(&caps[1], process) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            error.get_or_insert(e);
+                            String::new()
+                        }
+                    }
+                });
+                if let Some(e) = error {
+                    return Err(e);
+                }
+                let changed = expanded != *text;
+                *text = expanded.into_owned();
+                Ok(changed)
+            }
+            Value::Sequence(items) => items.iter_mut().try_fold(false, |changed, item| {
+                Ok(self.expand_tree(item, process)? || changed)
+            }),
+            Value::Mapping(mapping) => mapping.iter_mut().try_fold(false, |changed, (_, item)| {
+                Ok(self.expand_tree(item, process)? || changed)
+            }),
+            Value::Tagged(tagged) => self.expand_tree(&mut tagged.value, process),
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Digest of the variables a run can see here: those workflows may read, the
+/// .env values and LAO_* settings. Equal digests mean a run expands the same.
+pub fn fingerprint() -> String {
+    let env = global();
+    let mut seen: Vec<String> = std::env::vars()
+        .filter(|(name, _)| {
+            (env.allowed(name) || name.starts_with("LAO_")) && name != "LAO_DAEMON_FILE"
+        })
+        .map(|(name, value)| format!("{}={}", name, value))
+        .chain(
+            env.dotenv
+                .iter()
+                .map(|(name, value)| format!(".env {}={}", name, value)),
+        )
+        .chain(env.allow.iter().map(|entry| format!("allow {}", entry)))
+        .collect();
+    seen.sort();
+    crate::audit::sha256_hex(seen.join("\n").as_bytes())
+}
+
+/// Replace `${{ env.NAME }}` in every string of `value` with the initialised variables
+pub fn expand<T: Serialize + DeserializeOwned>(value: T) -> Result<T, String> {
+    global().expand(value)
+}
+
+fn reference() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\$\{\{\s*env\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap())
+}
+
+/// `NAME=value` lines, with optional `export`, quotes and `#` comments
+pub fn parse_dotenv(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut vars = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(format!("line {}: expected NAME=value", number + 1));
+        };
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!(
+                "line {}: invalid variable name '{}'",
+                number + 1,
+                name
+            ));
+        }
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+        {
+            quoted.replace("\\n", "\n").replace("\\\"", "\"")
+        } else if let Some(quoted) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            quoted.to_string()
+        } else {
+            // Unquoted values end at a comment
+            value
+                .split(" #")
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .to_string()
+        };
+        vars.insert(name.to_string(), value);
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dotenv() -> HashMap<String, String> {
+        parse_dotenv(
+            "# models on this machine\nexport MODEL=\"mistral:7b\"\nDATA_DIR=/data/audio # local disk\nOPENAI_API_KEY='sk-test'\nLAO_ENV_ALLOW=MODEL, DATA_DIR,PROJECT_*\n",
+        )
+        .unwrap()
+    }
+
+    /// Templates that may read the listed variables from .env and `process`.
+    fn env() -> TemplateEnv {
+        let dotenv = dotenv();
+        let listed = dotenv["LAO_ENV_ALLOW"].clone();
+        TemplateEnv::with_allowlist(dotenv, &listed)
+    }
+
+    fn process(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/lao".to_string()),
+            "PROJECT_NAME" => Some("meetings".to_string()),
+            "AWS_SECRET_ACCESS_KEY" => Some("secret".to_string()),
+            "MODEL" => Some("llama3".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let dotenv = dotenv();
+        assert_eq!(dotenv["DATA_DIR"], "/data/audio");
+        assert_eq!(dotenv["OPENAI_API_KEY"], "sk-test");
+        assert!(parse_dotenv("not a variable").is_err());
+    }
+
+    #[test]
+    fn test_listed_variables_expand() {
+        let params: serde_yaml::Value = serde_yaml::from_str(
+            "input: \"${{ env.DATA_DIR }}/call.wav\"\nmodel: ${{env.MODEL}}\nnotes: [\"${{ env.HOME }}/${{ env.PROJECT_NAME }}\"]\nplain: ${x}",
+        )
+        .unwrap();
+        let expanded = env().expand_with(params, &process).unwrap();
+        assert_eq!(expanded["input"], "/data/audio/call.wav");
+        // The process environment overrides .env
+        assert_eq!(expanded["model"], "llama3");
+        assert_eq!(expanded["notes"][0], "/home/lao/meetings");
+        assert_eq!(expanded["plain"], "${x}");
+    }
+
+    #[test]
+    fn test_unlisted_variables_are_refused() {
+        // Neither the environment nor .env is readable without being listed
+        for name in ["AWS_SECRET_ACCESS_KEY", "OPENAI_API_KEY"] {
+            let leak: serde_yaml::Value =
+                serde_yaml::from_str(&format!("input: ${{{{ env.{} }}}}", name)).unwrap();
+            assert!(env()
+                .expand_with(leak, &process)
+                .unwrap_err()
+                .contains("LAO_ENV_ALLOW"));
+        }
+    }
+
+    #[test]
+    fn test_unset_variables_are_errors() {
+        let unset: serde_yaml::Value =
+            serde_yaml::from_str("input: ${{ env.PROJECT_OWNER }}").unwrap();
+        assert_eq!(
+            env().expand_with(unset, &process).unwrap_err(),
+            "env.PROJECT_OWNER is not set"
+        );
+    }
+
+    #[test]
+    fn test_user_secrets_fill_in_under_the_project_env() {
+        let dir = std::env::temp_dir().join(format!("lao_dotenv_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(".env"), "MODEL=mistral\n").unwrap();
+        std::fs::write(dir.join("secrets.env"), "MODEL=llama3\nAPI_KEY=sk-user\n").unwrap();
+        let paths = [
+            dir.join(".env"),
+            dir.join("secrets.env"),
+            dir.join("missing.env"),
+        ];
+        let env = TemplateEnv::load_all(&paths).unwrap();
+        assert_eq!(env.dotenv["MODEL"], "mistral");
+        assert_eq!(env.dotenv["API_KEY"], "sk-user");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
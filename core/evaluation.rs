@@ -0,0 +1,343 @@
+// Step evaluations: LLM-as-judge scores kept as run history.
+use crate::plugins::PluginRegistry;
+use crate::WorkflowStep;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Criterion name used when a step lists none
+pub const OVERALL: &str = "overall";
+// Prompt previews in records are cut to this many characters
+const PREVIEW_CHARS: usize = 60;
+
+/// A step's `evaluate:` block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvaluateSpec {
+    /// Plugin asked for the scores
+    pub judge: String,
+    /// One score per criterion; a single overall score when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub criteria: Vec<String>,
+}
+
+impl EvaluateSpec {
+    pub fn problem(&self, registry: &PluginRegistry) -> Option<String> {
+        registry
+            .get(&self.judge)
+            .is_none()
+            .then(|| format!("evaluation judge '{}' not found", self.judge))
+    }
+
+    /// Score `output` per criterion; `call` calls a plugin with a prompt
+    pub fn score(
+        &self,
+        output: &str,
+        call: &mut dyn FnMut(&str, &str) -> Result<String, String>,
+    ) -> Result<BTreeMap<String, f64>, String> {
+        if self.criteria.is_empty() {
+            let score = crate::assertions::judge_score(call, &self.judge, None, output)?;
+            return Ok(BTreeMap::from([(OVERALL.to_string(), score)]));
+        }
+        self.criteria
+            .iter()
+            .map(|criterion| {
+                crate::assertions::judge_score(call, &self.judge, Some(criterion), output)
+                    .map(|score| (criterion.clone(), score))
+            })
+            .collect()
+    }
+}
+
+/// One evaluated step output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalRecord {
+    pub run_id: String,
+    pub workflow: String,
+    pub step_id: String,
+    pub plugin: String,
+    /// The step's `model` param, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Hash of the step's params other than `model`
+    pub prompt: String,
+    /// Start of the `prompt` or `input` param, for display
+    #[serde(default)]
+    pub prompt_preview: String,
+    pub judge: String,
+    pub scores: BTreeMap<String, f64>,
+    /// Mean of `scores`
+    pub score: f64,
+    pub evaluated_at: DateTime<Utc>,
+}
+
+impl EvalRecord {
+    pub fn new(
+        run_id: &str,
+        workflow: &str,
+        step_id: &str,
+        step: &WorkflowStep,
+        judge: &str,
+        scores: BTreeMap<String, f64>,
+    ) -> Self {
+        let score = scores.values().sum::<f64>() / scores.len().max(1) as f64;
+        let mut params = step.params.clone();
+        let model = params
+            .as_mapping_mut()
+            .and_then(|m| m.remove("model"))
+            .and_then(|model| model.as_str().map(String::from));
+        let preview = ["prompt", "input"]
+            .iter()
+            .find_map(|key| params.get(*key).and_then(|v| v.as_str()))
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self {
+            run_id: run_id.to_string(),
+            workflow: workflow.to_string(),
+            step_id: step_id.to_string(),
+            plugin: step.run.clone(),
+            model,
+            prompt: format!(
+                "{:08x}",
+                fnv1a(
+                    serde_yaml::to_string(&params)
+                        .unwrap_or_default()
+                        .as_bytes()
+                ) as u32
+            ),
+            prompt_preview: preview.chars().take(PREVIEW_CHARS).collect(),
+            judge: judge.to_string(),
+            scores,
+            score,
+            evaluated_at: Utc::now(),
+        }
+    }
+}
+
+struct EvalLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl EvalLog {
+    fn append(&self, record: &EvalRecord) -> std::io::Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(
+            file,
+            "{}",
+            crate::encryption::seal_line(&serde_json::to_string(record)?)?
+        )
+    }
+}
+
+static EVAL_LOG: OnceLock<EvalLog> = OnceLock::new();
+
+/// Start recording evaluations to `path`; later calls are ignored
+pub fn init(path: impl Into<PathBuf>) {
+    let _ = EVAL_LOG.set(EvalLog {
+        path: path.into(),
+        lock: Mutex::new(()),
+    });
+}
+
+/// LAO_EVAL_LOG, defaulting to evals/scores.jsonl in the data directory
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_EVAL_LOG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("evals").join("scores.jsonl"))
+}
+
+/// Append `record` to the evaluation log
+pub fn record(record: &EvalRecord) {
+    if let Some(log) = EVAL_LOG.get() {
+        if let Err(e) = log.append(record) {
+            crate::logging::warn("failed to write evaluation log")
+                .run(&record.run_id)
+                .field("path", log.path.display().to_string())
+                .field("error", e.to_string())
+                .emit();
+        }
+    }
+}
+
+pub fn read_history(path: &Path) -> std::io::Result<Vec<EvalRecord>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| crate::encryption::open_line(line).ok())
+        .filter_map(|line| serde_json::from_str::<EvalRecord>(&line).ok())
+        .collect())
+}
+
+/// Average scores of one variant of a step
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VariantScore {
+    pub workflow: String,
+    pub step_id: String,
+    pub plugin: String,
+    pub model: Option<String>,
+    pub prompt: String,
+    pub prompt_preview: String,
+    pub runs: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Mean per criterion
+    pub criteria: BTreeMap<String, f64>,
+}
+
+// Workflow, step, plugin, model and prompt hash
+type VariantKey<'a> = (&'a str, &'a str, &'a str, Option<&'a str>, &'a str);
+
+/// Variants of the matching steps, best mean score first
+pub fn rank(
+    history: &[EvalRecord],
+    workflow: Option<&str>,
+    step: Option<&str>,
+) -> Vec<VariantScore> {
+    let mut groups: BTreeMap<VariantKey, Vec<&EvalRecord>> = BTreeMap::new();
+    for record in history
+        .iter()
+        .filter(|r| workflow.is_none_or(|w| r.workflow == w) && step.is_none_or(|s| r.step_id == s))
+    {
+        groups
+            .entry((
+                &record.workflow,
+                &record.step_id,
+                &record.plugin,
+                record.model.as_deref(),
+                &record.prompt,
+            ))
+            .or_default()
+            .push(record);
+    }
+    let mut ranked: Vec<VariantScore> = groups
+        .into_values()
+        .map(|records| {
+            let first = records[0];
+            let runs = records.len();
+            let scores = records.iter().map(|r| r.score);
+            let mut criteria: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+            for (name, score) in records.iter().flat_map(|r| &r.scores) {
+                let entry = criteria.entry(name.clone()).or_default();
+                entry.0 += score;
+                entry.1 += 1;
+            }
+            VariantScore {
+                workflow: first.workflow.clone(),
+                step_id: first.step_id.clone(),
+                plugin: first.plugin.clone(),
+                model: first.model.clone(),
+                prompt: first.prompt.clone(),
+                prompt_preview: first.prompt_preview.clone(),
+                runs,
+                mean: scores.clone().sum::<f64>() / runs as f64,
+                min: scores.clone().fold(f64::INFINITY, f64::min),
+                max: scores.fold(f64::NEG_INFINITY, f64::max),
+                criteria: criteria
+                    .into_iter()
+                    .map(|(name, (sum, n))| (name, sum / n as f64))
+                    .collect(),
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.mean.total_cmp(&a.mean).then(b.runs.cmp(&a.runs)));
+    ranked
+}
+
+// FNV-1a, as for default cache keys
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 1469598103934665603;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(1099511628211);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(yaml: &str) -> WorkflowStep {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_score_asks_the_judge_per_criterion() {
+        let spec: EvaluateSpec =
+            serde_yaml::from_str("judge: Judge\ncriteria: [faithfulness, concision]").unwrap();
+        let mut call = |_: &str, prompt: &str| {
+            Ok(if prompt.contains("faithfulness") {
+                "8"
+            } else {
+                "6"
+            }
+            .to_string())
+        };
+        let scores = spec.score("summary", &mut call).unwrap();
+        assert_eq!(scores["faithfulness"], 8.0);
+        assert_eq!(scores["concision"], 6.0);
+    }
+
+    /// Two scored runs of mistral and one each of llama3 with the same
+    /// prompt and with a terser one.
+    fn history() -> Vec<EvalRecord> {
+        let mistral = step("run: OllamaPlugin\nmodel: mistral\nprompt: \"Summarize:\n  ${step1}\"");
+        let llama = step("run: OllamaPlugin\nmodel: llama3\nprompt: \"Summarize:\n  ${step1}\"");
+        let terse = step("run: OllamaPlugin\nmodel: llama3\nprompt: \"One line: ${step1}\"");
+        let record = |run: &str, step: &WorkflowStep, score: f64| {
+            EvalRecord::new(
+                run,
+                "notes",
+                "step2",
+                step,
+                "Judge",
+                BTreeMap::from([(OVERALL.to_string(), score)]),
+            )
+        };
+        vec![
+            record("r1", &mistral, 6.0),
+            record("r2", &mistral, 8.0),
+            record("r3", &llama, 9.0),
+            record("r4", &terse, 4.0),
+        ]
+    }
+
+    #[test]
+    fn test_records_key_prompts_by_template() {
+        let history = history();
+        // Same prompt template, different model
+        assert_eq!(history[0].prompt, history[2].prompt);
+        assert_ne!(history[2].prompt, history[3].prompt);
+        assert_eq!(history[0].prompt_preview, "Summarize: ${step1}");
+    }
+
+    #[test]
+    fn test_rank_orders_variants_by_mean_score() {
+        let history = history();
+        let ranked = rank(&history, Some("notes"), None);
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0].model.as_deref(), Some("llama3"));
+        assert_eq!(ranked[1].model.as_deref(), Some("mistral"));
+        assert_eq!((ranked[1].runs, ranked[1].mean), (2, 7.0));
+        assert_eq!((ranked[1].min, ranked[1].max), (6.0, 8.0));
+        assert!(rank(&history, Some("other"), None).is_empty());
+    }
+}
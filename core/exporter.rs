@@ -0,0 +1,332 @@
+// Renders a workflow's execution plan as a standalone POSIX shell script of
+// `lao exec` calls. What the script can't do the way a run would is listed
+// as NOTE comments on the step.
+use crate::plan::{ExecutionPlan, PlannedInput, PlannedStep};
+use crate::presets::INPUT_PLACEHOLDER;
+use crate::DisabledMode;
+use std::collections::HashSet;
+
+pub fn to_shell_script(plan: &ExecutionPlan) -> Result<String, String> {
+    if plan.order.len() < plan.steps.len() {
+        return Err(plan.error_summary());
+    }
+
+    let mut script = String::new();
+    script.push_str("#!/bin/sh\n");
+    script.push_str(&format!(
+        "# Generated by `lao export` from workflow \"{}\"\n",
+        single_line(&plan.workflow.workflow)
+    ));
+    script.push_str("# Set LAO to use a specific binary, e.g. LAO=./target/release/lao-cli\n");
+    script.push_str("set -eu\n");
+    script.push_str("LAO=\"${LAO:-lao}\"\n");
+
+    // Steps left out with `when_disabled: skip` have no output, so
+    // references to them stay as written, as they do in a run
+    let no_output: HashSet<&str> = plan
+        .steps
+        .iter()
+        .filter(|s| s.step.disabled_mode() == Some(DisabledMode::Skip))
+        .map(|s| s.id.as_str())
+        .collect();
+    let mut last = None;
+    for planned in plan.in_order() {
+        let id = planned.id.as_str();
+        let step = &planned.step;
+        script.push('\n');
+        script.push_str(&format!("# {}: {}\n", id, step.run));
+        for note in step_notes(plan, planned) {
+            script.push_str(&format!("# NOTE: {}\n", note));
+        }
+        if no_output.contains(id) {
+            continue;
+        }
+
+        let var = shell_var(id)?;
+        let source = step
+            .input_from
+            .as_deref()
+            .filter(|from| !no_output.contains(from))
+            .map(shell_var)
+            .transpose()?;
+        let command = format!("\"$LAO\" exec {}", shell_word(&step.run));
+        let line = match (step.disabled_mode(), source, &step.template) {
+            (Some(_), Some(source), None) => format!("{}=\"${}\"", var, source),
+            (Some(_), _, _) => format!("{}={}", var, step_input(planned, &no_output)),
+            (None, Some(source), None) => {
+                format!("{}=$(printf '%s' \"${}\" | {})", var, source, command)
+            }
+            (None, _, _) => format!(
+                "{}=$({} --input {})",
+                var,
+                command,
+                step_input(planned, &no_output)
+            ),
+        };
+        script.push_str(&line);
+        script.push('\n');
+        last = Some(var);
+    }
+
+    if let Some(last) = last {
+        script.push('\n');
+        script.push_str(&format!("printf '%s\\n' \"${}\"\n", last));
+    }
+    Ok(script)
+}
+
+// The input the executor would hand the plugin, as a shell word in which
+// step outputs and run inputs expand
+fn step_input(planned: &PlannedStep, no_output: &HashSet<&str>) -> String {
+    let step = &planned.step;
+    // Already rendered, template included
+    if let PlannedInput::Constant(input) = &planned.input {
+        return shell_word(input);
+    }
+    let input = match step
+        .input_from
+        .as_deref()
+        .filter(|from| !no_output.contains(from))
+    {
+        Some(source) => format!("\"${}\"", source),
+        None => {
+            let text = match step.params.get("input").and_then(|v| v.as_str()) {
+                Some(input) => input.to_string(),
+                None => serde_yaml::to_string(&step.params).unwrap_or_default(),
+            };
+            double_quoted(&text, no_output)
+        }
+    };
+    match &step.template {
+        // The template's own text is taken as written
+        Some(template) => template
+            .split(INPUT_PLACEHOLDER)
+            .map(shell_word)
+            .collect::<Vec<_>>()
+            .join(&input),
+        None => input,
+    }
+}
+
+fn step_notes(plan: &ExecutionPlan, planned: &PlannedStep) -> Vec<String> {
+    let step = &planned.step;
+    let mut notes = Vec::new();
+    if let Some(mode) = step.disabled_mode() {
+        notes.push(match mode {
+            DisabledMode::Skip => "disabled; not run".to_string(),
+            DisabledMode::PassThrough => "disabled; its input is passed through".to_string(),
+        });
+        return notes;
+    }
+    if step.condition.is_some() {
+        notes.push("the step's condition is not evaluated; it always runs".to_string());
+    }
+    if let Some(when) = &step.when {
+        notes.push(format!(
+            "when: {} is not evaluated; the step always runs",
+            single_line(when.trim())
+        ));
+    }
+    for source in &plan.steps {
+        let verb = if source
+            .step
+            .on_failure
+            .iter()
+            .flatten()
+            .any(|t| t == planned.id.as_str())
+        {
+            "fails"
+        } else if source
+            .step
+            .on_success
+            .iter()
+            .flatten()
+            .any(|t| t == planned.id.as_str())
+        {
+            "succeeds"
+        } else {
+            continue;
+        };
+        notes.push(format!(
+            "runs in the workflow only if {} {}; here it always runs, and the script stops at the first failure",
+            source.id, verb
+        ));
+    }
+    if step.foreach.is_some() {
+        notes
+            .push("foreach is not expanded; the plugin runs once with the whole input".to_string());
+    }
+    if step.strategy.is_some() || step.map_reduce.is_some() {
+        notes.push(
+            "map_reduce is not applied; the plugin runs once with the whole input".to_string(),
+        );
+    }
+    if let Some(timeout) = step.timeout {
+        notes.push(format!("timeout of {}s is not enforced", timeout));
+    }
+    if let Some(retries) = step.retries.filter(|r| *r > 0) {
+        notes.push(format!("{} retries in the workflow, none here", retries));
+    }
+    if let Some(cache_key) = &step.cache_key {
+        notes.push(format!("cache_key '{}' is ignored", single_line(cache_key)));
+    }
+    if !step.validate.is_empty() || !step.assert.is_empty() {
+        notes.push("input checks and output assertions are not made".to_string());
+    }
+    // Plugins with typed inputs get the other params as fields in a run;
+    // `lao exec` passes the input alone
+    let uses_input =
+        step.input_from.is_some() || step.params.get("input").is_some_and(|v| v.is_string());
+    let others: Vec<&str> = step
+        .params
+        .as_mapping()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, _)| key.as_str())
+        .filter(|key| *key != "input")
+        .collect();
+    if uses_input && !others.is_empty() {
+        notes.push(format!(
+            "only the input is passed, not {} (plugins with typed inputs get them in a run)",
+            others.join(", ")
+        ));
+    }
+    if let Some(depends_on) = &step.depends_on {
+        notes.push(format!("runs after {}", depends_on.join(", ")));
+    }
+    notes
+}
+
+// Step ids are used as shell variable names, so they must be identifiers
+fn shell_var(id: &str) -> Result<&str, String> {
+    let valid = id
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(id)
+    } else {
+        Err(format!(
+            "Step reference '{}' is not a valid shell variable name",
+            id
+        ))
+    }
+}
+
+// Plugin names and text taken as written are passed as single-quoted words
+fn shell_word(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+// A double-quoted shell string in which only ${name} references expand,
+// except to steps in `no_output`
+fn double_quoted(text: &str, no_output: &HashSet<&str>) -> String {
+    let mut out = String::from("\"");
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(reference) = rest
+            .strip_prefix("${")
+            .and_then(|r| r.split_once('}'))
+            .filter(|(name, _)| shell_var(name).is_ok() && !no_output.contains(name))
+        {
+            out.push_str(&format!("${{{}}}", reference.0));
+            rest = reference.1;
+            continue;
+        }
+        let ch = rest.chars().next().unwrap_or_default();
+        if matches!(ch, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(ch);
+        rest = &rest[ch.len_utf8()..];
+    }
+    out.push('"');
+    out
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::PluginRegistry;
+
+    fn export(yaml: &str) -> String {
+        let workflow = serde_yaml::from_str(yaml).unwrap();
+        to_shell_script(&ExecutionPlan::compile(workflow, &PluginRegistry::new())).unwrap()
+    }
+
+    #[test]
+    fn test_shell_script_pipes_and_substitutes() {
+        let script = export(
+            r#"
+workflow: "Clipboard Summary"
+steps:
+  - run: ClipboardPlugin
+    action: read
+  - run: SummarizerPlugin
+    input_from: step1
+    retries: 2
+  - run: ClipboardPlugin
+    input: "write: ${step2} costs $5 \"now\""
+    depends_on: [step2]
+"#,
+        );
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(
+            script.contains("step1=$(\"$LAO\" exec 'ClipboardPlugin' --input 'action: read\n')")
+        );
+        assert!(
+            script.contains("step2=$(printf '%s' \"$step1\" | \"$LAO\" exec 'SummarizerPlugin')")
+        );
+        assert!(script.contains("# NOTE: 2 retries in the workflow, none here"));
+        assert!(script.contains("--input \"write: ${step2} costs \\$5 \\\"now\\\"\""));
+        assert!(script.ends_with("printf '%s\\n' \"$step3\"\n"));
+    }
+
+    #[test]
+    fn test_shell_script_follows_the_plan() {
+        let script = export(
+            r#"
+workflow: "Digest"
+steps:
+  - run: EchoPlugin
+    input: "notes"
+    enabled: false
+  - run: EchoPlugin
+    input_from: step1
+    input: "fallback ${step1}"
+  - run: SummarizerPlugin
+    input_from: step2
+    template: "Summarize ${x}: {input}"
+    prompt: "Be brief"
+    timeout: 30
+  - run: EchoPlugin
+    input_from: step3
+    enabled: false
+    when_disabled: pass_through
+  - run: EchoPlugin
+    input: "${step4}"
+    when: steps.step3.output contains 'ok'
+    if_failed: step6
+  - run: EchoPlugin
+    input: "failed"
+"#,
+        );
+        // A skipped step has no variable, and references to it stay as written
+        assert!(script.contains("# step1: EchoPlugin\n# NOTE: disabled; not run\n\n"));
+        assert!(
+            script.contains("step2=$(\"$LAO\" exec 'EchoPlugin' --input \"fallback \\${step1}\")")
+        );
+        assert!(script.contains("--input 'Summarize ${x}: '\"$step2\"'')"));
+        assert!(script.contains("# NOTE: timeout of 30s is not enforced"));
+        assert!(script.contains("# NOTE: only the input is passed, not prompt (plugins with typed inputs get them in a run)"));
+        assert!(script.contains("step4=\"$step3\"\n"));
+        assert!(script.contains("# NOTE: when: steps.step3.output contains 'ok' is not evaluated"));
+        assert!(script.contains("# NOTE: runs in the workflow only if step5 fails"));
+        assert!(script.ends_with("printf '%s\\n' \"$step6\"\n"));
+    }
+}
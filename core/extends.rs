@@ -0,0 +1,204 @@
+// Workflow inheritance with `extends`.
+// Merge rules are described in docs/workflows.md (Extending Workflows).
+use crate::{presets, prompts};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// The workflow at `path` with its bases merged in
+pub fn load(path: &Path) -> Result<Value, String> {
+    let mut merged = load_chain(path, &mut Vec::new())?;
+    presets::expand(&mut merged, &presets::default_dir())?;
+    prompts::expand(&mut merged)?;
+    Ok(merged)
+}
+
+/// The workflow `text` merged with its bases, looked up relative to `dir`,
+/// and with the presets and prompts its steps name; `None` if it uses none
+/// of them. Text that isn't valid YAML is `None` too, so the caller's parser
+/// reports the error.
+pub fn resolve(text: &str, dir: &Path) -> Result<Option<Value>, String> {
+    match serde_yaml::from_str::<Value>(text) {
+        Ok(value)
+            if value.get("extends").is_some() || presets::used(&value) || prompts::used(&value) =>
+        {
+            let mut merged = with_bases(value, dir, &mut Vec::new())?;
+            presets::expand(&mut merged, &presets::default_dir())?;
+            prompts::expand(&mut merged)?;
+            Ok(Some(merged))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn load_chain(path: &Path, seen: &mut Vec<PathBuf>) -> Result<Value, String> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if seen.contains(&canonical) {
+        return Err(format!("{} is extended in a cycle", path.display()));
+    }
+    seen.push(canonical);
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let value: Value =
+        serde_yaml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    with_bases(value, path.parent().unwrap_or(Path::new("")), seen)
+}
+
+fn with_bases(mut value: Value, dir: &Path, seen: &mut Vec<PathBuf>) -> Result<Value, String> {
+    let Some(base) = value.as_mapping_mut().and_then(|m| m.remove("extends")) else {
+        return Ok(value);
+    };
+    let base = base
+        .as_str()
+        .ok_or("extends must be the path of a workflow file")?;
+    let base = load_chain(&dir.join(base), seen)?;
+    extend(base, value)
+}
+
+/// `child` applied on top of `base`, both without `extends`
+pub fn extend(base: Value, child: Value) -> Result<Value, String> {
+    let (Value::Mapping(mut merged), Value::Mapping(mut child)) = (base, child) else {
+        return Err("workflows must be YAML mappings".to_string());
+    };
+    let mut steps = match merged.remove("steps") {
+        Some(Value::Sequence(steps)) => steps,
+        None | Some(Value::Null) => Vec::new(),
+        Some(_) => return Err("the base's steps must be a list".to_string()),
+    };
+    if let Some(overrides) = child.remove("overrides") {
+        let Value::Mapping(overrides) = overrides else {
+            return Err("overrides must map step ids to the fields to change".to_string());
+        };
+        for (id, fields) in overrides {
+            let id = id.as_str().unwrap_or_default();
+            let ids: Vec<String> = steps
+                .iter()
+                .enumerate()
+                .map(
+                    |(index, step)| match step.get("id").and_then(Value::as_str) {
+                        Some(own) => own.to_string(),
+                        None => format!("step{}", index + 1),
+                    },
+                )
+                .collect();
+            let index = ids.iter().position(|known| known == id).ok_or_else(|| {
+                format!(
+                    "overrides: {:?} does not name a step of the base ({})",
+                    id,
+                    ids.join(", ")
+                )
+            })?;
+            merge(&mut steps[index], fields);
+        }
+    }
+    match child.remove("steps") {
+        Some(Value::Sequence(added)) => steps.extend(added),
+        None | Some(Value::Null) => {}
+        Some(_) => return Err("steps must be a list".to_string()),
+    }
+    for (key, value) in child {
+        match merged.get_mut(&key) {
+            Some(existing) => merge(existing, value),
+            None => {
+                merged.insert(key, value);
+            }
+        }
+    }
+    merged.insert(Value::String("steps".to_string()), Value::Sequence(steps));
+    Ok(Value::Mapping(merged))
+}
+
+// Mappings are merged key by key, null removing a key; anything else replaces
+fn merge(target: &mut Value, value: Value) {
+    match (target, value) {
+        (Value::Mapping(target), Value::Mapping(fields)) => merge_mapping(target, fields),
+        (target, value) => *target = value,
+    }
+}
+
+fn merge_mapping(target: &mut Mapping, fields: Mapping) {
+    for (key, value) in fields {
+        if value.is_null() {
+            target.remove(&key);
+        } else if let Some(existing) = target.get_mut(&key) {
+            merge(existing, value);
+        } else {
+            target.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temp dir with bases/root.yaml, bases/base.yaml extending it, and
+    /// flow.yaml extending base.yaml.
+    fn chain() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_extends_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("bases")).unwrap();
+        std::fs::write(
+            dir.join("bases/root.yaml"),
+            "workflow: root\nredaction:\n  detectors: [email]\nsteps:\n  - run: WhisperPlugin\n    input: meeting.wav\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("bases/base.yaml"),
+            "extends: root.yaml\nworkflow: base\nsteps:\n  - run: SummarizerPlugin\n    input_from: step1\n    model: mistral\n    retries: 2\n",
+        )
+        .unwrap();
+        let flow = dir.join("flow.yaml");
+        std::fs::write(
+            &flow,
+            "extends: bases/base.yaml\nworkflow: flow\noverrides:\n  step2:\n    model: llama3\n    retries: ~\nsteps:\n  - run: EchoPlugin\n    input_from: step2\n",
+        )
+        .unwrap();
+        (dir, flow)
+    }
+
+    #[test]
+    fn test_extends_overrides_and_appends_steps() {
+        let (dir, flow) = chain();
+        let workflow = crate::load_workflow_yaml(flow.to_str().unwrap()).unwrap();
+        assert_eq!(workflow.workflow, "flow");
+        let runs: Vec<&str> = workflow.steps.iter().map(|s| s.run.as_str()).collect();
+        assert_eq!(runs, ["WhisperPlugin", "SummarizerPlugin", "EchoPlugin"]);
+        assert_eq!(workflow.steps[1].params["model"], "llama3");
+        assert_eq!(workflow.steps[1].retries, None);
+        assert_eq!(workflow.steps[1].input_from.as_deref(), Some("step1"));
+        assert!(workflow.redaction.is_some());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_extends_cycle_is_an_error() {
+        let (dir, flow) = chain();
+        std::fs::write(&flow, "extends: flow.yaml\nworkflow: loop\nsteps: []\n").unwrap();
+        assert!(load(&flow).unwrap_err().contains("cycle"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_override_of_unknown_step_names_the_known_ones() {
+        let (dir, _) = chain();
+        let unknown = "extends: bases/base.yaml\noverrides:\n  step7: { model: llama3 }\n";
+        let error = resolve(unknown, &dir).unwrap_err();
+        assert!(error.contains("(step1, step2)"), "{}", error);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_overrides_find_a_named_step() {
+        let base = serde_yaml::from_str(
+            "workflow: base\nsteps:\n  - run: WhisperPlugin\n  - run: SummarizerPlugin\n    id: summarize\n",
+        )
+        .unwrap();
+        let child = serde_yaml::from_str("overrides:\n  summarize: { model: llama3 }\n").unwrap();
+        let merged = extend(base, child).unwrap();
+        assert_eq!(merged["steps"][1]["model"], "llama3");
+        let error = extend(
+            merged,
+            serde_yaml::from_str("overrides:\n  step2: { model: mistral }\n").unwrap(),
+        )
+        .unwrap_err();
+        assert!(error.contains("(step1, summarize)"), "{}", error);
+    }
+}
@@ -0,0 +1,357 @@
+// Fan-out steps: one plugin run per item of a list.
+// The `foreach` settings: docs/workflows.md (Fan-out Steps).
+use crate::plugins::{PluginInstance, PluginRegistry};
+use crate::usage::RunUsage;
+use crate::{cancel, is_error_output, WorkflowStep};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const SEPARATOR: &str = "\n\n";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Items {
+    List(Vec<String>),
+    /// A JSON array, or a glob of files
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ForeachSpec {
+    /// The step's input when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Items>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// `{item}` is replaced with the item; the item alone by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_prompt: Option<String>,
+    /// Plugin given all outputs at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<String>,
+    /// `{outputs}` is replaced with the outputs; them alone by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate_prompt: Option<String>,
+}
+
+/// How far a fan-out step is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instances {
+    pub total: usize,
+    pub done: usize,
+    pub running: usize,
+}
+
+/// What is wrong with the step's foreach settings, if anything
+pub fn problem(step: &WorkflowStep, registry: &PluginRegistry) -> Option<String> {
+    let spec = step.foreach.as_ref()?;
+    if step.strategy.is_some() {
+        return Some("foreach can't be combined with strategy: map_reduce".to_string());
+    }
+    if spec.max_concurrency == Some(0) {
+        return Some("foreach max_concurrency must be at least 1".to_string());
+    }
+    if let Some(Items::Text(text)) = &spec.items {
+        if !is_json(text) {
+            if let Err(e) = glob::Pattern::new(text) {
+                return Some(format!(
+                    "foreach items '{}' is not a valid glob: {}",
+                    text, e
+                ));
+            }
+        }
+    }
+    spec.aggregate
+        .as_ref()
+        .filter(|aggregate| registry.get(aggregate).is_none())
+        .map(|aggregate| format!("foreach aggregate plugin '{}' not found", aggregate))
+}
+
+fn is_json(text: &str) -> bool {
+    text.trim_start().starts_with('[')
+}
+
+// A JSON array's elements, strings as they are and other values as JSON
+fn json_items(text: &str) -> Result<Vec<String>, String> {
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(text).map_err(|e| format!("foreach items: {}", e))?;
+    Ok(values
+        .into_iter()
+        .map(|value| match value {
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        })
+        .collect())
+}
+
+/// The items the step runs over, given its input
+pub fn items(spec: &ForeachSpec, input: &str) -> Result<Vec<String>, String> {
+    match &spec.items {
+        Some(Items::List(items)) => Ok(items.clone()),
+        Some(Items::Text(text)) if is_json(text) => json_items(text),
+        Some(Items::Text(pattern)) => {
+            let paths = glob::glob(pattern)
+                .map_err(|e| format!("foreach items '{}' is not a valid glob: {}", pattern, e))?;
+            let mut files: Vec<String> = paths
+                .filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect();
+            files.sort();
+            if files.is_empty() {
+                return Err(format!("no files match foreach items '{}'", pattern));
+            }
+            Ok(files)
+        }
+        None if is_json(input) => json_items(input),
+        None => Ok(input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()),
+    }
+}
+
+fn fill(template: Option<&str>, placeholder: &str, text: &str) -> String {
+    template.map_or_else(|| text.to_string(), |t| t.replace(placeholder, text))
+}
+
+/// Run `plugin` once per item of the step's list; calls are added to `usage`
+/// and `on_progress` is called as items start and finish
+pub(crate) fn run(
+    spec: &ForeachSpec,
+    plugin: &PluginInstance,
+    registry: &PluginRegistry,
+    input: &str,
+    usage: &mut RunUsage,
+    on_progress: &mut dyn FnMut(Instances),
+) -> Result<String, String> {
+    let aggregate = match &spec.aggregate {
+        Some(name) => Some(
+            registry
+                .get(name)
+                .ok_or_else(|| format!("foreach aggregate plugin '{}' not found", name))?,
+        ),
+        None => None,
+    };
+    let items = items(spec, input)?;
+    let prompts: Vec<String> = items
+        .iter()
+        .map(|item| fill(spec.item_prompt.as_deref(), "{item}", item))
+        .collect();
+    let concurrency = spec
+        .max_concurrency
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .clamp(1, prompts.len().max(1));
+
+    let mut progress = Instances {
+        total: prompts.len(),
+        ..Instances::default()
+    };
+    on_progress(progress);
+    let next = AtomicUsize::new(0);
+    // Set once an item fails or the step is stopped: no further item starts
+    let halt = AtomicBool::new(false);
+    let mut results: Vec<Option<Result<String, String>>> = vec![None; prompts.len()];
+    let mut elapsed: Vec<Duration> = Vec::new();
+    std::thread::scope(|scope| {
+        let (sender, received) = mpsc::channel();
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (next, halt, prompts) = (&next, &halt, &prompts);
+            scope.spawn(move || loop {
+                if halt.load(Ordering::SeqCst) {
+                    break;
+                }
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(prompt) = prompts.get(i) else {
+                    break;
+                };
+                sender.send((i, None)).ok();
+                let start = Instant::now();
+                let result = plugin.call(prompt).and_then(|output| {
+                    if is_error_output(&output) {
+                        Err(output)
+                    } else {
+                        Ok(output)
+                    }
+                });
+                sender.send((i, Some((result, start.elapsed())))).ok();
+            });
+        }
+        drop(sender);
+        // Stops are checked here, on the step's thread, while items run
+        loop {
+            match received.recv_timeout(cancel::POLL) {
+                Ok((_, None)) => progress.running += 1,
+                Ok((i, Some((result, took)))) => {
+                    progress.running -= 1;
+                    progress.done += 1;
+                    elapsed.push(took);
+                    if result.is_err() {
+                        halt.store(true, Ordering::SeqCst);
+                    }
+                    results[i] = Some(result);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if cancel::stopped().is_some() {
+                        halt.store(true, Ordering::SeqCst);
+                    }
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            on_progress(progress);
+        }
+    });
+    // Token counts are the plugin's since the last take, so go with the last call
+    for (n, took) in elapsed.iter().enumerate() {
+        let tokens = (n + 1 == elapsed.len())
+            .then(|| plugin.take_usage())
+            .flatten();
+        usage.record_call(&plugin.info.name, *took, tokens);
+    }
+    if let Some(reason) = cancel::stopped() {
+        return Err(reason);
+    }
+
+    let mut outputs = Vec::with_capacity(results.len());
+    for (i, result) in results.into_iter().enumerate() {
+        match result {
+            Some(Ok(output)) => outputs.push(output),
+            Some(Err(e)) => {
+                return Err(format!(
+                    "item {} of {} ({}): {}",
+                    i + 1,
+                    items.len(),
+                    items[i],
+                    e
+                ))
+            }
+            // Not started because an earlier item failed
+            None => {}
+        }
+    }
+    match aggregate {
+        Some(aggregate) if !outputs.is_empty() => {
+            let prompt = fill(
+                spec.aggregate_prompt.as_deref(),
+                "{outputs}",
+                &outputs.join(SEPARATOR),
+            );
+            let start = Instant::now();
+            let result = aggregate.call(&prompt);
+            usage.record_call(
+                &aggregate.info.name,
+                start.elapsed(),
+                aggregate.take_usage(),
+            );
+            let output = result?;
+            if is_error_output(&output) {
+                return Err(format!("aggregating {} outputs: {}", outputs.len(), output));
+            }
+            Ok(output)
+        }
+        _ => serde_json::to_string(&outputs).map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures, MockSpec};
+    use std::collections::HashMap;
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("lao_foreach_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn glob(pattern: String) -> ForeachSpec {
+        ForeachSpec {
+            items: Some(Items::Text(pattern)),
+            ..ForeachSpec::default()
+        }
+    }
+
+    #[test]
+    fn test_glob_items_are_matching_files_in_order() {
+        let dir = temp_dir();
+        for name in ["b.wav", "a.wav", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let found = items(&glob(format!("{}/*.wav", dir.display())), "").unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found[0].ends_with("a.wav") && found[1].ends_with("b.wav"));
+        assert!(items(&glob(format!("{}/*.flac", dir.display())), "").is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_items_from_input_are_a_json_array_or_lines() {
+        let from_input = ForeachSpec::default();
+        assert_eq!(items(&from_input, "[\"x\", 2]").unwrap(), ["x", "2"]);
+        assert_eq!(
+            items(&from_input, "one\n\n two \n").unwrap(),
+            ["one", "two"]
+        );
+    }
+
+    fn fan_out() -> (std::path::PathBuf, crate::Workflow, PluginRegistry) {
+        let dir = temp_dir();
+        let path = dir.join("fan.yaml");
+        std::fs::write(
+            &path,
+            "workflow: fan\nsteps:\n  - run: EchoPlugin\n    input: \"[\\\"a\\\", \\\"b\\\", \\\"c\\\"]\"\n  - run: WhisperPlugin\n    input_from: step1\n    foreach:\n      max_concurrency: 2\n      item_prompt: \"file {item}\"\n  - run: SummarizerPlugin\n    input_from: step2\n    foreach:\n      aggregate: EchoPlugin\n      aggregate_prompt: \"all: {outputs}\"\n",
+        )
+        .unwrap();
+        let workflow = crate::load_workflow_yaml(path.to_str().unwrap()).unwrap();
+        let mut fixtures = MockFixtures::default();
+        fixtures.insert(
+            "EchoPlugin".to_string(),
+            MockSpec {
+                output: Some("{input}".to_string()),
+                ..MockSpec::default()
+            },
+        );
+        let registry = mock_registry(&workflow, &fixtures);
+        (path, workflow, registry)
+    }
+
+    #[test]
+    fn test_foreach_runs_each_item_and_aggregates() {
+        let (path, workflow, registry) = fan_out();
+        assert!(workflow
+            .steps
+            .iter()
+            .all(|s| problem(s, &registry).is_none()));
+        let logs =
+            crate::run_workflow_with_registry(path.to_str().unwrap(), HashMap::new(), &registry)
+                .unwrap();
+        assert_eq!(
+            logs[1].output.as_deref(),
+            Some(r#"["WhisperPlugin(file a)","WhisperPlugin(file b)","WhisperPlugin(file c)"]"#)
+        );
+        let output = logs[2].output.as_deref().unwrap();
+        assert!(
+            output.starts_with("all: SummarizerPlugin(WhisperPlugin(file a))"),
+            "{}",
+            output
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_missing_aggregate_plugin_is_a_problem() {
+        let (path, workflow, registry) = fan_out();
+        let mut step = workflow.steps[2].clone();
+        step.foreach.as_mut().unwrap().aggregate = Some("MissingPlugin".to_string());
+        assert!(problem(&step, &registry).unwrap().contains("MissingPlugin"));
+        std::fs::remove_dir_all(path.parent().unwrap()).ok();
+    }
+}
@@ -0,0 +1,181 @@
+// Golden-file tests for whole workflows, run by `lao test`.
+use crate::mock::{mock_registry, MockFixtures};
+use crate::run_diff::{line_diff, DiffLine};
+use crate::{load_workflow_yaml, run_workflow_with_registry, StepLog};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn default_dir() -> PathBuf {
+    PathBuf::from("tests").join("workflows")
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TestSpec {
+    #[serde(default)]
+    pub inputs: HashMap<String, String>,
+    #[serde(default)]
+    pub mocks: MockFixtures,
+}
+
+/// One step of a run as recorded in a golden file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenStep {
+    pub step_id: String,
+    pub runner: String,
+    pub input: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub attempt: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<String>,
+}
+
+impl From<&StepLog> for GoldenStep {
+    fn from(log: &StepLog) -> Self {
+        Self {
+            step_id: log.step_id.clone(),
+            runner: log.runner.clone(),
+            input: serde_json::to_value(&log.input).unwrap_or_default(),
+            output: log.output.clone(),
+            error: log.error.clone(),
+            attempt: log.attempt,
+            validation: log.validation.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GoldenCase {
+    pub name: String,
+    pub workflow: PathBuf,
+}
+
+impl GoldenCase {
+    pub fn expected_path(&self) -> PathBuf {
+        self.workflow
+            .with_file_name(format!("{}.expected.json", self.name))
+    }
+
+    pub fn spec_path(&self) -> PathBuf {
+        self.workflow
+            .with_file_name(format!("{}.test.yaml", self.name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaseOutcome {
+    Passed,
+    /// The golden file was (re)written
+    Updated,
+    /// No golden file yet; run with `--update` to record one
+    Missing,
+    /// Line diff from expected to actual
+    Mismatch(String),
+    Error(String),
+}
+
+/// Workflows in `dir`, sorted by name
+pub fn discover(dir: &Path) -> Result<Vec<GoldenCase>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {}", dir.display(), e))?;
+    let mut cases: Vec<GoldenCase> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_str()?;
+            let name = file_name.strip_suffix(".yaml")?;
+            if name.ends_with(".test") {
+                return None;
+            }
+            Some(GoldenCase {
+                name: name.to_string(),
+                workflow: path.clone(),
+            })
+        })
+        .collect();
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+/// Run `case` against its mocks and return the rendered results
+pub fn render_case(case: &GoldenCase) -> Result<String, String> {
+    let spec_path = case.spec_path();
+    let spec: TestSpec = if spec_path.exists() {
+        let text = fs::read_to_string(&spec_path)
+            .map_err(|e| format!("Cannot read {}: {}", spec_path.display(), e))?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| format!("Invalid {}: {}", spec_path.display(), e))?
+    } else {
+        TestSpec::default()
+    };
+
+    let path = case.workflow.to_string_lossy();
+    let workflow = load_workflow_yaml(&path)?;
+    let registry = mock_registry(&workflow, &spec.mocks);
+    let logs = run_workflow_with_registry(&path, spec.inputs, &registry)?;
+    let steps: Vec<GoldenStep> = logs.iter().map(GoldenStep::from).collect();
+    serde_json::to_string_pretty(&steps)
+        .map(|json| json + "\n")
+        .map_err(|e| e.to_string())
+}
+
+/// Run `case` and compare with, or with `update` rewrite, its golden file
+pub fn check(case: &GoldenCase, update: bool) -> CaseOutcome {
+    let actual = match render_case(case) {
+        Ok(actual) => actual,
+        Err(e) => return CaseOutcome::Error(e),
+    };
+    let expected_path = case.expected_path();
+    let expected = fs::read_to_string(&expected_path).ok();
+    if expected.as_deref() == Some(actual.as_str()) {
+        return CaseOutcome::Passed;
+    }
+    if update {
+        return match fs::write(&expected_path, &actual) {
+            Ok(()) => CaseOutcome::Updated,
+            Err(e) => {
+                CaseOutcome::Error(format!("Cannot write {}: {}", expected_path.display(), e))
+            }
+        };
+    }
+    match expected {
+        Some(expected) => CaseOutcome::Mismatch(diff_lines(&expected, &actual)),
+        None => CaseOutcome::Missing,
+    }
+}
+
+// `-` lines only in `expected`, `+` lines only in `actual`
+fn diff_lines(expected: &str, actual: &str) -> String {
+    line_diff(expected, actual)
+        .into_iter()
+        .filter_map(|line| match line {
+            DiffLine::Same(_) => None,
+            DiffLine::Added(text) => Some(format!("+{}\n", text)),
+            DiffLine::Removed(text) => Some(format!("-{}\n", text)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_golden_workflows() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(default_dir());
+        let cases = discover(&dir).unwrap();
+        assert!(!cases.is_empty());
+        for case in &cases {
+            match check(case, false) {
+                CaseOutcome::Passed => {}
+                other => panic!("{}: {:?}", case.name, other),
+            }
+        }
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nc\nd\n"), "-b\n+d\n");
+    }
+}
@@ -0,0 +1,178 @@
+// Execution hooks: cross-cutting work around runs and steps.
+use crate::node::NodeStatus;
+use crate::run_labels::RunLabels;
+use crate::{StepLog, Workflow, WorkflowStep};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+/// The run a hook is called for
+pub struct RunContext<'a> {
+    pub run_id: &'a str,
+    pub workflow: &'a Workflow,
+    /// Path the workflow was loaded from
+    pub path: &'a str,
+    /// Run inputs, `${name}` in steps
+    pub inputs: &'a HashMap<String, String>,
+    pub labels: &'a RunLabels,
+}
+
+/// The step a hook is called for
+pub struct StepContext<'a> {
+    /// Position in execution order
+    pub index: usize,
+    pub step_id: &'a str,
+    pub step: &'a WorkflowStep,
+}
+
+pub struct StepFinish<'a> {
+    pub status: NodeStatus,
+    /// Time spent on the step, including retries; zero when no plugin ran
+    pub duration: Duration,
+    pub log: &'a StepLog,
+}
+
+pub struct RunEnd<'a> {
+    /// "success", "error" if any step failed, or "cancelled"
+    pub status: &'a str,
+    pub duration: Duration,
+    pub logs: &'a [StepLog],
+    /// Time each step that ran a plugin took
+    pub step_durations: &'a HashMap<String, Duration>,
+    /// Model steps given untrusted content (see `guard`)
+    pub taint: &'a [crate::guard::Taint],
+}
+
+pub trait ExecutionHook: Send + Sync {
+    fn on_run_start(&self, _run: &RunContext) {}
+
+    fn on_step_start(&self, _run: &RunContext, _step: &StepContext) {}
+
+    /// Called once per step, whether it ran, was skipped, failed or came from a cache
+    fn on_step_finish(&self, _run: &RunContext, _step: &StepContext, _finish: &StepFinish) {}
+
+    fn on_run_end(&self, _run: &RunContext, _end: &RunEnd) {}
+}
+
+fn hooks() -> &'static RwLock<Vec<Arc<dyn ExecutionHook>>> {
+    static HOOKS: OnceLock<RwLock<Vec<Arc<dyn ExecutionHook>>>> = OnceLock::new();
+    HOOKS.get_or_init(|| {
+        RwLock::new(vec![
+            Arc::new(crate::metrics::MetricsHook),
+            Arc::new(crate::run_diff::SnapshotHook),
+            Arc::new(crate::checkpoint::CheckpointHook::default()),
+            Arc::new(crate::publish::PublishHook),
+            Arc::new(crate::script_hooks::ScriptHook::default()),
+        ])
+    })
+}
+
+/// Call `hook` for every run from now on
+pub fn register(hook: impl ExecutionHook + 'static) {
+    if let Ok(mut hooks) = hooks().write() {
+        hooks.push(Arc::new(hook));
+    }
+}
+
+// Hooks are copied out so one may register another without a deadlock
+fn each(f: impl Fn(&dyn ExecutionHook)) {
+    let hooks = hooks().read().map(|h| h.clone()).unwrap_or_default();
+    for hook in hooks {
+        f(hook.as_ref());
+    }
+}
+
+pub(crate) fn run_started(run: &RunContext) {
+    each(|hook| hook.on_run_start(run));
+}
+
+pub(crate) fn step_started(run: &RunContext, step: &StepContext) {
+    each(|hook| hook.on_step_start(run, step));
+}
+
+pub(crate) fn step_finished(run: &RunContext, step: &StepContext, finish: &StepFinish) {
+    each(|hook| hook.on_step_finish(run, step, finish));
+}
+
+pub(crate) fn run_ended(run: &RunContext, end: &RunEnd) {
+    each(|hook| hook.on_run_end(run, end));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::sync::Mutex;
+
+    // Events of the runs of one workflow; other tests run in parallel
+    struct Recorder {
+        workflow: String,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn push(&self, run: &RunContext, event: String) {
+            if run.workflow.workflow == self.workflow {
+                self.events.lock().unwrap().push(event);
+            }
+        }
+    }
+
+    impl ExecutionHook for Recorder {
+        fn on_run_start(&self, run: &RunContext) {
+            self.push(run, "run start".to_string());
+        }
+
+        fn on_step_start(&self, run: &RunContext, step: &StepContext) {
+            self.push(run, format!("{} start", step.step_id));
+        }
+
+        fn on_step_finish(&self, run: &RunContext, step: &StepContext, finish: &StepFinish) {
+            let output = finish.log.output.clone().unwrap_or_default();
+            let status = finish.status.as_str();
+            self.push(run, format!("{} {} {}", step.step_id, status, output));
+        }
+
+        fn on_run_end(&self, run: &RunContext, end: &RunEnd) {
+            self.push(run, format!("run {} {}", end.status, end.logs.len()));
+        }
+    }
+
+    #[test]
+    fn test_hooks_see_every_run_and_step() {
+        let dir = std::env::temp_dir().join(format!("lao_hooks_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hooked.yaml");
+        let name = format!("hooked-{}", uuid::Uuid::new_v4());
+        std::fs::write(
+            &path,
+            format!(
+                "workflow: {}\nsteps:\n  - run: EchoPlugin\n    input: hi\n  - run: EchoPlugin\n    input: hi\n",
+                name
+            ),
+        )
+        .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        register(Recorder {
+            workflow: name,
+            events: events.clone(),
+        });
+
+        let path = path.to_str().unwrap();
+        let workflow = crate::load_workflow_yaml(path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "run start",
+                "step1 start",
+                "step1 success EchoPlugin(hi)",
+                "step2 start",
+                "step2 success EchoPlugin(hi)",
+                "run success 2",
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,401 @@
+// Global hotkeys that run workflows, served by `lao daemon --hotkeys`.
+// hotkeys.yaml is described in docs/workflows.md (Global Hotkeys).
+use crate::plugins::PluginRegistry;
+use crate::{run_labels, StepLog};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Default hotkeys file, in the data directory; overridable with `LAO_HOTKEYS`
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_HOTKEYS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("hotkeys.yaml"))
+}
+
+/// What a hotkey captures before its workflow runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capture {
+    #[default]
+    None,
+    Clipboard,
+    Screenshot,
+    Microphone,
+}
+
+fn default_max_record_secs() -> u64 {
+    600
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hotkey {
+    /// Chord such as `ctrl+alt+s`
+    pub keys: String,
+    /// Workflow file, relative to the hotkeys file
+    pub workflow: String,
+    #[serde(default)]
+    pub capture: Capture,
+    /// Cap on a microphone recording that is never stopped
+    #[serde(default = "default_max_record_secs")]
+    pub max_record_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HotkeyConfig {
+    #[serde(default)]
+    pub hotkeys: Vec<Hotkey>,
+}
+
+pub fn load(path: &Path) -> Result<HotkeyConfig, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    serde_yaml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+const MODIFIERS: [(&str, &[&str]); 4] = [
+    ("ctrl", &["ctrl", "control"]),
+    ("alt", &["alt", "option"]),
+    ("shift", &["shift"]),
+    ("super", &["super", "cmd", "command"]),
+];
+
+const NAMED_KEYS: [&str; 17] = [
+    "space",
+    "enter",
+    "tab",
+    "escape",
+    "esc",
+    "backspace",
+    "delete",
+    "insert",
+    "home",
+    "end",
+    "pageup",
+    "pagedown",
+    "up",
+    "down",
+    "left",
+    "right",
+    "printscreen",
+];
+
+/// `keys` in canonical form (`ctrl+alt+s`), so chords written differently
+/// compare equal
+pub fn normalize(keys: &str) -> Result<String, String> {
+    let mut modifiers = Vec::new();
+    let mut key = None;
+    for token in keys.split('+').map(|t| t.trim().to_lowercase()) {
+        if token.is_empty() {
+            return Err(format!("'{}' has an empty key", keys));
+        }
+        if let Some((name, _)) = MODIFIERS
+            .iter()
+            .find(|(_, aliases)| aliases.contains(&token.as_str()))
+        {
+            modifiers.push(*name);
+            continue;
+        }
+        let valid = (token.len() == 1 && token.chars().all(|c| c.is_ascii_alphanumeric()))
+            || NAMED_KEYS.contains(&token.as_str())
+            || token
+                .strip_prefix('f')
+                .and_then(|n| n.parse::<u8>().ok())
+                .is_some_and(|n| (1..=12).contains(&n));
+        if !valid {
+            return Err(format!("'{}' is not a key in '{}'", token, keys));
+        }
+        if key.replace(token).is_some() {
+            return Err(format!("'{}' has more than one non-modifier key", keys));
+        }
+    }
+    let key = key.ok_or_else(|| format!("'{}' has only modifiers", keys))?;
+    let mut chord: Vec<&str> = MODIFIERS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| modifiers.contains(name))
+        .collect();
+    chord.push(&key);
+    Ok(chord.join("+"))
+}
+
+impl HotkeyConfig {
+    /// Bad chords, chords bound twice and missing workflows, for a config
+    /// loaded from `path`
+    pub fn problems(&self, path: &Path) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+        for hotkey in &self.hotkeys {
+            match normalize(&hotkey.keys) {
+                Ok(chord) if !seen.insert(chord.clone()) => {
+                    problems.push(format!("{} is bound more than once", chord))
+                }
+                Ok(_) => {}
+                Err(e) => problems.push(e),
+            }
+            let workflow = workflow_path(path, hotkey);
+            if !workflow.exists() {
+                problems.push(format!(
+                    "{} runs {}, which does not exist",
+                    hotkey.keys,
+                    workflow.display()
+                ));
+            }
+        }
+        problems
+    }
+}
+
+fn workflow_path(config_path: &Path, hotkey: &Hotkey) -> PathBuf {
+    config_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&hotkey.workflow)
+}
+
+/// A microphone capture waiting for its second press
+struct Recording {
+    stop_file: PathBuf,
+    handle: JoinHandle<Result<String, String>>,
+}
+
+/// Runs hotkey workflows; the OS listener calls `pressed` for each key press
+pub struct Dispatcher {
+    config: HotkeyConfig,
+    config_path: PathBuf,
+    registry: Arc<PluginRegistry>,
+    recordings: Mutex<HashMap<usize, Recording>>,
+}
+
+fn call_plugin(registry: &PluginRegistry, name: &str, input: &str) -> Result<String, String> {
+    let plugin = registry
+        .get(name)
+        .ok_or_else(|| format!("{} is not installed", name))?;
+    let output = crate::exec_plugin(plugin, input)?;
+    if crate::is_error_output(&output) {
+        return Err(format!("{} failed: {}", name, output.trim()));
+    }
+    Ok(output.trim().to_string())
+}
+
+impl Dispatcher {
+    pub fn new(config: HotkeyConfig, config_path: &Path, registry: Arc<PluginRegistry>) -> Self {
+        Self {
+            config,
+            config_path: config_path.to_path_buf(),
+            registry,
+            recordings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn hotkeys(&self) -> &[Hotkey] {
+        &self.config.hotkeys
+    }
+
+    /// Handle a press of hotkey `index`. Returns the run it started, or
+    /// `None` when the press only started a recording or triggers are paused.
+    pub fn pressed(&self, index: usize) -> Option<JoinHandle<Result<Vec<StepLog>, String>>> {
+        let hotkey = self.config.hotkeys.get(index)?.clone();
+        if crate::daemon::triggers_paused() {
+            crate::logging::info("hotkey ignored while triggers are paused")
+                .field("keys", hotkey.keys)
+                .emit();
+            return None;
+        }
+        let registry = self.registry.clone();
+        let capture: Box<dyn FnOnce() -> Result<String, String> + Send> = match hotkey.capture {
+            Capture::None => Box::new(|| Ok(String::new())),
+            Capture::Clipboard => {
+                Box::new(move || call_plugin(&registry, "ClipboardPlugin", "read"))
+            }
+            // The delay lets the key release and any menu close first
+            Capture::Screenshot => {
+                Box::new(move || call_plugin(&registry, "ScreenshotPlugin", "delay_secs: 0.3"))
+            }
+            Capture::Microphone => {
+                let mut recordings = self.recordings.lock().unwrap();
+                match recordings.remove(&index) {
+                    Some(recording) => {
+                        let _ = std::fs::write(&recording.stop_file, "");
+                        Box::new(move || {
+                            let result = recording
+                                .handle
+                                .join()
+                                .unwrap_or_else(|_| Err("recording panicked".to_string()));
+                            std::fs::remove_file(&recording.stop_file).ok();
+                            result
+                        })
+                    }
+                    None => {
+                        let stop_file = std::env::temp_dir()
+                            .join(format!("lao_hotkey_{}.stop", uuid::Uuid::new_v4()));
+                        let input = format!(
+                            "stop_file: {:?}\nmax_duration_secs: {}\n",
+                            stop_file.to_string_lossy(),
+                            hotkey.max_record_secs
+                        );
+                        let handle =
+                            thread::spawn(move || call_plugin(&registry, "RecordPlugin", &input));
+                        crate::logging::info("hotkey started recording")
+                            .field("keys", hotkey.keys.clone())
+                            .emit();
+                        recordings.insert(index, Recording { stop_file, handle });
+                        return None;
+                    }
+                }
+            }
+        };
+        let path = workflow_path(&self.config_path, &hotkey)
+            .to_string_lossy()
+            .to_string();
+        let registry = self.registry.clone();
+        Some(thread::spawn(move || {
+            let capture = capture()?;
+            crate::metrics::global().record_trigger("hotkey");
+            crate::logging::info("hotkey triggered workflow")
+                .field("keys", hotkey.keys.clone())
+                .field("path", path.clone())
+                .emit();
+            let inputs = HashMap::from([("capture".to_string(), capture)]);
+            run_labels::with_trigger("hotkey", || {
+                crate::run_workflow_with_registry(&path, inputs, &registry)
+            })
+        }))
+    }
+}
+
+/// Register the hotkeys in `config_path` with the OS and dispatch presses
+/// until the process exits
+#[cfg(feature = "hotkeys")]
+pub fn serve(config_path: &Path) -> Result<JoinHandle<()>, String> {
+    use global_hotkey::{hotkey::HotKey, GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+    if !cfg!(target_os = "linux") {
+        return Err(
+            "global hotkeys are only supported on Linux (X11) so far; they need the \
+             desktop's event loop on Windows and macOS"
+                .to_string(),
+        );
+    }
+    let config = load(config_path)?;
+    let problems = config.problems(config_path);
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+    let dispatcher = Dispatcher::new(config, config_path, crate::plugins::shared().snapshot());
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+    let handle = thread::spawn(move || {
+        let registered = GlobalHotKeyManager::new()
+            .map_err(|e| e.to_string())
+            .and_then(|manager| {
+                let mut ids = HashMap::new();
+                for (index, hotkey) in dispatcher.hotkeys().iter().enumerate() {
+                    let chord = normalize(&hotkey.keys)?;
+                    let key: HotKey = chord.parse().map_err(|e| format!("{}: {}", chord, e))?;
+                    manager
+                        .register(key)
+                        .map_err(|e| format!("Cannot register {}: {}", chord, e))?;
+                    ids.insert(key.id(), index);
+                }
+                Ok((manager, ids))
+            });
+        let (_manager, ids) = match registered {
+            Ok(registered) => {
+                let _ = ready_tx.send(Ok(()));
+                registered
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        for event in GlobalHotKeyEvent::receiver().iter() {
+            if event.state != HotKeyState::Pressed {
+                continue;
+            }
+            if let Some(run) = ids.get(&event.id).and_then(|&i| dispatcher.pressed(i)) {
+                thread::spawn(move || {
+                    if let Ok(Err(e)) = run.join() {
+                        log::warn!("hotkey workflow failed: {}", e);
+                    }
+                });
+            }
+        }
+    });
+    ready_rx
+        .recv()
+        .map_err(|_| "hotkey listener stopped".to_string())??;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_plugin, mock_registry, MockSpec};
+
+    /// A temp dir with a quick.yaml workflow and a hotkeys.yaml that runs it
+    /// with the clipboard or a microphone recording.
+    fn hotkeys_dir() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_hotkeys_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("quick.yaml"),
+            "workflow: quick\nsteps:\n  - run: EchoPlugin\n    input: \"got ${capture}\"\n",
+        )
+        .unwrap();
+        let config_path = dir.join("hotkeys.yaml");
+        std::fs::write(
+            &config_path,
+            "hotkeys:\n  - keys: Control+Alt+S\n    workflow: quick.yaml\n    capture: clipboard\n  - keys: ctrl+alt+m\n    workflow: quick.yaml\n    capture: microphone\n",
+        )
+        .unwrap();
+        (dir, config_path)
+    }
+
+    #[test]
+    fn test_normalize_keys() {
+        assert_eq!(normalize("Alt+CTRL+s").unwrap(), "ctrl+alt+s");
+        assert!(normalize("ctrl+alt").is_err());
+        assert!(normalize("ctrl+s+d").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_keys_are_problems() {
+        let (dir, config_path) = hotkeys_dir();
+        let config = load(&config_path).unwrap();
+        assert!(config.problems(&config_path).is_empty());
+        let duplicate = HotkeyConfig {
+            hotkeys: vec![config.hotkeys[0].clone(); 2],
+        };
+        assert_eq!(duplicate.problems(&config_path).len(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_pressed_hotkeys_run_with_their_capture() {
+        let (dir, config_path) = hotkeys_dir();
+        let workflow = crate::load_workflow_yaml(dir.join("quick.yaml").to_str().unwrap()).unwrap();
+        let mut registry = mock_registry(&workflow, &Default::default());
+        let spec = |output: &str| MockSpec {
+            output: Some(output.to_string()),
+            ..Default::default()
+        };
+        registry.register_plugin(mock_plugin("ClipboardPlugin", spec("selected text")));
+        registry.register_plugin(mock_plugin("RecordPlugin", spec("memo.wav")));
+        let config = load(&config_path).unwrap();
+        let dispatcher = Dispatcher::new(config, &config_path, Arc::new(registry));
+
+        let logs = dispatcher.pressed(0).unwrap().join().unwrap().unwrap();
+        assert_eq!(
+            logs[0].output.as_deref(),
+            Some("EchoPlugin(got selected text)")
+        );
+        // The first microphone press only starts recording
+        assert!(dispatcher.pressed(1).is_none());
+        let logs = dispatcher.pressed(1).unwrap().join().unwrap().unwrap();
+        assert_eq!(logs[0].output.as_deref(), Some("EchoPlugin(got memo.wav)"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
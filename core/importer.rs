@@ -0,0 +1,451 @@
+// Converts node-graph exports from other tools (n8n, LangChain) into LAO
+// workflows.
+use crate::{Workflow, WorkflowStep};
+use serde_json::Value;
+use std::collections::HashMap;
+
+pub const PLACEHOLDER_PLUGIN: &str = "EchoPlugin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    N8n,
+    LangChain,
+}
+
+impl ImportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "n8n" => Some(ImportFormat::N8n),
+            "langchain" => Some(ImportFormat::LangChain),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the document's top-level keys
+    pub fn detect(doc: &Value) -> Option<Self> {
+        if doc.get("nodes").is_some_and(Value::is_array) {
+            Some(ImportFormat::N8n)
+        } else if doc.get("_type").is_some_and(Value::is_string) {
+            Some(ImportFormat::LangChain)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappedNode {
+    pub source: String,
+    pub node_type: String,
+    pub step_id: String,
+    pub plugin: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmappedNode {
+    pub source: String,
+    pub node_type: String,
+    /// Placeholder step standing in for the node, if one was created
+    pub step_id: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub mapped: Vec<MappedNode>,
+    pub unmapped: Vec<UnmappedNode>,
+    /// Trigger nodes (manual, schedule, webhook) that LAO handles outside the workflow
+    pub triggers: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ImportResult {
+    pub workflow: Workflow,
+    pub report: ImportReport,
+}
+
+impl ImportResult {
+    pub fn to_yaml(&self) -> Result<String, String> {
+        serde_yaml::to_string(&self.workflow).map_err(|e| e.to_string())
+    }
+}
+
+/// Convert an export, detecting the format when `format` is None
+pub fn import_workflow(json: &str, format: Option<ImportFormat>) -> Result<ImportResult, String> {
+    let doc: Value = serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let format = format
+        .or_else(|| ImportFormat::detect(&doc))
+        .ok_or("Unrecognised export: expected n8n `nodes` or a LangChain `_type`")?;
+    match format {
+        ImportFormat::N8n => import_n8n(&doc),
+        ImportFormat::LangChain => import_langchain(&doc),
+    }
+}
+
+// Node type suffixes (after the package prefix) and the plugin that covers them
+const N8N_PLUGINS: &[(&str, &str)] = &[
+    ("lmchatollama", "OllamaPlugin"),
+    ("lmollama", "OllamaPlugin"),
+    ("ollama", "OllamaPlugin"),
+    ("lmchatopenai", "OllamaPlugin"),
+    ("openai", "OllamaPlugin"),
+    ("agent", "OllamaPlugin"),
+    ("chainllm", "OllamaPlugin"),
+    ("chainsummarization", "SummarizerPlugin"),
+    ("summarization", "SummarizerPlugin"),
+    ("textclassifier", "ClassifierPlugin"),
+    ("sentimentanalysis", "ClassifierPlugin"),
+    ("rssfeedread", "FeedPlugin"),
+    ("emailreadimap", "MailPlugin"),
+    ("compression", "ArchivePlugin"),
+    ("set", "EchoPlugin"),
+    ("noop", "EchoPlugin"),
+];
+
+const N8N_TRIGGERS: &[&str] = &["manualtrigger", "scheduletrigger", "crontrigger", "webhook"];
+
+// Parameters that carry a node's main text input, in order of preference
+const INPUT_PARAMS: &[&str] = &["text", "prompt", "url", "input", "query", "fileName"];
+
+fn import_n8n(doc: &Value) -> Result<ImportResult, String> {
+    let name = doc["name"].as_str().unwrap_or("Imported n8n workflow");
+    let nodes = doc["nodes"].as_array().ok_or("n8n export has no nodes")?;
+    let mut report = ImportReport::default();
+
+    // Node name -> (type, parameters), dropping triggers
+    let mut kept = Vec::new();
+    for node in nodes {
+        let node_name = node["name"].as_str().unwrap_or_default().to_string();
+        let node_type = node["type"].as_str().unwrap_or_default().to_string();
+        if N8N_TRIGGERS.contains(&type_suffix(&node_type).as_str()) {
+            report.triggers.push(node_name);
+            continue;
+        }
+        kept.push((node_name, node_type, node["parameters"].clone()));
+    }
+
+    // connections: { source: { main: [[{ node: target }, ...], ...] } }
+    let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+    if let Some(connections) = doc["connections"].as_object() {
+        for (source, outputs) in connections {
+            let targets = outputs["main"].as_array().into_iter().flatten().flat_map(|o| {
+                o.as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|c| c["node"].as_str())
+            });
+            for target in targets {
+                parents.entry(target).or_default().push(source.as_str());
+            }
+        }
+    }
+
+    let order = order_nodes(&kept, &parents)?;
+    let step_ids: HashMap<&str, String> = order
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| (kept[index].0.as_str(), format!("step{}", i + 1)))
+        .collect();
+
+    let mut steps = Vec::new();
+    for &index in &order {
+        let (node_name, node_type, parameters) = &kept[index];
+        let step_id = step_ids[node_name.as_str()].clone();
+        let node_parents: Vec<String> = parents
+            .get(node_name.as_str())
+            .into_iter()
+            .flatten()
+            .filter_map(|p| step_ids.get(p).cloned())
+            .collect();
+
+        let plugin = N8N_PLUGINS
+            .iter()
+            .find(|(suffix, _)| *suffix == type_suffix(node_type))
+            .map(|(_, plugin)| *plugin);
+        let plugin = match plugin {
+            Some(plugin) => {
+                report.mapped.push(MappedNode {
+                    source: node_name.clone(),
+                    node_type: node_type.clone(),
+                    step_id: step_id.clone(),
+                    plugin: plugin.to_string(),
+                });
+                plugin
+            }
+            None => {
+                report.unmapped.push(UnmappedNode {
+                    source: node_name.clone(),
+                    node_type: node_type.clone(),
+                    step_id: Some(step_id.clone()),
+                    reason: "no matching LAO plugin; replaced with a pass-through step".to_string(),
+                });
+                PLACEHOLDER_PLUGIN
+            }
+        };
+
+        let mut params = serde_yaml::Mapping::new();
+        if node_parents.is_empty() {
+            if let Some(input) = INPUT_PARAMS
+                .iter()
+                .find_map(|key| parameters[*key].as_str())
+                .filter(|s| !s.starts_with('='))
+            {
+                params.insert("input".into(), input.into());
+            }
+        }
+        if let Some(model) = parameters["model"].as_str() {
+            params.insert("model".into(), model.into());
+        }
+        steps.push(step(plugin, params, &node_parents));
+    }
+
+    Ok(ImportResult {
+        workflow: Workflow {
+            workflow: name.to_string(),
+            steps,
+            ..Default::default()
+        },
+        report,
+    })
+}
+
+// "n8n-nodes-base.rssFeedRead" -> "rssfeedread"
+fn type_suffix(node_type: &str) -> String {
+    node_type
+        .rsplit('.')
+        .next()
+        .unwrap_or(node_type)
+        .to_ascii_lowercase()
+}
+
+// Topological order of `nodes` (indices), keeping export order among independent nodes
+fn order_nodes(
+    nodes: &[(String, String, Value)],
+    parents: &HashMap<&str, Vec<&str>>,
+) -> Result<Vec<usize>, String> {
+    let index: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.0.as_str(), i))
+        .collect();
+    let mut order = Vec::new();
+    let mut state = vec![0u8; nodes.len()]; // 0 = new, 1 = visiting, 2 = done
+
+    fn visit(
+        i: usize,
+        nodes: &[(String, String, Value)],
+        parents: &HashMap<&str, Vec<&str>>,
+        index: &HashMap<&str, usize>,
+        state: &mut [u8],
+        order: &mut Vec<usize>,
+    ) -> Result<(), String> {
+        match state[i] {
+            1 => return Err(format!("Cycle in export involving node '{}'", nodes[i].0)),
+            2 => return Ok(()),
+            _ => {}
+        }
+        state[i] = 1;
+        for parent in parents.get(nodes[i].0.as_str()).into_iter().flatten() {
+            if let Some(&p) = index.get(parent) {
+                visit(p, nodes, parents, index, state, order)?;
+            }
+        }
+        state[i] = 2;
+        order.push(i);
+        Ok(())
+    }
+
+    for i in 0..nodes.len() {
+        visit(i, nodes, parents, &index, &mut state, &mut order)?;
+    }
+    Ok(order)
+}
+
+fn import_langchain(doc: &Value) -> Result<ImportResult, String> {
+    let mut report = ImportReport::default();
+    let mut steps = Vec::new();
+    let name = doc["name"]
+        .as_str()
+        .unwrap_or("Imported LangChain chain")
+        .to_string();
+    add_chain(doc, &mut steps, &mut report);
+    if steps.is_empty() {
+        return Err("LangChain export contains no chains LAO can import".to_string());
+    }
+    Ok(ImportResult {
+        workflow: Workflow {
+            workflow: name,
+            steps,
+            ..Default::default()
+        },
+        report,
+    })
+}
+
+fn add_chain(chain: &Value, steps: &mut Vec<WorkflowStep>, report: &mut ImportReport) {
+    let chain_type = chain["_type"].as_str().unwrap_or("unknown");
+    let parents: Vec<String> = (!steps.is_empty())
+        .then(|| format!("step{}", steps.len()))
+        .into_iter()
+        .collect();
+    let step_id = format!("step{}", steps.len() + 1);
+
+    let plugin = match chain_type {
+        "sequential_chain" | "simple_sequential_chain" => {
+            for sub in chain["chains"].as_array().into_iter().flatten() {
+                add_chain(sub, steps, report);
+            }
+            return;
+        }
+        "llm_chain" => "OllamaPlugin",
+        "stuff_documents_chain" | "map_reduce_documents_chain" | "refine_documents_chain" => {
+            "SummarizerPlugin"
+        }
+        other => {
+            report.unmapped.push(UnmappedNode {
+                source: chain["name"].as_str().unwrap_or(other).to_string(),
+                node_type: other.to_string(),
+                step_id: Some(step_id.clone()),
+                reason: "unsupported chain type; replaced with a pass-through step".to_string(),
+            });
+            steps.push(step(PLACEHOLDER_PLUGIN, serde_yaml::Mapping::new(), &parents));
+            return;
+        }
+    };
+
+    let mut params = serde_yaml::Mapping::new();
+    let llm = if chain["llm"].is_object() {
+        &chain["llm"]
+    } else {
+        &chain["llm_chain"]["llm"]
+    };
+    if let Some(model) = llm["model"].as_str().or(llm["model_name"].as_str()) {
+        params.insert("model".into(), model.into());
+    }
+    if let Some(template) = chain["prompt"]["template"].as_str() {
+        // Chain variables become a reference to the previous step's output
+        let input = match parents.first() {
+            Some(parent) => replace_variables(template, &format!("${{{}}}", parent)),
+            None => template.to_string(),
+        };
+        params.insert("input".into(), input.into());
+    }
+    report.mapped.push(MappedNode {
+        source: chain["name"].as_str().unwrap_or(chain_type).to_string(),
+        node_type: chain_type.to_string(),
+        step_id,
+        plugin: plugin.to_string(),
+    });
+    // With a template the input is explicit, so the previous step is only a dependency
+    let explicit_input = params.contains_key("input");
+    let feeding: &[String] = if explicit_input { &[] } else { &parents };
+    let mut new_step = step(plugin, params, feeding);
+    if explicit_input && !parents.is_empty() {
+        new_step.depends_on = Some(parents);
+    }
+    steps.push(new_step);
+}
+
+// "{text}" -> replacement; "{{" and "}}" are LangChain's escaped braces
+fn replace_variables(template: &str, replacement: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start].replace("}}", "}"));
+        let after = &rest[start + 1..];
+        if let Some(stripped) = after.strip_prefix('{') {
+            out.push('{');
+            rest = stripped;
+        } else if let Some(end) = after.find('}').filter(|&end| {
+            end > 0 && after[..end].chars().all(|c| c.is_alphanumeric() || c == '_')
+        }) {
+            out.push_str(replacement);
+            rest = &after[end + 1..];
+        } else {
+            out.push('{');
+            rest = after;
+        }
+    }
+    out.push_str(&rest.replace("}}", "}"));
+    out
+}
+
+// A step fed by its first parent and ordered after the rest
+fn step(plugin: &str, params: serde_yaml::Mapping, parents: &[String]) -> WorkflowStep {
+    WorkflowStep {
+        run: plugin.to_string(),
+        params: serde_yaml::Value::Mapping(params),
+        input_from: parents.first().cloned(),
+        depends_on: (parents.len() > 1).then(|| parents[1..].to_vec()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_n8n() {
+        let json = r#"{
+            "name": "Feed digest",
+            "nodes": [
+                {"name": "Summarize", "type": "@n8n/n8n-nodes-langchain.chainSummarization", "parameters": {}},
+                {"name": "Start", "type": "n8n-nodes-base.manualTrigger", "parameters": {}},
+                {"name": "Read RSS", "type": "n8n-nodes-base.rssFeedRead", "parameters": {"url": "https://example.com/feed.xml"}},
+                {"name": "Post to Slack", "type": "n8n-nodes-base.slack", "parameters": {}}
+            ],
+            "connections": {
+                "Start": {"main": [[{"node": "Read RSS", "type": "main", "index": 0}]]},
+                "Read RSS": {"main": [[{"node": "Summarize", "type": "main", "index": 0}]]},
+                "Summarize": {"main": [[{"node": "Post to Slack", "type": "main", "index": 0}]]}
+            }
+        }"#;
+
+        let result = import_workflow(json, None).unwrap();
+        let steps = &result.workflow.steps;
+        assert_eq!(result.workflow.workflow, "Feed digest");
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].run, "FeedPlugin");
+        assert_eq!(
+            steps[0].params["input"].as_str(),
+            Some("https://example.com/feed.xml")
+        );
+        assert_eq!(steps[1].run, "SummarizerPlugin");
+        assert_eq!(steps[1].input_from.as_deref(), Some("step1"));
+        assert_eq!(steps[2].run, PLACEHOLDER_PLUGIN);
+
+        assert_eq!(result.report.triggers, vec!["Start"]);
+        assert_eq!(result.report.mapped.len(), 2);
+        assert_eq!(result.report.unmapped[0].source, "Post to Slack");
+        assert!(result.to_yaml().unwrap().contains("run: FeedPlugin"));
+    }
+
+    #[test]
+    fn test_import_langchain_sequential_chain() {
+        let json = r#"{
+            "_type": "sequential_chain",
+            "chains": [
+                {"_type": "llm_chain", "llm": {"_type": "ollama", "model": "mistral"},
+                 "prompt": {"template": "Translate to English: {text}"}},
+                {"_type": "llm_chain", "llm": {"_type": "ollama", "model": "llama3"},
+                 "prompt": {"template": "Summarise {{briefly}}: {translation}"}},
+                {"_type": "api_chain"}
+            ]
+        }"#;
+
+        let result = import_workflow(json, Some(ImportFormat::LangChain)).unwrap();
+        let steps = &result.workflow.steps;
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].params["model"].as_str(), Some("mistral"));
+        assert_eq!(
+            steps[1].params["input"].as_str(),
+            Some("Summarise {briefly}: ${step1}")
+        );
+        assert_eq!(steps[1].depends_on, Some(vec!["step1".to_string()]));
+        assert_eq!(steps[2].input_from.as_deref(), Some("step2"));
+        assert_eq!(result.report.unmapped[0].node_type, "api_chain");
+
+        assert!(import_workflow("{\"foo\": 1}", None).is_err());
+    }
+}
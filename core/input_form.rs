@@ -0,0 +1,268 @@
+// Prompted plugin input for `lao exec`, asked field by field from the
+// plugin's input schema.
+use crate::assertions::check_schema;
+use serde_json::{Map, Value};
+use std::io::{BufRead, Write};
+
+/// One prompted value of a plugin's input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub schema: Value,
+    pub required: bool,
+}
+
+/// The fields asked for by `schema`, required ones first; `None` when the
+/// schema isn't an object with properties
+pub fn fields(schema: &Value) -> Option<Vec<Field>> {
+    let properties = schema.get("properties")?.as_object()?;
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .filter(|name| properties.contains_key(*name))
+        .collect();
+    let mut optional: Vec<&String> = properties
+        .keys()
+        .filter(|name| !required.contains(&name.as_str()))
+        .collect();
+    optional.sort();
+    let field = |name: &str, required: bool| Field {
+        name: name.to_string(),
+        schema: properties[name].clone(),
+        required,
+    };
+    Some(
+        required
+            .iter()
+            .map(|name| field(name, true))
+            .chain(optional.into_iter().map(|name| field(name, false)))
+            .collect(),
+    )
+}
+
+/// Ask for a value of `schema` on `output`, reading answers from `input`
+pub fn prompt<R: BufRead, W: Write>(
+    schema: &Value,
+    mut input: R,
+    mut output: W,
+) -> Result<Value, String> {
+    let Some(fields) = fields(schema) else {
+        let field = Field {
+            name: "input".to_string(),
+            schema: schema.clone(),
+            required: true,
+        };
+        return ask(&field, &mut input, &mut output)?.ok_or_else(|| "no input given".to_string());
+    };
+    let mut object = Map::new();
+    for field in &fields {
+        if let Some(value) = ask(field, &mut input, &mut output)? {
+            object.insert(field.name.clone(), value);
+        }
+    }
+    Ok(Value::Object(object))
+}
+
+// Ask for one field until the answer fits; `None` leaves it out
+fn ask<R: BufRead, W: Write>(
+    field: &Field,
+    input: &mut R,
+    output: &mut W,
+) -> Result<Option<Value>, String> {
+    let default = field.schema.get("default");
+    if let Some(description) = field.schema.get("description").and_then(Value::as_str) {
+        writeln!(output, "{}", description).map_err(|e| e.to_string())?;
+    }
+    loop {
+        let shown_default = default
+            .map(|value| format!(" [{}]", shown(value)))
+            .unwrap_or_default();
+        let required = if field.required && default.is_none() {
+            ", required"
+        } else {
+            ""
+        };
+        write!(
+            output,
+            "{}{} ({}{}): ",
+            field.name,
+            shown_default,
+            hint(&field.schema),
+            required
+        )
+        .and_then(|_| output.flush())
+        .map_err(|e| e.to_string())?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err(format!("input ended before '{}' was given", field.name));
+        }
+        let answer = line.trim();
+        if answer.is_empty() {
+            match default {
+                Some(value) => return Ok(Some(value.clone())),
+                None if !field.required => return Ok(None),
+                None => {
+                    writeln!(output, "  a value is required").map_err(|e| e.to_string())?;
+                    continue;
+                }
+            }
+        }
+        let checked = parse(answer, &field.schema)
+            .and_then(|value| check_schema(&value, &field.schema, &field.name).map(|_| value));
+        match checked {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) => writeln!(output, "  {}", e).map_err(|e| e.to_string())?,
+        }
+    }
+}
+
+// What the prompt says the answer should look like
+fn hint(schema: &Value) -> String {
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        let choices: Vec<String> = allowed.iter().map(shown).collect();
+        return format!("one of: {}", choices.join(", "));
+    }
+    match schema_type(schema) {
+        Some("boolean") => "y/n".to_string(),
+        Some("array") => "JSON array".to_string(),
+        Some("object") => "JSON object".to_string(),
+        Some(name) => name.to_string(),
+        None => "text or JSON".to_string(),
+    }
+}
+
+// Turn an answer into a value of the schema's type
+fn parse(answer: &str, schema: &Value) -> Result<Value, String> {
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        return allowed
+            .iter()
+            .find(|value| shown(value) == answer)
+            .cloned()
+            .ok_or_else(|| format!("'{}' is not one of the choices", answer));
+    }
+    match schema_type(schema) {
+        Some("string") => Ok(Value::String(answer.to_string())),
+        Some("integer") => answer
+            .parse::<i64>()
+            .map(Value::from)
+            .map_err(|_| format!("'{}' is not a whole number", answer)),
+        Some("number") => answer
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("'{}' is not a number", answer)),
+        Some("boolean") => match answer.to_ascii_lowercase().as_str() {
+            "y" | "yes" | "true" => Ok(Value::Bool(true)),
+            "n" | "no" | "false" => Ok(Value::Bool(false)),
+            _ => Err(format!("'{}' is not y or n", answer)),
+        },
+        Some("array") => serde_json::from_str(answer).or_else(|e| {
+            let strings = schema.pointer("/items/type").and_then(Value::as_str) == Some("string");
+            if strings && !answer.starts_with('[') {
+                Ok(answer
+                    .split(',')
+                    .map(|item| Value::String(item.trim().to_string()))
+                    .collect())
+            } else {
+                Err(format!("not a JSON array: {}", e))
+            }
+        }),
+        Some("object") => {
+            serde_json::from_str(answer).map_err(|e| format!("not a JSON object: {}", e))
+        }
+        _ => Ok(serde_json::from_str(answer).unwrap_or_else(|_| Value::String(answer.to_string()))),
+    }
+}
+
+// The first type a schema names, if any
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type")? {
+        Value::String(name) => Some(name),
+        Value::Array(names) => names.iter().find_map(Value::as_str),
+        _ => None,
+    }
+}
+
+// A value as typed at the prompt: strings without quotes
+fn shown(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "required": ["text"],
+            "properties": {
+                "text": {"type": "string"},
+                "style": {"enum": ["short", "bullets"], "default": "short"},
+                "max_words": {"type": "integer", "minimum": 10},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "verbose": {"type": "boolean", "description": "Explain the choice"}
+            }
+        })
+    }
+
+    #[test]
+    fn test_required_fields_come_first() {
+        let names: Vec<String> = fields(&schema())
+            .unwrap()
+            .into_iter()
+            .map(|field| field.name)
+            .collect();
+        assert_eq!(names, ["text", "max_words", "style", "tags", "verbose"]);
+    }
+
+    #[test]
+    fn test_invalid_answers_are_asked_again() {
+        // An empty required answer, a number below the minimum and an unknown
+        // choice are each asked again
+        let answers = "\nnotes\nfive\n3\n200\nlong\n\nwork, urgent\nyes\n";
+        let mut output = Vec::new();
+        let value = prompt(&schema(), answers.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "text": "notes",
+                "max_words": 200,
+                "style": "short",
+                "tags": ["work", "urgent"],
+                "verbose": true
+            })
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("text (string, required): "));
+        assert!(output.contains("style [short] (one of: short, bullets): "));
+        assert!(output.contains("max_words is below 10"));
+        assert!(output.contains("Explain the choice\nverbose (y/n): "));
+    }
+
+    #[test]
+    fn test_optional_fields_can_be_left_out() {
+        let value = prompt(&schema(), "notes\n\n\n\n\n".as_bytes(), Vec::new()).unwrap();
+        assert_eq!(value, json!({"text": "notes", "style": "short"}));
+    }
+
+    #[test]
+    fn test_running_out_of_answers_is_an_error() {
+        assert!(prompt(&schema(), "".as_bytes(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_non_object_schema_is_one_value() {
+        let value = prompt(&json!({"type": "string"}), "hello\n".as_bytes(), Vec::new()).unwrap();
+        assert_eq!(value, json!("hello"));
+    }
+}
@@ -0,0 +1,218 @@
+// Input validation rules declared by steps.
+// The rules a step can declare are in docs/workflows.md (Input Validation).
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Values longer than this are shortened in error messages
+const SHOWN_VALUE_CHARS: usize = 60;
+
+/// One `validate:` entry of a step
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRule {
+    /// Param to check; the plugin input when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    /// Maximum length in characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// The value must name an existing file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_exists: Option<bool>,
+    /// Allowed values
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+    /// Error to report instead of the default; `{value}` is replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl InputRule {
+    /// Why the rule can't be checked, if it can't
+    pub fn problem(&self) -> Option<String> {
+        if self.regex.is_none()
+            && self.max_length.is_none()
+            && self.file_exists.is_none()
+            && self.allowed.is_none()
+        {
+            return Some(format!(
+                "validation rule for {} has no check (regex, max_length, file_exists or enum)",
+                self.subject()
+            ));
+        }
+        let regex = self.regex.as_deref()?;
+        Regex::new(regex)
+            .err()
+            .map(|e| format!("invalid regex for {}: {}", self.subject(), e))
+    }
+
+    /// Check `value`, the rule's param or the plugin input
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        let failure = self.failure(value);
+        match (failure, &self.message) {
+            (None, _) => Ok(()),
+            (Some(_), Some(message)) => Err(message.replace("{value}", value)),
+            (Some(failure), None) => Err(failure),
+        }
+    }
+
+    fn failure(&self, value: &str) -> Option<String> {
+        let subject = self.subject();
+        if self.file_exists == Some(true) && !Path::new(value).is_file() {
+            return Some(format!("{} file not found: {}", subject, value));
+        }
+        if let Some(max) = self.max_length {
+            let length = value.chars().count();
+            if length > max {
+                return Some(format!(
+                    "{} is {} characters long, more than {}",
+                    subject, length, max
+                ));
+            }
+        }
+        if let Some(allowed) = &self.allowed {
+            if !allowed.iter().any(|a| a == value) {
+                return Some(format!(
+                    "{} must be one of {}, got '{}'",
+                    subject,
+                    allowed.join(", "),
+                    shown(value)
+                ));
+            }
+        }
+        if let Some(regex) = &self.regex {
+            let matches = Regex::new(regex).map(|re| re.is_match(value));
+            if !matches.unwrap_or(false) {
+                return Some(format!(
+                    "{} does not match /{}/: '{}'",
+                    subject,
+                    regex,
+                    shown(value)
+                ));
+            }
+        }
+        None
+    }
+
+    fn subject(&self) -> String {
+        match &self.param {
+            Some(param) => format!("param '{}'", param),
+            None => "input".to_string(),
+        }
+    }
+}
+
+/// Check a step's rules against its rendered params and plugin input.
+/// Every failed rule is reported, joined with "; ".
+pub fn check_step(
+    rules: &[InputRule],
+    params: &serde_yaml::Value,
+    input: &str,
+) -> Result<(), String> {
+    let failures: Vec<String> = rules
+        .iter()
+        .filter_map(|rule| {
+            let value = match &rule.param {
+                None => Some(input.to_string()),
+                Some(param) => params.get(param.as_str()).and_then(param_text),
+            };
+            match value {
+                Some(value) => rule.check(&value).err(),
+                None => Some(format!("{} is not set", rule.subject())),
+            }
+        })
+        .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures.join("; "))
+    }
+}
+
+// Scalars are checked as they would be written in YAML
+fn param_text(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn shown(value: &str) -> String {
+    if value.chars().count() <= SHOWN_VALUE_CHARS {
+        return value.to_string();
+    }
+    let head: String = value.chars().take(SHOWN_VALUE_CHARS).collect();
+    format!("{}...", head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> Vec<InputRule> {
+        serde_yaml::from_str(
+            r#"
+- file_exists: true
+  regex: '\.wav$'
+- param: language
+  enum: [en, de]
+- param: prompt
+  max_length: 10
+  message: "prompt too long: {value}"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_valid_input_passes() {
+        let dir = std::env::temp_dir().join(format!("lao_rules_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let audio = dir.join("meeting.wav");
+        std::fs::write(&audio, b"RIFF").unwrap();
+        let rules = rules();
+        assert!(rules.iter().all(|r| r.problem().is_none()));
+        let params: serde_yaml::Value =
+            serde_yaml::from_str("language: en\nprompt: short").unwrap();
+        check_step(&rules, &params, audio.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_every_broken_rule_is_reported() {
+        let missing = std::env::temp_dir().join(format!("lao_rules_{}.wav", uuid::Uuid::new_v4()));
+        let params: serde_yaml::Value =
+            serde_yaml::from_str("language: fr\nprompt: far too long a prompt").unwrap();
+        let error = check_step(&rules(), &params, missing.to_str().unwrap()).unwrap_err();
+        assert_eq!(
+            error,
+            format!(
+                "input file not found: {}; param 'language' must be one of en, de, got 'fr'; prompt too long: far too long a prompt",
+                missing.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_missing_param_is_reported() {
+        let error = check_step(&rules()[1..2], &serde_yaml::Value::Null, "").unwrap_err();
+        assert_eq!(error, "param 'language' is not set");
+    }
+
+    #[test]
+    fn test_rule_problems() {
+        let bad = InputRule {
+            regex: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(bad
+            .problem()
+            .unwrap()
+            .starts_with("invalid regex for input"));
+        assert!(InputRule::default().problem().is_some());
+    }
+}
@@ -1,50 +1,306 @@
 // --- Workflow Engine (Step 2) ---
-use lao_plugin_api::PluginInput;
-use std::collections::HashMap;
-use std::env as std_env;
-use std::ffi::CString;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::process::Command;
 use std::time::Instant;
-use std::{thread, time::Duration};
+use std::time::Duration;
+pub mod ab;
+pub mod api;
+pub mod artifacts;
+pub mod assertions;
+pub mod audit;
+pub mod backends;
+pub mod branching;
+pub mod breakpoints;
+pub mod cancel;
+pub mod channel;
+pub mod checkpoint;
+pub mod context_window;
+pub mod crash;
 pub mod cross_platform;
+pub mod daemon;
+pub mod data_dir;
+pub mod dedup;
+pub mod display;
+pub mod encryption;
+pub mod env_vars;
+pub mod evaluation;
+pub mod exporter;
+pub mod extends;
+pub mod foreach;
+pub mod golden;
+pub mod guard;
+pub mod hooks;
+pub mod hotkeys;
+pub mod importer;
+pub mod input_form;
+pub mod input_rules;
+pub mod logging;
+pub mod map_reduce;
+pub mod memo;
+pub mod metadata;
+pub mod metrics;
+pub mod mock;
+pub mod mqtt;
+pub mod node;
+pub mod ollama;
+pub mod orchestrator;
+pub mod params;
+pub mod pipeline;
+pub mod plan;
 pub mod plugin_dev_tools;
+pub mod plugin_fuzz;
 pub mod plugin_manager;
+pub mod plugin_policy;
 pub mod plugins;
+pub mod prefix_cache;
+pub mod presets;
+pub mod process_output;
+pub mod prompts;
+pub mod publish;
+pub mod redaction;
+pub mod replay;
+pub mod retry;
+pub mod rpc;
+pub mod run_class;
+pub mod run_diff;
+pub mod run_labels;
+pub mod run_logs;
+pub mod run_queue;
+pub mod sandbox;
 pub mod scheduler;
+pub mod script_hooks;
+pub mod secrets;
+pub mod service;
+pub mod sinks;
 pub mod state_manager;
+pub mod streaming;
+pub mod strict;
+pub mod telemetry;
+pub mod tiers;
+pub mod tray;
+pub mod triggers;
+pub mod typed_io;
+pub mod usage;
+pub mod warmup;
+pub mod wasm_plugins;
+pub mod webhook;
 pub mod workflow_state;
+pub mod workflow_validation;
 
+use assertions::AssertAction;
 use lao_plugin_api::{PluginInputType, PluginOutputType};
+use node::{NodeId, NodeStatus};
+use plan::{ExecutionPlan, PlannedStep};
 use plugins::*;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub use orchestrator::{Orchestrator, OrchestratorError, RunEvent, RunHandle, RunOutcome};
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct Workflow {
     pub workflow: String,
+    /// Title, description, author, required plugins and examples (see `metadata`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<metadata::WorkflowMetadata>,
     pub steps: Vec<WorkflowStep>,
+    /// Step whose output answers a webhook trigger (see `webhook`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ResponseSpec>,
+    /// Redaction rules for this workflow's runs, on top of redaction.yaml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<redaction::RedactionConfig>,
+    /// Models to load before the steps that need them (see `warmup`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<warmup::ModelSpec>,
+    /// The only plugins the steps may call (see `plugin_policy`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugins: Option<Vec<plugin_policy::PluginPin>>,
+    /// Files successful runs write step outputs to (see `publish`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<publish::PublishSpec>,
+    /// Topic whose messages run this workflow in the daemon (see `mqtt`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<mqtt::MqttTrigger>,
+    /// Sanitizing and fencing of untrusted content given to LLM steps (see
+    /// `guard`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guard: Option<guard::GuardConfig>,
+    /// Values runs give with `--param`, and their defaults (see `params`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<BTreeMap<String, serde_yaml::Value>>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct ResponseSpec {
+    pub step: String,
+    #[serde(default = "default_response_status")]
+    pub status: u16,
+    #[serde(default = "default_response_content_type")]
+    pub content_type: String,
+}
+
+fn default_response_status() -> u16 {
+    200
+}
+
+fn default_response_content_type() -> String {
+    "text/plain; charset=utf-8".to_string()
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, Clone)]
 pub struct WorkflowStep {
     pub run: String,
+    /// Name other steps refer to the step by; `step<n>` by position when
+    /// unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     #[serde(flatten)]
     pub params: serde_yaml::Value,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub retries: Option<u32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub retry_delay: Option<u64>, // milliseconds
-    #[serde(default)]
+    /// How the delay grows from one retry to the next (see `retry`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<retry::Backoff>,
+    /// Failures worth retrying; any failure when unset (see `retry`)
+    #[serde(
+        default,
+        deserialize_with = "branching::one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retry_on: Option<Vec<String>>,
+    /// Seconds an attempt may take before it is stopped (see `cancel`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cache_key: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub input_from: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub depends_on: Option<Vec<String>>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub condition: Option<StepCondition>,
-    #[serde(default)]
+    /// Expression over earlier steps; the step is skipped when it doesn't
+    /// hold (see `branching`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    #[serde(
+        default,
+        alias = "if_succeeded",
+        deserialize_with = "branching::one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub on_success: Option<Vec<String>>, // Step IDs to execute on success
-    #[serde(default)]
+    #[serde(
+        default,
+        alias = "if_failed",
+        deserialize_with = "branching::one_or_many",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub on_failure: Option<Vec<String>>, // Step IDs to execute on failure
+    /// Share the output across workflows by content hash (see `memo`);
+    /// defaults to on for plugins tagged `deterministic`
+    #[serde(default, alias = "cache", skip_serializing_if = "Option::is_none")]
+    pub memoize: Option<bool>,
+    /// Checks of the input made before the plugin runs (see `input_rules`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validate: Vec<input_rules::InputRule>,
+    /// Quality gates on the output (see `assertions`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assert: Vec<assertions::OutputAssertion>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_assert_fail: Option<assertions::AssertAction>,
+    /// Plugin given the input when the output fails its assertions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// Scores of the output kept for ranking (see `evaluation`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evaluate: Option<evaluation::EvaluateSpec>,
+    /// `false` keeps the step in the workflow but out of runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when_disabled: Option<DisabledMode>,
+    /// Pause before the step so its input can be checked or replaced (see
+    /// `breakpoints`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakpoint: Option<bool>,
+    /// `map_reduce` runs the plugin per chunk of a long input and combines
+    /// the results (see `map_reduce`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<map_reduce::StepStrategy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map_reduce: Option<map_reduce::MapReduceSpec>,
+    /// Runs the plugin once per item of a list (see `foreach`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreach: Option<foreach::ForeachSpec>,
+    /// Context window and what to do with inputs over it (see `context_window`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<context_window::ContextSpec>,
+    /// Label, icon and color in the UI graph (see `display`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display: Option<display::StepDisplay>,
+    /// Wraps the input before the plugin is called, `{input}` standing for
+    /// it (see `presets`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// The preset the step was filled from, merged in when loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// The prompt file, `path@version`, read into the `prompt` param when
+    /// loaded (see `prompts`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_file: Option<String>,
+    /// Lane the step waits for in a batch; from the plugin's tags when unset
+    /// (see `pipeline`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<pipeline::Resource>,
+    /// Server the step's LLM requests go to, e.g. `ollama@gpu-box` (see
+    /// `backends`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Whether the output is untrusted content; from the plugin's tags when
+    /// unset (see `guard`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub untrusted: Option<bool>,
+}
+
+/// What a disabled step leaves for the steps after it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisabledMode {
+    /// No output, like a step whose condition isn't met
+    #[default]
+    Skip,
+    /// The step's input becomes its output, as if the plugin echoed it
+    PassThrough,
+}
+
+impl DisabledMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DisabledMode::Skip => "skip",
+            DisabledMode::PassThrough => "pass_through",
+        }
+    }
+}
+
+impl WorkflowStep {
+    /// The step's id when it is at `index` in its workflow
+    pub fn id_at(&self, index: usize) -> String {
+        self.id
+            .clone()
+            .unwrap_or_else(|| format!("step{}", index + 1))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled != Some(false)
+    }
+
+    /// How the step is left out of runs, if it is disabled
+    pub fn disabled_mode(&self) -> Option<DisabledMode> {
+        (!self.is_enabled()).then(|| self.when_disabled.unwrap_or_default())
+    }
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -81,7 +337,7 @@ pub struct DagNode {
     pub parents: Vec<String>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct StepLog {
     pub step: usize,
     pub step_id: String,
@@ -89,64 +345,54 @@ pub struct StepLog {
     pub input: serde_yaml::Value,
     pub output: Option<String>,
     pub error: Option<String>,
+    /// Set when a binary output was captured into the artifact store
+    pub artifact: Option<lao_plugin_api::ArtifactRef>,
     pub attempt: u32,
     pub input_type: Option<lao_plugin_api::PluginInputType>,
     pub output_type: Option<lao_plugin_api::PluginOutputType>,
     pub validation: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct StepEvent {
     pub step: usize,
-    pub step_id: String,
+    pub step_id: NodeId,
     pub runner: String,
-    pub status: String, // pending | running | success | error | cache | skipped
+    pub status: NodeStatus,
     pub attempt: u32,
     pub message: Option<String>,
     pub output: Option<String>,
     pub error: Option<String>,
+    pub artifact: Option<lao_plugin_api::ArtifactRef>,
+    /// Progress of a fan-out step (see `foreach`)
+    #[serde(default)]
+    pub instances: Option<foreach::Instances>,
+    /// On a failed attempt that will be retried, milliseconds until the retry
+    #[serde(default)]
+    pub retry_in_ms: Option<u64>,
 }
 
 pub fn load_workflow_yaml(path: &str) -> Result<Workflow, String> {
     let yaml_str = fs::read_to_string(path).map_err(|e| e.to_string())?;
-    serde_yaml::from_str::<Workflow>(&yaml_str).map_err(|e| e.to_string())
+    let dir = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    parse_workflow_yaml(&yaml_str, dir)
 }
 
-pub fn run_model_runner(runner: &str, params: serde_yaml::Value) -> Result<String, String> {
-    // Example: match runner and build the real command
-    let mut cmd = Command::new(runner);
-    // For demonstration, handle Whisper.cpp and Ollama with basic params
-    if runner == "whisper" {
-        // Example: { input: "audio.wav" }
-        if let Some(input) = params.get("input").and_then(|v| v.as_str()) {
-            cmd.arg(input);
-        }
-    } else if runner == "ollama" {
-        // Example: { model: "mistral", prompt: "..." }
-        if let Some(model) = params.get("model").and_then(|v| v.as_str()) {
-            cmd.arg("run").arg(model);
-        }
-        if let Some(prompt) = params.get("prompt").and_then(|v| v.as_str()) {
-            cmd.arg(prompt);
-        }
-    } else {
-        // Fallback: pass all params as stringified args
-        for (k, v) in params.as_mapping().unwrap_or(&serde_yaml::Mapping::new()) {
-            cmd.arg(format!("--{k:?}")).arg(format!("{v:?}"));
-        }
-    }
-    // Run the command and capture output
-    let output = cmd
-        .output()
-        .map_err(|e| format!("Failed to run {runner}: {e}"))?;
-    if !output.status.success() {
-        return Err(format!(
-            "{} failed: {}",
-            runner,
-            String::from_utf8_lossy(&output.stderr)
-        ));
+/// Like `load_workflow_yaml`, for YAML that isn't in a file; `extends` paths
+/// are relative to `dir`
+pub fn parse_workflow_yaml(yaml_str: &str, dir: &std::path::Path) -> Result<Workflow, String> {
+    let workflow = match extends::resolve(yaml_str, dir)? {
+        Some(merged) => serde_yaml::from_value::<Workflow>(merged),
+        None => serde_yaml::from_str::<Workflow>(yaml_str),
     }
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    .map_err(|e| e.to_string())?;
+    // ${{ env.NAME }} and a run's ${{ params.NAME }} are filled in once,
+    // before the plan is compiled
+    let workflow = env_vars::expand(workflow)?;
+    let declared = workflow.params.clone();
+    params::expand(workflow, declared.as_ref())
 }
 
 pub fn build_dag(steps: &[WorkflowStep]) -> Result<Vec<DagNode>, String> {
@@ -159,8 +405,8 @@ pub fn build_dag(steps: &[WorkflowStep]) -> Result<Vec<DagNode>, String> {
         if let Some(depends_on) = &step.depends_on {
             parents.extend(depends_on.clone());
         }
-        // Use step{index+1} format for node IDs to match YAML conventions
-        let step_id = format!("step{}", index + 1);
+        // step{index+1} unless the step names itself, to match YAML conventions
+        let step_id = step.id_at(index);
         nodes.push(DagNode {
             id: step_id,
             step: step.clone(),
@@ -221,33 +467,54 @@ pub fn validate_workflow_types(
             continue;
         };
 
-        // Gather primary capability types (fallback to Any when unknown)
-        let (curr_in_ty, curr_out_ty) = primary_io_types(curr_plugin);
+        if let Some(signature) = curr_plugin.signature() {
+            for problem in typed_io::check_step(signature, &node.step) {
+                errors.push((i, problem));
+            }
+        }
 
         // Validate each parent edge type compatibility
         for parent_id in &node.parents {
             if let Some(parent_node) = dag.iter().find(|n| &n.id == parent_id) {
-                if let Some(parent_plugin) = plugin_registry.get(&parent_node.step.run) {
-                    let (_p_in, p_out) = primary_io_types(parent_plugin);
-                    if !types_compatible(p_out.clone(), curr_in_ty.clone()) {
-                        errors.push((
-                            i,
-                            format!(
-                                "Type mismatch: parent '{}' outputs {:?} but '{}' expects {:?}",
-                                parent_node.step.run, p_out, node.step.run, curr_in_ty
-                            ),
-                        ));
-                    }
+                for problem in edge_problems(plugin_registry, &parent_node.step.run, &node.step.run)
+                {
+                    errors.push((i, problem));
                 }
             }
         }
-        // Unused variable suppression
-        let _ = curr_out_ty;
     }
     errors
 }
 
+/// Why output of plugin `from` can't feed plugin `to`; empty when it can or
+/// when either isn't registered
+pub fn edge_problems(plugin_registry: &PluginRegistry, from: &str, to: &str) -> Vec<String> {
+    let (Some(parent_plugin), Some(curr_plugin)) =
+        (plugin_registry.get(from), plugin_registry.get(to))
+    else {
+        return Vec::new();
+    };
+    let mut problems = Vec::new();
+    let (_p_in, p_out) = primary_io_types(parent_plugin);
+    let (curr_in_ty, _c_out) = primary_io_types(curr_plugin);
+    if !types_compatible(p_out.clone(), curr_in_ty.clone()) {
+        problems.push(format!(
+            "Type mismatch: parent '{}' outputs {:?} but '{}' expects {:?}",
+            from, p_out, to, curr_in_ty
+        ));
+    }
+    if let (Some(from_sig), Some(to_sig)) = (parent_plugin.signature(), curr_plugin.signature()) {
+        if let Some(problem) = typed_io::check_edge(from_sig, to_sig) {
+            problems.push(format!("'{}' -> '{}': {}", from, to, problem));
+        }
+    }
+    problems
+}
+
 fn primary_io_types(plugin: &PluginInstance) -> (PluginInputType, PluginOutputType) {
+    if let Some(signature) = plugin.signature() {
+        return typed_io::io_types(signature);
+    }
     let caps = plugin.get_capabilities();
     if let Some(cap) = caps.first() {
         (cap.input_type.clone(), cap.output_type.clone())
@@ -269,6 +536,8 @@ fn types_compatible(from: PluginOutputType, to: PluginInputType) -> bool {
         (Out::Audio, In::Audio) => true,
         (Out::Image, In::Image) => true,
         (Out::Video, In::Video) => true,
+        // JSON is passed between steps as text, so text consumers can take it as-is
+        (Out::Json, In::Text) => true,
         // Allow cross-type compatibility for media files
         (Out::Audio, In::File) => true,
         (Out::Image, In::File) => true,
@@ -281,168 +550,296 @@ fn types_compatible(from: PluginOutputType, to: PluginInputType) -> bool {
 }
 
 pub fn run_workflow_yaml(path: &str) -> Result<Vec<StepLog>, String> {
-    let workflow = load_workflow_yaml(path)?;
-    let dag = build_dag(&workflow.steps)?;
-    let registry = PluginRegistry::default_registry();
+    run_workflow_yaml_with_inputs(path, HashMap::new())
+}
 
-    // Validate workflow
-    let errors = validate_workflow_types(&dag, &registry);
-    if !errors.is_empty() {
-        return Err(format!("Workflow validation failed: {:?}", errors));
-    }
+/// Like `run_workflow_yaml`, with `inputs` available to steps as `${name}`
+/// placeholders alongside step outputs.
+pub fn run_workflow_yaml_with_inputs(
+    path: &str,
+    inputs: HashMap<String, String>,
+) -> Result<Vec<StepLog>, String> {
+    run_workflow_with_registry(path, inputs, &plugins::shared().snapshot())
+}
 
-    // Topological sort
-    let execution_order = topo_sort(&dag)?;
+/// Like `run_workflow_yaml_with_inputs`, resolving steps in `registry`
+/// instead of the shared plugin registry, e.g. against mock plugins
+pub fn run_workflow_with_registry(
+    path: &str,
+    inputs: HashMap<String, String>,
+    registry: &PluginRegistry,
+) -> Result<Vec<StepLog>, String> {
+    run_steps(path, inputs, registry, &mut |_| {})
+}
+
+// The executor behind every sequential run. Each step's events are logged,
+// then passed to `on_event` with their text redacted
+fn run_steps(
+    path: &str,
+    inputs: HashMap<String, String>,
+    registry: &PluginRegistry,
+    on_event: &mut dyn FnMut(StepEvent),
+) -> Result<Vec<StepLog>, String> {
+    let plan = params::with_values(&inputs, || ExecutionPlan::load(path, registry))?;
+    reject_invalid(&plan)?;
+    let workflow = &plan.workflow;
+    let inputs = params::inputs(workflow.params.as_ref(), inputs);
 
     let mut logs = Vec::new();
-    let mut outputs: HashMap<String, String> = HashMap::new();
+    let mut outputs = inputs.clone();
+    // Artifact id per step, for lineage of downstream artifacts
+    let mut step_artifacts: HashMap<String, String> = HashMap::new();
     let start_time = Instant::now();
-
-    for (step_idx, node_id) in execution_order.iter().enumerate() {
-        let node = dag.iter().find(|n| &n.id == node_id).unwrap();
-        let step = &node.step;
-
-        // Build input parameters
-        let mut params = step.params.clone();
-
-        // Handle input_from: use output from referenced step as input
-        if let Some(input_from) = &step.input_from {
-            if let Some(step_output) = outputs.get(input_from) {
-                // Override the input parameter with the referenced step's output
-                if let Some(mapping) = params.as_mapping_mut() {
-                    mapping.insert(
-                        serde_yaml::Value::String("input".to_string()),
-                        serde_yaml::Value::String(step_output.clone()),
-                    );
-                } else {
-                    // Create new mapping if params wasn't a mapping
-                    let mut new_mapping = serde_yaml::Mapping::new();
-                    new_mapping.insert(
-                        serde_yaml::Value::String("input".to_string()),
-                        serde_yaml::Value::String(step_output.clone()),
-                    );
-                    params = serde_yaml::Value::Mapping(new_mapping);
-                }
+    let run_id = telemetry::new_run_id();
+    // Kept for the whole run so its events use the workflow's redaction rules
+    let _redaction_scope = redaction::RunScope::enter(&run_id, workflow.redaction.as_ref())?;
+    let run_span = start_run_span(workflow, &run_id);
+    let mut run_usage = usage::RunUsage::new(&run_id, &workflow.workflow);
+    let crash_scope = crash::RunScope::enter(&run_id, &workflow.workflow);
+    logging::info("workflow started")
+        .run(&run_id)
+        .field("workflow", workflow.workflow.clone())
+        .field("path", path)
+        .field("steps", plan.order.len())
+        .field("class", run_class::current().as_str())
+        .emit();
+    let labels = run_labels::for_run();
+    let run_ctx = hooks::RunContext {
+        run_id: &run_id,
+        workflow,
+        path,
+        inputs: &inputs,
+        labels: &labels,
+    };
+    hooks::run_started(&run_ctx);
+    let mut on_event = |event: StepEvent| {
+        // Streamed output would log a line per chunk; the finished output is logged
+        let streamed = event.status == NodeStatus::Running && event.output.is_some();
+        let mut log = step_log_event(
+            &run_id,
+            &event.step_id,
+            &event.runner,
+            event.status,
+            event.attempt,
+        );
+        if let Some(message) = &event.message {
+            log = log.field("detail", message.clone());
+        }
+        if let Some(error) = &event.error {
+            log = log.field("error", error.clone());
+        }
+        if let Some(delay) = event.retry_in_ms {
+            log = log.field("retry_in_ms", delay);
+        }
+        if !streamed {
+            log.emit();
+        }
+        // Step output shown in the UI gets the same redaction as the logs
+        let redact = |text: Option<String>| text.map(|t| redaction::redact(&t, Some(&run_id)));
+        on_event(StepEvent {
+            message: redact(event.message),
+            output: redact(event.output),
+            error: redact(event.error),
+            ..event
+        });
+    };
+
+    // Released when the run returns
+    let warmup = warmup::Warmup::start(&workflow.models, &run_id);
+    // Background runs wait for interactive ones between steps
+    let run_ticket = run_class::gate().begin_run(&run_id);
+    let mut dedup = dedup::RunDedup::new();
+    let mut run_guard = guard::RunGuard::new(&plan, registry);
+    let memo_store = memo::MemoStore::open_default();
+    // Time each step took, for the run snapshot
+    let mut step_durations: HashMap<String, Duration> = HashMap::new();
+
+    // Set when the run's cancel token stops it
+    let mut cancelled = false;
+    let mut run_sinks = sinks::RunSinks::new(
+        plan.in_order()
+            .map(|planned| (planned.id.to_string(), &planned.step)),
+    );
+    for (step_idx, planned) in plan.in_order().enumerate() {
+        let node_id = &planned.id.to_string();
+        if cancel::requested() {
+            cancelled = true;
+            logging::warn("run cancelled")
+                .run(&run_id)
+                .step(node_id.as_str())
+                .emit();
+            cancelled_events(&plan, step_idx).for_each(&mut on_event);
+            break;
+        }
+        let step = &planned.step;
+        let mut step_span = start_step_span(&run_span, node_id, step);
+        crash_scope.step(node_id, &step.run);
+        let step_ctx = hooks::StepContext {
+            index: step_idx,
+            step_id: node_id,
+            step,
+        };
+        hooks::step_started(&run_ctx, &step_ctx);
+        let step_id = planned.id.clone();
+
+        // Params with step outputs and run inputs filled in, untrusted ones guarded
+        let (mut params, mut plugin_input) =
+            planned.render(&run_guard.outputs(&run_id, planned, &outputs));
+        if let Some(mode) = step.disabled_mode() {
+            let output = (mode == DisabledMode::PassThrough).then(|| plugin_input.clone());
+            if let Some(output) = &output {
+                outputs.insert(node_id.clone(), output.clone());
             }
+            on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Skipped,
+                attempt: 1,
+                message: Some(match mode {
+                    DisabledMode::Skip => "disabled".to_string(),
+                    DisabledMode::PassThrough => "disabled, input passed through".to_string(),
+                }),
+                output: output.clone(),
+                error: None,
+                artifact: None,
+                instances: None,
+                retry_in_ms: None,
+            });
+            logs.push(StepLog {
+                step: step_idx,
+                step_id: node_id.clone(),
+                runner: step.run.clone(),
+                input: params.clone(),
+                output,
+                error: None,
+                artifact: None,
+                attempt: 1,
+                input_type: None,
+                output_type: None,
+                validation: Some("disabled".to_string()),
+            });
+            step_finished(
+                &run_ctx,
+                &step_ctx,
+                &logs,
+                NodeStatus::Skipped,
+                Duration::ZERO,
+            );
+            step_span.set_attribute("lao.status", NodeStatus::Skipped.as_str());
+            continue;
         }
-
-        substitute_params(&mut params, &outputs);
-
-        // Build plugin input
-        let plugin_input = build_plugin_input(&params);
-
-        // Get plugin
         let plugin = registry
             .get(&step.run)
             .ok_or_else(|| format!("Plugin '{}' not found", step.run))?;
 
-        // Run with retries
         let mut last_error = None;
-        let max_attempts = step.retries.unwrap_or(1) + 1;
-
-        // Check if step should be executed based on conditions
+        // The last attempt's output failed the step's assertions
+        let mut assert_failed = false;
+        // A failed assertion with `on_assert_fail: fail` ends the run
+        let mut stop_run = false;
+        let retry = retry::RetryPolicy::of(step);
+        let max_attempts = retry.max_attempts();
+        // Fewer than max_attempts when a failed assertion stops the run
+        let mut attempts_made = max_attempts;
+
+        // Check if step should be executed based on conditions and branches
         let dependent_step = step.depends_on.as_ref().and_then(|deps| deps.first());
-        if !should_execute_step(step, &logs, dependent_step.map(|s| s.as_str())) {
+        let skipped = if should_execute_step(step, &logs, dependent_step.map(|s| s.as_str())) {
+            branching::skip_reason(&plan, planned, &logs).map(|r| format!("skipped: {}", r))
+        } else {
+            Some("skipped due to condition".to_string())
+        };
+        if let Some(reason) = skipped {
+            on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Skipped,
+                attempt: 1,
+                message: Some(reason.clone()),
+                output: None,
+                error: None,
+                artifact: None,
+                instances: None,
+                retry_in_ms: None,
+            });
             logs.push(StepLog {
                 step: step_idx,
                 step_id: node_id.clone(),
                 runner: step.run.clone(),
                 input: params.clone(),
-                output: Some("skipped due to condition".to_string()),
+                output: Some(reason.clone()),
                 error: None,
+                artifact: None,
                 attempt: 1,
                 input_type: None,
                 output_type: None,
                 validation: Some("skipped".to_string()),
             });
+            step_finished(
+                &run_ctx,
+                &step_ctx,
+                &logs,
+                NodeStatus::Skipped,
+                Duration::ZERO,
+            );
+            step_span.set_attribute("lao.status", NodeStatus::Skipped.as_str());
             continue;
         }
 
-        for attempt in 1..=max_attempts {
-            let _attempt_start = Instant::now();
-
-            // Check cache first
-            let mut cache_status = None;
-            if let Some(cache_key) = &step.cache_key {
-                let cache_dir =
-                    std_env::var("LAO_CACHE_DIR").unwrap_or_else(|_| "cache".to_string());
-                let cache_path = format!("{}/{}.json", cache_dir, cache_key);
-                if let Ok(cached) = fs::read_to_string(&cache_path) {
-                    if let Ok(cached_output) = serde_json::from_str::<String>(&cached) {
-                        cache_status = Some("cache".to_string());
-                        outputs.insert(node_id.clone(), cached_output.clone());
-                        logs.push(StepLog {
-                            step: step_idx,
-                            step_id: node_id.clone(),
-                            runner: step.run.clone(),
-                            input: params.clone(),
-                            output: Some(cached_output),
-                            error: None,
-                            attempt,
-                            input_type: None,
-                            output_type: None,
-                            validation: cache_status,
-                        });
-                        break;
-                    }
-                }
-            }
-
-            // Run plugin
-            let result = unsafe { ((*plugin.vtable).run)(&plugin_input) };
-            let output_str = unsafe {
-                std::ffi::CStr::from_ptr(result.text)
-                    .to_string_lossy()
-                    .to_string()
-            };
-            unsafe { ((*plugin.vtable).free_output)(result) };
-
-            if !output_str.is_empty() && !output_str.contains("error") {
-                // Success
-                outputs.insert(node_id.clone(), output_str.clone());
-
-                // Save to cache
-                if let Some(cache_key) = &step.cache_key {
-                    let cache_dir =
-                        std_env::var("LAO_CACHE_DIR").unwrap_or_else(|_| "cache".to_string());
-                    fs::create_dir_all(&cache_dir).ok();
-                    let cache_path = format!("{}/{}.json", cache_dir, cache_key);
-                    if let Ok(cache_json) = serde_json::to_string(&output_str) {
-                        fs::write(&cache_path, cache_json).ok();
-                        cache_status = Some("saved".to_string());
-                    }
-                }
-
-                logs.push(StepLog {
-                    step: step_idx,
-                    step_id: node_id.clone(),
-                    runner: step.run.clone(),
-                    input: params.clone(),
-                    output: Some(output_str),
-                    error: None,
-                    attempt,
-                    input_type: None,
-                    output_type: None,
-                    validation: cache_status,
-                });
-                break;
-            } else {
-                // Error
-                last_error = Some(output_str);
-
-                if attempt < max_attempts {
-                    let retry_delay = step.retry_delay.unwrap_or(1000);
-                    let delay = if attempt > 1 {
-                        retry_delay * 2u64.pow(attempt - 2)
-                    } else {
-                        retry_delay
-                    };
-                    thread::sleep(Duration::from_millis(delay));
-                }
-            }
+        // A breakpoint can replace the input, or stop the run
+        if breakpoints::will_pause(node_id, step) {
+            on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Pending,
+                attempt: 1,
+                message: Some("paused at breakpoint".to_string()),
+                output: None,
+                error: None,
+                artifact: None,
+                instances: None,
+                retry_in_ms: None,
+            });
         }
-
-        if let Some(error) = last_error {
+        if !breakpoints::pause(&run_id, node_id, step, &mut params, &mut plugin_input) {
+            cancelled = true;
+            cancelled_events(&plan, step_idx).for_each(&mut on_event);
+            break;
+        }
+        // Inputs over the context window are cut, chunked or fail the step
+        let context = context_window::fit(
+            &run_id,
+            node_id,
+            step,
+            plugin,
+            &mut params,
+            &mut plugin_input,
+        );
+
+        // Rules the step declares are checked before any plugin call, and not retried
+        let checked = context
+            .clone()
+            .and_then(|_| run_guard.verdict(node_id))
+            .and_then(|_| input_rules::check_step(&step.validate, &params, &plugin_input));
+        if let Err(error) = checked {
+            on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Error,
+                attempt: 1,
+                message: Some("input validation failed".to_string()),
+                output: None,
+                error: Some(error.clone()),
+                artifact: None,
+                instances: None,
+                retry_in_ms: None,
+            });
+            step_span.set_attribute("lao.status", NodeStatus::Error.as_str());
+            step_span.set_error(&error);
             logs.push(StepLog {
                 step: step_idx,
                 step_id: node_id.clone(),
@@ -450,176 +847,391 @@ pub fn run_workflow_yaml(path: &str) -> Result<Vec<StepLog>, String> {
                 input: params.clone(),
                 output: None,
                 error: Some(error),
-                attempt: max_attempts,
+                artifact: None,
+                attempt: 1,
                 input_type: None,
                 output_type: None,
-                validation: None,
+                validation: Some("input".to_string()),
             });
-            // Continue execution instead of failing the entire workflow
-            // This allows tests to check for errors in the logs
+            step_finished(
+                &run_ctx,
+                &step_ctx,
+                &logs,
+                NodeStatus::Error,
+                Duration::ZERO,
+            );
+            continue;
         }
-    }
+        let chunks = map_reduce::spec(step).or(context.ok().flatten());
 
-    let _duration = start_time.elapsed();
-    Ok(logs)
-}
-
-// Compute default cache key when user does not provide one.
-fn compute_default_cache_key(step: &WorkflowStep, plugin_version: &str) -> String {
-    let params_str = serde_yaml::to_string(&step.params).unwrap_or_default();
-    let mut hash: u64 = 1469598103934665603; // FNV-1a 64-bit offset basis
-    for b in params_str.as_bytes() {
-        hash ^= *b as u64;
-        hash = hash.wrapping_mul(1099511628211);
-    }
-    format!("{}-{}-{:x}", step.run, plugin_version, hash)
-}
-
-// Streaming runner with callback events
-pub fn run_workflow_yaml_with_callback<F>(
-    path: &str,
-    mut on_event: F,
-) -> Result<Vec<StepLog>, String>
-where
-    F: FnMut(StepEvent) + Send,
-{
-    let workflow = load_workflow_yaml(path)?;
-    let dag = build_dag(&workflow.steps)?;
-    let registry = PluginRegistry::default_registry();
-
-    let errors = validate_workflow_types(&dag, &registry);
-    if !errors.is_empty() {
-        return Err(format!("Workflow validation failed: {:?}", errors));
-    }
-
-    let execution_order = topo_sort(&dag)?;
-
-    let mut logs = Vec::new();
-    let mut outputs = HashMap::new();
-
-    for (step_idx, node_id) in execution_order.iter().enumerate() {
-        let node = dag.iter().find(|n| &n.id == node_id).unwrap();
-        let step = &node.step;
-
-        let mut params = step.params.clone();
-        substitute_params(&mut params, &outputs);
-
-        let plugin_input = build_plugin_input(&params);
-        let plugin = registry
-            .get(&step.run)
-            .ok_or_else(|| format!("Plugin '{}' not found", step.run))?;
-
-        let mut last_error = None;
-        let max_attempts = step.retries.unwrap_or(1) + 1;
+        if let Some((output, run)) = checkpoint::resumed(node_id, &step.run, &params) {
+            outputs.insert(node_id.clone(), output.clone());
+            on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Cache,
+                attempt: 1,
+                message: Some(format!("resumed from run {}", run)),
+                output: Some(output.clone()),
+                error: None,
+                artifact: None,
+                instances: None,
+                retry_in_ms: None,
+            });
+            logs.push(StepLog {
+                step: step_idx,
+                step_id: node_id.clone(),
+                runner: step.run.clone(),
+                input: params.clone(),
+                output: Some(output),
+                error: None,
+                artifact: None,
+                attempt: 1,
+                input_type: None,
+                output_type: None,
+                validation: Some("resumed".to_string()),
+            });
+            step_finished(
+                &run_ctx,
+                &step_ctx,
+                &logs,
+                NodeStatus::Cache,
+                Duration::ZERO,
+            );
+            step_span.set_attribute("lao.status", NodeStatus::Cache.as_str());
+            continue;
+        }
 
-        // Check if step should be executed based on conditions
-        let dependent_step = step.depends_on.as_ref().and_then(|deps| deps.first());
-        if !should_execute_step(step, &logs, dependent_step.map(|s| s.as_str())) {
+        let dedup_call = dedup_call(planned, &params, &plugin_input);
+        if let Some(shared) = dedup_call.as_ref().and_then(|call| dedup.lookup(call)) {
+            outputs.insert(node_id.clone(), shared.output.clone());
+            if let Some(artifact) = &shared.artifact {
+                step_artifacts.insert(node_id.clone(), artifact.id.clone());
+            }
             on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Cache,
+                attempt: 1,
+                message: Some(format!("same call as {}", shared.step_id)),
+                output: Some(shared.output.clone()),
+                error: None,
+                artifact: shared.artifact.clone(),
+                instances: None,
+                retry_in_ms: None,
+            });
+            logs.push(StepLog {
                 step: step_idx,
                 step_id: node_id.clone(),
                 runner: step.run.clone(),
-                status: "skipped".to_string(),
+                input: params.clone(),
+                output: Some(shared.output),
+                error: None,
+                artifact: shared.artifact,
                 attempt: 1,
-                message: Some("condition not met".to_string()),
-                output: None,
+                input_type: None,
+                output_type: None,
+                validation: Some("dedup".to_string()),
+            });
+            step_finished(
+                &run_ctx,
+                &step_ctx,
+                &logs,
+                NodeStatus::Cache,
+                Duration::ZERO,
+            );
+            step_span.set_attribute("lao.status", NodeStatus::Cache.as_str());
+            step_span.set_attribute("lao.dedup.step", shared.step_id);
+            continue;
+        }
+
+        let memo_key = memo_key(planned, plugin, &params, &plugin_input);
+        if let Some(output) = memo_lookup(&memo_store, planned, memo_key.as_deref()) {
+            if let Some(call) = &dedup_call {
+                dedup.record(call, node_id, &output, None, Duration::ZERO);
+            }
+            outputs.insert(node_id.clone(), output.clone());
+            on_event(StepEvent {
+                step: step_idx,
+                step_id: step_id.clone(),
+                runner: step.run.clone(),
+                status: NodeStatus::Cache,
+                attempt: 1,
+                message: Some("memoized".to_string()),
+                output: Some(output.clone()),
                 error: None,
+                artifact: None,
+                instances: None,
+                retry_in_ms: None,
             });
             logs.push(StepLog {
                 step: step_idx,
                 step_id: node_id.clone(),
                 runner: step.run.clone(),
                 input: params.clone(),
-                output: Some("skipped due to condition".to_string()),
+                output: Some(output),
                 error: None,
+                artifact: None,
                 attempt: 1,
                 input_type: None,
                 output_type: None,
-                validation: Some("skipped".to_string()),
+                validation: Some("memo".to_string()),
             });
+            step_finished(
+                &run_ctx,
+                &step_ctx,
+                &logs,
+                NodeStatus::Cache,
+                Duration::ZERO,
+            );
+            step_span.set_attribute("lao.status", NodeStatus::Cache.as_str());
             continue;
         }
 
+        // Only explicit cache keys are written, so only they are looked up
+        if let Some(cache_key) = &step.cache_key {
+            let mut cache_span = telemetry::Span::child("cache.lookup", &step_span.context());
+            let cached_output = encryption::read_to_string(planned.cache_path())
+                .ok()
+                .and_then(|cached| serde_json::from_str::<String>(&cached).ok());
+            cache_span.set_attribute("lao.cache_key", cache_key.clone());
+            cache_span.set_attribute("lao.cache.hit", cached_output.is_some());
+            cache_span.end();
+            metrics::global().record_cache_lookup(cached_output.is_some());
+            if let Some(cached_output) = cached_output {
+                if let Some(call) = &dedup_call {
+                    dedup.record(call, node_id, &cached_output, None, Duration::ZERO);
+                }
+                outputs.insert(node_id.clone(), cached_output.clone());
+                on_event(StepEvent {
+                    step: step_idx,
+                    step_id: step_id.clone(),
+                    runner: step.run.clone(),
+                    status: NodeStatus::Cache,
+                    attempt: 1,
+                    message: Some("cache hit".to_string()),
+                    output: Some(cached_output.clone()),
+                    error: None,
+                    artifact: None,
+                    instances: None,
+                    retry_in_ms: None,
+                });
+                logs.push(StepLog {
+                    step: step_idx,
+                    step_id: node_id.clone(),
+                    runner: step.run.clone(),
+                    input: params.clone(),
+                    output: Some(cached_output),
+                    error: None,
+                    artifact: None,
+                    attempt: 1,
+                    input_type: None,
+                    output_type: None,
+                    validation: Some("cache".to_string()),
+                });
+                step_finished(
+                    &run_ctx,
+                    &step_ctx,
+                    &logs,
+                    NodeStatus::Cache,
+                    Duration::ZERO,
+                );
+                step_span.set_attribute("lao.status", NodeStatus::Cache.as_str());
+                continue;
+            }
+        }
+
+        let _permit = run_ticket.step(node_id);
+        let _lane = pipeline::acquire(planned.resource, node_id);
+        warmup.wait_for(node_id);
         on_event(StepEvent {
             step: step_idx,
-            step_id: node_id.clone(),
+            step_id: step_id.clone(),
             runner: step.run.clone(),
-            status: "running".to_string(),
+            status: NodeStatus::Running,
             attempt: 1,
             message: None,
             output: None,
             error: None,
+            artifact: None,
+            instances: None,
+            retry_in_ms: None,
         });
 
+        let step_start = Instant::now();
         for attempt in 1..=max_attempts {
-            // Check or compute cache key
-            let mut cache_status = None;
-            let cache_key_effective = if let Some(k) = &step.cache_key {
-                k.clone()
-            } else {
-                compute_default_cache_key(step, &plugin.info.version)
-            };
-            let cache_dir = std_env::var("LAO_CACHE_DIR").unwrap_or_else(|_| "cache".to_string());
-            let cache_path = format!("{}/{}.json", cache_dir, cache_key_effective);
-
-            if attempt == 1 {
-                if let Ok(cached) = fs::read_to_string(&cache_path) {
-                    if let Ok(cached_output) = serde_json::from_str::<String>(&cached) {
-                        cache_status = Some("cache".to_string());
-                        outputs.insert(node_id.clone(), cached_output.clone());
+            // Run plugin
+            let _process_output = process_output::StepScope::enter(&run_id, node_id);
+            let model = params.get("model").and_then(|model| model.as_str());
+            let _backend = backends::StepScope::enter(step.backend.as_deref(), model);
+            let step_stop = cancel::StepScope::enter(step.timeout.map(Duration::from_secs));
+            let call_start = Instant::now();
+            let mut call_span = telemetry::Span::child("plugin.call", &step_span.context());
+            call_span.set_attribute("lao.plugin", step.run.clone());
+            call_span.set_attribute("lao.attempt", attempt);
+            let result = match &chunks {
+                _ if step.foreach.is_some() => {
+                    let spec = step.foreach.as_ref().expect("checked above");
+                    // The node shows how many items are done as they finish
+                    let mut on_progress = |instances: foreach::Instances| {
                         on_event(StepEvent {
                             step: step_idx,
-                            step_id: node_id.clone(),
+                            step_id: step_id.clone(),
                             runner: step.run.clone(),
-                            status: "cache".to_string(),
+                            status: NodeStatus::Running,
                             attempt,
-                            message: Some("cache hit".to_string()),
-                            output: Some(cached_output.clone()),
+                            message: Some(format!(
+                                "{}/{} items done, {} running",
+                                instances.done, instances.total, instances.running
+                            )),
+                            output: None,
                             error: None,
-                        });
-                        logs.push(StepLog {
+                            artifact: None,
+                            instances: Some(instances),
+                            retry_in_ms: None,
+                        })
+                    };
+                    foreach::run(
+                        spec,
+                        plugin,
+                        registry,
+                        &plugin_input,
+                        &mut run_usage,
+                        &mut on_progress,
+                    )
+                }
+                Some(spec) => {
+                    map_reduce::run(spec, plugin, registry, &plugin_input, &mut run_usage)
+                }
+                None if sinks::is_builtin(plugin) => run_sinks.finish(node_id, step, &plugin_input),
+                None => {
+                    // Output streamed so far is shown as the running step's output,
+                    // and sinks reading from the step get it as it comes
+                    run_sinks.open_for(node_id);
+                    let mut streamed = String::new();
+                    let mut last_sent: Option<Instant> = None;
+                    let mut on_chunk = |chunk: &str| {
+                        run_sinks.chunk(node_id, chunk);
+                        streamed.push_str(chunk);
+                        if last_sent.is_some_and(|sent| sent.elapsed() < streaming::UPDATE_INTERVAL)
+                        {
+                            return;
+                        }
+                        last_sent = Some(Instant::now());
+                        on_event(StepEvent {
                             step: step_idx,
-                            step_id: node_id.clone(),
+                            step_id: step_id.clone(),
                             runner: step.run.clone(),
-                            input: params.clone(),
-                            output: Some(cached_output),
-                            error: None,
+                            status: NodeStatus::Running,
                             attempt,
-                            input_type: None,
-                            output_type: None,
-                            validation: cache_status,
+                            message: None,
+                            output: Some(streamed.clone()),
+                            error: None,
+                            artifact: None,
+                            instances: None,
+                            retry_in_ms: None,
                         });
-                        break;
-                    }
+                    };
+                    let result = streaming::with_sink(&mut on_chunk, || match plugin.signature() {
+                        Some(signature) => {
+                            typed_io::call(plugin, signature, &params, &plugin_input)
+                        }
+                        None => plugin.call(&plugin_input),
+                    });
+                    run_usage.record_call(&step.run, call_start.elapsed(), plugin.take_usage());
+                    result
                 }
+            };
+            metrics::global().record_plugin_call(&step.run, call_start.elapsed());
+            // A step stopped by its timeout or the run's cancellation fails
+            // with why, whatever the plugin returned
+            let stopped = step_stop.stopped();
+            drop(step_stop);
+            let result = match &stopped {
+                Some(reason) => Err(reason.clone()),
+                None => result,
+            };
+            let output_str = result.unwrap_or_else(|e| format!("error: {}", e));
+            if is_error_output(&output_str) {
+                call_span.set_error(&output_str);
             }
-
-            let result = unsafe { ((*plugin.vtable).run)(&plugin_input) };
-            let output_str = unsafe {
-                std::ffi::CStr::from_ptr(result.text)
-                    .to_string_lossy()
-                    .to_string()
+            call_span.end();
+            audit_external_call(&run_id, node_id, plugin, attempt, &output_str);
+            // An output failing the step's assertions counts as a failed attempt
+            assert_failed = false;
+            let output_str = if is_error_output(&output_str) || step.assert.is_empty() {
+                output_str
+            } else {
+                gate_output(
+                    planned,
+                    registry,
+                    &run_id,
+                    node_id,
+                    &plugin_input,
+                    output_str,
+                    &mut run_usage,
+                )
+                .unwrap_or_else(|failure| {
+                    assert_failed = true;
+                    format!("error: assertion failed: {}", failure)
+                })
             };
-            unsafe { ((*plugin.vtable).free_output)(result) };
 
-            if !output_str.is_empty() && !output_str.contains("error") {
+            if !is_error_output(&output_str) {
+                let artifact =
+                    capture_artifact(&run_id, planned, plugin, &output_str, &step_artifacts);
+                let output_str = artifact.as_ref().map_or(output_str, |a| a.path.clone());
+                if let Some(artifact) = &artifact {
+                    step_artifacts.insert(node_id.clone(), artifact.id.clone());
+                }
+                if let Some(call) = &dedup_call {
+                    dedup.record(
+                        call,
+                        node_id,
+                        &output_str,
+                        artifact.clone(),
+                        call_start.elapsed(),
+                    );
+                }
+                // Artifact paths can be pruned, so only plain outputs are memoized
+                if let (Some(key), None) = (&memo_key, &artifact) {
+                    memo_store_output(&memo_store, &run_id, planned, plugin, key, &output_str);
+                }
                 outputs.insert(node_id.clone(), output_str.clone());
-                if step.cache_key.is_some() {
-                    fs::create_dir_all(&cache_dir).ok();
-                    let _ = fs::write(
-                        &cache_path,
-                        serde_json::to_string(&output_str).unwrap_or_default(),
+                if let (Some(spec), None) = (&step.evaluate, &artifact) {
+                    evaluate_output(
+                        planned,
+                        spec,
+                        registry,
+                        &run_id,
+                        &workflow.workflow,
+                        &output_str,
+                        &mut run_usage,
                     );
                 }
+                // Save to cache
+                let mut cache_status = None;
+                if step.cache_key.is_some() && !secrets::reveals(&output_str) {
+                    fs::create_dir_all(plan::cache_dir()).ok();
+                    if let Ok(cache_json) = serde_json::to_string(&output_str) {
+                        encryption::write(planned.cache_path(), cache_json).ok();
+                        cache_status = Some("saved".to_string());
+                    }
+                }
                 on_event(StepEvent {
                     step: step_idx,
-                    step_id: node_id.clone(),
+                    step_id: step_id.clone(),
                     runner: step.run.clone(),
-                    status: "success".to_string(),
+                    status: NodeStatus::Success,
                     attempt,
                     message: None,
                     output: Some(output_str.clone()),
                     error: None,
+                    artifact: artifact.clone(),
+                    instances: None,
+                    retry_in_ms: None,
                 });
                 logs.push(StepLog {
                     step: step_idx,
@@ -628,42 +1240,92 @@ where
                     input: params.clone(),
                     output: Some(output_str),
                     error: None,
+                    artifact,
                     attempt,
                     input_type: None,
                     output_type: None,
                     validation: cache_status,
                 });
+                step_finished(
+                    &run_ctx,
+                    &step_ctx,
+                    &logs,
+                    NodeStatus::Success,
+                    step_start.elapsed(),
+                );
+                step_span.set_attribute("lao.status", NodeStatus::Success.as_str());
+                // Earlier failed attempts must not mark the step as failed
+                last_error = None;
                 break;
             } else {
                 last_error = Some(output_str.clone());
+                if stopped.as_deref() == Some(cancel::CANCELLED) {
+                    cancelled = true;
+                    attempts_made = attempt;
+                    break;
+                }
+                let retry_in = (!(assert_failed && assertions::action(step) == AssertAction::Fail)
+                    && retry.should_retry(attempt, &output_str, assert_failed))
+                .then(|| retry.delay(attempt));
                 on_event(StepEvent {
                     step: step_idx,
-                    step_id: node_id.clone(),
+                    step_id: step_id.clone(),
                     runner: step.run.clone(),
-                    status: "error".to_string(),
+                    status: NodeStatus::Error,
                     attempt,
-                    message: Some("attempt failed".to_string()),
+                    message: Some(match retry_in {
+                        Some(delay) => format!(
+                            "attempt {} of {} failed, retrying in {:.1}s",
+                            attempt,
+                            max_attempts,
+                            delay.as_secs_f64()
+                        ),
+                        None => "attempt failed".to_string(),
+                    }),
                     output: None,
                     error: Some(output_str.clone()),
+                    artifact: None,
+                    instances: None,
+                    retry_in_ms: retry_in.map(|delay| delay.as_millis() as u64),
                 });
-                if attempt < max_attempts {
-                    let retry_delay = step.retry_delay.unwrap_or(1000);
-                    thread::sleep(Duration::from_millis(retry_delay));
-                    on_event(StepEvent {
-                        step: step_idx,
-                        step_id: node_id.clone(),
-                        runner: step.run.clone(),
-                        status: "running".to_string(),
-                        attempt: attempt + 1,
-                        message: Some("retrying".to_string()),
-                        output: None,
-                        error: None,
-                    });
+                if assert_failed && assertions::action(step) == AssertAction::Fail {
+                    stop_run = true;
+                    attempts_made = attempt;
+                    break;
+                }
+                let Some(delay) = retry_in else {
+                    attempts_made = attempt;
+                    break;
+                };
+                if !retry::wait(delay) {
+                    cancelled = true;
+                    attempts_made = attempt;
+                    break;
                 }
+                on_event(StepEvent {
+                    step: step_idx,
+                    step_id: step_id.clone(),
+                    runner: step.run.clone(),
+                    status: NodeStatus::Running,
+                    attempt: attempt + 1,
+                    message: Some("retrying".to_string()),
+                    output: None,
+                    error: None,
+                    artifact: None,
+                    instances: None,
+                    retry_in_ms: None,
+                });
             }
         }
 
         if let Some(error) = last_error {
+            let status = if cancelled {
+                NodeStatus::Cancelled
+            } else {
+                NodeStatus::Error
+            };
+            step_span.set_attribute("lao.status", status.as_str());
+            step_span.set_error(&error);
             logs.push(StepLog {
                 step: step_idx,
                 step_id: node_id.clone(),
@@ -671,20 +1333,482 @@ where
                 input: params.clone(),
                 output: None,
                 error: Some(error),
-                attempt: max_attempts,
+                artifact: None,
+                attempt: attempts_made,
                 input_type: None,
                 output_type: None,
-                validation: None,
+                validation: assert_failed.then(|| "assert".to_string()),
             });
+            step_finished(&run_ctx, &step_ctx, &logs, status, step_start.elapsed());
+        }
+        step_durations.insert(node_id.clone(), step_start.elapsed());
+        if cancelled {
+            logging::warn("run cancelled")
+                .run(&run_id)
+                .step(node_id.as_str())
+                .emit();
+            // The stopped step and those after it are shown as cancelled
+            cancelled_events(&plan, step_idx).for_each(&mut on_event);
+            break;
+        }
+        if stop_run {
+            logging::warn("run stopped by a failed assertion")
+                .run(&run_id)
+                .step(node_id.as_str())
+                .emit();
+            break;
         }
     }
 
+    finish_run(
+        run_span,
+        &run_ctx,
+        &logs,
+        run_usage,
+        &dedup,
+        run_guard.records(),
+        &step_durations,
+        start_time.elapsed(),
+        cancelled,
+    );
+    if cancelled {
+        return Err(cancel::CANCELLED.to_string());
+    }
     Ok(logs)
 }
 
+/// Run a single plugin call outside a workflow, as `lao exec` does.
+/// Error outputs (see `is_error_output`) are returned as Err.
+pub fn exec_plugin(plugin: &PluginInstance, input: &str) -> Result<String, String> {
+    let start = Instant::now();
+    let result = plugin.call(input);
+    metrics::global().record_plugin_call(&plugin.info.name, start.elapsed());
+    let output = result?;
+    if is_error_output(&output) {
+        Err(output)
+    } else {
+        Ok(output)
+    }
+}
+
+// Plugins signal failure with an empty output or an "error: ..." message.
+// Only the prefix is checked so that outputs which merely mention errors
+// (diagnostic reports, summaries of logs) still count as successful.
+pub fn is_error_output(output: &str) -> bool {
+    let trimmed = output.trim_start();
+    trimmed.is_empty()
+        || trimmed
+            .get(..5)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("error"))
+}
+
+// Plans with errors are refused before any step runs
+fn reject_invalid(plan: &ExecutionPlan) -> Result<(), String> {
+    if plan.is_valid() {
+        return Ok(());
+    }
+    metrics::global().record_workflow_run("invalid", Duration::ZERO);
+    logging::error("workflow validation failed")
+        .field("workflow", plan.workflow.workflow.clone())
+        .field("errors", plan.error_summary())
+        .emit();
+    Err(format!(
+        "Workflow validation failed: {}",
+        plan.error_summary()
+    ))
+}
+
+// Root span for one run; run_id ties together every span and log event the run emits
+fn start_run_span(workflow: &Workflow, run_id: &str) -> telemetry::Span {
+    let mut span = telemetry::Span::root("workflow.run");
+    span.set_attribute("lao.run_id", run_id);
+    span.set_attribute("lao.workflow", workflow.workflow.clone());
+    span
+}
+
+fn start_step_span(
+    run_span: &telemetry::Span,
+    node_id: &str,
+    step: &WorkflowStep,
+) -> telemetry::Span {
+    let mut span = telemetry::Span::child(&format!("step {}", node_id), &run_span.context());
+    span.set_attribute("lao.step_id", node_id);
+    span.set_attribute("lao.runner", step.run.clone());
+    span
+}
+
+#[allow(clippy::too_many_arguments)]
+fn finish_run(
+    mut span: telemetry::Span,
+    run: &hooks::RunContext,
+    logs: &[StepLog],
+    run_usage: usage::RunUsage,
+    dedup: &dedup::RunDedup,
+    taint: &[guard::Taint],
+    step_durations: &HashMap<String, Duration>,
+    duration: Duration,
+    cancelled: bool,
+) {
+    let run_id = run.run_id;
+    let status = if cancelled {
+        "cancelled"
+    } else {
+        run_status(logs)
+    };
+    hooks::run_ended(
+        run,
+        &hooks::RunEnd {
+            status,
+            duration,
+            logs,
+            step_durations,
+            taint,
+        },
+    );
+    let tokens = run_usage.total_tokens();
+    run_usage.finish(duration);
+    let event = if status == "error" {
+        span.set_error("one or more steps failed");
+        logging::warn("workflow finished with failed steps")
+    } else {
+        logging::info("workflow finished")
+    };
+    event
+        .run(run_id)
+        .field("status", status)
+        .field("steps", logs.len())
+        .field("tokens", tokens)
+        .field("deduplicated", dedup.shared)
+        .field("dedup_saved_ms", dedup.saved.as_millis() as u64)
+        .field("duration_ms", duration.as_millis() as u64)
+        .emit();
+    span.set_attribute("lao.dedup.shared", dedup.shared as i64);
+    span.end();
+    // CLI runs exit right after returning, so send the trace now
+    telemetry::flush();
+}
+
+// Hand a step's outcome, with the log entry it just pushed, to the hooks
+fn step_finished(
+    run: &hooks::RunContext,
+    step: &hooks::StepContext,
+    logs: &[StepLog],
+    status: NodeStatus,
+    duration: Duration,
+) {
+    if let Some(log) = logs.last() {
+        hooks::step_finished(
+            run,
+            step,
+            &hooks::StepFinish {
+                status,
+                duration,
+                log,
+            },
+        );
+    }
+}
+
+// Events marking the steps of `plan` from `start` on as cancelled
+fn cancelled_events(
+    plan: &plan::ExecutionPlan,
+    start: usize,
+) -> impl Iterator<Item = StepEvent> + '_ {
+    plan.in_order()
+        .enumerate()
+        .skip(start)
+        .map(|(step, planned)| StepEvent {
+            step,
+            step_id: planned.id.clone(),
+            runner: planned.step.run.clone(),
+            status: NodeStatus::Cancelled,
+            attempt: 1,
+            message: Some(cancel::CANCELLED.to_string()),
+            output: None,
+            error: None,
+            artifact: None,
+            instances: None,
+            retry_in_ms: None,
+        })
+}
+
+// Log event for a step status change
+fn step_log_event(
+    run_id: &str,
+    step_id: &str,
+    runner: &str,
+    status: NodeStatus,
+    attempt: u32,
+) -> logging::LogEvent {
+    let (level, message) = match status {
+        NodeStatus::Running => (logging::LogLevel::Info, "step running"),
+        NodeStatus::Success => (logging::LogLevel::Info, "step succeeded"),
+        NodeStatus::Cache => (logging::LogLevel::Info, "step served from cache"),
+        NodeStatus::Skipped => (logging::LogLevel::Info, "step skipped"),
+        NodeStatus::Cancelled => (logging::LogLevel::Warn, "step cancelled"),
+        NodeStatus::Error => (logging::LogLevel::Error, "step failed"),
+        NodeStatus::Pending => (logging::LogLevel::Debug, "step status changed"),
+    };
+    logging::LogEvent::new(level, message)
+        .run(run_id)
+        .step(step_id)
+        .field("runner", runner)
+        .field("status", status.as_str())
+        .field("attempt", attempt)
+}
+
+// Calls to plugins that reach the network or write files go to the audit log
+fn audit_external_call(
+    run_id: &str,
+    step_id: &str,
+    plugin: &PluginInstance,
+    attempt: u32,
+    output: &str,
+) {
+    let Some(effect) = audit::external_effect(&plugin.info.tags) else {
+        return;
+    };
+    let status = if is_error_output(output) {
+        "error"
+    } else {
+        "success"
+    };
+    audit::event(audit::AuditKind::ExternalStep, plugin.info.name.as_str())
+        .run(run_id)
+        .detail("step_id", step_id)
+        .detail("effect", effect)
+        .detail("attempt", attempt)
+        .detail("status", status)
+        .record();
+}
+
+// A plugin output after the step's assertions: the output itself, the
+// fallback plugin's when it took over, or why neither passed
+fn gate_output(
+    planned: &PlannedStep,
+    registry: &PluginRegistry,
+    run_id: &str,
+    node_id: &str,
+    plugin_input: &str,
+    output: String,
+    run_usage: &mut usage::RunUsage,
+) -> Result<String, String> {
+    let step = &planned.step;
+    let mut call = |name: &str, input: &str| call_plugin(registry, run_usage, name, input);
+    let failure = match assertions::check_output(&step.assert, &output, &mut call) {
+        Ok(()) => return Ok(output),
+        Err(failure) => failure,
+    };
+    let Some(fallback) = step
+        .fallback
+        .as_deref()
+        .filter(|_| assertions::action(step) == AssertAction::Fallback)
+    else {
+        return Err(failure);
+    };
+    logging::warn("step output failed its assertions, trying the fallback")
+        .run(run_id)
+        .step(node_id)
+        .field("failure", failure.clone())
+        .field("fallback", fallback)
+        .emit();
+    let output = call(fallback, plugin_input)
+        .map_err(|e| format!("{}; fallback '{}' failed: {}", failure, fallback, e))?;
+    assertions::check_output(&step.assert, &output, &mut call)
+        .map(|()| output)
+        .map_err(|e| format!("{}; fallback '{}': {}", failure, fallback, e))
+}
+
+// Score a step output with its `evaluate:` judge and record the scores.
+// Evaluations never fail the step.
+fn evaluate_output(
+    planned: &PlannedStep,
+    spec: &evaluation::EvaluateSpec,
+    registry: &PluginRegistry,
+    run_id: &str,
+    workflow: &str,
+    output: &str,
+    run_usage: &mut usage::RunUsage,
+) {
+    let mut call = |name: &str, input: &str| call_plugin(registry, run_usage, name, input);
+    match spec.score(output, &mut call) {
+        Ok(scores) => {
+            let record = evaluation::EvalRecord::new(
+                run_id,
+                workflow,
+                planned.id.as_str(),
+                &planned.step,
+                &spec.judge,
+                scores,
+            );
+            logging::info("step evaluated")
+                .run(run_id)
+                .step(planned.id.as_str())
+                .field("judge", spec.judge.clone())
+                .field("score", record.score)
+                .emit();
+            evaluation::record(&record);
+        }
+        Err(e) => logging::warn("step evaluation failed")
+            .run(run_id)
+            .step(planned.id.as_str())
+            .field("error", e)
+            .emit(),
+    }
+}
+
+// Judges and fallbacks are plugin calls of the run like any other
+fn call_plugin(
+    registry: &PluginRegistry,
+    run_usage: &mut usage::RunUsage,
+    name: &str,
+    input: &str,
+) -> Result<String, String> {
+    let plugin = registry
+        .get(name)
+        .ok_or_else(|| format!("Plugin '{}' not found", name))?;
+    let start = Instant::now();
+    let result = exec_plugin(plugin, input);
+    run_usage.record_call(name, start.elapsed(), plugin.take_usage());
+    result
+}
+
+// Store a binary output in the artifact store, linked to the artifacts its
+// parent steps produced
+fn capture_artifact(
+    run_id: &str,
+    planned: &PlannedStep,
+    plugin: &PluginInstance,
+    output: &str,
+    step_artifacts: &HashMap<String, String>,
+) -> Option<lao_plugin_api::ArtifactRef> {
+    let (_, output_type) = primary_io_types(plugin);
+    let lineage = artifacts::Lineage {
+        run_id: run_id.to_string(),
+        step_id: planned.id.to_string(),
+        plugin: planned.step.run.clone(),
+        parents: planned
+            .parents
+            .iter()
+            .filter_map(|parent| step_artifacts.get(parent.as_str()).cloned())
+            .collect(),
+        source_path: output.trim().to_string(),
+        created_at: chrono::Utc::now(),
+    };
+    artifacts::capture_output(output, &output_type, lineage)
+}
+
+// Final status of a finished run for metrics: any failed step marks the run as an error
+fn run_status(logs: &[StepLog]) -> &'static str {
+    if logs.iter().any(|log| log.error.is_some()) {
+        "error"
+    } else {
+        "success"
+    }
+}
+
+// Content key of a memoized step's plugin call
+fn memo_key(
+    planned: &PlannedStep,
+    plugin: &PluginInstance,
+    params: &serde_yaml::Value,
+    plugin_input: &str,
+) -> Option<String> {
+    // A fan-out's items can be files whose contents the input doesn't show
+    (planned.memoize && planned.step.foreach.is_none()).then(|| {
+        memo::key(
+            &map_reduce::call_name(&planned.step),
+            &plugin.info.version,
+            params,
+            plugin_input,
+            planned.takes_file(),
+        )
+    })
+}
+
+// Run deduplication shares outputs only between calls memo could share
+fn dedup_call(
+    planned: &PlannedStep,
+    params: &serde_yaml::Value,
+    plugin_input: &str,
+) -> Option<dedup::Call> {
+    (planned.memoize && planned.step.foreach.is_none()).then(|| {
+        dedup::Call::new(
+            &map_reduce::call_name(&planned.step),
+            planned.step.backend.as_deref(),
+            params,
+            plugin_input,
+        )
+    })
+}
+
+fn memo_lookup(
+    store: &memo::MemoStore,
+    planned: &PlannedStep,
+    key: Option<&str>,
+) -> Option<String> {
+    let output = store.lookup(&planned.step.run, key?);
+    metrics::global().record_cache_lookup(output.is_some());
+    output
+}
+
+// A failed write only costs a later run the plugin call
+fn memo_store_output(
+    store: &memo::MemoStore,
+    run_id: &str,
+    planned: &PlannedStep,
+    plugin: &PluginInstance,
+    key: &str,
+    output: &str,
+) {
+    let name = &planned.step.run;
+    if secrets::reveals(output) {
+        return;
+    }
+    if let Err(e) = store.store(name, &plugin.info.version, key, output) {
+        logging::warn("memoizing step output failed")
+            .run(run_id)
+            .step(planned.id.as_str())
+            .field("plugin", name.clone())
+            .field("error", e)
+            .emit();
+    }
+}
+
+// Compute default cache key when user does not provide one.
+fn compute_default_cache_key(step: &WorkflowStep, plugin_version: &str) -> String {
+    let mut params_str = serde_yaml::to_string(&step.params).unwrap_or_default();
+    if step.strategy.is_some() || step.foreach.is_some() {
+        params_str.push_str(&map_reduce::call_name(step));
+    }
+    if let Some(template) = &step.template {
+        params_str.push_str(template);
+    }
+    let mut hash: u64 = 1469598103934665603; // FNV-1a 64-bit offset basis
+    for b in params_str.as_bytes() {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(1099511628211);
+    }
+    format!("{}-{}-{:x}", step.run, plugin_version, hash)
+}
+
+// Streaming runner with callback events; `inputs` as for
+// `run_workflow_yaml_with_inputs`
+pub fn run_workflow_yaml_with_callback<F>(
+    path: &str,
+    inputs: HashMap<String, String>,
+    mut on_event: F,
+) -> Result<Vec<StepLog>, String>
+where
+    F: FnMut(StepEvent) + Send,
+{
+    run_steps(path, inputs, &plugins::shared().snapshot(), &mut on_event)
+}
+
 // Parallel execution by levels (nodes on same level run concurrently)
 pub fn run_workflow_yaml_parallel_with_callback<F>(
     path: &str,
+    inputs: HashMap<String, String>,
     on_event: F,
 ) -> Result<Vec<StepLog>, String>
 where
@@ -692,7 +1816,7 @@ where
 {
     // NOTE: Current plugin VTable is not Send/Sync, so we cannot safely execute plugins across threads.
     // Fallback to sequential streaming execution to preserve correctness.
-    run_workflow_yaml_with_callback(path, on_event)
+    run_workflow_yaml_with_callback(path, inputs, on_event)
 }
 
 fn substitute_params(params: &mut serde_yaml::Value, outputs: &HashMap<String, String>) {
@@ -714,25 +1838,32 @@ fn substitute_vars(s: &str, outputs: &HashMap<String, String>) -> String {
     result
 }
 
-fn build_plugin_input(params: &serde_yaml::Value) -> PluginInput {
+// Give the step `replacement` as its input, in the params too so logs show it
+fn replace_input(params: &mut serde_yaml::Value, input: &mut String, replacement: String) {
+    if !params.is_mapping() {
+        *params = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+    }
+    if let Some(mapping) = params.as_mapping_mut() {
+        mapping.insert(
+            serde_yaml::Value::String("input".to_string()),
+            serde_yaml::Value::String(replacement.clone()),
+        );
+    }
+    *input = replacement;
+}
+
+fn build_plugin_input(params: &serde_yaml::Value) -> String {
     // Try to extract the "input" field first, fallback to full YAML
     if let Some(mapping) = params.as_mapping() {
         if let Some(input_val) = mapping.get("input") {
             if let Some(input_str) = input_val.as_str() {
-                let c_string = CString::new(input_str).unwrap();
-                return PluginInput {
-                    text: c_string.into_raw(),
-                };
+                return input_str.to_string();
             }
         }
     }
 
     // Fallback: serialize the entire params object
-    let text = serde_yaml::to_string(params).unwrap_or_default();
-    let c_string = CString::new(text).unwrap();
-    PluginInput {
-        text: c_string.into_raw(),
-    }
+    serde_yaml::to_string(params).unwrap_or_default()
 }
 
 // Evaluate a step condition against execution context
@@ -847,14 +1978,7 @@ mod tests {
         let steps = vec![WorkflowStep {
             run: "Echo".to_string(),
             params: serde_yaml::from_str("input: 'hello'").unwrap(),
-            retries: None,
-            retry_delay: None,
-            cache_key: None,
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }];
 
         let dag = build_dag(&steps).unwrap();
@@ -869,26 +1993,13 @@ mod tests {
             WorkflowStep {
                 run: "Step1".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
-                input_from: None,
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             WorkflowStep {
                 run: "Step2".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("step1".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
         ];
 
@@ -904,26 +2015,13 @@ mod tests {
             WorkflowStep {
                 run: "A".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
-                input_from: None,
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             WorkflowStep {
                 run: "B".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("step1".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
         ];
 
@@ -938,26 +2036,14 @@ mod tests {
             WorkflowStep {
                 run: "A".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("step2".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             WorkflowStep {
                 run: "B".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("step1".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
         ];
 
@@ -976,6 +2062,77 @@ mod tests {
         assert_eq!(result, "Input: hello world");
     }
 
+    #[test]
+    fn test_is_error_output() {
+        assert!(is_error_output(""));
+        assert!(is_error_output("error: invalid input"));
+        assert!(is_error_output("  Error: connection refused"));
+        assert!(!is_error_output("{\"summary\":{\"errors\":0}}"));
+        assert!(!is_error_output("Found 2 errors in the build log"));
+    }
+
+    #[test]
+    fn test_types_compatible_json_to_text() {
+        assert!(types_compatible(
+            PluginOutputType::Json,
+            PluginInputType::Text
+        ));
+        assert!(!types_compatible(
+            PluginOutputType::Text,
+            PluginInputType::Json
+        ));
+    }
+
+    #[test]
+    fn test_cached_step_sends_one_event() {
+        let dir = std::env::temp_dir().join(format!("lao_cached_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cached.yaml");
+        let key = format!("cached_{}", uuid::Uuid::new_v4());
+        fs::write(
+            &path,
+            format!(
+                "workflow: cached\nsteps:\n  - run: EchoPlugin\n    input: hi\n    cache_key: {}\n    retries: 1\n    retry_delay: 0\n",
+                key
+            ),
+        )
+        .unwrap();
+        let mut registry = PluginRegistry::new();
+        let spec = crate::mock::MockSpec {
+            output: Some("{input}".to_string()),
+            fail_times: 1,
+            ..Default::default()
+        };
+        registry.register_plugin(crate::mock::mock_plugin("EchoPlugin", spec));
+        let run = || {
+            let mut events = Vec::new();
+            let logs = run_steps(
+                path.to_str().unwrap(),
+                HashMap::new(),
+                &registry,
+                &mut |e| events.push((e.status, e.attempt)),
+            )
+            .unwrap();
+            (logs[0].validation.clone(), events)
+        };
+
+        let (validation, events) = run();
+        assert_eq!(validation.as_deref(), Some("saved"));
+        assert_eq!(
+            events,
+            [
+                (NodeStatus::Running, 1),
+                (NodeStatus::Error, 1),
+                (NodeStatus::Running, 2),
+                (NodeStatus::Success, 2),
+            ]
+        );
+        let (validation, events) = run();
+        assert_eq!(validation.as_deref(), Some("cache"));
+        assert_eq!(events, [(NodeStatus::Cache, 1)]);
+        fs::remove_file(format!("{}/{}.json", plan::cache_dir(), key)).ok();
+    }
+
     #[test]
     fn test_substitute_vars_no_match() {
         let outputs = HashMap::new();
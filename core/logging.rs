@@ -0,0 +1,283 @@
+// Structured log events shared by the engine, CLI, UI and log files.
+use crate::channel::{self, Overflow, Receiver, Sender};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "trace" => Some(LogLevel::Trace),
+            "debug" => Some(LogLevel::Debug),
+            "info" => Some(LogLevel::Info),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "error" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+
+    /// Level from LAO_LOG, defaulting to info
+    pub fn from_env() -> Self {
+        std::env::var("LAO_LOG")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(LogLevel::Info)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEvent {
+    pub timestamp: DateTime<Utc>,
+    pub level: LogLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_id: Option<String>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, serde_json::Value>,
+}
+
+impl LogEvent {
+    pub fn new(level: LogLevel, message: impl Into<String>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            level,
+            run_id: None,
+            step_id: None,
+            message: message.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn run(mut self, run_id: impl Into<String>) -> Self {
+        self.run_id = Some(run_id.into());
+        self
+    }
+
+    pub fn step(mut self, step_id: impl Into<String>) -> Self {
+        self.step_id = Some(step_id.into());
+        self
+    }
+
+    pub fn field(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.fields.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn field_str(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).and_then(|v| v.as_str())
+    }
+
+    pub fn emit(self) {
+        emit(self);
+    }
+
+    /// One line for terminals: time, level, run/step scope, message, then fields
+    pub fn render(&self) -> String {
+        let mut line = format!(
+            "{} {:<5}",
+            self.timestamp.format("%H:%M:%S%.3f"),
+            self.level.as_str().to_uppercase()
+        );
+        match (&self.run_id, &self.step_id) {
+            (Some(run), Some(step)) => line.push_str(&format!(" [{} {}]", short_id(run), step)),
+            (Some(run), None) => line.push_str(&format!(" [{}]", short_id(run))),
+            (None, Some(step)) => line.push_str(&format!(" [{}]", step)),
+            (None, None) => {}
+        }
+        line.push(' ');
+        line.push_str(&self.message);
+        for (key, value) in &self.fields {
+            match value {
+                serde_json::Value::String(s) => line.push_str(&format!(" {}={}", key, s)),
+                other => line.push_str(&format!(" {}={}", key, other)),
+            }
+        }
+        line
+    }
+
+    /// One JSON object per line, for log files and run history
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+fn short_id(run_id: &str) -> &str {
+    run_id.get(..8).unwrap_or(run_id)
+}
+
+pub trait LogSink: Send + Sync {
+    fn log(&self, event: &LogEvent);
+
+    /// Sinks returning false are removed on the next emit
+    fn is_alive(&self) -> bool {
+        true
+    }
+}
+
+impl<F: Fn(&LogEvent) + Send + Sync> LogSink for F {
+    fn log(&self, event: &LogEvent) {
+        self(event)
+    }
+}
+
+/// Writes rendered events at or above `level` to stderr
+pub struct StderrSink {
+    pub level: LogLevel,
+}
+
+impl LogSink for StderrSink {
+    fn log(&self, event: &LogEvent) {
+        if event.level >= self.level {
+            eprintln!("{}", event.render());
+        }
+    }
+}
+
+/// Events a `subscribe` receiver holds before the oldest are dropped
+pub const SUBSCRIBER_CAPACITY: usize = 10_000;
+
+struct ChannelSink {
+    sender: Sender<LogEvent>,
+}
+
+impl LogSink for ChannelSink {
+    fn log(&self, event: &LogEvent) {
+        let _ = self.sender.send(event.clone());
+    }
+
+    fn is_alive(&self) -> bool {
+        self.sender.is_connected()
+    }
+}
+
+static SINKS: RwLock<Vec<Arc<dyn LogSink>>> = RwLock::new(Vec::new());
+
+pub fn add_sink(sink: impl LogSink + 'static) {
+    if let Ok(mut sinks) = SINKS.write() {
+        sinks.push(Arc::new(sink));
+    }
+}
+
+/// Receive every event emitted from now on; dropping the receiver unsubscribes
+pub fn subscribe() -> Receiver<LogEvent> {
+    subscribe_with(SUBSCRIBER_CAPACITY, Overflow::DropOldest)
+}
+
+/// `subscribe` with a chosen queue size and overflow policy
+pub fn subscribe_with(capacity: usize, overflow: Overflow) -> Receiver<LogEvent> {
+    let (sender, receiver) = channel::bounded(capacity, overflow);
+    add_sink(ChannelSink { sender });
+    receiver
+}
+
+/// Redact an event and deliver it to every sink. Without any sink, warnings
+/// and errors still reach stderr so library users don't lose them.
+pub fn emit(mut event: LogEvent) {
+    crate::redaction::apply(&mut event);
+    let sinks: Vec<Arc<dyn LogSink>> = match SINKS.read() {
+        Ok(sinks) => sinks.clone(),
+        Err(_) => return,
+    };
+    if sinks.is_empty() {
+        StderrSink {
+            level: LogLevel::Warn,
+        }
+        .log(&event);
+        return;
+    }
+
+    let mut any_dead = false;
+    for sink in &sinks {
+        sink.log(&event);
+        any_dead |= !sink.is_alive();
+    }
+    if any_dead {
+        if let Ok(mut sinks) = SINKS.write() {
+            sinks.retain(|s| s.is_alive());
+        }
+    }
+}
+
+pub fn debug(message: impl Into<String>) -> LogEvent {
+    LogEvent::new(LogLevel::Debug, message)
+}
+
+pub fn info(message: impl Into<String>) -> LogEvent {
+    LogEvent::new(LogLevel::Info, message)
+}
+
+pub fn warn(message: impl Into<String>) -> LogEvent {
+    LogEvent::new(LogLevel::Warn, message)
+}
+
+pub fn error(message: impl Into<String>) -> LogEvent {
+    LogEvent::new(LogLevel::Error, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_and_json() {
+        let event = info("step finished")
+            .run("0123456789abcdef")
+            .step("step2")
+            .field("runner", "EchoPlugin")
+            .field("attempt", 2);
+
+        let line = event.render();
+        assert!(line.contains("INFO  [01234567 step2] step finished"));
+        assert!(line.ends_with("attempt=2 runner=EchoPlugin"));
+
+        let json = event.to_json_line();
+        assert!(json.contains("\"level\":\"info\""));
+        let parsed: LogEvent = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, event);
+        assert_eq!(parsed.field_str("runner"), Some("EchoPlugin"));
+    }
+
+    #[test]
+    fn test_subscribe_receives_events() {
+        let receiver = subscribe();
+        warn("disk almost full").field("test", "subscribe").emit();
+        let received = receiver
+            .try_iter()
+            .find(|e| e.field_str("test") == Some("subscribe"))
+            .unwrap();
+        assert_eq!(received.level, LogLevel::Warn);
+
+        assert_eq!(LogLevel::parse("WARNING"), Some(LogLevel::Warn));
+        assert!(LogLevel::Error > LogLevel::Info);
+    }
+}
@@ -0,0 +1,337 @@
+// The map-reduce strategy: one step over an input longer than a model's context.
+// `strategy: map_reduce` options are in docs/workflows.md (Long Inputs:
+// Map-Reduce).
+use crate::plugins::{PluginInstance, PluginRegistry};
+use crate::usage::RunUsage;
+use crate::{is_error_output, WorkflowStep};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CHUNK_SIZE: usize = 4000;
+const DEFAULT_OVERLAP: usize = 200;
+const DEFAULT_PARALLEL: usize = 4;
+const SEPARATOR: &str = "\n\n";
+
+/// How a step runs its plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStrategy {
+    /// Chunk the input, run the plugin per chunk and combine the results
+    MapReduce,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MapReduceSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overlap: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel: Option<usize>,
+    /// `{chunk}` is replaced with the chunk; the chunk alone by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combine: Option<String>,
+    /// `{parts}` is replaced with the partial results; them alone by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combine_prompt: Option<String>,
+}
+
+impl MapReduceSpec {
+    fn chunk_size(&self) -> usize {
+        self.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    fn overlap(&self) -> usize {
+        self.overlap
+            .unwrap_or(DEFAULT_OVERLAP.min(self.chunk_size() / 4))
+    }
+}
+
+/// What the step's plugin calls are known by in caches and dedup: the plugin,
+/// and the map-reduce or foreach settings when it runs that way
+pub(crate) fn call_name(step: &WorkflowStep) -> Cow<'_, str> {
+    match (step.strategy, &step.foreach) {
+        (Some(StepStrategy::MapReduce), _) => Cow::Owned(format!(
+            "{} map_reduce {}",
+            step.run,
+            serde_json::to_string(&step.map_reduce.clone().unwrap_or_default()).unwrap_or_default()
+        )),
+        (None, Some(spec)) => Cow::Owned(format!(
+            "{} foreach {}",
+            step.run,
+            serde_json::to_string(spec).unwrap_or_default()
+        )),
+        (None, None) => Cow::Borrowed(&step.run),
+    }
+}
+
+/// What is wrong with the step's map-reduce settings, if anything
+pub fn problem(step: &WorkflowStep, registry: &PluginRegistry) -> Option<String> {
+    let Some(StepStrategy::MapReduce) = step.strategy else {
+        return step
+            .map_reduce
+            .is_some()
+            .then(|| "map_reduce settings need strategy: map_reduce".to_string());
+    };
+    let spec = step.map_reduce.clone().unwrap_or_default();
+    if spec.chunk_size() == 0 {
+        return Some("map_reduce chunk_size must be at least 1".to_string());
+    }
+    if spec.overlap() >= spec.chunk_size() {
+        return Some("map_reduce overlap must be smaller than chunk_size".to_string());
+    }
+    if spec.parallel == Some(0) {
+        return Some("map_reduce parallel must be at least 1".to_string());
+    }
+    spec.combine
+        .as_ref()
+        .filter(|combine| registry.get(combine).is_none())
+        .map(|combine| format!("map_reduce combine plugin '{}' not found", combine))
+}
+
+/// `input` cut into chunks of at most `size` characters, each starting with
+/// the last `overlap` characters of the one before
+pub fn chunk(input: &str, size: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = input.chars().collect();
+    if chars.len() <= size {
+        return vec![input.to_string()];
+    }
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + size).min(chars.len());
+        if end < chars.len() {
+            end = break_before(&chars[start..end])
+                .map(|b| start + b)
+                .filter(|&b| b > start + overlap)
+                .unwrap_or(end);
+        }
+        chunks.push(chars[start..end].iter().collect());
+        if end == chars.len() {
+            break;
+        }
+        start = end - overlap;
+    }
+    chunks
+}
+
+// The end of the last paragraph, line or word in `text`, in that preference,
+// if it is in the second half
+fn break_before(text: &[char]) -> Option<usize> {
+    let half = text.len() / 2;
+    let after = |pattern: &[char]| {
+        (half..text.len().saturating_sub(pattern.len() - 1))
+            .rev()
+            .find(|&i| text[i..].starts_with(pattern))
+            .map(|i| i + pattern.len())
+    };
+    after(&['\n', '\n'])
+        .or_else(|| after(&['\n']))
+        .or_else(|| after(&[' ']))
+}
+
+/// The step's map-reduce settings, if it runs that way
+pub fn spec(step: &WorkflowStep) -> Option<MapReduceSpec> {
+    step.strategy
+        .map(|StepStrategy::MapReduce| step.map_reduce.clone().unwrap_or_default())
+}
+
+/// Run `plugin` over `input` the map-reduce way; calls are added to `usage`
+pub(crate) fn run(
+    spec: &MapReduceSpec,
+    plugin: &PluginInstance,
+    registry: &PluginRegistry,
+    input: &str,
+    usage: &mut RunUsage,
+) -> Result<String, String> {
+    let combine = match &spec.combine {
+        Some(name) => registry
+            .get(name)
+            .ok_or_else(|| format!("map_reduce combine plugin '{}' not found", name))?,
+        None => plugin,
+    };
+    let parallel = spec.parallel.unwrap_or(DEFAULT_PARALLEL).max(1);
+    let chunks = chunk(input, spec.chunk_size(), spec.overlap());
+    let prompts: Vec<String> = chunks
+        .iter()
+        .map(|chunk| fill(spec.map_prompt.as_deref(), "{chunk}", chunk))
+        .collect();
+    let mut parts = call_all(plugin, &prompts, parallel, usage)
+        .map_err(|(i, e)| format!("chunk {} of {}: {}", i + 1, chunks.len(), e))?;
+    // Combined in groups that fit a chunk until one result is left
+    while parts.len() > 1 {
+        let groups = group(&parts, spec.chunk_size());
+        let prompts: Vec<String> = groups
+            .iter()
+            .map(|group| {
+                fill(
+                    spec.combine_prompt.as_deref(),
+                    "{parts}",
+                    &group.join(SEPARATOR),
+                )
+            })
+            .collect();
+        parts = call_all(combine, &prompts, parallel, usage)
+            .map_err(|(_, e)| format!("combining {} parts: {}", groups.len(), e))?;
+    }
+    Ok(parts.remove(0))
+}
+
+fn fill(template: Option<&str>, placeholder: &str, text: &str) -> String {
+    template.map_or_else(|| text.to_string(), |t| t.replace(placeholder, text))
+}
+
+// Consecutive parts joined while they fit in `size`; always fewer groups than
+// parts, so repeated rounds end
+fn group(parts: &[String], size: usize) -> Vec<Vec<String>> {
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut length = 0;
+    for part in parts {
+        let part_length = part.chars().count() + SEPARATOR.len();
+        match groups.last_mut() {
+            Some(group) if length + part_length <= size || group.len() < 2 => {
+                group.push(part.clone());
+                length += part_length;
+            }
+            _ => {
+                groups.push(vec![part.clone()]);
+                length = part_length;
+            }
+        }
+    }
+    groups
+}
+
+// A plugin call's result and how long it took
+type Call = (Result<String, String>, Duration);
+
+// Each prompt given to `plugin`, `parallel` at a time, outputs in order; the
+// index and error of a failed call otherwise
+fn call_all(
+    plugin: &PluginInstance,
+    prompts: &[String],
+    parallel: usize,
+    usage: &mut RunUsage,
+) -> Result<Vec<String>, (usize, String)> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Call>>> = Mutex::new(vec![None; prompts.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..parallel.min(prompts.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(prompt) = prompts.get(i) else {
+                    break;
+                };
+                let start = Instant::now();
+                let result = plugin.call(prompt).and_then(|output| {
+                    if is_error_output(&output) {
+                        Err(output)
+                    } else {
+                        Ok(output)
+                    }
+                });
+                results.lock().unwrap()[i] = Some((result, start.elapsed()));
+            });
+        }
+    });
+    let results = results.into_inner().unwrap();
+    // Token counts are the plugin's since the last take, so go with the last call
+    for (n, (_, elapsed)) in results.iter().flatten().enumerate() {
+        let tokens = (n + 1 == results.len())
+            .then(|| plugin.take_usage())
+            .flatten();
+        usage.record_call(&plugin.info.name, *elapsed, tokens);
+    }
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(i, result)| {
+            let (result, _) = result.expect("every prompt is called");
+            result.map_err(|e| (i, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::collections::HashMap;
+
+    /// A temp dir holding a workflow that map-reduces three paragraphs.
+    fn long_workflow() -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("lao_map_reduce_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("long.yaml");
+        std::fs::write(
+            &path,
+            "workflow: long\nsteps:\n  - run: EchoPlugin\n    input: \"alpha beta\\n\\ngamma delta\\n\\nepsilon zeta\"\n    strategy: map_reduce\n    map_reduce:\n      chunk_size: 16\n      overlap: 0\n      map_prompt: \"part: {chunk}\"\n      combine_prompt: \"all: {parts}\"\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap().to_string();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_chunk_splits_on_paragraphs() {
+        let text = "alpha beta\n\ngamma delta\n\nepsilon zeta";
+        assert_eq!(
+            chunk(text, 16, 0),
+            ["alpha beta\n\n", "gamma delta\n\n", "epsilon zeta"]
+        );
+    }
+
+    #[test]
+    fn test_chunk_with_overlap() {
+        let overlapping = chunk("one two three four five", 10, 4);
+        assert!(overlapping.iter().all(|c| c.chars().count() <= 10));
+        assert_eq!(overlapping.first().unwrap(), "one two ");
+        assert!(overlapping.last().unwrap().ends_with("five"));
+    }
+
+    #[test]
+    fn test_short_text_is_one_chunk() {
+        assert_eq!(chunk("short", 100, 10), ["short"]);
+    }
+
+    #[test]
+    fn test_map_reduce_step_maps_and_combines() {
+        let (dir, path) = long_workflow();
+        let workflow = crate::load_workflow_yaml(&path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        let logs = crate::run_workflow_with_registry(&path, HashMap::new(), &registry).unwrap();
+        let output = logs[0].output.as_deref().unwrap();
+        assert!(output.starts_with("EchoPlugin(all: "), "{}", output);
+        for part in [
+            "part: alpha beta",
+            "part: gamma delta",
+            "part: epsilon zeta",
+        ] {
+            assert!(
+                output.contains(&format!("EchoPlugin({}", part)),
+                "{}",
+                output
+            );
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_map_reduce_problems() {
+        let (dir, path) = long_workflow();
+        let workflow = crate::load_workflow_yaml(&path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        assert!(problem(&workflow.steps[0], &registry).is_none());
+        let mut step = workflow.steps[0].clone();
+        step.map_reduce.as_mut().unwrap().combine = Some("MissingPlugin".to_string());
+        assert!(problem(&step, &registry).unwrap().contains("MissingPlugin"));
+        step.strategy = None;
+        assert!(problem(&step, &registry).is_some());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,420 @@
+// Memoized step outputs shared across workflows.
+use crate::encryption;
+use crate::plan;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Plugin tag that turns memoization on for every step using the plugin
+pub const DETERMINISTIC_TAG: &str = "deterministic";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    plugin: String,
+    version: String,
+    output: String,
+    created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Memoized entries of one plugin
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PluginStats {
+    pub plugin: String,
+    pub entries: usize,
+    pub bytes: u64,
+    pub newest: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Memoized outputs under one directory
+#[derive(Debug, Clone)]
+pub struct MemoStore {
+    dir: PathBuf,
+}
+
+/// One memoized output, as listed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryInfo {
+    pub plugin: String,
+    pub key: String,
+    pub version: String,
+    pub bytes: u64,
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+/// Content key for a call of `plugin` with `params` and `input`. When the
+/// plugin takes a file and `input` names one, the file's bytes are hashed
+/// instead of the path.
+pub fn key(
+    plugin: &str,
+    version: &str,
+    params: &serde_yaml::Value,
+    input: &str,
+    takes_file: bool,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(plugin.as_bytes());
+    hasher.update([0]);
+    hasher.update(version.as_bytes());
+    hasher.update([0]);
+    // The input is hashed on its own, by content for files
+    let mut params = params.clone();
+    if let Some(mapping) = params.as_mapping_mut() {
+        mapping.remove("input");
+    }
+    hasher.update(
+        serde_json::to_string(&params)
+            .unwrap_or_default()
+            .as_bytes(),
+    );
+    hasher.update([0]);
+    match takes_file.then(|| hash_file(Path::new(input))).flatten() {
+        Some(content) => {
+            hasher.update(b"file:");
+            hasher.update(content);
+        }
+        None => {
+            hasher.update(b"text:");
+            hasher.update(input.as_bytes());
+        }
+    }
+    hex(&hasher.finalize())
+}
+
+impl MemoStore {
+    /// The store under the LAO cache directory
+    pub fn open_default() -> Self {
+        Self::at(Path::new(&plan::cache_dir()).join("memo"))
+    }
+
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Output memoized for `plugin` under `key`
+    pub fn lookup(&self, plugin: &str, key: &str) -> Option<String> {
+        let text = encryption::read_to_string(self.entry_path(plugin, key)).ok()?;
+        serde_json::from_str::<Entry>(&text)
+            .ok()
+            .map(|entry| entry.output)
+    }
+
+    pub fn store(
+        &self,
+        plugin: &str,
+        version: &str,
+        key: &str,
+        output: &str,
+    ) -> Result<(), String> {
+        let path = self.entry_path(plugin, key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        let entry = Entry {
+            plugin: plugin.to_string(),
+            version: version.to_string(),
+            output: output.to_string(),
+            created: chrono::Utc::now(),
+        };
+        let json = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+        encryption::write(&path, json)
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+
+    /// Entries per plugin, largest first
+    pub fn stats(&self) -> Vec<PluginStats> {
+        let mut all: Vec<PluginStats> = self
+            .plugin_dirs()
+            .into_iter()
+            .map(|(plugin, dir)| {
+                let mut stats = PluginStats {
+                    plugin,
+                    ..Default::default()
+                };
+                for meta in entries(&dir).filter_map(|path| fs::metadata(path).ok()) {
+                    stats.entries += 1;
+                    stats.bytes += meta.len();
+                    let modified = meta.modified().ok().map(chrono::DateTime::from);
+                    stats.newest = stats.newest.max(modified);
+                }
+                stats
+            })
+            .filter(|stats| stats.entries > 0)
+            .collect();
+        all.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.plugin.cmp(&b.plugin)));
+        all
+    }
+
+    /// Remove the entries of `plugin`, or of every plugin. Returns how many were removed.
+    pub fn clear(&self, plugin: Option<&str>) -> Result<usize, String> {
+        let mut removed = 0;
+        for (name, dir) in self.plugin_dirs() {
+            if plugin.is_some_and(|p| p != name) {
+                continue;
+            }
+            for path in entries(&dir) {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Cannot remove {}: {}", path.display(), e))?;
+                removed += 1;
+            }
+            fs::remove_dir(&dir).ok();
+        }
+        Ok(removed)
+    }
+
+    /// Entries of `plugin`, or of every plugin, newest first
+    pub fn list(&self, plugin: Option<&str>) -> Vec<EntryInfo> {
+        let mut all: Vec<EntryInfo> = self
+            .plugin_dirs()
+            .into_iter()
+            .filter(|(name, _)| plugin.is_none_or(|p| sanitize(p) == *name))
+            .flat_map(|(_, dir)| entries(&dir).collect::<Vec<_>>())
+            .filter_map(|path| read_entry(&path).map(|(info, _)| info))
+            .collect();
+        all.sort_by(|a, b| b.created.cmp(&a.created).then(a.key.cmp(&b.key)));
+        all
+    }
+
+    /// The entry whose key starts with `prefix`, and its output
+    pub fn show(&self, prefix: &str) -> Result<(EntryInfo, String), String> {
+        let mut found = self
+            .plugin_dirs()
+            .into_iter()
+            .flat_map(|(_, dir)| entries(&dir).collect::<Vec<_>>())
+            .filter(|path| {
+                path.file_stem()
+                    .is_some_and(|stem| stem.to_string_lossy().starts_with(prefix))
+            });
+        let path = match (found.next(), found.next()) {
+            (Some(path), None) => path,
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "More than one entry's key starts with '{}'",
+                    prefix
+                ))
+            }
+            (None, _) => return Err(format!("No memoized output with key '{}'", prefix)),
+        };
+        read_entry(&path).ok_or_else(|| format!("Cannot read {}", path.display()))
+    }
+
+    /// Remove entries written before `before`, then the oldest others until
+    /// the entries take at most `max_bytes`. Returns how many were removed.
+    pub fn prune(
+        &self,
+        before: Option<chrono::DateTime<chrono::Utc>>,
+        max_bytes: Option<u64>,
+    ) -> Result<usize, String> {
+        let mut kept = self.list(None);
+        let mut doomed = Vec::new();
+        if let Some(before) = before {
+            let (old, recent) = kept.into_iter().partition(|e| e.created < before);
+            doomed = old;
+            kept = recent;
+        }
+        if let Some(max_bytes) = max_bytes {
+            let mut total: u64 = kept.iter().map(|e| e.bytes).sum();
+            // Newest first, so the oldest go first
+            while total > max_bytes {
+                let Some(entry) = kept.pop() else { break };
+                total -= entry.bytes;
+                doomed.push(entry);
+            }
+        }
+        for entry in &doomed {
+            let path = self.entry_path(&entry.plugin, &entry.key);
+            fs::remove_file(&path)
+                .map_err(|e| format!("Cannot remove {}: {}", path.display(), e))?;
+        }
+        for (_, dir) in self.plugin_dirs() {
+            // Only empty directories go
+            fs::remove_dir(&dir).ok();
+        }
+        Ok(doomed.len())
+    }
+
+    fn entry_path(&self, plugin: &str, key: &str) -> PathBuf {
+        self.dir
+            .join(sanitize(plugin))
+            .join(format!("{}.json", key))
+    }
+
+    fn plugin_dirs(&self) -> Vec<(String, PathBuf)> {
+        let Ok(read) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        read.filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| {
+                (
+                    entry.file_name().to_string_lossy().into_owned(),
+                    entry.path(),
+                )
+            })
+            .collect()
+    }
+}
+
+// Plugin names become directory names
+fn sanitize(plugin: &str) -> String {
+    plugin
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn entries(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+}
+
+fn read_entry(path: &Path) -> Option<(EntryInfo, String)> {
+    let bytes = fs::metadata(path).ok()?.len();
+    let entry: Entry = serde_json::from_str(&encryption::read_to_string(path).ok()?).ok()?;
+    let info = EntryInfo {
+        plugin: entry.plugin,
+        key: path.file_stem()?.to_string_lossy().into_owned(),
+        version: entry.version,
+        bytes,
+        created: entry.created,
+    };
+    Some((info, entry.output))
+}
+
+fn hash_file(path: &Path) -> Option<Vec<u8>> {
+    let mut file = fs::File::open(path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer).ok()?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Some(hasher.finalize().to_vec())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(yaml: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("lao_memo_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_file_inputs_are_keyed_by_content() {
+        let dir = temp_dir();
+        let a = dir.join("meeting.wav");
+        let b = dir.join("copy of meeting.wav");
+        fs::write(&a, b"RIFF audio").unwrap();
+        fs::write(&b, b"RIFF audio").unwrap();
+        let params = params("input: x\nlanguage: en");
+        let key_a = key("Whisper", "1.0", &params, a.to_str().unwrap(), true);
+        assert_eq!(
+            key_a,
+            key("Whisper", "1.0", &params, b.to_str().unwrap(), true)
+        );
+        // Text inputs hash the text itself
+        assert_ne!(
+            key_a,
+            key("Whisper", "1.0", &params, a.to_str().unwrap(), false)
+        );
+        fs::write(&b, b"RIFF other audio").unwrap();
+        assert_ne!(
+            key_a,
+            key("Whisper", "1.0", &params, b.to_str().unwrap(), true)
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_key_covers_version_and_other_params() {
+        let en = params("input: x\nlanguage: en");
+        let key_en = key("Whisper", "1.0", &en, "text", false);
+        assert_ne!(key_en, key("Whisper", "1.1", &en, "text", false));
+        let french = params("input: y\nlanguage: fr");
+        assert_ne!(key_en, key("Whisper", "1.0", &french, "text", false));
+        // The input param is hashed as the input, not as a param
+        let moved = params("input: y\nlanguage: en");
+        assert_eq!(key_en, key("Whisper", "1.0", &moved, "text", false));
+    }
+
+    fn filled(dir: &Path) -> (MemoStore, String, String) {
+        let memo = MemoStore::at(dir.join("memo"));
+        let null = serde_yaml::Value::Null;
+        let key_whisper = key("Whisper", "1.0", &null, "audio", false);
+        memo.store("Whisper", "1.0", &key_whisper, "hello").unwrap();
+        let key_embed = key("Embed", "1.0", &null, "text", false);
+        memo.store("Embed", "1.0", &key_embed, "[0.1]").unwrap();
+        (memo, key_whisper, key_embed)
+    }
+
+    #[test]
+    fn test_stored_outputs_are_listed_and_shown() {
+        let dir = temp_dir();
+        let (memo, key_whisper, key_embed) = filled(&dir);
+        assert_eq!(
+            memo.lookup("Whisper", &key_whisper).as_deref(),
+            Some("hello")
+        );
+        assert!(memo.lookup("Embed", &key_whisper).is_none());
+        let listed = memo.list(None);
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].key, key_embed);
+        let (info, output) = memo.show(&key_embed[..12]).unwrap();
+        assert_eq!((info.plugin.as_str(), output.as_str()), ("Embed", "[0.1]"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_clear_removes_one_plugins_outputs() {
+        let dir = temp_dir();
+        let (memo, key_whisper, _) = filled(&dir);
+        let stats = memo.stats();
+        assert_eq!(stats.len(), 2);
+        assert!(stats.iter().all(|s| s.entries == 1));
+        assert_eq!(memo.clear(Some("Whisper")).unwrap(), 1);
+        assert!(memo.lookup("Whisper", &key_whisper).is_none());
+        assert_eq!(memo.stats()[0].plugin, "Embed");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_prune_by_age_and_size() {
+        let dir = temp_dir();
+        let (memo, _, _) = filled(&dir);
+        let oldest = memo.list(None).pop().unwrap().created;
+        // Nothing is older than the oldest entry; a size limit of 0 removes all
+        assert_eq!(memo.prune(Some(oldest), None).unwrap(), 0);
+        assert_eq!(memo.prune(None, Some(0)).unwrap(), 2);
+        assert!(memo.list(None).is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
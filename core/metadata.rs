@@ -0,0 +1,153 @@
+// Self-describing workflows.
+use crate::plugins::PluginRegistry;
+use crate::Workflow;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkflowMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Plugins the workflow needs besides those its steps run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// Command lines showing how to run it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+}
+
+impl Workflow {
+    /// The metadata title, or the workflow's name
+    pub fn title(&self) -> &str {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.title.as_deref())
+            .unwrap_or(&self.workflow)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.description.as_deref())
+    }
+
+    /// Plugins from `requires`, then those the steps run, each once
+    pub fn required_plugins(&self) -> Vec<String> {
+        let mut plugins: Vec<String> = Vec::new();
+        let declared = self.metadata.iter().flat_map(|m| m.requires.iter());
+        for plugin in declared.chain(self.steps.iter().map(|s| &s.run)) {
+            if !plugins.contains(plugin) {
+                plugins.push(plugin.clone());
+            }
+        }
+        plugins
+    }
+
+    /// Plugins in `requires` that `registry` doesn't have
+    pub fn missing_requirements(&self, registry: &PluginRegistry) -> Vec<String> {
+        self.metadata
+            .iter()
+            .flat_map(|m| m.requires.iter())
+            .filter(|plugin| registry.get(plugin).is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+/// A workflow file as the gallery lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct GalleryEntry {
+    pub path: PathBuf,
+    pub title: String,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    /// Why the file couldn't be loaded, if it couldn't
+    pub error: Option<String>,
+}
+
+/// The workflows in `dir`, sorted by file name
+pub fn gallery(dir: &Path) -> Vec<GalleryEntry> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        })
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(
+            |path| match crate::load_workflow_yaml(&path.to_string_lossy()) {
+                Ok(workflow) => GalleryEntry {
+                    title: workflow.title().to_string(),
+                    description: workflow.description().map(str::to_string),
+                    author: workflow.metadata.and_then(|m| m.author),
+                    error: None,
+                    path,
+                },
+                Err(e) => GalleryEntry {
+                    title: path
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    description: None,
+                    author: None,
+                    error: Some(e),
+                    path,
+                },
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+
+    const NOTES: &str = "workflow: notes\nmetadata:\n  title: Meeting Notes\n  description: Files a summary\n  author: Jake\n  requires: [WhisperPlugin, EchoPlugin]\n  examples:\n    - lao run notes.yaml\nsteps:\n  - run: EchoPlugin\n    input: hi\n  - run: SummarizerPlugin\n    input_from: step1\n";
+
+    #[test]
+    fn test_gallery_lists_workflows_by_title() {
+        let dir = std::env::temp_dir().join(format!("lao_metadata_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.yaml"), NOTES).unwrap();
+        std::fs::write(
+            dir.join("plain.yaml"),
+            "workflow: plain\nsteps:\n  - run: EchoPlugin\n    input: hi\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.yaml"), "steps: [").unwrap();
+        std::fs::write(dir.join("README.md"), "not a workflow").unwrap();
+
+        let entries = gallery(&dir);
+        let titles: Vec<&str> = entries.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["broken", "Meeting Notes", "plain"]);
+        assert!(entries[0].error.is_some());
+        assert_eq!(entries[1].author.as_deref(), Some("Jake"));
+        assert_eq!(entries[2].description, None);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_missing_requirements_are_plan_warnings() {
+        let workflow: crate::Workflow = serde_yaml::from_str(NOTES).unwrap();
+        assert_eq!(
+            workflow.required_plugins(),
+            ["WhisperPlugin", "EchoPlugin", "SummarizerPlugin"]
+        );
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        assert_eq!(workflow.missing_requirements(&registry), ["WhisperPlugin"]);
+        let plan = crate::plan::ExecutionPlan::compile(workflow, &registry);
+        assert!(plan.is_valid());
+        assert_eq!(plan.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,382 @@
+use crate::hooks::{ExecutionHook, RunContext, RunEnd, StepContext, StepFinish};
+use crate::node::NodeStatus;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+// Upper bounds in seconds; plugin calls range from sub-millisecond echoes to multi-minute LLM runs
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; DURATION_BUCKETS.len()];
+        }
+        for (bucket, bound) in self.counts.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    workflow_runs: BTreeMap<String, u64>,
+    workflow_duration: Histogram,
+    steps: BTreeMap<(String, String), u64>,
+    step_duration: BTreeMap<String, Histogram>,
+    plugin_latency: BTreeMap<String, Histogram>,
+    cache_hits: u64,
+    cache_misses: u64,
+    queue_depth: u64,
+    trigger_fires: BTreeMap<String, u64>,
+}
+
+/// Process-wide counters for the executor and the scheduler, rendered in the
+/// Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// The metrics shared by every executor in this process
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+/// Records step and run outcomes into `global()`; a built-in execution hook
+pub struct MetricsHook;
+
+impl ExecutionHook for MetricsHook {
+    fn on_step_finish(&self, _run: &RunContext, step: &StepContext, finish: &StepFinish) {
+        global().record_step(&step.step.run, finish.status, finish.duration);
+    }
+
+    fn on_run_end(&self, _run: &RunContext, end: &RunEnd) {
+        global().record_workflow_run(end.status, end.duration);
+    }
+}
+
+impl Metrics {
+    fn with<R>(&self, f: impl FnOnce(&mut Registry) -> R) -> R {
+        let mut registry = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut registry)
+    }
+
+    /// Status is "success", "error" (some step failed) or "invalid" (rejected before running)
+    pub fn record_workflow_run(&self, status: &str, duration: Duration) {
+        self.with(|r| {
+            *r.workflow_runs.entry(status.to_string()).or_default() += 1;
+            r.workflow_duration.observe(duration.as_secs_f64());
+        });
+    }
+
+    /// Final status of a step: success, error, cache or skipped
+    pub fn record_step(&self, runner: &str, status: NodeStatus, duration: Duration) {
+        self.with(|r| {
+            *r.steps
+                .entry((runner.to_string(), status.as_str().to_string()))
+                .or_default() += 1;
+            r.step_duration
+                .entry(runner.to_string())
+                .or_default()
+                .observe(duration.as_secs_f64());
+        });
+    }
+
+    /// One call into a plugin's `run`, including failed attempts
+    pub fn record_plugin_call(&self, plugin: &str, duration: Duration) {
+        self.with(|r| {
+            r.plugin_latency
+                .entry(plugin.to_string())
+                .or_default()
+                .observe(duration.as_secs_f64());
+        });
+    }
+
+    pub fn record_cache_lookup(&self, hit: bool) {
+        self.with(|r| {
+            if hit {
+                r.cache_hits += 1;
+            } else {
+                r.cache_misses += 1;
+            }
+        });
+    }
+
+    /// Workflows that are due but not yet started
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.with(|r| r.queue_depth = depth as u64);
+    }
+
+    pub fn record_trigger(&self, kind: &str) {
+        self.with(|r| *r.trigger_fires.entry(kind.to_string()).or_default() += 1);
+    }
+
+    pub fn render(&self) -> String {
+        self.with(|r| {
+            let mut out = String::new();
+
+            header(
+                &mut out,
+                "lao_workflow_runs_total",
+                "counter",
+                "Workflow runs by final status",
+            );
+            for (status, count) in &r.workflow_runs {
+                let _ = writeln!(
+                    out,
+                    "lao_workflow_runs_total{{status=\"{}\"}} {}",
+                    escape(status),
+                    count
+                );
+            }
+            header(
+                &mut out,
+                "lao_workflow_duration_seconds",
+                "histogram",
+                "Wall-clock time of whole workflow runs",
+            );
+            histogram(
+                &mut out,
+                "lao_workflow_duration_seconds",
+                "",
+                &r.workflow_duration,
+            );
+
+            header(
+                &mut out,
+                "lao_steps_total",
+                "counter",
+                "Executed steps by runner and status",
+            );
+            for ((runner, status), count) in &r.steps {
+                let _ = writeln!(
+                    out,
+                    "lao_steps_total{{runner=\"{}\",status=\"{}\"}} {}",
+                    escape(runner),
+                    escape(status),
+                    count
+                );
+            }
+            header(
+                &mut out,
+                "lao_step_duration_seconds",
+                "histogram",
+                "Step time including retries and retry delays",
+            );
+            for (runner, hist) in &r.step_duration {
+                let labels = format!("runner=\"{}\"", escape(runner));
+                histogram(&mut out, "lao_step_duration_seconds", &labels, hist);
+            }
+
+            header(
+                &mut out,
+                "lao_plugin_call_duration_seconds",
+                "histogram",
+                "Latency of individual plugin calls",
+            );
+            for (plugin, hist) in &r.plugin_latency {
+                let labels = format!("plugin=\"{}\"", escape(plugin));
+                histogram(&mut out, "lao_plugin_call_duration_seconds", &labels, hist);
+            }
+
+            header(
+                &mut out,
+                "lao_cache_lookups_total",
+                "counter",
+                "Step cache lookups by result",
+            );
+            let _ = writeln!(
+                out,
+                "lao_cache_lookups_total{{result=\"hit\"}} {}",
+                r.cache_hits
+            );
+            let _ = writeln!(
+                out,
+                "lao_cache_lookups_total{{result=\"miss\"}} {}",
+                r.cache_misses
+            );
+
+            header(
+                &mut out,
+                "lao_queue_depth",
+                "gauge",
+                "Scheduled workflows that are due but not yet started",
+            );
+            let _ = writeln!(out, "lao_queue_depth {}", r.queue_depth);
+
+            header(
+                &mut out,
+                "lao_trigger_fires_total",
+                "counter",
+                "Workflow runs started by triggers",
+            );
+            for (kind, count) in &r.trigger_fires {
+                let _ = writeln!(
+                    out,
+                    "lao_trigger_fires_total{{trigger=\"{}\"}} {}",
+                    escape(kind),
+                    count
+                );
+            }
+
+            out
+        })
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn histogram(out: &mut String, name: &str, labels: &str, hist: &Histogram) {
+    let sep = if labels.is_empty() { "" } else { "," };
+    for (i, bound) in DURATION_BUCKETS.iter().enumerate() {
+        let count = hist.counts.get(i).copied().unwrap_or(0);
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"{}\"}} {}",
+            name, labels, sep, bound, count
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+        name, labels, sep, hist.count
+    );
+    let braces = if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    };
+    let _ = writeln!(out, "{}_sum{} {}", name, braces, hist.sum);
+    let _ = writeln!(out, "{}_count{} {}", name, braces, hist.count);
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serve `GET /metrics` from the global registry on a background thread
+pub fn serve<A: ToSocketAddrs>(addr: A) -> std::io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(e) = handle_connection(stream) {
+                log::warn!("metrics request failed: {}", e);
+            }
+        }
+    }))
+}
+
+fn handle_connection(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path == "/metrics" || path.starts_with("/metrics?") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            global().render(),
+        ),
+        _ => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "not found\n".to_string(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_exposition_format() {
+        let metrics = Metrics::default();
+        metrics.record_workflow_run("success", Duration::from_millis(300));
+        metrics.record_step("EchoPlugin", NodeStatus::Success, Duration::from_millis(20));
+        metrics.record_step("EchoPlugin", NodeStatus::Error, Duration::from_secs(2));
+        metrics.record_plugin_call("EchoPlugin", Duration::from_millis(20));
+        metrics.record_cache_lookup(true);
+        metrics.record_cache_lookup(false);
+        metrics.record_cache_lookup(false);
+        metrics.set_queue_depth(3);
+        metrics.record_trigger("schedule");
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE lao_workflow_runs_total counter"));
+        assert!(text.contains("lao_workflow_runs_total{status=\"success\"} 1"));
+        assert!(text.contains("lao_steps_total{runner=\"EchoPlugin\",status=\"error\"} 1"));
+        assert!(
+            text.contains("lao_step_duration_seconds_bucket{runner=\"EchoPlugin\",le=\"0.025\"} 1")
+        );
+        assert!(
+            text.contains("lao_step_duration_seconds_bucket{runner=\"EchoPlugin\",le=\"+Inf\"} 2")
+        );
+        assert!(text.contains("lao_step_duration_seconds_count{runner=\"EchoPlugin\"} 2"));
+        assert!(text.contains("lao_workflow_duration_seconds_bucket{le=\"0.5\"} 1"));
+        assert!(text.contains("lao_workflow_duration_seconds_count 1"));
+        assert!(text.contains("lao_cache_lookups_total{result=\"miss\"} 2"));
+        assert!(text.contains("lao_queue_depth 3"));
+        assert!(text.contains("lao_trigger_fires_total{trigger=\"schedule\"} 1"));
+    }
+
+    #[test]
+    fn test_serve_metrics_endpoint() {
+        use std::io::Read;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        serve(addr).unwrap();
+        global().record_trigger("test");
+
+        let mut response = String::new();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("lao_trigger_fires_total{trigger=\"test\"}"));
+
+        let mut response = String::new();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
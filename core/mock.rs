@@ -0,0 +1,115 @@
+// Mock plugins with canned responses, for `lao run --mock` and `lao test`.
+use crate::plugins::{PluginInstance, PluginRegistry};
+use crate::Workflow;
+use lao_plugin_api::PluginInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub type MockFixtures = HashMap<String, MockSpec>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MockSpec {
+    /// Answers for particular inputs, tried before `error` and `output`
+    #[serde(default)]
+    pub responses: Vec<CannedResponse>,
+    /// Output template; `{input}` is replaced with the plugin input
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Fail every call with this error instead
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Fail this many calls before answering, to exercise retries
+    #[serde(default)]
+    pub fail_times: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CannedResponse {
+    /// Answer only inputs containing this text; unset matches any input
+    #[serde(default)]
+    pub input_contains: Option<String>,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+pub fn load_fixtures(path: &Path) -> Result<MockFixtures, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    serde_yaml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+/// Version reported by every mock plugin
+pub const MOCK_VERSION: &str = "0.0.0-mock";
+
+/// A registry with a mock for every plugin `workflow` runs
+pub fn mock_registry(workflow: &Workflow, fixtures: &MockFixtures) -> PluginRegistry {
+    let mut registry = PluginRegistry::new();
+    for step in &workflow.steps {
+        if registry.get(&step.run).is_none() {
+            let spec = fixtures.get(&step.run).cloned().unwrap_or_default();
+            registry.register_plugin(mock_plugin(&step.run, spec));
+        }
+    }
+    registry
+}
+
+pub fn mock_plugin(name: &str, spec: MockSpec) -> PluginInstance {
+    let info = PluginInfo {
+        name: name.to_string(),
+        version: MOCK_VERSION.to_string(),
+        description: "mock plugin with canned responses".to_string(),
+        author: String::new(),
+        dependencies: Vec::new(),
+        tags: Vec::new(),
+        capabilities: Vec::new(),
+        input_schema: None,
+        output_schema: None,
+    };
+    let plugin = name.to_string();
+    let calls = AtomicU32::new(0);
+    // Errors are returned as "error: ..." outputs, as real plugins report them
+    PluginInstance::native(info, move |input| {
+        if calls.fetch_add(1, Ordering::Relaxed) < spec.fail_times {
+            return Ok(format!("error: {} mock failure", plugin));
+        }
+        let canned = spec.responses.iter().find(|r| {
+            r.input_contains
+                .as_deref()
+                .is_none_or(|text| input.contains(text))
+        });
+        let (output, error) = match canned {
+            Some(response) => (&response.output, &response.error),
+            None => (&spec.output, &spec.error),
+        };
+        Ok(match (error, output) {
+            (Some(error), _) => format!("error: {}", error),
+            (None, Some(template)) => template.replace("{input}", input),
+            (None, None) => format!("{}({})", plugin, input),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canned_responses_match_in_order() {
+        let fixtures: MockFixtures = serde_yaml::from_str(
+            "Whisper:\n  responses:\n    - input_contains: corrupt\n      error: bad audio\n    - input_contains: wav\n      output: \"heard {input}\"\n  output: silence\nFlaky:\n  fail_times: 1\n",
+        )
+        .unwrap();
+        let whisper = mock_plugin("Whisper", fixtures["Whisper"].clone());
+        assert_eq!(whisper.call("a.wav").unwrap(), "heard a.wav");
+        assert_eq!(whisper.call("corrupt.wav").unwrap(), "error: bad audio");
+        assert_eq!(whisper.call("a.mp3").unwrap(), "silence");
+
+        let flaky = mock_plugin("Flaky", fixtures["Flaky"].clone());
+        assert!(flaky.call("x").unwrap().starts_with("error:"));
+        assert_eq!(flaky.call("x").unwrap(), "Flaky(x)");
+    }
+}
@@ -0,0 +1,415 @@
+// MQTT trigger for the daemon.
+// The `mqtt:` block is described in docs/workflows.md (MQTT Triggers).
+use crate::run_labels;
+use crate::{load_workflow_yaml, run_workflow_yaml_with_inputs, StepLog};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Seconds the broker waits for a packet before dropping the client
+const KEEP_ALIVE_SECS: u16 = 60;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Payloads larger than this are dropped
+pub const MAX_PAYLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttTrigger {
+    /// Topic filter, with `+` and `#` wildcards
+    pub topic: String,
+}
+
+impl MqttTrigger {
+    pub fn problem(&self) -> Option<String> {
+        let levels: Vec<&str> = self.topic.split('/').collect();
+        let misplaced = levels.iter().enumerate().any(|(i, level)| {
+            (level.contains('#') && (*level != "#" || i + 1 < levels.len()))
+                || (level.contains('+') && *level != "+")
+        });
+        if self.topic.is_empty() {
+            Some("mqtt topic is empty".to_string())
+        } else if misplaced {
+            Some(format!(
+                "mqtt topic '{}' misuses a wildcard: '+' must be a whole level and '#' the last one",
+                self.topic
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `topic` matches the subscription `filter`
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(actual)) if level == actual => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// A workflow run by messages on `topic`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub path: PathBuf,
+    pub topic: String,
+}
+
+/// Workflows in `workflows_dir` with an `mqtt:` block
+pub fn subscriptions(workflows_dir: &Path) -> Vec<Subscription> {
+    let Ok(entries) = std::fs::read_dir(workflows_dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yaml"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let workflow = load_workflow_yaml(&path.to_string_lossy()).ok()?;
+            let trigger = workflow.mqtt?;
+            if let Some(problem) = trigger.problem() {
+                log::warn!("{}: {}", path.display(), problem);
+                return None;
+            }
+            Some(Subscription {
+                path,
+                topic: trigger.topic,
+            })
+        })
+        .collect()
+}
+
+/// Login for brokers that need one
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    /// LAO_MQTT_USERNAME and LAO_MQTT_PASSWORD
+    pub fn from_env() -> Self {
+        Self {
+            username: std::env::var("LAO_MQTT_USERNAME").ok(),
+            password: std::env::var("LAO_MQTT_PASSWORD").ok(),
+        }
+    }
+}
+
+/// Subscribe at `broker` for the workflows in `workflows_dir` on a background
+/// thread, reconnecting when the connection drops
+pub fn serve(broker: &str, workflows_dir: impl Into<PathBuf>) -> thread::JoinHandle<()> {
+    let broker = broker.to_string();
+    let workflows_dir = workflows_dir.into();
+    let credentials = Credentials::from_env();
+    thread::spawn(move || loop {
+        let subscriptions = subscriptions(&workflows_dir);
+        if subscriptions.is_empty() {
+            thread::sleep(RECONNECT_DELAY);
+            continue;
+        }
+        let filters: Vec<String> = subscriptions.iter().map(|s| s.topic.clone()).collect();
+        let result = listen(&broker, &credentials, &filters, &mut |topic, payload| {
+            for subscription in subscriptions
+                .iter()
+                .filter(|s| topic_matches(&s.topic, &topic))
+            {
+                // Workflows can take a while, so each message gets its own thread
+                let path = subscription.path.clone();
+                let topic = topic.clone();
+                let payload = payload.clone();
+                thread::spawn(move || {
+                    if let Err(e) = trigger(&path, &topic, payload) {
+                        log::warn!("mqtt workflow {} failed: {}", path.display(), e);
+                    }
+                });
+            }
+        });
+        if let Err(e) = result {
+            crate::logging::warn("mqtt connection lost")
+                .field("broker", broker.clone())
+                .field("error", e)
+                .emit();
+        }
+        thread::sleep(RECONNECT_DELAY);
+    })
+}
+
+/// Run the workflow at `path` for a message on `topic`
+pub fn trigger(path: &Path, topic: &str, payload: Vec<u8>) -> Result<Vec<StepLog>, String> {
+    if crate::daemon::triggers_paused() {
+        return Err("triggers are paused".to_string());
+    }
+    let message = match String::from_utf8(payload) {
+        Ok(text) => text,
+        Err(e) => {
+            let dir = std::env::temp_dir().join("lao_mqtt");
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+            let file = dir.join(format!("{}.bin", uuid::Uuid::new_v4()));
+            std::fs::write(&file, e.into_bytes())
+                .map_err(|e| format!("Cannot write {}: {}", file.display(), e))?;
+            file.to_string_lossy().to_string()
+        }
+    };
+    let path = path.to_string_lossy().to_string();
+    crate::metrics::global().record_trigger("mqtt");
+    crate::logging::info("mqtt message triggered workflow")
+        .field("topic", topic)
+        .field("path", path.clone())
+        .emit();
+    let inputs = HashMap::from([
+        ("message".to_string(), message),
+        ("topic".to_string(), topic.to_string()),
+    ]);
+    run_labels::with_trigger("mqtt", || run_workflow_yaml_with_inputs(&path, inputs))
+}
+
+/// Connect to `broker`, subscribe to `filters` and call `on_message` with the
+/// topic and payload of each message until the connection ends
+pub fn listen(
+    broker: &str,
+    credentials: &Credentials,
+    filters: &[String],
+    on_message: &mut dyn FnMut(String, Vec<u8>),
+) -> Result<(), String> {
+    let mut stream =
+        TcpStream::connect(broker).map_err(|e| format!("Cannot connect to {}: {}", broker, e))?;
+    let client_id = format!("lao-{}", &uuid::Uuid::new_v4().simple().to_string()[..12]);
+    write_packet(&mut stream, 0x10, &connect_body(&client_id, credentials))?;
+    match read_packet(&mut stream)? {
+        (0x20, body) if body.get(1) == Some(&0) => {}
+        (0x20, body) => {
+            return Err(format!(
+                "{} refused the connection (code {})",
+                broker,
+                body.get(1).copied().unwrap_or_default()
+            ))
+        }
+        (kind, _) => return Err(format!("expected CONNACK, got packet type {}", kind >> 4)),
+    }
+    write_packet(&mut stream, 0x82, &subscribe_body(1, filters))?;
+    crate::logging::info("mqtt subscribed")
+        .field("broker", broker)
+        .field("topics", filters.join(", "))
+        .emit();
+
+    // Reads wake up in time to ping before the broker's keep-alive runs out
+    let ping_every = Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2);
+    stream
+        .set_read_timeout(Some(ping_every))
+        .map_err(|e| e.to_string())?;
+    let mut last_sent = Instant::now();
+    loop {
+        if last_sent.elapsed() >= ping_every {
+            write_packet(&mut stream, 0xC0, &[])?;
+            last_sent = Instant::now();
+        }
+        let (kind, body) = match read_packet(&mut stream) {
+            Ok(packet) => packet,
+            Err(e) if e.contains("timed out") || e.contains("would block") => continue,
+            Err(e) => return Err(e),
+        };
+        match kind >> 4 {
+            // PUBLISH
+            3 => {
+                let qos = (kind >> 1) & 0x03;
+                let (topic, mut rest) = read_string(&body)?;
+                if qos > 0 {
+                    if rest.len() < 2 {
+                        return Err("PUBLISH without a packet id".to_string());
+                    }
+                    write_packet(&mut stream, 0x40, &rest[..2])?;
+                    last_sent = Instant::now();
+                    rest = &rest[2..];
+                }
+                on_message(topic, rest.to_vec());
+            }
+            // SUBACK
+            9 if body.iter().skip(2).any(|&code| code == 0x80) => {
+                return Err("the broker refused a subscription".to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn connect_body(client_id: &str, credentials: &Credentials) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if credentials.username.is_some() {
+        flags |= 0x80;
+    }
+    if credentials.password.is_some() {
+        flags |= 0x40;
+    }
+    let mut body = Vec::new();
+    push_string(&mut body, "MQTT");
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    push_string(&mut body, client_id);
+    for value in [&credentials.username, &credentials.password]
+        .into_iter()
+        .flatten()
+    {
+        push_string(&mut body, value);
+    }
+    body
+}
+
+fn subscribe_body(packet_id: u16, filters: &[String]) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    for filter in filters {
+        push_string(&mut body, filter);
+        body.push(1); // at least once
+    }
+    body
+}
+
+fn push_string(buf: &mut Vec<u8>, text: &str) {
+    buf.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    buf.extend_from_slice(text.as_bytes());
+}
+
+fn read_string(buf: &[u8]) -> Result<(String, &[u8]), String> {
+    let len = buf
+        .get(..2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+        .ok_or("truncated packet")?;
+    let text = buf.get(2..2 + len).ok_or("truncated packet")?;
+    Ok((String::from_utf8_lossy(text).to_string(), &buf[2 + len..]))
+}
+
+fn write_packet(stream: &mut TcpStream, kind: u8, body: &[u8]) -> Result<(), String> {
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    stream.write_all(&packet).map_err(|e| e.to_string())
+}
+
+fn read_packet(stream: &mut TcpStream) -> Result<(u8, Vec<u8>), String> {
+    let mut kind = [0u8];
+    match stream.read(&mut kind) {
+        Ok(0) => return Err("the broker closed the connection".to_string()),
+        Ok(_) => {}
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Err("read timed out".to_string())
+        }
+        Err(e) => return Err(e.to_string()),
+    }
+    let mut len = 0usize;
+    for shift in (0..4).map(|i| 7 * i) {
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte).map_err(|e| e.to_string())?;
+        len |= ((byte[0] & 0x7F) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    if len > MAX_PAYLOAD_BYTES {
+        return Err(format!("packet of {} bytes is too large", len));
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok((kind[0], body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_topic_wildcards() {
+        assert!(topic_matches("home/+/ring", "home/door/ring"));
+        assert!(topic_matches("home/#", "home/door/ring"));
+        assert!(topic_matches("home/#", "home"));
+        assert!(!topic_matches("home/+", "home/door/ring"));
+        assert!(!topic_matches("home/door", "home/window"));
+    }
+
+    #[test]
+    fn test_misplaced_wildcards_are_problems() {
+        let trigger = |topic: &str| MqttTrigger {
+            topic: topic.to_string(),
+        };
+        assert!(trigger("home/+/ring").problem().is_none());
+        assert!(trigger("home/#/ring").problem().is_some());
+        assert!(trigger("home/door+").problem().is_some());
+    }
+
+    #[test]
+    fn test_messages_from_a_broker() {
+        // A broker that accepts, acknowledges the subscription and sends two messages
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (kind, connect) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, 0x10);
+            assert_eq!(connect[7] & 0xC0, 0xC0);
+            write_packet(&mut stream, 0x20, &[0, 0]).unwrap();
+            let (kind, subscribe) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, 0x82);
+            let (filter, _) = read_string(&subscribe[2..]).unwrap();
+            write_packet(&mut stream, 0x90, &[0, 1, 1]).unwrap();
+
+            let mut publish = Vec::new();
+            push_string(&mut publish, "home/door/ring");
+            publish.extend_from_slice(b"ding");
+            write_packet(&mut stream, 0x30, &publish).unwrap();
+            let mut publish = Vec::new();
+            push_string(&mut publish, "home/door/audio");
+            publish.extend_from_slice(&[0, 7]);
+            publish.extend_from_slice(&[0xFF, 0xFE]);
+            write_packet(&mut stream, 0x32, &publish).unwrap();
+            let (kind, puback) = read_packet(&mut stream).unwrap();
+            assert_eq!((kind, puback), (0x40, vec![0, 7]));
+            filter
+        });
+
+        let credentials = Credentials {
+            username: Some("lao".to_string()),
+            password: Some("secret".to_string()),
+        };
+        let mut messages = Vec::new();
+        let result = listen(
+            &addr,
+            &credentials,
+            &["home/#".to_string()],
+            &mut |topic, payload| messages.push((topic, payload)),
+        );
+        assert!(result.is_err());
+        assert_eq!(broker.join().unwrap(), "home/#");
+        assert_eq!(
+            messages,
+            [
+                ("home/door/ring".to_string(), b"ding".to_vec()),
+                ("home/door/audio".to_string(), vec![0xFF, 0xFE]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+// Identity and status of workflow graph nodes, shared by the engine's step
+// events and the UI graph.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, OnceLock};
+
+#[derive(Clone)]
+pub struct NodeId(Arc<str>);
+
+static INTERNED: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+
+impl NodeId {
+    pub fn new(id: &str) -> Self {
+        let mut interned = INTERNED
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(existing) = interned.get(id) {
+            return Self(existing.clone());
+        }
+        let id: Arc<str> = Arc::from(id);
+        interned.insert(id.clone());
+        Self(id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for NodeId {
+    fn eq(&self, other: &Self) -> bool {
+        // Interned ids with the same text share a pointer, so this rarely
+        // compares strings
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for NodeId {}
+
+// Hashes like `str` so maps keyed by `NodeId` can be queried with `&str`
+impl Hash for NodeId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialEq<str> for NodeId {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for NodeId {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for NodeId {
+    fn eq(&self, other: &String) -> bool {
+        &*self.0 == other.as_str()
+    }
+}
+
+impl Deref for NodeId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for NodeId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for NodeId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<&String> for NodeId {
+    fn from(id: &String) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for NodeId {
+    fn from(id: String) -> Self {
+        Self::new(&id)
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        Ok(Self::new(&id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeStatus {
+    #[default]
+    Pending,
+    Running,
+    Success,
+    Error,
+    /// Output was read from the step cache
+    Cache,
+    /// The step's condition was not met
+    Skipped,
+    /// The run was cancelled before the step could finish
+    Cancelled,
+}
+
+impl NodeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeStatus::Pending => "pending",
+            NodeStatus::Running => "running",
+            NodeStatus::Success => "success",
+            NodeStatus::Error => "error",
+            NodeStatus::Cache => "cache",
+            NodeStatus::Skipped => "skipped",
+            NodeStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Success or cache: the step produced an output
+    pub fn is_complete(&self) -> bool {
+        matches!(self, NodeStatus::Success | NodeStatus::Cache)
+    }
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_ids_are_interned() {
+        let a = NodeId::new("step1");
+        let b = NodeId::from(format!("step{}", 1));
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, "step1");
+
+        let json = serde_json::to_string(&(a.clone(), NodeStatus::Cache)).unwrap();
+        assert_eq!(json, r#"["step1","cache"]"#);
+        let (c, status): (NodeId, NodeStatus) = serde_json::from_str(&json).unwrap();
+        assert!(Arc::ptr_eq(&a.0, &c.0));
+        assert!(status.is_complete());
+    }
+}
@@ -0,0 +1,528 @@
+// Shared Ollama client.
+use lao_plugin_api::{HostResponse, HostServices, PluginInput};
+use std::ffi::{c_char, CStr};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_URL: &str = "http://localhost:11434";
+const DEFAULT_PORT: u16 = 11434;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+// Idle connections kept for reuse
+const MAX_IDLE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OllamaConfig {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    /// Longest wait for a reply; generation on a CPU can take minutes
+    pub timeout: Duration,
+    /// Retries after the first attempt
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub backoff: Duration,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_URL.to_string(),
+            api_key: None,
+            timeout: Duration::from_secs(300),
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl OllamaConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+        Self {
+            base_url: env("LAO_OLLAMA_URL")
+                .or_else(|| env("OLLAMA_HOST"))
+                .unwrap_or(defaults.base_url),
+            api_key: env("LAO_OLLAMA_API_KEY"),
+            timeout: env("LAO_OLLAMA_TIMEOUT")
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.timeout),
+            retries: env("LAO_OLLAMA_RETRIES")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.retries),
+            backoff: defaults.backoff,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OllamaResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl OllamaResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+pub struct OllamaClient {
+    config: OllamaConfig,
+    host: String,
+    port: u16,
+    // Path prefix of the base URL, without a trailing slash
+    base_path: String,
+    idle: Mutex<Vec<TcpStream>>,
+}
+
+impl OllamaClient {
+    pub fn new(config: OllamaConfig) -> Result<Self, String> {
+        let url = config.base_url.trim();
+        let (rest, default_port) = match url.split_once("://") {
+            Some(("http", rest)) => (rest, 80),
+            Some((scheme, _)) => {
+                return Err(format!(
+                    "only http:// Ollama URLs are supported, got {}:// in '{}'; reach TLS endpoints through a local proxy",
+                    scheme, url
+                ))
+            }
+            // OLLAMA_HOST style: host[:port]
+            None => (url, DEFAULT_PORT),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid port in Ollama URL '{}'", url))?,
+            ),
+            None => (authority, default_port),
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in Ollama URL '{}'", url));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            base_path: path.trim_end_matches('/').to_string(),
+            config,
+            idle: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn config(&self) -> &OllamaConfig {
+        &self.config
+    }
+
+    /// Send a request, retrying connection failures, 429 and 5xx answers.
+    /// Other statuses are returned as they are.
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+    ) -> Result<OllamaResponse, String> {
+        self.request_streaming(method, path, body, &mut |_| {})
+    }
+
+    /// Like `request`, also handing `on_body` each piece of a successful
+    /// response's body as it arrives, e.g. the lines of a streamed reply
+    pub fn request_streaming(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+        on_body: &mut dyn FnMut(&[u8]),
+    ) -> Result<OllamaResponse, String> {
+        let mut attempt = 0;
+        loop {
+            if let Some(reason) = crate::cancel::stopped() {
+                return Err(reason);
+            }
+            let result = self.send(method, path, body, on_body);
+            let retryable = match &result {
+                Ok(response) => response.status == 429 || response.status >= 500,
+                Err(_) => true,
+            };
+            if !retryable || attempt >= self.config.retries {
+                return result.map_err(|e| {
+                    format!(
+                        "cannot reach Ollama at {}: {}",
+                        self.config.base_url.trim(),
+                        e
+                    )
+                });
+            }
+            let delay = self
+                .config
+                .backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(MAX_BACKOFF);
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// POST `body` as JSON and parse the JSON reply
+    pub fn post_json(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let response = self.request("POST", path, Some(&body.to_string()))?;
+        if !response.is_success() {
+            return Err(format!(
+                "Ollama returned {}: {}",
+                response.status,
+                response.body.trim()
+            ));
+        }
+        serde_json::from_str(&response.body).map_err(|e| format!("invalid Ollama reply: {}", e))
+    }
+
+    /// The server's version, from a single attempt at `/api/version`
+    pub fn health(&self) -> Result<String, String> {
+        let response = self
+            .send("GET", "/api/version", None, &mut |_| {})
+            .map_err(|e| format!("cannot reach Ollama at {}: {}", self.config.base_url, e))?;
+        if !response.is_success() {
+            return Err(format!("Ollama returned {}", response.status));
+        }
+        let reply: serde_json::Value = serde_json::from_str(&response.body)
+            .map_err(|e| format!("invalid Ollama reply: {}", e))?;
+        Ok(reply["version"].as_str().unwrap_or("unknown").to_string())
+    }
+
+    // One attempt, on an idle connection if there is one. A reused connection
+    // the server has since closed fails at once, so that gets one more try on
+    // a fresh connection.
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+        on_body: &mut dyn FnMut(&[u8]),
+    ) -> io::Result<OllamaResponse> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        if let Some(mut stream) = idle {
+            if let Ok(response) = self.exchange(&mut stream, method, path, body, on_body) {
+                return Ok(response);
+            }
+        }
+        let mut stream = self.connect()?;
+        self.exchange(&mut stream, method, path, body, on_body)
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address for host");
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.config.timeout))?;
+                    stream.set_write_timeout(Some(self.config.timeout))?;
+                    let _ = stream.set_nodelay(true);
+                    return Ok(stream);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    // Write one request and read its response, returning the connection to
+    // the idle pool if the server keeps it open
+    fn exchange(
+        &self,
+        stream: &mut TcpStream,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+        on_body: &mut dyn FnMut(&[u8]),
+    ) -> io::Result<OllamaResponse> {
+        let body = body.unwrap_or("");
+        let mut request = format!(
+            "{} {}{} HTTP/1.1\r\nHost: {}:{}\r\nAccept: application/json\r\nConnection: keep-alive\r\n",
+            method, self.base_path, path, self.host, self.port
+        );
+        if let Some(key) = &self.config.api_key {
+            request.push_str(&format!("Authorization: Bearer {}\r\n", key));
+        }
+        if !body.is_empty() || method != "GET" {
+            request.push_str(&format!(
+                "Content-Type: application/json\r\nContent-Length: {}\r\n",
+                body.len()
+            ));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        // A stopped step (see `cancel`) doesn't wait for the reply
+        let _abort = stream.try_clone().ok().map(|socket| {
+            crate::cancel::on_stop(move || {
+                let _ = socket.shutdown(Shutdown::Both);
+            })
+        });
+        stream.write_all(request.as_bytes())?;
+        stream.flush()?;
+
+        let mut reader = BufReader::new(&*stream);
+        let mut status_line = String::new();
+        if reader.read_line(&mut status_line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        }
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| invalid(format!("bad status line '{}'", status_line.trim())))?;
+
+        let mut content_length = None;
+        let mut chunked = false;
+        let mut keep_alive = status_line.starts_with("HTTP/1.1");
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse::<usize>().ok(),
+                "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+                "connection" => keep_alive = !value.eq_ignore_ascii_case("close"),
+                _ => {}
+            }
+        }
+
+        // Error bodies are only returned
+        let mut on_body = |bytes: &[u8]| {
+            if (200..300).contains(&status) {
+                on_body(bytes)
+            }
+        };
+        let mut bytes = Vec::new();
+        if chunked {
+            loop {
+                let mut size_line = String::new();
+                reader.read_line(&mut size_line)?;
+                let size = size_line.split(';').next().unwrap_or("").trim();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| invalid(format!("bad chunk size '{}'", size)))?;
+                if size == 0 {
+                    // Trailers, up to the closing blank line
+                    let mut trailer = String::new();
+                    while reader.read_line(&mut trailer)? > 0 && trailer.trim_end() != "" {
+                        trailer.clear();
+                    }
+                    break;
+                }
+                let start = bytes.len();
+                bytes.resize(start + size, 0);
+                reader.read_exact(&mut bytes[start..])?;
+                on_body(&bytes[start..]);
+                let mut crlf = [0u8; 2];
+                reader.read_exact(&mut crlf)?;
+            }
+        } else if let Some(length) = content_length {
+            bytes.resize(length, 0);
+            reader.read_exact(&mut bytes)?;
+            on_body(&bytes);
+        } else {
+            reader.read_to_end(&mut bytes)?;
+            on_body(&bytes);
+            keep_alive = false;
+        }
+        drop(reader);
+
+        if keep_alive {
+            let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+            if idle.len() < MAX_IDLE {
+                if let Ok(stream) = stream.try_clone() {
+                    idle.push(stream);
+                }
+            }
+        }
+        Ok(OllamaResponse {
+            status,
+            body: String::from_utf8_lossy(&bytes).into_owned(),
+        })
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+static SHARED: OnceLock<Result<OllamaClient, String>> = OnceLock::new();
+
+/// The client every plugin shares, configured from the environment
+pub fn shared() -> Result<&'static OllamaClient, String> {
+    SHARED
+        .get_or_init(|| OllamaClient::new(OllamaConfig::from_env()))
+        .as_ref()
+        .map_err(|e| e.clone())
+}
+
+/// Handed to plugins that export `plugin_host_services`
+pub static HOST_SERVICES: HostServices = HostServices {
+    ollama_request: host_ollama_request,
+    free_response: host_free_response,
+};
+
+unsafe extern "C" fn host_ollama_request(
+    method: *const c_char,
+    path: *const c_char,
+    body: *const PluginInput,
+) -> HostResponse {
+    if method.is_null() || path.is_null() {
+        return host_response(0, "missing method or path".to_string());
+    }
+    let method = CStr::from_ptr(method).to_string_lossy();
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let body = if body.is_null() {
+        None
+    } else {
+        Some((*body).to_string_lossy())
+    };
+    let (status, body) = plugin_request(&method, &path, body.as_deref());
+    host_response(status, body)
+}
+
+/// Serve a plugin's Ollama API request: status and body, or status 0 and
+/// why it failed. Shared by native and WebAssembly plugins.
+pub(crate) fn plugin_request(method: &str, path: &str, body: Option<&str>) -> (u32, String) {
+    // A streamed reply is forwarded as it arrives and returned whole (see `streaming`)
+    let streamed = body
+        .and_then(|body| serde_json::from_str::<serde_json::Value>(body).ok())
+        .is_some_and(|body| body["stream"] == true);
+    let mut lines = crate::streaming::OllamaLines::default();
+    let mut on_body = |bytes: &[u8]| {
+        if streamed {
+            lines.push(bytes)
+        }
+    };
+    let started = Instant::now();
+    // The step being run may target another server (see `backends`)
+    let result =
+        crate::backends::request(method, path, body.filter(|b| !b.is_empty()), &mut on_body);
+    log::debug!(
+        "Ollama {} {} took {} ms",
+        method,
+        path,
+        started.elapsed().as_millis()
+    );
+    match result {
+        Ok(response) if streamed && response.is_success() => {
+            let body = lao_plugin_api::merge_ollama_stream(&response.body)
+                .map_or(response.body, |merged| merged.to_string());
+            (response.status.into(), body)
+        }
+        Ok(response) => (response.status.into(), response.body),
+        Err(e) => (0, e),
+    }
+}
+
+pub(crate) fn host_response(status: u32, body: String) -> HostResponse {
+    let bytes = body.into_bytes().into_boxed_slice();
+    let len = bytes.len();
+    HostResponse {
+        status,
+        body: Box::into_raw(bytes) as *mut c_char,
+        len,
+    }
+}
+
+pub(crate) unsafe extern "C" fn host_free_response(response: HostResponse) {
+    if !response.body.is_null() {
+        let bytes = std::ptr::slice_from_raw_parts_mut(response.body as *mut u8, response.len);
+        drop(Box::from_raw(bytes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_client_reuses_connections_and_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        // Answers 503 once, then a chunked and a sized reply, all on one connection
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            for reply in [
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 4\r\n\r\nbusy",
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\n{\"resp\r\n8\r\nonse\":1}\r\n0\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 20\r\n\r\n{\"version\":\"0.5.1\"}\n",
+            ] {
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line.trim_end().is_empty() {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+
+        let client = OllamaClient::new(OllamaConfig {
+            base_url: format!("http://{}/", addr),
+            backoff: Duration::from_millis(1),
+            ..OllamaConfig::default()
+        })
+        .unwrap();
+        let reply = client
+            .post_json("/api/generate", &serde_json::json!({"prompt": "hi"}))
+            .unwrap();
+        assert_eq!(reply["response"], 1);
+        assert_eq!(client.health().unwrap(), "0.5.1");
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_base_url_takes_the_default_port_and_no_https() {
+        assert_eq!(
+            OllamaClient::new(OllamaConfig {
+                base_url: "127.0.0.1".to_string(),
+                ..OllamaConfig::default()
+            })
+            .unwrap()
+            .port,
+            DEFAULT_PORT
+        );
+        assert!(OllamaClient::new(OllamaConfig {
+            base_url: "https://ollama.example.com".to_string(),
+            ..OllamaConfig::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,402 @@
+// Embedding LAO as a workflow engine.
+use crate::cancel::{self, CancelToken};
+use crate::channel::{self, Overflow, Receiver, Sender};
+use crate::hooks::{self, ExecutionHook, RunContext, RunEnd, StepContext, StepFinish};
+use crate::node::NodeStatus;
+use crate::plan::ExecutionPlan;
+use crate::plugins::{self, PluginRegistry};
+use crate::run_labels;
+use crate::{StepLog, Workflow};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Once};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Events a background run buffers for a handle that isn't reading them
+const EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OrchestratorError {
+    /// The workflow file can't be read or parsed
+    Load(String),
+    /// The workflow parsed but its plan has errors; one message per error
+    Invalid(Vec<String>),
+    /// The run could not start or stopped on an internal failure. Failed
+    /// steps don't cause this; they are reported in the run's step logs.
+    Run(String),
+    Cancelled,
+}
+
+impl fmt::Display for OrchestratorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Load(e) => write!(f, "cannot load workflow: {}", e),
+            Self::Invalid(errors) => write!(f, "invalid workflow: {}", errors.join("; ")),
+            Self::Run(e) => write!(f, "run failed: {}", e),
+            Self::Cancelled => write!(f, "run cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for OrchestratorError {}
+
+/// A finished run
+#[derive(Debug, Serialize)]
+pub struct RunOutcome {
+    pub run_id: String,
+    /// "success", or "error" if any step failed
+    pub status: String,
+    pub steps: Vec<StepLog>,
+}
+
+/// Progress of a run started with `Orchestrator::start`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RunEvent {
+    RunStarted {
+        run_id: String,
+        workflow: String,
+    },
+    StepStarted {
+        step_id: String,
+        plugin: String,
+    },
+    StepFinished {
+        step_id: String,
+        plugin: String,
+        status: NodeStatus,
+        attempt: u32,
+        output: Option<String>,
+        error: Option<String>,
+        #[serde(rename = "duration_ms", serialize_with = "millis")]
+        duration: Duration,
+    },
+    RunFinished {
+        status: String,
+        #[serde(rename = "duration_ms", serialize_with = "millis")]
+        duration: Duration,
+    },
+}
+
+// Durations go out as whole milliseconds, like in log events
+fn millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+#[derive(Clone)]
+pub struct Orchestrator {
+    // None: the process-wide registry, as it is when each run starts
+    registry: Option<Arc<PluginRegistry>>,
+}
+
+impl Default for Orchestrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Orchestrator {
+    /// Runs use the process-wide plugin registry
+    pub fn new() -> Self {
+        Self { registry: None }
+    }
+
+    /// Runs use `registry` only, e.g. plugins the embedding application
+    /// registered itself
+    pub fn with_registry(registry: PluginRegistry) -> Self {
+        Self {
+            registry: Some(Arc::new(registry)),
+        }
+    }
+
+    /// The plugins runs started now would use
+    pub fn registry(&self) -> Arc<PluginRegistry> {
+        self.registry
+            .clone()
+            .unwrap_or_else(|| plugins::shared().snapshot())
+    }
+
+    /// Parse the workflow at `path` and check it as a run would
+    pub fn load(&self, path: &str) -> Result<Workflow, OrchestratorError> {
+        let plan = ExecutionPlan::load(path, &self.registry()).map_err(OrchestratorError::Load)?;
+        if !plan.is_valid() {
+            return Err(OrchestratorError::Invalid(
+                plan.errors.iter().map(|e| e.to_string()).collect(),
+            ));
+        }
+        Ok(plan.workflow)
+    }
+
+    /// Run the workflow at `path` to the end on this thread. `params` fill
+    /// `${name}` references like `lao run` inputs do.
+    pub fn run(
+        &self,
+        path: &str,
+        params: HashMap<String, String>,
+    ) -> Result<RunOutcome, OrchestratorError> {
+        self.run_scoped(path, params, CancelToken::new(), None)
+    }
+
+    /// Run the workflow at `path` on a thread of its own, with the calling
+    /// thread's run labels
+    pub fn start(&self, path: &str, params: HashMap<String, String>) -> RunHandle {
+        let (sender, events) = channel::bounded(EVENT_CAPACITY, Overflow::DropOldest);
+        let cancel = CancelToken::new();
+        let orchestrator = self.clone();
+        let path = path.to_string();
+        let token = cancel.clone();
+        let labels = run_labels::current();
+        let thread = std::thread::spawn(move || {
+            run_labels::with_labels(labels, || {
+                orchestrator.run_scoped(&path, params, token, Some(sender))
+            })
+        });
+        RunHandle {
+            events,
+            cancel,
+            thread,
+        }
+    }
+
+    fn run_scoped(
+        &self,
+        path: &str,
+        params: HashMap<String, String>,
+        token: CancelToken,
+        events: Option<Sender<RunEvent>>,
+    ) -> Result<RunOutcome, OrchestratorError> {
+        // Load errors are told apart from run errors up front
+        self.load(path)?;
+        install_forwarder();
+        let scope = RunScope {
+            run_id: None,
+            events,
+        };
+        let previous = SCOPE.with(|s| s.replace(Some(scope)));
+        let result = cancel::with_token(token, || {
+            run_labels::with_trigger("embedded", || {
+                crate::run_workflow_with_registry(path, params, &self.registry())
+            })
+        });
+        let scope = SCOPE.with(|s| s.replace(previous));
+        let run_id = scope.and_then(|s| s.run_id).unwrap_or_default();
+        match result {
+            Ok(steps) => Ok(RunOutcome {
+                run_id,
+                status: crate::run_status(&steps).to_string(),
+                steps,
+            }),
+            Err(e) if e == cancel::CANCELLED => Err(OrchestratorError::Cancelled),
+            Err(e) => Err(OrchestratorError::Run(e)),
+        }
+    }
+}
+
+/// A run started with `Orchestrator::start`
+pub struct RunHandle {
+    events: Receiver<RunEvent>,
+    cancel: CancelToken,
+    thread: JoinHandle<Result<RunOutcome, OrchestratorError>>,
+}
+
+impl RunHandle {
+    /// The run's events, ending when the run does
+    pub fn events(&self) -> &Receiver<RunEvent> {
+        &self.events
+    }
+
+    /// Start no further steps. Unless every step had started already,
+    /// `wait` then returns `Cancelled`.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Cancels the run like `cancel`, for code that doesn't hold the handle
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    pub fn wait(self) -> Result<RunOutcome, OrchestratorError> {
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(OrchestratorError::Run("run thread panicked".to_string())))
+    }
+}
+
+// The facade's run on this thread: where its events go
+struct RunScope {
+    run_id: Option<String>,
+    events: Option<Sender<RunEvent>>,
+}
+
+thread_local! {
+    static SCOPE: RefCell<Option<RunScope>> = const { RefCell::new(None) };
+}
+
+// Hooks run on the executor's thread, so the scope tells which run an event belongs to
+struct EventForwarder;
+
+impl EventForwarder {
+    fn send(event: RunEvent) {
+        SCOPE.with(|scope| {
+            if let Some(events) = scope.borrow().as_ref().and_then(|s| s.events.as_ref()) {
+                let _ = events.send(event);
+            }
+        });
+    }
+}
+
+impl ExecutionHook for EventForwarder {
+    fn on_run_start(&self, run: &RunContext) {
+        SCOPE.with(|scope| {
+            if let Some(scope) = scope.borrow_mut().as_mut() {
+                scope.run_id = Some(run.run_id.to_string());
+            }
+        });
+        Self::send(RunEvent::RunStarted {
+            run_id: run.run_id.to_string(),
+            workflow: run.workflow.workflow.clone(),
+        });
+    }
+
+    fn on_step_start(&self, _run: &RunContext, step: &StepContext) {
+        Self::send(RunEvent::StepStarted {
+            step_id: step.step_id.to_string(),
+            plugin: step.step.run.clone(),
+        });
+    }
+
+    fn on_step_finish(&self, _run: &RunContext, step: &StepContext, finish: &StepFinish) {
+        Self::send(RunEvent::StepFinished {
+            step_id: step.step_id.to_string(),
+            plugin: step.step.run.clone(),
+            status: finish.status,
+            attempt: finish.log.attempt,
+            output: finish.log.output.clone(),
+            error: finish.log.error.clone(),
+            duration: finish.duration,
+        });
+    }
+
+    fn on_run_end(&self, _run: &RunContext, end: &RunEnd) {
+        Self::send(RunEvent::RunFinished {
+            status: end.status.to_string(),
+            duration: end.duration,
+        });
+    }
+}
+
+fn install_forwarder() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| hooks::register(EventForwarder));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_plugin, MockSpec};
+
+    /// A temp dir with a three-step workflow whose middle step is SlowPlugin.
+    fn embedded() -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("lao_embed_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("embedded.yaml");
+        std::fs::write(
+            &path,
+            "workflow: embedded\nsteps:\n  - run: EchoPlugin\n    input: \"${topic}\"\n  - run: SlowPlugin\n    input_from: step1\n  - run: EchoPlugin\n    input_from: step2\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap().to_string();
+        (dir, path)
+    }
+
+    fn mocks() -> PluginRegistry {
+        let mut registry = PluginRegistry::new();
+        registry.register_plugin(mock_plugin("EchoPlugin", MockSpec::default()));
+        registry.register_plugin(mock_plugin("SlowPlugin", MockSpec::default()));
+        registry
+    }
+
+    #[test]
+    fn test_missing_workflow_is_a_load_error() {
+        let orchestrator = Orchestrator::with_registry(mocks());
+        let missing = std::env::temp_dir().join(format!("lao_embed_{}.yaml", uuid::Uuid::new_v4()));
+        let missing = orchestrator.load(missing.to_str().unwrap());
+        assert!(matches!(missing, Err(OrchestratorError::Load(_))));
+    }
+
+    #[test]
+    fn test_embedded_runs_report_events_and_cancel() {
+        let (dir, path) = embedded();
+        let mut registry = PluginRegistry::new();
+        registry.register_plugin(mock_plugin("EchoPlugin", MockSpec::default()));
+        let gate = Arc::new(std::sync::Barrier::new(2));
+        let slow_gate = gate.clone();
+        registry.register_plugin(crate::plugins::PluginInstance::native(
+            mock_plugin("SlowPlugin", MockSpec::default()).info,
+            move |input| {
+                slow_gate.wait();
+                slow_gate.wait();
+                Ok(format!("slow({})", input))
+            },
+        ));
+        let orchestrator = Orchestrator::with_registry(registry);
+
+        let handle = orchestrator.start(
+            &path,
+            HashMap::from([("topic".to_string(), "rust".to_string())]),
+        );
+        // Cancel while step2 runs: it finishes, step3 never starts
+        gate.wait();
+        handle.cancel();
+        gate.wait();
+        let events: Vec<RunEvent> = std::iter::from_fn(|| handle.events().recv().ok()).collect();
+        assert!(matches!(handle.wait(), Err(OrchestratorError::Cancelled)));
+        assert!(
+            matches!(&events[0], RunEvent::RunStarted { workflow, .. } if workflow == "embedded")
+        );
+        let finished: Vec<(&str, Option<&str>)> = events
+            .iter()
+            .filter_map(|e| match e {
+                RunEvent::StepFinished {
+                    step_id, output, ..
+                } => Some((step_id.as_str(), output.as_deref())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            finished,
+            [
+                ("step1", Some("EchoPlugin(rust)")),
+                ("step2", Some("slow(EchoPlugin(rust))"))
+            ]
+        );
+        assert!(
+            matches!(events.last(), Some(RunEvent::RunFinished { status, .. }) if status == "cancelled")
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_run_returns_the_outcome() {
+        let (dir, path) = embedded();
+        let outcome = Orchestrator::with_registry(mocks())
+            .run(
+                &path,
+                HashMap::from([("topic".to_string(), "go".to_string())]),
+            )
+            .unwrap();
+        assert_eq!(outcome.status, "success");
+        assert_eq!(outcome.steps.len(), 3);
+        assert!(!outcome.run_id.is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,141 @@
+// Workflow parameters.
+// Declaring and passing them: docs/workflows.md (Parameters).
+use regex::{Captures, Regex};
+use serde::{de::DeserializeOwned, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+thread_local! {
+    static VALUES: RefCell<Option<HashMap<String, String>>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with workflows loaded on this thread taking params from `values`
+pub fn with_values<T>(values: &HashMap<String, String>, f: impl FnOnce() -> T) -> T {
+    let previous = VALUES.with(|v| v.replace(Some(values.clone())));
+    let result = f();
+    VALUES.with(|v| *v.borrow_mut() = previous);
+    result
+}
+
+/// The run's inputs with the defaults of declared params it didn't set
+pub fn inputs(
+    declared: Option<&BTreeMap<String, serde_yaml::Value>>,
+    mut values: HashMap<String, String>,
+) -> HashMap<String, String> {
+    for (name, default) in declared.into_iter().flatten() {
+        if let Some(default) = text(default) {
+            values.entry(name.clone()).or_insert(default);
+        }
+    }
+    values
+}
+
+// A default as text; `~` has none
+fn text(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::Null => None,
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        other => serde_yaml::to_string(other)
+            .ok()
+            .map(|s| s.trim_end().to_string()),
+    }
+}
+
+/// Replace `${{ params.NAME }}` in every string of `value`, a workflow with
+/// the params `declared`, when a run is loading it
+pub fn expand<T: Serialize + DeserializeOwned>(
+    value: T,
+    declared: Option<&BTreeMap<String, serde_yaml::Value>>,
+) -> Result<T, String> {
+    let Some(values) = VALUES.with(|v| v.borrow().clone()) else {
+        return Ok(value);
+    };
+    if let Some((name, _)) = values.iter().find(|(_, v)| v.contains("${{")) {
+        return Err(format!(
+            "param {} can't contain '${{{{'; params are plain text",
+            name
+        ));
+    }
+    let values = inputs(declared, values);
+    let mut tree = serde_yaml::to_value(&value).map_err(|e| e.to_string())?;
+    if !expand_tree(&mut tree, &values)? {
+        return Ok(value);
+    }
+    serde_yaml::from_value(tree).map_err(|e| e.to_string())
+}
+
+// True when anything was replaced
+fn expand_tree(
+    value: &mut serde_yaml::Value,
+    values: &HashMap<String, String>,
+) -> Result<bool, String> {
+    use serde_yaml::Value;
+    match value {
+        Value::String(text) if text.contains("${{") => {
+            let mut error = None;
+            let expanded = reference().replace_all(text, |caps: &Captures| {
+                values.get(&caps[1]).cloned().unwrap_or_else(|| {
+                    error.get_or_insert(format!(
+                        "params.{0} has no value; pass --param {0}=VALUE or give it a default under params:",
+                        &caps[1]
+                    ));
+                    String::new()
+                })
+            });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            let changed = expanded != *text;
+            *text = expanded.into_owned();
+            Ok(changed)
+        }
+        Value::Sequence(items) => items.iter_mut().try_fold(false, |changed, item| {
+            Ok(expand_tree(item, values)? || changed)
+        }),
+        Value::Mapping(mapping) => mapping.iter_mut().try_fold(false, |changed, (_, item)| {
+            Ok(expand_tree(item, values)? || changed)
+        }),
+        Value::Tagged(tagged) => expand_tree(&mut tagged.value, values),
+        _ => Ok(false),
+    }
+}
+
+fn reference() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\$\{\{\s*params\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Workflow;
+
+    #[test]
+    fn test_params_fill_runs_only() {
+        let workflow: Workflow = serde_yaml::from_str(
+            "workflow: notes\nparams:\n  model: llama3\n  retries: 2\n  date: ~\nsteps:\n  - run: SummarizerPlugin\n    model: \"${{ params.model }}\"\n    input: \"notes/${{ params.date }}.md\"\n    retries_left: \"${{ params.retries }}\"\n",
+        )
+        .unwrap();
+        let step = |w: &Workflow, key: &str| w.steps[0].params[key].as_str().unwrap().to_string();
+        let declared = workflow.params.clone();
+
+        // Outside a run, references stay for the editor to save
+        let loaded = expand(workflow.clone(), declared.as_ref()).unwrap();
+        assert_eq!(step(&loaded, "model"), "${{ params.model }}");
+
+        let values = HashMap::from([("date".to_string(), "2026-10-17".to_string())]);
+        let run = with_values(&values, || expand(workflow.clone(), declared.as_ref())).unwrap();
+        assert_eq!(step(&run, "model"), "llama3");
+        assert_eq!(step(&run, "input"), "notes/2026-10-17.md");
+        assert_eq!(step(&run, "retries_left"), "2");
+        assert_eq!(inputs(declared.as_ref(), values)["model"], "llama3");
+
+        let missing = with_values(&HashMap::new(), || {
+            expand(workflow.clone(), declared.as_ref())
+        });
+        assert!(missing.unwrap_err().contains("--param date=VALUE"));
+        let sneaky = HashMap::from([("date".to_string(), "${{ secrets.KEY }}".to_string())]);
+        assert!(with_values(&sneaky, || expand(workflow.clone(), declared.as_ref())).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+// Batch runs pipelined over per-resource lanes.
+// `lao batch` and its lanes are described in docs/workflows.md (Batch Runs).
+use crate::plugins::PluginRegistry;
+use crate::{run_class, run_labels};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Plugins with this tag run on the GPU unless a step says otherwise
+pub const GPU_TAG: &str = "llm";
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+impl Resource {
+    pub const ALL: [Resource; 2] = [Resource::Cpu, Resource::Gpu];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Cpu => "cpu",
+            Resource::Gpu => "gpu",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The resource of a step running `tags`' plugin, without `resource:`
+    pub fn of_plugin(tags: &[String]) -> Self {
+        if tags.iter().any(|t| t == GPU_TAG) {
+            Resource::Gpu
+        } else {
+            Resource::Cpu
+        }
+    }
+}
+
+/// Time a lane was held by a step of a batch item
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LaneSpan {
+    /// Index of the batch item
+    pub item: usize,
+    pub step_id: String,
+    pub resource: Resource,
+    /// Since the batch started
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+#[derive(Default)]
+struct LaneState {
+    busy: [usize; 2],
+    // Item, step and resource of each lane held
+    holders: Vec<(usize, String, Resource)>,
+    spans: Vec<LaneSpan>,
+}
+
+/// The lanes of one batch
+pub struct Lanes {
+    slots: [usize; 2],
+    started: Instant,
+    state: Mutex<LaneState>,
+    changed: Condvar,
+}
+
+impl Lanes {
+    /// `slots` steps at once per resource, indexed like `Resource::ALL`
+    pub fn new(slots: [usize; 2]) -> Self {
+        Self {
+            slots: slots.map(|s| s.max(1)),
+            started: Instant::now(),
+            state: Mutex::new(LaneState::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// One lane per resource, overridden by LAO_CPU_LANES and LAO_GPU_LANES
+    pub fn from_env() -> Self {
+        let slots = [("LAO_CPU_LANES", 1), ("LAO_GPU_LANES", 1)].map(|(key, default)| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(default)
+        });
+        Self::new(slots)
+    }
+
+    pub fn slots(&self) -> [usize; 2] {
+        self.slots
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LaneState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn elapsed_ms(&self, at: Instant) -> u64 {
+        at.duration_since(self.started).as_millis() as u64
+    }
+
+    /// Spans recorded so far, in the order they ended
+    pub fn spans(&self) -> Vec<LaneSpan> {
+        self.lock().spans.clone()
+    }
+
+    /// The step of `item` holding a lane now, and its resource
+    pub fn holder(&self, item: usize) -> Option<(String, Resource)> {
+        self.lock()
+            .holders
+            .iter()
+            .find(|(i, _, _)| *i == item)
+            .map(|(_, step_id, resource)| (step_id.clone(), *resource))
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<(Arc<Lanes>, usize)>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the runs on this thread as batch item `item` of `lanes`
+pub fn with_lanes<T>(lanes: Arc<Lanes>, item: usize, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|c| c.replace(Some((lanes, item))));
+    let result = f();
+    CURRENT.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+/// Wait for a lane of `resource` when this thread runs a batch item; held
+/// until the guard drops
+pub fn acquire(resource: Resource, step_id: &str) -> Option<LaneGuard> {
+    let (lanes, item) = CURRENT.with(|c| c.borrow().clone())?;
+    let mut state = lanes.lock();
+    while state.busy[resource.index()] >= lanes.slots[resource.index()] {
+        state = lanes.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+    }
+    state.busy[resource.index()] += 1;
+    state.holders.push((item, step_id.to_string(), resource));
+    drop(state);
+    Some(LaneGuard {
+        lanes,
+        item,
+        step_id: step_id.to_string(),
+        resource,
+        start: Instant::now(),
+    })
+}
+
+pub struct LaneGuard {
+    lanes: Arc<Lanes>,
+    item: usize,
+    step_id: String,
+    resource: Resource,
+    start: Instant,
+}
+
+impl Drop for LaneGuard {
+    fn drop(&mut self) {
+        let span = LaneSpan {
+            item: self.item,
+            step_id: std::mem::take(&mut self.step_id),
+            resource: self.resource,
+            start_ms: self.lanes.elapsed_ms(self.start),
+            end_ms: self.lanes.elapsed_ms(Instant::now()),
+        };
+        let mut state = self.lanes.lock();
+        state.busy[self.resource.index()] -= 1;
+        if let Some(at) = state.holders.iter().position(|(item, step_id, resource)| {
+            (*item, step_id.as_str(), *resource)
+                == (span.item, span.step_id.as_str(), span.resource)
+        }) {
+            state.holders.remove(at);
+        }
+        state.spans.push(span);
+        drop(state);
+        self.lanes.changed.notify_all();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchSpec {
+    pub path: String,
+    /// One run per input
+    pub inputs: Vec<String>,
+    /// Run input the item is given as, `file` for `${file}`
+    pub input_name: String,
+    /// Runs in flight at once; the number of lanes when 0
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchItem {
+    pub input: String,
+    /// `success` or `error`
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub start_ms: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+    pub spans: Vec<LaneSpan>,
+    pub wall_ms: u64,
+    /// Time each resource had a step running
+    pub busy_ms: BTreeMap<Resource, u64>,
+    /// Time more than one resource had a step running
+    pub overlap_ms: u64,
+}
+
+impl BatchReport {
+    fn new(items: Vec<BatchItem>, mut spans: Vec<LaneSpan>, wall: Duration) -> Self {
+        spans.sort_by_key(|s| (s.start_ms, s.item));
+        let busy: BTreeMap<Resource, Vec<(u64, u64)>> = Resource::ALL
+            .iter()
+            .map(|&resource| {
+                let intervals = spans
+                    .iter()
+                    .filter(|s| s.resource == resource)
+                    .map(|s| (s.start_ms, s.end_ms))
+                    .collect();
+                (resource, merge(intervals))
+            })
+            .collect();
+        let total = |intervals: &[(u64, u64)]| intervals.iter().map(|(s, e)| e - s).sum();
+        let overlap = intersect(&busy[&Resource::Cpu], &busy[&Resource::Gpu]);
+        Self {
+            items,
+            spans,
+            wall_ms: wall.as_millis() as u64,
+            busy_ms: busy.iter().map(|(r, i)| (*r, total(i))).collect(),
+            overlap_ms: total(&overlap),
+        }
+    }
+
+    /// Share of the wall time more than one resource was busy
+    pub fn overlap_ratio(&self) -> f64 {
+        self.overlap_ms as f64 / self.wall_ms.max(1) as f64
+    }
+}
+
+// Sorted, non-overlapping union of `intervals`
+fn merge(mut intervals: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::new();
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// Intersection of two merged interval lists
+fn intersect(a: &[(u64, u64)], b: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let (mut i, mut j) = (0, 0);
+    let mut both = Vec::new();
+    while i < a.len() && j < b.len() {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if start < end {
+            both.push((start, end));
+        }
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    both
+}
+
+/// Run the batch against `registry`; `on_item` is called as each run ends
+pub fn run_batch(
+    spec: &BatchSpec,
+    registry: &PluginRegistry,
+    on_item: &(dyn Fn(usize, &BatchItem) + Sync),
+) -> Result<BatchReport, String> {
+    if spec.inputs.is_empty() {
+        return Err("A batch needs at least one input".to_string());
+    }
+    // Load errors are reported once instead of once per item
+    crate::load_workflow_yaml(&spec.path)?;
+    let lanes = Arc::new(Lanes::from_env());
+    let depth = match spec.depth {
+        0 => lanes.slots().iter().sum(),
+        depth => depth,
+    }
+    .min(spec.inputs.len());
+    // Workers run their items with the caller's class and labels
+    let class = run_class::current();
+    let labels = run_labels::current().with_tag("batch");
+    let next = AtomicUsize::new(0);
+    let items: Mutex<Vec<Option<BatchItem>>> = Mutex::new(vec![None; spec.inputs.len()]);
+
+    std::thread::scope(|scope| {
+        for _ in 0..depth {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(input) = spec.inputs.get(index) else {
+                    break;
+                };
+                let start = Instant::now();
+                let inputs = HashMap::from([(spec.input_name.clone(), input.clone())]);
+                let result = run_class::with_class(class, || {
+                    run_labels::with_labels(labels.clone(), || {
+                        with_lanes(lanes.clone(), index, || {
+                            crate::run_workflow_with_registry(&spec.path, inputs, registry)
+                        })
+                    })
+                });
+                let error = match result {
+                    Ok(logs) => logs
+                        .into_iter()
+                        .find_map(|log| log.error.map(|e| format!("{}: {}", log.step_id, e))),
+                    Err(e) => Some(e),
+                };
+                let item = BatchItem {
+                    input: input.clone(),
+                    status: if error.is_some() { "error" } else { "success" }.to_string(),
+                    error,
+                    start_ms: lanes.elapsed_ms(start),
+                    duration_ms: start.elapsed().as_millis() as u64,
+                };
+                on_item(index, &item);
+                items.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(item);
+            });
+        }
+    });
+
+    let items = items
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(BatchReport::new(
+        items,
+        lanes.spans(),
+        lanes.started.elapsed(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::PluginInstance;
+    use lao_plugin_api::PluginInfo;
+
+    fn slow_plugin(name: &str, tags: &[&str]) -> PluginInstance {
+        let info = PluginInfo {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: String::new(),
+            author: String::new(),
+            dependencies: Vec::new(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            capabilities: Vec::new(),
+            input_schema: None,
+            output_schema: None,
+        };
+        let name = name.to_string();
+        PluginInstance::native(info, move |input| {
+            std::thread::sleep(Duration::from_millis(60));
+            Ok(format!("{}({})", name, input))
+        })
+    }
+
+    #[test]
+    fn test_merge_and_intersect_spans() {
+        assert_eq!(merge(vec![(5, 9), (0, 3), (2, 4)]), [(0, 4), (5, 9)]);
+        assert_eq!(intersect(&[(0, 4), (5, 9)], &[(3, 6)]), [(3, 4), (5, 6)]);
+    }
+
+    #[test]
+    fn test_batch_overlaps_cpu_and_gpu_stages() {
+        let dir = std::env::temp_dir().join(format!("lao_pipeline_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("batch.yaml");
+        std::fs::write(
+            &path,
+            "workflow: batch\nsteps:\n  - run: Transcribe\n    input: ${file}\n  - run: Summarize\n    input_from: step1\n",
+        )
+        .unwrap();
+        let mut registry = PluginRegistry::new();
+        registry.register_plugin(slow_plugin("Transcribe", &["audio"]));
+        registry.register_plugin(slow_plugin("Summarize", &[GPU_TAG]));
+
+        let spec = BatchSpec {
+            path: path.to_string_lossy().into_owned(),
+            inputs: (1..=3).map(|i| format!("{}.wav", i)).collect(),
+            input_name: "file".to_string(),
+            depth: 0,
+        };
+        let finished = AtomicUsize::new(0);
+        let report = run_batch(&spec, &registry, &|_, _| {
+            finished.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+        assert_eq!(finished.load(Ordering::SeqCst), 3);
+        assert!(report.items.iter().all(|item| item.status == "success"));
+        assert_eq!(report.spans.len(), 6);
+        // One lane per resource: a resource never runs two steps at once
+        for resource in Resource::ALL {
+            let spans: Vec<&LaneSpan> = report
+                .spans
+                .iter()
+                .filter(|s| s.resource == resource)
+                .collect();
+            assert_eq!(spans.len(), 3);
+            assert!(spans.windows(2).all(|w| w[1].start_ms >= w[0].end_ms));
+        }
+        // A later file transcribes while an earlier one summarizes
+        assert!(report.overlap_ms > 0);
+        assert!(report.overlap_ratio() > 0.0);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
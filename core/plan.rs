@@ -0,0 +1,611 @@
+// Compiled form of a workflow.
+use crate::assertions::{self, AssertAction};
+use crate::backends;
+use crate::branching;
+use crate::cross_platform::PathUtils;
+use crate::foreach;
+use crate::input_rules::{self, InputRule};
+use crate::map_reduce;
+use crate::memo::DETERMINISTIC_TAG;
+use crate::node::NodeId;
+use crate::pipeline::Resource;
+use crate::plugin_policy;
+use crate::plugins::PluginRegistry;
+use crate::presets;
+use crate::retry;
+use crate::secrets;
+use crate::sinks;
+use crate::tiers;
+use crate::{
+    build_dag, build_plugin_input, compute_default_cache_key, load_workflow_yaml, primary_io_types,
+    substitute_params, topo_sort, validate_workflow_types, Workflow, WorkflowStep,
+};
+use lao_plugin_api::{PluginInputType, PluginOutputType};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanIssue {
+    /// Index into `ExecutionPlan::steps`, if the issue belongs to a step
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for PlanIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            Some(i) => write!(f, "step{}: {}", i + 1, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PlannedInput {
+    /// Rendered at compile time; nothing in the params depends on the run
+    Constant(String),
+    /// Filled from step outputs and run inputs when the step runs
+    Template,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedStep {
+    pub index: usize,
+    pub id: NodeId,
+    pub step: WorkflowStep,
+    /// Ids named by `input_from` and `depends_on`, unknown ones included
+    pub parents: Vec<NodeId>,
+    /// Primary IO types of the step's plugin; `None` if it isn't registered
+    pub io_types: Option<(PluginInputType, PluginOutputType)>,
+    pub input: PlannedInput,
+    /// Cache key from the YAML, or one derived from plugin version and params
+    pub cache_key: String,
+    /// An output was already cached under `cache_key` at compile time
+    pub cache_hit: bool,
+    /// Outputs are memoized by content across workflows (see `memo`)
+    pub memoize: bool,
+    /// Lane the step waits for in a batch (see `pipeline`)
+    pub resource: Resource,
+    /// Parallel group; 0 for steps without known parents
+    pub group: usize,
+}
+
+impl PlannedStep {
+    /// The step's params and plugin input for this run
+    pub fn render(&self, outputs: &HashMap<String, String>) -> (serde_yaml::Value, String) {
+        if let PlannedInput::Constant(input) = &self.input {
+            return (self.step.params.clone(), input.clone());
+        }
+        let mut params = self.step.params.clone();
+        // Secrets first, so outputs put in below can't ask for one
+        secrets::reveal(&mut params, self.id.as_str());
+        // input_from replaces the `input` param with the referenced output
+        if let Some(output) = self
+            .step
+            .input_from
+            .as_ref()
+            .and_then(|from| outputs.get(from))
+        {
+            if !params.is_mapping() {
+                params = serde_yaml::Value::Mapping(serde_yaml::Mapping::new());
+            }
+            if let Some(mapping) = params.as_mapping_mut() {
+                mapping.insert(
+                    serde_yaml::Value::String("input".to_string()),
+                    serde_yaml::Value::String(output.clone()),
+                );
+            }
+        }
+        substitute_params(&mut params, outputs);
+        normalize_path_params(&mut params, self.takes_file());
+        let mut input = build_plugin_input(&params);
+        presets::apply_template(self.step.template.as_deref(), &mut params, &mut input);
+        (params, input)
+    }
+
+    /// The plugin reads its input from a file path
+    pub fn takes_file(&self) -> bool {
+        matches!(
+            self.io_types,
+            Some((
+                PluginInputType::File
+                    | PluginInputType::Audio
+                    | PluginInputType::Image
+                    | PluginInputType::Video,
+                _
+            ))
+        )
+    }
+
+    pub fn cache_path(&self) -> String {
+        format!("{}/{}.json", cache_dir(), self.cache_key)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan {
+    pub workflow: Workflow,
+    /// In declaration order; `steps[i].id` is `step{i+1}` unless the step
+    /// has an `id`
+    pub steps: Vec<PlannedStep>,
+    /// Indices into `steps` in execution order; empty if there is a cycle
+    pub order: Vec<usize>,
+    /// Indices into `steps` by parallel group
+    pub groups: Vec<Vec<usize>>,
+    /// Problems that stop the plan from running
+    pub errors: Vec<PlanIssue>,
+    pub warnings: Vec<PlanIssue>,
+}
+
+impl ExecutionPlan {
+    pub fn load(path: &str, registry: &PluginRegistry) -> Result<Self, String> {
+        Ok(Self::compile(load_workflow_yaml(path)?, registry))
+    }
+
+    pub fn compile(mut workflow: Workflow, registry: &PluginRegistry) -> Self {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let tier_map = backends::registry().map(|registry| &registry.tiers);
+        for (step, message) in tiers::resolve_workflow(&mut workflow, tier_map) {
+            errors.push(PlanIssue { step, message });
+        }
+        for message in workflow.guard.iter().flat_map(|g| g.problems()) {
+            errors.push(PlanIssue {
+                step: None,
+                message,
+            });
+        }
+        let dag = build_dag(&workflow.steps).unwrap_or_default();
+        let index_of: HashMap<&str, usize> = dag
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.as_str(), i))
+            .collect();
+        for (i, node) in dag.iter().enumerate() {
+            let message = if node.id.is_empty()
+                || !node
+                    .id
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+            {
+                format!("id '{}' must be letters, digits, '_' and '-' only", node.id)
+            } else if dag.iter().filter(|other| other.id == node.id).count() > 1 {
+                format!("id '{}' is used by another step", node.id)
+            } else {
+                continue;
+            };
+            errors.push(PlanIssue {
+                step: Some(i),
+                message,
+            });
+        }
+
+        // Disabled steps don't call their plugins, so their plugin problems
+        // don't stop the plan
+        let enabled = |i: &usize| dag[*i].step.is_enabled();
+        for (i, message) in validate_workflow_types(&dag, registry)
+            .into_iter()
+            .filter(|(i, _)| enabled(i))
+        {
+            errors.push(PlanIssue {
+                step: Some(i),
+                message,
+            });
+        }
+        for (i, node) in dag.iter().enumerate() {
+            if let Some(target) = &node.step.backend {
+                if let Err(message) =
+                    backends::registry().and_then(|registry| registry.resolve(target))
+                {
+                    errors.push(PlanIssue {
+                        step: Some(i),
+                        message,
+                    });
+                }
+            }
+            if let Some(mode) = node.step.disabled_mode() {
+                warnings.push(PlanIssue {
+                    step: Some(i),
+                    message: format!("disabled ({})", mode.as_str()),
+                });
+            }
+            for parent in node
+                .parents
+                .iter()
+                .filter(|p| !index_of.contains_key(p.as_str()))
+            {
+                warnings.push(PlanIssue {
+                    step: Some(i),
+                    message: format!("'{}' does not name a step", parent),
+                });
+            }
+        }
+        for model in &workflow.models {
+            if let Some(problem) = model.problem() {
+                errors.push(PlanIssue {
+                    step: None,
+                    message: problem,
+                });
+            }
+            for step in model
+                .steps
+                .iter()
+                .filter(|s| !index_of.contains_key(s.as_str()))
+            {
+                warnings.push(PlanIssue {
+                    step: None,
+                    message: format!(
+                        "model '{}' lists '{}', which does not name a step",
+                        model.label(),
+                        step
+                    ),
+                });
+            }
+        }
+        for plugin in workflow.missing_requirements(registry) {
+            warnings.push(PlanIssue {
+                step: None,
+                message: format!("requires {}, which is not installed", plugin),
+            });
+        }
+        if let Some(problem) = workflow.mqtt.as_ref().and_then(|trigger| trigger.problem()) {
+            errors.push(PlanIssue {
+                step: None,
+                message: problem,
+            });
+        }
+        for output in &workflow.outputs {
+            let message = output.problem().or_else(|| {
+                (!index_of.contains_key(output.step.as_str()))
+                    .then(|| format!("output '{}' does not name a step", output.step))
+            });
+            if let Some(message) = message {
+                errors.push(PlanIssue {
+                    step: None,
+                    message,
+                });
+            }
+        }
+        let order: Vec<usize> = match topo_sort(&dag) {
+            Ok(ids) => ids.iter().map(|id| index_of[id.as_str()]).collect(),
+            Err(e) => {
+                let step = dag.iter().position(|n| e.ends_with(&n.id));
+                errors.push(PlanIssue { step, message: e });
+                Vec::new()
+            }
+        };
+
+        // Parents come first in `order`, so their groups are known
+        let mut group_of = vec![0; dag.len()];
+        for &i in &order {
+            group_of[i] = dag[i]
+                .parents
+                .iter()
+                .filter_map(|p| index_of.get(p.as_str()))
+                .map(|&p| group_of[p] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for &i in &order {
+            if groups.len() <= group_of[i] {
+                groups.resize(group_of[i] + 1, Vec::new());
+            }
+            groups[group_of[i]].push(i);
+        }
+        for group in &mut groups {
+            group.sort_unstable();
+        }
+
+        let steps = dag
+            .into_iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let plugin = registry.get(&node.step.run);
+                let cache_key = node.step.cache_key.clone().unwrap_or_else(|| {
+                    let version = plugin.map(|p| p.info.version.as_str()).unwrap_or_default();
+                    compute_default_cache_key(&node.step, version)
+                });
+                let mut planned = PlannedStep {
+                    index,
+                    id: NodeId::new(&node.id),
+                    parents: node.parents.iter().map(NodeId::from).collect(),
+                    io_types: plugin.map(primary_io_types),
+                    input: PlannedInput::Template,
+                    cache_key,
+                    cache_hit: false,
+                    memoize: node.step.memoize.unwrap_or_else(|| {
+                        plugin.is_some_and(|p| p.info.tags.iter().any(|t| t == DETERMINISTIC_TAG))
+                    }),
+                    resource: node.step.resource.unwrap_or_else(|| {
+                        plugin.map_or(Resource::default(), |p| Resource::of_plugin(&p.info.tags))
+                    }),
+                    group: group_of[index],
+                    step: node.step,
+                };
+                planned.cache_hit = Path::new(&planned.cache_path()).is_file();
+                if planned.step.input_from.is_none() && !has_placeholders(&planned.step.params) {
+                    let takes_file = planned.takes_file();
+                    normalize_path_params(&mut planned.step.params, takes_file);
+                    let mut input = build_plugin_input(&planned.step.params);
+                    presets::apply_template(
+                        planned.step.template.as_deref(),
+                        &mut planned.step.params,
+                        &mut input,
+                    );
+                    planned.input = PlannedInput::Constant(input);
+                }
+                planned
+            })
+            .collect::<Vec<PlannedStep>>();
+
+        let policy = plugin_policy::global();
+        let pins = workflow.plugins.as_deref();
+        let position: HashMap<&str, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(at, &i)| (steps[i].id.as_str(), at))
+            .collect();
+        for step in steps.iter().filter(|s| s.step.is_enabled()) {
+            let mut problems: Vec<String> = step
+                .step
+                .validate
+                .iter()
+                .filter_map(InputRule::problem)
+                .chain(step.step.assert.iter().filter_map(|a| a.problem(registry)))
+                .chain(
+                    step.step
+                        .evaluate
+                        .iter()
+                        .filter_map(|e| e.problem(registry)),
+                )
+                .chain(map_reduce::problem(&step.step, registry))
+                .chain(foreach::problem(&step.step, registry))
+                .chain(retry::problem(&step.step))
+                .chain(secrets::problem(&step.step))
+                .chain(sinks::problem(&step.step))
+                .chain(if order.is_empty() {
+                    Vec::new()
+                } else {
+                    branching::problems(step.id.as_str(), &step.step, &position)
+                })
+                .chain(step.step.context.iter().filter_map(|c| c.problem()))
+                .chain(step.step.display.iter().filter_map(|d| d.problem()))
+                .chain(
+                    plugin_policy::step_plugins(&step.step)
+                        .into_iter()
+                        .filter_map(|name| policy.problem(name, registry, pins)),
+                )
+                .collect();
+            match (&step.step.fallback, assertions::action(&step.step)) {
+                (Some(fallback), _) if registry.get(fallback).is_none() => {
+                    problems.push(format!("fallback plugin '{}' not found", fallback));
+                }
+                (None, AssertAction::Fallback) => {
+                    problems.push(
+                        "on_assert_fail is fallback but no fallback plugin is set".to_string(),
+                    );
+                }
+                _ => {}
+            }
+            // Inputs known now are checked now, so dry runs catch them
+            let failed = match &step.input {
+                PlannedInput::Constant(input) if problems.is_empty() => {
+                    input_rules::check_step(&step.step.validate, &step.step.params, input).err()
+                }
+                _ => None,
+            };
+            for message in problems.into_iter().chain(failed) {
+                errors.push(PlanIssue {
+                    step: Some(step.index),
+                    message,
+                });
+            }
+        }
+
+        Self {
+            workflow,
+            steps,
+            order,
+            groups,
+            errors,
+            warnings,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Steps in execution order
+    pub fn in_order(&self) -> impl Iterator<Item = &PlannedStep> {
+        self.order.iter().map(|&i| &self.steps[i])
+    }
+
+    pub fn step(&self, id: &str) -> Option<&PlannedStep> {
+        self.steps.iter().find(|s| s.id == id)
+    }
+
+    /// All errors on one line, for messages that must be a single string
+    pub fn error_summary(&self) -> String {
+        self.errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+pub(crate) fn cache_dir() -> String {
+    std::env::var("LAO_CACHE_DIR")
+        .unwrap_or_else(|_| crate::data_dir::path("cache").to_string_lossy().to_string())
+}
+
+// `${name}` anywhere in a string param
+fn has_placeholders(params: &serde_yaml::Value) -> bool {
+    params.as_mapping().is_some_and(|mapping| {
+        mapping
+            .values()
+            .filter_map(|v| v.as_str())
+            .any(|s| s.contains("${"))
+    })
+}
+
+// Path params (`path`, `file`, `*_path`, `*_file`, `*_dir`), and the input of
+// plugins that take files, are written for whichever OS the workflow author
+// used; rewrite them for this one
+fn normalize_path_params(params: &mut serde_yaml::Value, takes_file: bool) {
+    let Some(mapping) = params.as_mapping_mut() else {
+        return;
+    };
+    for (key, value) in mapping.iter_mut() {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let is_path = matches!(key, "path" | "file")
+            || key.ends_with("_path")
+            || key.ends_with("_file")
+            || key.ends_with("_dir")
+            || (key == "input" && takes_file);
+        if let (true, Some(path)) = (is_path, value.as_str()) {
+            *value = serde_yaml::Value::String(PathUtils::normalize_str(path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_groups_and_folds_constant_inputs() {
+        let workflow: Workflow = serde_yaml::from_str(
+            "workflow: plan\nsteps:\n  - run: Echo\n    input: hello\n  - run: Echo\n    input: \"${step1} again\"\n    depends_on: [step1]\n  - run: Echo\n    input_from: step1\n  - run: Echo\n    depends_on: [step2, step3, step9]\n",
+        )
+        .unwrap();
+        let plan = ExecutionPlan::compile(workflow, &PluginRegistry::new());
+
+        assert_eq!(plan.groups, vec![vec![0], vec![1, 2], vec![3]]);
+        assert_eq!(plan.order.len(), 4);
+        assert!(matches!(&plan.steps[0].input, PlannedInput::Constant(s) if s == "hello"));
+        assert!(matches!(plan.steps[1].input, PlannedInput::Template));
+        assert!(matches!(plan.steps[2].input, PlannedInput::Template));
+        // Echo isn't registered in an empty registry
+        assert_eq!(plan.errors.len(), 4);
+        assert_eq!(plan.warnings.len(), 1);
+        assert_eq!(plan.warnings[0].step, Some(3));
+
+        let outputs = HashMap::from([("step1".to_string(), "hi".to_string())]);
+        assert_eq!(plan.steps[1].render(&outputs).1, "hi again");
+        assert_eq!(plan.steps[2].render(&outputs).1, "hi");
+    }
+
+    #[test]
+    fn test_disabled_steps_skip_or_pass_input_through() {
+        let dir = std::env::temp_dir().join(format!("lao_disabled_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("disabled.yaml");
+        std::fs::write(
+            &path,
+            "workflow: disabled\nsteps:\n  - run: EchoPlugin\n    input: hi\n  - run: NotInstalled\n    input_from: step1\n    enabled: false\n    when_disabled: pass_through\n  - run: EchoPlugin\n    input_from: step2\n  - run: NotInstalled\n    enabled: false\n",
+        )
+        .unwrap();
+        let mut registry = PluginRegistry::new();
+        registry.register_plugin(crate::mock::mock_plugin(
+            "EchoPlugin",
+            crate::mock::MockSpec::default(),
+        ));
+
+        let path = path.to_str().unwrap();
+        let plan = ExecutionPlan::load(path, &registry).unwrap();
+        assert!(plan.is_valid(), "{}", plan.error_summary());
+        assert_eq!(plan.warnings.len(), 2);
+        assert_eq!(plan.warnings[0].message, "disabled (pass_through)");
+
+        let logs = crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+        let outputs: Vec<Option<&str>> = logs.iter().map(|l| l.output.as_deref()).collect();
+        assert_eq!(
+            outputs,
+            [
+                Some("EchoPlugin(hi)"),
+                Some("EchoPlugin(hi)"),
+                Some("EchoPlugin(EchoPlugin(hi))"),
+                None,
+            ]
+        );
+        assert_eq!(logs[3].validation.as_deref(), Some("disabled"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_steps_can_be_named() {
+        let dir = std::env::temp_dir().join(format!("lao_named_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("named.yaml");
+        std::fs::write(
+            &path,
+            "workflow: named\nsteps:\n  - run: Classifier\n    id: classify\n    input: hi\n  - run: EchoPlugin\n    input_from: classify\n    when: steps.classify.output.label == 'urgent'\n  - run: EchoPlugin\n    id: archive\n    when: steps.classify.output.label == 'spam'\n",
+        )
+        .unwrap();
+        let mut registry = PluginRegistry::new();
+        for (name, output) in [
+            ("Classifier", Some(r#"{"label": "urgent"}"#)),
+            ("EchoPlugin", None),
+        ] {
+            let spec = crate::mock::MockSpec {
+                output: output.map(str::to_string),
+                ..Default::default()
+            };
+            registry.register_plugin(crate::mock::mock_plugin(name, spec));
+        }
+
+        let path = path.to_str().unwrap();
+        let plan = ExecutionPlan::load(path, &registry).unwrap();
+        assert!(plan.is_valid(), "{}", plan.error_summary());
+        assert!(plan.warnings.is_empty(), "{:?}", plan.warnings);
+        let ids: Vec<&str> = plan.steps.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["classify", "step2", "archive"]);
+
+        let logs = crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+        let ran: Vec<(&str, Option<&str>)> = logs
+            .iter()
+            .map(|l| (l.step_id.as_str(), l.validation.as_deref()))
+            .collect();
+        assert_eq!(
+            ran,
+            [
+                ("classify", None),
+                ("step2", None),
+                ("archive", Some("skipped"))
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_step_ids_must_be_unique_and_plain() {
+        let workflow: Workflow = serde_yaml::from_str(
+            "workflow: named\nsteps:\n  - run: Echo\n    id: step2\n  - run: Echo\n  - run: Echo\n    id: a.b\n",
+        )
+        .unwrap();
+        let plan = ExecutionPlan::compile(workflow, &PluginRegistry::new());
+        let ids: Vec<(Option<usize>, &str)> = plan
+            .errors
+            .iter()
+            .filter(|e| e.message.starts_with("id "))
+            .map(|e| (e.step, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                (Some(0), "id 'step2' is used by another step"),
+                (Some(1), "id 'step2' is used by another step"),
+                (
+                    Some(2),
+                    "id 'a.b' must be letters, digits, '_' and '-' only"
+                ),
+            ]
+        );
+    }
+}
@@ -501,7 +501,7 @@ path = "examples/basic.rs"
 //! Generated using LAO Plugin Development Tools.
 
 use lao_plugin_api::*;
-use std::ffi::{{CStr, CString}};
+use std::ffi::CString;
 use std::os::raw::c_char;
 use serde::{{Deserialize, Serialize}};
 use anyhow::Result;
@@ -561,17 +561,14 @@ unsafe extern "C" fn name() -> *const c_char {{
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {{
     if input.is_null() {{
         error!("Received null input");
-        let error_msg = CString::new("error: null input").unwrap();
-        return PluginOutput {{ text: error_msg.into_raw() }};
+        return PluginOutput::new("error: null input");
     }}
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {{
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {{
         Ok(s) => s,
         Err(_) => {{
             error!("Invalid UTF-8 in input");
-            let error_msg = CString::new("error: invalid UTF-8 input").unwrap();
-            return PluginOutput {{ text: error_msg.into_raw() }};
+            return PluginOutput::new("error: invalid UTF-8 input");
         }}
     }};
 
@@ -579,8 +576,7 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {{
 
     // Validate input
     if !validate_input_internal(input_text) {{
-        let error_msg = CString::new("error: invalid input format").unwrap();
-        return PluginOutput {{ text: error_msg.into_raw() }};
+        return PluginOutput::new("error: invalid input format");
     }}
 
     // Process input
@@ -593,15 +589,12 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {{
     }};
 
     info!("Returning output: {{}}", result);
-    let output_cstring = CString::new(result).unwrap();
-    PluginOutput {{ text: output_cstring.into_raw() }}
+    PluginOutput::new(result)
 }}
 
 /// Free output memory
 unsafe extern "C" fn free_output(output: PluginOutput) {{
-    if !output.text.is_null() {{
-        let _ = CString::from_raw(output.text);
-    }}
+    output.free();
 }}
 
 /// Run with buffer function
@@ -614,8 +607,7 @@ unsafe extern "C" fn run_with_buffer(
         return 0;
     }}
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {{
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {{
         Ok(s) => s,
         Err(_) => return 0,
     }};
@@ -677,8 +669,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {{
         return false;
     }}
     
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {{
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {{
         Ok(s) => s,
         Err(_) => return false,
     }};
@@ -707,7 +698,7 @@ fn process_input(input_text: &str) -> Result<String> {{
 /// Plugin vtable export
 #[no_mangle]
 pub static plugin_vtable: PluginVTable = PluginVTable {{
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -717,11 +708,21 @@ pub static plugin_vtable: PluginVTable = PluginVTable {{
     get_capabilities,
 }};
 
+/// Take the host allocator for output buffers
+///
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const HostAllocator) -> bool {{
+    accept_host_allocator(allocator)
+}}
+
 #[cfg(test)]
 mod tests {{
     use super::*;
     use lao_plugin_api::*;
-    use std::ffi::CString;
+    use std::ffi::CStr;
 
     #[test]
     fn test_plugin_name() {{
@@ -736,12 +737,12 @@ mod tests {{
     #[test]
     fn test_validate_input() {{
         unsafe {{
-            let valid_input = CString::new("valid input").unwrap();
-            let input = PluginInput {{ text: valid_input.into_raw() }};
+            let valid_input = PluginInputBuf::new("valid input");
+            let input = valid_input.as_input();
             assert!(validate_input(&input));
             
-            let invalid_input = CString::new("").unwrap();
-            let input = PluginInput {{ text: invalid_input.into_raw() }};
+            let invalid_input = PluginInputBuf::new("");
+            let input = invalid_input.as_input();
             assert!(!validate_input(&input));
         }}
     }}
@@ -755,12 +756,11 @@ mod tests {{
     #[test]
     fn test_plugin_run() {{
         unsafe {{
-            let input_text = CString::new("test input").unwrap();
-            let input = PluginInput {{ text: input_text.into_raw() }};
+            let input_text = PluginInputBuf::new("test input");
+            let input = input_text.as_input();
             
             let output = run(&input);
-            let output_cstr = CStr::from_ptr(output.text);
-            let output_str = output_cstr.to_str().unwrap();
+            let output_str = std::str::from_utf8(output.as_bytes()).unwrap();
             
             assert!(output_str.contains("test input"));
             
@@ -889,13 +889,13 @@ fn test_input_validation() {{
     // Test input validation
     unsafe {{
         // Valid input
-        let valid_input = std::ffi::CString::new("valid test input").unwrap();
-        let input = PluginInput {{ text: valid_input.into_raw() }};
+        let valid_input = PluginInputBuf::new("valid test input");
+        let input = valid_input.as_input();
         assert!(validate_input(&input));
         
         // Invalid input (empty)
-        let invalid_input = std::ffi::CString::new("").unwrap();
-        let input = PluginInput {{ text: invalid_input.into_raw() }};
+        let invalid_input = PluginInputBuf::new("");
+        let input = invalid_input.as_input();
         assert!(!validate_input(&input));
     }}
 }}
@@ -904,14 +904,13 @@ fn test_input_validation() {{
 fn test_plugin_execution() {{
     // Test actual plugin execution
     unsafe {{
-        let input_text = std::ffi::CString::new("Hello, Plugin!").unwrap();
-        let input = PluginInput {{ text: input_text.into_raw() }};
+        let input_text = PluginInputBuf::new("Hello, Plugin!");
+        let input = input_text.as_input();
         
         let output = run(&input);
-        assert!(!output.text.is_null());
+        assert!(!output.is_null());
         
-        let output_cstr = std::ffi::CStr::from_ptr(output.text);
-        let output_str = output_cstr.to_str().unwrap();
+        let output_str = std::str::from_utf8(output.as_bytes()).unwrap();
         
         // Verify output contains expected content
         assert!(output_str.contains("Hello, Plugin!"));
@@ -925,8 +924,8 @@ fn test_plugin_execution() {{
 fn test_plugin_buffer_execution() {{
     // Test plugin execution with buffer
     unsafe {{
-        let input_text = std::ffi::CString::new("Buffer test").unwrap();
-        let input = PluginInput {{ text: input_text.into_raw() }};
+        let input_text = PluginInputBuf::new("Buffer test");
+        let input = input_text.as_input();
         
         let mut buffer = [0u8; 1024];
         let written = run_with_buffer(&input, buffer.as_mut_ptr() as *mut i8, buffer.len());
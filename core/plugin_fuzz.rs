@@ -0,0 +1,369 @@
+// Conformance fuzzing of plugin libraries at the C ABI, each case in its
+// own process.
+use crate::plugins::open_plugin;
+use lao_plugin_api::{PluginInput, PluginInputBuf};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const PLUGIN_ENV: &str = "LAO_FUZZ_PLUGIN";
+pub const CASE_ENV: &str = "LAO_FUZZ_CASE";
+
+// Marks the child's result line among whatever else it prints
+const RESULT_PREFIX: &str = "lao-fuzz-result ";
+const HUGE_INPUT_BYTES: usize = 8 << 20;
+const RANDOM_INPUTS: usize = 64;
+const CONCURRENT_THREADS: usize = 8;
+const CALLS_PER_THREAD: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FuzzCase {
+    /// `run` with a null `*const PluginInput`
+    NullInput,
+    /// A `PluginInput` whose `text` is null
+    NullText,
+    EmptyInput,
+    NonUtf8,
+    EmbeddedNul,
+    HugeInput,
+    RandomBytes,
+    /// `validate_input` with null and malformed inputs
+    ValidateInput,
+    ConcurrentCalls,
+}
+
+impl FuzzCase {
+    pub const ALL: [FuzzCase; 9] = [
+        FuzzCase::NullInput,
+        FuzzCase::NullText,
+        FuzzCase::EmptyInput,
+        FuzzCase::NonUtf8,
+        FuzzCase::EmbeddedNul,
+        FuzzCase::HugeInput,
+        FuzzCase::RandomBytes,
+        FuzzCase::ValidateInput,
+        FuzzCase::ConcurrentCalls,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FuzzCase::NullInput => "null_input",
+            FuzzCase::NullText => "null_text",
+            FuzzCase::EmptyInput => "empty_input",
+            FuzzCase::NonUtf8 => "non_utf8",
+            FuzzCase::EmbeddedNul => "embedded_nul",
+            FuzzCase::HugeInput => "huge_input",
+            FuzzCase::RandomBytes => "random_bytes",
+            FuzzCase::ValidateInput => "validate_input",
+            FuzzCase::ConcurrentCalls => "concurrent_calls",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", content = "detail", rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    /// The plugin returned, but broke the ABI contract
+    Failed(String),
+    /// The probe process died, e.g. from a segfault, abort or panic across the ABI
+    Crashed(String),
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub case: FuzzCase,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConformanceReport {
+    pub library: PathBuf,
+    pub results: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.outcome == Outcome::Passed)
+    }
+
+    /// One line per case, for terminals
+    pub fn render(&self) -> String {
+        let mut text = format!("Conformance report for {}\n", self.library.display());
+        for result in &self.results {
+            let (mark, detail) = match &result.outcome {
+                Outcome::Passed => ("PASS", String::new()),
+                Outcome::Failed(e) => ("FAIL", format!(": {}", e)),
+                Outcome::Crashed(e) => ("CRASH", format!(": {}", e)),
+                Outcome::TimedOut => ("TIMEOUT", String::new()),
+            };
+            text.push_str(&format!(
+                "  {:<7} {:<16} {:>6} ms{}\n",
+                mark,
+                result.case.name(),
+                result.duration_ms,
+                detail
+            ));
+        }
+        let passed = self
+            .results
+            .iter()
+            .filter(|r| r.outcome == Outcome::Passed)
+            .count();
+        text.push_str(&format!("{}/{} cases passed\n", passed, self.results.len()));
+        text
+    }
+}
+
+/// How to start the isolated probe process
+#[derive(Debug, Clone)]
+pub struct Prober {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Per case; a probe still running after this is killed
+    pub timeout: Duration,
+}
+
+impl Prober {
+    pub fn new(program: impl Into<PathBuf>, args: &[&str]) -> Self {
+        Self {
+            program: program.into(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Run every `FuzzCase` against the library at `library`, one probe process each
+pub fn fuzz_plugin(library: &Path, prober: &Prober) -> ConformanceReport {
+    let results = FuzzCase::ALL
+        .into_iter()
+        .map(|case| {
+            let started = Instant::now();
+            let outcome = probe(library, case, prober)
+                .unwrap_or_else(|e| Outcome::Crashed(format!("probe failed to start: {}", e)));
+            CaseResult {
+                case,
+                outcome,
+                duration_ms: started.elapsed().as_millis() as u64,
+            }
+        })
+        .collect();
+    ConformanceReport {
+        library: library.to_path_buf(),
+        results,
+    }
+}
+
+fn probe(library: &Path, case: FuzzCase, prober: &Prober) -> std::io::Result<Outcome> {
+    let mut child = Command::new(&prober.program)
+        .args(&prober.args)
+        .env(PLUGIN_ENV, library)
+        .env(CASE_ENV, case.name())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Drain stdout on a thread so a chatty plugin can't fill the pipe and stall
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+    let deadline = Instant::now() + prober.timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(Outcome::TimedOut);
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let output = reader.join().unwrap_or_default();
+
+    let reported = output
+        .lines()
+        // The test harness may have printed the test name on the same line
+        .filter_map(|line| line.split_once(RESULT_PREFIX).map(|(_, json)| json))
+        .next_back()
+        .and_then(|json| serde_json::from_str(json).ok());
+    Ok(match reported {
+        Some(outcome) => outcome,
+        None => Outcome::Crashed(describe_exit(status)),
+    })
+}
+
+fn describe_exit(status: ExitStatus) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return format!("killed by signal {}", signal);
+        }
+    }
+    format!("exited with {} before reporting", status)
+}
+
+/// Child half of `fuzz_plugin`: if LAO_FUZZ_PLUGIN and LAO_FUZZ_CASE are
+/// set, run that case, print the result line and return the outcome
+pub fn probe_from_env() -> Option<Outcome> {
+    let library = std::env::var_os(PLUGIN_ENV)?;
+    let case = FuzzCase::parse(&std::env::var(CASE_ENV).ok()?)?;
+    let outcome = run_case(Path::new(&library), case);
+    println!(
+        "{}{}",
+        RESULT_PREFIX,
+        serde_json::to_string(&outcome).unwrap_or_default()
+    );
+    Some(outcome)
+}
+
+/// Run one case in this process. A misbehaving plugin can crash the caller;
+/// use `fuzz_plugin` to isolate it.
+pub fn run_case(library: &Path, case: FuzzCase) -> Outcome {
+    let plugin = match open_plugin(library) {
+        Ok(plugin) => Arc::new(plugin),
+        Err(e) => return Outcome::Failed(e),
+    };
+    // A call may fail ("returned no output"), but output it does return must be UTF-8
+    let call = |bytes: &[u8]| -> Result<(), String> {
+        let buffer = PluginInputBuf::new(bytes);
+        let input = buffer.as_input();
+        check_output(unsafe { plugin.run_raw(&input) })
+    };
+
+    let result = match case {
+        FuzzCase::NullInput => check_output(unsafe { plugin.run_raw(std::ptr::null()) }),
+        FuzzCase::NullText => {
+            let input = PluginInput {
+                text: std::ptr::null(),
+                len: 0,
+            };
+            check_output(unsafe { plugin.run_raw(&input) })
+        }
+        FuzzCase::EmptyInput => call(b""),
+        FuzzCase::NonUtf8 => call(&[0xff, 0xfe, 0xc3, 0x28, 0xa0, 0xa1, 0xe2, 0x28, 0xa1]),
+        FuzzCase::EmbeddedNul => call(b"before\0after\0"),
+        FuzzCase::HugeInput => call(&vec![b'a'; HUGE_INPUT_BYTES]),
+        FuzzCase::RandomBytes => {
+            let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+            (0..RANDOM_INPUTS).try_for_each(|_| {
+                let len = (rng.next() % 4096) as usize;
+                let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+                call(&bytes)
+            })
+        }
+        FuzzCase::ValidateInput => {
+            let buffer = PluginInputBuf::new(&[0xffu8, 0x00, 0xfe][..]);
+            let null_text = PluginInput {
+                text: std::ptr::null(),
+                len: 0,
+            };
+            unsafe {
+                plugin
+                    .validate_raw(std::ptr::null())
+                    .and_then(|_| plugin.validate_raw(&null_text))
+                    .and_then(|_| plugin.validate_raw(&buffer.as_input()))
+                    .map(|_| ())
+            }
+        }
+        FuzzCase::ConcurrentCalls => {
+            let threads: Vec<_> = (0..CONCURRENT_THREADS)
+                .map(|t| {
+                    let plugin = plugin.clone();
+                    thread::spawn(move || {
+                        (0..CALLS_PER_THREAD).try_for_each(|i| {
+                            let buffer = PluginInputBuf::new(format!("concurrent {} {}", t, i));
+                            let input = buffer.as_input();
+                            check_output(unsafe { plugin.run_raw(&input) })
+                        })
+                    })
+                })
+                .collect();
+            threads.into_iter().try_for_each(|t| {
+                t.join()
+                    .unwrap_or_else(|_| Err("a calling thread panicked".to_string()))
+            })
+        }
+    };
+    match result {
+        Ok(()) => Outcome::Passed,
+        Err(e) => Outcome::Failed(e),
+    }
+}
+
+fn check_output(output: Result<Vec<u8>, String>) -> Result<(), String> {
+    match output {
+        Ok(bytes) if std::str::from_utf8(&bytes).is_err() => {
+            Err("returned output that is not valid UTF-8".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+// Deterministic, so a failing random case can be reproduced
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cross_platform::{PathUtils, Platform};
+
+    // Probe processes re-run this test binary with only this test selected
+    #[test]
+    fn fuzz_probe_child() {
+        probe_from_env();
+    }
+
+    #[test]
+    fn test_bundled_plugins_conform() {
+        let prober = Prober::new(
+            std::env::current_exe().unwrap(),
+            &[
+                "--exact",
+                "plugin_fuzz::tests::fuzz_probe_child",
+                "--nocapture",
+                "--test-threads=1",
+            ],
+        );
+        for name in ["echo", "whisper", "summarizer", "prompt_dispatcher"] {
+            let library = PathUtils::plugin_dir()
+                .join(Platform::shared_lib_name(&format!("{}_plugin", name)));
+            if !library.exists() {
+                println!("⚠️  {} not found, skipping", library.display());
+                continue;
+            }
+            let report = fuzz_plugin(&library, &prober);
+            assert!(report.passed(), "{}", report.render());
+        }
+
+        let missing = fuzz_plugin(Path::new("no/such/plugin.so"), &prober);
+        assert!(matches!(missing.results[0].outcome, Outcome::Failed(_)));
+    }
+}
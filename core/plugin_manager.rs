@@ -1,3 +1,4 @@
+use crate::audit::{self, AuditKind};
 use crate::plugins::PluginRegistry;
 use anyhow::{anyhow, Result};
 use lao_plugin_api::*;
@@ -180,6 +181,7 @@ impl PluginManager {
                 self.configs
                     .insert(plugin_name.clone(), default_config.clone());
                 self.save_plugin_config(plugin_name, &default_config)?;
+                record_permission_changes(plugin_name, &[], &default_config.permissions);
             }
         }
 
@@ -259,6 +261,11 @@ impl PluginManager {
         let plugin_path = self.plugin_directory.join(name);
         std::fs::create_dir_all(&plugin_path)?;
 
+        audit::event(AuditKind::PluginInstall, name)
+            .detail("url", url)
+            .detail("path", plugin_path.display().to_string())
+            .record();
+
         // In a real implementation, download the plugin binary here
         // For now, we'll simulate success
 
@@ -379,6 +386,7 @@ impl PluginManager {
         self.emit_event(PluginEvent::PluginUnloaded {
             plugin_name: name.to_string(),
         });
+        audit::event(AuditKind::PluginUninstall, name).record();
 
         println!("✓ Successfully uninstalled plugin: {}", name);
         Ok(())
@@ -438,8 +446,14 @@ impl PluginManager {
 
     /// Update plugin configuration
     pub fn update_plugin_config(&mut self, name: &str, config: PluginConfig) -> Result<()> {
+        let previous = self
+            .configs
+            .get(name)
+            .map(|c| c.permissions.clone())
+            .unwrap_or_default();
         self.configs.insert(name.to_string(), config.clone());
         self.save_plugin_config(name, &config)?;
+        record_permission_changes(name, &previous, &config.permissions);
         Ok(())
     }
 
@@ -483,6 +497,13 @@ impl PluginManager {
         // Reload plugins
         self.load_plugins()?;
 
+        // Runs started from now on pick up the new build; in-flight runs keep the old one
+        if crate::plugins::shared().snapshot().get(name).is_some() {
+            crate::plugins::shared()
+                .reload(name)
+                .map_err(|e| anyhow!("Failed to reload plugin {}: {}", name, e))?;
+        }
+
         println!("✓ Successfully hot reloaded plugin: {}", name);
         Ok(())
     }
@@ -565,3 +586,17 @@ impl PluginManager {
         }
     }
 }
+
+// One audit entry per permission added to or removed from a plugin's config
+fn record_permission_changes(plugin_name: &str, before: &[String], after: &[String]) {
+    for permission in after.iter().filter(|p| !before.contains(p)) {
+        audit::event(AuditKind::PermissionGrant, plugin_name)
+            .detail("permission", permission.as_str())
+            .record();
+    }
+    for permission in before.iter().filter(|p| !after.contains(p)) {
+        audit::event(AuditKind::PermissionRevoke, plugin_name)
+            .detail("permission", permission.as_str())
+            .record();
+    }
+}
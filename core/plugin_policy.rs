@@ -0,0 +1,296 @@
+// Which plugins a workflow may invoke.
+// The allowlist keys are described in docs/workflows.md (Plugin Allowlists).
+use crate::plugins::PluginRegistry;
+use crate::{Workflow, WorkflowStep};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustLevel {
+    Trusted,
+    #[default]
+    Standard,
+    Restricted,
+}
+
+/// `Name` or `Name@version`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginPin {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+impl PluginPin {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (name, version) = match text.trim().split_once('@') {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (text.trim(), None),
+        };
+        if name.is_empty() || version.is_some_and(str::is_empty) {
+            return Err(format!(
+                "invalid plugin '{}', expected Name or Name@version",
+                text
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            version: version.map(String::from),
+        })
+    }
+
+    // Why the installed version doesn't match the pin. Plugins that aren't
+    // installed are reported by the plan already; mocks stand in for any version.
+    fn version_problem(&self, installed: Option<&str>) -> Option<String> {
+        let (Some(pinned), Some(installed)) = (&self.version, installed) else {
+            return None;
+        };
+        (pinned != installed && installed != crate::mock::MOCK_VERSION)
+            .then(|| format!("{} is pinned but version {} is installed", self, installed))
+    }
+}
+
+impl fmt::Display for PluginPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl Serialize for PluginPin {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PluginPin {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Self::parse(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PluginPolicy {
+    /// Plugins any workflow may use; all when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<PluginPin>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub trust: BTreeMap<String, TrustLevel>,
+}
+
+impl PluginPolicy {
+    /// The policy without a plugin_policy.yaml
+    pub fn defaults() -> Self {
+        Self {
+            allow: Vec::new(),
+            trust: BTreeMap::from([("ShellPlugin".to_string(), TrustLevel::Restricted)]),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .map_err(|e| format!("invalid plugin policy {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::defaults()),
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn trust_of(&self, name: &str) -> TrustLevel {
+        self.trust.get(name).copied().unwrap_or_default()
+    }
+
+    /// Why a workflow pinning `pins` may not call `name`, if it may not
+    pub fn problem(
+        &self,
+        name: &str,
+        registry: &PluginRegistry,
+        pins: Option<&[PluginPin]>,
+    ) -> Option<String> {
+        let installed = registry.get(name).map(|p| p.info.version.as_str());
+        if !self.allow.is_empty() {
+            let Some(pin) = self.allow.iter().find(|p| p.name == name) else {
+                return Some(format!(
+                    "plugin '{}' is not in the plugin policy's allowlist",
+                    name
+                ));
+            };
+            if let Some(problem) = pin.version_problem(installed) {
+                return Some(problem);
+            }
+        }
+        if let Some(pin) = pins.and_then(|pins| pins.iter().find(|p| p.name == name)) {
+            return pin.version_problem(installed);
+        }
+        match (self.trust_of(name), pins) {
+            (TrustLevel::Trusted, _) | (TrustLevel::Standard, None) => None,
+            (TrustLevel::Standard, Some(_)) => Some(format!(
+                "plugin '{}' is not in the workflow's plugins list",
+                name
+            )),
+            (TrustLevel::Restricted, _) => Some(format!(
+                "plugin '{}' is restricted; list it in the workflow's plugins to use it",
+                name
+            )),
+        }
+    }
+}
+
+static POLICY: OnceLock<PluginPolicy> = OnceLock::new();
+
+/// Use `policy` for every workflow; later calls are ignored
+pub fn init(policy: PluginPolicy) -> Result<(), String> {
+    let _ = POLICY.set(policy);
+    Ok(())
+}
+
+pub fn global() -> &'static PluginPolicy {
+    POLICY.get_or_init(PluginPolicy::defaults)
+}
+
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_PLUGIN_POLICY")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("plugin_policy.yaml"))
+}
+
+/// Plugins a step calls: its runner, fallback and judges
+pub fn step_plugins(step: &WorkflowStep) -> Vec<&str> {
+    let mut names = vec![step.run.as_str()];
+    names.extend(step.fallback.as_deref());
+    names.extend(
+        step.assert
+            .iter()
+            .filter_map(|a| a.judge.as_ref())
+            .map(|judge| judge.plugin.as_str()),
+    );
+    names.extend(step.evaluate.iter().map(|e| e.judge.as_str()));
+    names.extend(step.map_reduce.iter().filter_map(|m| m.combine.as_deref()));
+    names
+}
+
+/// Check a workflow generated from a prompt against the global policy, as if
+/// it declared no plugins, and pin it to the plugins it uses
+pub fn admit_generated(workflow: &mut Workflow, registry: &PluginRegistry) -> Result<(), String> {
+    let used: BTreeSet<&str> = workflow.steps.iter().flat_map(step_plugins).collect();
+    let problems: Vec<String> = used
+        .iter()
+        .filter_map(|name| global().problem(name, registry, None))
+        .collect();
+    if !problems.is_empty() {
+        return Err(problems.join("; "));
+    }
+    let pins = used
+        .into_iter()
+        .map(|name| PluginPin {
+            name: name.to_string(),
+            version: registry.get(name).map(|p| p.info.version.clone()),
+        })
+        .collect();
+    workflow.plugins = Some(pins);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_plugin, MockSpec};
+
+    fn registry() -> PluginRegistry {
+        let mut registry = PluginRegistry::new();
+        for name in ["EchoPlugin", "OllamaPlugin", "ShellPlugin"] {
+            let mut plugin = mock_plugin(name, MockSpec::default());
+            plugin.info.version = "1.2.0".to_string();
+            registry.register_plugin(plugin);
+        }
+        registry
+    }
+
+    fn policy() -> PluginPolicy {
+        serde_yaml::from_str("trust:\n  EchoPlugin: trusted\n  ShellPlugin: restricted").unwrap()
+    }
+
+    #[test]
+    fn test_without_a_list_only_restricted_plugins_are_refused() {
+        let registry = registry();
+        assert_eq!(policy().problem("OllamaPlugin", &registry, None), None);
+        assert!(policy()
+            .problem("ShellPlugin", &registry, None)
+            .unwrap()
+            .contains("restricted"));
+    }
+
+    #[test]
+    fn test_with_a_list_only_listed_and_trusted_plugins_run() {
+        let registry = registry();
+        let pins: Vec<PluginPin> = serde_yaml::from_str("[OllamaPlugin@1.2.0]").unwrap();
+        assert_eq!(
+            policy().problem("OllamaPlugin", &registry, Some(&pins)),
+            None
+        );
+        assert_eq!(policy().problem("EchoPlugin", &registry, Some(&pins)), None);
+        assert_eq!(
+            policy()
+                .problem("ShellPlugin", &registry, Some(&pins))
+                .unwrap(),
+            "plugin 'ShellPlugin' is restricted; list it in the workflow's plugins to use it"
+        );
+        let shell = [PluginPin::parse("ShellPlugin").unwrap()];
+        assert_eq!(
+            policy().problem("ShellPlugin", &registry, Some(&shell)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pinned_version_must_be_installed() {
+        let old = [PluginPin::parse("OllamaPlugin@1.0.0").unwrap()];
+        assert_eq!(
+            policy()
+                .problem("OllamaPlugin", &registry(), Some(&old))
+                .unwrap(),
+            "OllamaPlugin@1.0.0 is pinned but version 1.2.0 is installed"
+        );
+        assert!(PluginPin::parse("Ollama@").is_err());
+    }
+
+    #[test]
+    fn test_global_allow_list() {
+        let global: PluginPolicy = serde_yaml::from_str("allow: [EchoPlugin]").unwrap();
+        assert!(global.problem("OllamaPlugin", &registry(), None).is_some());
+        assert!(global.problem("EchoPlugin", &registry(), None).is_none());
+    }
+
+    #[test]
+    fn test_generated_workflows_cannot_list_restricted_plugins() {
+        let mut generated: Workflow = serde_yaml::from_str(
+            "workflow: gen\nplugins: [ShellPlugin]\nsteps:\n  - run: ShellPlugin\n    input: rm -rf ~",
+        )
+        .unwrap();
+        assert!(admit_generated(&mut generated, &registry())
+            .unwrap_err()
+            .contains("restricted"));
+    }
+
+    #[test]
+    fn test_generated_workflows_are_pinned_to_installed_versions() {
+        let mut generated: Workflow = serde_yaml::from_str(
+            "workflow: gen\nsteps:\n  - run: OllamaPlugin\n    input: hi\n    fallback: EchoPlugin",
+        )
+        .unwrap();
+        admit_generated(&mut generated, &registry()).unwrap();
+        let pinned: Vec<String> = generated
+            .plugins
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(pinned, ["EchoPlugin@1.2.0", "OllamaPlugin@1.2.0"]);
+    }
+}
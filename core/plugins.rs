@@ -1,19 +1,66 @@
+use crate::audit;
 use crate::cross_platform::{PathUtils, Platform};
+use crate::logging;
+use crate::ollama;
+use lao_plugin_api::typed::{Field, Payload, Signature, TypedVTable, TYPED_VERSION};
 use lao_plugin_api::*;
 use libloading::{Library, Symbol};
 use std::collections::HashMap;
 use std::ffi::CStr;
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
 #[derive(Debug, Clone)]
 pub struct PluginInstance {
-    pub library: Arc<Library>,
-    pub vtable: PluginVTablePtr,
     pub info: PluginInfo,
     pub metadata: PluginInfo, // Use PluginInfo instead of PluginMetadata for Debug/Clone
+    /// Library file the plugin was loaded from, for `reload`
+    pub path: Option<PathBuf>,
+    /// What `get_capabilities` reported when the library was first opened
+    capabilities: Vec<PluginCapability>,
+    /// Declared fields of plugins that export `plugin_typed_vtable`
+    signature: Option<Signature>,
+    // Opened on first use for plugins registered from the metadata cache
+    loaded: Arc<OnceLock<Result<LoadedPlugin, String>>>,
+    /// Set for plugins implemented in this process instead of a library
+    native: Option<NativeRun>,
 }
 
+type NativeFn = dyn Fn(&str) -> Result<String, String> + Send + Sync;
+
+/// `run` of an in-process plugin, such as the mocks of `lao test`
+#[derive(Clone)]
+pub struct NativeRun(Arc<NativeFn>);
+
+impl std::fmt::Debug for NativeRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NativeRun")
+    }
+}
+
+/// The dlopened half of a plugin
+#[derive(Debug)]
+struct LoadedPlugin {
+    // Keeps `vtable` and the function pointers below valid
+    _library: Library,
+    vtable: PluginVTablePtr,
+    /// Optional `plugin_last_usage` export for token reporting
+    usage_fn: Option<unsafe extern "C" fn() -> *const std::os::raw::c_char>,
+    /// Optional `plugin_context_window` export
+    context_fn: Option<unsafe extern "C" fn() -> u64>,
+    /// Whether the plugin took `HOST_ALLOCATOR` for its output buffers
+    host_allocated: bool,
+    /// Optional `plugin_typed_vtable` export for structured calls
+    typed: Option<*const TypedVTable>,
+}
+
+// SAFETY: `vtable` points into the plugin library, which stays mapped while
+// any clone of the instance holds `loaded`. Plugins are called from worker
+// threads (UI runs, daemon triggers), so the C ABI requires their entry
+// points to be thread-safe.
+unsafe impl Send for LoadedPlugin {}
+unsafe impl Sync for LoadedPlugin {}
+
 impl PluginInstance {
     /// # Safety
     ///
@@ -21,7 +68,9 @@ impl PluginInstance {
     /// The caller must ensure that `vtable` is a valid pointer to a `PluginVTable`.
     pub unsafe fn new(library: Library, vtable: PluginVTablePtr) -> Result<Self, String> {
         unsafe {
-            println!("[DEBUG] Creating PluginInstance with vtable: {:?}", vtable);
+            logging::debug("creating plugin instance")
+                .field("vtable", format!("{:?}", vtable))
+                .emit();
 
             // Check if vtable is valid
             if vtable.is_null() {
@@ -29,45 +78,365 @@ impl PluginInstance {
             }
 
             let vtable_ref = &*vtable;
-            println!("[DEBUG] VTable version: {}", vtable_ref.version);
-            println!(
-                "[DEBUG] VTable get_metadata function pointer: {:?}",
-                vtable_ref.get_metadata
-            );
+            logging::debug("reading plugin metadata")
+                .field("vtable_version", vtable_ref.version)
+                .emit();
+            if vtable_ref.version > ABI_VERSION {
+                return Err(format!(
+                    "plugin ABI version {} is newer than this host supports ({})",
+                    vtable_ref.version, ABI_VERSION
+                ));
+            }
 
             let metadata = (vtable_ref.get_metadata)();
-            println!("[DEBUG] Got metadata from plugin");
-
             let info = PluginInfo::from_metadata(&metadata);
-            println!("[DEBUG] Created PluginInfo from metadata");
+            let capabilities = {
+                let caps_ptr = (vtable_ref.get_capabilities)();
+                if caps_ptr.is_null() {
+                    Vec::new()
+                } else {
+                    let caps_str = CStr::from_ptr(caps_ptr).to_string_lossy();
+                    serde_json::from_str(&caps_str).unwrap_or_default()
+                }
+            };
+            let usage_fn = library
+                .get::<unsafe extern "C" fn() -> *const std::os::raw::c_char>(USAGE_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let context_fn = library
+                .get::<unsafe extern "C" fn() -> u64>(CONTEXT_SYMBOL)
+                .ok()
+                .map(|symbol| *symbol);
+            let host_allocated = library
+                .get::<unsafe extern "C" fn(*const HostAllocator) -> bool>(INIT_SYMBOL)
+                .map(|init| init(&HOST_ALLOCATOR))
+                .unwrap_or(false);
+            if let Ok(accept) =
+                library.get::<unsafe extern "C" fn(*const HostServices) -> bool>(SERVICES_SYMBOL)
+            {
+                accept(&ollama::HOST_SERVICES);
+            }
+            if let Ok(accept) = library
+                .get::<unsafe extern "C" fn(*const HostProcessOutput) -> bool>(
+                    PROCESS_OUTPUT_SYMBOL,
+                )
+            {
+                accept(&crate::process_output::HOST_PROCESS_OUTPUT);
+            }
+            if let Ok(accept) = library
+                .get::<unsafe extern "C" fn(*const HostStreamOutput) -> bool>(STREAM_OUTPUT_SYMBOL)
+            {
+                accept(&crate::streaming::HOST_STREAM_OUTPUT);
+            }
+            if let Ok(accept) =
+                library.get::<unsafe extern "C" fn(*const HostStop) -> bool>(STOP_SYMBOL)
+            {
+                accept(&crate::cancel::HOST_STOP);
+            }
+            if let Ok(accept) =
+                library.get::<unsafe extern "C" fn(*const HostCommand) -> bool>(COMMAND_SYMBOL)
+            {
+                accept(&crate::sandbox::HOST_COMMAND);
+            }
+            let typed = library
+                .get::<unsafe extern "C" fn() -> *const TypedVTable>(typed::TYPED_VTABLE_SYMBOL)
+                .ok()
+                .map(|typed_vtable| typed_vtable())
+                .filter(|typed| !typed.is_null());
+            let signature = match typed {
+                Some(typed) => Some(read_signature(&*typed)?),
+                None => None,
+            };
 
             Ok(PluginInstance {
-                library: Arc::new(library),
-                vtable,
                 info: info.clone(),
                 metadata: info,
+                path: None,
+                capabilities,
+                signature,
+                loaded: Arc::new(OnceLock::from(Ok(LoadedPlugin {
+                    _library: library,
+                    vtable,
+                    usage_fn,
+                    context_fn,
+                    host_allocated,
+                    typed,
+                }))),
+                native: None,
+            })
+        }
+    }
+
+    /// A plugin known from the metadata cache; its library is opened on first use
+    pub fn deferred(path: PathBuf, info: PluginInfo, capabilities: Vec<PluginCapability>) -> Self {
+        PluginInstance {
+            info: info.clone(),
+            metadata: info,
+            path: Some(path),
+            capabilities,
+            signature: None,
+            loaded: Arc::new(OnceLock::new()),
+            native: None,
+        }
+    }
+
+    /// A plugin whose `run` is the Rust function `run`; it has no library
+    pub fn native(
+        info: PluginInfo,
+        run: impl Fn(&str) -> Result<String, String> + Send + Sync + 'static,
+    ) -> Self {
+        PluginInstance {
+            capabilities: info.capabilities.clone(),
+            signature: None,
+            info: info.clone(),
+            metadata: info,
+            path: None,
+            loaded: Arc::new(OnceLock::new()),
+            native: Some(NativeRun(Arc::new(run))),
+        }
+    }
+
+    /// Declare the plugin's typed fields, e.g. from the metadata cache
+    pub fn with_signature(mut self, signature: Option<Signature>) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Input and output fields the plugin declares, if it takes a `Payload`
+    pub fn signature(&self) -> Option<&Signature> {
+        self.signature.as_ref()
+    }
+
+    /// Whether the library has been opened
+    pub fn is_loaded(&self) -> bool {
+        self.native.is_some() || self.loaded.get().is_some()
+    }
+
+    fn loaded(&self) -> Result<&LoadedPlugin, String> {
+        self.loaded
+            .get_or_init(|| {
+                let path = self
+                    .path
+                    .as_deref()
+                    .ok_or_else(|| format!("{} has no library path", self.info.name))?;
+                let plugin = open_plugin(path)?;
+                // Only this instance's OnceLock holds the library, so unwrapping succeeds
+                Arc::try_unwrap(plugin.loaded)
+                    .ok()
+                    .and_then(OnceLock::into_inner)
+                    .unwrap_or_else(|| Err(format!("failed to open {}", path.display())))
             })
+            .as_ref()
+            .map_err(|e| e.clone())
+    }
+
+    /// Call `run` with `input` and return the output as validated UTF-8.
+    /// Version 1 plugins read and return NUL-terminated strings instead of
+    /// length-prefixed ones, so for them the output ends at the first NUL.
+    pub fn call(&self, input: &str) -> Result<String, String> {
+        if let Some(NativeRun(run)) = &self.native {
+            return run(input);
+        }
+        let buffer = PluginInputBuf::new(input);
+        let input = buffer.as_input();
+        let bytes = unsafe { self.run_raw(&input)? };
+        String::from_utf8(bytes)
+            .map_err(|_| format!("{} returned output that is not valid UTF-8", self.info.name))
+    }
+
+    /// Call the plugin with a structured `input`. Plugins without a typed
+    /// vtable get the text of its first field as their string input, and
+    /// their output comes back as an `output` text field.
+    pub fn call_typed(&self, input: &Payload) -> Result<Payload, String> {
+        let typed = match &self.native {
+            Some(_) => None,
+            None => self.loaded()?.typed,
+        };
+        let Some(typed) = typed else {
+            let text = input
+                .fields
+                .first()
+                .map(|field| field.value.to_text())
+                .unwrap_or_default();
+            return Ok(Payload::new().with(Field::text("output", self.call(&text)?)));
+        };
+        let buffer = input.to_raw();
+        let raw = buffer.as_raw();
+        let _sandbox = crate::sandbox::PluginScope::enter(&self.info.name);
+        let output = unsafe {
+            let output = ((*typed).run)(&raw);
+            let payload = Payload::from_raw(&output);
+            ((*typed).free_output)(output);
+            payload
+        };
+        output
+            .map_err(|e| format!("{} returned an invalid payload: {}", self.info.name, e))?
+            .into_result()
+    }
+
+    /// Call `run` with an arbitrary, possibly null, input pointer and return
+    /// the raw output bytes. Used by `plugin_fuzz` to probe edge cases.
+    ///
+    /// # Safety
+    ///
+    /// `input` is handed to the plugin as-is; it must be null or valid for the call.
+    pub(crate) unsafe fn run_raw(&self, input: *const PluginInput) -> Result<Vec<u8>, String> {
+        let loaded = self.loaded()?;
+        let vtable = &*loaded.vtable;
+        // Commands the plugin runs get its sandbox profile
+        let _sandbox = crate::sandbox::PluginScope::enter(&self.info.name);
+        if vtable.version < 2 {
+            // SAFETY: v1 entry points take the same arguments and return
+            // the single-pointer output that v1::PluginOutput describes
+            let run = std::mem::transmute::<*const (), v1::RunFn>(vtable.run as *const ());
+            let free_output =
+                std::mem::transmute::<*const (), v1::FreeOutputFn>(vtable.free_output as *const ());
+            let output = run(input);
+            if output.text.is_null() {
+                return Err(format!("{} returned no output", self.info.name));
+            }
+            let bytes = CStr::from_ptr(output.text).to_bytes().to_vec();
+            free_output(output);
+            return Ok(bytes);
+        }
+        let output = (vtable.run)(input);
+        if output.is_null() {
+            return Err(format!("{} returned no output", self.info.name));
         }
+        let bytes = output.as_bytes().to_vec();
+        if loaded.host_allocated {
+            (HOST_ALLOCATOR.free)(output.text as *mut u8, output.len + 1);
+        } else {
+            (vtable.free_output)(output);
+        }
+        Ok(bytes)
+    }
+
+    /// Call `validate_input` with an arbitrary, possibly null, input pointer
+    ///
+    /// # Safety
+    ///
+    /// See `run_raw`.
+    pub(crate) unsafe fn validate_raw(&self, input: *const PluginInput) -> Result<bool, String> {
+        let loaded = self.loaded()?;
+        Ok(((*loaded.vtable).validate_input)(input))
+    }
+
+    /// ABI version the library reports, once it is loaded
+    pub fn abi_version(&self) -> Option<u32> {
+        let loaded = self.loaded.get()?.as_ref().ok()?;
+        Some(unsafe { (*loaded.vtable).version })
     }
 
     pub fn validate_input(&self, input: &PluginInput) -> bool {
-        unsafe { ((*self.vtable).validate_input)(input) }
+        match self.loaded() {
+            Ok(loaded) => unsafe { ((*loaded.vtable).validate_input)(input) },
+            Err(_) => false,
+        }
     }
 
-    pub fn get_capabilities(&self) -> Vec<PluginCapability> {
+    /// Context window of the plugin's model in tokens, if it reports one
+    pub fn context_window(&self) -> Option<u64> {
+        let context_fn = self.loaded().ok()?.context_fn?;
+        Some(unsafe { context_fn() }).filter(|&tokens| tokens > 0)
+    }
+
+    /// Token usage the plugin reported for its last `run` on this thread
+    pub fn take_usage(&self) -> Option<PluginUsage> {
+        let usage_fn = self.loaded.get()?.as_ref().ok()?.usage_fn?;
         unsafe {
-            let caps_ptr = ((*self.vtable).get_capabilities)();
-            if caps_ptr.is_null() {
-                return Vec::new();
+            let ptr = usage_fn();
+            if ptr.is_null() {
+                return None;
             }
+            serde_json::from_str(&CStr::from_ptr(ptr).to_string_lossy()).ok()
+        }
+    }
+
+    pub fn get_capabilities(&self) -> Vec<PluginCapability> {
+        self.capabilities.clone()
+    }
+}
+
+/// # Safety
+///
+/// `typed` must be a typed vtable exported by a loaded plugin.
+unsafe fn read_signature(typed: &TypedVTable) -> Result<Signature, String> {
+    if typed.version > TYPED_VERSION {
+        return Err(format!(
+            "plugin typed vtable version {} is newer than this host supports ({})",
+            typed.version, TYPED_VERSION
+        ));
+    }
+    let json = (typed.signature)();
+    if json.is_null() {
+        return Ok(Signature::default());
+    }
+    serde_json::from_str(&CStr::from_ptr(json).to_string_lossy())
+        .map_err(|e| format!("invalid plugin signature: {}", e))
+}
 
-            let caps_str = CStr::from_ptr(caps_ptr).to_string_lossy();
-            serde_json::from_str(&caps_str).unwrap_or_default()
+/// Open the plugin library at `path`, recording the load in the audit log
+pub fn open_plugin(dll_path: &Path) -> Result<PluginInstance, String> {
+    unsafe {
+        logging::debug("loading plugin library")
+            .field("path", dll_path.display().to_string())
+            .emit();
+
+        let library = Library::new(dll_path)
+            .map_err(|e| format!("Failed to load plugin {}: {}", dll_path.display(), e))?;
+
+        let plugin_vtable_fn: Symbol<unsafe extern "C" fn() -> PluginVTablePtr> =
+            library.get(b"plugin_vtable").map_err(|e| {
+                format!(
+                    "Failed to get plugin_vtable from {}: {}",
+                    dll_path.display(),
+                    e
+                )
+            })?;
+
+        let vtable = plugin_vtable_fn();
+
+        let mut plugin = PluginInstance::new(library, vtable)?;
+        plugin.path = Some(dll_path.to_path_buf());
+        if !audit::enabled() {
+            return Ok(plugin);
+        }
+        let mut entry = audit::event(audit::AuditKind::PluginLoad, plugin.info.name.as_str())
+            .detail("version", plugin.info.version.as_str())
+            .detail("path", dll_path.display().to_string());
+        if let Ok(sha256) = audit::file_sha256(dll_path) {
+            entry = entry.detail("sha256", sha256);
         }
+        entry.record();
+        Ok(plugin)
     }
 }
 
-#[derive(Debug)]
+// Output buffers of plugins that accept the allocator handshake come from
+// the host heap, so the host can free them without calling back into the plugin
+pub static HOST_ALLOCATOR: HostAllocator = HostAllocator {
+    alloc: host_alloc,
+    free: host_free,
+};
+
+unsafe extern "C" fn host_alloc(size: usize) -> *mut u8 {
+    match std::alloc::Layout::array::<u8>(size.max(1)) {
+        Ok(layout) => std::alloc::alloc(layout),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn host_free(ptr: *mut u8, size: usize) {
+    if ptr.is_null() {
+        return;
+    }
+    if let Ok(layout) = std::alloc::Layout::array::<u8>(size.max(1)) {
+        std::alloc::dealloc(ptr, layout);
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PluginRegistry {
     pub plugins: HashMap<String, PluginInstance>,
     pub plugin_versions: HashMap<String, Vec<String>>, // name -> versions
@@ -91,96 +460,94 @@ impl PluginRegistry {
 
     pub fn dynamic_registry(plugin_dir: &str) -> Self {
         let mut registry = PluginRegistry::new();
+        registry.register_plugin(crate::sinks::plugin());
         registry.load_plugins_from_directory(plugin_dir);
         registry
     }
 
-    /// Create a dynamic registry using the default plugin directory
+    /// Create a dynamic registry from the default plugin directories. A
+    /// plugin in an earlier directory shadows one of the same name later on.
     pub fn default_registry() -> Self {
-        let plugin_dir = PathUtils::plugin_dir();
-        Self::dynamic_registry(plugin_dir.to_str().unwrap_or("plugins"))
+        let mut registry = PluginRegistry::new();
+        registry.register_plugin(crate::sinks::plugin());
+        for plugin_dir in PathUtils::plugin_dirs().iter().rev() {
+            registry.load_plugins_from_directory(&plugin_dir.to_string_lossy());
+        }
+        registry
     }
 
     pub fn load_plugins_from_directory(&mut self, plugin_dir: &str) {
-        if let Ok(entries) = std::fs::read_dir(plugin_dir) {
-            for entry in entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.is_dir() {
-                    // Load any shared libraries within the subdirectory (.so/.dylib/.dll)
-                    if let Ok(files) = std::fs::read_dir(&path) {
-                        for f in files.filter_map(|e| e.ok()) {
-                            let fpath = f.path();
-                            if let Some(ext) = fpath.extension().and_then(|s| s.to_str()) {
-                                if self.is_shared_library_extension(ext) {
-                                    match self.load_plugin(&fpath) {
-                                        Ok(plugin) => {
-                                            self.register_plugin(plugin);
-                                        }
-                                        Err(e) => {
-                                            println!(
-                                                "[ERROR] Failed to load plugin {}: {}",
-                                                fpath.display(),
-                                                e
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                } else if self.is_shared_library_file(&path) {
-                    // Direct shared library loading across platforms
-                    match self.load_plugin(&path) {
-                        Ok(plugin) => {
-                            self.register_plugin(plugin);
-                        }
-                        Err(e) => {
-                            println!("[ERROR] Failed to load plugin {}: {}", path.display(), e);
-                        }
-                    }
+        self.load_plugins_with_cache(plugin_dir, &MetadataCache::default_path());
+    }
+
+    /// Register the libraries in `plugin_dir`. Libraries whose metadata is in
+    /// the cache at `cache_path` are registered without being opened and load
+    /// on first use; the rest are opened in parallel and added to the cache.
+    pub fn load_plugins_with_cache(&mut self, plugin_dir: &str, cache_path: &Path) {
+        let started = std::time::Instant::now();
+        let mut cache = MetadataCache::load(cache_path);
+        let mut found = Vec::new();
+        let mut pending = Vec::new();
+        for path in discover_libraries(Path::new(plugin_dir)) {
+            match cache.lookup(&path) {
+                Some(cached) => {
+                    let plugin =
+                        PluginInstance::deferred(path.clone(), cached.info, cached.capabilities)
+                            .with_signature(cached.signature);
+                    found.push((path, Ok(plugin)));
                 }
+                None => pending.push(path),
             }
         }
-    }
 
-    /// Check if file extension is a shared library extension for current platform
-    fn is_shared_library_extension(&self, ext: &str) -> bool {
-        Platform::is_shared_lib_extension(ext)
-    }
+        let opened = open_in_parallel(&pending);
+        let cache_changed = !opened.is_empty();
+        for (path, result) in &opened {
+            if let Ok(plugin) = result {
+                cache.insert(path, plugin);
+            }
+        }
+        found.extend(opened);
+        // WebAssembly modules are compiled on first call, so opening them is cheap
+        found.extend(
+            discover_files(Path::new(plugin_dir), crate::wasm_plugins::is_module)
+                .into_iter()
+                .map(|path| {
+                    let plugin = crate::wasm_plugins::open(&path);
+                    (path, plugin)
+                }),
+        );
+        // Register in path order so duplicate names resolve the same way every time
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, result) in found {
+            match result {
+                Ok(plugin) => self.register_plugin(plugin),
+                Err(e) => {
+                    logging::error("failed to load plugin")
+                        .field("path", path.display().to_string())
+                        .field("error", e)
+                        .emit();
+                }
+            }
+        }
 
-    /// Check if file is a shared library for current platform
-    fn is_shared_library_file(&self, path: &std::path::Path) -> bool {
-        Platform::is_shared_lib_file(path)
+        if cache_changed {
+            if let Err(e) = cache.save(cache_path) {
+                logging::warn("failed to save plugin metadata cache")
+                    .field("path", cache_path.display().to_string())
+                    .field("error", e.to_string())
+                    .emit();
+            }
+        }
+        logging::debug("plugin registry ready")
+            .field("plugins", self.plugins.len())
+            .field("opened", pending.len())
+            .field("elapsed_ms", started.elapsed().as_millis() as u64)
+            .emit();
     }
 
     pub fn load_plugin(&self, dll_path: &Path) -> Result<PluginInstance, String> {
-        unsafe {
-            println!("[DEBUG] Loading plugin from: {}", dll_path.display());
-
-            let library = Library::new(dll_path)
-                .map_err(|e| format!("Failed to load plugin {}: {}", dll_path.display(), e))?;
-
-            println!("[DEBUG] Library loaded successfully");
-
-            let plugin_vtable_fn: Symbol<unsafe extern "C" fn() -> PluginVTablePtr> =
-                library.get(b"plugin_vtable").map_err(|e| {
-                    format!(
-                        "Failed to get plugin_vtable from {}: {}",
-                        dll_path.display(),
-                        e
-                    )
-                })?;
-
-            println!("[DEBUG] Got plugin_vtable function");
-
-            let vtable = plugin_vtable_fn();
-            println!(
-                "[DEBUG] Called plugin_vtable function, got pointer: {:?}",
-                vtable
-            );
-
-            PluginInstance::new(library, vtable)
-        }
+        open_plugin(dll_path)
     }
 
     pub fn register_plugin(&mut self, plugin: PluginInstance) {
@@ -200,7 +567,10 @@ impl PluginRegistry {
         // Track dependencies
         self.plugin_dependencies.insert(name.clone(), dependencies);
 
-        println!("[DIAG] Loaded plugin: {}", name);
+        logging::debug("loaded plugin")
+            .field("plugin", name.as_str())
+            .field("version", self.plugin_versions[&name].last().cloned())
+            .emit();
     }
 
     pub fn get(&self, name: &str) -> Option<&PluginInstance> {
@@ -314,3 +684,322 @@ impl PluginRegistry {
         Ok(())
     }
 }
+
+// Shared libraries directly in `plugin_dir` or one directory below it
+fn discover_libraries(plugin_dir: &Path) -> Vec<PathBuf> {
+    discover_files(plugin_dir, Platform::is_shared_lib_file)
+}
+
+// Files in `plugin_dir` or its subdirectories that `matches`
+fn discover_files(plugin_dir: &Path, matches: fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut libraries = Vec::new();
+    let Ok(entries) = std::fs::read_dir(plugin_dir) else {
+        return libraries;
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if path.is_dir() {
+            if let Ok(files) = std::fs::read_dir(&path) {
+                libraries.extend(
+                    files
+                        .filter_map(|e| e.ok())
+                        .map(|f| f.path())
+                        .filter(|f| matches(f)),
+                );
+            }
+        } else if matches(&path) {
+            libraries.push(path);
+        }
+    }
+    libraries
+}
+
+// Open `paths` across the available cores
+fn open_in_parallel(paths: &[PathBuf]) -> Vec<(PathBuf, Result<PluginInstance, String>)> {
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    let workers = std::thread::available_parallelism()
+        .map_or(4, |n| n.get())
+        .min(paths.len());
+    let chunk_size = paths.len().div_ceil(workers);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| (path.clone(), open_plugin(path)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    })
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct CachedPlugin {
+    size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    modified: u64,
+    info: PluginInfo,
+    #[serde(default)]
+    capabilities: Vec<PluginCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<Signature>,
+}
+
+/// Parsed metadata of plugin libraries, so startup doesn't have to open
+/// every plugin. Entries are keyed by path and dropped when the library's
+/// size or modification time changes.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MetadataCache {
+    #[serde(default)]
+    entries: HashMap<String, CachedPlugin>,
+}
+
+impl MetadataCache {
+    /// plugin_metadata.json in the LAO cache directory (LAO_CACHE_DIR)
+    pub fn default_path() -> PathBuf {
+        PathUtils::cache_dir().join("plugin_metadata.json")
+    }
+
+    /// The cache at `path`; a missing or unreadable file gives an empty cache
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(self)?)
+    }
+
+    fn lookup(&self, library: &Path) -> Option<CachedPlugin> {
+        let (size, modified) = file_stamp(library)?;
+        self.entries
+            .get(&library.to_string_lossy().to_string())
+            .filter(|entry| entry.size == size && entry.modified == modified)
+            .cloned()
+    }
+
+    fn insert(&mut self, library: &Path, plugin: &PluginInstance) {
+        let Some((size, modified)) = file_stamp(library) else {
+            return;
+        };
+        self.entries.insert(
+            library.to_string_lossy().to_string(),
+            CachedPlugin {
+                size,
+                modified,
+                info: plugin.info.clone(),
+                capabilities: plugin.get_capabilities(),
+                signature: plugin.signature.clone(),
+            },
+        );
+    }
+}
+
+fn file_stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+/// A plugin registry shared by the UI, CLI, daemon triggers and executors.
+///
+/// Readers take a `snapshot`, an immutable registry they use without holding
+/// any lock, so a run keeps the plugins it started with. `load`, `unload`
+/// and `reload` build a new registry and swap it in without pausing readers.
+/// Each PluginInstance holds its library through an Arc, so an unloaded or
+/// replaced library is only closed once the last snapshot using it, and with
+/// it the last in-flight vtable call, is dropped.
+pub struct SharedPluginRegistry {
+    current: RwLock<Arc<PluginRegistry>>,
+    // Writers are serialized so concurrent loads don't drop each other's changes
+    writer: Mutex<()>,
+}
+
+impl SharedPluginRegistry {
+    pub fn new(registry: PluginRegistry) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(registry)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<PluginRegistry> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // Apply `change` to a copy of the current registry and publish it
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut PluginRegistry) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut next = (*self.snapshot()).clone();
+        let result = change(&mut next)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(next);
+        Ok(result)
+    }
+
+    /// Load the library at `path`, replacing any plugin with the same name
+    pub fn load(&self, path: &Path) -> Result<PluginInfo, String> {
+        self.update(|registry| {
+            let plugin = registry.load_plugin(path)?;
+            let info = plugin.info.clone();
+            registry.register_plugin(plugin);
+            Ok(info)
+        })
+    }
+
+    pub fn unload(&self, name: &str) -> Result<(), String> {
+        self.update(|registry| {
+            if !registry.plugins.contains_key(name) {
+                return Err(format!("Plugin {} not found", name));
+            }
+            registry.remove_plugin(name)
+        })
+    }
+
+    /// Load a plugin again from the file it came from. The platform loader
+    /// hands back the mapped library while an older snapshot still uses it,
+    /// so a rebuilt file takes effect once earlier runs have finished.
+    pub fn reload(&self, name: &str) -> Result<PluginInfo, String> {
+        let path = self
+            .snapshot()
+            .get(name)
+            .ok_or_else(|| format!("Plugin {} not found", name))?
+            .path
+            .clone()
+            .ok_or_else(|| format!("Plugin {} was not loaded from a file", name))?;
+        self.load(&path)
+    }
+
+    /// Replace everything with the plugins currently in `plugin_dir`
+    pub fn reload_all(&self, plugin_dir: &str) {
+        let _ = self.update(|registry| {
+            *registry = PluginRegistry::dynamic_registry(plugin_dir);
+            Ok(())
+        });
+    }
+}
+
+static SHARED: OnceLock<SharedPluginRegistry> = OnceLock::new();
+
+/// The process-wide registry, loaded from the default plugin directory on first use
+pub fn shared() -> &'static SharedPluginRegistry {
+    SHARED.get_or_init(|| SharedPluginRegistry::new(PluginRegistry::default_registry()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_registry_snapshots_are_isolated() {
+        let dir = std::env::temp_dir().join(format!("lao_shared_plugins_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let shared = SharedPluginRegistry::new(PluginRegistry::new());
+        let before = shared.snapshot();
+
+        // Failed writes leave the published registry untouched
+        assert!(shared.unload("EchoPlugin").is_err());
+        assert!(shared.reload("EchoPlugin").is_err());
+        assert!(shared.load(&dir.join("missing.so")).is_err());
+        assert!(Arc::ptr_eq(&before, &shared.snapshot()));
+
+        // A successful write publishes a new registry; old snapshots stay valid
+        shared.reload_all(dir.to_str().unwrap());
+        let after = shared.snapshot();
+        assert!(!Arc::ptr_eq(&before, &after));
+        assert!(before.plugins.is_empty());
+        // An empty directory leaves only the host's built-in `sink`
+        assert_eq!(
+            after.plugins.keys().collect::<Vec<_>>(),
+            [crate::sinks::RUNNER]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cached_plugins_load_on_first_use() {
+        let dir = std::env::temp_dir().join(format!("lao_plugin_cache_{}", uuid::Uuid::new_v4()));
+        let plugin_dir = dir.join("plugins");
+        std::fs::create_dir_all(plugin_dir.join("Fake")).unwrap();
+        let library = plugin_dir
+            .join("Fake")
+            .join(Platform::shared_lib_name("fake"));
+        std::fs::write(&library, b"not really a library").unwrap();
+        let cache_path = dir.join("plugin_metadata.json");
+
+        // Not cached yet: the library is opened, fails, and stays out of the cache
+        let mut registry = PluginRegistry::new();
+        registry.load_plugins_with_cache(plugin_dir.to_str().unwrap(), &cache_path);
+        assert!(registry.plugins.is_empty());
+        assert!(MetadataCache::load(&cache_path).lookup(&library).is_none());
+
+        let (size, modified) = file_stamp(&library).unwrap();
+        let info: PluginInfo = serde_json::from_value(serde_json::json!({
+            "name": "FakePlugin", "version": "1.0.0", "description": "", "author": "",
+            "dependencies": [], "tags": [], "capabilities": [],
+            "input_schema": null, "output_schema": null
+        }))
+        .unwrap();
+        let mut cache = MetadataCache::default();
+        cache.entries.insert(
+            library.to_string_lossy().to_string(),
+            CachedPlugin {
+                size,
+                modified,
+                info,
+                capabilities: Vec::new(),
+                signature: None,
+            },
+        );
+        cache.save(&cache_path).unwrap();
+
+        let mut registry = PluginRegistry::new();
+        registry.load_plugins_with_cache(plugin_dir.to_str().unwrap(), &cache_path);
+        let plugin = registry
+            .get("FakePlugin")
+            .expect("registered from the cache");
+        assert!(!plugin.is_loaded());
+        assert!(plugin.call("hello").is_err());
+        assert!(plugin.is_loaded());
+
+        // A changed library no longer matches its cache entry
+        std::fs::write(&library, b"rebuilt, and longer than before").unwrap();
+        assert!(MetadataCache::load(&cache_path).lookup(&library).is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_host_allocator_round_trip() {
+        unsafe {
+            assert!(accept_host_allocator(&HOST_ALLOCATOR));
+            let output = PluginOutput::new("from the host heap");
+            assert_eq!(output.as_bytes(), b"from the host heap");
+            assert_eq!(*output.text.add(output.len), 0);
+            (HOST_ALLOCATOR.free)(output.text as *mut u8, output.len + 1);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+// Prefix caching hints for LLM requests.
+// The hints each server kind gets are described in docs/workflows.md (Prefix
+// Caching).
+use crate::backends::BackendKind;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+const DEFAULT_MIN_SHARED: usize = 1000;
+const DEFAULT_KEEP_ALIVE: &str = "30m";
+// Servers and models whose last prompts are kept
+const MAX_TRACKED: usize = 32;
+
+#[derive(Default)]
+struct Slot {
+    prompt: String,
+    used: u64,
+}
+
+/// The last prompts sent to each server and model
+pub struct PrefixCache {
+    min_shared: usize,
+    keep_alive: String,
+    tracked: Mutex<HashMap<String, Vec<Slot>>>,
+    clock: Mutex<u64>,
+}
+
+impl PrefixCache {
+    pub fn new(min_shared: usize, keep_alive: &str) -> Self {
+        Self {
+            min_shared,
+            keep_alive: keep_alive.to_string(),
+            tracked: Mutex::new(HashMap::new()),
+            clock: Mutex::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+        Self::new(
+            env("LAO_PREFIX_CACHE_MIN")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MIN_SHARED),
+            &env("LAO_PREFIX_KEEP_ALIVE").unwrap_or_else(|| DEFAULT_KEEP_ALIVE.to_string()),
+        )
+    }
+
+    /// Add hints to `body`, an Ollama API request for `path` to `server`,
+    /// a server of `kind` with `slots` slots. Returns the characters shared
+    /// with an earlier prompt, if enough to count.
+    pub fn hint(
+        &self,
+        server: &str,
+        kind: BackendKind,
+        slots: usize,
+        path: &str,
+        body: &mut Value,
+    ) -> Option<usize> {
+        if self.min_shared == 0 {
+            return None;
+        }
+        let prompt = prompt_of(path, body)?;
+        if kind == BackendKind::Llamacpp {
+            body["cache_prompt"] = json!(true);
+        }
+        let key = format!("{}|{}", server, body["model"].as_str().unwrap_or_default());
+        let now = {
+            let mut clock = self.clock.lock().unwrap_or_else(|e| e.into_inner());
+            *clock += 1;
+            *clock
+        };
+
+        let mut tracked = self.tracked.lock().unwrap_or_else(|e| e.into_inner());
+        if !tracked.contains_key(&key) && tracked.len() >= MAX_TRACKED {
+            let oldest = tracked
+                .iter()
+                .min_by_key(|(_, slots)| slots.iter().map(|s| s.used).max())
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                tracked.remove(&oldest);
+            }
+        }
+        let entry = tracked.entry(key).or_default();
+        entry.resize_with(slots.max(1), Slot::default);
+        let (slot, shared) = entry
+            .iter()
+            .enumerate()
+            .map(|(i, slot)| (i, shared_prefix(&slot.prompt, &prompt)))
+            .max_by_key(|&(i, shared)| (shared, std::cmp::Reverse(entry[i].used)))
+            .unwrap_or((0, 0));
+        let shared = (shared >= self.min_shared).then_some(shared);
+        // Without a shared prefix, the least recently used slot is given up
+        let slot = match shared {
+            Some(_) => slot,
+            None => (0..entry.len()).min_by_key(|&i| entry[i].used).unwrap_or(0),
+        };
+        entry[slot] = Slot { prompt, used: now };
+
+        match kind {
+            BackendKind::Ollama if shared.is_some() && body.get("keep_alive").is_none() => {
+                body["keep_alive"] = json!(self.keep_alive);
+            }
+            BackendKind::Llamacpp if slots > 1 => body["id_slot"] = json!(slot),
+            _ => {}
+        }
+        shared
+    }
+}
+
+/// Hints for the requests of this process
+pub fn global() -> &'static PrefixCache {
+    static CACHE: OnceLock<PrefixCache> = OnceLock::new();
+    CACHE.get_or_init(PrefixCache::from_env)
+}
+
+// The text a generate or chat request makes the model read, in order
+fn prompt_of(path: &str, body: &Value) -> Option<String> {
+    match path {
+        "/api/generate" => {
+            let prompt = body["prompt"].as_str()?;
+            Some(match body["system"].as_str() {
+                Some(system) => format!("{}\n\n{}", system, prompt),
+                None => prompt.to_string(),
+            })
+        }
+        "/api/chat" => Some(
+            body["messages"]
+                .as_array()?
+                .iter()
+                .map(|m| {
+                    format!(
+                        "{}: {}",
+                        m["role"].as_str().unwrap_or_default(),
+                        m["content"].as_str().unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        _ => None,
+    }
+}
+
+// Length in bytes of the common prefix, cut back to a char boundary
+fn shared_prefix(a: &str, b: &str) -> usize {
+    let mut len = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    while !b.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> String {
+        "A long report about the quarterly numbers. ".repeat(2)
+    }
+
+    fn generate(question: &str) -> serde_json::Value {
+        json!({"model": "llama3", "system": "Be brief.", "prompt": format!("{}{}", document(), question)})
+    }
+
+    fn hint(cache: &PrefixCache, request: &mut serde_json::Value) -> Option<usize> {
+        cache.hint("ollama", BackendKind::Ollama, 1, "/api/generate", request)
+    }
+
+    #[test]
+    fn test_first_prompt_gets_no_hint() {
+        let cache = PrefixCache::new(20, "5m");
+        let mut first = generate("Who wrote it?");
+        assert_eq!(hint(&cache, &mut first), None);
+        assert!(first.get("keep_alive").is_none());
+    }
+
+    #[test]
+    fn test_shared_prefix_keeps_the_model_loaded() {
+        let cache = PrefixCache::new(20, "5m");
+        hint(&cache, &mut generate("Who wrote it?"));
+        let mut second = generate("Is it due soon?");
+        assert_eq!(
+            hint(&cache, &mut second),
+            Some("Be brief.\n\n".len() + document().len())
+        );
+        assert_eq!(second["keep_alive"], "5m");
+    }
+
+    #[test]
+    fn test_other_models_share_no_prefix() {
+        let cache = PrefixCache::new(20, "5m");
+        let mut first = generate("Who wrote it?");
+        hint(&cache, &mut first);
+        let mut other = json!({"model": "mistral", "prompt": first["prompt"]});
+        assert_eq!(hint(&cache, &mut other), None);
+    }
+
+    #[test]
+    fn test_llamacpp_prompts_go_to_the_slot_holding_their_prefix() {
+        let cache = PrefixCache::new(20, "5m");
+        let mut a = generate("Summarize it.");
+        cache.hint("tiny", BackendKind::Llamacpp, 2, "/api/generate", &mut a);
+        let mut b =
+            json!({"model": "llama3", "prompt": "Something else entirely, and long enough."});
+        cache.hint("tiny", BackendKind::Llamacpp, 2, "/api/generate", &mut b);
+        assert_ne!(a["id_slot"], b["id_slot"]);
+        let mut c = generate("List the risks.");
+        assert!(cache
+            .hint("tiny", BackendKind::Llamacpp, 2, "/api/generate", &mut c)
+            .is_some());
+        assert_eq!(c["id_slot"], a["id_slot"]);
+        assert_eq!(c["cache_prompt"], true);
+    }
+}
@@ -0,0 +1,238 @@
+// Node presets: a configured step saved under a name.
+// Saving and using presets: docs/workflows.md (Node Presets).
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+pub const INPUT_PLACEHOLDER: &str = "{input}";
+
+pub fn default_dir() -> PathBuf {
+    std::env::var("LAO_PRESET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("presets"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub description: Option<String>,
+    /// Step fields given to steps that use the preset
+    pub fields: Mapping,
+}
+
+impl Preset {
+    pub fn run(&self) -> Option<&str> {
+        self.fields.get("run").and_then(Value::as_str)
+    }
+
+    pub fn template(&self) -> Option<&str> {
+        self.fields.get("template").and_then(Value::as_str)
+    }
+
+    /// `input` as the preset's plugin would be given it
+    pub fn input(&self, input: &str) -> String {
+        match self.template() {
+            Some(template) => fill(template, input),
+            None => input.to_string(),
+        }
+    }
+}
+
+/// `template` with `input` in place of `{input}`
+pub fn fill(template: &str, input: &str) -> String {
+    template.replace(INPUT_PLACEHOLDER, input)
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid preset name '{}': use letters, digits, '-' and '_'",
+            name
+        ))
+    }
+}
+
+pub fn load(dir: &Path, name: &str) -> Result<Preset, String> {
+    check_name(name)?;
+    let path = dir.join(format!("{}.yaml", name));
+    let text = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("No preset named '{}' in {}", name, dir.display()),
+        _ => format!("Cannot read {}: {}", path.display(), e),
+    })?;
+    let Value::Mapping(mut fields) =
+        serde_yaml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+    else {
+        return Err(format!("{}: a preset must be a mapping", path.display()));
+    };
+    let description = fields
+        .shift_remove("description")
+        .and_then(|d| d.as_str().map(str::to_string));
+    if fields.get("run").and_then(Value::as_str).is_none() {
+        return Err(format!("{}: a preset must set run", path.display()));
+    }
+    Ok(Preset {
+        name: name.to_string(),
+        description,
+        fields,
+    })
+}
+
+pub fn save(dir: &Path, preset: &Preset) -> Result<PathBuf, String> {
+    check_name(&preset.name)?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let mut fields = Mapping::new();
+    if let Some(description) = &preset.description {
+        fields.insert("description".into(), description.as_str().into());
+    }
+    fields.extend(preset.fields.clone());
+    let path = dir.join(format!("{}.yaml", preset.name));
+    let text = serde_yaml::to_string(&fields).map_err(|e| e.to_string())?;
+    std::fs::write(&path, text).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Presets under `dir` by name; files that aren't valid presets are left out
+pub fn list(dir: &Path) -> Vec<Preset> {
+    let mut presets: Vec<Preset> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            (path.extension()? == "yaml")
+                .then(|| load(dir, path.file_stem()?.to_str()?).ok())
+                .flatten()
+        })
+        .collect();
+    presets.sort_by(|a, b| a.name.cmp(&b.name));
+    presets
+}
+
+/// Whether any step of `workflow` names a preset
+pub fn used(workflow: &Value) -> bool {
+    workflow
+        .get("steps")
+        .and_then(Value::as_sequence)
+        .is_some_and(|steps| steps.iter().any(|s| s.get("preset").is_some()))
+}
+
+/// Merge the preset of each step that names one, from `dir`, under the
+/// step's own fields. `preset` stays on the step to show where it came from.
+pub fn expand(workflow: &mut Value, dir: &Path) -> Result<(), String> {
+    let Some(steps) = workflow.get_mut("steps").and_then(Value::as_sequence_mut) else {
+        return Ok(());
+    };
+    for (index, step) in steps.iter_mut().enumerate() {
+        let Some(name) = step.get("preset") else {
+            continue;
+        };
+        let name = name
+            .as_str()
+            .ok_or_else(|| format!("step{}: preset must be a name", index + 1))?;
+        let preset = load(dir, name).map_err(|e| format!("step{}: {}", index + 1, e))?;
+        let Value::Mapping(own) = std::mem::take(step) else {
+            return Err(format!("step{}: a step must be a mapping", index + 1));
+        };
+        let mut merged = preset.fields;
+        merged.extend(own);
+        *step = Value::Mapping(merged);
+    }
+    Ok(())
+}
+
+/// Wrap the step's input in its template, in the params too
+pub(crate) fn apply_template(
+    template: Option<&str>,
+    params: &mut serde_yaml::Value,
+    input: &mut String,
+) {
+    if let Some(template) = template {
+        let filled = fill(template, input);
+        crate::replace_input(params, input, filled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::collections::HashMap;
+
+    fn shout() -> Preset {
+        Preset {
+            name: "shout".to_string(),
+            description: Some("Says it loudly".to_string()),
+            fields: serde_yaml::from_str(
+                "run: EchoPlugin\nretries: 2\ntemplate: \"LOUD {input}\"\n",
+            )
+            .unwrap(),
+        }
+    }
+
+    /// A temp dir holding the `shout` preset in presets/.
+    fn presets_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lao_presets_{}", uuid::Uuid::new_v4()));
+        save(&dir.join("presets"), &shout()).unwrap();
+        dir
+    }
+
+    fn workflow() -> Value {
+        serde_yaml::from_str(
+            "workflow: p\nsteps:\n  - run: EchoPlugin\n    input: hi\n  - run: EchoPlugin\n    input_from: step1\n    preset: shout\n    retries: 0\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_save_and_load_presets() {
+        let dir = presets_dir();
+        let presets = dir.join("presets");
+        assert_eq!(load(&presets, "shout").unwrap(), shout());
+        assert_eq!(list(&presets).len(), 1);
+        assert!(load(&presets, "../shout").is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_template_wraps_input() {
+        assert_eq!(shout().input("hi"), "LOUD hi");
+    }
+
+    #[test]
+    fn test_expand_fills_fields_the_step_leaves_unset() {
+        let dir = presets_dir();
+        let mut workflow = workflow();
+        assert!(used(&workflow));
+        expand(&mut workflow, &dir.join("presets")).unwrap();
+        let step = &workflow["steps"][1];
+        assert_eq!(step["retries"], 0);
+        assert_eq!(step["template"], "LOUD {input}");
+        assert_eq!(step["preset"], "shout");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_expanded_steps_run_with_their_template() {
+        let dir = presets_dir();
+        let mut workflow = workflow();
+        expand(&mut workflow, &dir.join("presets")).unwrap();
+        let mut workflow: crate::Workflow = serde_yaml::from_value(workflow).unwrap();
+        // Already merged; left in, loading would look in the default directory
+        workflow.steps[1].preset = None;
+        let path = dir.join("p.yaml");
+        std::fs::write(&path, serde_yaml::to_string(&workflow).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        let logs = crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+        assert_eq!(
+            logs[1].output.as_deref(),
+            Some("EchoPlugin(LOUD EchoPlugin(hi))")
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
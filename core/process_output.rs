@@ -0,0 +1,154 @@
+// Output of the commands plugins run, in the run's logs.
+use lao_plugin_api::{HostProcessOutput, PluginInput, STDOUT};
+use std::cell::RefCell;
+
+const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+fn max_bytes() -> usize {
+    std::env::var("LAO_PROCESS_OUTPUT_MAX_BYTES")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_BYTES)
+}
+
+struct StepOutput {
+    run_id: String,
+    step_id: String,
+    logged: usize,
+    max_bytes: usize,
+    truncated: bool,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<StepOutput>> = const { RefCell::new(None) };
+}
+
+/// While alive, process output on this thread belongs to `step_id` of `run_id`
+pub struct StepScope {
+    previous: Option<StepOutput>,
+}
+
+impl StepScope {
+    pub fn enter(run_id: &str, step_id: &str) -> Self {
+        let step = StepOutput {
+            run_id: run_id.to_string(),
+            step_id: step_id.to_string(),
+            logged: 0,
+            max_bytes: max_bytes(),
+            truncated: false,
+        };
+        Self {
+            previous: CURRENT.with(|current| current.replace(Some(step))),
+        }
+    }
+}
+
+impl Drop for StepScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Log `line`, written to `stream` by a command of the current step
+pub fn record(stream: u32, line: &str) {
+    let stream = if stream == STDOUT { "stdout" } else { "stderr" };
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        let Some(step) = current.as_mut() else {
+            log::debug!("process output ({}): {}", stream, line);
+            return;
+        };
+        if step.truncated {
+            return;
+        }
+        if step.logged + line.len() > step.max_bytes {
+            step.truncated = true;
+            crate::logging::warn("process output truncated")
+                .run(step.run_id.as_str())
+                .step(step.step_id.as_str())
+                .field("max_bytes", step.max_bytes)
+                .emit();
+            return;
+        }
+        step.logged += line.len();
+        crate::logging::info("process output")
+            .run(step.run_id.as_str())
+            .step(step.step_id.as_str())
+            .field("stream", stream)
+            .field("line", line)
+            .emit();
+    });
+}
+
+/// Handed to plugins that export `plugin_process_output`
+pub static HOST_PROCESS_OUTPUT: HostProcessOutput = HostProcessOutput {
+    line: host_process_line,
+};
+
+unsafe extern "C" fn host_process_line(stream: u32, text: *const PluginInput) {
+    if !text.is_null() {
+        record(stream, &(*text).to_string_lossy());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{self, LogEvent};
+    use lao_plugin_api::STDERR;
+
+    #[test]
+    fn test_process_output_is_logged_per_step_up_to_a_cap() {
+        let run_id = format!("process-{}", uuid::Uuid::new_v4());
+        let events = logging::subscribe();
+        {
+            let _scope = StepScope::enter(&run_id, "step2");
+            CURRENT.with(|current| current.borrow_mut().as_mut().unwrap().max_bytes = 24);
+            record(STDERR, "loading model");
+            record(STDOUT, "hello world");
+            record(STDOUT, "dropped");
+        }
+        record(STDOUT, "outside any step");
+
+        let ours: Vec<LogEvent> = events
+            .try_iter()
+            .filter(|event| event.run_id.as_deref() == Some(run_id.as_str()))
+            .collect();
+        assert_eq!(ours.len(), 3);
+        assert_eq!(ours[0].message, "process output");
+        assert_eq!(ours[0].step_id.as_deref(), Some("step2"));
+        assert_eq!(ours[0].field_str("stream"), Some("stderr"));
+        assert_eq!(ours[0].field_str("line"), Some("loading model"));
+        assert_eq!(ours[1].field_str("stream"), Some("stdout"));
+        assert_eq!(ours[2].message, "process output truncated");
+        CURRENT.with(|current| assert!(current.borrow().is_none()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plugin_processes_report_their_output() {
+        let run_id = format!("process-{}", uuid::Uuid::new_v4());
+        let events = logging::subscribe();
+        unsafe { lao_plugin_api::accept_host_process_output(&HOST_PROCESS_OUTPUT) };
+        let _scope = StepScope::enter(&run_id, "step3");
+        let output = lao_plugin_api::run_process(
+            std::process::Command::new("sh").args(["-c", "echo transcript; echo progress >&2"]),
+        )
+        .unwrap();
+        assert_eq!(output.stdout, "transcript\n");
+        let lines: Vec<(String, String)> = events
+            .try_iter()
+            .filter(|event| event.run_id.as_deref() == Some(run_id.as_str()))
+            .map(|event| {
+                let stream = event.field_str("stream").unwrap_or_default().to_string();
+                (
+                    stream,
+                    event.field_str("line").unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
+        assert!(lines.contains(&("stdout".to_string(), "transcript".to_string())));
+        assert!(lines.contains(&("stderr".to_string(), "progress".to_string())));
+    }
+}
@@ -0,0 +1,404 @@
+// Prompt library: named, versioned prompt files.
+// Prompt references such as `prompts/x.md@v2` are described in
+// docs/workflows.md (Prompt Files).
+use crate::audit::sha256_hex;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+pub const VERSIONS_DIR: &str = "versions";
+
+pub fn default_dir() -> PathBuf {
+    std::env::var("LAO_PROMPT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("prompts"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// The prompt file as it is now
+    Working,
+    /// The highest saved version
+    Latest,
+    Number(u32),
+}
+
+impl Version {
+    /// `working`, `latest`, `v2` or `2`
+    pub fn parse(version: &str) -> Result<Self, String> {
+        match version.trim() {
+            "working" => Ok(Self::Working),
+            "latest" => Ok(Self::Latest),
+            v => v
+                .strip_prefix('v')
+                .unwrap_or(v)
+                .parse()
+                .map(Self::Number)
+                .map_err(|_| format!("'{}' is not a prompt version (v2, latest)", version)),
+        }
+    }
+}
+
+/// `prompts/summarize.md@v2` split into the file and the version
+pub fn parse_reference(reference: &str) -> Result<(PathBuf, Version), String> {
+    match reference.rsplit_once('@') {
+        Some((path, version)) if !version.contains('/') => {
+            Ok((PathBuf::from(path), Version::parse(version)?))
+        }
+        _ => Ok((PathBuf::from(reference), Version::Working)),
+    }
+}
+
+fn versions_dir(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    path.parent()
+        .unwrap_or(Path::new(""))
+        .join(VERSIONS_DIR)
+        .join(stem)
+}
+
+fn version_path(path: &Path, number: u32) -> PathBuf {
+    let file = match path.extension() {
+        Some(extension) => format!("v{}.{}", number, extension.to_string_lossy()),
+        None => format!("v{}", number),
+    };
+    versions_dir(path).join(file)
+}
+
+/// Saved versions of the prompt at `path`, oldest first
+pub fn versions(path: &Path) -> Vec<u32> {
+    let mut numbers: Vec<u32> = std::fs::read_dir(versions_dir(path))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            path.file_stem()?.to_str()?.strip_prefix('v')?.parse().ok()
+        })
+        .collect();
+    numbers.sort_unstable();
+    numbers
+}
+
+/// Text of the prompt at `path` in `version`, and the version number
+pub fn read(path: &Path, version: Version) -> Result<(String, Option<u32>), String> {
+    let number = match version {
+        Version::Working => None,
+        Version::Latest => Some(
+            versions(path)
+                .last()
+                .copied()
+                .ok_or_else(|| format!("{} has no saved versions", path.display()))?,
+        ),
+        Version::Number(number) => Some(number),
+    };
+    let file = number.map_or_else(|| path.to_path_buf(), |n| version_path(path, n));
+    let text = std::fs::read_to_string(&file).map_err(|e| match (e.kind(), number) {
+        (std::io::ErrorKind::NotFound, Some(n)) => {
+            format!("{} has no version v{}", path.display(), n)
+        }
+        _ => format!("Cannot read {}: {}", file.display(), e),
+    })?;
+    Ok((text, number))
+}
+
+/// Text of the prompt `reference` names
+pub fn resolve(reference: &str) -> Result<String, String> {
+    let (path, version) = parse_reference(reference)?;
+    read(&path, version).map(|(text, _)| text)
+}
+
+/// Save the working copy at `path` as a new version if it differs from the
+/// latest one; the new version's number
+pub fn save_version(path: &Path) -> Result<Option<u32>, String> {
+    let (text, _) = read(path, Version::Working)?;
+    let latest = versions(path).last().copied();
+    if let Some(latest) = latest {
+        if read(path, Version::Number(latest))?.0 == text {
+            return Ok(None);
+        }
+    }
+    let number = latest.unwrap_or(0) + 1;
+    let file = version_path(path, number);
+    let dir = versions_dir(path);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    std::fs::write(&file, text).map_err(|e| format!("Cannot write {}: {}", file.display(), e))?;
+    Ok(Some(number))
+}
+
+/// A prompt file and its saved versions
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub versions: Vec<u32>,
+    /// Whether the working copy differs from the latest version
+    pub edited: bool,
+}
+
+impl PromptFile {
+    fn at(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_string();
+        let versions = versions(&path);
+        let working = read(&path, Version::Working).ok()?.0;
+        let edited = match versions.last() {
+            Some(&latest) => read(&path, Version::Number(latest)).ok()?.0 != working,
+            None => true,
+        };
+        Some(Self {
+            name,
+            path,
+            versions,
+            edited,
+        })
+    }
+}
+
+/// Prompt files under `dir` by name
+pub fn list(dir: &Path) -> Vec<PromptFile> {
+    let mut prompts: Vec<PromptFile> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(PromptFile::at)
+        .collect();
+    prompts.sort_by(|a, b| a.name.cmp(&b.name));
+    prompts
+}
+
+/// The file in `dir` called `name`, with or without its extension; a new
+/// `<name>.md` if there is none
+pub fn find(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("Invalid prompt name '{}'", name));
+    }
+    let exact = dir.join(name);
+    if exact.is_file() {
+        return Ok(exact);
+    }
+    Ok(list(dir)
+        .into_iter()
+        .find(|p| p.path.file_stem().and_then(|s| s.to_str()) == Some(name))
+        .map_or_else(|| dir.join(format!("{}.md", name)), |p| p.path))
+}
+
+/// Whether `prompt` names a prompt file rather than being the prompt
+pub fn is_reference(prompt: &str) -> bool {
+    let path = prompt.rsplit_once('@').map_or(prompt, |(path, _)| path);
+    !prompt.is_empty()
+        && !prompt.contains(char::is_whitespace)
+        && (path.ends_with(".md") || path.ends_with(".txt"))
+}
+
+/// Whether any step of `workflow` names a prompt file
+pub fn used(workflow: &Value) -> bool {
+    workflow
+        .get("steps")
+        .and_then(Value::as_sequence)
+        .is_some_and(|steps| {
+            steps.iter().any(|s| {
+                s.get("prompt_file").is_some()
+                    || s.get("prompt")
+                        .and_then(Value::as_str)
+                        .is_some_and(is_reference)
+            })
+        })
+}
+
+/// Read the prompt file of each step that names one into its `prompt`,
+/// keeping the reference as `prompt_file`
+pub fn expand(workflow: &mut Value) -> Result<(), String> {
+    let Some(steps) = workflow.get_mut("steps").and_then(Value::as_sequence_mut) else {
+        return Ok(());
+    };
+    for (index, step) in steps.iter_mut().enumerate() {
+        let Some(step) = step.as_mapping_mut() else {
+            continue;
+        };
+        let named = step
+            .get("prompt")
+            .and_then(Value::as_str)
+            .filter(|p| is_reference(p))
+            .map(str::to_string);
+        if let Some(reference) = named {
+            step.insert("prompt_file".into(), reference.into());
+        }
+        let Some(reference) = step.get("prompt_file") else {
+            continue;
+        };
+        let text = reference
+            .as_str()
+            .ok_or_else(|| "prompt_file must be a path".to_string())
+            .and_then(resolve)
+            .map_err(|e| format!("step{}: {}", index + 1, e))?;
+        step.insert("prompt".into(), text.into());
+    }
+    Ok(())
+}
+
+/// The prompt a step was given in a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptUse {
+    pub step_id: String,
+    /// The step's `prompt_file`
+    pub prompt: String,
+    /// The saved version the text was, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    pub sha256: String,
+}
+
+impl PromptUse {
+    /// `summarize.md@v2`, or `summarize.md (unsaved <hash>)` for text that
+    /// isn't a saved version
+    pub fn label(&self) -> String {
+        let path = parse_reference(&self.prompt)
+            .map(|(path, _)| path)
+            .unwrap_or_default();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match self.version {
+            Some(version) => format!("{}@v{}", name, version),
+            None => format!("{} (unsaved {})", name, &self.sha256[..8]),
+        }
+    }
+}
+
+/// The prompts the steps of `workflow` were given, as loaded. A prompt
+/// named without a version is matched to a saved version by its text.
+pub fn recorded(workflow: &crate::Workflow) -> Vec<PromptUse> {
+    workflow
+        .steps
+        .iter()
+        .enumerate()
+        .filter_map(|(index, step)| {
+            let prompt = step.prompt_file.clone()?;
+            let text = step.params.get("prompt")?.as_str()?;
+            let (path, version) = parse_reference(&prompt).ok()?;
+            let version = match version {
+                Version::Number(number) => Some(number),
+                _ => versions(&path)
+                    .into_iter()
+                    .rev()
+                    .find(|&n| read(&path, Version::Number(n)).is_ok_and(|(t, _)| t == text)),
+            };
+            Some(PromptUse {
+                step_id: step.id_at(index),
+                prompt,
+                version,
+                sha256: sha256_hex(text.as_bytes()),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A prompts folder holding summarize.md with two saved versions and an
+    /// unsaved edit on top.
+    fn versioned() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_prompts_{}", uuid::Uuid::new_v4()));
+        let prompts = dir.join("prompts");
+        std::fs::create_dir_all(&prompts).unwrap();
+        let path = prompts.join("summarize.md");
+        std::fs::write(&path, "Summarize: ${step1}").unwrap();
+        save_version(&path).unwrap();
+        std::fs::write(&path, "Summarize briefly: ${step1}").unwrap();
+        save_version(&path).unwrap();
+        std::fs::write(&path, "One line: ${step1}").unwrap();
+        (dir, path)
+    }
+
+    fn reference(path: &Path, version: &str) -> String {
+        format!("{}{}", path.display(), version)
+    }
+
+    #[test]
+    fn test_find_stays_in_the_prompts_folder() {
+        let dir = std::env::temp_dir().join(format!("lao_prompts_{}", uuid::Uuid::new_v4()));
+        let prompts = dir.join("prompts");
+        std::fs::create_dir_all(&prompts).unwrap();
+        assert_eq!(
+            find(&prompts, "summarize").unwrap(),
+            prompts.join("summarize.md")
+        );
+        assert!(find(&prompts, "../summarize").is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_save_version_skips_unchanged_prompts() {
+        let dir = std::env::temp_dir().join(format!("lao_prompts_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("summarize.md");
+        std::fs::write(&path, "Summarize: ${step1}").unwrap();
+        assert_eq!(save_version(&path).unwrap(), Some(1));
+        assert_eq!(save_version(&path).unwrap(), None);
+        std::fs::write(&path, "Summarize briefly: ${step1}").unwrap();
+        assert_eq!(save_version(&path).unwrap(), Some(2));
+        assert!(dir.join("versions/summarize/v2.md").is_file());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_list_shows_versions_and_edits() {
+        let (dir, path) = versioned();
+        let listed = list(path.parent().unwrap());
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].versions, vec![1, 2]);
+        assert!(listed[0].edited);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_resolve_references() {
+        let (dir, path) = versioned();
+        assert!(is_reference(&reference(&path, "@v1")));
+        assert!(!is_reference("Summarize: ${step1}"));
+        assert!(!is_reference("${step1}"));
+        assert_eq!(
+            resolve(&reference(&path, "@v1")).unwrap(),
+            "Summarize: ${step1}"
+        );
+        assert_eq!(
+            resolve(&reference(&path, "@latest")).unwrap(),
+            "Summarize briefly: ${step1}"
+        );
+        assert_eq!(
+            resolve(&reference(&path, "")).unwrap(),
+            "One line: ${step1}"
+        );
+        assert!(resolve(&reference(&path, "@v9"))
+            .unwrap_err()
+            .contains("no version v9"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_workflow_records_prompt_versions() {
+        let (dir, path) = versioned();
+        let yaml = format!(
+            "workflow: p\nsteps:\n  - run: EchoPlugin\n    input: hi\n  - run: OllamaPlugin\n    prompt: {}\n  - run: OllamaPlugin\n    prompt: {}\n  - run: OllamaPlugin\n    prompt: \"Inline: ${{step1}}\"\n",
+            reference(&path, "@v1"),
+            reference(&path, "")
+        );
+        let workflow_path = dir.join("p.yaml");
+        std::fs::write(&workflow_path, yaml).unwrap();
+        let workflow = crate::load_workflow_yaml(workflow_path.to_str().unwrap()).unwrap();
+        assert_eq!(workflow.steps[1].params["prompt"], "Summarize: ${step1}");
+        assert_eq!(workflow.steps[1].prompt_file, Some(reference(&path, "@v1")));
+        assert_eq!(workflow.steps[3].prompt_file, None);
+
+        let uses = recorded(&workflow);
+        assert_eq!(uses.len(), 2);
+        assert_eq!(uses[0].version, Some(1));
+        assert_eq!(uses[0].label(), "summarize.md@v1");
+        assert_eq!(uses[1].step_id, "step3");
+        assert_eq!(uses[1].version, None);
+        assert!(uses[1].label().starts_with("summarize.md (unsaved "));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,309 @@
+// Publishing run outputs to files.
+// `publish` targets are described in docs/workflows.md (Publishing Outputs).
+use crate::hooks::{ExecutionHook, RunContext, RunEnd};
+use crate::StepLog;
+use chrono::{DateTime, Local};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// What to do when a target file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExists {
+    #[default]
+    Overwrite,
+    Version,
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishSpec {
+    /// Step whose output is written
+    pub step: String,
+    /// Path template
+    pub publish: String,
+    #[serde(default)]
+    pub on_exists: OnExists,
+}
+
+/// Values a path template can use
+pub struct Vars<'a> {
+    pub params: &'a HashMap<String, String>,
+    pub run_id: &'a str,
+    pub now: DateTime<Local>,
+}
+
+fn reference() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\$\{\{\s*([A-Za-z_]+)\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap()
+    })
+}
+
+impl PublishSpec {
+    /// What is wrong with the path template, before any run
+    pub fn problem(&self) -> Option<String> {
+        if self.publish.trim().is_empty() {
+            return Some(format!("output of '{}' has no publish path", self.step));
+        }
+        reference()
+            .captures_iter(&self.publish)
+            .find_map(|caps| match (&caps[1], &caps[2]) {
+                ("params", _) | ("run", "date" | "time" | "id") => None,
+                (namespace, name) => Some(format!(
+                    "publish path of '{}' uses unknown '{}.{}' (expected params.NAME, run.date, run.time or run.id)",
+                    self.step, namespace, name
+                )),
+            })
+    }
+
+    /// The path this run writes to, for a workflow loaded from `workflow_path`
+    pub fn target(&self, workflow_path: &str, vars: &Vars) -> Result<PathBuf, String> {
+        let mut error = None;
+        let rendered = reference().replace_all(&self.publish, |caps: &Captures| {
+            let value = match (&caps[1], &caps[2]) {
+                ("params", name) => match vars.params.get(name) {
+                    // A param names a file or folder, it can't move the target elsewhere
+                    Some(value) if !is_path_segment(value) => {
+                        error.get_or_insert(format!(
+                            "params.{} can't be used in a path: '{}'",
+                            name, value
+                        ));
+                        Some(String::new())
+                    }
+                    value => value.cloned(),
+                },
+                ("run", "date") => Some(vars.now.format("%Y-%m-%d").to_string()),
+                ("run", "time") => Some(vars.now.format("%H-%M-%S").to_string()),
+                ("run", "id") => Some(vars.run_id.to_string()),
+                _ => None,
+            };
+            value.unwrap_or_else(|| {
+                error.get_or_insert(format!(
+                    "{}.{} has no value in this run",
+                    &caps[1], &caps[2]
+                ));
+                String::new()
+            })
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+        let home = self.publish.starts_with("~/");
+        let path = match rendered.strip_prefix("~/").filter(|_| home) {
+            Some(rest) => std::env::var("HOME")
+                .map(|home| Path::new(&home).join(rest))
+                .map_err(|_| "~ needs HOME to be set".to_string())?,
+            None => PathBuf::from(rendered.as_ref()),
+        };
+        Ok(if path.is_relative() {
+            Path::new(workflow_path)
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(path)
+        } else {
+            path
+        })
+    }
+}
+
+// Text that stays one path component: no separators and no `.` or `..`
+fn is_path_segment(value: &str) -> bool {
+    !value.contains(['/', '\\']) && value != "." && value != ".."
+}
+
+/// `path`, or the first of `stem-2.ext`, `stem-3.ext`, ... that is free
+fn next_version(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, ext)))
+        .find(|candidate| !candidate.exists())
+        .expect("some version number is free")
+}
+
+/// Write `output` to `path` following `on_exists`; the path written, or
+/// `None` when an existing file was kept
+pub fn write(path: &Path, output: &str, on_exists: OnExists) -> Result<Option<PathBuf>, String> {
+    let path = match on_exists {
+        OnExists::Skip if path.exists() => return Ok(None),
+        OnExists::Version => next_version(path),
+        _ => path.to_path_buf(),
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Cannot create {}: {}", parent.display(), e))?;
+    }
+    fs::write(&path, output).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(Some(path))
+}
+
+/// Publish `spec`'s step output from `logs`
+pub fn publish(
+    spec: &PublishSpec,
+    workflow_path: &str,
+    vars: &Vars,
+    logs: &[StepLog],
+) -> Result<Option<PathBuf>, String> {
+    let output = logs
+        .iter()
+        .find(|log| log.step_id == spec.step)
+        .and_then(|log| log.output.as_deref())
+        .ok_or_else(|| format!("{} produced no output", spec.step))?;
+    write(&spec.target(workflow_path, vars)?, output, spec.on_exists)
+}
+
+/// Publishes the `outputs:` of successful runs
+pub struct PublishHook;
+
+impl ExecutionHook for PublishHook {
+    fn on_run_end(&self, run: &RunContext, end: &RunEnd) {
+        if end.status != "success" {
+            return;
+        }
+        let vars = Vars {
+            params: run.inputs,
+            run_id: run.run_id,
+            now: Local::now(),
+        };
+        for spec in &run.workflow.outputs {
+            let event = match publish(spec, run.path, &vars, end.logs) {
+                Ok(Some(path)) => crate::logging::info("published output")
+                    .field("path", path.display().to_string()),
+                Ok(None) => crate::logging::info("kept existing published output")
+                    .field("publish", spec.publish.clone()),
+                Err(e) => crate::logging::warn("publishing output failed").field("error", e),
+            };
+            event.run(run.run_id).step(spec.step.as_str()).emit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+
+    #[test]
+    fn test_successful_runs_publish_with_policies() {
+        let dir = std::env::temp_dir().join(format!("lao_publish_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("publish.yaml");
+        std::fs::write(
+            &path,
+            "workflow: publish\nsteps:\n  - run: EchoPlugin\n    input: \"notes for ${date}\"\noutputs:\n  - step: step1\n    publish: \"out/${{ params.date }}-summary.md\"\n    on_exists: version\n  - step: step1\n    publish: \"out/latest.md\"\n  - step: step1\n    publish: \"out/first.md\"\n    on_exists: skip\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let workflow = crate::load_workflow_yaml(path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        let inputs = HashMap::from([("date".to_string(), "2026-10-17".to_string())]);
+        for _ in 0..2 {
+            crate::run_workflow_with_registry(path, inputs.clone(), &registry).unwrap();
+        }
+
+        let out = dir.join("out");
+        let first = std::fs::read_to_string(out.join("2026-10-17-summary.md")).unwrap();
+        assert!(out.join("2026-10-17-summary-2.md").exists());
+        assert!(!out.join("2026-10-17-summary-3.md").exists());
+        assert!(out.join("latest.md").exists());
+        assert!(!out.join("latest-2.md").exists());
+        assert_eq!(
+            std::fs::read_to_string(out.join("first.md")).unwrap(),
+            first
+        );
+        assert!(!out.join("first-2.md").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    fn dated() -> PublishSpec {
+        PublishSpec {
+            step: "step1".to_string(),
+            publish: "out/${{ params.date }}-summary.md".to_string(),
+            on_exists: OnExists::Version,
+        }
+    }
+
+    #[test]
+    fn test_target_without_its_param_is_an_error() {
+        let vars = Vars {
+            params: &HashMap::new(),
+            run_id: "run",
+            now: Local::now(),
+        };
+        assert!(dated().target("publish.yaml", &vars).is_err());
+    }
+
+    #[test]
+    fn test_secrets_cannot_name_targets() {
+        let bad = PublishSpec {
+            publish: "${{ secrets.TOKEN }}.md".to_string(),
+            ..dated()
+        };
+        assert!(bad.problem().is_some());
+        assert!(dated().problem().is_none());
+    }
+
+    fn spec(publish: &str) -> PublishSpec {
+        PublishSpec {
+            step: "step1".to_string(),
+            publish: publish.to_string(),
+            on_exists: OnExists::Overwrite,
+        }
+    }
+
+    fn target(publish: &str, name: &str) -> Result<PathBuf, String> {
+        let params = HashMap::from([("name".to_string(), name.to_string())]);
+        let vars = Vars {
+            params: &params,
+            run_id: "run",
+            now: Local::now(),
+        };
+        spec(publish).target("/flows/notes.yaml", &vars)
+    }
+
+    #[test]
+    fn test_params_fill_one_path_component() {
+        assert_eq!(
+            target("out/${{ params.name }}.md", "weekly").unwrap(),
+            Path::new("/flows/out/weekly.md")
+        );
+        assert_eq!(
+            target("out/${{ params.name }}.md", "v1..2").unwrap(),
+            Path::new("/flows/out/v1..2.md")
+        );
+    }
+
+    #[test]
+    fn test_params_cannot_leave_the_target_folder() {
+        for name in [
+            "../../.ssh/authorized_keys",
+            "..",
+            "a/b",
+            "a\\b",
+            "/etc/passwd",
+        ] {
+            let e = target("out/${{ params.name }}", name).unwrap_err();
+            assert!(e.contains("params.name"), "{}: {}", name, e);
+        }
+    }
+
+    #[test]
+    fn test_params_cannot_make_a_home_path() {
+        let path = target("${{ params.name }}/notes.md", "~").unwrap();
+        assert_eq!(path, Path::new("/flows/~/notes.md"));
+    }
+}
@@ -0,0 +1,435 @@
+// Redaction of secrets and personal data before events reach any log sink.
+// The rules and redaction.yaml are described in docs/observability.md
+// (Redaction).
+use crate::logging::LogEvent;
+use crate::secrets;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Built-in detectors, usable by name in `detectors` and `disable`
+pub const DETECTORS: &[&str] = &[
+    "secrets",
+    "email",
+    "phone",
+    "credit_card",
+    "ssn",
+    "ip_address",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    /// `false` turns redaction off; in a workflow, for that workflow only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detectors: Vec<String>,
+    /// Detector or pattern names to drop from the inherited rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disable: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<PatternRule>,
+    /// Environment variables whose values are always hidden
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_env: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternRule {
+    pub name: String,
+    pub regex: String,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+fn default_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+impl RedactionConfig {
+    /// Rules used when no redaction.yaml exists
+    pub fn defaults() -> Self {
+        Self {
+            detectors: vec!["secrets".to_string()],
+            ..Default::default()
+        }
+    }
+
+    /// Read `path`, falling back to `defaults` if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_yaml::from_str(&content)
+                .map_err(|e| format!("invalid redaction config {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::defaults()),
+            Err(e) => Err(format!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// These rules with a workflow's overrides applied
+    pub fn merged(&self, overrides: &RedactionConfig) -> RedactionConfig {
+        let keep = |name: &String| !overrides.disable.contains(name);
+        let mut detectors: Vec<String> =
+            self.detectors.iter().filter(|d| keep(d)).cloned().collect();
+        for detector in &overrides.detectors {
+            if !detectors.contains(detector) {
+                detectors.push(detector.clone());
+            }
+        }
+        let mut secret_env = self.secret_env.clone();
+        secret_env.extend(overrides.secret_env.iter().cloned());
+        RedactionConfig {
+            enabled: overrides.enabled.or(self.enabled),
+            detectors,
+            disable: Vec::new(),
+            patterns: self
+                .patterns
+                .iter()
+                .filter(|p| keep(&p.name))
+                .chain(&overrides.patterns)
+                .cloned()
+                .collect(),
+            secret_env,
+        }
+    }
+}
+
+/// LAO_REDACTION_CONFIG, defaulting to redaction.yaml
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_REDACTION_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("redaction.yaml"))
+}
+
+/// Compiled rules
+pub struct Redactor {
+    enabled: bool,
+    keyed_secrets: bool,
+    secret_values: Vec<String>,
+    rules: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactionConfig) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for detector in &config.detectors {
+            if detector == "secrets" {
+                continue;
+            }
+            let (pattern, replacement) = builtin(detector)
+                .ok_or_else(|| format!("unknown redaction detector: {}", detector))?;
+            let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+            rules.push((regex, replacement.to_string()));
+        }
+        for rule in &config.patterns {
+            let regex = Regex::new(&rule.regex)
+                .map_err(|e| format!("invalid regex for redaction rule {}: {}", rule.name, e))?;
+            rules.push((regex, rule.replacement.clone()));
+        }
+
+        let keyed_secrets = config.detectors.iter().any(|d| d == "secrets");
+        let mut secret_values: Vec<String> = config
+            .secret_env
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .collect();
+        if keyed_secrets {
+            // API keys and tokens handed to plugins through the environment
+            secret_values.extend(std::env::vars().filter_map(|(name, value)| {
+                let name = name.to_ascii_uppercase();
+                ["TOKEN", "SECRET", "PASSWORD", "API_KEY", "APIKEY"]
+                    .iter()
+                    .any(|marker| name.contains(marker))
+                    .then_some(value)
+            }));
+        }
+        // Short values would hide ordinary words
+        secret_values.retain(|value| value.len() >= 8);
+        secret_values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        secret_values.dedup();
+
+        Ok(Self {
+            enabled: config.enabled.unwrap_or(true),
+            keyed_secrets,
+            secret_values,
+            rules,
+        })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        if !self.enabled {
+            return text.to_string();
+        }
+        let mut out = text.to_string();
+        for value in &self.secret_values {
+            if out.contains(value.as_str()) {
+                out = out.replace(value.as_str(), "[REDACTED]");
+            }
+        }
+        if self.keyed_secrets {
+            out = redact_keyed_secrets(&out);
+        }
+        for (regex, replacement) in &self.rules {
+            if regex.is_match(&out) {
+                out = regex.replace_all(&out, replacement.as_str()).into_owned();
+            }
+        }
+        out
+    }
+
+    pub fn redact_event(&self, event: &mut LogEvent) {
+        map_event(event, &|text| self.redact(text));
+    }
+}
+
+// Replace the message and every string field of `event` with `f`'s result
+fn map_event(event: &mut LogEvent, f: &dyn Fn(&str) -> String) {
+    event.message = f(&event.message);
+    for value in event.fields.values_mut() {
+        map_json(value, f);
+    }
+}
+
+fn map_json(value: &mut serde_json::Value, f: &dyn Fn(&str) -> String) {
+    match value {
+        serde_json::Value::String(s) => *s = f(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|v| map_json(v, f)),
+        serde_json::Value::Object(map) => map.values_mut().for_each(|v| map_json(v, f)),
+        _ => {}
+    }
+}
+
+fn builtin(detector: &str) -> Option<(&'static str, &'static str)> {
+    Some(match detector {
+        "email" => (r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", "[EMAIL]"),
+        "phone" => (
+            r"(?:\+\d{1,3}[ .-]?)?\(?\b\d{3}\)?[ .-]\d{3}[ .-]\d{4}\b",
+            "[PHONE]",
+        ),
+        "credit_card" => (r"\b(?:\d[ -]?){12,15}\d\b", "[CARD]"),
+        "ssn" => (r"\b\d{3}-\d{2}-\d{4}\b", "[SSN]"),
+        "ip_address" => (r"\b(?:\d{1,3}\.){3}\d{1,3}\b", "[IP]"),
+        _ => return None,
+    })
+}
+
+struct State {
+    config: RedactionConfig,
+    base: Arc<Redactor>,
+}
+
+static STATE: OnceLock<State> = OnceLock::new();
+static RUNS: Mutex<Option<HashMap<String, Arc<Redactor>>>> = Mutex::new(None);
+
+/// Start redacting with `config`; later calls are ignored
+pub fn init(config: RedactionConfig) -> Result<(), String> {
+    let base = Arc::new(Redactor::new(&config)?);
+    let _ = STATE.set(State { config, base });
+    Ok(())
+}
+
+/// The rules for events of `run_id`: the run's own if it has overrides, else the global ones
+pub fn for_run(run_id: Option<&str>) -> Option<Arc<Redactor>> {
+    if let Some(run_id) = run_id {
+        let runs = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(redactor) = runs.as_ref().and_then(|runs| runs.get(run_id)) {
+            return Some(redactor.clone());
+        }
+    }
+    STATE.get().map(|state| state.base.clone())
+}
+
+/// Redact `text` with the rules for `run_id`
+pub fn redact(text: &str, run_id: Option<&str>) -> String {
+    // Secrets from the store are hidden whatever the rules (see `secrets`)
+    let text = secrets::hide(text);
+    match for_run(run_id) {
+        Some(redactor) => redactor.redact(&text),
+        None => text,
+    }
+}
+
+/// Redact every string in `value` with the rules for `run_id`
+pub fn redact_json(value: &mut serde_json::Value, run_id: Option<&str>) {
+    map_json(value, &|text| redact(text, run_id));
+}
+
+/// Apply the rules to an event on its way to the log sinks
+pub fn apply(event: &mut LogEvent) {
+    map_event(event, &secrets::hide);
+    if let Some(redactor) = for_run(event.run_id.as_deref()) {
+        redactor.redact_event(event);
+    }
+}
+
+/// A workflow's redaction overrides, active until dropped
+pub struct RunScope {
+    run_id: Option<String>,
+}
+
+impl RunScope {
+    pub fn enter(run_id: &str, overrides: Option<&RedactionConfig>) -> Result<Self, String> {
+        let Some(overrides) = overrides else {
+            return Ok(Self { run_id: None });
+        };
+        let config = match STATE.get() {
+            Some(state) => state.config.merged(overrides),
+            None => RedactionConfig::default().merged(overrides),
+        };
+        let redactor = Arc::new(Redactor::new(&config)?);
+        RUNS.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(HashMap::new)
+            .insert(run_id.to_string(), redactor);
+        Ok(Self {
+            run_id: Some(run_id.to_string()),
+        })
+    }
+}
+
+impl Drop for RunScope {
+    fn drop(&mut self) {
+        if let Some(run_id) = &self.run_id {
+            if let Some(runs) = RUNS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+                runs.remove(run_id);
+            }
+        }
+    }
+}
+
+// Key names whose values are hidden by the `secrets` detector and in crash reports
+const SECRET_KEYS: &[&str] = &[
+    "token",
+    "secret",
+    "password",
+    "passwd",
+    "api_key",
+    "apikey",
+    "authorization",
+    "bearer",
+];
+
+/// Hide values following secret-looking keys (`token=...`, `"password": "..."`,
+/// `Bearer ...`)
+pub fn redact_keyed_secrets(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let lower = text.to_ascii_lowercase();
+    let mut i = 0;
+    while i < text.len() {
+        let key = SECRET_KEYS.iter().find(|key| lower[i..].starts_with(*key));
+        let Some(key) = key else {
+            let ch = text[i..].chars().next().unwrap_or(' ');
+            out.push(ch);
+            i += ch.len_utf8();
+            continue;
+        };
+        let key_end = i + key.len();
+        out.push_str(&text[i..key_end]);
+        // Skip separators and quotes, then hide the value up to whitespace or a delimiter
+        let rest = &text[key_end..];
+        let sep_len = rest
+            .find(|c: char| !matches!(c, '=' | ':' | ' ' | '"' | '\''))
+            .unwrap_or(rest.len());
+        let separator = &rest[..sep_len];
+        if separator.is_empty() || separator.contains('\n') {
+            i = key_end;
+            continue;
+        }
+        out.push_str(separator);
+        let mut value_start = key_end + sep_len;
+        let value_end = |start: usize| {
+            start
+                + text[start..]
+                    .find(|c: char| {
+                        c.is_whitespace() || matches!(c, '"' | '\'' | ',' | '&' | ';' | '}')
+                    })
+                    .unwrap_or(text.len() - start)
+        };
+        let mut end = value_end(value_start);
+        // "Authorization: Bearer <token>" hides the token, not the scheme
+        if matches!(&lower[value_start..end], "bearer" | "basic") && text[end..].starts_with(' ') {
+            out.push_str(&text[value_start..=end]);
+            value_start = end + 1;
+            end = value_end(value_start);
+        }
+        if end > value_start {
+            out.push_str("[REDACTED]");
+        }
+        i = end;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RedactionConfig {
+        serde_yaml::from_str(
+            r#"
+detectors: [secrets, email, phone]
+patterns:
+  - name: employee_id
+    regex: "EMP-\\d{6}"
+    replacement: "[EMPLOYEE]"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_detectors_and_patterns_redact() {
+        let redactor = Redactor::new(&config()).unwrap();
+        assert_eq!(
+            redactor.redact("mail jane.doe@corp.example or call 555-123-4567 about EMP-004211"),
+            "mail [EMAIL] or call [PHONE] about [EMPLOYEE]"
+        );
+        assert_eq!(redactor.redact("api_key=sk-123456"), "api_key=[REDACTED]");
+    }
+
+    #[test]
+    fn test_overrides_disable_and_add_rules() {
+        let overrides: RedactionConfig =
+            serde_yaml::from_str("disable: [email, employee_id]\ndetectors: [ssn]").unwrap();
+        let merged = config().merged(&overrides);
+        assert_eq!(merged.detectors, vec!["secrets", "phone", "ssn"]);
+        assert!(merged.patterns.is_empty());
+        let redactor = Redactor::new(&merged).unwrap();
+        assert_eq!(
+            redactor.redact("jane@corp.example 123-45-6789"),
+            "jane@corp.example [SSN]"
+        );
+    }
+
+    #[test]
+    fn test_disabled_redaction_passes_text_through() {
+        let off = config().merged(&RedactionConfig {
+            enabled: Some(false),
+            ..Default::default()
+        });
+        assert_eq!(
+            Redactor::new(&off).unwrap().redact("token=abc"),
+            "token=abc"
+        );
+    }
+
+    #[test]
+    fn test_unknown_detector_is_an_error() {
+        assert!(Redactor::new(&RedactionConfig {
+            detectors: vec!["zip".to_string()],
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_redact_event_message_and_fields() {
+        let mut event = crate::logging::info("sent to bob@corp.example")
+            .field("output", "reply to alice@corp.example");
+        Redactor::new(&config()).unwrap().redact_event(&mut event);
+        assert_eq!(event.message, "sent to [EMAIL]");
+        assert_eq!(event.field_str("output"), Some("reply to [EMAIL]"));
+    }
+}
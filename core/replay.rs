@@ -0,0 +1,184 @@
+// Replaying a recorded run, one step at a time.
+use crate::plugins::PluginRegistry;
+use crate::run_diff::{self, DiffLine, RunSnapshot, StepSnapshot};
+use crate::{exec_plugin, redaction};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+pub struct Replay {
+    pub run: RunSnapshot,
+}
+
+/// The outcome of re-executing one step of a recorded run
+#[derive(Debug, Clone, Serialize)]
+pub struct Rerun {
+    pub step_id: String,
+    pub runner: String,
+    /// The input the plugin was given this time
+    pub input: String,
+    /// Whether it differs from the recorded input
+    pub input_changed: bool,
+    pub original: Option<String>,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// Recorded output against the new one
+    pub diff: Vec<DiffLine>,
+}
+
+impl Replay {
+    /// The run `run_id` saved under `dir`
+    pub fn load(dir: &Path, run_id: &str) -> Result<Self, String> {
+        run_diff::load(dir, run_id).map(|run| Self { run })
+    }
+
+    /// Steps in the order they ran
+    pub fn steps(&self) -> &[StepSnapshot] {
+        &self.run.steps
+    }
+
+    pub fn step(&self, step_id: &str) -> Result<&StepSnapshot, String> {
+        self.run
+            .steps
+            .iter()
+            .find(|s| s.step_id == step_id)
+            .ok_or_else(|| format!("Run {} has no step {}", self.run.run_id, step_id))
+    }
+
+    /// Recorded outputs of the steps that ran before `step_id`
+    pub fn context(&self, step_id: &str) -> HashMap<String, String> {
+        self.run
+            .steps
+            .iter()
+            .take_while(|s| s.step_id != step_id)
+            .filter_map(|s| Some((s.step_id.clone(), s.output.clone()?)))
+            .collect()
+    }
+
+    /// Call the plugin of `step_id` again, with `input` (its `${stepN}`
+    /// filled in from the recorded context) or the recorded input
+    pub fn rerun(
+        &self,
+        step_id: &str,
+        input: Option<&str>,
+        registry: &PluginRegistry,
+    ) -> Result<Rerun, String> {
+        let step = self.step(step_id)?;
+        let input = match input {
+            Some(input) => crate::substitute_vars(input, &self.context(step_id)),
+            None => step.input.clone().ok_or_else(|| {
+                format!(
+                    "Run {} was recorded without step inputs; give the input to use",
+                    self.run.run_id
+                )
+            })?,
+        };
+        let plugin = registry
+            .get(&step.runner)
+            .ok_or_else(|| format!("Plugin '{}' not found", step.runner))?;
+        let start = Instant::now();
+        let result = exec_plugin(plugin, &input);
+        let duration_ms = start.elapsed().as_millis() as u64;
+        // Redacted like the recorded output, so the diff shows real changes
+        let (output, error) = match result {
+            Ok(output) => (Some(redaction::redact(&output, None)), None),
+            Err(error) => (None, Some(redaction::redact(&error, None))),
+        };
+        let diff = run_diff::line_diff(
+            step.output.as_deref().unwrap_or_default(),
+            output.as_deref().unwrap_or_default(),
+        );
+        Ok(Rerun {
+            step_id: step.step_id.clone(),
+            runner: step.runner.clone(),
+            input_changed: step.input.as_deref() != Some(input.as_str()),
+            input,
+            original: step.output.clone(),
+            output,
+            error,
+            duration_ms,
+            diff,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+
+    /// Runs a two-step echo workflow and loads its saved run for replay.
+    fn recorded() -> (std::path::PathBuf, Replay, PluginRegistry) {
+        let dir = std::env::temp_dir().join(format!("lao_replay_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("replayed.yaml");
+        std::fs::write(
+            &path,
+            "workflow: replayed\nsteps:\n  - run: EchoPlugin\n    input: draft\n  - run: EchoPlugin\n    input_from: step1\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let workflow = crate::load_workflow_yaml(path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        let logs = crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+        let snapshot = RunSnapshot::from_logs(
+            "run1",
+            "replayed",
+            &logs,
+            &HashMap::new(),
+            "success",
+            std::time::Duration::ZERO,
+        );
+        let runs = dir.join("runs");
+        run_diff::save(&runs, &snapshot).unwrap();
+        let replay = Replay::load(&runs, "run1").unwrap();
+        (dir, replay, registry)
+    }
+
+    #[test]
+    fn test_load_keeps_recorded_inputs() {
+        let (dir, replay, _) = recorded();
+        assert_eq!(
+            replay.steps()[1].input.as_deref(),
+            Some("EchoPlugin(draft)")
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rerun_with_recorded_input() {
+        let (dir, replay, registry) = recorded();
+        let same = replay.rerun("step2", None, &registry).unwrap();
+        assert!(!same.input_changed);
+        assert_eq!(same.output, same.original);
+        assert!(same.diff.iter().all(|l| matches!(l, DiffLine::Same(_))));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rerun_with_new_input() {
+        let (dir, replay, registry) = recorded();
+        let edited = replay
+            .rerun("step2", Some("shorter: ${step1}"), &registry)
+            .unwrap();
+        assert!(edited.input_changed);
+        assert_eq!(edited.input, "shorter: EchoPlugin(draft)");
+        assert_eq!(
+            edited.output.as_deref(),
+            Some("EchoPlugin(shorter: EchoPlugin(draft))")
+        );
+        assert!(edited.diff.contains(&DiffLine::Added(
+            "EchoPlugin(shorter: EchoPlugin(draft))".to_string()
+        )));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rerun_unknown_step() {
+        let (dir, replay, registry) = recorded();
+        assert!(replay.rerun("step9", None, &registry).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
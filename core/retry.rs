@@ -0,0 +1,195 @@
+// Retry policies of steps.
+// `retries`, `backoff` and `retry_on` are described in docs/workflows.md
+// (Retries).
+use crate::cancel;
+use crate::WorkflowStep;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_RETRIES: u32 = 1;
+pub const DEFAULT_DELAY_MS: u64 = 1000;
+const MAX_DELAY: Duration = Duration::from_secs(300);
+// Checked this often while waiting for a retry, so stopping the run doesn't wait it out
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    /// The same delay before every retry
+    #[default]
+    Fixed,
+    /// The delay doubles with each retry
+    Exponential,
+}
+
+/// One `retry_on` entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryOn {
+    Timeout,
+    Assert,
+    Exit(i32),
+    Contains(String),
+}
+
+impl RetryOn {
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let entry = entry.trim();
+        match entry {
+            "timeout" => Ok(RetryOn::Timeout),
+            "assert" => Ok(RetryOn::Assert),
+            _ => {
+                match entry.strip_prefix("exit:") {
+                    Some(code) => code.trim().parse().map(RetryOn::Exit).map_err(|_| {
+                        format!("retry_on '{}' needs an exit code, e.g. exit:1", entry)
+                    }),
+                    None if entry.is_empty() => Err("retry_on has an empty entry".to_string()),
+                    None => Ok(RetryOn::Contains(entry.to_lowercase())),
+                }
+            }
+        }
+    }
+
+    fn matches(&self, error: &str, assert_failed: bool) -> bool {
+        let error = error.to_lowercase();
+        match self {
+            RetryOn::Timeout => error.contains("timed out after"),
+            RetryOn::Assert => assert_failed,
+            RetryOn::Exit(code) => ["exit status: ", "exit code: ", "exit code ", "exited with "]
+                .iter()
+                .any(|prefix| {
+                    error.match_indices(prefix).any(|(at, _)| {
+                        let rest = &error[at + prefix.len()..];
+                        let digits: String = rest
+                            .chars()
+                            .take_while(|c| c.is_ascii_digit() || *c == '-')
+                            .collect();
+                        digits.parse() == Ok(*code)
+                    })
+                }),
+            RetryOn::Contains(text) => error.contains(text.as_str()),
+        }
+    }
+}
+
+/// How a step retries failed attempts
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+    pub backoff: Backoff,
+    /// Empty retries every failure
+    pub retry_on: Vec<RetryOn>,
+}
+
+impl RetryPolicy {
+    /// The step's policy; `retry_on` entries that don't parse are left out,
+    /// the plan reports them
+    pub fn of(step: &WorkflowStep) -> Self {
+        Self {
+            retries: step.retries.unwrap_or(DEFAULT_RETRIES),
+            delay: Duration::from_millis(step.retry_delay.unwrap_or(DEFAULT_DELAY_MS)),
+            backoff: step.backoff.unwrap_or_default(),
+            retry_on: step
+                .retry_on
+                .iter()
+                .flatten()
+                .filter_map(|entry| RetryOn::parse(entry).ok())
+                .collect(),
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.retries + 1
+    }
+
+    /// Whether to try again after `attempt` failed with `error`
+    pub fn should_retry(&self, attempt: u32, error: &str, assert_failed: bool) -> bool {
+        attempt < self.max_attempts()
+            && (self.retry_on.is_empty()
+                || self
+                    .retry_on
+                    .iter()
+                    .any(|on| on.matches(error, assert_failed)))
+    }
+
+    /// How long to wait after `attempt` failed
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed => self.delay,
+            Backoff::Exponential => self
+                .delay
+                .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+                .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY)),
+        }
+    }
+}
+
+/// Sleep `delay` before a retry; false if the run was cancelled meanwhile
+pub fn wait(delay: Duration) -> bool {
+    let until = Instant::now() + delay;
+    while !cancel::requested() {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        std::thread::sleep(left.min(CANCEL_POLL));
+    }
+    false
+}
+
+/// Why the step's `retry_on` can't work, for the plan
+pub fn problem(step: &WorkflowStep) -> Option<String> {
+    step.retry_on
+        .iter()
+        .flatten()
+        .find_map(|entry| RetryOn::parse(entry).err())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(yaml: &str) -> RetryPolicy {
+        let step: WorkflowStep = serde_yaml::from_str(yaml).unwrap();
+        RetryPolicy::of(&step)
+    }
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = policy("run: Echo\nretries: 3\nretry_delay: 500\nbackoff: exponential\n");
+        assert_eq!(policy.max_attempts(), 4);
+        let delays: Vec<u64> = (1..=3)
+            .map(|a| policy.delay(a).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000]);
+        assert_eq!(policy.delay(40), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_fixed_backoff_keeps_the_delay() {
+        assert_eq!(policy("run: Echo\n").delay(3), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_retry_on_matches_error_kinds() {
+        let policy = policy("run: Echo\nretries: 3\nretry_on: [timeout, refused, \"exit:75\"]\n");
+        assert!(policy.should_retry(1, "error: timed out after 30s", false));
+        assert!(policy.should_retry(2, "error: Connection REFUSED", false));
+        assert!(policy.should_retry(1, "error: whisper exited with exit status: 75", false));
+        assert!(!policy.should_retry(1, "error: exit status: 7", false));
+        assert!(!policy.should_retry(1, "error: assertion failed: too short", true));
+    }
+
+    #[test]
+    fn test_no_retry_after_the_last_attempt() {
+        let policy = policy("run: Echo\nretries: 3\n");
+        assert!(policy.should_retry(3, "error: timed out after 30s", false));
+        assert!(!policy.should_retry(4, "error: timed out after 30s", false));
+    }
+
+    #[test]
+    fn test_unknown_retry_on_is_a_problem() {
+        let step: WorkflowStep = serde_yaml::from_str("run: Echo\nretry_on: exit:x\n").unwrap();
+        assert!(problem(&step).unwrap().contains("exit:1"));
+    }
+}
@@ -0,0 +1,555 @@
+// JSON-RPC over stdio for editors, `lao rpc`.
+// Methods and their params are listed in docs/cli.md (Editor integration).
+use crate::orchestrator::{Orchestrator, OrchestratorError, RunEvent};
+use crate::plugins::PluginRegistry;
+use crate::strict::{STEP_FIELDS, WORKFLOW_FIELDS};
+use crate::workflow_validation::{step_positions, ValidationService};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// A workflow that can't run, or a run that stopped
+const RUN_FAILED: i64 = -32000;
+
+const METHODS: &[&str] = &[
+    "initialize",
+    "validate",
+    "close",
+    "plugins",
+    "complete",
+    "run",
+    "cancel",
+    "shutdown",
+    "exit",
+];
+
+// Keys whose values are ids of other steps
+const STEP_REFERENCES: &[&str] = &[
+    "input_from",
+    "depends_on",
+    "on_success",
+    "on_failure",
+    "if_failed",
+    "if_succeeded",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Completion {
+    pub label: String,
+    /// "plugin", "step", "field" or "param"
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    path: String,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompleteParams {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Deserialize)]
+struct RunParams {
+    path: String,
+    #[serde(default)]
+    params: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct CancelParams {
+    run_id: String,
+}
+
+// Writes one framed message at a time, from the request loop and run threads
+struct Output<W: Write>(Arc<Mutex<W>>);
+
+impl<W: Write> Clone for Output<W> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<W: Write> Output<W> {
+    fn send(&self, message: Value) {
+        let body = message.to_string();
+        let mut out = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let written =
+            write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body).and_then(|_| out.flush());
+        if let Err(e) = written {
+            log::warn!("rpc write failed: {}", e);
+        }
+    }
+
+    fn reply(&self, id: Value, result: Result<Value, (i64, String)>) {
+        self.send(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        });
+    }
+}
+
+/// Answer requests from `input` on `output` until `exit` or the end of input
+pub fn serve<R: BufRead, W: Write + Send + 'static>(
+    mut input: R,
+    output: W,
+    orchestrator: Orchestrator,
+) -> std::io::Result<()> {
+    let output = Output(Arc::new(Mutex::new(output)));
+    let mut validation = ValidationService::new();
+    let runs: Arc<Mutex<HashMap<String, crate::cancel::CancelToken>>> = Arc::default();
+    let mut threads: Vec<JoinHandle<()>> = Vec::new();
+    while let Some(body) = read_message(&mut input)? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                output.reply(Value::Null, Err((PARSE_ERROR, e.to_string())));
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        // Notifications have no id and get no answer
+        let id = message.get("id").cloned();
+        if method == "exit" {
+            break;
+        }
+        let result = match method {
+            "initialize" => Ok(json!({
+                "name": "lao",
+                "version": env!("CARGO_PKG_VERSION"),
+                "methods": METHODS,
+            })),
+            "shutdown" => Ok(Value::Null),
+            "plugins" => {
+                let registry = orchestrator.registry();
+                let mut plugins = registry.list_plugins();
+                plugins.sort_by(|a, b| a.name.cmp(&b.name));
+                Ok(json!(plugins))
+            }
+            "validate" => parse::<Document>(params).and_then(|doc| {
+                let registry = orchestrator.registry();
+                let diagnostics = match doc.text {
+                    Some(text) => validation.update(&doc.path, &text, &registry),
+                    None => validation
+                        .update_file(Path::new(&doc.path), &registry)
+                        .map_err(|e| (INVALID_PARAMS, e))?,
+                };
+                Ok(json!({ "diagnostics": &*diagnostics }))
+            }),
+            "close" => parse::<Document>(params).map(|doc| {
+                validation.close(&doc.path);
+                Value::Null
+            }),
+            "complete" => parse::<CompleteParams>(params).map(|p| {
+                json!(complete(
+                    &p.text,
+                    p.line,
+                    p.column,
+                    &orchestrator.registry()
+                ))
+            }),
+            "cancel" => parse::<CancelParams>(params).map(|p| {
+                let runs = runs.lock().unwrap_or_else(|e| e.into_inner());
+                let token = runs.get(&p.run_id);
+                if let Some(token) = token {
+                    token.cancel();
+                }
+                json!(token.is_some())
+            }),
+            "run" => match parse::<RunParams>(params) {
+                Ok(run) => {
+                    let (output, runs) = (output.clone(), runs.clone());
+                    let handle = orchestrator.start(&run.path, run.params);
+                    let id = id.clone().unwrap_or(Value::Null);
+                    threads.push(thread::spawn(move || {
+                        let mut run_id = String::new();
+                        while let Ok(event) = handle.events().recv() {
+                            if let RunEvent::RunStarted {
+                                run_id: started, ..
+                            } = &event
+                            {
+                                run_id = started.clone();
+                                runs.lock()
+                                    .unwrap_or_else(|e| e.into_inner())
+                                    .insert(run_id.clone(), handle.cancel_token());
+                            }
+                            let mut event = json!(event);
+                            crate::redaction::redact_json(&mut event, Some(&run_id));
+                            output.send(json!({
+                                "jsonrpc": "2.0",
+                                "method": "run/event",
+                                "params": { "run_id": run_id, "event": event },
+                            }));
+                        }
+                        runs.lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .remove(&run_id);
+                        let result = match handle.wait() {
+                            Ok(outcome) => {
+                                let mut outcome = json!(outcome);
+                                crate::redaction::redact_json(&mut outcome, Some(&run_id));
+                                Ok(outcome)
+                            }
+                            Err(OrchestratorError::Invalid(errors)) => {
+                                Err((RUN_FAILED, errors.join("\n")))
+                            }
+                            Err(e) => Err((RUN_FAILED, e.to_string())),
+                        };
+                        output.reply(id, result);
+                    }));
+                    continue;
+                }
+                Err(e) => Err(e),
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        };
+        if let Some(id) = id {
+            output.reply(id, result);
+        }
+    }
+    for thread in threads {
+        let _ = thread.join();
+    }
+    Ok(())
+}
+
+fn parse<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+// The body of the next message; None at the end of input
+fn read_message<R: BufRead>(input: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// What fits at 1-based `line` and `column` of the workflow `text`
+pub fn complete(
+    text: &str,
+    line: usize,
+    column: usize,
+    registry: &PluginRegistry,
+) -> Vec<Completion> {
+    let lines: Vec<&str> = text.lines().collect();
+    let current = lines.get(line.wrapping_sub(1)).copied().unwrap_or("");
+    let before: String = current.chars().take(column.saturating_sub(1)).collect();
+    let trimmed = before.trim_start();
+    let indent = before.len() - trimmed.len();
+    let key_text = trimmed.strip_prefix("- ").unwrap_or(trimmed);
+    let step = enclosing_step(&lines, line, indent);
+
+    if let Some((key, value)) = key_text.split_once(':') {
+        let partial = value
+            .rsplit(|c: char| c == '[' || c == ',' || c.is_whitespace())
+            .next()
+            .unwrap_or("");
+        return if key == "run" {
+            let mut plugins = registry.list_plugins();
+            plugins.sort_by(|a, b| a.name.cmp(&b.name));
+            plugins
+                .into_iter()
+                .filter(|p| starts_with(&p.name, partial))
+                .map(|p| Completion {
+                    label: p.name.clone(),
+                    kind: "plugin",
+                    detail: Some(p.description.clone()).filter(|d| !d.is_empty()),
+                })
+                .collect()
+        } else if STEP_REFERENCES.contains(&key) {
+            step_ids(text)
+                .into_iter()
+                .enumerate()
+                .filter(|(n, _)| step.map(|(index, ..)| index) != Some(*n))
+                .map(|(_, id)| id)
+                .filter(|id| starts_with(id, partial))
+                .map(|id| Completion {
+                    label: id,
+                    kind: "step",
+                    detail: None,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+    }
+    if !key_text
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Vec::new();
+    }
+    let fields = |names: &[&str]| -> Vec<Completion> {
+        names
+            .iter()
+            .filter(|name| starts_with(name, key_text))
+            .map(|name| Completion {
+                label: name.to_string(),
+                kind: "field",
+                detail: None,
+            })
+            .collect()
+    };
+    match step {
+        None if indent == 0 => fields(WORKFLOW_FIELDS),
+        None => Vec::new(),
+        Some((_, start, column)) => {
+            let mut completions = plugin_params(&lines, start, column, registry)
+                .into_iter()
+                .filter(|c| starts_with(&c.label, key_text))
+                .collect::<Vec<_>>();
+            completions.extend(fields(STEP_FIELDS));
+            completions
+        }
+    }
+}
+
+fn starts_with(name: &str, partial: &str) -> bool {
+    name.to_lowercase().starts_with(&partial.to_lowercase())
+}
+
+// Ids of the steps in `text`: each step's `id`, else step<n>
+fn step_ids(text: &str) -> Vec<String> {
+    let parsed: Option<serde_yaml::Value> = serde_yaml::from_str(text).ok();
+    (0..step_positions(text).len())
+        .map(|i| {
+            parsed
+                .as_ref()
+                .and_then(|workflow| workflow["steps"][i]["id"].as_str())
+                .map_or_else(|| format!("step{}", i + 1), str::to_string)
+        })
+        .collect()
+}
+
+// Index and 1-based position of the step `line` is in, if it's inside one
+fn enclosing_step(lines: &[&str], line: usize, indent: usize) -> Option<(usize, usize, usize)> {
+    if indent == 0 {
+        return None;
+    }
+    let text = lines.join("\n");
+    let (index, &(start, column)) = step_positions(&text)
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, (start, _))| *start <= line)?;
+    // A top-level key between the step and the line ends the steps
+    let ended = lines[start..line.saturating_sub(1).max(start)]
+        .iter()
+        .any(|l| !l.is_empty() && !l.starts_with([' ', '\t', '-', '#']));
+    (!ended).then_some((index, start, column))
+}
+
+// Parameters from the input schema of the plugin run by the step at `start`
+// and `column`
+fn plugin_params(
+    lines: &[&str],
+    start: usize,
+    column: usize,
+    registry: &PluginRegistry,
+) -> Vec<Completion> {
+    let plugin = lines
+        .iter()
+        .skip(start - 1)
+        .enumerate()
+        // The step ends at the next line indented no further than its `-`
+        .take_while(|(n, l)| {
+            let trimmed = l.trim_start();
+            *n == 0 || trimmed.is_empty() || l.len() - trimmed.len() >= column
+        })
+        .find_map(|(_, l)| {
+            let key = l.trim_start();
+            let key = key.strip_prefix("- ").unwrap_or(key);
+            key.strip_prefix("run:").map(|name| name.trim().to_string())
+        });
+    let schema = plugin
+        .and_then(|name| registry.get(&name))
+        .and_then(|plugin| plugin.info.input_schema.clone())
+        .and_then(|schema| serde_json::from_str::<Value>(&schema).ok());
+    let Some(properties) = schema
+        .as_ref()
+        .and_then(|s| s.get("properties"))
+        .and_then(Value::as_object)
+    else {
+        return Vec::new();
+    };
+    properties
+        .iter()
+        .map(|(name, property)| Completion {
+            label: name.clone(),
+            kind: "param",
+            detail: property
+                .get("description")
+                .or_else(|| property.get("type"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_plugin, MockSpec};
+    use std::io::Cursor;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(message: Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn registry() -> PluginRegistry {
+        let mut registry = PluginRegistry::new();
+        let mut echo = mock_plugin("EchoPlugin", MockSpec::default());
+        echo.info.input_schema = Some(
+            r#"{"properties":{"input":{"type":"string","description":"Text to echo"}}}"#
+                .to_string(),
+        );
+        registry.register_plugin(echo);
+        registry
+    }
+
+    const EDITING: &str =
+        "workflow: demo\nsteps:\n  - run: EchoPlugin\n    in\n  - run: Ec\n    input_from: st\nou";
+
+    #[test]
+    fn test_complete_plugin_names() {
+        assert_eq!(
+            complete(EDITING, 5, 12, &registry())
+                .iter()
+                .map(|c| (c.label.as_str(), c.kind))
+                .collect::<Vec<_>>(),
+            [("EchoPlugin", "plugin")]
+        );
+    }
+
+    #[test]
+    fn test_complete_step_keys_with_schema_descriptions() {
+        let keys = complete(EDITING, 4, 7, &registry());
+        assert_eq!(keys[0].detail.as_deref(), Some("Text to echo"));
+        assert!(keys.iter().any(|c| c.label == "input_from"));
+    }
+
+    #[test]
+    fn test_complete_earlier_step_ids() {
+        let steps: Vec<String> = complete(EDITING, 6, 19, &registry())
+            .into_iter()
+            .map(|c| c.label)
+            .collect();
+        assert_eq!(steps, ["step1"]);
+    }
+
+    #[test]
+    fn test_complete_named_step_ids() {
+        let text = "workflow: demo\nsteps:\n  - run: EchoPlugin\n    id: greet\n  - run: EchoPlugin\n    input_from: \n";
+        let steps: Vec<String> = complete(text, 6, 17, &registry())
+            .into_iter()
+            .map(|c| c.label)
+            .collect();
+        assert_eq!(steps, ["greet"]);
+    }
+
+    #[test]
+    fn test_complete_top_level_keys() {
+        assert_eq!(complete(EDITING, 7, 3, &registry())[0].label, "outputs");
+    }
+
+    #[test]
+    fn test_rpc_validates_and_runs() {
+        let dir = std::env::temp_dir().join(format!("lao_rpc_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("echo.yaml");
+        std::fs::write(
+            &path,
+            "workflow: echo\nsteps:\n  - run: EchoPlugin\n    input: hi\n",
+        )
+        .unwrap();
+        let requests = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "validate", "params": {"path": "demo.yaml", "text": "workflow: demo\nsteps:\n  - run: Missing\n"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "run", "params": {"path": path.to_str().unwrap()}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "nope"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ]
+        .map(frame)
+        .concat();
+        let written = Shared::default();
+        serve(
+            Cursor::new(requests),
+            written.clone(),
+            Orchestrator::with_registry(registry()),
+        )
+        .unwrap();
+        let written = String::from_utf8(written.0.lock().unwrap().clone()).unwrap();
+        let mut input = Cursor::new(written);
+        let messages: Vec<Value> = std::iter::from_fn(|| read_message(&mut input).unwrap())
+            .map(|body| serde_json::from_slice(&body).unwrap())
+            .collect();
+        let by_id = |id: i64| messages.iter().find(|m| m["id"] == id).unwrap();
+        assert_eq!(by_id(1)["result"]["diagnostics"][0]["line"], 3);
+        assert_eq!(by_id(2)["result"]["status"], "success");
+        assert_eq!(by_id(3)["error"]["code"], METHOD_NOT_FOUND);
+        let events: Vec<&str> = messages
+            .iter()
+            .filter(|m| m["method"] == "run/event")
+            .map(|m| m["params"]["event"]["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "run_started",
+                "step_started",
+                "step_finished",
+                "run_finished"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,320 @@
+// Run classes: interactive runs go ahead of background runs.
+// Slot limits and leases are described in docs/cli.md (Run classes).
+use crate::logging;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// How often a waiting background step looks for leases from other processes
+const LEASE_POLL: Duration = Duration::from_millis(500);
+// Without a way to check the owner process, leases this old are ignored
+#[cfg(not(unix))]
+const LEASE_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunClass {
+    #[default]
+    Interactive,
+    Background,
+}
+
+impl RunClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunClass::Interactive => "interactive",
+            RunClass::Background => "background",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<RunClass> = const { Cell::new(RunClass::Interactive) };
+}
+
+/// Class of runs started on this thread
+pub fn current() -> RunClass {
+    CURRENT.with(Cell::get)
+}
+
+/// Run `f` with runs on this thread in `class`
+pub fn with_class<T>(class: RunClass, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|c| c.replace(class));
+    let result = f();
+    CURRENT.with(|c| c.set(previous));
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassQuotas {
+    /// Steps of each class that may run at once, indexed by `RunClass`
+    pub step_slots: [usize; 2],
+}
+
+impl Default for ClassQuotas {
+    fn default() -> Self {
+        Self { step_slots: [4, 1] }
+    }
+}
+
+impl ClassQuotas {
+    /// Defaults overridden by LAO_INTERACTIVE_STEP_SLOTS and LAO_BACKGROUND_STEP_SLOTS
+    pub fn from_env() -> Self {
+        let mut quotas = Self::default();
+        for (class, key) in [
+            (RunClass::Interactive, "LAO_INTERACTIVE_STEP_SLOTS"),
+            (RunClass::Background, "LAO_BACKGROUND_STEP_SLOTS"),
+        ] {
+            if let Some(slots) = std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<usize>().ok())
+            {
+                quotas.step_slots[class.index()] = slots.max(1);
+            }
+        }
+        quotas
+    }
+}
+
+#[derive(Debug, Default)]
+struct GateState {
+    running: [usize; 2],
+    interactive_runs: usize,
+}
+
+/// Hands out step permits by class
+pub struct Gate {
+    quotas: ClassQuotas,
+    lease_dir: Option<PathBuf>,
+    state: Mutex<GateState>,
+    changed: Condvar,
+}
+
+static GATE: OnceLock<Gate> = OnceLock::new();
+
+/// Share interactive runs with other processes through leases in `lease_dir`;
+/// later calls, and calls after the first run, are ignored
+pub fn init(lease_dir: impl Into<PathBuf>) {
+    let _ = GATE.set(Gate::new(ClassQuotas::from_env(), Some(lease_dir.into())));
+}
+
+/// daemon/interactive, next to the daemon file
+pub fn default_lease_dir() -> PathBuf {
+    crate::daemon::default_path()
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+        .join("interactive")
+}
+
+pub fn gate() -> &'static Gate {
+    GATE.get_or_init(|| Gate::new(ClassQuotas::from_env(), None))
+}
+
+impl Gate {
+    pub fn new(quotas: ClassQuotas, lease_dir: Option<PathBuf>) -> Self {
+        Self {
+            quotas,
+            lease_dir,
+            state: Mutex::new(GateState::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Register a run of the current thread's class
+    pub fn begin_run(&self, run_id: &str) -> RunTicket<'_> {
+        let class = current();
+        let mut lease = None;
+        if class == RunClass::Interactive {
+            self.lock().interactive_runs += 1;
+            lease = self.lease_dir.as_ref().and_then(|dir| {
+                let path = dir.join(format!("{}-{}", std::process::id(), run_id));
+                fs::create_dir_all(dir).ok()?;
+                fs::write(&path, run_id).ok()?;
+                Some(path)
+            });
+        }
+        RunTicket {
+            gate: self,
+            class,
+            run_id: run_id.to_string(),
+            lease,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, GateState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Interactive runs of other live processes
+    fn foreign_interactive(&self) -> bool {
+        let Some(dir) = &self.lease_dir else {
+            return false;
+        };
+        let own = format!("{}-", std::process::id());
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .any(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                !name.starts_with(&own) && lease_is_live(&name, &entry.path())
+            })
+    }
+}
+
+#[cfg(unix)]
+fn lease_is_live(name: &str, _path: &Path) -> bool {
+    let Some(pid) = name
+        .split_once('-')
+        .and_then(|(pid, _)| pid.parse::<libc::pid_t>().ok())
+    else {
+        return false;
+    };
+    // Signal 0 only checks that the process exists
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn lease_is_live(_name: &str, path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| std::time::SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < LEASE_MAX_AGE)
+}
+
+/// One registered run; dropping it ends the run
+pub struct RunTicket<'a> {
+    gate: &'a Gate,
+    class: RunClass,
+    run_id: String,
+    lease: Option<PathBuf>,
+}
+
+impl RunTicket<'_> {
+    pub fn class(&self) -> RunClass {
+        self.class
+    }
+
+    /// Wait for a step slot. Background steps also wait while any
+    /// interactive run is active. The slot is held until the permit drops.
+    pub fn step(&self, step_id: &str) -> StepPermit<'_> {
+        let gate = self.gate;
+        let slots = gate.quotas.step_slots[self.class.index()];
+        let mut state = gate.lock();
+        let mut waiting_since = None;
+        loop {
+            let yielding = self.class == RunClass::Background
+                && (state.interactive_runs > 0 || gate.foreign_interactive());
+            if !yielding && state.running[self.class.index()] < slots {
+                break;
+            }
+            if waiting_since.is_none() {
+                waiting_since = Some(Instant::now());
+                logging::info("step waiting for a run slot")
+                    .run(&self.run_id)
+                    .step(step_id)
+                    .field("class", self.class.as_str())
+                    .field("yielding", yielding)
+                    .emit();
+            }
+            state = gate
+                .changed
+                .wait_timeout(state, LEASE_POLL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        state.running[self.class.index()] += 1;
+        drop(state);
+        if let Some(since) = waiting_since {
+            logging::info("step resumed")
+                .run(&self.run_id)
+                .step(step_id)
+                .field("class", self.class.as_str())
+                .field("waited_ms", since.elapsed().as_millis() as u64)
+                .emit();
+        }
+        if let Some(lease) = &self.lease {
+            // Refreshed so age-based liveness checks see a long run as active
+            let _ = fs::write(lease, &self.run_id);
+        }
+        StepPermit {
+            gate,
+            class: self.class,
+        }
+    }
+}
+
+impl Drop for RunTicket<'_> {
+    fn drop(&mut self) {
+        if self.class == RunClass::Interactive {
+            self.gate.lock().interactive_runs -= 1;
+            self.gate.changed.notify_all();
+        }
+        if let Some(lease) = &self.lease {
+            let _ = fs::remove_file(lease);
+        }
+    }
+}
+
+pub struct StepPermit<'a> {
+    gate: &'a Gate,
+    class: RunClass,
+}
+
+impl Drop for StepPermit<'_> {
+    fn drop(&mut self) {
+        self.gate.lock().running[self.class.index()] -= 1;
+        self.gate.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_background_steps_yield_to_interactive_runs() {
+        let gate = Arc::new(Gate::new(ClassQuotas::default(), None));
+        let interactive = gate.begin_run("ui-run");
+        let step = interactive.step("step1");
+
+        let started = Arc::new(AtomicBool::new(false));
+        let background = {
+            let (gate, started) = (gate.clone(), started.clone());
+            std::thread::spawn(move || {
+                with_class(RunClass::Background, || {
+                    let run = gate.begin_run("batch-run");
+                    assert_eq!(run.class(), RunClass::Background);
+                    let _first = run.step("step1");
+                    started.store(true, Ordering::SeqCst);
+                    // The quota of one background step is taken
+                    assert_eq!(gate.lock().running, [0, 1]);
+                })
+            })
+        };
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!started.load(Ordering::SeqCst));
+        // Between interactive steps the run is still active
+        drop(step);
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!started.load(Ordering::SeqCst));
+
+        drop(interactive);
+        background.join().unwrap();
+        assert!(started.load(Ordering::SeqCst));
+        assert_eq!(gate.lock().running, [0, 0]);
+        assert_eq!(current(), RunClass::Interactive);
+    }
+}
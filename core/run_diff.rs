@@ -0,0 +1,495 @@
+// Run snapshots and the diff between two runs.
+use crate::guard::Taint;
+use crate::hooks::{ExecutionHook, RunContext, RunEnd};
+use crate::prompts::{self, PromptUse};
+use crate::run_labels::RunLabels;
+use crate::{encryption, redaction, run_logs, StepLog};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+
+// Outputs with more line pairs than this are shown as replaced wholesale
+// rather than diffed, to bound the O(n*m) table
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSnapshot {
+    pub run_id: String,
+    pub workflow: String,
+    pub finished: DateTime<Utc>,
+    /// `success` or `error`
+    pub status: String,
+    pub duration_ms: u64,
+    pub steps: Vec<StepSnapshot>,
+    /// Labels and tags the run was started with (see `run_labels`)
+    #[serde(default, flatten)]
+    pub labels: RunLabels,
+    /// Prompt files the steps were given, by version and hash
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompts: Vec<PromptUse>,
+    /// LLM steps given untrusted content, and where it came from (see `guard`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taint: Vec<Taint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepSnapshot {
+    pub step_id: String,
+    pub runner: String,
+    /// `success`, `error`, `skipped`, or where a reused output came from:
+    /// `cache`, `memo`, `dedup` or `resumed`
+    pub status: String,
+    /// What the plugin was given; absent in snapshots from before inputs were kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub attempts: u32,
+    pub duration_ms: u64,
+}
+
+impl RunSnapshot {
+    /// Snapshot of a finished run; call while the run's redaction scope is active
+    pub fn from_logs(
+        run_id: &str,
+        workflow: &str,
+        logs: &[StepLog],
+        step_durations: &HashMap<String, Duration>,
+        status: &str,
+        duration: Duration,
+    ) -> Self {
+        let redact =
+            |text: &Option<String>| text.as_ref().map(|t| redaction::redact(t, Some(run_id)));
+        let steps = logs
+            .iter()
+            .map(|log| StepSnapshot {
+                step_id: log.step_id.clone(),
+                runner: log.runner.clone(),
+                status: step_status(log).to_string(),
+                input: redact(&Some(crate::build_plugin_input(&log.input))),
+                output: redact(&log.output),
+                error: redact(&log.error),
+                attempts: log.attempt,
+                duration_ms: step_durations
+                    .get(&log.step_id)
+                    .map_or(0, |d| d.as_millis() as u64),
+            })
+            .collect();
+        Self {
+            run_id: run_id.to_string(),
+            workflow: workflow.to_string(),
+            finished: Utc::now(),
+            status: status.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            steps,
+            labels: RunLabels::default(),
+            prompts: Vec::new(),
+            taint: Vec::new(),
+        }
+    }
+
+    fn step(&self, step_id: &str) -> Option<&StepSnapshot> {
+        self.steps.iter().find(|s| s.step_id == step_id)
+    }
+
+    /// The prompt `step_id` was given, as `name@version`
+    fn prompt(&self, step_id: &str) -> Option<String> {
+        self.prompts
+            .iter()
+            .find(|p| p.step_id == step_id)
+            .map(PromptUse::label)
+    }
+}
+
+fn step_status(log: &StepLog) -> &str {
+    if log.error.is_some() {
+        return "error";
+    }
+    match log.validation.as_deref() {
+        Some(reused @ ("skipped" | "cache" | "memo" | "dedup" | "resumed")) => reused,
+        _ => "success",
+    }
+}
+
+static SNAPSHOT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Start saving run snapshots under `dir`; later calls are ignored
+pub fn init(dir: impl Into<PathBuf>) {
+    let _ = SNAPSHOT_DIR.set(dir.into());
+}
+
+/// The run log directory (LAO_RUNS_DIR, default runs/ in the data directory)
+pub fn default_dir() -> PathBuf {
+    run_logs::RunLogConfig::from_env().dir
+}
+
+/// Save `snapshot` if `init` was called
+/// Records a snapshot of every finished run; a built-in execution hook
+pub struct SnapshotHook;
+
+impl ExecutionHook for SnapshotHook {
+    fn on_run_end(&self, run: &RunContext, end: &RunEnd) {
+        record(&RunSnapshot {
+            labels: run.labels.clone(),
+            prompts: prompts::recorded(run.workflow),
+            taint: end.taint.to_vec(),
+            ..RunSnapshot::from_logs(
+                run.run_id,
+                &run.workflow.workflow,
+                end.logs,
+                end.step_durations,
+                end.status,
+                end.duration,
+            )
+        });
+    }
+}
+
+pub fn record(snapshot: &RunSnapshot) {
+    let Some(dir) = SNAPSHOT_DIR.get() else {
+        return;
+    };
+    if let Err(e) = save(dir, snapshot) {
+        crate::logging::warn("saving run snapshot failed")
+            .run(&snapshot.run_id)
+            .field("error", e)
+            .emit();
+    }
+}
+
+pub fn save(dir: &Path, snapshot: &RunSnapshot) -> Result<(), String> {
+    let run_dir = dir.join(&snapshot.run_id);
+    fs::create_dir_all(&run_dir)
+        .map_err(|e| format!("Cannot create {}: {}", run_dir.display(), e))?;
+    let json = serde_json::to_string_pretty(snapshot).map_err(|e| e.to_string())?;
+    let path = run_dir.join(SNAPSHOT_FILE);
+    encryption::write(&path, json).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+pub fn load(dir: &Path, run_id: &str) -> Result<RunSnapshot, String> {
+    if run_id.is_empty() || run_id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid run id '{}'", run_id));
+    }
+    let path = dir.join(run_id).join(SNAPSHOT_FILE);
+    let text = encryption::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("No snapshot for run {}", run_id),
+        _ => format!("Cannot read {}: {}", path.display(), e),
+    })?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+/// Snapshots under `dir`, newest first
+pub fn list(dir: &Path) -> Vec<RunSnapshot> {
+    let mut snapshots: Vec<RunSnapshot> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| load(dir, &entry.file_name().to_string_lossy()).ok())
+        .collect();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.finished));
+    snapshots
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunDiff {
+    pub a: RunSummary,
+    pub b: RunSummary,
+    /// `b` minus `a`
+    pub duration_delta_ms: i64,
+    /// Steps of `a` in order, then steps only in `b`
+    pub steps: Vec<StepDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub workflow: String,
+    pub finished: DateTime<Utc>,
+    pub status: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepChange {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StepDiff {
+    pub step_id: String,
+    pub runner: String,
+    pub change: StepChange,
+    /// Status in `a` and `b`; `None` where the step didn't run
+    pub status: (Option<String>, Option<String>),
+    /// Line diff of the outputs, empty unless they differ
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<DiffLine>,
+    /// Errors in `a` and `b`, when they differ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<(Option<String>, Option<String>)>,
+    /// Prompt versions in `a` and `b`, when they differ
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<(Option<String>, Option<String>)>,
+    pub duration_delta_ms: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "text", rename_all = "lowercase")]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Compare run `a` with run `b`
+pub fn diff(a: &RunSnapshot, b: &RunSnapshot) -> RunDiff {
+    let prompt = |step_id: &str| {
+        let prompts = (a.prompt(step_id), b.prompt(step_id));
+        (prompts.0 != prompts.1).then_some(prompts)
+    };
+    let mut steps: Vec<StepDiff> = a
+        .steps
+        .iter()
+        .map(|step_a| {
+            let step_b = b.step(&step_a.step_id);
+            step_diff(Some(step_a), step_b, prompt(&step_a.step_id))
+        })
+        .collect();
+    steps.extend(
+        b.steps
+            .iter()
+            .filter(|step_b| a.step(&step_b.step_id).is_none())
+            .map(|step_b| step_diff(None, Some(step_b), None)),
+    );
+    RunDiff {
+        a: summary(a),
+        b: summary(b),
+        duration_delta_ms: b.duration_ms as i64 - a.duration_ms as i64,
+        steps,
+    }
+}
+
+/// Load both snapshots from `dir` and compare them
+pub fn diff_runs(dir: &Path, a: &str, b: &str) -> Result<RunDiff, String> {
+    Ok(diff(&load(dir, a)?, &load(dir, b)?))
+}
+
+fn summary(run: &RunSnapshot) -> RunSummary {
+    RunSummary {
+        run_id: run.run_id.clone(),
+        workflow: run.workflow.clone(),
+        finished: run.finished,
+        status: run.status.clone(),
+        duration_ms: run.duration_ms,
+    }
+}
+
+fn step_diff(
+    a: Option<&StepSnapshot>,
+    b: Option<&StepSnapshot>,
+    prompt: Option<(Option<String>, Option<String>)>,
+) -> StepDiff {
+    let either = a.or(b).expect("step in at least one run");
+    let output = |s: Option<&StepSnapshot>| s.and_then(|s| s.output.clone()).unwrap_or_default();
+    let error = |s: Option<&StepSnapshot>| s.and_then(|s| s.error.clone());
+    let duration = |s: Option<&StepSnapshot>| s.map_or(0, |s| s.duration_ms as i64);
+
+    let (output_a, output_b) = (output(a), output(b));
+    let output_diff = if output_a == output_b {
+        Vec::new()
+    } else {
+        line_diff(&output_a, &output_b)
+    };
+    let errors = (error(a), error(b));
+    let status = (a.map(|s| s.status.clone()), b.map(|s| s.status.clone()));
+    let change = match (a, b) {
+        (None, _) => StepChange::Added,
+        (_, None) => StepChange::Removed,
+        _ if output_diff.is_empty()
+            && errors.0 == errors.1
+            && status.0 == status.1
+            && prompt.is_none() =>
+        {
+            StepChange::Unchanged
+        }
+        _ => StepChange::Changed,
+    };
+    StepDiff {
+        step_id: either.step_id.clone(),
+        runner: b.unwrap_or(either).runner.clone(),
+        change,
+        status,
+        output: output_diff,
+        error: (errors.0 != errors.1).then_some(errors),
+        prompt,
+        duration_delta_ms: duration(b) - duration(a),
+    }
+}
+
+/// Line diff of `a` into `b` from a longest-common-subsequence walk
+pub fn line_diff(a: &str, b: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+    if (a.len() + 1).saturating_mul(b.len() + 1) > MAX_DIFF_CELLS {
+        return a
+            .iter()
+            .map(|line| DiffLine::Removed(line.to_string()))
+            .chain(b.iter().map(|line| DiffLine::Added(line.to_string())))
+            .collect();
+    }
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            diff.push(DiffLine::Same(a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(DiffLine::Removed(a[i].to_string()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(b[j].to_string()));
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(id: &str, output: &str, duration_ms: u64) -> StepSnapshot {
+        StepSnapshot {
+            step_id: id.to_string(),
+            runner: "Echo".to_string(),
+            status: "success".to_string(),
+            input: None,
+            output: Some(output.to_string()),
+            error: None,
+            attempts: 1,
+            duration_ms,
+        }
+    }
+
+    fn run(id: &str, steps: Vec<StepSnapshot>) -> RunSnapshot {
+        RunSnapshot {
+            run_id: id.to_string(),
+            workflow: "Diff".to_string(),
+            finished: Utc::now(),
+            status: "success".to_string(),
+            duration_ms: steps.iter().map(|s| s.duration_ms).sum(),
+            steps,
+            labels: RunLabels::default(),
+            prompts: Vec::new(),
+            taint: Vec::new(),
+        }
+    }
+
+    fn a() -> RunSnapshot {
+        run(
+            "run-a",
+            vec![
+                step("step1", "same", 10),
+                step("step2", "alpha\nbeta\ngamma", 100),
+                step("step3", "gone", 5),
+            ],
+        )
+    }
+
+    fn b() -> RunSnapshot {
+        run(
+            "run-b",
+            vec![
+                step("step1", "same", 12),
+                step("step2", "alpha\nBETA\ngamma", 60),
+                step("step4", "new", 7),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_saved_runs_are_diffed_by_id() {
+        let dir = std::env::temp_dir().join(format!("lao_run_diff_{}", uuid::Uuid::new_v4()));
+        save(&dir, &a()).unwrap();
+        save(&dir, &b()).unwrap();
+        assert_eq!(list(&dir).len(), 2);
+        let diff = diff_runs(&dir, "run-a", "run-b").unwrap();
+        assert_eq!(diff.duration_delta_ms, 79 - 115);
+        assert!(diff_runs(&dir, "run-a", "../run-b").is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_diff_classifies_steps() {
+        let diff = diff(&a(), &b());
+        let changes: Vec<(&str, StepChange)> = diff
+            .steps
+            .iter()
+            .map(|s| (s.step_id.as_str(), s.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("step1", StepChange::Unchanged),
+                ("step2", StepChange::Changed),
+                ("step3", StepChange::Removed),
+                ("step4", StepChange::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changed_output_is_diffed_by_line() {
+        let diff = diff(&a(), &b());
+        assert_eq!(
+            diff.steps[1].output,
+            vec![
+                DiffLine::Same("alpha".to_string()),
+                DiffLine::Removed("beta".to_string()),
+                DiffLine::Added("BETA".to_string()),
+                DiffLine::Same("gamma".to_string()),
+            ]
+        );
+        assert_eq!(diff.steps[1].duration_delta_ms, -40);
+    }
+
+    #[test]
+    fn test_other_prompt_version_changes_the_step() {
+        let mut prompted = a();
+        prompted.prompts = vec![PromptUse {
+            step_id: "step1".to_string(),
+            prompt: "prompts/summarize.md@v2".to_string(),
+            version: Some(2),
+            sha256: "ab".repeat(32),
+        }];
+        let diff = diff(&a(), &prompted);
+        assert_eq!(diff.steps[0].change, StepChange::Changed);
+        assert_eq!(
+            diff.steps[0].prompt,
+            Some((None, Some("summarize.md@v2".to_string())))
+        );
+    }
+}
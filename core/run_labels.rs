@@ -0,0 +1,234 @@
+// Labels and tags on runs, for finding them again in the run history.
+use crate::run_class::{self, RunClass};
+use crate::run_diff::{self, RunSnapshot};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunLabels {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl RunLabels {
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.tags.is_empty()
+    }
+
+    pub fn with_label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        let tag = tag.into();
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag);
+        }
+        self
+    }
+
+    /// `key=value` and `tag` words, for one-line listings
+    pub fn render(&self) -> String {
+        self.labels
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .chain(self.tags.iter().map(|tag| format!("#{}", tag)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Parse a `key=value` label
+pub fn parse_label(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("Invalid label '{}', expected key=value", text)),
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<RunLabels> = RefCell::new(RunLabels::default());
+}
+
+/// Labels and tags given to runs started on this thread
+pub fn current() -> RunLabels {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+/// Run `f` with runs on this thread labelled `labels`
+pub fn with_labels<T>(labels: RunLabels, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|c| c.replace(labels));
+    let result = f();
+    CURRENT.with(|c| *c.borrow_mut() = previous);
+    result
+}
+
+/// Run `f` with `trigger` added to the tags of runs on this thread
+pub fn with_trigger<T>(trigger: &str, f: impl FnOnce() -> T) -> T {
+    with_labels(current().with_tag(trigger), f)
+}
+
+/// What a run starting on this thread carries: `current` and its class
+pub(crate) fn for_run() -> RunLabels {
+    match run_class::current() {
+        RunClass::Background => current().with_tag(RunClass::Background.as_str()),
+        RunClass::Interactive => current(),
+    }
+}
+
+/// Which saved runs to list; unset fields match every run
+#[derive(Debug, Clone, Default)]
+pub struct RunQuery {
+    pub workflow: Option<String>,
+    pub status: Option<String>,
+    /// Labels the run must have, each with this value
+    pub labels: Vec<(String, String)>,
+    /// Tags the run must have
+    pub tags: Vec<String>,
+    /// Case-insensitive text found in the run id, workflow, labels or tags
+    pub text: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Newest runs to return
+    pub limit: Option<usize>,
+}
+
+impl RunQuery {
+    pub fn matches(&self, run: &RunSnapshot) -> bool {
+        let text = self.text.as_ref().map(|t| t.to_lowercase());
+        self.workflow.as_ref().is_none_or(|w| &run.workflow == w)
+            && self.status.as_ref().is_none_or(|s| &run.status == s)
+            && self.since.is_none_or(|since| run.finished >= since)
+            && self
+                .labels
+                .iter()
+                .all(|(key, value)| run.labels.labels.get(key) == Some(value))
+            && self.tags.iter().all(|tag| run.labels.tags.contains(tag))
+            && text.is_none_or(|text| {
+                [&run.run_id, &run.workflow, &run.labels.render()]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&text))
+            })
+    }
+}
+
+/// Saved runs under `dir` that match `query`, newest first
+pub fn query(dir: &Path, query: &RunQuery) -> Vec<RunSnapshot> {
+    let mut runs: Vec<RunSnapshot> = run_diff::list(dir)
+        .into_iter()
+        .filter(|run| query.matches(run))
+        .collect();
+    if let Some(limit) = query.limit {
+        runs.truncate(limit);
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::{self, ExecutionHook, RunContext, RunEnd};
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    // Labels of the runs of the test workflow; other tests run in parallel
+    struct Recorder(Arc<Mutex<Vec<RunLabels>>>);
+
+    impl ExecutionHook for Recorder {
+        fn on_run_end(&self, run: &RunContext, _end: &RunEnd) {
+            if run.workflow.workflow == "labelled" {
+                self.0.lock().unwrap().push(run.labels.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(
+            parse_label("project = thesis").unwrap(),
+            ("project".to_string(), "thesis".to_string())
+        );
+        assert!(parse_label("=thesis").is_err());
+    }
+
+    #[test]
+    fn test_labels_and_triggers_reach_hooks() {
+        let dir = std::env::temp_dir().join(format!("lao_labels_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("labelled.yaml");
+        std::fs::write(
+            &path,
+            "workflow: labelled\nsteps:\n  - run: EchoPlugin\n    input: hi\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let workflow = crate::load_workflow_yaml(path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        hooks::register(Recorder(seen.clone()));
+
+        let labels = RunLabels::default().with_label("project", "thesis");
+        with_labels(labels, || {
+            with_trigger("cli", || {
+                run_class::with_class(RunClass::Background, || {
+                    crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+                })
+            })
+        });
+        crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(seen[0].render(), "project=thesis #cli #background");
+        assert!(seen[1].is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_history_filters_by_label_tag_and_text() {
+        let runs_dir = std::env::temp_dir().join(format!("lao_labels_{}", uuid::Uuid::new_v4()));
+        let thesis = RunLabels::default()
+            .with_label("project", "thesis")
+            .with_tag("background");
+        for (i, labels) in [thesis, RunLabels::default()].into_iter().enumerate() {
+            let snapshot = RunSnapshot {
+                run_id: format!("run{}", i),
+                workflow: "labelled".to_string(),
+                finished: Utc::now() + chrono::Duration::seconds(i as i64),
+                status: "success".to_string(),
+                duration_ms: 1,
+                steps: Vec::new(),
+                labels,
+                prompts: Vec::new(),
+                taint: Vec::new(),
+            };
+            run_diff::save(&runs_dir, &snapshot).unwrap();
+        }
+        let ids = |q: RunQuery| -> Vec<String> {
+            query(&runs_dir, &q).into_iter().map(|r| r.run_id).collect()
+        };
+        assert_eq!(ids(RunQuery::default()), ["run1", "run0"]);
+        let by_label = RunQuery {
+            labels: vec![("project".to_string(), "thesis".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(ids(by_label), ["run0"]);
+        let by_tag = RunQuery {
+            tags: vec!["background".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(ids(by_tag), ["run0"]);
+        let by_text = RunQuery {
+            text: Some("THESIS".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(by_text), ["run0"]);
+        std::fs::remove_dir_all(&runs_dir).ok();
+    }
+}
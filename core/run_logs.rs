@@ -0,0 +1,256 @@
+// Per-run JSONL log files under `<runs dir>/<run id>/run.log`.
+use crate::logging::{LogEvent, LogSink};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+pub const RUN_LOG_FILE: &str = "run.log";
+
+#[derive(Debug, Clone)]
+pub struct RunLogConfig {
+    pub dir: PathBuf,
+    /// Rotate run.log once it reaches this size
+    pub max_bytes: u64,
+    /// Rotated files kept per run, not counting run.log itself
+    pub max_files: usize,
+    /// Most recent run directories to keep
+    pub keep_runs: usize,
+    /// Remove run directories older than this
+    pub max_age: Option<Duration>,
+}
+
+impl Default for RunLogConfig {
+    fn default() -> Self {
+        Self {
+            dir: crate::data_dir::path("runs"),
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 3,
+            keep_runs: 100,
+            max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+        }
+    }
+}
+
+impl RunLogConfig {
+    /// Defaults overridden by LAO_RUNS_DIR, LAO_RUN_LOG_MAX_BYTES,
+    /// LAO_RUN_LOG_MAX_FILES, LAO_RUN_LOG_KEEP_RUNS and LAO_RUN_LOG_MAX_AGE_DAYS
+    /// (0 disables the age limit)
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(dir) = std::env::var("LAO_RUNS_DIR") {
+            config.dir = PathBuf::from(dir);
+        }
+        if let Some(bytes) = env_number("LAO_RUN_LOG_MAX_BYTES") {
+            config.max_bytes = bytes;
+        }
+        if let Some(files) = env_number("LAO_RUN_LOG_MAX_FILES") {
+            config.max_files = files as usize;
+        }
+        if let Some(runs) = env_number("LAO_RUN_LOG_KEEP_RUNS") {
+            config.keep_runs = runs as usize;
+        }
+        if let Some(days) = env_number("LAO_RUN_LOG_MAX_AGE_DAYS") {
+            config.max_age = (days > 0).then(|| Duration::from_secs(days * 24 * 60 * 60));
+        }
+        config
+    }
+
+    pub fn run_dir(&self, run_id: &str) -> PathBuf {
+        self.dir.join(run_id)
+    }
+
+    pub fn log_path(&self, run_id: &str) -> PathBuf {
+        self.run_dir(run_id).join(RUN_LOG_FILE)
+    }
+}
+
+fn env_number(key: &str) -> Option<u64> {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+pub struct RunLogSink {
+    config: RunLogConfig,
+    // Serialises writes and rotation; events from parallel steps share a file
+    lock: Mutex<()>,
+}
+
+impl RunLogSink {
+    pub fn new(config: RunLogConfig) -> Self {
+        Self {
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn write(&self, run_id: &str, event: &LogEvent) -> std::io::Result<()> {
+        let run_dir = self.config.run_dir(run_id);
+        if !run_dir.exists() {
+            fs::create_dir_all(&run_dir)?;
+            prune_runs(&self.config, run_id)?;
+        }
+
+        let path = run_dir.join(RUN_LOG_FILE);
+        let line = crate::encryption::seal_line(&event.to_json_line())?;
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 + 1 > self.config.max_bytes {
+            rotate(&path, self.config.max_files)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+impl LogSink for RunLogSink {
+    fn log(&self, event: &LogEvent) {
+        let Some(run_id) = event.run_id.as_deref() else {
+            return;
+        };
+        // Run ids become directory names, so anything path-like is ignored
+        if run_id.is_empty() || run_id.contains(['/', '\\', '.']) {
+            return;
+        }
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = self.write(run_id, event) {
+            // Logging the failure through `emit` would recurse back into this sink
+            eprintln!("[run log] failed to write {}: {}", run_id, e);
+        }
+    }
+}
+
+// run.log -> run.log.1 -> run.log.2 ...; the oldest file past max_files is dropped
+fn rotate(path: &Path, max_files: usize) -> std::io::Result<()> {
+    let numbered = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    if max_files == 0 {
+        return fs::remove_file(path);
+    }
+    let _ = fs::remove_file(numbered(max_files));
+    for n in (1..max_files).rev() {
+        let from = numbered(n);
+        if from.exists() {
+            fs::rename(&from, numbered(n + 1))?;
+        }
+    }
+    fs::rename(path, numbered(1))
+}
+
+/// Remove run directories beyond `keep_runs` or older than `max_age`,
+/// never touching `current`
+pub fn prune_runs(config: &RunLogConfig, current: &str) -> std::io::Result<()> {
+    let mut runs: Vec<(PathBuf, SystemTime)> = fs::read_dir(&config.dir)?
+        .flatten()
+        .filter(|entry| entry.file_name() != current)
+        .filter(|entry| entry.path().join(RUN_LOG_FILE).exists())
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((entry.path(), modified))
+        })
+        .collect();
+    // Newest first; the current run takes one of the kept slots
+    runs.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+
+    let now = SystemTime::now();
+    for (index, (path, modified)) in runs.iter().enumerate() {
+        let too_many = index + 1 >= config.keep_runs;
+        let too_old = config
+            .max_age
+            .is_some_and(|age| now.duration_since(*modified).unwrap_or_default() > age);
+        if too_many || too_old {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Events from a run's log files, oldest first
+pub fn read_run_log(config: &RunLogConfig, run_id: &str) -> std::io::Result<Vec<LogEvent>> {
+    let path = config.log_path(run_id);
+    let mut files = Vec::new();
+    for n in (1..=config.max_files).rev() {
+        let rotated = PathBuf::from(format!("{}.{}", path.display(), n));
+        if rotated.exists() {
+            files.push(rotated);
+        }
+    }
+    files.push(path);
+
+    let mut events = Vec::new();
+    for file in files {
+        let content = fs::read_to_string(&file)?;
+        events.extend(
+            content
+                .lines()
+                .filter_map(|line| crate::encryption::open_line(line).ok())
+                .filter_map(|line| serde_json::from_str::<LogEvent>(&line).ok()),
+        );
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging;
+
+    fn temp_config() -> RunLogConfig {
+        RunLogConfig {
+            dir: std::env::temp_dir().join(format!("lao-run-logs-{}", uuid::Uuid::new_v4())),
+            ..RunLogConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_writes_and_rotates_run_log() {
+        let config = RunLogConfig {
+            max_bytes: 400,
+            max_files: 2,
+            ..temp_config()
+        };
+        let sink = RunLogSink::new(config.clone());
+
+        for i in 0..20 {
+            sink.log(
+                &logging::info(format!("event {}", i))
+                    .run("run-a")
+                    .step("step1"),
+            );
+        }
+        // Events without a run id have no file to go to
+        sink.log(&logging::info("no run"));
+
+        let log_path = config.log_path("run-a");
+        assert!(log_path.exists());
+        assert!(fs::metadata(&log_path).unwrap().len() <= 400);
+        assert!(config.run_dir("run-a").join("run.log.2").exists());
+        assert!(!config.run_dir("run-a").join("run.log.3").exists());
+
+        let events = read_run_log(&config, "run-a").unwrap();
+        assert!(events.len() < 20);
+        assert_eq!(events.last().unwrap().message, "event 19");
+        assert_eq!(events.last().unwrap().step_id.as_deref(), Some("step1"));
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_prunes_old_runs() {
+        let config = RunLogConfig {
+            keep_runs: 2,
+            ..temp_config()
+        };
+        let sink = RunLogSink::new(config.clone());
+
+        for run in ["run-1", "run-2", "run-3"] {
+            sink.log(&logging::info("workflow started").run(run));
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(!config.run_dir("run-1").exists());
+        assert!(config.run_dir("run-2").exists());
+        assert!(config.run_dir("run-3").exists());
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+// A queue of workflow runs, as the UI's queue panel keeps.
+use crate::cancel::{self, CancelToken};
+use crate::pipeline::{self, Lanes, Resource};
+use crate::plugins::PluginRegistry;
+use crate::{run_class, run_labels};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueStatus {
+    Queued,
+    Running,
+    Success,
+    Error,
+    Cancelled,
+}
+
+impl QueueStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, QueueStatus::Queued | QueueStatus::Running)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedRun {
+    pub id: usize,
+    pub path: String,
+    pub params: HashMap<String, String>,
+    pub status: QueueStatus,
+    /// The first failed step, or why the run didn't complete
+    pub error: Option<String>,
+    /// While running, the step holding a lane and its resource
+    pub step: Option<(String, Resource)>,
+    /// Steps logged, once finished
+    pub steps: usize,
+    pub duration_ms: Option<u64>,
+    #[serde(skip)]
+    started: Option<Instant>,
+    #[serde(skip)]
+    token: CancelToken,
+}
+
+#[derive(Default)]
+struct QueueState {
+    runs: Vec<QueuedRun>,
+    next_id: usize,
+    closed: bool,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    lanes: Arc<Lanes>,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub struct RunQueue {
+    shared: Arc<Shared>,
+    depth: usize,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl RunQueue {
+    /// Run queued workflows against `registry`, `depth` at once (0 for one
+    /// per lane) and each step within `lanes`. Runs carry the class and
+    /// labels of the thread creating the queue, tagged `queue`.
+    pub fn new(registry: Arc<PluginRegistry>, lanes: Lanes, depth: usize) -> Self {
+        let depth = match depth {
+            0 => lanes.slots().iter().sum(),
+            depth => depth,
+        };
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+            lanes: Arc::new(lanes),
+        });
+        let class = run_class::current();
+        let labels = run_labels::current().with_tag("queue");
+        let workers = (0..depth)
+            .map(|_| {
+                let shared = shared.clone();
+                let registry = registry.clone();
+                let labels = labels.clone();
+                std::thread::spawn(move || {
+                    while let Some((id, path, params, token)) = next_run(&shared) {
+                        let result = run_class::with_class(class, || {
+                            run_labels::with_labels(labels.clone(), || {
+                                cancel::with_token(token, || {
+                                    pipeline::with_lanes(shared.lanes.clone(), id, || {
+                                        crate::run_workflow_with_registry(&path, params, &registry)
+                                    })
+                                })
+                            })
+                        });
+                        finish_run(&shared, id, result);
+                    }
+                })
+            })
+            .collect();
+        Self {
+            shared,
+            depth,
+            workers,
+        }
+    }
+
+    /// How many runs are in flight at most
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn slots(&self) -> [usize; 2] {
+        self.shared.lanes.slots()
+    }
+
+    /// Queue a run of `path`; returns its id
+    pub fn push(&self, path: &str, params: HashMap<String, String>) -> usize {
+        let mut state = self.shared.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.runs.push(QueuedRun {
+            id,
+            path: path.to_string(),
+            params,
+            status: QueueStatus::Queued,
+            error: None,
+            step: None,
+            steps: 0,
+            duration_ms: None,
+            started: None,
+            token: CancelToken::new(),
+        });
+        drop(state);
+        self.shared.changed.notify_all();
+        id
+    }
+
+    /// Swap a queued run with the queued run before it (`up`) or after it;
+    /// false if there is none
+    pub fn move_run(&self, id: usize, up: bool) -> bool {
+        let mut state = self.shared.lock();
+        let queued: Vec<usize> = (0..state.runs.len())
+            .filter(|&i| state.runs[i].status == QueueStatus::Queued)
+            .collect();
+        let Some(at) = queued.iter().position(|&i| state.runs[i].id == id) else {
+            return false;
+        };
+        let other = if up {
+            at.checked_sub(1)
+        } else {
+            Some(at + 1).filter(|&o| o < queued.len())
+        };
+        match other {
+            Some(other) => {
+                state.runs.swap(queued[at], queued[other]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop a run that isn't running; false if it is or isn't listed
+    pub fn remove(&self, id: usize) -> bool {
+        let mut state = self.shared.lock();
+        match state.runs.iter().position(|r| r.id == id) {
+            Some(at) if state.runs[at].status != QueueStatus::Running => {
+                state.runs.remove(at);
+                drop(state);
+                self.shared.changed.notify_all();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Stop a running run, or mark a queued one cancelled so it never starts
+    pub fn cancel(&self, id: usize) {
+        let mut state = self.shared.lock();
+        if let Some(run) = state.runs.iter_mut().find(|r| r.id == id) {
+            match run.status {
+                QueueStatus::Running => run.token.cancel(),
+                QueueStatus::Queued => run.status = QueueStatus::Cancelled,
+                _ => {}
+            }
+        }
+        drop(state);
+        self.shared.changed.notify_all();
+    }
+
+    /// Drop the finished runs from the list
+    pub fn clear_finished(&self) {
+        self.shared.lock().runs.retain(|r| !r.status.is_finished());
+    }
+
+    /// The runs listed, in queue order
+    pub fn runs(&self) -> Vec<QueuedRun> {
+        let mut runs = self.shared.lock().runs.clone();
+        for run in &mut runs {
+            if run.status == QueueStatus::Running {
+                run.step = self.shared.lanes.holder(run.id);
+                run.duration_ms = run.started.map(|s| s.elapsed().as_millis() as u64);
+            }
+        }
+        runs
+    }
+
+    /// Whether a run is queued or running
+    pub fn is_busy(&self) -> bool {
+        self.shared
+            .lock()
+            .runs
+            .iter()
+            .any(|r| !r.status.is_finished())
+    }
+
+    /// Block until no run is queued or running
+    pub fn wait_idle(&self) {
+        let mut state = self.shared.lock();
+        while state.runs.iter().any(|r| !r.status.is_finished()) {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl Drop for RunQueue {
+    // Runs still queued are dropped and running ones cancelled
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        for run in &state.runs {
+            run.token.cancel();
+        }
+        drop(state);
+        self.shared.changed.notify_all();
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+    }
+}
+
+// Wait for the first queued run and mark it running; None once closed
+fn next_run(shared: &Shared) -> Option<(usize, String, HashMap<String, String>, CancelToken)> {
+    let mut state = shared.lock();
+    loop {
+        if state.closed {
+            return None;
+        }
+        if let Some(run) = state
+            .runs
+            .iter_mut()
+            .find(|r| r.status == QueueStatus::Queued)
+        {
+            run.status = QueueStatus::Running;
+            run.started = Some(Instant::now());
+            return Some((
+                run.id,
+                run.path.clone(),
+                run.params.clone(),
+                run.token.clone(),
+            ));
+        }
+        state = shared
+            .changed
+            .wait(state)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+fn finish_run(shared: &Shared, id: usize, result: Result<Vec<crate::StepLog>, String>) {
+    let mut state = shared.lock();
+    // A run removed meanwhile can't be: running runs aren't removable
+    if let Some(run) = state.runs.iter_mut().find(|r| r.id == id) {
+        run.duration_ms = run.started.map(|s| s.elapsed().as_millis() as u64);
+        run.step = None;
+        match result {
+            Ok(logs) => {
+                run.steps = logs.len();
+                run.error = logs.iter().find_map(|log| {
+                    log.error
+                        .as_ref()
+                        .map(|e| format!("{}: {}", log.step_id, e))
+                });
+                run.status = if run.error.is_some() {
+                    QueueStatus::Error
+                } else {
+                    QueueStatus::Success
+                };
+            }
+            Err(e) if e == cancel::CANCELLED => run.status = QueueStatus::Cancelled,
+            Err(e) => {
+                run.error = Some(e);
+                run.status = QueueStatus::Error;
+            }
+        }
+    }
+    drop(state);
+    shared.changed.notify_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::PluginInstance;
+    use lao_plugin_api::PluginInfo;
+    use std::time::Duration;
+
+    struct Queued {
+        dir: std::path::PathBuf,
+        path: String,
+        queue: RunQueue,
+        // Inputs in the order their runs started
+        order: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Queued {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("lao_run_queue_{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("queued.yaml");
+            std::fs::write(
+                &path,
+                "workflow: queued\nsteps:\n  - run: Record\n    input: ${name}\n",
+            )
+            .unwrap();
+            let info = PluginInfo {
+                name: "Record".to_string(),
+                version: "1.0.0".to_string(),
+                description: String::new(),
+                author: String::new(),
+                dependencies: Vec::new(),
+                tags: Vec::new(),
+                capabilities: Vec::new(),
+                input_schema: None,
+                output_schema: None,
+            };
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let seen = order.clone();
+            let mut registry = PluginRegistry::new();
+            registry.register_plugin(PluginInstance::native(info, move |input| {
+                // Failed steps are retried; only the first attempt counts
+                let mut seen = seen.lock().unwrap();
+                if !seen.iter().any(|s: &String| s == input) {
+                    seen.push(input.to_string());
+                }
+                drop(seen);
+                std::thread::sleep(Duration::from_millis(100));
+                if input == "bad" {
+                    return Err("no such thing".to_string());
+                }
+                Ok(input.to_string())
+            }));
+            Self {
+                path: path.to_string_lossy().into_owned(),
+                dir,
+                queue: RunQueue::new(Arc::new(registry), Lanes::new([1, 1]), 1),
+                order,
+            }
+        }
+
+        fn push(&self, name: &str) -> usize {
+            let params = HashMap::from([("name".to_string(), name.to_string())]);
+            self.queue.push(&self.path, params)
+        }
+
+        fn status(&self, id: usize) -> QueueStatus {
+            let runs = self.queue.runs();
+            runs.iter().find(|r| r.id == id).unwrap().status
+        }
+    }
+
+    impl Drop for Queued {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_queue_runs_in_order_with_params() {
+        let queued = Queued::new();
+        let a = queued.push("a");
+        let b = queued.push("b");
+        queued.queue.wait_idle();
+        assert_eq!(queued.status(a), QueueStatus::Success);
+        assert_eq!(queued.status(b), QueueStatus::Success);
+        assert!(queued.queue.runs().iter().all(|r| r.step.is_none()));
+        assert_eq!(*queued.order.lock().unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_moved_run_goes_first_and_cancelled_run_never_starts() {
+        let queued = Queued::new();
+        queued.push("a");
+        let b = queued.push("b");
+        let c = queued.push("c");
+        assert!(queued.queue.move_run(c, true));
+        queued.queue.cancel(b);
+        queued.queue.wait_idle();
+        assert_eq!(queued.status(b), QueueStatus::Cancelled);
+        assert_eq!(queued.status(c), QueueStatus::Success);
+        assert_eq!(*queued.order.lock().unwrap(), ["a", "c"]);
+    }
+
+    #[test]
+    fn test_failed_run_is_an_error_until_cleared() {
+        let queued = Queued::new();
+        let bad = queued.push("bad");
+        queued.queue.wait_idle();
+        assert_eq!(queued.status(bad), QueueStatus::Error);
+        queued.queue.clear_finished();
+        assert!(queued.queue.runs().is_empty());
+    }
+}
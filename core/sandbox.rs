@@ -0,0 +1,987 @@
+// OS sandboxing for the commands plugins run, enabled with LAO_SANDBOX.
+// Plugins hand their commands to the host through `plugin_host_command`.
+use crate::cross_platform::{PathUtils, Platform};
+use crate::plugin_manager::PluginConfig;
+use lao_plugin_api::{
+    CommandReply, CommandRequest, HostCommand, HostResponse, PluginInput, ProcessOutput,
+    COMMAND_FAILED, COMMAND_RAN, COMMAND_STOPPED,
+};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxMode {
+    Off,
+    /// Sandbox where the OS supports it, warn and run unsandboxed elsewhere
+    On,
+    /// Refuse to run a runner that can't be sandboxed
+    Strict,
+}
+
+impl SandboxMode {
+    /// LAO_SANDBOX: off (default), on, or strict
+    pub fn from_env() -> Self {
+        match std::env::var("LAO_SANDBOX")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "1" | "on" | "true" => SandboxMode::On,
+            "strict" => SandboxMode::Strict,
+            _ => SandboxMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxProfile {
+    pub allow_network: bool,
+    /// Directory hidden from the process (the user's home)
+    pub hidden: Option<PathBuf>,
+    pub read_paths: Vec<PathBuf>,
+    pub write_paths: Vec<PathBuf>,
+}
+
+impl SandboxProfile {
+    /// Map a permission manifest to a profile. `read_files`/`write_files`
+    /// apply to `allowed_file_paths`; `network_access` keeps network access.
+    pub fn from_config(config: &PluginConfig) -> Self {
+        let has = |permission: &str| config.permissions.iter().any(|p| p == permission);
+        let paths: Vec<PathBuf> = config
+            .resource_limits
+            .allowed_file_paths
+            .iter()
+            .filter_map(|p| absolute(Path::new(p)))
+            .collect();
+        let (read_paths, write_paths) = if has("write_files") {
+            (Vec::new(), paths)
+        } else if has("read_files") {
+            (paths, Vec::new())
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        Self {
+            allow_network: has("network_access") || has("network"),
+            hidden: Platform::home_dir(),
+            read_paths,
+            write_paths,
+        }
+    }
+
+    /// Profile from the plugin's saved config, or the default manifest
+    pub fn for_plugin(name: &str) -> Self {
+        let config_path = PathUtils::plugin_dir()
+            .join("configs")
+            .join(format!("{}.json", name));
+        let config = std::fs::read_to_string(config_path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<PluginConfig>(&data).ok())
+            .unwrap_or_default();
+        Self::from_config(&config)
+    }
+
+    // Allowed paths inside the hidden directory, which have to be mounted back
+    #[cfg(target_os = "linux")]
+    fn exposed(&self) -> Vec<(PathBuf, bool)> {
+        let Some(hidden) = &self.hidden else {
+            return Vec::new();
+        };
+        self.read_paths
+            .iter()
+            .map(|p| (p.clone(), false))
+            .chain(self.write_paths.iter().map(|p| (p.clone(), true)))
+            .filter(|(p, _)| p.starts_with(hidden) && p != hidden && p.exists())
+            .collect()
+    }
+}
+
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let path = PathUtils::normalize(path);
+    if let Ok(canonical) = path.canonicalize() {
+        return Some(canonical);
+    }
+    std::env::current_dir().ok().map(|cwd| cwd.join(path))
+}
+
+/// Whether this platform can enforce a SandboxProfile
+pub fn supported() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        linux::user_namespaces_enabled()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        Path::new("/usr/bin/sandbox-exec").exists()
+    }
+    #[cfg(windows)]
+    {
+        true
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        false
+    }
+}
+
+/// A Command for `program`, sandboxed according to `profile` and LAO_SANDBOX
+pub fn command(program: &str, profile: &SandboxProfile) -> Result<Command, String> {
+    command_in(SandboxMode::from_env(), program, profile)
+}
+
+fn command_in(
+    mode: SandboxMode,
+    program: &str,
+    profile: &SandboxProfile,
+) -> Result<Command, String> {
+    if mode == SandboxMode::Off {
+        return Ok(Command::new(program));
+    }
+    if !supported() {
+        if mode == SandboxMode::Strict {
+            return Err(format!(
+                "LAO_SANDBOX=strict but sandboxing is not supported on {}",
+                Platform::os()
+            ));
+        }
+        crate::logging::warn("sandbox not supported; running unsandboxed")
+            .field("program", program)
+            .field("os", Platform::os())
+            .emit();
+        return Ok(Command::new(program));
+    }
+
+    // The runner binary itself may live under the hidden directory
+    let mut profile = profile.clone();
+    if let Some(dir) = resolve_program(program).and_then(|p| p.parent().map(Path::to_path_buf)) {
+        profile.read_paths.push(dir);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let mut cmd = Command::new(program);
+        linux::confine(&mut cmd, &profile)?;
+        Ok(cmd)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let mut cmd = Command::new("/usr/bin/sandbox-exec");
+        cmd.arg("-p").arg(seatbelt_profile(&profile)).arg(program);
+        Ok(cmd)
+    }
+    // The token is applied when the command is run, by `output`
+    #[cfg(windows)]
+    {
+        if !profile.allow_network {
+            if mode == SandboxMode::Strict {
+                return Err(
+                    "LAO_SANDBOX=strict but network access can't be blocked on windows".to_string(),
+                );
+            }
+            crate::logging::warn("sandbox can't block network access on windows")
+                .field("program", program)
+                .emit();
+        }
+        Ok(Command::new(program))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = profile;
+        Ok(Command::new(program))
+    }
+}
+
+/// Run a command from `command` to completion, like `Command::output`
+pub fn output(cmd: &mut Command) -> std::io::Result<Output> {
+    #[cfg(windows)]
+    {
+        if SandboxMode::from_env() != SandboxMode::Off {
+            return windows::output(cmd);
+        }
+    }
+    cmd.output()
+}
+
+thread_local! {
+    static PLUGIN: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// While alive, commands run for plugins on this thread are `name`'s
+pub struct PluginScope {
+    previous: Option<String>,
+}
+
+impl PluginScope {
+    pub fn enter(name: &str) -> Self {
+        Self {
+            previous: PLUGIN.with(|plugin| plugin.replace(Some(name.to_string()))),
+        }
+    }
+}
+
+impl Drop for PluginScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        PLUGIN.with(|plugin| *plugin.borrow_mut() = previous);
+    }
+}
+
+/// Handed to plugins that export `plugin_host_command`
+pub static HOST_COMMAND: HostCommand = HostCommand {
+    run: host_run_command,
+    free_response: crate::ollama::host_free_response,
+};
+
+unsafe extern "C" fn host_run_command(request: *const PluginInput) -> HostResponse {
+    let request = if request.is_null() {
+        String::new()
+    } else {
+        (*request).to_string_lossy().into_owned()
+    };
+    let (status, body) = match serde_json::from_str::<CommandRequest>(&request) {
+        Ok(request) => match run_for_plugin(SandboxMode::from_env(), &request) {
+            Ok(output) => (
+                COMMAND_RAN,
+                serde_json::to_string(&CommandReply::from(output)).unwrap_or_default(),
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                (COMMAND_STOPPED, e.to_string())
+            }
+            Err(e) => (COMMAND_FAILED, e.to_string()),
+        },
+        Err(e) => (COMMAND_FAILED, format!("invalid command request: {}", e)),
+    };
+    crate::ollama::host_response(status, body)
+}
+
+// Run a command for the plugin being called on this thread, in its sandbox.
+// What it writes goes to the step's logs, and it is killed when the step stops.
+fn run_for_plugin(mode: SandboxMode, request: &CommandRequest) -> std::io::Result<ProcessOutput> {
+    let plugin = PLUGIN.with(|plugin| plugin.borrow().clone());
+    let profile = SandboxProfile::for_plugin(plugin.as_deref().unwrap_or_default());
+    let mut cmd = command_in(mode, &request.program, &profile).map_err(std::io::Error::other)?;
+    request.apply(&mut cmd);
+    // The restricted token is only applied by `windows::output`, which
+    // can't stream lines or end the command early
+    #[cfg(windows)]
+    if mode != SandboxMode::Off {
+        let output = windows::output(&mut cmd)?;
+        let (stdout, stderr) = (
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        );
+        for line in stdout.lines() {
+            crate::process_output::record(lao_plugin_api::STDOUT, line);
+        }
+        for line in stderr.lines() {
+            crate::process_output::record(lao_plugin_api::STDERR, line);
+        }
+        return Ok(ProcessOutput {
+            status: output.status,
+            stdout,
+            stderr,
+        });
+    }
+    lao_plugin_api::spawn_process(
+        &mut cmd,
+        request.stop_file.as_ref(),
+        &mut crate::process_output::record,
+        &|| crate::cancel::stopped().is_some(),
+    )
+}
+
+fn resolve_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return absolute(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// macOS sandbox-exec (SBPL) profile; later rules take precedence
+pub fn seatbelt_profile(profile: &SandboxProfile) -> String {
+    let quote = |p: &Path| {
+        format!(
+            "\"{}\"",
+            p.display()
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        )
+    };
+    let mut sbpl = String::from("(version 1)\n(allow default)\n");
+    if let Some(hidden) = &profile.hidden {
+        sbpl.push_str(&format!(
+            "(deny file-read* file-write* (subpath {}))\n",
+            quote(hidden)
+        ));
+    }
+    for path in &profile.read_paths {
+        sbpl.push_str(&format!("(allow file-read* (subpath {}))\n", quote(path)));
+    }
+    for path in &profile.write_paths {
+        sbpl.push_str(&format!(
+            "(allow file-read* file-write* (subpath {}))\n",
+            quote(path)
+        ));
+    }
+    if !profile.allow_network {
+        sbpl.push_str("(deny network*)\n");
+    }
+    sbpl
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::SandboxProfile;
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::Command;
+
+    pub fn user_namespaces_enabled() -> bool {
+        let disabled = |path: &str| {
+            std::fs::read_to_string(path)
+                .map(|v| v.trim() == "0")
+                .unwrap_or(false)
+        };
+        !disabled("/proc/sys/kernel/unprivileged_userns_clone")
+            && !disabled("/proc/sys/user/max_user_namespaces")
+    }
+
+    // Everything pre_exec needs, allocated before fork
+    struct Mount {
+        source: CString,
+        target: CString,
+        // Directories to create inside the tmpfs, outermost first
+        parents: Vec<CString>,
+        is_dir: bool,
+        writable: bool,
+    }
+
+    fn cstring(path: &Path) -> Result<CString, String> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| format!("path contains a NUL byte: {}", path.display()))
+    }
+
+    pub fn confine(cmd: &mut Command, profile: &SandboxProfile) -> Result<(), String> {
+        let hidden = profile.hidden.as_deref().map(cstring).transpose()?;
+        let mut mounts = Vec::new();
+        if let Some(hidden_dir) = &profile.hidden {
+            for (path, writable) in profile.exposed() {
+                let parents = path
+                    .ancestors()
+                    .skip(1)
+                    .take_while(|p| p.starts_with(hidden_dir) && p != hidden_dir)
+                    .map(cstring)
+                    .collect::<Result<Vec<_>, _>>()?;
+                mounts.push(Mount {
+                    source: cstring(&path)?,
+                    target: cstring(&path)?,
+                    parents: parents.into_iter().rev().collect(),
+                    is_dir: path.is_dir(),
+                    writable,
+                });
+            }
+        }
+        let uid_map = CString::new(format!("{0} {0} 1", unsafe { libc::getuid() })).unwrap();
+        let gid_map = CString::new(format!("{0} {0} 1", unsafe { libc::getgid() })).unwrap();
+        let mut flags = libc::CLONE_NEWUSER | libc::CLONE_NEWNS;
+        if !profile.allow_network {
+            flags |= libc::CLONE_NEWNET;
+        }
+        let mut fds: Vec<libc::c_int> = Vec::with_capacity(mounts.len());
+        let filter = seccomp_filter(profile.allow_network);
+
+        unsafe {
+            cmd.pre_exec(move || {
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+                check(libc::unshare(flags))?;
+                write_file(c"/proc/self/setgroups", b"deny")?;
+                write_file(c"/proc/self/uid_map", uid_map.as_bytes())?;
+                write_file(c"/proc/self/gid_map", gid_map.as_bytes())?;
+                check(libc::mount(
+                    std::ptr::null(),
+                    c"/".as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                ))?;
+                // Hold the allowed paths open before the tmpfs hides them. Only
+                // mounts of the new namespace can be bound, so not before unshare.
+                fds.clear();
+                for mount in &mounts {
+                    fds.push(check(libc::open(
+                        mount.source.as_ptr(),
+                        libc::O_PATH | libc::O_CLOEXEC,
+                    ))?);
+                }
+                if let Some(hidden) = &hidden {
+                    hide(hidden, &mounts, &fds)?;
+                }
+                // Last, since the filter denies the mounts above
+                if !filter.is_empty() {
+                    let program = libc::sock_fprog {
+                        len: filter.len() as u16,
+                        filter: filter.as_ptr() as *mut libc::sock_filter,
+                    };
+                    check(libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER as libc::c_ulong,
+                        &program as *const libc::sock_fprog as libc::c_ulong,
+                        0,
+                        0,
+                    ))?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    // Cover the hidden directory with a tmpfs and bind the allowed paths back
+    unsafe fn hide(hidden: &CString, mounts: &[Mount], fds: &[libc::c_int]) -> std::io::Result<()> {
+        check(libc::mount(
+            c"tmpfs".as_ptr(),
+            hidden.as_ptr(),
+            c"tmpfs".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            std::ptr::null(),
+        ))?;
+        for (mount, fd) in mounts.iter().zip(fds) {
+            for parent in &mount.parents {
+                libc::mkdir(parent.as_ptr(), 0o755);
+            }
+            if mount.is_dir {
+                libc::mkdir(mount.target.as_ptr(), 0o755);
+            } else {
+                let created = libc::open(
+                    mount.target.as_ptr(),
+                    libc::O_CREAT | libc::O_CLOEXEC,
+                    0o644,
+                );
+                if created >= 0 {
+                    libc::close(created);
+                }
+            }
+            let mut source = [0u8; 32];
+            fd_path(*fd, &mut source);
+            check(libc::mount(
+                source.as_ptr().cast(),
+                mount.target.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND | libc::MS_REC,
+                std::ptr::null(),
+            ))?;
+            if !mount.writable {
+                check(libc::mount(
+                    std::ptr::null(),
+                    mount.target.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND | libc::MS_REMOUNT | libc::MS_RDONLY,
+                    std::ptr::null(),
+                ))?;
+            }
+            libc::close(*fd);
+        }
+        Ok(())
+    }
+
+    fn check(result: libc::c_int) -> std::io::Result<libc::c_int> {
+        if result < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    // open/write/close only: this runs between fork and exec
+    unsafe fn write_file(path: &std::ffi::CStr, contents: &[u8]) -> std::io::Result<()> {
+        let fd = check(libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC))?;
+        let written = libc::write(fd, contents.as_ptr().cast(), contents.len());
+        libc::close(fd);
+        if written < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    // "/proc/self/fd/<fd>\0" without allocating
+    fn fd_path(fd: libc::c_int, out: &mut [u8; 32]) {
+        const PREFIX: &[u8] = b"/proc/self/fd/";
+        out[..PREFIX.len()].copy_from_slice(PREFIX);
+        let mut digits = [0u8; 10];
+        let mut n = fd.max(0) as u32;
+        let mut len = 0;
+        loop {
+            digits[len] = b'0' + (n % 10) as u8;
+            len += 1;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        for i in 0..len {
+            out[PREFIX.len() + i] = digits[len - 1 - i];
+        }
+        out[PREFIX.len() + len] = 0;
+    }
+
+    // Syscalls a model runner has no use for: tracing or reading other
+    // processes, loading kernel code, undoing the confinement set up above,
+    // and changing system-wide state
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_acct,
+        libc::SYS_quotactl,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_iopl,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_ioperm,
+    ];
+
+    // struct seccomp_data: nr, arch, instruction pointer, args
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+    const ARG0_OFFSET: u32 = 16;
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+    // x32 syscalls on x86_64, which would get around the numbers above
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    /// Seccomp BPF program denying DENIED_SYSCALLS with EPERM, and IPv4/IPv6
+    /// sockets without `allow_network`; empty on other architectures
+    pub fn seccomp_filter(allow_network: bool) -> Vec<libc::sock_filter> {
+        let Some(audit_arch) = AUDIT_ARCH else {
+            return Vec::new();
+        };
+        let stmt = |code: u32, k: u32| libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jump = |code: u32, k: u32, jf: u8| libc::sock_filter {
+            code: (libc::BPF_JMP | code | libc::BPF_K) as u16,
+            jt: 0,
+            jf,
+            k,
+        };
+        let load = |offset: u32| stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+
+        let mut program = vec![
+            load(ARCH_OFFSET),
+            libc::sock_filter {
+                jt: 1,
+                ..jump(libc::BPF_JEQ, audit_arch, 0)
+            },
+            stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            load(NR_OFFSET),
+        ];
+        // Checks that jump to the final EPERM when they match
+        let mut denials = Vec::new();
+        if cfg!(target_arch = "x86_64") {
+            denials.push(program.len());
+            program.push(jump(libc::BPF_JGE, X32_SYSCALL_BIT, 0));
+        }
+        for nr in DENIED_SYSCALLS {
+            denials.push(program.len());
+            program.push(jump(libc::BPF_JEQ, *nr as u32, 0));
+        }
+        if !allow_network {
+            // Anything but socket() skips the domain checks
+            program.push(jump(libc::BPF_JEQ, libc::SYS_socket as u32, 3));
+            program.push(load(ARG0_OFFSET));
+            for domain in [libc::AF_INET, libc::AF_INET6] {
+                denials.push(program.len());
+                program.push(jump(libc::BPF_JEQ, domain as u32, 0));
+            }
+        }
+        program.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        let deny = program.len();
+        program.push(stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+        ));
+        for at in denials {
+            program[at].jt = (deny - at - 1) as u8;
+        }
+        program
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsStr;
+    use std::io::Read;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{FromRawHandle, OwnedHandle};
+    use std::os::windows::process::ExitStatusExt;
+    use std::process::{Command, ExitStatus, Output};
+    use windows_sys::Win32::Foundation::{SetHandleInformation, HANDLE, HANDLE_FLAG_INHERIT};
+    use windows_sys::Win32::Security::{
+        CreateRestrictedToken, CreateWellKnownSid, SetTokenInformation, TokenIntegrityLevel,
+        WinBuiltinAdministratorsSid, WinLowLabelSid, DISABLE_MAX_PRIVILEGE, LUA_TOKEN,
+        SECURITY_ATTRIBUTES, SECURITY_MAX_SID_SIZE, SID_AND_ATTRIBUTES, TOKEN_ADJUST_DEFAULT,
+        TOKEN_ASSIGN_PRIMARY, TOKEN_DUPLICATE, TOKEN_MANDATORY_LABEL, TOKEN_QUERY,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+    use windows_sys::Win32::System::Pipes::CreatePipe;
+    use windows_sys::Win32::System::SystemServices::SE_GROUP_INTEGRITY;
+    use windows_sys::Win32::System::Threading::{
+        CreateProcessAsUserW, GetCurrentProcess, GetExitCodeProcess, OpenProcessToken,
+        ResumeThread, TerminateProcess, WaitForSingleObject, CREATE_NO_WINDOW, CREATE_SUSPENDED,
+        INFINITE, PROCESS_INFORMATION, STARTF_USESTDHANDLES, STARTUPINFOW,
+    };
+
+    fn check(ok: i32) -> std::io::Result<()> {
+        if ok == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn owned(handle: HANDLE) -> OwnedHandle {
+        unsafe { OwnedHandle::from_raw_handle(handle) }
+    }
+
+    fn wide(text: &OsStr) -> Vec<u16> {
+        text.encode_wide().chain(Some(0)).collect()
+    }
+
+    // Quote one argument the way CommandLineToArgvW splits it
+    fn quote(arg: &OsStr, line: &mut Vec<u16>) {
+        let arg: Vec<u16> = arg.encode_wide().collect();
+        let plain = !arg.is_empty()
+            && !arg
+                .iter()
+                .any(|c| [b' ', b'\t', b'"'].iter().any(|q| *c == *q as u16));
+        if plain {
+            line.extend(arg);
+            return;
+        }
+        line.push(b'"' as u16);
+        let mut backslashes = 0;
+        for c in arg {
+            if c == b'\\' as u16 {
+                backslashes += 1;
+            } else {
+                if c == b'"' as u16 {
+                    line.extend(std::iter::repeat_n(b'\\' as u16, backslashes + 1));
+                }
+                backslashes = 0;
+            }
+            line.push(c);
+        }
+        line.extend(std::iter::repeat_n(b'\\' as u16, backslashes));
+        line.push(b'"' as u16);
+    }
+
+    // A copy of our token without admin group or privileges, at low integrity
+    fn restricted_token() -> std::io::Result<OwnedHandle> {
+        unsafe {
+            let mut token: HANDLE = std::ptr::null_mut();
+            check(OpenProcessToken(
+                GetCurrentProcess(),
+                TOKEN_DUPLICATE | TOKEN_QUERY | TOKEN_ASSIGN_PRIMARY | TOKEN_ADJUST_DEFAULT,
+                &mut token,
+            ))?;
+            let token = owned(token);
+
+            let mut admins = [0u8; SECURITY_MAX_SID_SIZE as usize];
+            let mut size = admins.len() as u32;
+            check(CreateWellKnownSid(
+                WinBuiltinAdministratorsSid,
+                std::ptr::null_mut(),
+                admins.as_mut_ptr().cast(),
+                &mut size,
+            ))?;
+            let disabled = SID_AND_ATTRIBUTES {
+                Sid: admins.as_mut_ptr().cast(),
+                Attributes: 0,
+            };
+            let mut restricted: HANDLE = std::ptr::null_mut();
+            check(CreateRestrictedToken(
+                std::os::windows::io::AsRawHandle::as_raw_handle(&token),
+                DISABLE_MAX_PRIVILEGE | LUA_TOKEN,
+                1,
+                &disabled,
+                0,
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                &mut restricted,
+            ))?;
+            let restricted = owned(restricted);
+
+            let mut low = [0u8; SECURITY_MAX_SID_SIZE as usize];
+            let mut size = low.len() as u32;
+            check(CreateWellKnownSid(
+                WinLowLabelSid,
+                std::ptr::null_mut(),
+                low.as_mut_ptr().cast(),
+                &mut size,
+            ))?;
+            let label = TOKEN_MANDATORY_LABEL {
+                Label: SID_AND_ATTRIBUTES {
+                    Sid: low.as_mut_ptr().cast(),
+                    Attributes: SE_GROUP_INTEGRITY as u32,
+                },
+            };
+            check(SetTokenInformation(
+                std::os::windows::io::AsRawHandle::as_raw_handle(&restricted),
+                TokenIntegrityLevel,
+                (&label as *const TOKEN_MANDATORY_LABEL).cast(),
+                std::mem::size_of::<TOKEN_MANDATORY_LABEL>() as u32 + size,
+            ))?;
+            Ok(restricted)
+        }
+    }
+
+    // A pipe whose `child` end is inherited
+    fn pipe(child_writes: bool) -> std::io::Result<(OwnedHandle, OwnedHandle)> {
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: std::ptr::null_mut(),
+            bInheritHandle: 1,
+        };
+        let (mut read, mut write): (HANDLE, HANDLE) = (std::ptr::null_mut(), std::ptr::null_mut());
+        unsafe {
+            check(CreatePipe(&mut read, &mut write, &attributes, 0))?;
+            let (ours, child) = if child_writes {
+                (owned(read), owned(write))
+            } else {
+                (owned(write), owned(read))
+            };
+            check(SetHandleInformation(
+                std::os::windows::io::AsRawHandle::as_raw_handle(&ours),
+                HANDLE_FLAG_INHERIT,
+                0,
+            ))?;
+            Ok((ours, child))
+        }
+    }
+
+    pub fn output(cmd: &mut Command) -> std::io::Result<Output> {
+        use std::os::windows::io::AsRawHandle;
+
+        let mut line = Vec::new();
+        quote(cmd.get_program(), &mut line);
+        for arg in cmd.get_args() {
+            line.push(b' ' as u16);
+            quote(arg, &mut line);
+        }
+        line.push(0);
+        let dir = cmd.get_current_dir().map(|dir| wide(dir.as_os_str()));
+
+        let token = restricted_token()?;
+        let (stdin, child_stdin) = pipe(false)?;
+        let (stdout, child_stdout) = pipe(true)?;
+        let (stderr, child_stderr) = pipe(true)?;
+        let mut startup: STARTUPINFOW = unsafe { std::mem::zeroed() };
+        startup.cb = std::mem::size_of::<STARTUPINFOW>() as u32;
+        startup.dwFlags = STARTF_USESTDHANDLES;
+        startup.hStdInput = child_stdin.as_raw_handle();
+        startup.hStdOutput = child_stdout.as_raw_handle();
+        startup.hStdError = child_stderr.as_raw_handle();
+        let mut info: PROCESS_INFORMATION = unsafe { std::mem::zeroed() };
+
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                return Err(std::io::Error::last_os_error());
+            }
+            let job = owned(job);
+            let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+            limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            check(SetInformationJobObject(
+                job.as_raw_handle(),
+                JobObjectExtendedLimitInformation,
+                (&limits as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION).cast(),
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            ))?;
+
+            check(CreateProcessAsUserW(
+                token.as_raw_handle(),
+                std::ptr::null(),
+                line.as_mut_ptr(),
+                std::ptr::null(),
+                std::ptr::null(),
+                1,
+                CREATE_SUSPENDED | CREATE_NO_WINDOW,
+                std::ptr::null(),
+                dir.as_ref().map_or(std::ptr::null(), |dir| dir.as_ptr()),
+                &startup,
+                &mut info,
+            ))?;
+            let process = owned(info.hProcess);
+            let thread = owned(info.hThread);
+            drop((child_stdin, child_stdout, child_stderr, stdin));
+            // Resumed only once it is in the job, so nothing it starts escapes
+            if let Err(e) = check(AssignProcessToJobObject(
+                job.as_raw_handle(),
+                process.as_raw_handle(),
+            )) {
+                TerminateProcess(process.as_raw_handle(), 1);
+                return Err(e);
+            }
+            ResumeThread(thread.as_raw_handle());
+
+            let read_all = |handle: OwnedHandle| {
+                std::thread::spawn(move || {
+                    let mut buffer = Vec::new();
+                    let _ = std::fs::File::from(handle).read_to_end(&mut buffer);
+                    buffer
+                })
+            };
+            let stdout = read_all(stdout);
+            let stderr = read_all(stderr);
+            WaitForSingleObject(process.as_raw_handle(), INFINITE);
+            let mut code = 0u32;
+            check(GetExitCodeProcess(process.as_raw_handle(), &mut code))?;
+            Ok(Output {
+                status: ExitStatus::from_raw(code),
+                stdout: stdout.join().unwrap_or_default(),
+                stderr: stderr.join().unwrap_or_default(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_from_manifest() {
+        let mut config = PluginConfig {
+            permissions: vec!["read_files".to_string(), "network_access".to_string()],
+            ..Default::default()
+        };
+        config.resource_limits.allowed_file_paths = vec!["/srv/models".to_string()];
+        let mut profile = SandboxProfile::from_config(&config);
+        assert!(profile.allow_network);
+        assert_eq!(profile.read_paths, vec![PathBuf::from("/srv/models")]);
+        assert!(profile.write_paths.is_empty());
+
+        profile.hidden = Some(PathBuf::from("/home/alice"));
+        profile.allow_network = false;
+        profile.write_paths = vec![PathBuf::from("/home/alice/lao \"out\"")];
+        let sbpl = seatbelt_profile(&profile);
+        assert!(sbpl.contains("(deny file-read* file-write* (subpath \"/home/alice\"))"));
+        assert!(sbpl.contains("(allow file-read* (subpath \"/srv/models\"))"));
+        assert!(sbpl.contains("(subpath \"/home/alice/lao \\\"out\\\"\")"));
+        assert!(sbpl.ends_with("(deny network*)\n"));
+
+        config.permissions.clear();
+        assert!(SandboxProfile::from_config(&config).read_paths.is_empty());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_plugin_commands_cannot_read_home() {
+        if !supported() {
+            return;
+        }
+        let home = Platform::home_dir().expect("a home directory");
+        let secret = home.join(format!(".lao_sandbox_{}", uuid::Uuid::new_v4()));
+        std::fs::write(&secret, "secret").unwrap();
+        let _plugin = PluginScope::enter("SandboxTestPlugin");
+        // The way a plugin's run_process reaches the host
+        unsafe { lao_plugin_api::accept_host_command(&HOST_COMMAND) };
+        let mut cat = Command::new("cat");
+        cat.arg(&secret);
+        let unsandboxed = lao_plugin_api::run_process(&mut cat);
+        let request = CommandRequest::of(&cat, None);
+        let sandboxed = run_for_plugin(SandboxMode::Strict, &request);
+        std::fs::remove_file(&secret).unwrap();
+
+        assert_eq!(unsandboxed.unwrap().stdout, "secret");
+        let sandboxed = sandboxed.unwrap();
+        assert!(!sandboxed.status.success());
+        assert_eq!(sandboxed.stdout, "");
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn test_seccomp_filter_denies_syscalls() {
+        use std::os::unix::process::CommandExt;
+
+        // The child checks from inside the filter that unshare() is denied;
+        // spawning fails otherwise
+        let filter = linux::seccomp_filter(false);
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-c").arg("exit 0");
+        unsafe {
+            cmd.pre_exec(move || {
+                libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+                let program = libc::sock_fprog {
+                    len: filter.len() as u16,
+                    filter: filter.as_ptr() as *mut libc::sock_filter,
+                };
+                let installed = libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER as libc::c_ulong,
+                    &program as *const libc::sock_fprog as libc::c_ulong,
+                    0,
+                    0,
+                );
+                if installed != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let denied = libc::unshare(0) == -1
+                    && std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+                    && libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0) == -1;
+                if denied {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::other("syscall was not denied"))
+                }
+            });
+        }
+        let status = cmd.status().expect("runner starts under the filter");
+        assert!(status.success());
+    }
+}
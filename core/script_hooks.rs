@@ -0,0 +1,337 @@
+// Local commands run on run and step events, from hooks.yaml.
+// The hooks.yaml format is in docs/workflows.md (Script Hooks).
+use crate::hooks::{ExecutionHook, RunContext, RunEnd, StepContext, StepFinish};
+use crate::{redaction, StepLog};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Default hooks file, in the data directory; overridable with `LAO_HOOKS`
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_HOOKS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("hooks.yaml"))
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+/// One command or a list of them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Commands {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Commands {
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        match self {
+            Commands::One(command) => std::slice::from_ref(command).iter(),
+            Commands::Many(commands) => commands.iter(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HooksConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_run_start: Option<Commands>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_step_complete: Option<Commands>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_run_complete: Option<Commands>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_run_failed: Option<Commands>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+/// The hooks file at `path`; `None` if there is none
+pub fn load(path: &Path) -> Result<Option<HooksConfig>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    serde_yaml::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+/// Run `command` in `dir` with `payload` on stdin and wait for it, killing it
+/// after `timeout`
+pub fn run_command(
+    command: &str,
+    dir: &Path,
+    payload: &str,
+    timeout: Duration,
+) -> Result<(), String> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    };
+    if !dir.as_os_str().is_empty() {
+        cmd.current_dir(dir);
+    }
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot start: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read its input closes the pipe early
+        let _ = writeln!(stdin, "{}", payload);
+    }
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.stderr.take() {
+                    let _ = std::io::Read::read_to_string(&mut pipe, &mut stderr);
+                }
+                return Err(format!("{}: {}", status, stderr.trim()));
+            }
+            None if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("killed after {:?}", timeout));
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+/// Runs the commands of a hooks file; the built-in one reads `default_path`
+#[derive(Default)]
+pub struct ScriptHook {
+    path: Option<PathBuf>,
+}
+
+impl ScriptHook {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+        }
+    }
+
+    fn fire(
+        &self,
+        run_id: &str,
+        event: &str,
+        select: fn(&HooksConfig) -> Option<&Commands>,
+        payload: Value,
+    ) {
+        let path = self.path.clone().unwrap_or_else(default_path);
+        let config = match load(&path) {
+            Ok(Some(config)) => config,
+            Ok(None) => return,
+            Err(e) => {
+                crate::logging::warn("hooks file not loaded")
+                    .field("error", e)
+                    .run(run_id)
+                    .emit();
+                return;
+            }
+        };
+        let Some(commands) = select(&config) else {
+            return;
+        };
+        let mut payload = payload;
+        payload["event"] = json!(event);
+        let payload = payload.to_string();
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let timeout = Duration::from_secs(config.timeout_secs);
+        for command in commands.iter().cloned() {
+            let (run_id, dir, payload) = (run_id.to_string(), dir.clone(), payload.clone());
+            thread::spawn(move || {
+                if let Err(e) = run_command(&command, &dir, &payload, timeout) {
+                    crate::logging::warn("hook command failed")
+                        .field("command", command)
+                        .field("error", e)
+                        .run(run_id)
+                        .emit();
+                }
+            });
+        }
+    }
+}
+
+fn run_payload(run: &RunContext) -> Value {
+    json!({
+        "run_id": run.run_id,
+        "workflow": run.workflow.workflow,
+        "path": run.path,
+        "inputs": run.inputs,
+        "labels": run.labels,
+    })
+}
+
+fn step_payload(run_id: &str, log: &StepLog) -> Value {
+    let redact = |text: &Option<String>| text.as_ref().map(|t| redaction::redact(t, Some(run_id)));
+    json!({
+        "step_id": log.step_id,
+        "plugin": log.runner,
+        "output": redact(&log.output),
+        "error": redact(&log.error),
+    })
+}
+
+impl ExecutionHook for ScriptHook {
+    fn on_run_start(&self, run: &RunContext) {
+        self.fire(
+            run.run_id,
+            "run_start",
+            |c| c.on_run_start.as_ref(),
+            run_payload(run),
+        );
+    }
+
+    fn on_step_finish(&self, run: &RunContext, _step: &StepContext, finish: &StepFinish) {
+        let mut payload = run_payload(run);
+        let step = step_payload(run.run_id, finish.log);
+        payload
+            .as_object_mut()
+            .unwrap()
+            .extend(step.as_object().cloned().unwrap_or_default());
+        payload["status"] = json!(finish.status.as_str());
+        payload["duration_ms"] = json!(finish.duration.as_millis() as u64);
+        self.fire(
+            run.run_id,
+            "step_complete",
+            |c| c.on_step_complete.as_ref(),
+            payload,
+        );
+    }
+
+    fn on_run_end(&self, run: &RunContext, end: &RunEnd) {
+        let mut payload = run_payload(run);
+        payload["status"] = json!(end.status);
+        payload["duration_ms"] = json!(end.duration.as_millis() as u64);
+        payload["steps"] = end
+            .logs
+            .iter()
+            .map(|log| {
+                let mut step = step_payload(run.run_id, log);
+                step["status"] = json!(if log.error.is_some() {
+                    "error"
+                } else {
+                    "success"
+                });
+                step
+            })
+            .collect();
+        if end.status == "error" {
+            self.fire(
+                run.run_id,
+                "run_failed",
+                |c| c.on_run_failed.as_ref(),
+                payload.clone(),
+            );
+        }
+        self.fire(
+            run.run_id,
+            "run_complete",
+            |c| c.on_run_complete.as_ref(),
+            payload,
+        );
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+    use std::collections::HashMap;
+
+    /// A temp dir with a hooks.yaml that appends every event to events.jsonl.
+    fn hooks_dir() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_script_hooks_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hooks_path = dir.join("hooks.yaml");
+        std::fs::write(
+            &hooks_path,
+            "on_run_complete: cat >> events.jsonl\non_step_complete:\n  - cat >> events.jsonl\n  - exit 3\n",
+        )
+        .unwrap();
+        (dir, hooks_path)
+    }
+
+    #[test]
+    fn test_load_hooks_config() {
+        let (dir, hooks_path) = hooks_dir();
+        let config = load(&hooks_path).unwrap().unwrap();
+        assert_eq!(config.timeout_secs, 60);
+        assert_eq!(config.on_step_complete.unwrap().iter().count(), 2);
+        assert!(load(&dir.join("none.yaml")).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_slow_commands_are_killed() {
+        let dir = std::env::temp_dir();
+        let slow = run_command("sleep 5", &dir, "{}", Duration::from_millis(200));
+        assert_eq!(slow.unwrap_err(), "killed after 200ms");
+    }
+
+    #[test]
+    fn test_hook_commands_get_events_on_stdin() {
+        let (dir, hooks_path) = hooks_dir();
+        // Registered hooks see the runs of parallel tests too
+        crate::hooks::register(ScriptHook::new(&hooks_path));
+        let name = format!("scripted-{}", uuid::Uuid::new_v4());
+        let flow = dir.join("flow.yaml");
+        std::fs::write(
+            &flow,
+            format!(
+                "workflow: {}\nsteps:\n  - run: EchoPlugin\n    input: hi\n",
+                name
+            ),
+        )
+        .unwrap();
+        let path = flow.to_str().unwrap();
+        let workflow = crate::load_workflow_yaml(path).unwrap();
+        let registry = mock_registry(&workflow, &MockFixtures::default());
+        crate::run_workflow_with_registry(path, HashMap::new(), &registry).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let events = loop {
+            let events: Vec<Value> = std::fs::read_to_string(dir.join("events.jsonl"))
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+                .filter(|event| event["workflow"] == name.as_str())
+                .collect();
+            if events.len() == 2 || Instant::now() > deadline {
+                break events;
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        let step = events
+            .iter()
+            .find(|e| e["event"] == "step_complete")
+            .unwrap();
+        assert_eq!(step["output"], "EchoPlugin(hi)");
+        assert_eq!(step["status"], "success");
+        let run = events
+            .iter()
+            .find(|e| e["event"] == "run_complete")
+            .unwrap();
+        assert_eq!(run["status"], "success");
+        assert_eq!(run["steps"][0]["step_id"], "step1");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
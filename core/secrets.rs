@@ -0,0 +1,266 @@
+// The encrypted secrets store.
+// `lao secret` and `${{ secrets.NAME }}`: docs/workflows.md (Secrets).
+use crate::encryption::{self, Cipher};
+use crate::WorkflowStep;
+use regex::{Captures, Regex};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+// Shorter values would hide ordinary words
+const MIN_HIDDEN_LEN: usize = 4;
+
+/// Default store, overridable with `LAO_SECRETS`
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_SECRETS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("secrets.enc"))
+}
+
+/// Default keyfile, overridable with `LAO_SECRETS_KEYFILE`
+pub fn default_keyfile() -> PathBuf {
+    std::env::var("LAO_SECRETS_KEYFILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("secrets.key"))
+}
+
+/// Secret names are like environment variable names
+pub fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Secrets sealed in one file
+pub struct SecretStore {
+    path: PathBuf,
+    keyfile: PathBuf,
+}
+
+impl SecretStore {
+    pub fn open_default() -> Self {
+        Self::at(default_path(), default_keyfile())
+    }
+
+    pub fn at(path: impl Into<PathBuf>, keyfile: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            keyfile: keyfile.into(),
+        }
+    }
+
+    /// Every secret by name; none when the store doesn't exist yet
+    pub fn load(&self) -> Result<BTreeMap<String, String>, String> {
+        let sealed = match fs::read(&self.path) {
+            Ok(sealed) => sealed,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(format!("Cannot read {}: {}", self.path.display(), e)),
+        };
+        let json = Cipher::from_keyfile(&self.keyfile)?
+            .open(&sealed)
+            .map_err(|e| format!("Cannot open {}: {}", self.path.display(), e))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid {}: {}", self.path.display(), e))
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        if !valid_name(name) {
+            return Err(format!(
+                "'{}' is not a valid secret name (letters, digits and _, not starting with a digit)",
+                name
+            ));
+        }
+        let mut secrets = self.load()?;
+        secrets.insert(name.to_string(), value.to_string());
+        self.save(&secrets)
+    }
+
+    /// Whether `name` was stored
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        let mut secrets = self.load()?;
+        if secrets.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.save(&secrets).map(|_| true)
+    }
+
+    fn save(&self, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+        if !self.keyfile.exists() {
+            encryption::generate_keyfile(&self.keyfile)
+                .map_err(|e| format!("Cannot create {}: {}", self.keyfile.display(), e))?;
+        }
+        let json = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+        let sealed = Cipher::from_keyfile(&self.keyfile)?.seal(&json)?;
+        if let Some(dir) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        write_private(&self.path, &sealed)
+            .map_err(|e| format!("Cannot write {}: {}", self.path.display(), e))
+    }
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn reference() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\$\{\{\s*secrets\.([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap())
+}
+
+// Names of the secrets the step's params refer to
+fn referenced(params: &serde_yaml::Value) -> Vec<String> {
+    let mut names: Vec<String> = params
+        .as_mapping()
+        .into_iter()
+        .flat_map(|mapping| mapping.values())
+        .filter_map(|value| value.as_str())
+        .flat_map(|text| {
+            reference()
+                .captures_iter(text)
+                .map(|caps| caps[1].to_string())
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Why the step's secrets can't be filled in, for the plan
+pub fn problem(step: &WorkflowStep) -> Option<String> {
+    let names = referenced(&step.params);
+    if names.is_empty() {
+        return None;
+    }
+    let secrets = match SecretStore::open_default().load() {
+        Ok(secrets) => secrets,
+        Err(e) => return Some(e),
+    };
+    names
+        .into_iter()
+        .find(|name| !secrets.contains_key(name))
+        .map(|name| {
+            format!(
+                "secret {0} is not in the store; add it with `lao secret set {0}`",
+                name
+            )
+        })
+}
+
+static REVEALED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Fill `${{ secrets.NAME }}` in the step's params for `accessor`, the step
+/// about to run with them
+pub(crate) fn reveal(params: &mut serde_yaml::Value, accessor: &str) {
+    if referenced(params).is_empty() {
+        return;
+    }
+    let secrets = match SecretStore::open_default().load() {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            crate::logging::warn("reading secrets failed")
+                .step(accessor)
+                .field("error", e)
+                .emit();
+            return;
+        }
+    };
+    if let Some(mapping) = params.as_mapping_mut() {
+        for (_, value) in mapping.iter_mut() {
+            if let Some(text) = value.as_str() {
+                *value = serde_yaml::Value::String(fill(text, &secrets, accessor));
+            }
+        }
+    }
+}
+
+// `text` with the stored secrets it refers to; unknown ones are left, the
+// plan reports them
+fn fill(text: &str, secrets: &BTreeMap<String, String>, accessor: &str) -> String {
+    reference()
+        .replace_all(text, |caps: &Captures| match secrets.get(&caps[1]) {
+            Some(value) => {
+                crate::audit::secret_access(&caps[1], accessor);
+                remember(value);
+                value.clone()
+            }
+            None => caps[0].to_string(),
+        })
+        .into_owned()
+}
+
+fn remember(value: &str) {
+    if value.len() < MIN_HIDDEN_LEN {
+        return;
+    }
+    let mut revealed = REVEALED.lock().unwrap_or_else(|e| e.into_inner());
+    if !revealed.iter().any(|v| v == value) {
+        revealed.push(value.to_string());
+        // Longer first, so a secret containing another is hidden whole
+        revealed.sort_by_key(|v| std::cmp::Reverse(v.len()));
+    }
+}
+
+/// Whether `text` holds a secret handed to a step of this process
+pub fn reveals(text: &str) -> bool {
+    let revealed = REVEALED.lock().unwrap_or_else(|e| e.into_inner());
+    revealed.iter().any(|value| text.contains(value.as_str()))
+}
+
+/// `text` with the secrets handed to steps of this process hidden
+pub fn hide(text: &str) -> String {
+    let revealed = REVEALED.lock().unwrap_or_else(|e| e.into_inner());
+    let mut out = text.to_string();
+    for value in revealed.iter() {
+        if out.contains(value.as_str()) {
+            out = out.replace(value.as_str(), "[REDACTED]");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_fills_and_hides_secrets() {
+        let dir = std::env::temp_dir().join(format!("lao_secrets_{}", uuid::Uuid::new_v4()));
+        let store = SecretStore::at(dir.join("secrets.enc"), dir.join("k.key"));
+        assert!(store.load().unwrap().is_empty());
+        store.set("NOTION_TOKEN", "ntn-test-0042-value").unwrap();
+        store.set("SHORT", "abc").unwrap();
+        assert!(store.set("2FA", "x").is_err());
+        let raw = fs::read(dir.join("secrets.enc")).unwrap();
+        assert!(!String::from_utf8_lossy(&raw).contains("ntn-test-0042-value"));
+
+        let secrets = store.load().unwrap();
+        let filled = fill(
+            "Bearer ${{ secrets.NOTION_TOKEN }} ${{ secrets.MISSING }}",
+            &secrets,
+            "step1",
+        );
+        assert_eq!(filled, "Bearer ntn-test-0042-value ${{ secrets.MISSING }}");
+        assert!(reveals("reply: ntn-test-0042-value"));
+        assert_eq!(hide("token ntn-test-0042-value"), "token [REDACTED]");
+        fill("${{ secrets.SHORT }}", &secrets, "step1");
+        assert_eq!(hide("abc"), "abc");
+
+        assert!(store.remove("SHORT").unwrap());
+        assert!(!store.remove("SHORT").unwrap());
+        assert_eq!(store.load().unwrap().len(), 1);
+        // Without its key the store can't be read
+        fs::remove_file(dir.join("k.key")).unwrap();
+        assert!(store.load().is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,435 @@
+// Installing `lao daemon` as a user service that starts at login.
+use crate::cross_platform::Platform;
+use crate::data_dir;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const SYSTEMD_UNIT: &str = "lao-daemon.service";
+pub const LAUNCHD_LABEL: &str = "dev.lao.daemon";
+pub const TASK_NAME: &str = "LAO Daemon";
+
+// Settings a service file shouldn't hold in the clear
+const SECRET_MARKERS: [&str; 5] = ["PASSWORD", "PASSPHRASE", "SECRET", "API_KEY", "TOKEN"];
+
+/// What the service runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceSpec {
+    pub exe: PathBuf,
+    /// Arguments after `lao daemon`
+    pub args: Vec<String>,
+    pub working_dir: PathBuf,
+    pub log_dir: PathBuf,
+    pub env: Vec<(String, String)>,
+}
+
+impl ServiceSpec {
+    /// This executable's daemon with `args`, as this process would run it
+    pub fn current(args: Vec<String>) -> Result<Self, String> {
+        let exe = std::env::current_exe()
+            .map_err(|e| format!("Cannot find the lao executable: {}", e))?;
+        let working_dir = std::env::current_dir()
+            .map_err(|e| format!("Cannot read the working directory: {}", e))?;
+        let mut env: Vec<(String, String)> = std::env::vars()
+            .filter(|(key, _)| key.starts_with("LAO_") && key != "LAO_DATA_DIR")
+            .filter(|(key, _)| !SECRET_MARKERS.iter().any(|m| key.contains(m)))
+            .collect();
+        env.push(("LAO_DATA_DIR".to_string(), absolute(&data_dir::root())));
+        env.sort();
+        Ok(Self {
+            exe,
+            args,
+            working_dir,
+            log_dir: data_dir::path("logs"),
+            env,
+        })
+    }
+
+    pub fn stdout_log(&self) -> PathBuf {
+        self.log_dir.join("daemon.log")
+    }
+
+    pub fn stderr_log(&self) -> PathBuf {
+        self.log_dir.join("daemon.err.log")
+    }
+
+    fn command(&self) -> Vec<String> {
+        let mut command = vec![
+            self.exe.to_string_lossy().into_owned(),
+            "daemon".to_string(),
+        ];
+        command.extend(self.args.iter().cloned());
+        command
+    }
+}
+
+fn absolute(path: &Path) -> String {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Systemd,
+    Launchd,
+    TaskScheduler,
+}
+
+impl Manager {
+    /// The service manager of this OS
+    pub fn current() -> Result<Self, String> {
+        match Platform::os() {
+            "linux" => Ok(Manager::Systemd),
+            "macos" => Ok(Manager::Launchd),
+            "windows" => Ok(Manager::TaskScheduler),
+            os => Err(format!("Installing the daemon isn't supported on {}", os)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Manager::Systemd => "systemd",
+            Manager::Launchd => "launchd",
+            Manager::TaskScheduler => "Task Scheduler",
+        }
+    }
+
+    /// Where the service's file goes
+    pub fn unit_path(&self) -> Result<PathBuf, String> {
+        let home = || Platform::home_dir().ok_or("Cannot find the home directory".to_string());
+        Ok(match self {
+            Manager::Systemd => Platform::config_dir()
+                .ok_or("Cannot find the config directory")?
+                .join("systemd")
+                .join("user")
+                .join(SYSTEMD_UNIT),
+            Manager::Launchd => home()?
+                .join("Library")
+                .join("LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL)),
+            Manager::TaskScheduler => data_dir::path("daemon").join("lao-daemon.cmd"),
+        })
+    }
+
+    /// The service's file for `spec`
+    pub fn render(&self, spec: &ServiceSpec) -> String {
+        match self {
+            Manager::Systemd => render_systemd(spec),
+            Manager::Launchd => render_launchd(spec),
+            Manager::TaskScheduler => render_script(spec),
+        }
+    }
+
+    /// Commands registering the file at `unit`, and starting it if `start`
+    pub fn install_commands(&self, unit: &Path, start: bool) -> Vec<Vec<String>> {
+        let unit = unit.to_string_lossy().into_owned();
+        let mut commands = match self {
+            Manager::Systemd => {
+                let mut enable = args(&["systemctl", "--user", "enable"]);
+                if start {
+                    enable.push("--now".to_string());
+                }
+                enable.push(SYSTEMD_UNIT.to_string());
+                vec![args(&["systemctl", "--user", "daemon-reload"]), enable]
+            }
+            // Loading an agent starts it; unloaded, it loads at the next login
+            Manager::Launchd if start => vec![args(&["launchctl", "load", "-w", &unit])],
+            Manager::Launchd => Vec::new(),
+            Manager::TaskScheduler => vec![args(&[
+                "schtasks",
+                "/create",
+                "/tn",
+                TASK_NAME,
+                "/sc",
+                "onlogon",
+                "/tr",
+                &format!("cmd /c \"{}\"", unit),
+                "/f",
+            ])],
+        };
+        if start && *self == Manager::TaskScheduler {
+            commands.push(args(&["schtasks", "/run", "/tn", TASK_NAME]));
+        }
+        commands
+    }
+
+    /// Commands stopping and unregistering the service
+    pub fn uninstall_commands(&self, unit: &Path) -> Vec<Vec<String>> {
+        match self {
+            Manager::Systemd => vec![args(&[
+                "systemctl",
+                "--user",
+                "disable",
+                "--now",
+                SYSTEMD_UNIT,
+            ])],
+            Manager::Launchd => vec![args(&[
+                "launchctl",
+                "unload",
+                "-w",
+                &unit.to_string_lossy(),
+            ])],
+            Manager::TaskScheduler => vec![
+                args(&["schtasks", "/end", "/tn", TASK_NAME]),
+                args(&["schtasks", "/delete", "/tn", TASK_NAME, "/f"]),
+            ],
+        }
+    }
+
+    /// Command printing whether the service is running
+    pub fn status_command(&self) -> Vec<String> {
+        match self {
+            Manager::Systemd => args(&["systemctl", "--user", "status", SYSTEMD_UNIT]),
+            Manager::Launchd => args(&["launchctl", "list", LAUNCHD_LABEL]),
+            Manager::TaskScheduler => args(&["schtasks", "/query", "/tn", TASK_NAME, "/v"]),
+        }
+    }
+}
+
+fn args(words: &[&str]) -> Vec<String> {
+    words.iter().map(|w| w.to_string()).collect()
+}
+
+// A systemd word: quoted, with `%` specifiers escaped
+fn systemd_quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+fn render_systemd(spec: &ServiceSpec) -> String {
+    let exec: Vec<String> = spec.command().iter().map(|a| systemd_quote(a)).collect();
+    let mut unit = String::from("[Unit]\nDescription=LAO workflow daemon\n");
+    unit.push_str("After=network-online.target\n\n[Service]\n");
+    unit.push_str(&format!("ExecStart={}\n", exec.join(" ")));
+    // Paths aren't unquoted, only specifiers expanded
+    let path = |path: &Path| path.to_string_lossy().replace('%', "%%");
+    unit.push_str(&format!("WorkingDirectory={}\n", path(&spec.working_dir)));
+    for (key, value) in &spec.env {
+        unit.push_str(&format!(
+            "Environment={}\n",
+            systemd_quote(&format!("{}={}", key, value))
+        ));
+    }
+    unit.push_str("Restart=on-failure\nRestartSec=5\n");
+    unit.push_str(&format!(
+        "StandardOutput=append:{}\nStandardError=append:{}\n",
+        path(&spec.stdout_log()),
+        path(&spec.stderr_log())
+    ));
+    unit.push_str("\n[Install]\nWantedBy=default.target\n");
+    unit
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_launchd(spec: &ServiceSpec) -> String {
+    let string = |text: &str| format!("<string>{}</string>", xml_escape(text));
+    let mut plist = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+        "<plist version=\"1.0\">\n<dict>\n"
+    ));
+    plist.push_str(&format!(
+        "  <key>Label</key>\n  {}\n",
+        string(LAUNCHD_LABEL)
+    ));
+    plist.push_str("  <key>ProgramArguments</key>\n  <array>\n");
+    for arg in spec.command() {
+        plist.push_str(&format!("    {}\n", string(&arg)));
+    }
+    plist.push_str("  </array>\n");
+    plist.push_str(&format!(
+        "  <key>WorkingDirectory</key>\n  {}\n",
+        string(&spec.working_dir.to_string_lossy())
+    ));
+    plist.push_str("  <key>EnvironmentVariables</key>\n  <dict>\n");
+    for (key, value) in &spec.env {
+        plist.push_str(&format!(
+            "    <key>{}</key>\n    {}\n",
+            xml_escape(key),
+            string(value)
+        ));
+    }
+    plist.push_str("  </dict>\n");
+    plist.push_str("  <key>RunAtLoad</key>\n  <true/>\n");
+    // Restarted unless it exits cleanly
+    plist.push_str("  <key>KeepAlive</key>\n  <dict>\n    <key>SuccessfulExit</key>\n    <false/>\n  </dict>\n");
+    plist.push_str(&format!(
+        "  <key>StandardOutPath</key>\n  {}\n  <key>StandardErrorPath</key>\n  {}\n",
+        string(&spec.stdout_log().to_string_lossy()),
+        string(&spec.stderr_log().to_string_lossy())
+    ));
+    plist.push_str("</dict>\n</plist>\n");
+    plist
+}
+
+// cmd.exe quoting: `%` doubled, arguments in double quotes
+fn cmd_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('%', "%%").replace('"', "\"\""))
+}
+
+// Task Scheduler can't set variables or redirect output, so the task runs
+// this script, which also restarts a failed daemon
+fn render_script(spec: &ServiceSpec) -> String {
+    let mut script =
+        String::from("@echo off\r\nrem LAO daemon, started at logon by Task Scheduler\r\n");
+    script.push_str(&format!(
+        "cd /d {}\r\n",
+        cmd_quote(&spec.working_dir.to_string_lossy())
+    ));
+    for (key, value) in &spec.env {
+        script.push_str(&format!("set \"{}={}\"\r\n", key, value.replace('%', "%%")));
+    }
+    let command: Vec<String> = spec.command().iter().map(|a| cmd_quote(a)).collect();
+    script.push_str(":start\r\n");
+    script.push_str(&format!(
+        "{} >> {} 2>> {}\r\n",
+        command.join(" "),
+        cmd_quote(&spec.stdout_log().to_string_lossy()),
+        cmd_quote(&spec.stderr_log().to_string_lossy())
+    ));
+    script.push_str("if errorlevel 1 (\r\n  timeout /t 5 /nobreak > nul\r\n  goto start\r\n)\r\n");
+    script
+}
+
+fn run(command: &[String]) -> Result<(), String> {
+    let (program, rest) = command.split_first().ok_or("Empty command")?;
+    let output = Command::new(program)
+        .args(rest)
+        .output()
+        .map_err(|e| format!("Cannot run {}: {}", program, e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` failed: {}",
+            command.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Write the service's file and register it; returns where the file is
+pub fn install(manager: Manager, spec: &ServiceSpec, start: bool) -> Result<PathBuf, String> {
+    let unit = manager.unit_path()?;
+    for dir in [unit.parent(), Some(spec.log_dir.as_path())]
+        .into_iter()
+        .flatten()
+    {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+    // A launchd agent must be unloaded before its file changes take effect
+    if manager == Manager::Launchd && unit.exists() {
+        manager.uninstall_commands(&unit).iter().for_each(|c| {
+            run(c).ok();
+        });
+    }
+    std::fs::write(&unit, manager.render(spec))
+        .map_err(|e| format!("Cannot write {}: {}", unit.display(), e))?;
+    for command in manager.install_commands(&unit, start) {
+        run(&command)?;
+    }
+    Ok(unit)
+}
+
+/// Stop and unregister the service and remove its file; returns the
+/// commands that failed, e.g. because it wasn't running
+pub fn uninstall(manager: Manager) -> Result<Vec<String>, String> {
+    let unit = manager.unit_path()?;
+    let failed = manager
+        .uninstall_commands(&unit)
+        .iter()
+        .filter_map(|command| run(command).err())
+        .collect();
+    match std::fs::remove_file(&unit) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "The daemon isn't installed ({} doesn't exist)",
+                unit.display()
+            ))
+        }
+        Err(e) => return Err(format!("Cannot remove {}: {}", unit.display(), e)),
+    }
+    if manager == Manager::Systemd {
+        run(&args(&["systemctl", "--user", "daemon-reload"])).ok();
+    }
+    Ok(failed)
+}
+
+/// Print the service manager's view of the service
+pub fn status(manager: Manager) -> Result<bool, String> {
+    let command = manager.status_command();
+    let status = Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .map_err(|e| format!("Cannot run {}: {}", command[0], e))?;
+    Ok(status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ServiceSpec {
+        ServiceSpec {
+            exe: PathBuf::from("/opt/lao/bin/lao"),
+            args: vec!["--webhook-addr".to_string(), "127.0.0.1:8787".to_string()],
+            working_dir: PathBuf::from("/home/ada/My Flows"),
+            log_dir: PathBuf::from("/home/ada/.local/share/lao/logs"),
+            env: vec![(
+                "LAO_PLUGIN_DIR".to_string(),
+                "/opt/lao/100% plugins".to_string(),
+            )],
+        }
+    }
+
+    #[test]
+    fn test_systemd_unit_quotes_arguments_and_settings() {
+        let unit = Manager::Systemd.render(&spec());
+        assert!(unit.contains(
+            "ExecStart=\"/opt/lao/bin/lao\" \"daemon\" \"--webhook-addr\" \"127.0.0.1:8787\"\n"
+        ));
+        assert!(unit.contains("WorkingDirectory=/home/ada/My Flows\n"));
+        assert!(unit.contains("Environment=\"LAO_PLUGIN_DIR=/opt/lao/100%% plugins\"\n"));
+        assert!(unit.contains("StandardOutput=append:/home/ada/.local/share/lao/logs/daemon.log"));
+        assert!(unit.contains("Restart=on-failure"));
+    }
+
+    #[test]
+    fn test_launchd_plist_runs_at_load() {
+        let plist = Manager::Launchd.render(&spec());
+        assert!(plist.contains("<string>daemon</string>\n    <string>--webhook-addr</string>"));
+        assert!(plist.contains("<key>RunAtLoad</key>\n  <true/>"));
+    }
+
+    #[test]
+    fn test_task_scheduler_script_restarts() {
+        let script = Manager::TaskScheduler.render(&spec());
+        assert!(script.contains("set \"LAO_PLUGIN_DIR=/opt/lao/100%% plugins\"\r\n"));
+        assert!(script.contains("goto start"));
+    }
+
+    #[test]
+    fn test_install_commands() {
+        let unit_path = Path::new("/u/lao-daemon.service");
+        assert_eq!(
+            Manager::Systemd.install_commands(unit_path, true)[1],
+            ["systemctl", "--user", "enable", "--now", SYSTEMD_UNIT]
+        );
+        assert!(Manager::Launchd
+            .install_commands(unit_path, false)
+            .is_empty());
+    }
+}
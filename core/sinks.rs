@@ -0,0 +1,394 @@
+// Realtime sinks: steps that get another step's output while it is being
+// generated, token by token.
+// `sink` steps are described in docs/workflows.md (Realtime Sinks).
+use crate::plugins::PluginInstance;
+use crate::WorkflowStep;
+use base64::Engine;
+use lao_plugin_api::PluginInfo;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The `run` of sink steps
+pub const RUNNER: &str = "sink";
+
+const VERSION: &str = "builtin";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where a sink writes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Stdout,
+    File(PathBuf),
+    WebSocket(String),
+}
+
+impl Target {
+    pub fn parse(to: &str) -> Result<Self, String> {
+        let to = to.trim();
+        if to == "stdout" {
+            Ok(Target::Stdout)
+        } else if let Some(path) = to.strip_prefix("file:").filter(|p| !p.is_empty()) {
+            Ok(Target::File(PathBuf::from(path)))
+        } else if to.starts_with("ws://") {
+            Ok(Target::WebSocket(to.to_string()))
+        } else {
+            Err(format!(
+                "sink target '{}' is not stdout, file:<path> or a ws:// URL",
+                to
+            ))
+        }
+    }
+}
+
+/// Where the sink step `step` writes, from its `to` param
+pub fn target_of(step: &WorkflowStep) -> Result<Target, String> {
+    let to = step
+        .params
+        .get("to")
+        .and_then(|to| to.as_str())
+        .ok_or("sink step needs `to`: stdout, file:<path> or a ws:// URL")?;
+    Target::parse(to)
+}
+
+/// What's wrong with `step` as a sink step, for the plan
+pub fn problem(step: &WorkflowStep) -> Option<String> {
+    if step.run != RUNNER {
+        return None;
+    }
+    if step.input_from.is_none() {
+        return Some("sink step needs `input_from`, the step it streams".to_string());
+    }
+    target_of(step).err()
+}
+
+/// The host's `sink` runner, registered before the plugin directories so a
+/// plugin of that name would replace it. Outside of a run it echoes its input.
+pub fn plugin() -> PluginInstance {
+    let info = PluginInfo {
+        name: RUNNER.to_string(),
+        version: VERSION.to_string(),
+        description: "Streams the output of the step in input_from to stdout, a file or a WebSocket as it is generated".to_string(),
+        author: "LAO".to_string(),
+        dependencies: Vec::new(),
+        tags: vec!["builtin".to_string()],
+        capabilities: Vec::new(),
+        input_schema: None,
+        output_schema: None,
+    };
+    PluginInstance::native(info, |input| Ok(input.to_string()))
+}
+
+/// Whether `plugin` is the host's sink rather than a plugin or mock of that name
+pub fn is_builtin(plugin: &PluginInstance) -> bool {
+    plugin.info.name == RUNNER && plugin.info.version == VERSION
+}
+
+enum Connection {
+    Stdout,
+    File(File),
+    WebSocket(TcpStream),
+}
+
+impl Connection {
+    fn open(target: &Target) -> Result<Self, String> {
+        match target {
+            Target::Stdout => Ok(Connection::Stdout),
+            Target::File(path) => std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(Connection::File)
+                .map_err(|e| format!("cannot open {}: {}", path.display(), e)),
+            Target::WebSocket(url) => websocket_connect(url)
+                .map(Connection::WebSocket)
+                .map_err(|e| format!("cannot connect to {}: {}", url, e)),
+        }
+    }
+
+    fn write(&mut self, text: &str) -> std::io::Result<()> {
+        match self {
+            Connection::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()
+            }
+            Connection::File(file) => file.write_all(text.as_bytes()),
+            Connection::WebSocket(stream) => stream.write_all(&frame(OP_TEXT, text.as_bytes())),
+        }
+    }
+
+    fn close(mut self) -> std::io::Result<()> {
+        match &mut self {
+            Connection::Stdout | Connection::File(_) => self.write("\n"),
+            Connection::WebSocket(stream) => stream.write_all(&frame(OP_CLOSE, &[])),
+        }
+    }
+}
+
+struct OpenSink {
+    target: Target,
+    connection: Result<Connection, String>,
+    streamed: bool,
+}
+
+impl OpenSink {
+    fn write(&mut self, text: &str) {
+        if let Ok(connection) = &mut self.connection {
+            if let Err(e) = connection.write(text) {
+                self.connection = Err(format!("writing to {:?} failed: {}", self.target, e));
+            }
+        }
+        self.streamed = true;
+    }
+}
+
+/// The sinks of one run, each opened before the step it reads from runs
+#[derive(Default)]
+pub struct RunSinks {
+    // Sink steps with their targets, by the step they read from
+    feeds: HashMap<String, Vec<(String, Target)>>,
+    open: HashMap<String, OpenSink>,
+}
+
+impl RunSinks {
+    /// Sinks for `steps`, by step id; the plan has checked their targets
+    pub fn new<'a>(steps: impl IntoIterator<Item = (String, &'a WorkflowStep)>) -> Self {
+        let mut sinks = RunSinks::default();
+        for (id, step) in steps {
+            if let (RUNNER, Some(source), Ok(target)) =
+                (step.run.as_str(), &step.input_from, target_of(step))
+            {
+                sinks
+                    .feeds
+                    .entry(source.clone())
+                    .or_default()
+                    .push((id, target));
+            }
+        }
+        sinks
+    }
+
+    /// Open the sinks `source` feeds, if not open yet
+    pub fn open_for(&mut self, source: &str) {
+        for (id, target) in self.feeds.get(source).into_iter().flatten() {
+            self.open.entry(id.clone()).or_insert_with(|| OpenSink {
+                connection: Connection::open(target),
+                target: target.clone(),
+                streamed: false,
+            });
+        }
+    }
+
+    /// Hand `text`, streamed by `source`, to its open sinks
+    pub fn chunk(&mut self, source: &str, text: &str) {
+        for (id, _) in self.feeds.get(source).into_iter().flatten() {
+            if let Some(sink) = self.open.get_mut(id) {
+                sink.write(text);
+            }
+        }
+    }
+
+    /// Run sink step `id` with `input`, the whole output of its source:
+    /// write it if nothing was streamed, end the stream and pass it on
+    pub fn finish(&mut self, id: &str, step: &WorkflowStep, input: &str) -> Result<String, String> {
+        let mut sink = match self.open.remove(id) {
+            Some(sink) => sink,
+            None => {
+                let target = target_of(step)?;
+                OpenSink {
+                    connection: Connection::open(&target),
+                    target,
+                    streamed: false,
+                }
+            }
+        };
+        if !sink.streamed {
+            sink.write(input);
+        }
+        let target = sink.target;
+        sink.connection?
+            .close()
+            .map_err(|e| format!("closing {:?} failed: {}", target, e))?;
+        Ok(input.to_string())
+    }
+}
+
+const OP_TEXT: u8 = 0x1;
+const OP_CLOSE: u8 = 0x8;
+
+// Open a WebSocket to `url` (ws://host[:port]/path)
+fn websocket_connect(url: &str) -> std::io::Result<TcpStream> {
+    use std::net::ToSocketAddrs;
+
+    let rest = url.trim_start_matches("ws://");
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let socket = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no address for host"))?;
+    let mut stream = TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let key = base64::engine::general_purpose::STANDARD.encode(uuid::Uuid::new_v4().as_bytes());
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path, authority, key
+    )?;
+    // Byte by byte, so nothing after the headers is consumed
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("101") {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            format!("server answered '{}'", status.trim()),
+        ));
+    }
+    Ok(stream)
+}
+
+// A final, masked client frame
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(0x80 | 126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    let mask: [u8; 4] = uuid::Uuid::new_v4().as_bytes()[..4]
+        .try_into()
+        .unwrap_or_default();
+    frame.extend(mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    // Text of each frame a client sends, up to its close frame
+    fn read_frames(stream: TcpStream) -> Vec<String> {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+            line.clear();
+        }
+        let mut stream = stream;
+        stream
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n")
+            .unwrap();
+        let mut texts = Vec::new();
+        loop {
+            let mut head = [0u8; 2];
+            reader.read_exact(&mut head).unwrap();
+            let len = match head[1] & 0x7F {
+                126 => {
+                    let mut len = [0u8; 2];
+                    reader.read_exact(&mut len).unwrap();
+                    u16::from_be_bytes(len) as usize
+                }
+                len => len as usize,
+            };
+            let mut mask = [0u8; 4];
+            reader.read_exact(&mut mask).unwrap();
+            let mut payload = vec![0u8; len];
+            reader.read_exact(&mut payload).unwrap();
+            if head[0] & 0x0F == OP_CLOSE {
+                return texts;
+            }
+            let text: Vec<u8> = payload
+                .iter()
+                .enumerate()
+                .map(|(i, b)| b ^ mask[i % 4])
+                .collect();
+            texts.push(String::from_utf8(text).unwrap());
+        }
+    }
+
+    fn sink_steps(to: &str) -> crate::Workflow {
+        let yaml = format!(
+            "workflow: captions\nsteps:\n  - run: OllamaPlugin\n    input: hi\n  - run: sink\n    input_from: step1\n    to: {}\n",
+            to
+        );
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    #[test]
+    fn test_unknown_sink_schemes_are_problems() {
+        let workflow = sink_steps("ftp://nope");
+        assert_eq!(problem(&workflow.steps[0]), None);
+        assert!(problem(&workflow.steps[1]).unwrap().contains("ftp://nope"));
+    }
+
+    #[test]
+    fn test_sinks_get_chunks_as_they_are_streamed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/captions", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || read_frames(listener.accept().unwrap().0));
+        let file = std::env::temp_dir().join(format!("lao_sink_{}.txt", uuid::Uuid::new_v4()));
+
+        let yaml = format!(
+            "workflow: captions\nsteps:\n  - run: OllamaPlugin\n    input: hi\n  - run: sink\n    input_from: step1\n    to: {}\n  - run: sink\n    input_from: step1\n    to: file:{}\n",
+            url,
+            file.display()
+        );
+        let workflow: crate::Workflow = serde_yaml::from_str(&yaml).unwrap();
+        let steps = &workflow.steps;
+        let mut sinks = RunSinks::new(
+            steps
+                .iter()
+                .enumerate()
+                .map(|(i, step)| (format!("step{}", i + 1), step)),
+        );
+        sinks.open_for("step1");
+        for chunk in ["Hello", ", ", "world"] {
+            sinks.chunk("step1", chunk);
+        }
+        let output = "Hello, world";
+        assert_eq!(sinks.finish("step2", &steps[1], output).unwrap(), output);
+        assert_eq!(sinks.finish("step3", &steps[2], output).unwrap(), output);
+        assert_eq!(server.join().unwrap(), ["Hello", ", ", "world"]);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "Hello, world\n");
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn test_unstreamed_output_reaches_its_sink_when_the_sink_runs() {
+        let file = std::env::temp_dir().join(format!("lao_sink_{}.txt", uuid::Uuid::new_v4()));
+        let workflow = sink_steps(&format!("file:{}", file.display()));
+        let sink = &workflow.steps[1];
+        let mut quiet = RunSinks::new([("step2".to_string(), sink)]);
+        quiet.finish("step2", sink, "later").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "later\n");
+        let _ = std::fs::remove_file(&file);
+    }
+}
@@ -26,7 +26,7 @@ impl WorkflowStateManager {
         let file_path = self.state_dir.join(format!("{}.json", state.workflow_id));
         let json = serde_json::to_string_pretty(state)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        fs::write(file_path, json)?;
+        crate::encryption::write(file_path, json)?;
         self.states.insert(state.workflow_id.clone(), state.clone());
         Ok(())
     }
@@ -37,7 +37,7 @@ impl WorkflowStateManager {
             return Ok(None);
         }
 
-        let json = fs::read_to_string(file_path)?;
+        let json = crate::encryption::read_to_string(file_path)?;
         let state: WorkflowState = serde_json::from_str(&json)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
         Ok(Some(state))
@@ -0,0 +1,105 @@
+// Plugin output shown while the plugin is still producing it.
+use lao_plugin_api::{HostStreamOutput, PluginInput};
+use std::cell::Cell;
+use std::time::Duration;
+
+/// Least time between two Running events carrying streamed output
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+
+type Sink = *mut (dyn FnMut(&str) + 'static);
+
+thread_local! {
+    static SINK: Cell<Option<Sink>> = const { Cell::new(None) };
+}
+
+// Puts back the sink that was current when it was made
+struct Restore(Option<Sink>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        SINK.with(|current| current.set(self.0));
+    }
+}
+
+/// Run `f`, handing `sink` whatever output is streamed on this thread meanwhile
+pub fn with_sink<R>(sink: &mut dyn FnMut(&str), f: impl FnOnce() -> R) -> R {
+    // SAFETY: only `chunk` on this thread uses the pointer, and only while
+    // `f` runs; `_restore` takes it out again before `sink`'s borrow ends,
+    // even if `f` panics
+    let sink: Sink = unsafe { std::mem::transmute::<*mut (dyn FnMut(&str) + '_), Sink>(sink) };
+    let _restore = Restore(SINK.with(|current| current.replace(Some(sink))));
+    f()
+}
+
+/// Hand `text` to the current sink, if any
+pub fn chunk(text: &str) {
+    // Taken out while it runs, so a sink that streams itself can't alias it
+    let Some(sink) = SINK.with(Cell::take) else {
+        return;
+    };
+    let _restore = Restore(Some(sink));
+    unsafe { (*sink)(text) };
+}
+
+/// Forwards the text of a streamed Ollama reply to `chunk` as its lines arrive
+#[derive(Default)]
+pub(crate) struct OllamaLines {
+    pending: Vec<u8>,
+}
+
+impl OllamaLines {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            if let Ok(part) = serde_json::from_slice::<serde_json::Value>(&line) {
+                let text = part["response"]
+                    .as_str()
+                    .or(part["message"]["content"].as_str());
+                chunk(text.unwrap_or_default());
+            }
+        }
+    }
+}
+
+/// Handed to plugins that export `plugin_stream_output`
+pub static HOST_STREAM_OUTPUT: HostStreamOutput = HostStreamOutput {
+    chunk: host_stream_chunk,
+};
+
+unsafe extern "C" fn host_stream_chunk(text: *const PluginInput) {
+    if !text.is_null() {
+        chunk(&(*text).to_string_lossy());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_reach_the_current_sink() {
+        chunk("before any step");
+        let mut received = Vec::new();
+        let mut sink = |text: &str| received.push(text.to_string());
+        let returned = with_sink(&mut sink, || {
+            unsafe { lao_plugin_api::accept_host_stream_output(&HOST_STREAM_OUTPUT) };
+            lao_plugin_api::emit_chunk("Once");
+            let mut lines = OllamaLines::default();
+            lines.push(b"{\"response\":\" upon\",\"done\":false}\n{\"resp");
+            lines.push(b"onse\":\" a time\",\"done\":false}\n");
+            lines.push(b"{\"message\":{\"role\":\"assistant\",\"content\":\".\"}}\n");
+            42
+        });
+        chunk("after the step");
+        assert_eq!(returned, 42);
+        assert_eq!(received, ["Once", " upon", " a time", "."]);
+
+        let merged = lao_plugin_api::merge_ollama_stream(
+            "{\"response\":\"Once\",\"done\":false}\n{\"response\":\" upon\",\"done\":true,\"eval_count\":2}\n",
+        )
+        .unwrap();
+        assert_eq!(merged["response"], "Once upon");
+        assert_eq!(merged["eval_count"], 2);
+    }
+}
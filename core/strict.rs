@@ -0,0 +1,219 @@
+// Unknown-field checks for `lao validate`.
+use serde_yaml::Value;
+use std::fmt;
+
+pub(crate) const WORKFLOW_FIELDS: &[&str] = &[
+    "workflow",
+    "metadata",
+    "steps",
+    "response",
+    "redaction",
+    "models",
+    "plugins",
+    "outputs",
+    "mqtt",
+    "guard",
+    "params",
+    "extends",
+    "overrides",
+];
+
+pub(crate) const STEP_FIELDS: &[&str] = &[
+    "run",
+    "id",
+    "retries",
+    "retry_delay",
+    "backoff",
+    "retry_on",
+    "timeout",
+    "cache",
+    "cache_key",
+    "input_from",
+    "depends_on",
+    "condition",
+    "on_success",
+    "on_failure",
+    "when",
+    "if_failed",
+    "if_succeeded",
+    "memoize",
+    "validate",
+    "assert",
+    "on_assert_fail",
+    "fallback",
+    "evaluate",
+    "enabled",
+    "when_disabled",
+    "breakpoint",
+    "strategy",
+    "map_reduce",
+    "foreach",
+    "context",
+    "display",
+    "template",
+    "preset",
+    "prompt_file",
+    "resource",
+    "backend",
+    "untrusted",
+];
+
+// Parameters of the bundled plugins that are a typo away from a step field
+const STEP_PARAMS: &[&str] = &["callback", "content", "resources", "source"];
+
+const SECTIONS: &[(&str, &[&str])] = &[
+    (
+        "metadata",
+        &["title", "description", "author", "requires", "examples"],
+    ),
+    ("response", &["step", "status", "content_type"]),
+    ("mqtt", &["topic"]),
+    (
+        "guard",
+        &["sanitize", "wrap", "on_detect", "sources", "patterns"],
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownField {
+    pub key: String,
+    /// The mapping it is in: `metadata`, `response`, `mqtt` or `guard`; `None` at the
+    /// top level and in steps
+    pub section: Option<&'static str>,
+    /// Index of the step it is in, if any
+    pub step: Option<usize>,
+    pub suggestion: Option<&'static str>,
+}
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown field `{}`", self.key)?;
+        if let Some(section) = self.section {
+            write!(f, " in `{}`", section)?;
+        }
+        if let Some(step) = self.step {
+            write!(f, " in step {}", step + 1)?;
+        }
+        if let Some(suggestion) = self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys of the workflow document `workflow` that no part of LAO reads
+pub fn unknown_fields(workflow: &Value) -> Vec<UnknownField> {
+    let mut unknown = Vec::new();
+    let Some(top) = workflow.as_mapping() else {
+        return unknown;
+    };
+    for key in top.keys().filter_map(Value::as_str) {
+        if !WORKFLOW_FIELDS.contains(&key) {
+            unknown.push(UnknownField {
+                key: key.to_string(),
+                section: None,
+                step: None,
+                suggestion: suggest(key, WORKFLOW_FIELDS),
+            });
+        }
+    }
+    for (section, fields) in SECTIONS {
+        let Some(mapping) = top.get(*section).and_then(Value::as_mapping) else {
+            continue;
+        };
+        for key in mapping.keys().filter_map(Value::as_str) {
+            if !fields.contains(&key) {
+                unknown.push(UnknownField {
+                    key: key.to_string(),
+                    section: Some(section),
+                    step: None,
+                    suggestion: suggest(key, fields),
+                });
+            }
+        }
+    }
+    let steps = top.get("steps").and_then(Value::as_sequence);
+    for (index, step) in steps.into_iter().flatten().enumerate() {
+        let Some(step) = step.as_mapping() else {
+            continue;
+        };
+        for key in step.keys().filter_map(Value::as_str) {
+            if STEP_FIELDS.contains(&key) || STEP_PARAMS.contains(&key) {
+                continue;
+            }
+            if let Some(suggestion) = suggest(key, STEP_FIELDS) {
+                unknown.push(UnknownField {
+                    key: key.to_string(),
+                    section: None,
+                    step: Some(index),
+                    suggestion: Some(suggestion),
+                });
+            }
+        }
+    }
+    unknown
+}
+
+/// The candidate `key` is most likely a misspelling of, if one is within an
+/// edit per four of its characters
+pub fn suggest(key: &str, candidates: &[&'static str]) -> Option<&'static str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(key, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance > 0 && distance * 4 <= candidate.len())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Insertions, deletions, substitutions and swaps of adjacent characters
+// turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_fields_suggest_known_ones() {
+        let workflow: Value = serde_yaml::from_str(
+            "workflow: demo\nwrokflow: typo\nnotes: hi\nmetadata:\n  titel: Demo\nsteps:\n  - run: EchoPlugin\n    input: hi\n    content: kept\n  - run: SummarizerPlugin\n    input_form: step1\n    retires: 2\n    model: mistral\n",
+        )
+        .unwrap();
+        let messages: Vec<String> = unknown_fields(&workflow)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown field `wrokflow`, did you mean `workflow`?",
+                "unknown field `notes`",
+                "unknown field `titel` in `metadata`, did you mean `title`?",
+                "unknown field `input_form` in step 2, did you mean `input_from`?",
+                "unknown field `retires` in step 2, did you mean `retries`?",
+            ]
+        );
+        assert_eq!(suggest("run", STEP_FIELDS), None);
+        assert_eq!(edit_distance("input_form", "input_from"), 1);
+    }
+}
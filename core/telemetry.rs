@@ -0,0 +1,425 @@
+// OpenTelemetry trace export over OTLP/HTTP with JSON encoding.
+// Configuration is described in docs/observability.md (Tracing).
+use crate::channel::{self, Overflow};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const BATCH_SIZE: usize = 256;
+const BATCH_DELAY: Duration = Duration::from_secs(2);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+// Spans waiting for export before new ones are dropped
+const QUEUE_SIZE: usize = 8 * BATCH_SIZE;
+
+/// Identifies a span so children can be attached to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+impl From<u32> for AttributeValue {
+    fn from(value: u32) -> Self {
+        AttributeValue::Int(value as i64)
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(value: bool) -> Self {
+        AttributeValue::Bool(value)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SpanData {
+    context: SpanContext,
+    parent: Option<[u8; 8]>,
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(String, AttributeValue)>,
+    error: Option<String>,
+}
+
+/// A span that is exported when it ends or is dropped
+#[derive(Debug)]
+pub struct Span {
+    data: Option<SpanData>,
+    started: Instant,
+}
+
+impl Span {
+    /// Start a new trace
+    pub fn root(name: &str) -> Self {
+        Self::start(name, random_bytes(), None)
+    }
+
+    pub fn child(name: &str, parent: &SpanContext) -> Self {
+        Self::start(name, parent.trace_id, Some(parent.span_id))
+    }
+
+    fn start(name: &str, trace_id: [u8; 16], parent: Option<[u8; 8]>) -> Self {
+        let span_id = random_bytes();
+        Self {
+            data: Some(SpanData {
+                context: SpanContext { trace_id, span_id },
+                parent,
+                name: name.to_string(),
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                attributes: Vec::new(),
+                error: None,
+            }),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn context(&self) -> SpanContext {
+        self.data
+            .as_ref()
+            .map(|d| d.context)
+            .expect("span context read after end")
+    }
+
+    pub fn set_attribute(&mut self, key: &str, value: impl Into<AttributeValue>) {
+        if let Some(data) = &mut self.data {
+            data.attributes.push((key.to_string(), value.into()));
+        }
+    }
+
+    /// Mark the span as failed
+    pub fn set_error(&mut self, message: &str) {
+        if let Some(data) = &mut self.data {
+            data.error = Some(message.to_string());
+        }
+    }
+
+    pub fn end(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            // Derive the end from the monotonic clock so wall-clock jumps can't reorder spans
+            data.end = data.start + self.started.elapsed();
+            if let Some(exporter) = exporter() {
+                let _ = exporter.sender.send(Message::Span(data));
+            }
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Block until spans ended so far have been sent, e.g. before a CLI run exits
+pub fn flush() {
+    let Some(exporter) = exporter() else {
+        return;
+    };
+    let (done_tx, done_rx) = mpsc::channel();
+    // A flush dropped from a full queue returns at once, like a failed send
+    if exporter.sender.send(Message::Flush(done_tx)).is_ok() {
+        let _ = done_rx.recv_timeout(FLUSH_TIMEOUT);
+    }
+}
+
+/// A fresh id to correlate the spans and logs of one workflow run
+pub fn new_run_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+enum Message {
+    Span(SpanData),
+    Flush(Sender<()>),
+}
+
+struct Exporter {
+    sender: channel::Sender<Message>,
+}
+
+fn exporter() -> Option<&'static Exporter> {
+    static EXPORTER: OnceLock<Option<Exporter>> = OnceLock::new();
+    EXPORTER
+        .get_or_init(|| {
+            let endpoint = traces_endpoint(
+                std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").ok(),
+                std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+            )?;
+            let target = match HttpTarget::parse(&endpoint) {
+                Ok(target) => target,
+                Err(e) => {
+                    log::warn!("OTLP export disabled: {}", e);
+                    return None;
+                }
+            };
+            let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "lao".to_string());
+            let (sender, receiver) = channel::bounded(QUEUE_SIZE, Overflow::DropNewest);
+            thread::spawn(move || export_loop(receiver, target, service));
+            Some(Exporter { sender })
+        })
+        .as_ref()
+}
+
+fn traces_endpoint(traces: Option<String>, base: Option<String>) -> Option<String> {
+    match (traces, base) {
+        (Some(traces), _) if !traces.trim().is_empty() => Some(traces.trim().to_string()),
+        (_, Some(base)) if !base.trim().is_empty() => {
+            Some(format!("{}/v1/traces", base.trim().trim_end_matches('/')))
+        }
+        _ => None,
+    }
+}
+
+// Sends a batch when it is full, when a flush is requested and after BATCH_DELAY of quiet
+fn export_loop(receiver: channel::Receiver<Message>, target: HttpTarget, service: String) {
+    let mut batch = Vec::new();
+    loop {
+        let (send_now, flushed, closed) = match receiver.recv_timeout(BATCH_DELAY) {
+            Ok(Message::Span(span)) => {
+                batch.push(span);
+                (batch.len() >= BATCH_SIZE, None, false)
+            }
+            Ok(Message::Flush(done)) => (true, Some(done), false),
+            Err(RecvTimeoutError::Timeout) => (true, None, false),
+            Err(RecvTimeoutError::Disconnected) => (true, None, true),
+        };
+
+        if send_now && !batch.is_empty() {
+            let body = encode_spans(&batch, &service).to_string();
+            if let Err(e) = target.post(&body) {
+                log::warn!("Failed to export {} spans: {}", batch.len(), e);
+            }
+            batch.clear();
+        }
+        let dropped = receiver.take_dropped();
+        if dropped > 0 {
+            log::warn!("Dropped {} spans while the exporter was behind", dropped);
+        }
+        if let Some(done) = flushed {
+            let _ = done.send(());
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+fn encode_spans(spans: &[SpanData], service: &str) -> Value {
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let mut encoded = json!({
+                "traceId": hex(&span.context.trace_id),
+                "spanId": hex(&span.context.span_id),
+                "name": span.name,
+                "kind": 1,
+                "startTimeUnixNano": unix_nanos(span.start).to_string(),
+                "endTimeUnixNano": unix_nanos(span.end).to_string(),
+                "attributes": span.attributes.iter().map(|(k, v)| attribute(k, v)).collect::<Vec<_>>(),
+                "status": match &span.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({ "code": 1 }),
+                },
+            });
+            if let Some(parent) = &span.parent {
+                encoded["parentSpanId"] = json!(hex(parent));
+            }
+            encoded
+        })
+        .collect();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &AttributeValue::from(service))]
+            },
+            "scopeSpans": [{
+                "scope": { "name": "lao-orchestrator-core", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans
+            }]
+        }]
+    })
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(s) => json!({ "stringValue": s }),
+        // OTLP/JSON carries 64-bit integers as strings
+        AttributeValue::Int(i) => json!({ "intValue": i.to_string() }),
+        AttributeValue::Bool(b) => json!({ "boolValue": b }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut out = [0u8; N];
+    for chunk in out.chunks_mut(16) {
+        let id = uuid::Uuid::new_v4();
+        chunk.copy_from_slice(&id.as_bytes()[..chunk.len()]);
+    }
+    out
+}
+
+// Plain HTTP is enough for a collector on localhost or the LAN
+#[derive(Debug, Clone, PartialEq)]
+struct HttpTarget {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl HttpTarget {
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            format!(
+                "only http:// OTLP endpoints are supported, got '{}'; run a local collector to forward over TLS",
+                url
+            )
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/v1/traces"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid port in OTLP endpoint '{}'", url))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in OTLP endpoint '{}'", url));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    fn post(&self, body: &str) -> Result<(), String> {
+        let mut stream =
+            TcpStream::connect((self.host.as_str(), self.port)).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .map_err(|e| e.to_string())?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            self.port,
+            body.len(),
+            body
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut status_line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut status_line)
+            .map_err(|e| e.to_string())?;
+        match status_line.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(format!("collector replied '{}'", status_line.trim())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traces_endpoint() {
+        assert_eq!(traces_endpoint(None, None), None);
+        assert_eq!(
+            traces_endpoint(None, Some("http://localhost:4318/".to_string())).as_deref(),
+            Some("http://localhost:4318/v1/traces")
+        );
+        assert_eq!(
+            traces_endpoint(
+                Some("http://tempo:4318/custom".to_string()),
+                Some("http://ignored:4318".to_string())
+            )
+            .as_deref(),
+            Some("http://tempo:4318/custom")
+        );
+
+        let target = HttpTarget::parse("http://localhost:4318/v1/traces").unwrap();
+        assert_eq!((target.host.as_str(), target.port), ("localhost", 4318));
+        assert_eq!(HttpTarget::parse("http://collector").unwrap().port, 80);
+        assert!(HttpTarget::parse("https://collector:4318").is_err());
+    }
+
+    #[test]
+    fn test_encode_spans() {
+        let mut root = Span::root("workflow.run");
+        root.set_attribute("lao.run_id", "run-1");
+        let mut child = Span::child("plugin.call", &root.context());
+        child.set_attribute("lao.attempt", 2u32);
+        child.set_error("error: boom");
+
+        let root_data = root.data.take().unwrap();
+        let child_data = child.data.take().unwrap();
+        let encoded = encode_spans(&[root_data.clone(), child_data], "lao-test");
+
+        let resource = &encoded["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "lao-test"
+        );
+        let spans = &resource["scopeSpans"][0]["spans"];
+        assert_eq!(spans[0]["traceId"], hex(&root_data.context.trace_id));
+        assert_eq!(spans[0]["traceId"].as_str().unwrap().len(), 32);
+        assert!(spans[0].get("parentSpanId").is_none());
+        assert_eq!(spans[0]["attributes"][0]["value"]["stringValue"], "run-1");
+        assert_eq!(spans[1]["traceId"], spans[0]["traceId"]);
+        assert_eq!(spans[1]["parentSpanId"], spans[0]["spanId"]);
+        assert_eq!(spans[1]["attributes"][0]["value"]["intValue"], "2");
+        assert_eq!(spans[1]["status"]["code"], 2);
+    }
+}
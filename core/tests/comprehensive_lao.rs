@@ -3,7 +3,6 @@ use lao_orchestrator_core::plugins::PluginRegistry;
 use lao_orchestrator_core::{
     build_dag, run_workflow_yaml, validate_workflow_types, Workflow, WorkflowStep,
 };
-use lao_plugin_api::PluginInput;
 use serial_test::serial;
 use std::fs;
 use std::path::Path;
@@ -39,9 +38,6 @@ fn test_plugin_loading() {
         reg.plugins.keys().collect::<Vec<_>>()
     );
 
-    // Check if we can create the registry without crashing
-    assert!(true, "Registry creation should not crash");
-
     // Test that EchoPlugin loads (if available)
     if reg.get("EchoPlugin").is_some() {
         println!("[TEST] EchoPlugin loaded successfully");
@@ -59,14 +55,9 @@ fn test_workflow_execution_success() {
             run: "EchoPlugin".to_string(),
             params: serde_yaml::from_str("input: 'Hello, LAO!'").unwrap(),
             retries: Some(1),
-            retry_delay: None,
-            cache_key: None,
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }],
+        ..Default::default()
     };
     let path = "temp_workflow.yaml";
     fs::write(path, serde_yaml::to_string(&workflow).unwrap()).unwrap();
@@ -103,15 +94,9 @@ fn test_workflow_plugin_missing() {
         steps: vec![WorkflowStep {
             run: "NonExistentPlugin".to_string(),
             params: serde_yaml::Value::Null,
-            retries: None,
-            retry_delay: None,
-            cache_key: None,
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }],
+        ..Default::default()
     };
     let dag = build_dag(&workflow.steps).unwrap();
     let plugin_dir = PathUtils::plugin_dir();
@@ -128,20 +113,14 @@ fn test_workflow_invalid_step() {
         steps: vec![WorkflowStep {
             run: "EchoPlugin".to_string(),
             params: serde_yaml::Value::Null, // missing required input
-            retries: None,
-            retry_delay: None,
-            cache_key: None,
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }],
+        ..Default::default()
     };
     let dag = build_dag(&workflow.steps).unwrap();
     let plugin_dir = PathUtils::plugin_dir();
     let reg = PluginRegistry::dynamic_registry(plugin_dir.to_str().unwrap_or("plugins"));
-    let errors = validate_workflow_types(&dag, &reg);
+    let _errors = validate_workflow_types(&dag, &reg);
     // Should not error at type level, but runtime may fail
     let path = "temp_invalid.yaml";
     fs::write(path, serde_yaml::to_string(&workflow).unwrap()).unwrap();
@@ -167,7 +146,7 @@ fn test_prompt_to_workflow_success() {
     let mut reg = PluginRegistry::dynamic_registry(plugin_dir.to_str().unwrap_or("plugins"));
 
     // Check if PromptDispatcherPlugin is available
-    if reg.plugins.get("PromptDispatcherPlugin").is_none() {
+    if !reg.plugins.contains_key("PromptDispatcherPlugin") {
         println!("⚠️  PromptDispatcherPlugin not found, skipping prompt to workflow test");
         return;
     }
@@ -176,15 +155,9 @@ fn test_prompt_to_workflow_success() {
         .plugins
         .get_mut("PromptDispatcherPlugin")
         .expect("PromptDispatcherPlugin not found");
-    let input = PluginInput {
-        text: std::ffi::CString::new("Summarize this Markdown doc and extract key ideas")
-            .unwrap()
-            .into_raw(),
-    };
-    let result = unsafe { ((*dispatcher.vtable).run)(&input) };
-    let c_str = unsafe { std::ffi::CStr::from_ptr(result.text) };
-    let output = c_str.to_string_lossy().to_string();
-    unsafe { ((*dispatcher.vtable).free_output)(result) };
+    let output = dispatcher
+        .call("Summarize this Markdown doc and extract key ideas")
+        .unwrap_or_default();
     assert!(!output.is_empty(), "PromptDispatcher should return YAML");
     assert!(
         output.contains("MarkdownSummarizer"),
@@ -192,15 +165,9 @@ fn test_prompt_to_workflow_success() {
     );
 
     // Test Multi-modal prompt (Audio)
-    let input_audio = PluginInput {
-        text: std::ffi::CString::new("Summarize this audio and create a todo list")
-            .unwrap()
-            .into_raw(),
-    };
-    let result_audio = unsafe { ((*dispatcher.vtable).run)(&input_audio) };
-    let c_str_audio = unsafe { std::ffi::CStr::from_ptr(result_audio.text) };
-    let output_audio = c_str_audio.to_string_lossy().to_string();
-    unsafe { ((*dispatcher.vtable).free_output)(result_audio) };
+    let output_audio = dispatcher
+        .call("Summarize this audio and create a todo list")
+        .unwrap_or_default();
     assert!(
         output_audio.contains("WhisperPlugin"),
         "Should contain WhisperPlugin for audio prompt"
@@ -214,7 +181,7 @@ fn test_prompt_to_workflow_failure() {
     let mut reg = PluginRegistry::dynamic_registry(plugin_dir.to_str().unwrap_or("plugins"));
 
     // Check if PromptDispatcherPlugin is available
-    if reg.plugins.get("PromptDispatcherPlugin").is_none() {
+    if !reg.plugins.contains_key("PromptDispatcherPlugin") {
         println!("⚠️  PromptDispatcherPlugin not found, skipping prompt to workflow failure test");
         return;
     }
@@ -223,15 +190,9 @@ fn test_prompt_to_workflow_failure() {
         .plugins
         .get_mut("PromptDispatcherPlugin")
         .expect("PromptDispatcherPlugin not found");
-    let input = PluginInput {
-        text: std::ffi::CString::new("nonsense input that should fail")
-            .unwrap()
-            .into_raw(),
-    };
-    let result = unsafe { ((*dispatcher.vtable).run)(&input) };
-    let c_str = unsafe { std::ffi::CStr::from_ptr(result.text) };
-    let output = c_str.to_string_lossy().to_string();
-    unsafe { ((*dispatcher.vtable).free_output)(result) };
+    let output = dispatcher
+        .call("nonsense input that should fail")
+        .unwrap_or_default();
     println!("PromptDispatcherPlugin nonsense input output: '{output}'");
     assert!(
         output.contains("error") || output.is_empty(),
@@ -251,12 +212,9 @@ fn test_caching_and_retries() {
             retries: Some(2),
             retry_delay: Some(10),
             cache_key: Some("echo_cache_test".to_string()),
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }],
+        ..Default::default()
     };
     let path = "temp_cache.yaml";
     let cache_path = "cache/echo_cache_test.json";
@@ -304,14 +262,9 @@ fn test_log_output() {
             run: "EchoPlugin".to_string(),
             params: serde_yaml::from_str("input: 'Log this!'").unwrap(),
             retries: Some(1),
-            retry_delay: None,
-            cache_key: None,
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }],
+        ..Default::default()
     };
     let path = "temp_log.yaml";
     fs::write(path, serde_yaml::to_string(&workflow).unwrap()).unwrap();
@@ -351,27 +304,17 @@ fn test_multi_plugin_workflow() {
                 run: "EchoPlugin".to_string(),
                 params: serde_yaml::from_str("input: 'Chain this!'").unwrap(),
                 retries: Some(1),
-                retry_delay: None,
-                cache_key: None,
-                input_from: None,
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             WorkflowStep {
                 run: "SummarizerPlugin".to_string(),
                 params: serde_yaml::Value::Null,
                 retries: Some(1),
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("EchoPlugin".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
         ],
+        ..Default::default()
     };
     let path = "temp_multi_plugin.yaml";
     fs::write(path, serde_yaml::to_string(&workflow).unwrap()).unwrap();
@@ -400,28 +343,17 @@ fn test_circular_dependency() {
             WorkflowStep {
                 run: "EchoPlugin".to_string(),
                 params: serde_yaml::from_str("input: 'A'").unwrap(),
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("step2".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             WorkflowStep {
                 run: "SummarizerPlugin".to_string(),
                 params: serde_yaml::Value::Null,
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
                 input_from: Some("step1".to_string()),
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
         ],
+        ..Default::default()
     };
     let dag = build_dag(&workflow.steps).unwrap();
     let result = lao_orchestrator_core::topo_sort(&dag);
@@ -451,15 +383,9 @@ fn test_plugin_type_mismatch() {
         steps: vec![WorkflowStep {
             run: "EchoPlugin".to_string(),
             params: serde_yaml::from_str("input: { not: 'a string' }").unwrap(),
-            retries: None,
-            retry_delay: None,
-            cache_key: None,
-            input_from: None,
-            depends_on: None,
-            condition: None,
-            on_success: None,
-            on_failure: None,
+            ..Default::default()
         }],
+        ..Default::default()
     };
     let path = "temp_type_mismatch.yaml";
     fs::write(path, serde_yaml::to_string(&workflow).unwrap()).unwrap();
@@ -490,23 +416,12 @@ fn test_conditional_execution() {
             WorkflowStep {
                 run: "EchoPlugin".to_string(),
                 params: serde_yaml::from_str("input: 'trigger'").unwrap(),
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
-                input_from: None,
-                depends_on: None,
-                condition: None,
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             // Step 2: Should run (OutputContains "trigger")
             WorkflowStep {
                 run: "EchoPlugin".to_string(),
                 params: serde_yaml::from_str("input: 'Ran Step 2'").unwrap(),
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
-                input_from: None,
                 depends_on: Some(vec!["step1".to_string()]),
                 condition: Some(StepCondition {
                     condition_type: ConditionType::OutputContains,
@@ -514,17 +429,12 @@ fn test_conditional_execution() {
                     operator: ConditionOperator::Contains,
                     value: "trigger".to_string(),
                 }),
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
             // Step 3: Should skip (OutputContains "foobar")
             WorkflowStep {
                 run: "EchoPlugin".to_string(),
                 params: serde_yaml::from_str("input: 'Ran Step 3'").unwrap(),
-                retries: None,
-                retry_delay: None,
-                cache_key: None,
-                input_from: None,
                 depends_on: Some(vec!["step1".to_string()]),
                 condition: Some(StepCondition {
                     condition_type: ConditionType::OutputContains,
@@ -532,10 +442,10 @@ fn test_conditional_execution() {
                     operator: ConditionOperator::Contains,
                     value: "foobar".to_string(),
                 }),
-                on_success: None,
-                on_failure: None,
+                ..Default::default()
             },
         ],
+        ..Default::default()
     };
 
     let path = "temp_conditional.yaml";
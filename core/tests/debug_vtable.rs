@@ -1,17 +1,18 @@
+use lao_orchestrator_core::cross_platform::Platform;
 use lao_plugin_api::*;
-use std::mem;
 
 #[test]
 fn debug_vtable_memory() {
     // Try to load the echo plugin and examine its vtable memory
-    let dll_path = std::path::Path::new("../plugins/echo_plugin.dll");
+    let dll_path =
+        std::path::Path::new("../plugins").join(Platform::shared_lib_name("echo_plugin"));
     if !dll_path.exists() {
         println!("DLL not found at: {}", dll_path.display());
         return;
     }
 
     unsafe {
-        let library = libloading::Library::new(dll_path).expect("Failed to load library");
+        let library = libloading::Library::new(&dll_path).expect("Failed to load library");
         let plugin_vtable: libloading::Symbol<PluginVTablePtr> = library
             .get(b"plugin_vtable")
             .expect("Failed to get plugin_vtable symbol");
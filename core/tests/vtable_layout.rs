@@ -45,9 +45,7 @@ fn test_vtable_layout() {
         std::ptr::null()
     }
     unsafe extern "C" fn dummy_run(_: *const PluginInput) -> PluginOutput {
-        PluginOutput {
-            text: std::ptr::null_mut(),
-        }
+        PluginOutput::null()
     }
     unsafe extern "C" fn dummy_free_output(_: PluginOutput) {}
     unsafe extern "C" fn dummy_run_with_buffer(
@@ -78,7 +76,7 @@ fn test_vtable_layout() {
     }
 
     let dummy_vtable = PluginVTable {
-        version: 1,
+        version: ABI_VERSION,
         name: dummy_name,
         run: dummy_run,
         free_output: dummy_free_output,
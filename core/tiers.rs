@@ -0,0 +1,146 @@
+// Model tiers: workflows name the kind of model a step needs, each machine
+// names the model.
+// The `tiers:` map is described in docs/workflows.md (Model Tiers).
+use crate::Workflow;
+use std::collections::BTreeMap;
+
+const OPEN: &str = "${{";
+const CLOSE: &str = "}}";
+
+/// `text` with its `${{ tiers.<name> }}` references replaced by their models
+pub fn expand(text: &str, tiers: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        let Some(len) = rest[start..].find(CLOSE) else {
+            break;
+        };
+        let reference = rest[start + OPEN.len()..start + len].trim();
+        result.push_str(&rest[..start]);
+        match reference.strip_prefix("tiers.") {
+            Some(name) => result.push_str(tiers.get(name.trim()).ok_or_else(|| {
+                let known: Vec<&str> = tiers.keys().map(String::as_str).collect();
+                format!(
+                    "no model tier '{}' (known: {})",
+                    name.trim(),
+                    if known.is_empty() {
+                        "none".to_string()
+                    } else {
+                        known.join(", ")
+                    }
+                )
+            })?),
+            // Not ours; keep it for whatever reads it
+            None => result.push_str(&rest[start..start + len + CLOSE.len()]),
+        }
+        rest = &rest[start + len + CLOSE.len()..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn expand_value(
+    value: &mut serde_yaml::Value,
+    tiers: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    match value {
+        serde_yaml::Value::String(text) if text.contains(OPEN) => {
+            *text = expand(text, tiers)?;
+        }
+        serde_yaml::Value::Sequence(items) => {
+            for item in items {
+                expand_value(item, tiers)?;
+            }
+        }
+        serde_yaml::Value::Mapping(mapping) => {
+            for (_, item) in mapping.iter_mut() {
+                expand_value(item, tiers)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand_option(
+    text: &mut Option<String>,
+    tiers: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    if let Some(text) = text.as_mut().filter(|text| text.contains(OPEN)) {
+        *text = expand(text, tiers)?;
+    }
+    Ok(())
+}
+
+/// Resolve the tier references of `workflow` in place. Returns a problem per
+/// step (by index) or warm-up model (`None`) that names an unknown tier, or
+/// the registry's load error if it couldn't be read and a tier is used.
+pub fn resolve_workflow(
+    workflow: &mut Workflow,
+    tiers: Result<&BTreeMap<String, String>, String>,
+) -> Vec<(Option<usize>, String)> {
+    let mentions = |text: &str| text.contains(OPEN);
+    let used = workflow.steps.iter().any(|step| {
+        serde_yaml::to_string(&step.params).is_ok_and(|params| mentions(&params))
+            || step.backend.as_deref().is_some_and(mentions)
+    }) || workflow
+        .models
+        .iter()
+        .any(|model| model.ollama.as_deref().is_some_and(mentions));
+    if !used {
+        return Vec::new();
+    }
+    let tiers = match tiers {
+        Ok(tiers) => tiers,
+        Err(e) => return vec![(None, e)],
+    };
+    let mut problems = Vec::new();
+    for (index, step) in workflow.steps.iter_mut().enumerate() {
+        if let Err(e) = expand_value(&mut step.params, tiers)
+            .and_then(|_| expand_option(&mut step.backend, tiers))
+        {
+            problems.push((Some(index), e));
+        }
+    }
+    for model in &mut workflow.models {
+        if let Err(e) = expand_option(&mut model.ollama, tiers) {
+            problems.push((None, e));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tier_references_resolve_to_models() {
+        let tiers: BTreeMap<String, String> = [("fast", "phi3:mini"), ("quality", "llama3:70b")]
+            .into_iter()
+            .map(|(tier, model)| (tier.to_string(), model.to_string()))
+            .collect();
+        let mut workflow: Workflow = serde_yaml::from_str(
+            "workflow: tiers\nmodels:\n  - ollama: ${{tiers.quality}}\nsteps:\n  - run: ClassifierPlugin\n    model: ${{ tiers.fast }}\n    note: \"${{ other.thing }} ${step1}\"\n  - run: SummarizerPlugin\n    options:\n      model: ${{ tiers.turbo }}\n",
+        )
+        .unwrap();
+        let problems = resolve_workflow(&mut workflow, Ok(&tiers));
+        assert_eq!(
+            problems,
+            [(
+                Some(1),
+                "no model tier 'turbo' (known: fast, quality)".to_string()
+            )]
+        );
+        assert_eq!(workflow.steps[0].params["model"], "phi3:mini");
+        assert_eq!(
+            workflow.steps[0].params["note"],
+            "${{ other.thing }} ${step1}"
+        );
+        assert_eq!(workflow.models[0].ollama.as_deref(), Some("llama3:70b"));
+
+        let mut plain: Workflow =
+            serde_yaml::from_str("workflow: plain\nsteps:\n  - run: EchoPlugin\n").unwrap();
+        assert!(resolve_workflow(&mut plain, Err("Invalid backends.yaml".into())).is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+// System tray icon of `lao daemon --tray`.
+// The menu's entries are described in docs/workflows.md (Tray Menu).
+use crate::plugins::PluginRegistry;
+use crate::run_diff::RunSnapshot;
+use crate::run_labels::{self, RunQuery};
+use crate::StepLog;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Default tray file, in the data directory; overridable with `LAO_TRAY`
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_TRAY")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("tray.yaml"))
+}
+
+fn default_recent() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrayConfig {
+    /// Workflow files, relative to the tray file
+    #[serde(default)]
+    pub favorites: Vec<String>,
+    /// How many recent runs the menu lists
+    #[serde(default = "default_recent")]
+    pub recent: usize,
+}
+
+impl Default for TrayConfig {
+    fn default() -> Self {
+        Self {
+            favorites: Vec::new(),
+            recent: default_recent(),
+        }
+    }
+}
+
+/// The tray file at `path`, or the defaults when there is none
+pub fn load(path: &Path) -> Result<TrayConfig, String> {
+    if !path.exists() {
+        return Ok(TrayConfig::default());
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    serde_yaml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Favorite {
+    /// The workflow's name, or its file name when it can't be loaded
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl TrayConfig {
+    pub fn favorites(&self, config_path: &Path) -> Vec<Favorite> {
+        let dir = config_path.parent().unwrap_or(Path::new(""));
+        self.favorites
+            .iter()
+            .map(|favorite| {
+                let path = dir.join(favorite);
+                let name = crate::load_workflow_yaml(&path.to_string_lossy())
+                    .map(|workflow| workflow.workflow)
+                    .unwrap_or_else(|_| favorite.clone());
+                Favorite { name, path }
+            })
+            .collect()
+    }
+}
+
+/// The newest `limit` runs saved under `runs_dir`
+pub fn recent_runs(runs_dir: &Path, limit: usize) -> Vec<RunSnapshot> {
+    let query = RunQuery {
+        limit: Some(limit),
+        ..Default::default()
+    };
+    run_labels::query(runs_dir, &query)
+}
+
+/// A recent run as the menu shows it, e.g. `✓ Daily Digest · Oct 17 09:12`
+pub fn run_label(run: &RunSnapshot) -> String {
+    let mark = if run.status == "success" {
+        "✓"
+    } else {
+        "✗"
+    };
+    format!(
+        "{} {} · {}",
+        mark,
+        run.workflow,
+        run.finished
+            .with_timezone(&chrono::Local)
+            .format("%b %d %H:%M")
+    )
+}
+
+/// Run `favorite` in the background, as a click on its menu item does
+pub fn run_favorite(
+    favorite: &Favorite,
+    registry: Arc<PluginRegistry>,
+) -> JoinHandle<Result<Vec<StepLog>, String>> {
+    let path = favorite.path.to_string_lossy().to_string();
+    crate::metrics::global().record_trigger("tray");
+    crate::logging::info("tray menu started workflow")
+        .field("path", path.clone())
+        .emit();
+    thread::spawn(move || {
+        run_labels::with_trigger("tray", || {
+            crate::run_workflow_with_registry(&path, HashMap::new(), &registry)
+        })
+    })
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+struct LaoTray {
+    favorites: Vec<Favorite>,
+    recent: Vec<RunSnapshot>,
+    recent_limit: usize,
+    runs_dir: PathBuf,
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+impl LaoTray {
+    fn refresh(&mut self) {
+        self.recent = recent_runs(&self.runs_dir, self.recent_limit);
+    }
+}
+
+#[cfg(all(feature = "tray", target_os = "linux"))]
+impl ksni::Tray for LaoTray {
+    fn id(&self) -> String {
+        "lao".to_string()
+    }
+
+    fn title(&self) -> String {
+        "LAO".to_string()
+    }
+
+    fn icon_name(&self) -> String {
+        if crate::daemon::triggers_paused() {
+            "media-playback-pause".to_string()
+        } else {
+            "system-run".to_string()
+        }
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        let description = match (crate::daemon::triggers_paused(), self.recent.first()) {
+            (true, _) => "Triggers paused".to_string(),
+            (false, Some(run)) => format!("Last run: {}", run_label(run)),
+            (false, None) => "No runs yet".to_string(),
+        };
+        ksni::ToolTip {
+            title: "LAO".to_string(),
+            description,
+            ..Default::default()
+        }
+    }
+
+    fn menu_about_to_show(&mut self) {
+        self.refresh();
+    }
+
+    fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::{CheckmarkItem, StandardItem};
+        let mut items: Vec<ksni::MenuItem<Self>> = Vec::new();
+        for favorite in &self.favorites {
+            let favorite = favorite.clone();
+            items.push(
+                StandardItem {
+                    label: format!("Run {}", favorite.name),
+                    activate: Box::new(move |_: &mut Self| {
+                        run_favorite(&favorite, crate::plugins::shared().snapshot());
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        if !self.favorites.is_empty() {
+            items.push(ksni::MenuItem::Separator);
+        }
+        for run in &self.recent {
+            items.push(
+                StandardItem {
+                    label: run_label(run),
+                    enabled: false,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        if !self.recent.is_empty() {
+            items.push(ksni::MenuItem::Separator);
+        }
+        items.push(
+            CheckmarkItem {
+                label: "Pause triggers".to_string(),
+                checked: crate::daemon::triggers_paused(),
+                activate: Box::new(|_: &mut Self| {
+                    crate::daemon::set_triggers_paused(!crate::daemon::triggers_paused());
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+        items.push(
+            StandardItem {
+                label: "Quit LAO daemon".to_string(),
+                icon_name: "application-exit".to_string(),
+                activate: Box::new(|_: &mut Self| std::process::exit(0)),
+                ..Default::default()
+            }
+            .into(),
+        );
+        items
+    }
+}
+
+// Hosts don't all ask before showing the menu, so the runs are also
+// refreshed on a timer
+#[cfg(all(feature = "tray", target_os = "linux"))]
+const REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Show the tray icon for the favorites in `config_path` and the runs saved
+/// under `runs_dir`
+#[cfg(all(feature = "tray", target_os = "linux"))]
+pub fn serve(config_path: &Path, runs_dir: &Path) -> Result<(), String> {
+    use ksni::blocking::TrayMethods;
+
+    let config = load(config_path)?;
+    let mut tray = LaoTray {
+        favorites: config.favorites(config_path),
+        recent: Vec::new(),
+        recent_limit: config.recent,
+        runs_dir: runs_dir.to_path_buf(),
+    };
+    tray.refresh();
+    let handle = tray
+        .spawn()
+        .map_err(|e| format!("No system tray available: {}", e))?;
+    thread::spawn(move || {
+        while !handle.is_closed() {
+            thread::sleep(REFRESH_INTERVAL);
+            handle.update(LaoTray::refresh);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(all(feature = "tray", not(target_os = "linux")))]
+pub fn serve(_config_path: &Path, _runs_dir: &Path) -> Result<(), String> {
+    Err("the tray icon is only supported on Linux so far".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{mock_registry, MockFixtures};
+
+    /// A temp dir with a tray.yaml listing one real and one missing workflow.
+    fn tray_dir() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_tray_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("flows")).unwrap();
+        std::fs::write(
+            dir.join("flows/digest.yaml"),
+            "workflow: Daily Digest\nsteps:\n  - run: EchoPlugin\n    input: digest\n",
+        )
+        .unwrap();
+        let config_path = dir.join("tray.yaml");
+        std::fs::write(
+            &config_path,
+            "favorites:\n  - flows/digest.yaml\n  - flows/missing.yaml\n",
+        )
+        .unwrap();
+        (dir, config_path)
+    }
+
+    #[test]
+    fn test_missing_config_shows_five_recent_runs() {
+        let dir = std::env::temp_dir().join(format!("lao_tray_{}", uuid::Uuid::new_v4()));
+        assert_eq!(load(&dir.join("tray.yaml")).unwrap().recent, 5);
+        assert!(recent_runs(&dir.join("no_runs"), 5).is_empty());
+    }
+
+    #[test]
+    fn test_favorites_are_named_after_their_workflow() {
+        let (dir, config_path) = tray_dir();
+        let favorites = load(&config_path).unwrap().favorites(&config_path);
+        assert_eq!(favorites[0].name, "Daily Digest");
+        assert_eq!(favorites[1].name, "flows/missing.yaml");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_run_favorite() {
+        let (dir, config_path) = tray_dir();
+        let favorites = load(&config_path).unwrap().favorites(&config_path);
+        let workflow = crate::load_workflow_yaml(&favorites[0].path.to_string_lossy()).unwrap();
+        let registry = Arc::new(mock_registry(&workflow, &MockFixtures::default()));
+        let logs = run_favorite(&favorites[0], registry)
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(logs[0].output.as_deref(), Some("EchoPlugin(digest)"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_failed_run_label() {
+        let run = RunSnapshot {
+            run_id: "run".to_string(),
+            workflow: "Daily Digest".to_string(),
+            finished: chrono::Utc::now(),
+            status: "error".to_string(),
+            duration_ms: 12,
+            steps: Vec::new(),
+            labels: Default::default(),
+            prompts: Vec::new(),
+            taint: Vec::new(),
+        };
+        assert!(run_label(&run).starts_with("✗ Daily Digest · "));
+    }
+}
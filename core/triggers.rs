@@ -0,0 +1,665 @@
+// Schedules and file watches that start workflows from the daemon.
+// `lao schedule` and `lao watch` are described in docs/workflows.md
+// (Schedules and Watched Folders).
+use crate::run_class::{self, RunClass};
+use crate::{run_labels, run_workflow_yaml_with_inputs};
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDateTime, TimeZone, Timelike, Utc,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Default store, overridable with `LAO_TRIGGERS`
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_TRIGGERS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("triggers.json"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Cron(Cron),
+    /// `interval:MINUTES`: this long after the last run
+    Every(ChronoDuration),
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let expr = expr.trim();
+        let invalid = |e: String| format!("invalid schedule '{}': {}", expr, e);
+        let cron = match expr {
+            "@hourly" => "0 * * * *".to_string(),
+            "@daily" | "@midnight" => "0 0 * * *".to_string(),
+            "@weekly" => "0 0 * * sun".to_string(),
+            _ => match expr.split(':').collect::<Vec<_>>().as_slice() {
+                ["interval", minutes] => {
+                    return match minutes.parse::<u32>() {
+                        Ok(minutes) if minutes > 0 => {
+                            Ok(Self::Every(ChronoDuration::minutes(minutes.into())))
+                        }
+                        _ => Err(invalid("the interval is a whole number of minutes".into())),
+                    }
+                }
+                ["daily", hour, minute] => format!("{} {} * * *", minute, hour),
+                ["weekly", day, hour, minute] => format!("{} {} * * {}", minute, hour, day),
+                _ => expr.to_string(),
+            },
+        };
+        Cron::parse(&cron).map(Self::Cron).map_err(invalid)
+    }
+
+    /// The first time after `after` that the schedule fires
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Cron(cron) => cron
+                .next_after(after.with_timezone(&Local))
+                .map(|next| next.with_timezone(&Utc)),
+            Self::Every(interval) => Some(after + *interval),
+        }
+    }
+}
+
+/// A five-field cron expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    // Bit n is set when value n matches; weekdays count from Sunday = 0
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // `*` in day of month or day of week; with both restricted, either matches
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+        let mut weekdays = field(weekday, 0, 7, WEEKDAYS, 0)?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: field(minute, 0, 59, &[], 0)?,
+            hours: field(hour, 0, 23, &[], 0)?,
+            days: field(day, 1, 31, &[], 0)?,
+            months: field(month, 1, 12, MONTHS, 1)?,
+            weekdays,
+            any_day: *day == "*",
+            any_weekday: *weekday == "*",
+        })
+    }
+
+    /// The first minute after `after` that matches
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)?;
+        let mut t = start + ChronoDuration::minutes(1);
+        // Long enough for February 29th on a given weekday
+        let limit = t + ChronoDuration::days(366 * 28);
+        while t < limit {
+            if !bit(self.months, t.month()) {
+                let (year, month) = match t.month() {
+                    12 => (t.year() + 1, 1),
+                    month => (t.year(), month + 1),
+                };
+                t = midnight(year, month, 1)?;
+            } else if !self.day_matches(&t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !bit(self.hours, t.hour()) {
+                t = t.date().and_hms_opt(t.hour(), 0, 0)? + ChronoDuration::hours(1);
+            } else if !bit(self.minutes, t.minute()) {
+                t += ChronoDuration::minutes(1);
+            } else {
+                // A time skipped by a daylight saving change doesn't fire
+                match zone.from_local_datetime(&t).earliest() {
+                    Some(next) => return Some(next),
+                    None => t += ChronoDuration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: &NaiveDateTime) -> bool {
+        let day = bit(self.days, t.day());
+        let weekday = bit(self.weekdays, t.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn midnight(year: i32, month: u32, day: u32) -> Option<NaiveDateTime> {
+    chrono::NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(0, 0, 0)
+}
+
+// Mask of the values a cron field allows; `names[i]` stands for `i + first_name`
+fn field(text: &str, min: u32, max: u32, names: &[&str], first_name: u32) -> Result<u64, String> {
+    let value = |v: &str| -> Result<u32, String> {
+        let lower = v.to_ascii_lowercase();
+        let named = lower
+            .get(..3)
+            .filter(|_| lower.chars().all(|c| c.is_ascii_alphabetic()))
+            .and_then(|prefix| names.iter().position(|n| *n == prefix));
+        let n = match named {
+            Some(i) => i as u32 + first_name,
+            None => v.parse().map_err(|_| format!("'{}' is not a number", v))?,
+        };
+        if n < min || n > max {
+            return Err(format!("{} is outside {}-{}", n, min, max));
+        }
+        Ok(n)
+    };
+    let mut mask = 0u64;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                Some(
+                    step.parse::<u32>()
+                        .ok()
+                        .filter(|s| *s > 0)
+                        .ok_or_else(|| format!("invalid step '{}'", step))?,
+                ),
+            ),
+            None => (part, None),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // `5/15` runs from 5 to the end
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let v = value(range)?;
+                (v, v)
+            }
+        };
+        if from > to {
+            return Err(format!("range {} is backwards", range));
+        }
+        for v in (from..=to).step_by(step.unwrap_or(1) as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub id: String,
+    /// Absolute path of the workflow, so the daemon's directory doesn't matter
+    pub workflow: String,
+    #[serde(flatten)]
+    pub on: TriggerOn,
+    pub created: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub runs: u32,
+    /// Stop after this many runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_runs: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TriggerOn {
+    Schedule {
+        schedule: String,
+    },
+    Watch {
+        dir: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pattern: Option<String>,
+        /// Files seen in the directory; only others run the workflow
+        #[serde(default)]
+        known: BTreeSet<String>,
+    },
+}
+
+impl Trigger {
+    pub fn schedule(workflow: &str, schedule: &str, max_runs: Option<u32>) -> Result<Self, String> {
+        Schedule::parse(schedule)?;
+        Ok(Self::new(
+            workflow,
+            TriggerOn::Schedule {
+                schedule: schedule.trim().to_string(),
+            },
+            max_runs,
+        ))
+    }
+
+    /// A watch of `dir`, which must exist; the files in it now are known
+    pub fn watch(workflow: &str, dir: &Path, pattern: Option<&str>) -> Result<Self, String> {
+        let matcher = pattern
+            .map(glob::Pattern::new)
+            .transpose()
+            .map_err(|e| format!("invalid pattern: {}", e))?;
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
+        }
+        Ok(Self::new(
+            workflow,
+            TriggerOn::Watch {
+                dir: dir.to_string_lossy().to_string(),
+                pattern: pattern.map(str::to_string),
+                known: scan(dir, matcher.as_ref()).into_keys().collect(),
+            },
+            None,
+        ))
+    }
+
+    fn new(workflow: &str, on: TriggerOn, max_runs: Option<u32>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            workflow: workflow.to_string(),
+            on,
+            created: Utc::now(),
+            last_run: None,
+            runs: 0,
+            max_runs,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.max_runs.is_some_and(|max| self.runs >= max)
+    }
+
+    /// When a schedule fires next; None for watches and finished schedules
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        match &self.on {
+            TriggerOn::Schedule { schedule } if !self.finished() => Schedule::parse(schedule)
+                .ok()?
+                .next_after(self.last_run.unwrap_or(self.created)),
+            _ => None,
+        }
+    }
+
+    /// What starts the workflow, e.g. `0 9 * * *` or `new *.wav in /home/me/recordings`
+    pub fn describe(&self) -> String {
+        match &self.on {
+            TriggerOn::Schedule { schedule } => schedule.clone(),
+            TriggerOn::Watch { dir, pattern, .. } => {
+                format!("new {} in {}", pattern.as_deref().unwrap_or("files"), dir)
+            }
+        }
+    }
+}
+
+// Files in `dir` that a watch with `pattern` looks at, with their sizes
+fn scan(dir: &Path, pattern: Option<&glob::Pattern>) -> BTreeMap<String, u64> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            !name.starts_with('.') && pattern.is_none_or(|p| p.matches(&name))
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((entry.path().to_string_lossy().to_string(), metadata.len()))
+        })
+        .collect()
+}
+
+/// Triggers kept in one file
+pub struct TriggerStore {
+    path: PathBuf,
+}
+
+impl TriggerStore {
+    pub fn open_default() -> Self {
+        Self::at(default_path())
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every trigger; none when the store doesn't exist yet
+    pub fn load(&self) -> Result<Vec<Trigger>, String> {
+        match fs::read_to_string(&self.path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid {}: {}", self.path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(format!("Cannot read {}: {}", self.path.display(), e)),
+        }
+    }
+
+    pub fn add(&self, trigger: Trigger) -> Result<(), String> {
+        self.update(|triggers| triggers.push(trigger))
+    }
+
+    /// Whether a trigger with `id` was stored
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut removed = false;
+        self.update(|triggers| {
+            let before = triggers.len();
+            triggers.retain(|t| t.id != id);
+            removed = triggers.len() < before;
+        })?;
+        Ok(removed)
+    }
+
+    // Read, change and write back at once, so the daemon and the CLI can
+    // both change the file
+    fn update(&self, f: impl FnOnce(&mut Vec<Trigger>)) -> Result<(), String> {
+        let mut triggers = self.load()?;
+        f(&mut triggers);
+        if let Some(dir) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(&triggers).map_err(|e| e.to_string())?;
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, json)
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .map_err(|e| format!("Cannot write {}: {}", self.path.display(), e))
+    }
+}
+
+/// A workflow a trigger starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Firing {
+    pub trigger: String,
+    /// "schedule" or "watch", the run's trigger tag
+    pub kind: &'static str,
+    pub workflow: String,
+    pub inputs: HashMap<String, String>,
+}
+
+/// What the triggers in `store` start at `now`, recorded in the store as run.
+/// `pending` holds the sizes of new files from the last check.
+pub fn due(
+    store: &TriggerStore,
+    now: DateTime<Utc>,
+    pending: &mut HashMap<String, u64>,
+) -> Result<Vec<Firing>, String> {
+    let mut firings = Vec::new();
+    let mut changed = HashMap::new();
+    for mut trigger in store.load()? {
+        if trigger.finished() {
+            continue;
+        }
+        let before = trigger.clone();
+        match &mut trigger.on {
+            TriggerOn::Schedule { .. } => {
+                if trigger.next_run().is_some_and(|next| next <= now) {
+                    firings.push(Firing {
+                        trigger: trigger.id.clone(),
+                        kind: "schedule",
+                        workflow: trigger.workflow.clone(),
+                        inputs: HashMap::new(),
+                    });
+                    trigger.last_run = Some(now);
+                    trigger.runs += 1;
+                }
+            }
+            TriggerOn::Watch {
+                dir,
+                pattern,
+                known,
+            } => {
+                let matcher = pattern.as_deref().and_then(|p| glob::Pattern::new(p).ok());
+                let files = scan(Path::new(dir), matcher.as_ref());
+                known.retain(|file| files.contains_key(file));
+                let new: Vec<(&String, &u64)> = files
+                    .iter()
+                    .filter(|(file, _)| !known.contains(*file))
+                    .collect();
+                for (file, size) in new {
+                    if pending.get(file) != Some(size) {
+                        pending.insert(file.clone(), *size);
+                        continue;
+                    }
+                    pending.remove(file);
+                    known.insert(file.clone());
+                    firings.push(Firing {
+                        trigger: trigger.id.clone(),
+                        kind: "watch",
+                        workflow: trigger.workflow.clone(),
+                        inputs: HashMap::from([("file".to_string(), file.clone())]),
+                    });
+                    trigger.last_run = Some(now);
+                    trigger.runs += 1;
+                }
+            }
+        }
+        if trigger != before {
+            changed.insert(trigger.id.clone(), trigger);
+        }
+    }
+    if !changed.is_empty() {
+        // Only the triggers checked here; ones added meanwhile stay
+        store.update(|triggers| {
+            for trigger in triggers.iter_mut() {
+                if let Some(updated) = changed.remove(&trigger.id) {
+                    *trigger = updated;
+                }
+            }
+        })?;
+    }
+    Ok(firings)
+}
+
+/// Check the triggers in `store` every `interval` on a background thread and
+/// run what they start, one run at a time
+pub fn serve(store: TriggerStore, interval: Duration) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut pending = HashMap::new();
+        loop {
+            // Paused schedules stay due and run once triggers are resumed
+            if !crate::daemon::triggers_paused() {
+                match due(&store, Utc::now(), &mut pending) {
+                    Ok(firings) => {
+                        for (started, firing) in firings.iter().enumerate() {
+                            crate::metrics::global().set_queue_depth(firings.len() - started);
+                            start(firing);
+                        }
+                        crate::metrics::global().set_queue_depth(0);
+                    }
+                    Err(e) => crate::logging::warn("reading triggers failed")
+                        .field("error", e)
+                        .emit(),
+                }
+            }
+            thread::sleep(interval);
+        }
+    })
+}
+
+fn start(firing: &Firing) {
+    crate::metrics::global().record_trigger(firing.kind);
+    crate::logging::info("trigger started workflow")
+        .field("trigger", firing.trigger.clone())
+        .field("kind", firing.kind)
+        .field("path", firing.workflow.clone())
+        .emit();
+    // Triggered runs give way to runs someone is waiting on
+    let result = run_class::with_class(RunClass::Background, || {
+        run_labels::with_trigger(firing.kind, || {
+            run_workflow_yaml_with_inputs(&firing.workflow, firing.inputs.clone())
+        })
+    });
+    if let Err(e) = result {
+        crate::logging::warn("triggered workflow failed")
+            .field("trigger", firing.trigger.clone())
+            .field("path", firing.workflow.clone())
+            .field("error", e)
+            .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    fn next(expr: &str, after: &str) -> String {
+        match Schedule::parse(expr).unwrap() {
+            Schedule::Cron(cron) => cron
+                .next_after(at(after))
+                .unwrap()
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            Schedule::Every(interval) => (at(after) + interval).format("%H:%M").to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cron_fields_and_steps() {
+        // 2026-10-18 is a Sunday
+        assert_eq!(next("0 9 * * *", "2026-10-18 09:00"), "2026-10-19 09:00");
+        assert_eq!(next("*/15 * * * *", "2026-10-18 09:07"), "2026-10-18 09:15");
+        assert_eq!(
+            next("30 8 * * mon-fri", "2026-10-17 12:00"),
+            "2026-10-19 08:30"
+        );
+    }
+
+    #[test]
+    fn test_cron_rare_days() {
+        assert_eq!(next("0 0 29 feb *", "2026-10-18 00:00"), "2028-02-29 00:00");
+        // Day of month or day of week, as cron does when both are set
+        assert_eq!(next("0 12 1 * 0", "2026-10-18 12:00"), "2026-10-25 12:00");
+    }
+
+    #[test]
+    fn test_daily_and_interval_schedules() {
+        assert_eq!(next("daily:07:45", "2026-10-18 08:00"), "2026-10-19 07:45");
+        assert_eq!(next("interval:90", "2026-10-18 08:00"), "09:30");
+    }
+
+    #[test]
+    fn test_invalid_schedules_are_errors() {
+        assert!(Schedule::parse("0 25 * * *").is_err());
+        assert!(Schedule::parse("every day").is_err());
+    }
+
+    struct Triggers {
+        dir: PathBuf,
+        inbox: PathBuf,
+        store: TriggerStore,
+        hourly: Trigger,
+        watch: Trigger,
+    }
+
+    impl Triggers {
+        // An hourly schedule and a watch on a folder with one old recording
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("lao_triggers_{}", uuid::Uuid::new_v4()));
+            let inbox = dir.join("recordings");
+            fs::create_dir_all(&inbox).unwrap();
+            fs::write(inbox.join("old.wav"), "x").unwrap();
+            let store = TriggerStore::at(dir.join("triggers.json"));
+            let mut hourly = Trigger::schedule("/w/hourly.yaml", "interval:60", None).unwrap();
+            hourly.created = at("2026-10-18 08:00");
+            store.add(hourly.clone()).unwrap();
+            let watch = Trigger::watch("/w/transcribe.yaml", &inbox, Some("*.wav")).unwrap();
+            store.add(watch.clone()).unwrap();
+            Self {
+                dir,
+                inbox,
+                store,
+                hourly,
+                watch,
+            }
+        }
+    }
+
+    impl Drop for Triggers {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_schedule_fires_once_when_due() {
+        let triggers = Triggers::new();
+        let mut pending = HashMap::new();
+        assert!(due(&triggers.store, at("2026-10-18 08:59"), &mut pending)
+            .unwrap()
+            .is_empty());
+        let fired = due(&triggers.store, at("2026-10-18 09:00"), &mut pending).unwrap();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(
+            (fired[0].kind, fired[0].trigger.as_str()),
+            ("schedule", triggers.hourly.id.as_str())
+        );
+        assert!(due(&triggers.store, at("2026-10-18 09:01"), &mut pending)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_watch_fires_once_per_new_matching_file() {
+        let triggers = Triggers::new();
+        let mut pending = HashMap::new();
+        due(&triggers.store, at("2026-10-18 08:30"), &mut pending).unwrap();
+        fs::write(triggers.inbox.join("new.wav"), "audio").unwrap();
+        fs::write(triggers.inbox.join("notes.txt"), "skip").unwrap();
+        // The new file waits one check for its size to settle
+        assert!(due(&triggers.store, at("2026-10-18 08:31"), &mut pending)
+            .unwrap()
+            .is_empty());
+        let fired = due(&triggers.store, at("2026-10-18 08:32"), &mut pending).unwrap();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(
+            fired[0].inputs["file"],
+            triggers.inbox.join("new.wav").to_string_lossy()
+        );
+        assert!(due(&triggers.store, at("2026-10-18 08:33"), &mut pending)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_progress_is_kept_in_the_store() {
+        let triggers = Triggers::new();
+        let mut pending = HashMap::new();
+        due(&triggers.store, at("2026-10-18 08:30"), &mut pending).unwrap();
+        fs::write(triggers.inbox.join("new.wav"), "audio").unwrap();
+        due(&triggers.store, at("2026-10-18 09:00"), &mut pending).unwrap();
+        due(&triggers.store, at("2026-10-18 09:01"), &mut pending).unwrap();
+
+        // A restarted daemon carries on from here
+        let saved = triggers.store.load().unwrap();
+        assert_eq!(saved[0].next_run(), Some(at("2026-10-18 10:00")));
+        assert_eq!(saved[1].runs, 1);
+        assert!(triggers.store.remove(&triggers.watch.id).unwrap());
+        assert_eq!(triggers.store.load().unwrap().len(), 1);
+    }
+}
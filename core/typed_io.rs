@@ -0,0 +1,233 @@
+// Typed plugin input and output.
+use crate::plugins::PluginInstance;
+use crate::WorkflowStep;
+use lao_plugin_api::typed::{Field, FieldSpec, Payload, PayloadKind, Signature, Value};
+use lao_plugin_api::{PluginInputType, PluginOutputType};
+
+/// Primary input and output types of `signature`, for the edge checks that
+/// untyped plugins declare through capabilities
+pub fn io_types(signature: &Signature) -> (PluginInputType, PluginOutputType) {
+    let input = match signature.inputs.first() {
+        Some(spec) => match media(spec) {
+            Some("audio") => PluginInputType::Audio,
+            Some("image") => PluginInputType::Image,
+            Some("video") => PluginInputType::Video,
+            _ => match spec.kind {
+                PayloadKind::Text => PluginInputType::Text,
+                PayloadKind::Json => PluginInputType::Json,
+                PayloadKind::Blob => PluginInputType::Binary,
+                PayloadKind::File => PluginInputType::File,
+            },
+        },
+        None => PluginInputType::Any,
+    };
+    let output = match signature.outputs.first() {
+        Some(spec) => match media(spec) {
+            Some("audio") => PluginOutputType::Audio,
+            Some("image") => PluginOutputType::Image,
+            Some("video") => PluginOutputType::Video,
+            _ => match spec.kind {
+                PayloadKind::Text => PluginOutputType::Text,
+                PayloadKind::Json => PluginOutputType::Json,
+                PayloadKind::Blob => PluginOutputType::Binary,
+                PayloadKind::File => PluginOutputType::File,
+            },
+        },
+        None => PluginOutputType::Any,
+    };
+    (input, output)
+}
+
+// `audio` for a file or blob of `audio/wav`
+fn media(spec: &FieldSpec) -> Option<&str> {
+    if !matches!(spec.kind, PayloadKind::File | PayloadKind::Blob) {
+        return None;
+    }
+    spec.content_type.as_deref()?.split('/').next()
+}
+
+/// Whether content type `offered` satisfies `wanted`, either possibly `type/*`
+fn content_types_match(offered: &str, wanted: &str) -> bool {
+    let split = |content_type: &str| {
+        let (kind, subtype) = content_type.split_once('/').unwrap_or((content_type, "*"));
+        (
+            kind.trim().to_ascii_lowercase(),
+            subtype.trim().to_ascii_lowercase(),
+        )
+    };
+    let (offered, wanted) = (split(offered), split(wanted));
+    offered.0 == wanted.0 && (offered.1 == wanted.1 || offered.1 == "*" || wanted.1 == "*")
+}
+
+/// Problems with how `step` supplies the inputs `signature` declares
+pub fn check_step(signature: &Signature, step: &WorkflowStep) -> Vec<String> {
+    let mut problems = Vec::new();
+    let params = step.params.as_mapping();
+    for (index, spec) in signature.inputs.iter().enumerate() {
+        let param = params.and_then(|params| params.get(spec.name.as_str()));
+        let from_input = index == 0
+            && (step.input_from.is_some()
+                || params.is_some_and(|params| params.contains_key("input")));
+        match param {
+            None if !spec.optional && !from_input => problems.push(format!(
+                "'{}' needs input `{}` ({})",
+                step.run,
+                spec.name,
+                spec.kind.as_str()
+            )),
+            Some(serde_yaml::Value::String(text))
+                if spec.kind == PayloadKind::Json && !text.contains("${") =>
+            {
+                if let Err(e) = serde_json::from_str::<serde_json::Value>(text) {
+                    problems.push(format!("input `{}` is not valid JSON: {}", spec.name, e));
+                }
+            }
+            _ => {}
+        }
+    }
+    problems
+}
+
+/// Why the primary output of `parent` can't feed the first input of
+/// `child`, if their content types disagree
+pub fn check_edge(parent: &Signature, child: &Signature) -> Option<String> {
+    let output = parent.outputs.first()?;
+    let input = child.inputs.first()?;
+    let (offered, wanted) = (
+        output.content_type.as_deref()?,
+        input.content_type.as_deref()?,
+    );
+    (!content_types_match(offered, wanted)).then(|| {
+        format!(
+            "output `{}` is {} but input `{}` expects {}",
+            output.name, offered, input.name, wanted
+        )
+    })
+}
+
+/// The payload for a step with `params`, whose rendered input is `input`
+pub fn payload_for(
+    signature: &Signature,
+    params: &serde_yaml::Value,
+    input: &str,
+) -> Result<Payload, String> {
+    let mut payload = Payload::new();
+    for (index, spec) in signature.inputs.iter().enumerate() {
+        let param = params
+            .as_mapping()
+            .and_then(|params| params.get(spec.name.as_str()));
+        let value = match param {
+            Some(param) => convert(spec, param)?,
+            None if index == 0 => Value::parse(spec.kind, input)
+                .map_err(|e| format!("input `{}`: {}", spec.name, e))?,
+            None if spec.optional => continue,
+            None => return Err(format!("missing input `{}`", spec.name)),
+        };
+        let mut field = Field::new(&spec.name, value);
+        field.content_type = spec.content_type.clone();
+        payload = payload.with(field);
+    }
+    Ok(payload)
+}
+
+fn convert(spec: &FieldSpec, param: &serde_yaml::Value) -> Result<Value, String> {
+    let text = match param {
+        serde_yaml::Value::String(text) => text.clone(),
+        other if spec.kind == PayloadKind::Json => {
+            return serde_json::to_value(other)
+                .map(Value::Json)
+                .map_err(|e| format!("input `{}`: {}", spec.name, e));
+        }
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    };
+    Value::parse(spec.kind, &text).map_err(|e| format!("input `{}`: {}", spec.name, e))
+}
+
+/// Call `plugin` for a step and return its primary output as text
+pub fn call(
+    plugin: &PluginInstance,
+    signature: &Signature,
+    params: &serde_yaml::Value,
+    input: &str,
+) -> Result<String, String> {
+    let payload = payload_for(signature, params, input)?;
+    signature.output_text(plugin.call_typed(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcriber() -> Signature {
+        serde_json::from_str(
+            r#"{"inputs":[{"name":"audio","type":"file","content_type":"audio/*"},
+                          {"name":"options","type":"json","optional":true},
+                          {"name":"speakers","type":"json"}],
+                "outputs":[{"name":"transcript","type":"text"}]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_io_types_follow_the_signature() {
+        assert_eq!(
+            io_types(&transcriber()),
+            (PluginInputType::Audio, PluginOutputType::Text)
+        );
+    }
+
+    #[test]
+    fn test_check_step_reports_bad_and_missing_inputs() {
+        let step: WorkflowStep =
+            serde_yaml::from_str("run: Transcriber\ninput_from: step1\noptions: '{oops'\n")
+                .unwrap();
+        assert_eq!(
+            check_step(&transcriber(), &step),
+            [
+                "input `options` is not valid JSON: key must be a string at line 1 column 2",
+                "'Transcriber' needs input `speakers` (json)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_payload_fills_fields_from_params_and_input() {
+        let params: serde_yaml::Value =
+            serde_yaml::from_str("speakers: [alice, bob]\noptions: '{\"language\": \"en\"}'")
+                .unwrap();
+        let payload = payload_for(&transcriber(), &params, "talk.wav").unwrap();
+        assert_eq!(payload.fields.len(), 3);
+        assert_eq!(
+            payload.value("audio"),
+            Some(&Value::File("talk.wav".into()))
+        );
+        assert_eq!(
+            payload.get("audio").unwrap().content_type.as_deref(),
+            Some("audio/*")
+        );
+        assert_eq!(
+            payload.value("speakers"),
+            Some(&Value::Json(serde_json::json!(["alice", "bob"])))
+        );
+        assert_eq!(
+            payload.value("options"),
+            Some(&Value::Json(serde_json::json!({"language": "en"})))
+        );
+    }
+
+    #[test]
+    fn test_check_edge_compares_content_types() {
+        let recorder: Signature = serde_json::from_str(
+            r#"{"outputs":[{"name":"recording","type":"file","content_type":"image/png"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            check_edge(&recorder, &transcriber()).unwrap(),
+            "output `recording` is image/png but input `audio` expects audio/*"
+        );
+        assert!(content_types_match("audio/wav", "audio/*"));
+    }
+}
@@ -0,0 +1,268 @@
+// Token and compute usage history.
+// `lao usage` is described in docs/observability.md (Usage).
+use chrono::{DateTime, NaiveDate, Utc};
+use lao_plugin_api::PluginUsage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Power draw used for energy estimates when LAO_POWER_WATTS is unset
+pub const DEFAULT_POWER_WATTS: f64 = 150.0;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PluginTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Wall time spent inside plugin calls
+    pub seconds: f64,
+}
+
+impl PluginTotals {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    fn add(&mut self, other: &PluginTotals) {
+        self.calls += other.calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.seconds += other.seconds;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunUsage {
+    pub run_id: String,
+    pub workflow: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub plugins: BTreeMap<String, PluginTotals>,
+    /// Plugin time times LAO_POWER_WATTS at the time of the run
+    pub energy_wh: f64,
+}
+
+impl RunUsage {
+    pub fn new(run_id: &str, workflow: &str) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            workflow: workflow.to_string(),
+            started_at: Utc::now(),
+            duration_ms: 0,
+            plugins: BTreeMap::new(),
+            energy_wh: 0.0,
+        }
+    }
+
+    pub fn record_call(&mut self, plugin: &str, elapsed: Duration, usage: Option<PluginUsage>) {
+        let totals = self.plugins.entry(plugin.to_string()).or_default();
+        totals.calls += 1;
+        totals.seconds += elapsed.as_secs_f64();
+        if let Some(usage) = usage {
+            totals.prompt_tokens += usage.prompt_tokens;
+            totals.completion_tokens += usage.completion_tokens;
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.plugins.values().map(PluginTotals::total_tokens).sum()
+    }
+
+    pub fn plugin_seconds(&self) -> f64 {
+        self.plugins.values().map(|p| p.seconds).sum()
+    }
+
+    /// Set the run duration and energy estimate, then append to the usage log
+    pub fn finish(mut self, duration: Duration) {
+        self.duration_ms = duration.as_millis() as u64;
+        self.energy_wh = self.plugin_seconds() * power_watts() / 3600.0;
+        if let Some(log) = USAGE_LOG.get() {
+            if let Err(e) = log.append(&self) {
+                crate::logging::warn("failed to write usage log")
+                    .run(&self.run_id)
+                    .field("path", log.path.display().to_string())
+                    .field("error", e.to_string())
+                    .emit();
+            }
+        }
+    }
+}
+
+/// LAO_POWER_WATTS, the average draw of the machine while plugins run
+pub fn power_watts() -> f64 {
+    std::env::var("LAO_POWER_WATTS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_POWER_WATTS)
+}
+
+struct UsageLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl UsageLog {
+    fn append(&self, usage: &RunUsage) -> std::io::Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(
+            file,
+            "{}",
+            crate::encryption::seal_line(&serde_json::to_string(usage)?)?
+        )
+    }
+}
+
+static USAGE_LOG: OnceLock<UsageLog> = OnceLock::new();
+
+/// Start recording run usage to `path`; later calls are ignored
+pub fn init(path: impl Into<PathBuf>) {
+    let _ = USAGE_LOG.set(UsageLog {
+        path: path.into(),
+        lock: Mutex::new(()),
+    });
+}
+
+/// LAO_USAGE_LOG, defaulting to usage/usage.jsonl in the data directory
+pub fn default_path() -> PathBuf {
+    std::env::var("LAO_USAGE_LOG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| crate::data_dir::path("usage").join("usage.jsonl"))
+}
+
+pub fn read_history(path: &Path) -> std::io::Result<Vec<RunUsage>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter_map(|line| crate::encryption::open_line(line).ok())
+        .filter_map(|line| serde_json::from_str::<RunUsage>(&line).ok())
+        .collect())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WorkflowTotals {
+    pub runs: u64,
+    pub tokens: u64,
+    pub seconds: f64,
+    pub energy_wh: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageSummary {
+    pub runs: u64,
+    pub plugins: BTreeMap<String, PluginTotals>,
+    pub workflows: BTreeMap<String, WorkflowTotals>,
+    /// Tokens per UTC day, for charts
+    pub daily_tokens: BTreeMap<NaiveDate, u64>,
+    pub energy_wh: f64,
+}
+
+impl UsageSummary {
+    pub fn total_tokens(&self) -> u64 {
+        self.plugins.values().map(PluginTotals::total_tokens).sum()
+    }
+
+    pub fn plugin_seconds(&self) -> f64 {
+        self.plugins.values().map(|p| p.seconds).sum()
+    }
+
+    /// Workflows ordered by plugin time, heaviest first
+    pub fn workflows_by_time(&self) -> Vec<(&String, &WorkflowTotals)> {
+        let mut workflows: Vec<_> = self.workflows.iter().collect();
+        workflows.sort_by(|a, b| b.1.seconds.total_cmp(&a.1.seconds));
+        workflows
+    }
+}
+
+/// Totals over runs started at or after `since`
+pub fn summarize(history: &[RunUsage], since: Option<DateTime<Utc>>) -> UsageSummary {
+    let mut summary = UsageSummary::default();
+    for run in history
+        .iter()
+        .filter(|run| since.is_none_or(|since| run.started_at >= since))
+    {
+        summary.runs += 1;
+        summary.energy_wh += run.energy_wh;
+        for (plugin, totals) in &run.plugins {
+            summary
+                .plugins
+                .entry(plugin.clone())
+                .or_default()
+                .add(totals);
+        }
+        let workflow = summary.workflows.entry(run.workflow.clone()).or_default();
+        workflow.runs += 1;
+        workflow.tokens += run.total_tokens();
+        workflow.seconds += run.plugin_seconds();
+        workflow.energy_wh += run.energy_wh;
+        *summary
+            .daily_tokens
+            .entry(run.started_at.date_naive())
+            .or_default() += run.total_tokens();
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two runs from today and a larger one from a month ago.
+    fn history() -> Vec<RunUsage> {
+        let mut first = RunUsage::new("run-1", "summarize");
+        first.record_call(
+            "OllamaPlugin",
+            Duration::from_secs(3),
+            Some(PluginUsage {
+                prompt_tokens: 100,
+                completion_tokens: 50,
+            }),
+        );
+        first.record_call("EchoPlugin", Duration::from_millis(1), None);
+        first.energy_wh = 0.125;
+
+        let mut second = RunUsage::new("run-2", "transcribe");
+        second.record_call("WhisperPlugin", Duration::from_secs(10), None);
+        let mut old = RunUsage::new("run-0", "summarize");
+        old.started_at = Utc::now() - chrono::Duration::days(30);
+        old.record_call(
+            "OllamaPlugin",
+            Duration::from_secs(1),
+            Some(PluginUsage {
+                prompt_tokens: 1000,
+                completion_tokens: 0,
+            }),
+        );
+        vec![old, first, second]
+    }
+
+    #[test]
+    fn test_summarize_recent_runs() {
+        let summary = summarize(&history(), Some(Utc::now() - chrono::Duration::days(7)));
+        assert_eq!(summary.runs, 2);
+        assert_eq!(summary.total_tokens(), 150);
+        assert_eq!(summary.plugins["OllamaPlugin"].calls, 1);
+        assert_eq!(summary.workflows["summarize"].tokens, 150);
+        assert_eq!(summary.workflows_by_time()[0].0, "transcribe");
+        assert_eq!(summary.energy_wh, 0.125);
+    }
+
+    #[test]
+    fn test_summarize_all_runs() {
+        assert_eq!(summarize(&history(), None).total_tokens(), 1150);
+    }
+}
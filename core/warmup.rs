@@ -0,0 +1,294 @@
+// Model warm-up for workflow runs.
+use crate::cross_platform::PathUtils;
+use crate::logging;
+use crate::ollama;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+const DEFAULT_KEEP_ALIVE: &str = "30m";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelSpec {
+    /// Ollama model to load, e.g. `mistral`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ollama: Option<String>,
+    /// Model file to map into memory, e.g. whisper.cpp weights
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// How long Ollama keeps the model loaded, as an Ollama duration (default 30m)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    /// Steps that need the model; without any, it is loaded before the first step
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<String>,
+    /// Leave an Ollama model loaded after the run
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep: bool,
+}
+
+impl ModelSpec {
+    /// `ollama:<name>` or `file:<path>`
+    pub fn label(&self) -> String {
+        match (&self.ollama, &self.file) {
+            (Some(name), _) => format!("ollama:{}", name),
+            (None, Some(path)) => format!("file:{}", path),
+            (None, None) => "unnamed model".to_string(),
+        }
+    }
+
+    /// Why the spec can't be loaded, if it can't
+    pub fn problem(&self) -> Option<String> {
+        match (&self.ollama, &self.file) {
+            (Some(_), Some(_)) => Some(format!(
+                "model '{}' sets both ollama and file; declare them separately",
+                self.label()
+            )),
+            (None, None) => Some("model sets neither ollama nor file".to_string()),
+            _ => None,
+        }
+    }
+
+    fn needed_by(&self, step_id: &str) -> bool {
+        self.steps.is_empty() || self.steps.iter().any(|s| s == step_id)
+    }
+}
+
+enum Loaded {
+    Ollama(String),
+    // Held only to keep the pages mapped until the run ends
+    #[allow(dead_code)]
+    File(Mapping),
+}
+
+enum State {
+    Loading(JoinHandle<Result<Loaded, String>>),
+    Ready(Loaded),
+    Failed,
+}
+
+struct WarmModel {
+    spec: ModelSpec,
+    state: Mutex<Option<State>>,
+}
+
+/// Models being loaded for one run; dropping it releases them
+pub struct Warmup {
+    run_id: String,
+    models: Vec<WarmModel>,
+}
+
+impl Warmup {
+    /// Start loading `models` in the background
+    pub fn start(models: &[ModelSpec], run_id: &str) -> Self {
+        let models = models
+            .iter()
+            .filter(|spec| spec.problem().is_none())
+            .map(|spec| {
+                let load_spec = spec.clone();
+                let handle = thread::spawn(move || load(&load_spec));
+                WarmModel {
+                    spec: spec.clone(),
+                    state: Mutex::new(Some(State::Loading(handle))),
+                }
+            })
+            .collect();
+        Self {
+            run_id: run_id.to_string(),
+            models,
+        }
+    }
+
+    /// Block until the models `step_id` needs are loaded. Returns the labels
+    /// of those that failed to load.
+    pub fn wait_for(&self, step_id: &str) -> Vec<String> {
+        let mut failed = Vec::new();
+        for model in self.models.iter().filter(|m| m.spec.needed_by(step_id)) {
+            let mut state = model.state.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(State::Loading(handle)) = state.take_if(|s| matches!(s, State::Loading(_)))
+            {
+                let started = Instant::now();
+                let result = handle
+                    .join()
+                    .unwrap_or_else(|_| Err("warm-up thread panicked".to_string()));
+                *state = Some(match result {
+                    Ok(loaded) => {
+                        logging::info("model ready")
+                            .run(&self.run_id)
+                            .step(step_id)
+                            .field("model", model.spec.label())
+                            .field("waited_ms", started.elapsed().as_millis() as u64)
+                            .emit();
+                        State::Ready(loaded)
+                    }
+                    Err(e) => {
+                        logging::warn("model warm-up failed")
+                            .run(&self.run_id)
+                            .field("model", model.spec.label())
+                            .field("error", e)
+                            .emit();
+                        State::Failed
+                    }
+                });
+            }
+            if let Some(State::Failed) = state.as_ref() {
+                failed.push(model.spec.label());
+            }
+        }
+        failed
+    }
+}
+
+impl Drop for Warmup {
+    fn drop(&mut self) {
+        for model in &mut self.models {
+            let state = model
+                .state
+                .get_mut()
+                .unwrap_or_else(|e| e.into_inner())
+                .take();
+            let loaded = match state {
+                Some(State::Loading(handle)) => handle.join().ok().and_then(Result::ok),
+                Some(State::Ready(loaded)) => Some(loaded),
+                _ => None,
+            };
+            match loaded {
+                Some(Loaded::Ollama(name)) if !model.spec.keep => {
+                    if let Err(e) = unload_ollama(&name) {
+                        logging::warn("model release failed")
+                            .run(&self.run_id)
+                            .field("model", model.spec.label())
+                            .field("error", e)
+                            .emit();
+                    }
+                }
+                Some(loaded) => drop(loaded),
+                None => {}
+            }
+        }
+    }
+}
+
+fn load(spec: &ModelSpec) -> Result<Loaded, String> {
+    if let Some(name) = &spec.ollama {
+        let keep_alive = spec.keep_alive.as_deref().unwrap_or(DEFAULT_KEEP_ALIVE);
+        ollama::shared()?.post_json(
+            "/api/generate",
+            &serde_json::json!({ "model": name, "keep_alive": keep_alive }),
+        )?;
+        return Ok(Loaded::Ollama(name.clone()));
+    }
+    let path = PathUtils::normalize(Path::new(spec.file.as_deref().unwrap_or_default()));
+    map_file(&path)
+        .map(Loaded::File)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn unload_ollama(name: &str) -> Result<(), String> {
+    ollama::shared()?
+        .post_json(
+            "/api/generate",
+            &serde_json::json!({ "model": name, "keep_alive": 0 }),
+        )
+        .map(|_| ())
+}
+
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is read-only and only unmapped on drop
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn map_file(path: &Path) -> Result<Mapping, String> {
+    use std::os::unix::io::AsRawFd;
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len() as usize;
+    if len == 0 {
+        return Err("file is empty".to_string());
+    }
+    unsafe {
+        let ptr = libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        libc::madvise(ptr, len, libc::MADV_WILLNEED);
+        // WILLNEED only starts readahead; touching each page waits for it
+        let bytes = ptr as *const u8;
+        let mut sum = 0u8;
+        for offset in (0..len).step_by(4096) {
+            sum = sum.wrapping_add(std::ptr::read_volatile(bytes.add(offset)));
+        }
+        std::hint::black_box(sum);
+        Ok(Mapping { ptr, len })
+    }
+}
+
+// Without mmap, reading the file through still leaves it in the page cache
+#[cfg(not(unix))]
+struct Mapping;
+
+#[cfg(not(unix))]
+fn map_file(path: &Path) -> Result<Mapping, String> {
+    use std::io::Read;
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut buffer = vec![0u8; 1 << 20];
+    while file.read(&mut buffer).map_err(|e| e.to_string())? > 0 {}
+    Ok(Mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_models_warm_before_their_steps() {
+        let dir = std::env::temp_dir().join(format!("lao_warmup_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let weights = dir.join("ggml-tiny.bin");
+        std::fs::write(&weights, vec![7u8; 3 * 4096 + 5]).unwrap();
+
+        let models: Vec<ModelSpec> = serde_yaml::from_str(&format!(
+            "- file: {}\n  steps: [step2]\n- file: {}\n- ollama: mistral\n  file: both.bin\n",
+            weights.display(),
+            dir.join("missing.bin").display()
+        ))
+        .unwrap();
+        assert!(models[2].problem().is_some());
+
+        let warmup = Warmup::start(&models, "run-test");
+        assert_eq!(warmup.models.len(), 2);
+        // step1 needs only the model without steps, which doesn't exist
+        let failed = warmup.wait_for("step1");
+        assert_eq!(failed, vec![models[1].label()]);
+        assert!(warmup.wait_for("step2").len() == 1);
+        assert!(matches!(
+            *warmup.models[0].state.lock().unwrap(),
+            Some(State::Ready(Loaded::File(_)))
+        ));
+        drop(warmup);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,533 @@
+// WebAssembly plugins: WASI modules run by the host, with only what their
+// manifest grants.
+// Manifest grants are described in docs/PLUGIN_DEVELOPMENT.md (WebAssembly
+// Plugins).
+use lao_plugin_api::{PluginCapability, PluginInfo, PluginInputType, PluginOutputType};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MEMORY_MB: u64 = 256;
+
+/// Network a module may be granted
+pub const OLLAMA: &str = "ollama";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WasmManifest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, alias = "maintainer")]
+    pub author: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub input: Option<IoSpec>,
+    #[serde(default)]
+    pub output: Option<IoSpec>,
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct IoSpec {
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+}
+
+/// What a module may reach; everything else is denied
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Permissions {
+    /// Directories it may read, at the same path as on the host
+    #[serde(default)]
+    pub read: Vec<PathBuf>,
+    /// Directories it may read and write
+    #[serde(default)]
+    pub write: Vec<PathBuf>,
+    /// Services it may call; only `ollama`
+    #[serde(default)]
+    pub network: Vec<String>,
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+}
+
+impl Permissions {
+    pub fn ollama(&self) -> bool {
+        self.network.iter().any(|n| n == OLLAMA)
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        (self.memory_mb.unwrap_or(DEFAULT_MEMORY_MB) as usize).saturating_mul(1024 * 1024)
+    }
+}
+
+/// Whether `path` is a WebAssembly module
+pub fn is_module(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "wasm")
+}
+
+/// Where the manifest of `module` is: `<stem>.yaml` beside it, else `plugin.yaml`
+pub fn manifest_path(module: &Path) -> PathBuf {
+    let own = module.with_extension("yaml");
+    if own.is_file() {
+        return own;
+    }
+    module.with_file_name("plugin.yaml")
+}
+
+/// Read and check the manifest of `module`
+pub fn load_manifest(module: &Path) -> Result<WasmManifest, String> {
+    let path = manifest_path(module);
+    let text = std::fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "{} needs a manifest declaring its permissions at {}: {}",
+            module.display(),
+            path.display(),
+            e
+        )
+    })?;
+    let manifest: WasmManifest =
+        serde_yaml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+    if let Some(other) = manifest.permissions.network.iter().find(|n| *n != OLLAMA) {
+        return Err(format!(
+            "{}: unknown network permission '{}' (only {})",
+            path.display(),
+            other,
+            OLLAMA
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Plugin info of `module` from its manifest, named after the file if the
+/// manifest doesn't say
+pub fn info(module: &Path, manifest: &WasmManifest) -> PluginInfo {
+    let stem = module.file_stem().unwrap_or_default().to_string_lossy();
+    let name = manifest.name.clone().unwrap_or_else(|| stem.to_string());
+    // plugin.yaml spells types in lowercase
+    let kind = |spec: &Option<IoSpec>| {
+        let kind = spec
+            .as_ref()
+            .and_then(|s| s.kind.as_deref())
+            .unwrap_or("any");
+        let mut chars = kind.trim().chars();
+        let title: String = chars
+            .next()
+            .map(|c| c.to_ascii_uppercase())
+            .into_iter()
+            .chain(chars.map(|c| c.to_ascii_lowercase()))
+            .collect();
+        serde_json::Value::String(title)
+    };
+    let capabilities = vec![PluginCapability {
+        name: "run".to_string(),
+        description: manifest.description.clone(),
+        input_type: serde_json::from_value(kind(&manifest.input)).unwrap_or(PluginInputType::Any),
+        output_type: serde_json::from_value(kind(&manifest.output))
+            .unwrap_or(PluginOutputType::Any),
+    }];
+    let mut tags = manifest.tags.clone();
+    tags.push("wasm".to_string());
+    PluginInfo {
+        name,
+        version: manifest
+            .version
+            .clone()
+            .unwrap_or_else(|| "0.0.0".to_string()),
+        description: manifest.description.clone(),
+        author: manifest.author.clone(),
+        dependencies: Vec::new(),
+        tags,
+        capabilities,
+        input_schema: None,
+        output_schema: None,
+    }
+}
+
+/// The plugin for the WASI module at `path`
+pub fn open(path: &Path) -> Result<crate::plugins::PluginInstance, String> {
+    let manifest = load_manifest(path)?;
+    let info = info(path, &manifest);
+    open_with(path.to_path_buf(), info, manifest.permissions)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn open_with(
+    path: PathBuf,
+    _info: PluginInfo,
+    _permissions: Permissions,
+) -> Result<crate::plugins::PluginInstance, String> {
+    Err(format!(
+        "{} is a WebAssembly plugin; this build has no wasm support (build with --features wasm)",
+        path.display()
+    ))
+}
+
+#[cfg(feature = "wasm")]
+fn open_with(
+    path: PathBuf,
+    info: PluginInfo,
+    permissions: Permissions,
+) -> Result<crate::plugins::PluginInstance, String> {
+    let module = runtime::WasmModule::new(path, info.name.clone(), permissions);
+    Ok(crate::plugins::PluginInstance::native(info, move |input| {
+        module.run(input)
+    }))
+}
+
+#[cfg(feature = "wasm")]
+mod runtime {
+    use super::Permissions;
+    use lao_plugin_api::{STDERR, STDOUT};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+    use wasmtime::{
+        Caller, Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder,
+        UpdateDeadline,
+    };
+    use wasmtime_wasi::pipe::MemoryOutputPipe;
+    use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+    use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+
+    // Output a call may write to stdout and stderr each
+    const OUTPUT_CAPACITY: usize = 64 * 1024;
+
+    fn engine() -> &'static Engine {
+        static ENGINE: OnceLock<Engine> = OnceLock::new();
+        ENGINE.get_or_init(|| {
+            // Running modules check the epoch, so a stopped step can interrupt them
+            let mut config = Config::new();
+            config.epoch_interruption(true);
+            Engine::new(&config).expect("wasm engine config is valid")
+        })
+    }
+
+    struct State {
+        wasi: WasiP1Ctx,
+        limits: StoreLimits,
+        ollama: bool,
+    }
+
+    pub struct WasmModule {
+        path: PathBuf,
+        name: String,
+        permissions: Permissions,
+        module: OnceLock<Result<Module, String>>,
+    }
+
+    fn pack(ptr: i32, len: usize) -> i64 {
+        (((ptr as u32 as u64) << 32) | len as u32 as u64) as i64
+    }
+
+    fn unpack(packed: i64) -> (usize, usize) {
+        let packed = packed as u64;
+        ((packed >> 32) as usize, (packed & 0xFFFF_FFFF) as usize)
+    }
+
+    // Copy `bytes` into memory the module allocates, returning where
+    fn write_guest(caller: &mut Caller<'_, State>, bytes: &[u8]) -> wasmtime::Result<i64> {
+        let alloc = caller
+            .get_export("lao_alloc")
+            .and_then(|e| e.into_func())
+            .ok_or_else(|| wasmtime::Error::msg("module does not export lao_alloc"))?
+            .typed::<i32, i32>(&*caller)?;
+        let ptr = alloc.call(&mut *caller, bytes.len() as i32)?;
+        let memory = caller
+            .get_export("memory")
+            .and_then(|e| e.into_memory())
+            .ok_or_else(|| wasmtime::Error::msg("module does not export memory"))?;
+        memory.write(&mut *caller, ptr as u32 as usize, bytes)?;
+        Ok(pack(ptr, bytes.len()))
+    }
+
+    fn read_guest(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+        let memory = caller
+            .get_export("memory")
+            .and_then(|e| e.into_memory())
+            .ok_or_else(|| wasmtime::Error::msg("module does not export memory"))?;
+        let start = ptr as u32 as usize;
+        let bytes = memory
+            .data(&*caller)
+            .get(start..start + len as u32 as usize)
+            .ok_or_else(|| wasmtime::Error::msg("string out of bounds"))?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    impl WasmModule {
+        pub fn new(path: PathBuf, name: String, permissions: Permissions) -> Self {
+            Self {
+                path,
+                name,
+                permissions,
+                module: OnceLock::new(),
+            }
+        }
+
+        fn module(&self) -> Result<&Module, String> {
+            self.module
+                .get_or_init(|| {
+                    Module::from_file(engine(), &self.path)
+                        .map_err(|e| format!("cannot compile {}: {}", self.path.display(), e))
+                })
+                .as_ref()
+                .map_err(|e| e.clone())
+        }
+
+        fn linker(&self) -> Result<Linker<State>, String> {
+            let mut linker = Linker::new(engine());
+            preview1::add_to_linker_sync(&mut linker, |state: &mut State| &mut state.wasi)
+                .map_err(|e| e.to_string())?;
+            let name = self.name.clone();
+            linker
+                .func_wrap(
+                    "lao",
+                    "ollama_request",
+                    move |mut caller: Caller<'_, State>,
+                          method: i32,
+                          method_len: i32,
+                          path: i32,
+                          path_len: i32,
+                          body: i32,
+                          body_len: i32|
+                          -> wasmtime::Result<i64> {
+                        let (status, reply) = if caller.data().ollama {
+                            let method = read_guest(&mut caller, method, method_len)?;
+                            let path = read_guest(&mut caller, path, path_len)?;
+                            let body = read_guest(&mut caller, body, body_len)?;
+                            crate::ollama::plugin_request(&method, &path, Some(&body))
+                        } else {
+                            let denied = format!(
+                                "{} is not granted network: [{}] in its manifest",
+                                name,
+                                super::OLLAMA
+                            );
+                            (0, denied)
+                        };
+                        let reply = serde_json::json!({ "status": status, "body": reply });
+                        write_guest(&mut caller, reply.to_string().as_bytes())
+                    },
+                )
+                .map_err(|e| e.to_string())?;
+            Ok(linker)
+        }
+
+        fn context(
+            &self,
+            stdout: &MemoryOutputPipe,
+            stderr: &MemoryOutputPipe,
+        ) -> Result<WasiP1Ctx, String> {
+            let mut builder = WasiCtxBuilder::new();
+            builder
+                .arg(&self.name)
+                .stdout(stdout.clone())
+                .stderr(stderr.clone());
+            let grants = self
+                .permissions
+                .read
+                .iter()
+                .map(|dir| (dir, DirPerms::READ, FilePerms::READ))
+                .chain(
+                    self.permissions
+                        .write
+                        .iter()
+                        .map(|dir| (dir, DirPerms::all(), FilePerms::all())),
+                );
+            for (dir, dir_perms, file_perms) in grants {
+                builder
+                    .preopened_dir(dir, dir.to_string_lossy(), dir_perms, file_perms)
+                    .map_err(|e| format!("cannot grant {}: {}", dir.display(), e))?;
+            }
+            Ok(builder.build_p1())
+        }
+
+        /// Call `lao_run` with `input` in a fresh instance
+        pub fn run(&self, input: &str) -> Result<String, String> {
+            let module = self.module()?;
+            let (stdout, stderr) = (
+                MemoryOutputPipe::new(OUTPUT_CAPACITY),
+                MemoryOutputPipe::new(OUTPUT_CAPACITY),
+            );
+            let state = State {
+                wasi: self.context(&stdout, &stderr)?,
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.permissions.memory_bytes())
+                    .build(),
+                ollama: self.permissions.ollama(),
+            };
+            let mut store = Store::new(engine(), state);
+            store.limiter(|state| &mut state.limits);
+            // The epoch is shared by all stores, so others just keep going
+            let stopped = Arc::new(AtomicBool::new(false));
+            store.set_epoch_deadline(1);
+            store.epoch_deadline_callback({
+                let stopped = stopped.clone();
+                move |_| match stopped.load(Ordering::SeqCst) {
+                    true => Err(wasmtime::Error::msg("stopped")),
+                    false => Ok(UpdateDeadline::Continue(1)),
+                }
+            });
+            let _stop = crate::cancel::on_stop(move || {
+                stopped.store(true, Ordering::SeqCst);
+                engine().increment_epoch();
+            });
+            let result = self.call(module, &mut store, input);
+            for (stream, pipe) in [(STDOUT, &stdout), (STDERR, &stderr)] {
+                let text = String::from_utf8_lossy(&pipe.contents()).into_owned();
+                for line in text.lines() {
+                    crate::process_output::record(stream, line);
+                }
+            }
+            result.map_err(|e| format!("{} failed: {}", self.name, e))
+        }
+
+        fn call(
+            &self,
+            module: &Module,
+            store: &mut Store<State>,
+            input: &str,
+        ) -> wasmtime::Result<String> {
+            let linker = self.linker().map_err(wasmtime::Error::msg)?;
+            let instance = linker.instantiate(&mut *store, module)?;
+            if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut *store, "_initialize") {
+                initialize.call(&mut *store, ())?;
+            }
+            let memory = instance
+                .get_memory(&mut *store, "memory")
+                .ok_or_else(|| wasmtime::Error::msg("module does not export memory"))?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "lao_alloc")?;
+            let run = instance.get_typed_func::<(i32, i32), i64>(&mut *store, "lao_run")?;
+            let ptr = alloc.call(&mut *store, input.len() as i32)?;
+            memory.write(&mut *store, ptr as u32 as usize, input.as_bytes())?;
+            let (start, len) = unpack(run.call(&mut *store, (ptr, input.len() as i32))?);
+            let output = memory
+                .data(&*store)
+                .get(start..start + len)
+                .ok_or_else(|| wasmtime::Error::msg("output out of bounds"))?;
+            String::from_utf8(output.to_vec())
+                .map_err(|_| wasmtime::Error::msg("output is not valid UTF-8"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temp dir with a plugin.yaml for echo.wasm that grants reading data/.
+    fn echo_dir() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("lao_wasm_{}", uuid::Uuid::new_v4()));
+        let data = dir.join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(
+            dir.join("plugin.yaml"),
+            format!(
+                "name: WasmEcho\nversion: 1.2.0\ninput: {{type: text}}\noutput: {{type: json}}\npermissions:\n  read: [{}]\n  memory_mb: 16\n",
+                data.display()
+            ),
+        )
+        .unwrap();
+        let module = dir.join("echo.wasm");
+        (dir, module)
+    }
+
+    #[test]
+    fn test_wasm_manifests_declare_what_modules_may_reach() {
+        let (dir, module) = echo_dir();
+        let manifest = load_manifest(&module).unwrap();
+        assert!(!manifest.permissions.ollama());
+        assert_eq!(manifest.permissions.memory_bytes(), 16 * 1024 * 1024);
+        let plugin_info = info(&module, &manifest);
+        assert_eq!(plugin_info.name, "WasmEcho");
+        assert!(plugin_info.tags.contains(&"wasm".to_string()));
+        assert_eq!(
+            plugin_info.capabilities[0].output_type,
+            PluginOutputType::Json
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_unknown_permissions_are_errors() {
+        let (dir, module) = echo_dir();
+        std::fs::write(
+            dir.join("echo.yaml"),
+            "permissions:\n  network: [internet]\n",
+        )
+        .unwrap();
+        assert!(load_manifest(&module)
+            .unwrap_err()
+            .contains("unknown network permission 'internet'"));
+        std::fs::write(dir.join("echo.yaml"), "permissions:\n  exec: true\n").unwrap();
+        assert!(load_manifest(&module).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn test_modules_only_reach_what_they_are_granted() {
+        let (dir, module) = echo_dir();
+        // Echoes its input, then asks for Ollama without the grant
+        std::fs::write(
+            &module,
+            r#"(module
+              (import "lao" "ollama_request" (func $ollama (param i32 i32 i32 i32 i32 i32) (result i64)))
+              (memory (export "memory") 1)
+              (global $next (mut i32) (i32.const 1024))
+              (func (export "lao_alloc") (param $len i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $next))
+                (global.set $next (i32.add (global.get $next) (local.get $len)))
+                (local.get $ptr))
+              (func (export "lao_run") (param $ptr i32) (param $len i32) (result i64)
+                (if (i32.eq (local.get $len) (i32.const 6))
+                  (then (return (call $ollama (i32.const 0) (i32.const 4) (i32.const 0) (i32.const 4) (i32.const 0) (i32.const 0)))))
+                (i64.or
+                  (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                  (i64.extend_i32_u (local.get $len)))))"#,
+        )
+        .unwrap();
+        let plugin = open(&module).unwrap();
+        assert_eq!(plugin.call("hello wasm").unwrap(), "hello wasm");
+        let denied: serde_json::Value =
+            serde_json::from_str(&plugin.call("ollama").unwrap()).unwrap();
+        assert_eq!(denied["status"], 0);
+        assert!(denied["body"]
+            .as_str()
+            .unwrap()
+            .contains("not granted network: [ollama]"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn test_stopped_step_interrupts_a_looping_module() {
+        let dir = std::env::temp_dir().join(format!("lao_wasm_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let module = dir.join("spin.wasm");
+        std::fs::write(dir.join("plugin.yaml"), "name: WasmSpin\nversion: 0.1.0\n").unwrap();
+        std::fs::write(
+            &module,
+            r#"(module
+              (memory (export "memory") 1)
+              (func (export "lao_alloc") (param i32) (result i32) (i32.const 1024))
+              (func (export "lao_run") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0)))"#,
+        )
+        .unwrap();
+        let plugin = open(&module).unwrap();
+        let start = std::time::Instant::now();
+        let result = {
+            let _step =
+                crate::cancel::StepScope::enter(Some(std::time::Duration::from_millis(200)));
+            plugin.call("spin")
+        };
+        assert!(result.unwrap_err().contains("failed"));
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
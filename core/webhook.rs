@@ -0,0 +1,281 @@
+// Webhook trigger for the daemon.
+// Routes and `response:` blocks are described in docs/workflows.md (Webhook
+// Triggers).
+use crate::run_labels;
+use crate::{load_workflow_yaml, run_workflow_yaml_with_inputs, ResponseSpec, StepLog};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Request bodies larger than this are rejected with 413
+pub const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: String,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub(crate) fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: body.into(),
+        }
+    }
+
+    pub(crate) fn json(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json".to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Serve webhook triggers for workflows in `workflows_dir` on a background thread
+pub fn serve<A: ToSocketAddrs>(
+    addr: A,
+    workflows_dir: impl Into<PathBuf>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    let workflows_dir = workflows_dir.into();
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Workflows can take a while, so each request gets its own thread
+            let workflows_dir = workflows_dir.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &workflows_dir) {
+                    log::warn!("webhook request failed: {}", e);
+                }
+            });
+        }
+    }))
+}
+
+fn handle_connection(mut stream: TcpStream, workflows_dir: &Path) -> std::io::Result<()> {
+    let response = match read_request(&stream)? {
+        Err(rejected) => rejected,
+        Ok(request) => match (request.method.as_str(), hook_name(&request.path)) {
+            ("POST", Some(name)) => trigger(
+                workflows_dir,
+                name,
+                String::from_utf8_lossy(&request.body).into(),
+            ),
+            (_, Some(_)) => HttpResponse::text(405, "use POST\n"),
+            (_, None) => HttpResponse::text(404, "not found\n"),
+        },
+    };
+    write_response(&mut stream, &response)
+}
+
+pub(crate) struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Read one request, or the response rejecting it when the body is too large
+pub(crate) fn read_request(
+    stream: &TcpStream,
+) -> std::io::Result<Result<HttpRequest, HttpResponse>> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY_BYTES {
+        return Ok(Err(HttpResponse::text(413, "request body too large\n")));
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let mut body = Vec::new();
+    if method == "POST" {
+        body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+    }
+    Ok(Ok(HttpRequest {
+        method,
+        path,
+        headers,
+        body,
+    }))
+}
+
+pub(crate) fn write_response(
+    stream: &mut TcpStream,
+    response: &HttpResponse,
+) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+// `/hooks/<name>`, where name is a plain file stem so requests can't leave the directory
+fn hook_name(path: &str) -> Option<&str> {
+    let path = path.split('?').next().unwrap_or(path);
+    path.strip_prefix("/hooks/").filter(|name| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// Run the named workflow with `body` as `${request}` and build its response
+pub fn trigger(workflows_dir: &Path, name: &str, body: String) -> HttpResponse {
+    let path = workflows_dir.join(format!("{}.yaml", name));
+    if !path.is_file() {
+        return HttpResponse::text(404, format!("no workflow named {}\n", name));
+    }
+    if crate::daemon::triggers_paused() {
+        return HttpResponse::text(503, "triggers are paused\n");
+    }
+    let path = path.to_string_lossy().to_string();
+    let workflow = match load_workflow_yaml(&path) {
+        Ok(workflow) => workflow,
+        Err(e) => return HttpResponse::text(500, format!("failed to load workflow: {}\n", e)),
+    };
+    crate::metrics::global().record_trigger("webhook");
+    crate::logging::info("webhook triggered workflow")
+        .field("workflow", workflow.workflow.clone())
+        .field("path", path.clone())
+        .emit();
+
+    let inputs = HashMap::from([("request".to_string(), body)]);
+    match run_labels::with_trigger("webhook", || run_workflow_yaml_with_inputs(&path, inputs)) {
+        Ok(logs) => build_response(workflow.response.as_ref(), &logs),
+        Err(e) => HttpResponse::text(500, format!("workflow failed: {}\n", e)),
+    }
+}
+
+/// The HTTP response for a finished run, following the workflow's `response:` block
+pub fn build_response(spec: Option<&ResponseSpec>, logs: &[StepLog]) -> HttpResponse {
+    let Some(spec) = spec else {
+        let failed: Vec<&str> = logs
+            .iter()
+            .filter(|log| log.error.is_some())
+            .map(|log| log.step_id.as_str())
+            .collect();
+        let status = if failed.is_empty() { 200 } else { 500 };
+        return HttpResponse::json(
+            status,
+            serde_json::json!({
+                "status": if failed.is_empty() { "success" } else { "error" },
+                "steps": logs.len(),
+                "failed_steps": failed,
+            }),
+        );
+    };
+
+    match logs.iter().find(|log| log.step_id == spec.step) {
+        Some(StepLog {
+            output: Some(output),
+            error: None,
+            ..
+        }) => HttpResponse {
+            status: spec.status,
+            content_type: spec.content_type.clone(),
+            body: output.clone(),
+        },
+        Some(StepLog {
+            error: Some(error), ..
+        }) => HttpResponse::text(500, format!("{} failed: {}\n", spec.step, error)),
+        _ => HttpResponse::text(500, format!("{} produced no output\n", spec.step)),
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(step_id: &str, output: Option<&str>, error: Option<&str>) -> StepLog {
+        StepLog {
+            step: 0,
+            step_id: step_id.to_string(),
+            runner: "EchoPlugin".to_string(),
+            input: serde_yaml::Value::Null,
+            output: output.map(str::to_string),
+            error: error.map(str::to_string),
+            artifact: None,
+            attempt: 1,
+            input_type: None,
+            output_type: None,
+            validation: None,
+        }
+    }
+
+    #[test]
+    fn test_build_response_uses_designated_step() {
+        let spec: ResponseSpec =
+            serde_yaml::from_str("step: step2\nstatus: 201\ncontent_type: application/json")
+                .unwrap();
+        let logs = vec![
+            log("step1", Some("raw"), None),
+            log("step2", Some("{\"ok\":true}"), None),
+        ];
+        let response = build_response(Some(&spec), &logs);
+        assert_eq!(response.status, 201);
+        assert_eq!(response.content_type, "application/json");
+        assert_eq!(response.body, "{\"ok\":true}");
+
+        let logs = vec![
+            log("step1", Some("raw"), None),
+            log("step2", None, Some("boom")),
+        ];
+        assert_eq!(build_response(Some(&spec), &logs).status, 500);
+
+        let spec: ResponseSpec = serde_yaml::from_str("step: step1").unwrap();
+        assert_eq!(spec.status, 200);
+        assert_eq!(build_response(None, &logs).status, 500);
+        assert_eq!(hook_name("/hooks/summarize?x=1"), Some("summarize"));
+        assert_eq!(hook_name("/hooks/../secrets"), None);
+    }
+}
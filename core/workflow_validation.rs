@@ -0,0 +1,337 @@
+// Incremental workflow validation for the YAML editor and `lao validate --watch`.
+use crate::extends;
+use crate::plan::ExecutionPlan;
+use crate::plugins::PluginRegistry;
+use crate::strict;
+use crate::Workflow;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    /// Index of the step this refers to, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, severity, self.message
+        )
+    }
+}
+
+struct DocumentState {
+    hash: u64,
+    registry: Weak<PluginRegistry>,
+    diagnostics: Arc<Vec<Diagnostic>>,
+}
+
+/// Cached diagnostics for a set of open workflow documents
+#[derive(Default)]
+pub struct ValidationService {
+    documents: HashMap<String, DocumentState>,
+    lenient: bool,
+}
+
+impl ValidationService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A service that reports unknown fields as warnings
+    pub fn lenient() -> Self {
+        Self {
+            lenient: true,
+            ..Self::default()
+        }
+    }
+
+    /// Diagnostics for document `id` with contents `text`. Returns the cached
+    /// result if neither `text` nor `registry` changed since the last call.
+    pub fn update(
+        &mut self,
+        id: &str,
+        text: &str,
+        registry: &Arc<PluginRegistry>,
+    ) -> Arc<Vec<Diagnostic>> {
+        let hash = text_hash(text);
+        if let Some(state) = self.documents.get(id) {
+            if state.hash == hash && Weak::ptr_eq(&state.registry, &Arc::downgrade(registry)) {
+                return state.diagnostics.clone();
+            }
+        }
+        let dir = Path::new(id).parent().unwrap_or(Path::new(""));
+        let diagnostics = Arc::new(validate_text(text, dir, registry, !self.lenient));
+        self.documents.insert(
+            id.to_string(),
+            DocumentState {
+                hash,
+                registry: Arc::downgrade(registry),
+                diagnostics: diagnostics.clone(),
+            },
+        );
+        diagnostics
+    }
+
+    /// Read `path` and `update` the document keyed by it
+    pub fn update_file(
+        &mut self,
+        path: &Path,
+        registry: &Arc<PluginRegistry>,
+    ) -> Result<Arc<Vec<Diagnostic>>, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Ok(self.update(&path.to_string_lossy(), &text, registry))
+    }
+
+    /// Last diagnostics computed for `id`
+    pub fn diagnostics(&self, id: &str) -> Option<Arc<Vec<Diagnostic>>> {
+        self.documents.get(id).map(|s| s.diagnostics.clone())
+    }
+
+    /// Forget a document, e.g. when its editor tab closes
+    pub fn close(&mut self, id: &str) {
+        self.documents.remove(id);
+    }
+}
+
+/// Parse and validate workflow YAML without caching. A workflow that
+/// `extends` another is checked merged with it, its base found in `dir`.
+/// Unknown fields are errors if `strict`, warnings otherwise.
+pub fn validate_text(
+    text: &str,
+    dir: &Path,
+    registry: &PluginRegistry,
+    strict: bool,
+) -> Vec<Diagnostic> {
+    let parsed = match extends::resolve(text, dir) {
+        Ok(Some(merged)) => serde_yaml::from_value::<Workflow>(merged),
+        Ok(None) => serde_yaml::from_str::<Workflow>(text),
+        Err(message) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                line: 1,
+                column: 1,
+                step: None,
+                message,
+            }];
+        }
+    };
+    let workflow = match parsed {
+        Ok(w) => w,
+        Err(e) => {
+            let (line, column) = e
+                .location()
+                .map(|loc| (loc.line(), loc.column()))
+                .unwrap_or((1, 1));
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                line,
+                column,
+                step: None,
+                message: e.to_string(),
+            }];
+        }
+    };
+
+    // Steps of this file come after any inherited ones
+    let mut positions = step_positions(text);
+    let inherited = workflow.steps.len().saturating_sub(positions.len());
+    positions.splice(0..0, std::iter::repeat_n((1, 1), inherited));
+    let unknown_severity = if strict {
+        Severity::Error
+    } else {
+        Severity::Warning
+    };
+    let document = serde_yaml::from_str(text).unwrap_or(serde_yaml::Value::Null);
+    let mut diagnostics: Vec<Diagnostic> = strict::unknown_fields(&document)
+        .into_iter()
+        .map(|field| {
+            let step = field.step.map(|i| i + inherited);
+            let (line, column) = key_position(text, &field, step.and_then(|i| positions.get(i)));
+            Diagnostic {
+                severity: unknown_severity,
+                line,
+                column,
+                step,
+                message: field.to_string(),
+            }
+        })
+        .collect();
+    let plan = ExecutionPlan::compile(workflow, registry);
+    let issues = plan
+        .errors
+        .iter()
+        .map(|e| (Severity::Error, e))
+        .chain(plan.warnings.iter().map(|w| (Severity::Warning, w)));
+    diagnostics.extend(issues.map(|(severity, issue)| {
+        let (line, column) = issue
+            .step
+            .and_then(|i| positions.get(i).copied())
+            .unwrap_or((1, 1));
+        Diagnostic {
+            severity,
+            line,
+            column,
+            step: issue.step,
+            message: issue.message.clone(),
+        }
+    }));
+    diagnostics
+}
+
+// 1-based (line, column) of an unknown field's key: the first line after its
+// step's or section's start that sets it, or one at the top level
+fn key_position(
+    text: &str,
+    field: &strict::UnknownField,
+    step: Option<&(usize, usize)>,
+) -> (usize, usize) {
+    let section_start = field.section.and_then(|section| {
+        text.lines()
+            .position(|line| line.starts_with(&format!("{}:", section)))
+    });
+    let start = match (step, section_start) {
+        (Some(&(line, _)), _) => line - 1,
+        (None, Some(line)) => line + 1,
+        (None, None) => 0,
+    };
+    let nested = step.is_some() || section_start.is_some();
+    let prefix = format!("{}:", field.key);
+    text.lines()
+        .enumerate()
+        .skip(start)
+        .find_map(|(n, line)| {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            let key = trimmed.strip_prefix("- ").unwrap_or(trimmed);
+            let column = indent + trimmed.len() - key.len() + 1;
+            (key.starts_with(&prefix) && (column > 1) == nested).then_some((n + 1, column))
+        })
+        .unwrap_or((1, 1))
+}
+
+// 1-based (line, column) of each entry in the top-level `steps:` sequence
+pub(crate) fn step_positions(text: &str) -> Vec<(usize, usize)> {
+    let mut positions = Vec::new();
+    let mut in_steps = false;
+    let mut item_indent = None;
+    for (n, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+        if indent == 0 && !trimmed.starts_with('-') {
+            in_steps = trimmed.starts_with("steps:");
+            continue;
+        }
+        if !in_steps || !(trimmed == "-" || trimmed.starts_with("- ")) {
+            continue;
+        }
+        if *item_indent.get_or_insert(indent) == indent {
+            positions.push((n + 1, indent + 1));
+        }
+    }
+    positions
+}
+
+fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEMO: &str =
+        "workflow: demo\nsteps:\n  - run: Echo\n  - run: Echo\n    input_from: step9\n";
+    const BROKEN: &str = "workflow: demo\nsteps: [\n";
+    const TYPO: &str =
+        "workflow: demo\nsteps:\n  - run: Echo\n  - run: Echo\n    input_form: step1\n";
+
+    #[test]
+    fn test_diagnostics_point_at_steps() {
+        let registry = Arc::new(PluginRegistry::new());
+        let first = ValidationService::new().update("demo.yaml", DEMO, &registry);
+        assert!(first
+            .iter()
+            .any(|d| d.severity == Severity::Warning && d.step == Some(1) && d.line == 4));
+        assert!(first
+            .iter()
+            .any(|d| d.severity == Severity::Error && d.line == 3 && d.column == 3));
+    }
+
+    #[test]
+    fn test_unchanged_documents_are_not_revalidated() {
+        let registry = Arc::new(PluginRegistry::new());
+        let mut service = ValidationService::new();
+        let first = service.update("demo.yaml", DEMO, &registry);
+        assert!(Arc::ptr_eq(
+            &first,
+            &service.update("demo.yaml", DEMO, &registry)
+        ));
+    }
+
+    #[test]
+    fn test_unparsable_document_is_one_diagnostic() {
+        let registry = Arc::new(PluginRegistry::new());
+        let broken = ValidationService::new().update("demo.yaml", BROKEN, &registry);
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].step, None);
+        assert!(broken[0].line >= 2);
+    }
+
+    #[test]
+    fn test_new_registry_revalidates() {
+        let registry = Arc::new(PluginRegistry::new());
+        let reloaded = Arc::new(PluginRegistry::new());
+        let mut service = ValidationService::new();
+        let broken = service.update("demo.yaml", BROKEN, &registry);
+        let again = service.update("demo.yaml", BROKEN, &reloaded);
+        assert!(!Arc::ptr_eq(&broken, &again));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_errors_unless_lenient() {
+        let registry = Arc::new(PluginRegistry::new());
+        let strict = ValidationService::new().update("typo.yaml", TYPO, &registry);
+        let unknown = strict
+            .iter()
+            .find(|d| d.message.contains("did you mean `input_from`?"))
+            .unwrap();
+        assert_eq!(
+            (unknown.severity, unknown.line, unknown.column, unknown.step),
+            (Severity::Error, 5, 5, Some(1))
+        );
+        let lenient = ValidationService::lenient().update("typo.yaml", TYPO, &registry);
+        assert!(lenient
+            .iter()
+            .any(|d| d.severity == Severity::Warning && d.message.contains("input_form")));
+    }
+}
@@ -0,0 +1,68 @@
+/*
+ * C API of the LAO orchestrator (liblao_core_ffi).
+ *
+ * Strings are UTF-8 and NUL-terminated. Strings returned by the library are
+ * owned by the caller and freed with lao_string_free. Params, events and
+ * outcomes are JSON. A failed call returns NULL, 0 or an error kind;
+ * lao_last_error and lao_last_error_kind describe it, per calling thread.
+ */
+#ifndef LAO_CORE_H
+#define LAO_CORE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Error kinds */
+#define LAO_OK 0
+#define LAO_ERR_ARGUMENT 1
+#define LAO_ERR_LOAD 2
+#define LAO_ERR_INVALID 3
+#define LAO_ERR_RUN 4
+#define LAO_ERR_CANCELLED 5
+#define LAO_ERR_UNKNOWN_RUN 6
+
+/* Run states returned by lao_run_status; -1 for an unknown run */
+#define LAO_RUN_RUNNING 0
+#define LAO_RUN_FINISHED 1
+
+typedef struct LaoOrchestrator LaoOrchestrator;
+
+/* Called with each event of a run as JSON, valid only during the call */
+typedef void (*LaoEventCallback)(const char *event_json, void *user_data);
+
+/* plugin_dir NULL uses the default plugin directory */
+LaoOrchestrator *lao_orchestrator_new(const char *plugin_dir);
+void lao_orchestrator_free(LaoOrchestrator *orchestrator);
+
+/* Parse and check a workflow; LAO_OK or an error kind */
+int32_t lao_validate(const LaoOrchestrator *orchestrator, const char *path);
+
+/* Run to the end on this thread; the outcome as JSON, or NULL */
+char *lao_run(const LaoOrchestrator *orchestrator, const char *path, const char *params_json);
+
+/* Start in the background; a run id, or 0. With a callback, events go to it
+ * on the run's thread instead of the lao_poll queue. */
+uint64_t lao_submit(const LaoOrchestrator *orchestrator, const char *path,
+                    const char *params_json, LaoEventCallback callback, void *user_data);
+
+/* Next queued event, waiting up to timeout_ms; NULL when there is none */
+char *lao_poll(const LaoOrchestrator *orchestrator, uint64_t run, uint32_t timeout_ms);
+int32_t lao_run_status(const LaoOrchestrator *orchestrator, uint64_t run);
+/* Start no further steps; LAO_OK or an error kind */
+int32_t lao_cancel(const LaoOrchestrator *orchestrator, uint64_t run);
+/* Wait for the run, release it and return its outcome as JSON, or NULL */
+char *lao_wait(const LaoOrchestrator *orchestrator, uint64_t run);
+
+/* Valid until the next call on this thread; not to be freed */
+const char *lao_last_error(void);
+int32_t lao_last_error_kind(void);
+void lao_string_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* LAO_CORE_H */
@@ -0,0 +1,583 @@
+// C API for embedding LAO in other languages; include/lao_core.h declares
+// the functions and their ownership rules.
+use lao_orchestrator_core::cancel::CancelToken;
+use lao_orchestrator_core::plugins::PluginRegistry;
+use lao_orchestrator_core::{Orchestrator, OrchestratorError, RunOutcome};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+// Error kinds, as in lao_core.h
+pub const LAO_OK: i32 = 0;
+pub const LAO_ERR_ARGUMENT: i32 = 1;
+pub const LAO_ERR_LOAD: i32 = 2;
+pub const LAO_ERR_INVALID: i32 = 3;
+pub const LAO_ERR_RUN: i32 = 4;
+pub const LAO_ERR_CANCELLED: i32 = 5;
+pub const LAO_ERR_UNKNOWN_RUN: i32 = 6;
+
+// Run states returned by `lao_run_status`
+pub const LAO_RUN_RUNNING: i32 = 0;
+pub const LAO_RUN_FINISHED: i32 = 1;
+
+/// Called with each event of a run as JSON, valid only during the call
+pub type LaoEventCallback =
+    Option<extern "C" fn(event_json: *const c_char, user_data: *mut c_void)>;
+
+struct Error {
+    kind: i32,
+    message: String,
+}
+
+impl Error {
+    fn argument(message: impl Into<String>) -> Self {
+        Self {
+            kind: LAO_ERR_ARGUMENT,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<OrchestratorError> for Error {
+    fn from(e: OrchestratorError) -> Self {
+        let kind = match &e {
+            OrchestratorError::Load(_) => LAO_ERR_LOAD,
+            OrchestratorError::Invalid(_) => LAO_ERR_INVALID,
+            OrchestratorError::Cancelled => LAO_ERR_CANCELLED,
+            _ => LAO_ERR_RUN,
+        };
+        Self {
+            kind,
+            message: e.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<(i32, CString)> = RefCell::new((LAO_OK, CString::default()));
+}
+
+// Run `f`, turning its error or panic into this thread's last error
+fn guard<T>(fallback: T, f: impl FnOnce() -> Result<T, Error>) -> T {
+    LAST_ERROR.with(|e| *e.borrow_mut() = (LAO_OK, CString::default()));
+    let error = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(error)) => error,
+        Err(_) => Error {
+            kind: LAO_ERR_RUN,
+            message: "panic inside lao_core_ffi".to_string(),
+        },
+    };
+    LAST_ERROR.with(|e| *e.borrow_mut() = (error.kind, c_string(error.message)));
+    fallback
+}
+
+fn c_string(text: String) -> CString {
+    CString::new(text.replace('\0', " ")).unwrap_or_default()
+}
+
+unsafe fn text<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, Error> {
+    if ptr.is_null() {
+        return Err(Error::argument(format!("{} is NULL", what)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| Error::argument(format!("{} is not UTF-8", what)))
+}
+
+// A JSON object of params; NULL or "" for none. Non-string values are
+// passed as their JSON text.
+unsafe fn params(json: *const c_char) -> Result<HashMap<String, String>, Error> {
+    if json.is_null() {
+        return Ok(HashMap::new());
+    }
+    let json = text(json, "params_json")?;
+    if json.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+    let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)
+        .map_err(|e| Error::argument(format!("params_json is not a JSON object: {}", e)))?;
+    Ok(object
+        .into_iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(s) => (name, s),
+            other => (name, other.to_string()),
+        })
+        .collect())
+}
+
+fn outcome_json(outcome: RunOutcome) -> Result<String, Error> {
+    serde_json::to_string(&outcome).map_err(|e| Error {
+        kind: LAO_ERR_RUN,
+        message: format!("cannot serialize run outcome: {}", e),
+    })
+}
+
+/// Opaque to C
+pub struct LaoOrchestrator {
+    inner: Orchestrator,
+    runs: Mutex<HashMap<u64, Arc<Run>>>,
+    next_id: AtomicU64,
+}
+
+impl LaoOrchestrator {
+    pub fn new(inner: Orchestrator) -> Self {
+        Self {
+            inner,
+            runs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn run(&self, id: u64) -> Result<Arc<Run>, Error> {
+        self.runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Error {
+                kind: LAO_ERR_UNKNOWN_RUN,
+                message: format!("no run {}", id),
+            })
+    }
+}
+
+struct Run {
+    state: Mutex<RunState>,
+    changed: Condvar,
+    cancel: CancelToken,
+}
+
+#[derive(Default)]
+struct RunState {
+    // Events not polled yet, as JSON
+    events: VecDeque<String>,
+    outcome: Option<Result<String, Error>>,
+}
+
+impl Run {
+    fn lock(&self) -> MutexGuard<'_, RunState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut RunState)) {
+        f(&mut self.lock());
+        self.changed.notify_all();
+    }
+}
+
+// The callback and its user data go to the run's thread; the caller
+// promises they stay valid until the run has finished
+struct Callback {
+    call: extern "C" fn(*const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+unsafe impl Send for Callback {}
+
+/// Create an orchestrator. With a `plugin_dir`, runs use the plugins in it;
+/// with NULL, the process-wide registry (the default plugin directory).
+///
+/// # Safety
+/// `plugin_dir` is NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lao_orchestrator_new(plugin_dir: *const c_char) -> *mut LaoOrchestrator {
+    guard(ptr::null_mut(), || {
+        let inner = if plugin_dir.is_null() {
+            Orchestrator::new()
+        } else {
+            let dir = text(plugin_dir, "plugin_dir")?;
+            Orchestrator::with_registry(PluginRegistry::dynamic_registry(dir))
+        };
+        Ok(Box::into_raw(Box::new(LaoOrchestrator::new(inner))))
+    })
+}
+
+/// Free an orchestrator; runs still going finish on their own.
+///
+/// # Safety
+/// `orchestrator` is NULL or came from `lao_orchestrator_new` and is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn lao_orchestrator_free(orchestrator: *mut LaoOrchestrator) {
+    if !orchestrator.is_null() {
+        drop(Box::from_raw(orchestrator));
+    }
+}
+
+unsafe fn resolve<'a>(ptr: *const LaoOrchestrator) -> Result<&'a LaoOrchestrator, Error> {
+    ptr.as_ref()
+        .ok_or_else(|| Error::argument("orchestrator is NULL"))
+}
+
+/// Parse and check the workflow at `path`; LAO_OK or an error kind.
+///
+/// # Safety
+/// `orchestrator` came from `lao_orchestrator_new`; `path` is a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn lao_validate(
+    orchestrator: *const LaoOrchestrator,
+    path: *const c_char,
+) -> i32 {
+    let result = guard(false, || {
+        resolve(orchestrator)?
+            .inner
+            .load(text(path, "path")?)?;
+        Ok(true)
+    });
+    if result {
+        LAO_OK
+    } else {
+        lao_last_error_kind()
+    }
+}
+
+/// Run the workflow at `path` on this thread and return the outcome as JSON.
+///
+/// # Safety
+/// `orchestrator` came from `lao_orchestrator_new`; `path` is a NUL-terminated
+/// string and `params_json` NULL or one.
+#[no_mangle]
+pub unsafe extern "C" fn lao_run(
+    orchestrator: *const LaoOrchestrator,
+    path: *const c_char,
+    params_json: *const c_char,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let orchestrator = resolve(orchestrator)?;
+        let outcome = orchestrator
+            .inner
+            .run(text(path, "path")?, params(params_json)?)?;
+        Ok(c_string(outcome_json(outcome)?).into_raw())
+    })
+}
+
+/// Start the workflow at `path` in the background and return its run id, or
+/// 0 if it can't start. With a `callback`, events go to it instead of the
+/// `lao_poll` queue.
+///
+/// # Safety
+/// As `lao_run`; `callback` and `user_data` stay valid until the run has finished.
+#[no_mangle]
+pub unsafe extern "C" fn lao_submit(
+    orchestrator: *const LaoOrchestrator,
+    path: *const c_char,
+    params_json: *const c_char,
+    callback: LaoEventCallback,
+    user_data: *mut c_void,
+) -> u64 {
+    guard(0, || {
+        let orchestrator = resolve(orchestrator)?;
+        let path = text(path, "path")?;
+        let params = params(params_json)?;
+        // Load and plan errors are reported here rather than by `lao_wait`
+        orchestrator.inner.load(path)?;
+        let handle = orchestrator.inner.start(path, params);
+        let run = Arc::new(Run {
+            state: Mutex::new(RunState::default()),
+            changed: Condvar::new(),
+            cancel: handle.cancel_token(),
+        });
+        let callback = callback.map(|call| Callback { call, user_data });
+        let pump = run.clone();
+        std::thread::spawn(move || {
+            let callback = callback;
+            while let Ok(event) = handle.events().recv() {
+                let json = serde_json::to_string(&event).unwrap_or_default();
+                match &callback {
+                    Some(callback) => {
+                        let json = c_string(json);
+                        (callback.call)(json.as_ptr(), callback.user_data);
+                    }
+                    None => pump.update(|state| state.events.push_back(json)),
+                }
+            }
+            let outcome = handle.wait().map_err(Error::from).and_then(outcome_json);
+            pump.update(|state| state.outcome = Some(outcome));
+        });
+        let id = orchestrator.next_id.fetch_add(1, Ordering::Relaxed);
+        orchestrator
+            .runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, run);
+        Ok(id)
+    })
+}
+
+/// Next queued event of `run` as JSON, waiting up to `timeout_ms` for one.
+/// NULL with no error when there is none yet, or none left once the run finished.
+///
+/// # Safety
+/// `orchestrator` came from `lao_orchestrator_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lao_poll(
+    orchestrator: *const LaoOrchestrator,
+    run: u64,
+    timeout_ms: u32,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let run = resolve(orchestrator)?.run(run)?;
+        let state = run.lock();
+        let (mut state, _) = run
+            .changed
+            .wait_timeout_while(state, Duration::from_millis(timeout_ms as u64), |s| {
+                s.events.is_empty() && s.outcome.is_none()
+            })
+            .unwrap_or_else(|e| e.into_inner());
+        Ok(state
+            .events
+            .pop_front()
+            .map_or(ptr::null_mut(), |json| c_string(json).into_raw()))
+    })
+}
+
+/// LAO_RUN_RUNNING, LAO_RUN_FINISHED, or -1 for an unknown run
+///
+/// # Safety
+/// `orchestrator` came from `lao_orchestrator_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lao_run_status(orchestrator: *const LaoOrchestrator, run: u64) -> i32 {
+    guard(-1, || {
+        let run = resolve(orchestrator)?.run(run)?;
+        let finished = run.lock().outcome.is_some();
+        Ok(if finished {
+            LAO_RUN_FINISHED
+        } else {
+            LAO_RUN_RUNNING
+        })
+    })
+}
+
+/// Start no further steps of `run`; LAO_OK or an error kind.
+///
+/// # Safety
+/// `orchestrator` came from `lao_orchestrator_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lao_cancel(orchestrator: *const LaoOrchestrator, run: u64) -> i32 {
+    let cancelled = guard(false, || {
+        resolve(orchestrator)?.run(run)?.cancel.cancel();
+        Ok(true)
+    });
+    if cancelled {
+        LAO_OK
+    } else {
+        lao_last_error_kind()
+    }
+}
+
+/// Wait for `run` to finish, release it and return its outcome as JSON
+///
+/// # Safety
+/// `orchestrator` came from `lao_orchestrator_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lao_wait(orchestrator: *const LaoOrchestrator, run: u64) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let orchestrator = resolve(orchestrator)?;
+        let id = run;
+        let run = orchestrator.run(id)?;
+        let outcome = {
+            let state = run
+                .changed
+                .wait_while(run.lock(), |s| s.outcome.is_none())
+                .unwrap_or_else(|e| e.into_inner());
+            match &state.outcome {
+                Some(Ok(json)) => Ok(json.clone()),
+                Some(Err(e)) => Err(Error {
+                    kind: e.kind,
+                    message: e.message.clone(),
+                }),
+                None => unreachable!("waited for the outcome"),
+            }
+        };
+        orchestrator
+            .runs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id);
+        Ok(c_string(outcome?).into_raw())
+    })
+}
+
+/// Message of the last failed call on this thread; "" after a successful
+/// one. Valid until the next call on this thread; not to be freed.
+#[no_mangle]
+pub extern "C" fn lao_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().1.as_ptr())
+}
+
+/// Kind of the last failed call on this thread, LAO_OK after a successful one
+#[no_mangle]
+pub extern "C" fn lao_last_error_kind() -> i32 {
+    LAST_ERROR.with(|e| e.borrow().0)
+}
+
+/// Free a string returned by the library.
+///
+/// # Safety
+/// `text` is NULL or was returned by this library and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lao_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lao_orchestrator_core::mock::{mock_plugin, MockSpec};
+    use std::sync::atomic::AtomicUsize;
+
+    extern "C" fn count_events(_event: *const c_char, user_data: *mut c_void) {
+        unsafe { &*(user_data as *const AtomicUsize) }.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn take(text: *mut c_char) -> Option<String> {
+        if text.is_null() {
+            return None;
+        }
+        let owned = unsafe { CStr::from_ptr(text) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { lao_string_free(text) };
+        Some(owned)
+    }
+
+    /// An orchestrator with EchoPlugin, plus a workflow echoing `${name}`
+    /// and one that can't be planned.
+    struct Fixture {
+        dir: std::path::PathBuf,
+        path: CString,
+        broken: CString,
+        params: CString,
+        orchestrator: *mut LaoOrchestrator,
+    }
+
+    impl Fixture {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("lao_ffi_{}_{}", std::process::id(), test));
+            std::fs::create_dir_all(&dir).unwrap();
+            let workflow = dir.join("ffi.yaml");
+            std::fs::write(
+                &workflow,
+                "workflow: ffi\nsteps:\n  - run: EchoPlugin\n    input: \"${name}\"\n",
+            )
+            .unwrap();
+            let broken = dir.join("broken.yaml");
+            std::fs::write(&broken, "workflow: broken\nsteps:\n  - run: NoSuchPlugin\n").unwrap();
+            let mut registry = PluginRegistry::new();
+            registry.register_plugin(mock_plugin("EchoPlugin", MockSpec::default()));
+            Fixture {
+                path: CString::new(workflow.to_str().unwrap()).unwrap(),
+                broken: CString::new(broken.to_str().unwrap()).unwrap(),
+                params: CString::new(r#"{"name": "lao"}"#).unwrap(),
+                orchestrator: Box::into_raw(Box::new(LaoOrchestrator::new(
+                    Orchestrator::with_registry(registry),
+                ))),
+                dir,
+            }
+        }
+
+        fn submit(&self, callback: LaoEventCallback, user_data: *mut c_void) -> u64 {
+            unsafe {
+                lao_submit(
+                    self.orchestrator,
+                    self.path.as_ptr(),
+                    self.params.as_ptr(),
+                    callback,
+                    user_data,
+                )
+            }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            unsafe { lao_orchestrator_free(self.orchestrator) };
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    #[test]
+    fn test_run_returns_the_outcome() {
+        let ffi = Fixture::new("run");
+        let outcome =
+            take(unsafe { lao_run(ffi.orchestrator, ffi.path.as_ptr(), ffi.params.as_ptr()) })
+                .unwrap();
+        assert!(outcome.contains(r#""status":"success""#));
+        assert!(outcome.contains("EchoPlugin(lao)"));
+    }
+
+    #[test]
+    fn test_invalid_workflow_sets_the_last_error() {
+        let ffi = Fixture::new("invalid");
+        unsafe {
+            assert!(lao_run(ffi.orchestrator, ffi.broken.as_ptr(), ptr::null()).is_null());
+            assert_eq!(lao_last_error_kind(), LAO_ERR_INVALID);
+            let message = CStr::from_ptr(lao_last_error()).to_string_lossy();
+            assert!(message.contains("NoSuchPlugin"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_params_must_be_an_object() {
+        let ffi = Fixture::new("params");
+        let bad_params = CString::new("[1, 2]").unwrap();
+        let run = unsafe {
+            lao_submit(
+                ffi.orchestrator,
+                ffi.path.as_ptr(),
+                bad_params.as_ptr(),
+                None,
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(run, 0);
+        assert_eq!(lao_last_error_kind(), LAO_ERR_ARGUMENT);
+    }
+
+    /// Polls a submitted run until its events run out.
+    fn poll(ffi: &Fixture, run: u64) -> Vec<String> {
+        let mut events = Vec::new();
+        while let Some(event) = take(unsafe { lao_poll(ffi.orchestrator, run, 5000) }) {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_submitted_runs_are_polled_then_forgotten() {
+        let ffi = Fixture::new("poll");
+        let run = ffi.submit(None, ptr::null_mut());
+        assert_ne!(run, 0);
+        let events = poll(&ffi, run);
+        assert!(events[0].contains(r#""event":"run_started""#));
+        assert!(events.last().unwrap().contains(r#""event":"run_finished""#));
+        unsafe {
+            assert_eq!(lao_run_status(ffi.orchestrator, run), LAO_RUN_FINISHED);
+            assert!(take(lao_wait(ffi.orchestrator, run))
+                .unwrap()
+                .contains("EchoPlugin(lao)"));
+            assert_eq!(lao_run_status(ffi.orchestrator, run), -1);
+            assert_eq!(lao_cancel(ffi.orchestrator, run), LAO_ERR_UNKNOWN_RUN);
+        }
+    }
+
+    #[test]
+    fn test_callback_gets_the_events_polling_would() {
+        let ffi = Fixture::new("callback");
+        let polled = poll(&ffi, ffi.submit(None, ptr::null_mut()));
+        let count = AtomicUsize::new(0);
+        let run = ffi.submit(
+            Some(count_events),
+            &count as *const AtomicUsize as *mut c_void,
+        );
+        assert!(take(unsafe { lao_wait(ffi.orchestrator, run) }).is_some());
+        assert_eq!(count.load(Ordering::SeqCst), polled.len());
+    }
+}
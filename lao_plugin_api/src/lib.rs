@@ -1,8 +1,62 @@
-use std::ffi::{c_char, CStr};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::sync::OnceLock;
 
+pub mod typed;
+
+/// ABI version of the types below, set as `PluginVTable::version`.
+/// Version 2 passes `run` input and output as pointer + length; plugins
+/// built for version 1 (NUL-terminated strings, see `v1`) still load.
+pub const ABI_VERSION: u32 = 2;
+
+/// Input for `run` and `validate_input`: `len` bytes of UTF-8 at `text`.
+/// The host also NUL-terminates the bytes, so `text` remains readable as a
+/// C string by plugins that don't handle embedded NULs.
 #[repr(C)]
 pub struct PluginInput {
-    pub text: *mut c_char,
+    pub text: *const c_char,
+    pub len: usize,
+}
+
+impl PluginInput {
+    /// # Safety
+    ///
+    /// `text` must point to `len` readable bytes, or be null.
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        if self.text.is_null() {
+            return &[];
+        }
+        std::slice::from_raw_parts(self.text as *const u8, self.len)
+    }
+
+    /// # Safety
+    ///
+    /// See `as_bytes`.
+    pub unsafe fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+}
+
+/// Owns the bytes behind a `PluginInput`; keep it alive for the duration of the call
+pub struct PluginInputBuf {
+    // Input bytes followed by a NUL
+    bytes: Vec<u8>,
+}
+
+impl PluginInputBuf {
+    pub fn new(text: impl Into<Vec<u8>>) -> Self {
+        let mut bytes = text.into();
+        bytes.push(0);
+        Self { bytes }
+    }
+
+    pub fn as_input(&self) -> PluginInput {
+        PluginInput {
+            text: self.bytes.as_ptr() as *const c_char,
+            len: self.bytes.len() - 1,
+        }
+    }
 }
 
 #[repr(C)]
@@ -15,9 +69,641 @@ pub struct MultiModalInput {
     pub metadata: *mut c_char, // JSON metadata for additional context
 }
 
+/// Output of `run`: `len` bytes at `text`, followed by a NUL. Build it with
+/// `PluginOutput::new` and release it with `PluginOutput::free`. Once the
+/// host allocator is accepted (see `INIT_SYMBOL`) the buffer comes from it
+/// and the host frees it directly; otherwise it is plugin-allocated and the
+/// host calls `free_output`.
 #[repr(C)]
 pub struct PluginOutput {
     pub text: *mut c_char,
+    pub len: usize,
+}
+
+impl PluginOutput {
+    pub fn new(text: impl Into<Vec<u8>>) -> Self {
+        let mut bytes = text.into();
+        let len = bytes.len();
+        if let Some(allocator) = HOST_ALLOCATOR.get() {
+            unsafe {
+                let buffer = (allocator.alloc)(len + 1);
+                if buffer.is_null() {
+                    return PluginOutput::null();
+                }
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer, len);
+                *buffer.add(len) = 0;
+                return PluginOutput {
+                    text: buffer as *mut c_char,
+                    len,
+                };
+            }
+        }
+        bytes.push(0);
+        let bytes = Box::into_raw(bytes.into_boxed_slice());
+        PluginOutput {
+            text: bytes as *mut c_char,
+            len,
+        }
+    }
+
+    /// No output, which the host reports as a failed call
+    pub fn null() -> Self {
+        PluginOutput {
+            text: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.text.is_null()
+    }
+
+    /// # Safety
+    ///
+    /// `text` must point to `len` readable bytes, or be null.
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        if self.text.is_null() {
+            return &[];
+        }
+        std::slice::from_raw_parts(self.text as *const u8, self.len)
+    }
+
+    /// Release an output built with `PluginOutput::new`
+    ///
+    /// # Safety
+    ///
+    /// Must be called once, by the plugin that created the output.
+    pub unsafe fn free(self) {
+        if self.text.is_null() {
+            return;
+        }
+        match HOST_ALLOCATOR.get() {
+            Some(allocator) => (allocator.free)(self.text as *mut u8, self.len + 1),
+            None => {
+                let bytes = std::ptr::slice_from_raw_parts_mut(self.text as *mut u8, self.len + 1);
+                drop(Box::from_raw(bytes));
+            }
+        }
+    }
+}
+
+/// Allocator the host passes to `plugin_init`, so output buffers are
+/// allocated and freed by the same heap even when host and plugin were
+/// built with different toolchains or allocators.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostAllocator {
+    pub alloc: unsafe extern "C" fn(size: usize) -> *mut u8,
+    pub free: unsafe extern "C" fn(ptr: *mut u8, size: usize),
+}
+
+/// Name of the optional symbol a plugin exports to take the host allocator:
+/// `extern "C" fn plugin_init(allocator: *const HostAllocator) -> bool`,
+/// returning true once `PluginOutput::new` allocates from it. The host
+/// calls it when loading the plugin, before any `run`.
+pub const INIT_SYMBOL: &[u8] = b"plugin_init";
+
+static HOST_ALLOCATOR: OnceLock<HostAllocator> = OnceLock::new();
+
+/// Body for a plugin's `plugin_init` export. The first allocator sticks for
+/// the life of the library, so reloading the same library keeps it.
+///
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+pub unsafe fn accept_host_allocator(allocator: *const HostAllocator) -> bool {
+    if allocator.is_null() {
+        return false;
+    }
+    HOST_ALLOCATOR.get_or_init(|| *allocator);
+    true
+}
+
+/// Reply to a host service call. `status` is the HTTP status, or 0 when the
+/// host could not get an answer, in which case `body` holds the error. The
+/// plugin hands it back to `HostServices::free_response`.
+#[repr(C)]
+pub struct HostResponse {
+    pub status: u32,
+    pub body: *mut c_char,
+    pub len: usize,
+}
+
+/// Services the host passes to `plugin_host_services`, so plugins share the
+/// host's connections and configuration instead of opening their own.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostServices {
+    /// Send `method` (e.g. "POST") for `path` (e.g. "/api/generate") to the
+    /// Ollama server the host is configured for, with `body` as JSON (null
+    /// or empty for none). The host pools connections and retries failures.
+    pub ollama_request: unsafe extern "C" fn(
+        method: *const c_char,
+        path: *const c_char,
+        body: *const PluginInput,
+    ) -> HostResponse,
+    pub free_response: unsafe extern "C" fn(response: HostResponse),
+}
+
+/// Name of the optional symbol a plugin exports to take host services:
+/// `extern "C" fn plugin_host_services(services: *const HostServices) -> bool`.
+/// The host calls it when loading the plugin, before any `run`.
+pub const SERVICES_SYMBOL: &[u8] = b"plugin_host_services";
+
+static HOST_SERVICES: OnceLock<HostServices> = OnceLock::new();
+
+/// Body for a plugin's `plugin_host_services` export
+///
+/// # Safety
+///
+/// `services` must be null or point to a valid HostServices.
+pub unsafe fn accept_host_services(services: *const HostServices) -> bool {
+    if services.is_null() {
+        return false;
+    }
+    HOST_SERVICES.get_or_init(|| *services);
+    true
+}
+
+/// POST `body` to `path` on the host's Ollama server and return the response
+/// body. Fails if the host gave no services, the server could not be reached
+/// or it answered with an error status.
+pub fn ollama_post(path: &str, body: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let services = HOST_SERVICES
+        .get()
+        .ok_or("the host does not provide Ollama access; update LAO")?;
+    let method = CString::new("POST").map_err(|e| e.to_string())?;
+    let path = CString::new(path).map_err(|e| e.to_string())?;
+    let body = PluginInputBuf::new(body.to_string());
+    let input = body.as_input();
+    let (status, text) = unsafe {
+        let response = (services.ollama_request)(method.as_ptr(), path.as_ptr(), &input);
+        let text = if response.body.is_null() {
+            String::new()
+        } else {
+            let bytes = std::slice::from_raw_parts(response.body as *const u8, response.len);
+            String::from_utf8_lossy(bytes).into_owned()
+        };
+        let status = response.status;
+        (services.free_response)(response);
+        (status, text)
+    };
+    match status {
+        0 => Err(text),
+        200..=299 => serde_json::from_str(&text)
+            .or_else(|e| merge_ollama_stream(&text).ok_or(e))
+            .map_err(|e| format!("invalid Ollama reply: {}", e)),
+        _ => Err(format!("Ollama returned {}: {}", status, text.trim())),
+    }
+}
+
+/// The reply to a `"stream": true` Ollama request, one JSON object per line,
+/// as the single object the same request without streaming gets: the last
+/// line, with the `response` (or `message.content`) of every line. None if
+/// no line is JSON.
+pub fn merge_ollama_stream(body: &str) -> Option<serde_json::Value> {
+    let (mut response, mut content) = (String::new(), String::new());
+    let mut last = None;
+    for line in body.lines().filter(|line| !line.trim().is_empty()) {
+        let Ok(part) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        response.push_str(part["response"].as_str().unwrap_or_default());
+        content.push_str(part["message"]["content"].as_str().unwrap_or_default());
+        last = Some(part);
+    }
+    let mut merged = last?;
+    if merged.get("response").is_some() {
+        merged["response"] = response.into();
+    }
+    if merged["message"].is_object() {
+        merged["message"]["content"] = content.into();
+    }
+    Some(merged)
+}
+
+/// Callback the host passes to `plugin_stream_output`, given each piece of
+/// its output a plugin has ready before `run` returns, e.g. an LLM's tokens,
+/// on the thread that called `run`. The host shows the pieces so far as the
+/// step's output while it runs; what `run` returns is still the output.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostStreamOutput {
+    pub chunk: unsafe extern "C" fn(text: *const PluginInput),
+}
+
+/// Name of the optional symbol a plugin exports to stream its output:
+/// `extern "C" fn plugin_stream_output(output: *const HostStreamOutput) -> bool`.
+/// The host calls it once when loading the plugin, before any `run`.
+pub const STREAM_OUTPUT_SYMBOL: &[u8] = b"plugin_stream_output";
+
+static HOST_STREAM_OUTPUT: OnceLock<HostStreamOutput> = OnceLock::new();
+
+/// Body for a plugin's `plugin_stream_output` export
+///
+/// # Safety
+///
+/// `output` must be null or point to a valid HostStreamOutput.
+pub unsafe fn accept_host_stream_output(output: *const HostStreamOutput) -> bool {
+    if output.is_null() {
+        return false;
+    }
+    HOST_STREAM_OUTPUT.get_or_init(|| *output);
+    true
+}
+
+/// Hand the next piece of the current `run`'s output to the host (see
+/// `STREAM_OUTPUT_SYMBOL`). Does nothing if the host doesn't take streamed
+/// output.
+pub fn emit_chunk(text: &str) {
+    if let (Some(host), false) = (HOST_STREAM_OUTPUT.get(), text.is_empty()) {
+        let buffer = PluginInputBuf::new(text);
+        unsafe { (host.chunk)(&buffer.as_input()) };
+    }
+}
+
+/// Callback the host passes to `plugin_host_stop`, answering on the thread
+/// that called `run` whether the host has stopped the step: its run was
+/// cancelled or it went past its `timeout`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostStop {
+    pub stopped: unsafe extern "C" fn() -> bool,
+}
+
+/// Name of the optional symbol a plugin exports to learn when its step is
+/// stopped: `extern "C" fn plugin_host_stop(stop: *const HostStop) -> bool`.
+/// The host calls it once when loading the plugin, before any `run`.
+pub const STOP_SYMBOL: &[u8] = b"plugin_host_stop";
+
+static HOST_STOP: OnceLock<HostStop> = OnceLock::new();
+
+/// Body for a plugin's `plugin_host_stop` export
+///
+/// # Safety
+///
+/// `stop` must be null or point to a valid HostStop.
+pub unsafe fn accept_host_stop(stop: *const HostStop) -> bool {
+    if stop.is_null() {
+        return false;
+    }
+    HOST_STOP.get_or_init(|| *stop);
+    true
+}
+
+/// Whether the host has stopped the current `run` (see `STOP_SYMBOL`). A
+/// plugin working for long should check it and return; `run_process` kills
+/// its command when it turns true. Always false if the host never said.
+pub fn stop_requested() -> bool {
+    HOST_STOP
+        .get()
+        .is_some_and(|host| unsafe { (host.stopped)() })
+}
+
+/// `stream` of a `HostProcessOutput::line` call
+pub const STDOUT: u32 = 1;
+pub const STDERR: u32 = 2;
+
+/// Callback the host passes to `plugin_process_output`, given each line a
+/// child process of the plugin writes, as it is written, on the thread that
+/// called `run`. The host logs it with the step being run.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostProcessOutput {
+    pub line: unsafe extern "C" fn(stream: u32, text: *const PluginInput),
+}
+
+/// Name of the optional symbol a plugin that runs commands exports to stream
+/// their output to the host:
+/// `extern "C" fn plugin_process_output(output: *const HostProcessOutput) -> bool`.
+/// The host calls it once when loading the plugin, before any `run`.
+pub const PROCESS_OUTPUT_SYMBOL: &[u8] = b"plugin_process_output";
+
+static HOST_PROCESS_OUTPUT: OnceLock<HostProcessOutput> = OnceLock::new();
+
+/// Body for a plugin's `plugin_process_output` export
+///
+/// # Safety
+///
+/// `output` must be null or point to a valid HostProcessOutput.
+pub unsafe fn accept_host_process_output(output: *const HostProcessOutput) -> bool {
+    if output.is_null() {
+        return false;
+    }
+    HOST_PROCESS_OUTPUT.get_or_init(|| *output);
+    true
+}
+
+// How often `run_process` asks whether the step was stopped while its
+// command is quiet
+const STOP_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// What a command run with `run_process` wrote, and how it exited
+#[derive(Debug)]
+pub struct ProcessOutput {
+    pub status: std::process::ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Ends a command run with `run_process_until` early, the way the command
+/// asks to be stopped: once `path` exists, it is removed and `input` is
+/// written to the command's stdin (e.g. "q" for ffmpeg)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StopFile {
+    pub path: String,
+    pub input: String,
+}
+
+/// Run `command` to completion like `Command::output`, handing each line it
+/// writes to the host as it is written (see `PROCESS_OUTPUT_SYMBOL`). Its
+/// stdin is closed. If the host stops the step meanwhile (see `STOP_SYMBOL`),
+/// the command is killed and an `Interrupted` error returned. Hosts that
+/// run commands for plugins (see `COMMAND_SYMBOL`) run it instead.
+pub fn run_process(command: &mut std::process::Command) -> std::io::Result<ProcessOutput> {
+    run_process_until(command, None)
+}
+
+/// `run_process`, ending the command early as `stop_file` says
+pub fn run_process_until(
+    command: &mut std::process::Command,
+    stop_file: Option<StopFile>,
+) -> std::io::Result<ProcessOutput> {
+    #[cfg(any(unix, windows))]
+    if let Some(host) = HOST_COMMAND.get() {
+        return host_run(host, &CommandRequest::of(command, stop_file));
+    }
+    let mut to_host = |stream: u32, line: &str| {
+        if let Some(host) = HOST_PROCESS_OUTPUT.get() {
+            let buffer = PluginInputBuf::new(line);
+            unsafe { (host.line)(stream, &buffer.as_input()) };
+        }
+    };
+    spawn_process(command, stop_file.as_ref(), &mut to_host, &stop_requested)
+}
+
+/// Run `command` in this process, as `run_process` does when the host
+/// doesn't: each line it writes goes to `on_line` without its line ending,
+/// and it is killed once `stopped` returns true. Hosts run plugins'
+/// commands with it.
+pub fn spawn_process(
+    command: &mut std::process::Command,
+    stop_file: Option<&StopFile>,
+    on_line: &mut dyn FnMut(u32, &str),
+    stopped: &dyn Fn() -> bool,
+) -> std::io::Result<ProcessOutput> {
+    use std::io::{BufRead, Write};
+    use std::process::Stdio;
+
+    let stdin = match stop_file {
+        Some(_) => Stdio::piped(),
+        None => Stdio::null(),
+    };
+    let mut child = command
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take();
+    let (sender, receiver) = std::sync::mpsc::channel::<(u32, Vec<u8>)>();
+    let mut readers = Vec::new();
+    let pipes: [(u32, Option<Box<dyn std::io::Read + Send>>); 2] = [
+        (STDOUT, child.stdout.take().map(|p| Box::new(p) as _)),
+        (STDERR, child.stderr.take().map(|p| Box::new(p) as _)),
+    ];
+    for (stream, pipe) in pipes {
+        let (Some(pipe), sender) = (pipe, sender.clone()) else {
+            continue;
+        };
+        readers.push(std::thread::spawn(move || {
+            let mut reader = std::io::BufReader::new(pipe);
+            loop {
+                let mut line = Vec::new();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {
+                        if sender.send((stream, line)).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }));
+    }
+    drop(sender);
+    // Lines reach the host from this thread, which is the one running the step
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let mut killed = false;
+    loop {
+        let received = receiver.recv_timeout(STOP_POLL);
+        if !killed && stopped() {
+            // Its pipes close with it, which ends the loop
+            let _ = child.kill();
+            killed = true;
+        }
+        if let (Some(stop_file), Some(_)) = (stop_file, &stdin) {
+            if std::fs::remove_file(&stop_file.path).is_ok() {
+                let mut stdin = stdin.take().expect("checked above");
+                let _ = stdin.write_all(stop_file.input.as_bytes());
+            }
+        }
+        let (stream, line) = match received {
+            Ok(message) => message,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let text = String::from_utf8_lossy(&line);
+        on_line(stream, text.trim_end_matches(['\r', '\n']));
+        match stream {
+            STDOUT => stdout.extend(line),
+            _ => stderr.extend(line),
+        }
+    }
+    drop(stdin);
+    for reader in readers {
+        let _ = reader.join();
+    }
+    if killed {
+        let _ = child.wait();
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            "stopped by the host",
+        ));
+    }
+    Ok(ProcessOutput {
+        status: child.wait()?,
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+    })
+}
+
+/// Callback the host passes to `plugin_host_command`, running the plugin's
+/// commands in the host, where they get the OS sandbox the plugin's
+/// permissions allow (see LAO_SANDBOX), their lines reach the step's logs
+/// and they are killed when the step is stopped. `request` is a
+/// `CommandRequest` as JSON. The reply's status is `COMMAND_RAN` with a
+/// `CommandReply` as JSON, or `COMMAND_STOPPED` or `COMMAND_FAILED` with
+/// why; the plugin hands it back to `free_response`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostCommand {
+    pub run: unsafe extern "C" fn(request: *const PluginInput) -> HostResponse,
+    pub free_response: unsafe extern "C" fn(response: HostResponse),
+}
+
+/// `HostResponse::status` of a `HostCommand::run` reply
+pub const COMMAND_FAILED: u32 = 0;
+pub const COMMAND_RAN: u32 = 1;
+pub const COMMAND_STOPPED: u32 = 2;
+
+/// Name of the optional symbol a plugin that runs commands exports to have
+/// the host run them: `extern "C" fn plugin_host_command(command: *const HostCommand) -> bool`.
+/// The host calls it once when loading the plugin, before any `run`.
+pub const COMMAND_SYMBOL: &[u8] = b"plugin_host_command";
+
+static HOST_COMMAND: OnceLock<HostCommand> = OnceLock::new();
+
+/// Body for a plugin's `plugin_host_command` export
+///
+/// # Safety
+///
+/// `command` must be null or point to a valid HostCommand.
+pub unsafe fn accept_host_command(command: *const HostCommand) -> bool {
+    if command.is_null() {
+        return false;
+    }
+    HOST_COMMAND.get_or_init(|| *command);
+    true
+}
+
+/// A command a plugin asks the host to run (see `HostCommand`)
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CommandRequest {
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Working directory; the host's when unset
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Variables to set, or to remove when they have no value
+    #[serde(default)]
+    pub env: Vec<(String, Option<String>)>,
+    #[serde(default)]
+    pub stop_file: Option<StopFile>,
+}
+
+impl CommandRequest {
+    /// What `command` would run
+    pub fn of(command: &std::process::Command, stop_file: Option<StopFile>) -> Self {
+        let text = |s: &std::ffi::OsStr| s.to_string_lossy().into_owned();
+        Self {
+            program: text(command.get_program()),
+            args: command.get_args().map(text).collect(),
+            dir: command.get_current_dir().map(|dir| text(dir.as_os_str())),
+            env: command
+                .get_envs()
+                .map(|(key, value)| (text(key), value.map(text)))
+                .collect(),
+            stop_file,
+        }
+    }
+
+    /// Add the request's arguments, directory and environment to `command`,
+    /// a command for `program`
+    pub fn apply(&self, command: &mut std::process::Command) {
+        command.args(&self.args);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+    }
+}
+
+/// How a command run for a plugin exited and what it wrote. `status` is
+/// the platform's raw exit status.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CommandReply {
+    pub status: i64,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+#[cfg(any(unix, windows))]
+impl From<ProcessOutput> for CommandReply {
+    fn from(output: ProcessOutput) -> Self {
+        #[cfg(unix)]
+        let status = std::os::unix::process::ExitStatusExt::into_raw(output.status) as i64;
+        #[cfg(windows)]
+        let status = output.status.code().unwrap_or(1) as u32 as i64;
+        Self {
+            status,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl From<CommandReply> for ProcessOutput {
+    fn from(reply: CommandReply) -> Self {
+        #[cfg(unix)]
+        let status = std::os::unix::process::ExitStatusExt::from_raw(reply.status as i32);
+        #[cfg(windows)]
+        let status = std::os::windows::process::ExitStatusExt::from_raw(reply.status as u32);
+        Self {
+            status,
+            stdout: reply.stdout,
+            stderr: reply.stderr,
+        }
+    }
+}
+
+#[cfg(any(unix, windows))]
+fn host_run(host: &HostCommand, request: &CommandRequest) -> std::io::Result<ProcessOutput> {
+    use std::io::{Error, ErrorKind};
+
+    let request = serde_json::to_string(request).map_err(Error::other)?;
+    let buffer = PluginInputBuf::new(request);
+    let (status, text) = unsafe {
+        let response = (host.run)(&buffer.as_input());
+        let text = if response.body.is_null() {
+            String::new()
+        } else {
+            let bytes = std::slice::from_raw_parts(response.body as *const u8, response.len);
+            String::from_utf8_lossy(bytes).into_owned()
+        };
+        let status = response.status;
+        (host.free_response)(response);
+        (status, text)
+    };
+    match status {
+        COMMAND_RAN => serde_json::from_str::<CommandReply>(&text)
+            .map(ProcessOutput::from)
+            .map_err(Error::other),
+        COMMAND_STOPPED => Err(Error::new(ErrorKind::Interrupted, text)),
+        _ => Err(Error::other(text)),
+    }
+}
+
+/// The version 1 ABI, kept so hosts can call plugins built before
+/// `ABI_VERSION` 2. Its input is a prefix of `PluginInput`, so only `run`
+/// and `free_output` need these signatures.
+pub mod v1 {
+    use std::ffi::c_char;
+
+    /// NUL-terminated output
+    #[repr(C)]
+    pub struct PluginOutput {
+        pub text: *mut c_char,
+    }
+
+    pub type RunFn = unsafe extern "C" fn(*const super::PluginInput) -> PluginOutput;
+    pub type FreeOutputFn = unsafe extern "C" fn(PluginOutput);
 }
 
 #[repr(C)]
@@ -71,6 +757,32 @@ pub enum PluginOutputType {
     Any,
 }
 
+impl PluginOutputType {
+    /// Outputs that are files on disk rather than text, passed on as ArtifactRefs
+    pub fn is_artifact(&self) -> bool {
+        matches!(
+            self,
+            PluginOutputType::Binary
+                | PluginOutputType::File
+                | PluginOutputType::Audio
+                | PluginOutputType::Image
+                | PluginOutputType::Video
+        )
+    }
+}
+
+/// A binary output (audio, image, ...) held in the host's artifact store.
+/// Consuming plugins receive `path` as their input text.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ArtifactRef {
+    pub id: String,
+    pub path: String,
+    pub kind: PluginOutputType,
+    pub mime: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PluginCapability {
     pub name: String,
@@ -86,7 +798,7 @@ pub struct PluginDependency {
     pub optional: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PluginInfo {
     pub name: String,
     pub version: String,
@@ -176,3 +888,56 @@ impl PluginInfo {
         }
     }
 }
+
+/// Token counts for one `run` call, reported by plugins that call an LLM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PluginUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+}
+
+impl PluginUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Name of the optional symbol a plugin exports to report usage:
+/// `extern "C" fn plugin_last_usage() -> *const c_char`, returning
+/// PluginUsage as JSON for the last `run` on the calling thread, or null.
+pub const USAGE_SYMBOL: &[u8] = b"plugin_last_usage";
+
+/// Name of the optional symbol a plugin that calls an LLM exports to report
+/// the model's context window: `extern "C" fn plugin_context_window() -> u64`,
+/// in tokens, or 0 when unknown. The host checks inputs against it.
+pub const CONTEXT_SYMBOL: &[u8] = b"plugin_context_window";
+
+/// Context window Ollama gives a model when the request doesn't set `num_ctx`
+pub const OLLAMA_DEFAULT_CONTEXT: u64 = 2048;
+
+thread_local! {
+    static PENDING_USAGE: RefCell<Option<CString>> = const { RefCell::new(None) };
+    static RETURNED_USAGE: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Record usage for the current `run` call; the host collects it right after `run` returns
+pub fn report_usage(usage: PluginUsage) {
+    let json = serde_json::to_string(&usage).unwrap_or_default();
+    PENDING_USAGE.with(|pending| *pending.borrow_mut() = CString::new(json).ok());
+}
+
+/// Body for a plugin's `plugin_last_usage` export. Hands out the usage
+/// reported since the previous call, so a run that reports nothing yields
+/// null. The pointer stays valid until the next call on this thread.
+pub fn take_usage_ptr() -> *const c_char {
+    let pending = PENDING_USAGE.with(|pending| pending.borrow_mut().take());
+    RETURNED_USAGE.with(|returned| {
+        let mut returned = returned.borrow_mut();
+        *returned = pending;
+        returned
+            .as_ref()
+            .map_or(std::ptr::null(), |usage| usage.as_ptr())
+    })
+}
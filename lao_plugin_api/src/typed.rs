@@ -0,0 +1,475 @@
+//! Structured input and output, for plugins that take more than one string.
+//!
+//! Plugins export `plugin_typed_vtable` next to `plugin_vtable`; the host
+//! passes plugins without it one text field as their string input.
+use crate::{PluginInput, PluginOutput};
+use serde::{Deserialize, Serialize};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::path::PathBuf;
+
+/// Version of `TypedVTable`, set as its `version`
+pub const TYPED_VERSION: u32 = 1;
+
+/// Name of the optional symbol a plugin exports for structured calls:
+/// `extern "C" fn plugin_typed_vtable() -> *const TypedVTable`
+pub const TYPED_VTABLE_SYMBOL: &[u8] = b"plugin_typed_vtable";
+
+/// An output with a text field of this name, and no other, is a failed call
+pub const ERROR_FIELD: &str = "error";
+
+/// `RawField::kind` values
+pub const KIND_TEXT: u32 = 1;
+pub const KIND_JSON: u32 = 2;
+pub const KIND_BLOB: u32 = 3;
+pub const KIND_FILE: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadKind {
+    Text,
+    Json,
+    Blob,
+    /// A path to a file the receiver reads itself
+    File,
+}
+
+impl PayloadKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PayloadKind::Text => "text",
+            PayloadKind::Json => "json",
+            PayloadKind::Blob => "blob",
+            PayloadKind::File => "file",
+        }
+    }
+
+    pub fn as_raw(self) -> u32 {
+        match self {
+            PayloadKind::Text => KIND_TEXT,
+            PayloadKind::Json => KIND_JSON,
+            PayloadKind::Blob => KIND_BLOB,
+            PayloadKind::File => KIND_FILE,
+        }
+    }
+
+    pub fn from_raw(kind: u32) -> Option<Self> {
+        match kind {
+            KIND_TEXT => Some(PayloadKind::Text),
+            KIND_JSON => Some(PayloadKind::Json),
+            KIND_BLOB => Some(PayloadKind::Blob),
+            KIND_FILE => Some(PayloadKind::File),
+            _ => None,
+        }
+    }
+}
+
+/// A declared input or output field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: PayloadKind,
+    /// MIME type, e.g. `audio/wav`; `audio/*` style wildcards are allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Inputs only: the plugin runs without it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+/// What `TypedVTable::signature` returns, as JSON:
+/// `{"inputs":[{"name":"audio","type":"file","content_type":"audio/*"}],
+///   "outputs":[{"name":"transcript","type":"text"}]}`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Signature {
+    #[serde(default)]
+    pub inputs: Vec<FieldSpec>,
+    #[serde(default)]
+    pub outputs: Vec<FieldSpec>,
+}
+
+impl Signature {
+    /// A payload of `text` as the first input field, for callers that only
+    /// have a string
+    pub fn payload_from_text(&self, text: &str) -> Result<Payload, String> {
+        let Some(spec) = self.inputs.first() else {
+            return Ok(Payload::new().with(Field::text("input", text)));
+        };
+        Ok(Payload::new().with(Field::new(&spec.name, Value::parse(spec.kind, text)?)))
+    }
+
+    /// The primary output of `payload` as text: its first declared output
+    /// field, or its first field when that is missing
+    pub fn output_text(&self, payload: Payload) -> Result<String, String> {
+        let payload = payload.into_result()?;
+        let field = self
+            .outputs
+            .first()
+            .and_then(|spec| payload.get(&spec.name))
+            .or_else(|| payload.fields.first())
+            .ok_or("the plugin returned no fields")?;
+        Ok(field.value.to_text())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Json(serde_json::Value),
+    Blob(Vec<u8>),
+    File(PathBuf),
+}
+
+impl Value {
+    pub fn kind(&self) -> PayloadKind {
+        match self {
+            Value::Text(_) => PayloadKind::Text,
+            Value::Json(_) => PayloadKind::Json,
+            Value::Blob(_) => PayloadKind::Blob,
+            Value::File(_) => PayloadKind::File,
+        }
+    }
+
+    /// A `kind` value from step text: JSON is parsed, a file is the path
+    /// itself and a blob is the content of the file `text` names, the way
+    /// binary outputs are passed between steps
+    pub fn parse(kind: PayloadKind, text: &str) -> Result<Value, String> {
+        match kind {
+            PayloadKind::Text => Ok(Value::Text(text.to_string())),
+            PayloadKind::Json => serde_json::from_str(text)
+                .map(Value::Json)
+                .map_err(|e| format!("not valid JSON: {}", e)),
+            PayloadKind::Blob => std::fs::read(text)
+                .map(Value::Blob)
+                .map_err(|e| format!("cannot read {}: {}", text, e)),
+            PayloadKind::File => Ok(Value::File(PathBuf::from(text))),
+        }
+    }
+
+    /// The value as step output text: JSON serialized, a file as its path and
+    /// a blob as lossy UTF-8
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Json(json) => json.to_string(),
+            Value::Blob(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            Value::File(path) => path.to_string_lossy().into_owned(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Blob(bytes) => bytes.clone(),
+            other => other.to_text().into_bytes(),
+        }
+    }
+
+    fn from_bytes(kind: PayloadKind, bytes: &[u8]) -> Result<Value, String> {
+        let text = || String::from_utf8_lossy(bytes).into_owned();
+        match kind {
+            PayloadKind::Text => Ok(Value::Text(text())),
+            PayloadKind::Json => serde_json::from_slice(bytes)
+                .map(Value::Json)
+                .map_err(|e| format!("not valid JSON: {}", e)),
+            PayloadKind::Blob => Ok(Value::Blob(bytes.to_vec())),
+            PayloadKind::File => Ok(Value::File(PathBuf::from(text()))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub value: Value,
+    pub content_type: Option<String>,
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Field {
+    pub fn new(name: &str, value: Value) -> Self {
+        Field {
+            name: name.to_string(),
+            value,
+            content_type: None,
+            metadata: serde_json::Map::new(),
+        }
+    }
+
+    pub fn text(name: &str, text: impl Into<String>) -> Self {
+        Field::new(name, Value::Text(text.into()))
+    }
+
+    pub fn json(name: &str, json: serde_json::Value) -> Self {
+        Field::new(name, Value::Json(json))
+    }
+
+    pub fn blob(name: &str, bytes: Vec<u8>) -> Self {
+        Field::new(name, Value::Blob(bytes))
+    }
+
+    pub fn file(name: &str, path: impl Into<PathBuf>) -> Self {
+        Field::new(name, Value::File(path.into()))
+    }
+
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: &str, value: serde_json::Value) -> Self {
+        self.metadata.insert(key.to_string(), value);
+        self
+    }
+}
+
+/// Named fields passed to or returned by `TypedVTable::run`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Payload {
+    pub fields: Vec<Field>,
+}
+
+impl Payload {
+    pub fn new() -> Self {
+        Payload::default()
+    }
+
+    pub fn with(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.get(name).map(|field| &field.value)
+    }
+
+    /// The output of a failed call
+    pub fn error(message: impl Into<String>) -> Self {
+        Payload::new().with(Field::text(ERROR_FIELD, message))
+    }
+
+    /// `Err` with the message of an `error` payload, the payload otherwise
+    pub fn into_result(self) -> Result<Payload, String> {
+        match self.fields.as_slice() {
+            [Field {
+                name,
+                value: Value::Text(message),
+                ..
+            }] if name == ERROR_FIELD => Err(message.clone()),
+            _ => Ok(self),
+        }
+    }
+
+    /// The payload in its C layout, valid while the returned buffer lives
+    pub fn to_raw(&self) -> RawPayloadBuf {
+        let mut strings = Vec::new();
+        let mut data = Vec::new();
+        let mut fields = Vec::with_capacity(self.fields.len());
+        // Moving a CString or Vec doesn't move its heap buffer, so the
+        // pointers below stay valid as the buffers are pushed
+        let mut keep = |text: String| -> *const c_char {
+            let text = CString::new(text.replace('\0', "")).unwrap_or_default();
+            let ptr = text.as_ptr();
+            strings.push(text);
+            ptr
+        };
+        for field in &self.fields {
+            let name = keep(field.name.clone());
+            let content_type = match &field.content_type {
+                Some(content_type) => keep(content_type.clone()),
+                None => std::ptr::null(),
+            };
+            let metadata = if field.metadata.is_empty() {
+                std::ptr::null()
+            } else {
+                keep(serde_json::Value::Object(field.metadata.clone()).to_string())
+            };
+            let bytes = field.value.to_bytes();
+            fields.push(RawField {
+                name,
+                kind: field.value.kind().as_raw(),
+                content_type,
+                metadata,
+                data: bytes.as_ptr(),
+                len: bytes.len(),
+            });
+            data.push(bytes);
+        }
+        RawPayloadBuf {
+            _strings: strings,
+            _data: data,
+            fields,
+        }
+    }
+
+    /// Copy a payload out of its C layout
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be null or point to a valid RawPayload.
+    pub unsafe fn from_raw(raw: *const RawPayload) -> Result<Payload, String> {
+        if raw.is_null() || (*raw).fields.is_null() {
+            return Ok(Payload::new());
+        }
+        let raw = &*raw;
+        let mut payload = Payload::new();
+        for field in std::slice::from_raw_parts(raw.fields, raw.count) {
+            let name = cstr(field.name).unwrap_or_default();
+            let kind = PayloadKind::from_raw(field.kind)
+                .ok_or_else(|| format!("field `{}` has unknown type {}", name, field.kind))?;
+            let bytes = if field.data.is_null() {
+                &[]
+            } else {
+                std::slice::from_raw_parts(field.data, field.len)
+            };
+            let value =
+                Value::from_bytes(kind, bytes).map_err(|e| format!("field `{}`: {}", name, e))?;
+            let metadata = match cstr(field.metadata) {
+                Some(json) => serde_json::from_str(&json)
+                    .map_err(|e| format!("field `{}` has invalid metadata: {}", name, e))?,
+                None => serde_json::Map::new(),
+            };
+            payload.fields.push(Field {
+                name,
+                value,
+                content_type: cstr(field.content_type),
+                metadata,
+            });
+        }
+        Ok(payload)
+    }
+
+    /// The payload in its C layout, owned by the returned value until it is
+    /// passed to `free_raw`. Plugins return their output this way.
+    pub fn into_raw(self) -> RawPayload {
+        let buffer = Box::new(self.to_raw());
+        RawPayload {
+            fields: buffer.fields.as_ptr(),
+            count: buffer.fields.len(),
+            owner: Box::into_raw(buffer) as *mut c_void,
+        }
+    }
+}
+
+unsafe fn cstr(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+/// Release a payload built with `Payload::into_raw`
+///
+/// # Safety
+///
+/// Must be called once, by the side that built the payload.
+pub unsafe fn free_raw(raw: RawPayload) {
+    if !raw.owner.is_null() {
+        drop(Box::from_raw(raw.owner as *mut RawPayloadBuf));
+    }
+}
+
+/// One field in C layout. `name`, `content_type` and `metadata` (a JSON
+/// object) are NUL-terminated, the latter two null when unset; `data` holds
+/// `len` bytes: UTF-8 text, serialized JSON, the blob or the file's path.
+#[repr(C)]
+pub struct RawField {
+    pub name: *const c_char,
+    pub kind: u32,
+    pub content_type: *const c_char,
+    pub metadata: *const c_char,
+    pub data: *const u8,
+    pub len: usize,
+}
+
+/// `count` fields at `fields`. `owner` belongs to the side that built the
+/// payload, which uses it to free it.
+#[repr(C)]
+pub struct RawPayload {
+    pub fields: *const RawField,
+    pub count: usize,
+    pub owner: *mut c_void,
+}
+
+/// Owns the memory behind a `RawPayload`
+pub struct RawPayloadBuf {
+    _strings: Vec<CString>,
+    _data: Vec<Vec<u8>>,
+    fields: Vec<RawField>,
+}
+
+impl RawPayloadBuf {
+    pub fn as_raw(&self) -> RawPayload {
+        RawPayload {
+            fields: self.fields.as_ptr(),
+            count: self.fields.len(),
+            owner: std::ptr::null_mut(),
+        }
+    }
+}
+
+#[repr(C)]
+pub struct TypedVTable {
+    pub version: u32,
+    /// The plugin's `Signature` as NUL-terminated JSON, owned by the plugin
+    pub signature: unsafe extern "C" fn() -> *const c_char,
+    pub run: unsafe extern "C" fn(*const RawPayload) -> RawPayload,
+    pub free_output: unsafe extern "C" fn(RawPayload),
+}
+
+/// Body for `TypedVTable::run`: reads the input, calls `run` and returns its
+/// output, or an `error` payload if either fails
+///
+/// # Safety
+///
+/// `input` must be null or point to a valid RawPayload.
+pub unsafe fn serve(
+    input: *const RawPayload,
+    run: impl FnOnce(Payload) -> Result<Payload, String>,
+) -> RawPayload {
+    Payload::from_raw(input)
+        .and_then(run)
+        .unwrap_or_else(Payload::error)
+        .into_raw()
+}
+
+/// `TypedVTable::free_output` for outputs built with `serve`
+///
+/// # Safety
+///
+/// See `free_raw`.
+pub unsafe extern "C" fn free_output(output: RawPayload) {
+    free_raw(output)
+}
+
+/// Body for `PluginVTable::run` of a typed plugin: the string input becomes
+/// the first input field of `signature` and the primary output comes back as
+/// text, with failures as `error: ...`
+///
+/// # Safety
+///
+/// `input` must be null or point to a valid PluginInput.
+pub unsafe fn serve_text(
+    input: *const PluginInput,
+    signature: &Signature,
+    run: impl FnOnce(Payload) -> Result<Payload, String>,
+) -> PluginOutput {
+    let text = if input.is_null() {
+        String::new()
+    } else {
+        (*input).to_string_lossy().into_owned()
+    };
+    let output = signature
+        .payload_from_text(&text)
+        .and_then(run)
+        .and_then(|payload| signature.output_text(payload));
+    match output {
+        Ok(text) => PluginOutput::new(text),
+        Err(e) => PluginOutput::new(format!("error: {}", e)),
+    }
+}
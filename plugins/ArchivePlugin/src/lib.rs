@@ -0,0 +1,427 @@
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+// Archive request, accepted either as YAML params or as "<action> <archive> [paths...]" text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ArchiveRequest {
+    pub action: String,
+    /// Archive to create (zip/tar) or to unpack (extract)
+    pub archive: String,
+    /// Files and directories to pack
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// Directory to unpack into; defaults to the archive path without its extension
+    #[serde(default)]
+    pub destination: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveReport {
+    pub action: String,
+    pub archive: String,
+    pub destination: Option<String>,
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else {
+            None
+        }
+    }
+
+    fn strip_extension(name: &str) -> &str {
+        [".tar.gz", ".tgz", ".tar", ".zip"]
+            .iter()
+            .find_map(|ext| {
+                name.len()
+                    .checked_sub(ext.len())
+                    .filter(|&i| name.is_char_boundary(i))
+                    .filter(|&i| name[i..].eq_ignore_ascii_case(ext))
+                    .map(|i| &name[..i])
+            })
+            .unwrap_or(name)
+    }
+}
+
+const ACTIONS: [&str; 3] = ["zip", "tar", "extract"];
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"ArchivePlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for ArchivePlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"ArchivePlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Creates and extracts zip and tar archives\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"archive\", \"zip\", \"tar\", \"files\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"zip\",\"description\":\"Pack files and directories into a zip archive\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"tar\",\"description\":\"Pack files and directories into a tar or tar.gz archive\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"extract\",\"description\":\"Unpack a zip, tar or tar.gz archive and list its files\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"zip\",\"description\":\"Pack files and directories into a zip archive\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"tar\",\"description\":\"Pack files and directories into a tar or tar.gz archive\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"extract\",\"description\":\"Unpack a zip, tar or tar.gz archive and list its files\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<ArchiveRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => {
+            let mut parts = trimmed.split_whitespace();
+            let (Some(action), Some(archive)) = (parts.next(), parts.next()) else {
+                return Err(anyhow!("expected '<action> <archive> [paths...]'"));
+            };
+            let rest: Vec<String> = parts.map(str::to_string).collect();
+            if action == "extract" {
+                ArchiveRequest {
+                    action: action.to_string(),
+                    archive: archive.to_string(),
+                    sources: Vec::new(),
+                    destination: rest.into_iter().next(),
+                }
+            } else {
+                ArchiveRequest {
+                    action: action.to_string(),
+                    archive: archive.to_string(),
+                    sources: rest,
+                    destination: None,
+                }
+            }
+        }
+    };
+
+    if !ACTIONS.contains(&request.action.as_str()) {
+        return Err(anyhow!(
+            "unsupported action '{}', expected one of: {}",
+            request.action,
+            ACTIONS.join(", ")
+        ));
+    }
+    if request.action != "extract" && request.sources.is_empty() {
+        return Err(anyhow!("'{}' needs at least one source", request.action));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let archive = Path::new(&request.archive);
+
+    let report = match request.action.as_str() {
+        "zip" => ArchiveReport {
+            action: request.action.clone(),
+            archive: request.archive.clone(),
+            destination: None,
+            files: create_zip(archive, &request.sources)?,
+        },
+        "tar" => ArchiveReport {
+            action: request.action.clone(),
+            archive: request.archive.clone(),
+            destination: None,
+            files: create_tar(archive, &request.sources)?,
+        },
+        _ => {
+            let destination = request
+                .destination
+                .clone()
+                .unwrap_or_else(|| ArchiveFormat::strip_extension(&request.archive).to_string());
+            let files = extract(archive, Path::new(&destination))?;
+            ArchiveReport {
+                action: request.action.clone(),
+                archive: request.archive.clone(),
+                destination: Some(destination),
+                files: files
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect(),
+            }
+        }
+    };
+
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+// Expand sources into (file on disk, name inside the archive) pairs
+fn collect_entries(sources: &[String]) -> Result<Vec<(PathBuf, String)>> {
+    let mut entries = Vec::new();
+    for source in sources {
+        let source = Path::new(source);
+        if !source.exists() {
+            return Err(anyhow!("source not found: {}", source.display()));
+        }
+        let base = source.parent().unwrap_or(Path::new(""));
+        for entry in walkdir::WalkDir::new(source).sort_by_file_name() {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(base).unwrap_or(entry.path());
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            entries.push((entry.path().to_path_buf(), name));
+        }
+    }
+    Ok(entries)
+}
+
+fn create_zip(archive: &Path, sources: &[String]) -> Result<Vec<String>> {
+    let entries = collect_entries(sources)?;
+    let mut writer = zip::ZipWriter::new(File::create(archive)?);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    for (path, name) in &entries {
+        writer.start_file(name.as_str(), options)?;
+        std::io::copy(&mut File::open(path)?, &mut writer)?;
+    }
+    writer.finish()?;
+    Ok(entries.into_iter().map(|(_, name)| name).collect())
+}
+
+fn create_tar(archive: &Path, sources: &[String]) -> Result<Vec<String>> {
+    let entries = collect_entries(sources)?;
+    let file = File::create(archive)?;
+    let writer: Box<dyn Write> = match ArchiveFormat::from_path(archive) {
+        Some(ArchiveFormat::TarGz) => Box::new(GzEncoder::new(file, Compression::default())),
+        _ => Box::new(file),
+    };
+
+    let mut builder = tar::Builder::new(writer);
+    for (path, name) in &entries {
+        builder.append_path_with_name(path, name)?;
+    }
+    builder.into_inner()?.flush()?;
+    Ok(entries.into_iter().map(|(_, name)| name).collect())
+}
+
+// Unpack into `destination`, skipping entries that would escape it
+fn extract(archive: &Path, destination: &Path) -> Result<Vec<PathBuf>> {
+    let format = ArchiveFormat::from_path(archive)
+        .ok_or_else(|| anyhow!("unrecognised archive format: {}", archive.display()))?;
+    let file =
+        File::open(archive).map_err(|e| anyhow!("failed to open {}: {}", archive.display(), e))?;
+    std::fs::create_dir_all(destination)?;
+
+    let mut files = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i)?;
+                let Some(relative) = entry.enclosed_name() else {
+                    warn!("Skipping unsafe zip entry: {}", entry.name());
+                    continue;
+                };
+                let target = destination.join(relative);
+                if entry.is_dir() {
+                    std::fs::create_dir_all(&target)?;
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::io::copy(&mut entry, &mut File::create(&target)?)?;
+                files.push(target);
+            }
+        }
+        ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+            let reader: Box<dyn Read> = if format == ArchiveFormat::TarGz {
+                Box::new(GzDecoder::new(file))
+            } else {
+                Box::new(file)
+            };
+            let mut tar = tar::Archive::new(reader);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                let relative = entry.path()?.to_path_buf();
+                if !entry.unpack_in(destination)? {
+                    warn!("Skipping unsafe tar entry: {}", relative.display());
+                    continue;
+                }
+                if entry.header().entry_type().is_file() {
+                    files.push(destination.join(relative));
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn sample_tree(root: &Path) -> String {
+        let docs = root.join("docs");
+        std::fs::create_dir_all(docs.join("nested")).unwrap();
+        std::fs::write(docs.join("a.txt"), "alpha").unwrap();
+        std::fs::write(docs.join("nested").join("b.txt"), "beta").unwrap();
+        docs.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "ArchivePlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("zip out.zip docs notes.md").unwrap();
+        assert_eq!(req.sources, vec!["docs", "notes.md"]);
+
+        let req = parse_request("extract inbox.tar.gz /tmp/inbox").unwrap();
+        assert_eq!(req.destination.as_deref(), Some("/tmp/inbox"));
+
+        let req = parse_request("action: tar\narchive: out.tgz\nsources: [docs]").unwrap();
+        assert_eq!(req.action, "tar");
+
+        assert!(parse_request("zip out.zip").is_err());
+        assert!(parse_request("rar out.rar docs").is_err());
+        assert_eq!(ArchiveFormat::strip_extension("in/box.TAR.GZ"), "in/box");
+    }
+
+    #[test]
+    fn test_zip_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = sample_tree(dir.path());
+        let archive = dir.path().join("docs.zip");
+
+        let files = create_zip(&archive, &[docs]).unwrap();
+        assert_eq!(files, vec!["docs/a.txt", "docs/nested/b.txt"]);
+
+        let out = dir.path().join("out");
+        let extracted = extract(&archive, &out).unwrap();
+        assert_eq!(extracted.len(), 2);
+        let content = std::fs::read_to_string(out.join("docs/nested/b.txt")).unwrap();
+        assert_eq!(content, "beta");
+    }
+
+    #[test]
+    fn test_tar_gz_round_trip_via_plugin_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = sample_tree(dir.path());
+        let archive = dir.path().join("docs.tar.gz");
+
+        let input = format!("tar {} {}", archive.display(), docs);
+        let report: ArchiveReport = serde_json::from_str(&process_input(&input).unwrap()).unwrap();
+        assert_eq!(report.files.len(), 2);
+
+        let input = format!("extract {}", archive.display());
+        let report: ArchiveReport = serde_json::from_str(&process_input(&input).unwrap()).unwrap();
+        let destination = PathBuf::from(report.destination.unwrap());
+        assert_eq!(destination, dir.path().join("docs"));
+        assert!(report.files.iter().any(|f| f.ends_with("a.txt")));
+    }
+}
@@ -0,0 +1,353 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    run_process, PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr,
+    ABI_VERSION,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::os::raw::c_char;
+use std::process::Command;
+
+// Analysis request, accepted either as YAML/JSON params or as "<action> [path]" text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AnalysisRequest {
+    #[serde(default = "default_action")]
+    pub action: String,
+    #[serde(default = "default_path")]
+    pub path: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_action() -> String {
+    "check".to_string()
+}
+
+fn default_path() -> String {
+    ".".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub level: String,
+    pub code: Option<String>,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    pub status: String, // passed | failed | ignored
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AnalysisSummary {
+    pub errors: usize,
+    pub warnings: usize,
+    pub tests_passed: usize,
+    pub tests_failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisReport {
+    pub action: String,
+    pub path: String,
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    pub tests: Vec<TestCase>,
+    pub summary: AnalysisSummary,
+}
+
+const ACTIONS: [&str; 3] = ["check", "clippy", "test"];
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"CargoAnalyzerPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for CargoAnalyzerPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"CargoAnalyzerPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] =
+        b"Runs cargo check/clippy/test and reports structured diagnostics\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"code\", \"rust\", \"cargo\", \"analysis\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"analyze\",\"description\":\"Run cargo check, clippy or test and parse the results\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"analyze\",\"description\":\"Run cargo check, clippy or test and parse the results\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<AnalysisRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => {
+            let mut parts = trimmed.split_whitespace();
+            let action = parts
+                .next()
+                .map(str::to_string)
+                .unwrap_or_else(default_action);
+            let path = parts
+                .next()
+                .map(str::to_string)
+                .unwrap_or_else(default_path);
+            AnalysisRequest {
+                action,
+                path,
+                args: parts.map(str::to_string).collect(),
+            }
+        }
+    };
+
+    if !ACTIONS.contains(&request.action.as_str()) {
+        return Err(anyhow!(
+            "unsupported action '{}', expected one of: {}",
+            request.action,
+            ACTIONS.join(", ")
+        ));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+
+    let mut cmd = Command::new("cargo");
+    cmd.arg(&request.action)
+        .arg("--message-format=json")
+        .args(&request.args)
+        .current_dir(&request.path);
+    if request.action == "test" {
+        cmd.arg("--no-fail-fast");
+    }
+
+    let output = run_process(&mut cmd)
+        .map_err(|e| anyhow!("failed to run cargo {}: {}", request.action, e))?;
+
+    let report = build_report(&request, &output.stdout, output.status.success());
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+fn build_report(request: &AnalysisRequest, stdout: &str, success: bool) -> AnalysisReport {
+    let diagnostics = parse_compiler_messages(stdout);
+    let tests = parse_test_results(stdout);
+
+    let summary = AnalysisSummary {
+        errors: diagnostics.iter().filter(|d| d.level == "error").count(),
+        warnings: diagnostics.iter().filter(|d| d.level == "warning").count(),
+        tests_passed: tests.iter().filter(|t| t.status == "passed").count(),
+        tests_failed: tests.iter().filter(|t| t.status == "failed").count(),
+    };
+
+    AnalysisReport {
+        action: request.action.clone(),
+        path: request.path.clone(),
+        success,
+        diagnostics,
+        tests,
+        summary,
+    }
+}
+
+// Extract compiler diagnostics from `--message-format=json` output
+fn parse_compiler_messages(stdout: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    for line in stdout.lines().filter(|l| l.starts_with('{')) {
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+        if value["reason"] != "compiler-message" {
+            continue;
+        }
+        let message = &value["message"];
+        let Some(span) = message["spans"]
+            .as_array()
+            .and_then(|spans| spans.iter().find(|s| s["is_primary"] == true))
+        else {
+            // Span-less messages are summaries like "aborting due to previous error"
+            continue;
+        };
+
+        let diagnostic = Diagnostic {
+            level: message["level"].as_str().unwrap_or("unknown").to_string(),
+            code: message["code"]["code"].as_str().map(str::to_string),
+            message: message["message"].as_str().unwrap_or_default().to_string(),
+            file: span["file_name"].as_str().map(str::to_string),
+            line: span["line_start"].as_u64(),
+            column: span["column_start"].as_u64(),
+        };
+        // Workspaces report the same diagnostic once per target
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+// Extract libtest results ("test name ... ok") from test harness output
+fn parse_test_results(stdout: &str) -> Vec<TestCase> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("test "))
+        .filter_map(|rest| rest.rsplit_once(" ... "))
+        .filter_map(|(name, result)| {
+            let status = match result.trim() {
+                "ok" => "passed",
+                "FAILED" => "failed",
+                "ignored" => "ignored",
+                _ => return None,
+            };
+            Some(TestCase {
+                name: name.trim().to_string(),
+                status: status.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `command` must be null or point to a valid HostCommand.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_command(command: *const lao_plugin_api::HostCommand) -> bool {
+    lao_plugin_api::accept_host_command(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "CargoAnalyzerPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("action: clippy\npath: ./core\nargs: [\"--all-targets\"]").unwrap();
+        assert_eq!(req.action, "clippy");
+        assert_eq!(req.path, "./core");
+        assert_eq!(req.args, vec!["--all-targets"]);
+
+        let req = parse_request("test ../repo").unwrap();
+        assert_eq!(req.action, "test");
+        assert_eq!(req.path, "../repo");
+
+        assert!(parse_request("build").is_err());
+    }
+
+    #[test]
+    fn test_parse_compiler_messages() {
+        let stdout = r#"{"reason":"compiler-artifact","target":{}}
+{"reason":"compiler-message","message":{"level":"warning","code":{"code":"unused_variables"},"message":"unused variable: `x`","spans":[{"file_name":"src/lib.rs","line_start":3,"column_start":9,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"error","code":null,"message":"aborting due to 1 previous error","spans":[]}}
+{"reason":"build-finished","success":false}"#;
+        let diagnostics = parse_compiler_messages(stdout);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, "warning");
+        assert_eq!(diagnostics[0].code.as_deref(), Some("unused_variables"));
+        assert_eq!(diagnostics[0].file.as_deref(), Some("src/lib.rs"));
+        assert_eq!(diagnostics[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_parse_test_results() {
+        let stdout = "running 3 tests\ntest tests::a ... ok\ntest tests::b ... FAILED\ntest tests::c ... ignored\n\ntest result: FAILED. 1 passed; 1 failed; 1 ignored";
+        let tests = parse_test_results(stdout);
+        assert_eq!(tests.len(), 3);
+        assert_eq!(tests[1].name, "tests::b");
+        assert_eq!(tests[1].status, "failed");
+
+        let request = parse_request("test").unwrap();
+        let report = build_report(&request, stdout, false);
+        assert_eq!(report.summary.tests_passed, 1);
+        assert_eq!(report.summary.tests_failed, 1);
+    }
+}
@@ -0,0 +1,463 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+
+// Classification request, accepted either as YAML params or as plain text for sentiment
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClassifyRequest {
+    pub text: String,
+    /// Candidate labels; defaults to the keyword labels or to positive/negative/neutral
+    #[serde(default)]
+    pub labels: Option<Vec<String>>,
+    #[serde(default)]
+    pub backend: Backend,
+    /// Label -> trigger words, used by the keyword backend
+    #[serde(default)]
+    pub keywords: BTreeMap<String, Vec<String>>,
+    /// Label reported when no keyword matches; defaults to the first label
+    #[serde(default)]
+    pub default_label: Option<String>,
+    #[serde(default = "default_model")]
+    pub model: String,
+    /// Extra guidance appended to the LLM prompt, e.g. what makes a ticket urgent
+    #[serde(default)]
+    pub instructions: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Llm,
+    Keywords,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Classification {
+    pub label: String,
+    pub confidence: f64,
+    pub scores: BTreeMap<String, f64>,
+}
+
+fn default_model() -> String {
+    "mistral".to_string()
+}
+
+fn sentiment_labels() -> Vec<String> {
+    ["positive", "negative", "neutral"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+impl ClassifyRequest {
+    fn label_set(&self) -> Vec<String> {
+        match &self.labels {
+            Some(labels) => labels.clone(),
+            None if !self.keywords.is_empty() => self.keywords.keys().cloned().collect(),
+            None => sentiment_labels(),
+        }
+    }
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"ClassifierPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for ClassifierPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"ClassifierPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Assigns labels with confidence scores to text\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"classification\", \"sentiment\", \"text\", \"ai\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"classify\",\"description\":\"Pick a label for text and report confidence scores\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"classify\",\"description\":\"Pick a label for text and report confidence scores\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<ClassifyRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        // Upstream text such as "Note: ..." also parses as a mapping, so require `text`
+        Ok(value @ serde_yaml::Value::Mapping(_)) if value.get("text").is_some() => {
+            serde_yaml::from_value(value)?
+        }
+        _ => ClassifyRequest {
+            text: trimmed.to_string(),
+            labels: None,
+            backend: Backend::default(),
+            keywords: BTreeMap::new(),
+            default_label: None,
+            model: default_model(),
+            instructions: None,
+        },
+    };
+
+    if request.text.trim().is_empty() {
+        return Err(anyhow!("nothing to classify"));
+    }
+    let labels = request.label_set();
+    if labels.len() < 2 {
+        return Err(anyhow!("need at least two labels"));
+    }
+    if request.backend == Backend::Keywords {
+        if request.keywords.is_empty() {
+            return Err(anyhow!("the keywords backend needs a 'keywords' mapping"));
+        }
+        if let Some(unknown) = request.keywords.keys().find(|k| !labels.contains(k)) {
+            return Err(anyhow!("keyword label '{}' is not in 'labels'", unknown));
+        }
+    }
+    if let Some(label) = request
+        .default_label
+        .as_ref()
+        .filter(|l| !labels.contains(l))
+    {
+        return Err(anyhow!("default_label '{}' is not in 'labels'", label));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let classification = match request.backend {
+        Backend::Keywords => classify_with_keywords(&request),
+        Backend::Llm => classify_with_llm(&request)?,
+    };
+    info!(
+        "Classified as '{}' ({:.2})",
+        classification.label, classification.confidence
+    );
+    // Compact JSON keeps `"label":"..."` matchable by OutputContains conditions
+    Ok(serde_json::to_string(&classification)?)
+}
+
+fn classify_with_keywords(request: &ClassifyRequest) -> Classification {
+    let labels = request.label_set();
+    let text = request.text.to_lowercase();
+    let counts: Vec<(String, usize)> = labels
+        .iter()
+        .map(|label| {
+            let hits = request
+                .keywords
+                .get(label)
+                .map(|words| {
+                    words
+                        .iter()
+                        .map(|w| text.matches(&w.to_lowercase()).count())
+                        .sum()
+                })
+                .unwrap_or(0);
+            (label.clone(), hits)
+        })
+        .collect();
+
+    let total: usize = counts.iter().map(|(_, hits)| hits).sum();
+    if total == 0 {
+        let label = request
+            .default_label
+            .clone()
+            .unwrap_or_else(|| labels[0].clone());
+        let scores = labels.iter().map(|l| (l.clone(), 0.0)).collect();
+        return Classification {
+            label,
+            confidence: 0.0,
+            scores,
+        };
+    }
+
+    let scores: BTreeMap<String, f64> = counts
+        .iter()
+        .map(|(label, hits)| (label.clone(), *hits as f64 / total as f64))
+        .collect();
+    best_of(&labels, scores)
+}
+
+fn classify_with_llm(request: &ClassifyRequest) -> Result<Classification> {
+    let labels = request.label_set();
+    let prompt = build_prompt(request, &labels);
+    let response = lao_plugin_api::ollama_post(
+        "/api/generate",
+        &serde_json::json!({
+            "model": request.model,
+            "prompt": prompt,
+            "format": "json",
+            "stream": false,
+            "options": { "temperature": 0 }
+        }),
+    )
+    .map_err(|e| anyhow!("classifier request failed: {}", e))?;
+    lao_plugin_api::report_usage(lao_plugin_api::PluginUsage {
+        prompt_tokens: response["prompt_eval_count"].as_u64().unwrap_or(0),
+        completion_tokens: response["eval_count"].as_u64().unwrap_or(0),
+    });
+    let text = response["response"]
+        .as_str()
+        .ok_or_else(|| anyhow!("model returned no response"))?;
+    parse_llm_response(text, &labels)
+}
+
+fn build_prompt(request: &ClassifyRequest, labels: &[String]) -> String {
+    let mut prompt = format!(
+        "Classify the text below into exactly one of these labels: {}.\n",
+        labels.join(", ")
+    );
+    if let Some(instructions) = &request.instructions {
+        prompt.push_str(instructions.trim());
+        prompt.push('\n');
+    }
+    prompt.push_str(
+        "Reply with JSON only, in the form {\"label\": \"<label>\", \"scores\": {\"<label>\": <probability>, ...}} \
+         where the scores cover every label and sum to 1.\n\nText:\n",
+    );
+    prompt.push_str(&request.text);
+    prompt
+}
+
+// Maps the model's reply onto the label set, tolerating stray prose and label casing
+fn parse_llm_response(text: &str, labels: &[String]) -> Result<Classification> {
+    let start = text.find('{');
+    let end = text.rfind('}');
+    let json: serde_json::Value = match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&text[start..=end])?,
+        _ => return Err(anyhow!("model reply is not JSON: {}", text.trim())),
+    };
+    let canonical = |name: &str| {
+        labels
+            .iter()
+            .find(|l| l.eq_ignore_ascii_case(name.trim()))
+            .cloned()
+    };
+
+    let mut scores: BTreeMap<String, f64> = labels.iter().map(|l| (l.clone(), 0.0)).collect();
+    if let Some(reported) = json["scores"].as_object() {
+        for (name, score) in reported {
+            if let (Some(label), Some(score)) = (canonical(name), score.as_f64()) {
+                scores.insert(label, score.max(0.0));
+            }
+        }
+    }
+
+    let total: f64 = scores.values().sum();
+    if total > 0.0 {
+        scores.values_mut().for_each(|s| *s /= total);
+        return Ok(best_of(labels, scores));
+    }
+
+    // No usable scores: fall back to the label and confidence fields
+    let label = json["label"]
+        .as_str()
+        .and_then(canonical)
+        .ok_or_else(|| anyhow!("model picked no known label: {}", json))?;
+    let confidence = json["confidence"].as_f64().unwrap_or(1.0).clamp(0.0, 1.0);
+    let rest = (1.0 - confidence) / (labels.len() - 1) as f64;
+    for (name, score) in scores.iter_mut() {
+        *score = if *name == label { confidence } else { rest };
+    }
+    Ok(Classification {
+        label,
+        confidence,
+        scores,
+    })
+}
+
+// Highest score wins; ties go to the label listed first
+fn best_of(labels: &[String], scores: BTreeMap<String, f64>) -> Classification {
+    let mut best = &labels[0];
+    for label in labels {
+        if scores[label] > scores[best] {
+            best = label;
+        }
+    }
+    Classification {
+        label: best.clone(),
+        confidence: scores[best],
+        scores,
+    }
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `services` must be null or point to a valid HostServices.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_services(
+    services: *const lao_plugin_api::HostServices,
+) -> bool {
+    lao_plugin_api::accept_host_services(services)
+}
+
+// Token counts for the last run, read by the host after each call
+#[no_mangle]
+pub extern "C" fn plugin_last_usage() -> *const c_char {
+    lao_plugin_api::take_usage_ptr()
+}
+
+// Requests don't set num_ctx, so the model gets Ollama's default window
+#[no_mangle]
+pub extern "C" fn plugin_context_window() -> u64 {
+    lao_plugin_api::OLLAMA_DEFAULT_CONTEXT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "ClassifierPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("I love this!").unwrap();
+        assert_eq!(req.backend, Backend::Llm);
+        assert_eq!(req.label_set(), sentiment_labels());
+        let req = parse_request("Subject: refund request").unwrap();
+        assert_eq!(req.text, "Subject: refund request");
+
+        let req = parse_request(
+            "text: server down\nbackend: keywords\nkeywords:\n  urgent: [down, outage]\n  normal: [question]",
+        )
+        .unwrap();
+        assert_eq!(req.label_set(), labels(&["normal", "urgent"]));
+
+        assert!(parse_request("text: hi\nlabels: [only]").is_err());
+        assert!(parse_request("text: hi\nbackend: keywords").is_err());
+        assert!(parse_request("text: hi\nlabels: [a, b]\ndefault_label: c").is_err());
+        assert!(parse_request("   ").is_err());
+    }
+
+    #[test]
+    fn test_classify_with_keywords() {
+        let output = process_input(
+            "text: Outage! The API is down.\nbackend: keywords\nlabels: [urgent, normal]\nkeywords:\n  urgent: [down, outage]\n  normal: [question, thanks]",
+        )
+        .unwrap();
+        assert!(output.contains("\"label\":\"urgent\""));
+        let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(json["confidence"], 1.0);
+
+        let req = parse_request(
+            "text: hello\nbackend: keywords\nlabels: [urgent, normal]\ndefault_label: normal\nkeywords:\n  urgent: [down]",
+        )
+        .unwrap();
+        let result = classify_with_keywords(&req);
+        assert_eq!(result.label, "normal");
+        assert_eq!(result.confidence, 0.0);
+    }
+
+    #[test]
+    fn test_parse_llm_response() {
+        let set = labels(&["urgent", "normal", "low"]);
+
+        let result = parse_llm_response(
+            "Sure! {\"label\": \"URGENT\", \"scores\": {\"Urgent\": 6, \"normal\": 3, \"low\": 1, \"other\": 5}}",
+            &set,
+        )
+        .unwrap();
+        assert_eq!(result.label, "urgent");
+        assert!((result.confidence - 0.6).abs() < 1e-9);
+        assert!((result.scores["low"] - 0.1).abs() < 1e-9);
+
+        let result = parse_llm_response("{\"label\": \"low\", \"confidence\": 0.8}", &set).unwrap();
+        assert_eq!(result.label, "low");
+        assert!((result.scores["normal"] - 0.1).abs() < 1e-9);
+
+        assert!(parse_llm_response("{\"label\": \"spam\"}", &set).is_err());
+        assert!(parse_llm_response("no idea", &set).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info};
+use serde::Deserialize;
+use std::os::raw::c_char;
+
+// Clipboard request, accepted either as YAML params or as "read" / "write: <text>" text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum ClipboardRequest {
+    Read,
+    Write { text: String },
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"ClipboardPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing clipboard request");
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Clipboard error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    // Clipboard contents may contain interior NULs; strip them rather than panic
+    PluginOutput::new(result.replace('\0', ""))
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for ClipboardPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"ClipboardPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Reads text from or writes text to the system clipboard\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"clipboard\", \"io\", \"desktop\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"read\",\"description\":\"Return the current clipboard text\",\"input_type\":\"Text\",\"output_type\":\"Text\"},{\"name\":\"write\",\"description\":\"Replace the clipboard text and pass it through\",\"input_type\":\"Text\",\"output_type\":\"Text\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"read\",\"description\":\"Return the current clipboard text\",\"input_type\":\"Text\",\"output_type\":\"Text\"},{\"name\":\"write\",\"description\":\"Replace the clipboard text and pass it through\",\"input_type\":\"Text\",\"output_type\":\"Text\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<ClipboardRequest> {
+    let trimmed = input.trim();
+
+    // Text form first so that written content is never reinterpreted as YAML
+    if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("read") {
+        return Ok(ClipboardRequest::Read);
+    }
+    if let Some(text) = input.trim_start().strip_prefix("write:") {
+        return Ok(ClipboardRequest::Write {
+            text: text.strip_prefix(' ').unwrap_or(text).to_string(),
+        });
+    }
+
+    match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => {
+            serde_yaml::from_value(value).map_err(|e| anyhow!("invalid clipboard request: {}", e))
+        }
+        _ => Err(anyhow!("expected 'read' or 'write: <text>'")),
+    }
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let mut clipboard =
+        arboard::Clipboard::new().map_err(|e| anyhow!("clipboard unavailable: {}", e))?;
+
+    match request {
+        ClipboardRequest::Read => clipboard
+            .get_text()
+            .map_err(|e| anyhow!("failed to read clipboard: {}", e)),
+        ClipboardRequest::Write { text } => {
+            clipboard
+                .set_text(text.clone())
+                .map_err(|e| anyhow!("failed to write clipboard: {}", e))?;
+            Ok(text)
+        }
+    }
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "ClipboardPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_text_request() {
+        assert_eq!(parse_request("").unwrap(), ClipboardRequest::Read);
+        assert_eq!(parse_request(" READ\n").unwrap(), ClipboardRequest::Read);
+        assert_eq!(
+            parse_request("write: action: read\nsecond line").unwrap(),
+            ClipboardRequest::Write {
+                text: "action: read\nsecond line".to_string()
+            }
+        );
+        assert!(parse_request("paste everything").is_err());
+    }
+
+    #[test]
+    fn test_parse_yaml_request() {
+        assert_eq!(
+            parse_request("action: read\n").unwrap(),
+            ClipboardRequest::Read
+        );
+        assert_eq!(
+            parse_request("action: write\ntext: hello\n").unwrap(),
+            ClipboardRequest::Write {
+                text: "hello".to_string()
+            }
+        );
+        assert!(parse_request("action: write\n").is_err());
+    }
+}
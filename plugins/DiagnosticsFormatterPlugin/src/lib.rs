@@ -0,0 +1,230 @@
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::os::raw::c_char;
+
+// Mirrors the report emitted by CargoAnalyzerPlugin; unknown fields are ignored
+#[derive(Debug, Deserialize)]
+struct AnalysisReport {
+    action: String,
+    path: String,
+    success: bool,
+    #[serde(default)]
+    diagnostics: Vec<Diagnostic>,
+    #[serde(default)]
+    tests: Vec<TestCase>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Diagnostic {
+    level: String,
+    code: Option<String>,
+    message: String,
+    file: Option<String>,
+    line: Option<u64>,
+    column: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TestCase {
+    name: String,
+    status: String,
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"DiagnosticsFormatterPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        return PluginOutput::null();
+    }
+    let text = (*input).to_string_lossy();
+    let result = match serde_json::from_str::<AnalysisReport>(&text) {
+        Ok(report) => format_report(&report),
+        Err(e) => format!("error: input is not an analysis report: {}", e),
+    };
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for DiagnosticsFormatterPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"DiagnosticsFormatterPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Formats structured cargo diagnostics into a readable report\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"code\", \"rust\", \"report\", \"formatting\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"format-diagnostics\",\"description\":\"Render an analysis report as Markdown\",\"input_type\":\"Json\",\"output_type\":\"Text\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    serde_json::from_str::<AnalysisReport>(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"format-diagnostics\",\"description\":\"Render an analysis report as Markdown\",\"input_type\":\"Json\",\"output_type\":\"Text\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn format_report(report: &AnalysisReport) -> String {
+    let count = |level: &str| {
+        report
+            .diagnostics
+            .iter()
+            .filter(|d| d.level == level)
+            .count()
+    };
+    let failed_tests: Vec<&TestCase> = report
+        .tests
+        .iter()
+        .filter(|t| t.status == "failed")
+        .collect();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "## cargo {} report for `{}`",
+        report.action, report.path
+    );
+    let _ = write!(
+        out,
+        "\nResult: {} ({} errors, {} warnings",
+        if report.success { "passed" } else { "failed" },
+        count("error"),
+        count("warning")
+    );
+    if !report.tests.is_empty() {
+        let passed = report.tests.iter().filter(|t| t.status == "passed").count();
+        let _ = write!(
+            out,
+            ", {} tests passed, {} failed",
+            passed,
+            failed_tests.len()
+        );
+    }
+    out.push_str(")\n");
+
+    for (level, heading) in [("error", "Errors"), ("warning", "Warnings")] {
+        let items: Vec<&Diagnostic> = report
+            .diagnostics
+            .iter()
+            .filter(|d| d.level == level)
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n### {}", heading);
+        for d in items {
+            let location = match (&d.file, d.line, d.column) {
+                (Some(file), Some(line), Some(col)) => format!("{}:{}:{}", file, line, col),
+                (Some(file), Some(line), None) => format!("{}:{}", file, line),
+                (Some(file), None, _) => file.clone(),
+                _ => "<unknown>".to_string(),
+            };
+            match &d.code {
+                Some(code) => {
+                    let _ = writeln!(out, "- `{}` [{}] {}", location, code, d.message);
+                }
+                None => {
+                    let _ = writeln!(out, "- `{}` {}", location, d.message);
+                }
+            }
+        }
+    }
+
+    if !failed_tests.is_empty() {
+        out.push_str("\n### Failed tests\n");
+        for t in failed_tests {
+            let _ = writeln!(out, "- {}", t.name);
+        }
+    }
+
+    out
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "DiagnosticsFormatterPlugin");
+        }
+    }
+
+    #[test]
+    fn test_format_report() {
+        let report: AnalysisReport = serde_json::from_str(
+            r#"{"action":"clippy","path":".","success":false,
+                "diagnostics":[
+                  {"level":"error","code":"E0308","message":"mismatched types","file":"src/main.rs","line":4,"column":12},
+                  {"level":"warning","code":null,"message":"unused import","file":"src/lib.rs","line":1,"column":null}
+                ],
+                "tests":[{"name":"tests::a","status":"failed"}]}"#,
+        )
+        .unwrap();
+        let text = format_report(&report);
+        assert!(text.starts_with("## cargo clippy report"));
+        assert!(text.contains("failed (1 errors, 1 warnings, 0 tests passed, 1 failed)"));
+        assert!(text.contains("- `src/main.rs:4:12` [E0308] mismatched types"));
+        assert!(text.contains("- `src/lib.rs:1` unused import"));
+        assert!(text.contains("### Failed tests\n- tests::a"));
+    }
+}
@@ -1,6 +1,77 @@
-use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr};
-use std::ffi::{CStr, CString};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ffi::CString;
 use std::os::raw::c_char;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Failures handed out so far per text, for `fail_times`
+static FAILURES: Mutex<Option<HashMap<String, u32>>> = Mutex::new(None);
+
+// Testing options; only mappings with a `text` key are treated as options
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EchoRequest {
+    text: String,
+    /// Artificial latency before answering
+    #[serde(default)]
+    delay_ms: u64,
+    /// Random extra latency, up to this many milliseconds
+    #[serde(default)]
+    jitter_ms: u64,
+    /// Probability (0.0-1.0) that a call fails
+    #[serde(default)]
+    fail_rate: f64,
+    /// Fail this many calls for the same text before succeeding, to exercise retries
+    #[serde(default)]
+    fail_times: u32,
+    #[serde(default)]
+    error_message: Option<String>,
+    /// Seed for the latency and failure dice, for reproducible runs
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    transform: Transforms,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    suffix: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Transforms {
+    One(Transform),
+    Many(Vec<Transform>),
+}
+
+impl Default for Transforms {
+    fn default() -> Self {
+        Transforms::Many(Vec::new())
+    }
+}
+
+impl Transforms {
+    fn as_slice(&self) -> &[Transform] {
+        match self {
+            Transforms::One(t) => std::slice::from_ref(t),
+            Transforms::Many(ts) => ts,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Transform {
+    Upper,
+    Lower,
+    Reverse,
+    Trim,
+    Length,
+}
 
 unsafe extern "C" fn name() -> *const c_char {
     CString::new("EchoPlugin").unwrap().into_raw()
@@ -9,35 +80,111 @@ unsafe extern "C" fn name() -> *const c_char {
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
         println!("[EchoPlugin] Received null input");
-        return PluginOutput {
-            text: std::ptr::null_mut(),
-        };
+        return PluginOutput::null();
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let s = c_str.to_string_lossy();
+    let s = (*input).to_string_lossy();
     println!("[EchoPlugin] Received input: {}", s);
 
-    // Validate input - should be a simple string, not YAML object or empty
-    if s.trim().is_empty() || s.contains("not:") || s.contains("{") || s.contains("}") {
-        let error_msg = "error: invalid input for Echo plugin";
-        let out = CString::new(error_msg).unwrap();
-        println!("[EchoPlugin] Returning error: {}", error_msg);
-        return PluginOutput {
-            text: out.into_raw(),
-        };
+    let result = match parse_request(&s) {
+        Ok(request) => echo(&request),
+        Err(e) => Err(e),
+    };
+    let text = match result {
+        Ok(text) => text,
+        Err(e) => {
+            let error_msg = format!("error: {}", e);
+            println!("[EchoPlugin] Returning error: {}", error_msg);
+            error_msg
+        }
+    };
+
+    println!("[EchoPlugin] Returning output: {}", text);
+    PluginOutput::new(text)
+}
+
+fn parse_request(input: &str) -> Result<EchoRequest, String> {
+    let options = match serde_yaml::from_str::<serde_yaml::Value>(input) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) if value.get("text").is_some() => Some(value),
+        _ => None,
+    };
+    let request = match options {
+        Some(value) => serde_yaml::from_value::<EchoRequest>(value)
+            .map_err(|e| format!("invalid input for Echo plugin: {}", e))?,
+        // Plain text should be a simple string, not a YAML object or empty
+        None if input.trim().is_empty()
+            || input.contains("not:")
+            || input.contains('{')
+            || input.contains('}') =>
+        {
+            return Err("invalid input for Echo plugin".to_string());
+        }
+        None => EchoRequest {
+            text: input.to_string(),
+            ..Default::default()
+        },
+    };
+
+    if !(0.0..=1.0).contains(&request.fail_rate) {
+        return Err("fail_rate must be between 0.0 and 1.0".to_string());
     }
+    Ok(request)
+}
+
+fn echo(request: &EchoRequest) -> Result<String, String> {
+    let mut rng = match request.seed {
+        Some(seed) => fastrand::Rng::with_seed(seed),
+        None => fastrand::Rng::new(),
+    };
 
-    let out = CString::new(s.as_ref()).unwrap();
-    println!("[EchoPlugin] Returning output: {}", out.to_string_lossy());
-    PluginOutput {
-        text: out.into_raw(),
+    let jitter = match request.jitter_ms {
+        0 => 0,
+        max => rng.u64(0..=max),
+    };
+    if request.delay_ms + jitter > 0 {
+        std::thread::sleep(Duration::from_millis(request.delay_ms + jitter));
     }
+
+    let message = || {
+        request
+            .error_message
+            .clone()
+            .unwrap_or_else(|| "simulated failure".to_string())
+    };
+    if request.fail_times > 0 {
+        let mut guard = FAILURES.lock().unwrap_or_else(|e| e.into_inner());
+        let failures = guard.get_or_insert_with(HashMap::new);
+        let count = failures.entry(request.text.clone()).or_insert(0);
+        if *count < request.fail_times {
+            *count += 1;
+            return Err(format!(
+                "{} (attempt {} of {} planned failures)",
+                message(),
+                count,
+                request.fail_times
+            ));
+        }
+        // Start over so the next run sees the same failure pattern
+        failures.remove(&request.text);
+    }
+    if request.fail_rate > 0.0 && rng.f64() < request.fail_rate {
+        return Err(message());
+    }
+
+    let mut text = request.text.clone();
+    for transform in request.transform.as_slice() {
+        text = match transform {
+            Transform::Upper => text.to_uppercase(),
+            Transform::Lower => text.to_lowercase(),
+            Transform::Reverse => text.chars().rev().collect(),
+            Transform::Trim => text.trim().to_string(),
+            Transform::Length => text.chars().count().to_string(),
+        };
+    }
+    Ok(format!("{}{}{}", request.prefix, text, request.suffix))
 }
 
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 unsafe extern "C" fn run_with_buffer(
@@ -48,8 +195,7 @@ unsafe extern "C" fn run_with_buffer(
     if input.is_null() || buffer.is_null() || buffer_len == 0 {
         return 0;
     }
-    let c_str = std::ffi::CStr::from_ptr((*input).text);
-    let bytes = c_str.to_bytes();
+    let bytes = (*input).as_bytes();
     if bytes.is_empty() {
         return 0;
     }
@@ -63,9 +209,10 @@ unsafe extern "C" fn get_metadata() -> PluginMetadata {
     // Use simple static strings with proper null termination
     static NAME: &str = "EchoPlugin\0";
     static VERSION: &str = "1.0.0\0";
-    static DESCRIPTION: &str = "Simple echo plugin for LAO\0";
+    static DESCRIPTION: &str =
+        "Echo plugin for LAO with optional delay, failures and transforms for testing\0";
     static AUTHOR: &str = "LAO Team\0";
-    static TAGS: &str = "[\"echo\", \"test\", \"debug\"]\0";
+    static TAGS: &str = "[\"echo\", \"test\", \"debug\", \"mock\"]\0";
     static CAPABILITIES: &str = "[{\"name\":\"echo\",\"description\":\"Echo input back as output\",\"input_type\":\"Text\",\"output_type\":\"Text\"}]\0";
 
     PluginMetadata {
@@ -85,9 +232,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
     if input.is_null() {
         return false;
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let text = c_str.to_string_lossy();
-    !text.trim().is_empty() && !text.contains("not:") && !text.contains("{") && !text.contains("}")
+    parse_request(&(*input).to_string_lossy()).is_ok()
 }
 
 unsafe extern "C" fn get_capabilities() -> *const c_char {
@@ -97,7 +242,7 @@ unsafe extern "C" fn get_capabilities() -> *const c_char {
 
 #[no_mangle]
 pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -112,11 +257,19 @@ pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
 
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use lao_plugin_api::*;
-    use std::ffi::CString;
+    use lao_plugin_api::PluginInputBuf;
+    use std::ffi::CStr;
 
     #[test]
     fn test_plugin_name() {
@@ -131,22 +284,16 @@ mod tests {
     #[test]
     fn test_validate_input() {
         unsafe {
-            let valid_input = CString::new("Hello World").unwrap();
-            let input = PluginInput {
-                text: valid_input.into_raw(),
-            };
+            let valid_input = PluginInputBuf::new("Hello World");
+            let input = valid_input.as_input();
             assert!(validate_input(&input));
 
-            let empty_input = CString::new("").unwrap();
-            let input = PluginInput {
-                text: empty_input.into_raw(),
-            };
+            let empty_input = PluginInputBuf::new("");
+            let input = empty_input.as_input();
             assert!(!validate_input(&input));
 
-            let forbidden_input = CString::new("not: allowed").unwrap();
-            let input = PluginInput {
-                text: forbidden_input.into_raw(),
-            };
+            let forbidden_input = PluginInputBuf::new("not: allowed");
+            let input = forbidden_input.as_input();
             assert!(!validate_input(&input));
         }
     }
@@ -154,14 +301,11 @@ mod tests {
     #[test]
     fn test_run_success() {
         unsafe {
-            let input_text = CString::new("Echo").unwrap();
-            let input = PluginInput {
-                text: input_text.into_raw(),
-            };
+            let input_text = PluginInputBuf::new("Echo");
+            let input = input_text.as_input();
 
             let output = run(&input);
-            let output_cstr = CStr::from_ptr(output.text);
-            let output_str = output_cstr.to_str().unwrap();
+            let output_str = std::str::from_utf8(output.as_bytes()).unwrap();
 
             assert_eq!(output_str, "Echo");
 
@@ -172,18 +316,67 @@ mod tests {
     #[test]
     fn test_run_error() {
         unsafe {
-            let input_text = CString::new("{ invalid json }").unwrap();
-            let input = PluginInput {
-                text: input_text.into_raw(),
-            };
+            let input_text = PluginInputBuf::new("{ invalid json }");
+            let input = input_text.as_input();
 
             let output = run(&input);
-            let output_cstr = CStr::from_ptr(output.text);
-            let output_str = output_cstr.to_str().unwrap();
+            let output_str = std::str::from_utf8(output.as_bytes()).unwrap();
 
             assert!(output_str.contains("error: invalid input"));
 
             free_output(output);
         }
     }
+
+    #[test]
+    fn test_run_keeps_embedded_nul() {
+        unsafe {
+            let input_text = PluginInputBuf::new("before\0after");
+            let input = input_text.as_input();
+
+            let output = run(&input);
+            assert_eq!(output.as_bytes(), b"before\0after");
+
+            free_output(output);
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request("Step 1: Hello from echo!").unwrap();
+        assert_eq!(request.text, "Step 1: Hello from echo!");
+
+        let request = parse_request("text: hi\ndelay_ms: 5\ntransform: upper").unwrap();
+        assert_eq!(request.delay_ms, 5);
+        assert_eq!(request.transform.as_slice(), &[Transform::Upper]);
+
+        assert!(parse_request("text: hi\ndelay: 5").is_err());
+        assert!(parse_request("text: hi\nfail_rate: 2").is_err());
+    }
+
+    #[test]
+    fn test_transforms() {
+        let request =
+            parse_request("text: \" Hello \"\ntransform: [trim, reverse, lower]\nprefix: \"> \"")
+                .unwrap();
+        assert_eq!(echo(&request).unwrap(), "> olleh");
+
+        let request = parse_request("text: hello\ntransform: length").unwrap();
+        assert_eq!(echo(&request).unwrap(), "5");
+    }
+
+    #[test]
+    fn test_simulated_failures() {
+        let request = parse_request("text: flaky-echo-test\nfail_times: 2").unwrap();
+        assert!(echo(&request).unwrap_err().contains("attempt 1 of 2"));
+        assert!(echo(&request).unwrap_err().contains("attempt 2 of 2"));
+        assert_eq!(echo(&request).unwrap(), "flaky-echo-test");
+
+        let request =
+            parse_request("text: doomed\nfail_rate: 1.0\nerror_message: backend offline").unwrap();
+        assert_eq!(echo(&request).unwrap_err(), "backend offline");
+
+        let request = parse_request("text: safe\nfail_rate: 0.0\njitter_ms: 3\nseed: 7").unwrap();
+        assert_eq!(echo(&request).unwrap(), "safe");
+    }
 }
@@ -0,0 +1,300 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::os::raw::c_char;
+use std::time::Duration;
+
+// Feed request, accepted either as YAML params or as whitespace-separated URLs
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FeedRequest {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub urls: Vec<String>,
+    /// Maximum number of items to return across all feeds
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Only keep items published or updated after this RFC 3339 timestamp
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl FeedRequest {
+    fn sources(&self) -> Vec<String> {
+        self.url.iter().chain(self.urls.iter()).cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedItem {
+    pub feed: String,
+    pub id: String,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+    pub summary: Option<String>,
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"FeedPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for FeedPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"FeedPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Fetches RSS/Atom feeds and outputs their items as JSON\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"feed\", \"rss\", \"atom\", \"web\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"fetch-feed\",\"description\":\"Fetch one or more feeds and list their items\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"fetch-feed\",\"description\":\"Fetch one or more feeds and list their items\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<FeedRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => FeedRequest {
+            url: None,
+            urls: trimmed.split_whitespace().map(str::to_string).collect(),
+            limit: None,
+            since: None,
+        },
+    };
+
+    if request.sources().is_empty() {
+        return Err(anyhow!("no feed url given"));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let mut items = Vec::new();
+    for source in request.sources() {
+        let body = fetch_source(&client, &source)?;
+        let feed = feed_rs::parser::parse(body.as_slice())
+            .map_err(|e| anyhow!("failed to parse feed {}: {}", source, e))?;
+        items.extend(feed_items(&source, feed));
+    }
+
+    let items = select_items(items, &request);
+    Ok(serde_json::to_string_pretty(&items)?)
+}
+
+fn fetch_source(client: &reqwest::blocking::Client, source: &str) -> Result<Vec<u8>> {
+    if !source.starts_with("http://") && !source.starts_with("https://") {
+        let path = source.strip_prefix("file://").unwrap_or(source);
+        return std::fs::read(path).map_err(|e| anyhow!("failed to read feed {}: {}", path, e));
+    }
+
+    let response = client
+        .get(source)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| anyhow!("failed to fetch feed {}: {}", source, e))?;
+    Ok(response.bytes()?.to_vec())
+}
+
+fn feed_items(source: &str, feed: feed_rs::model::Feed) -> Vec<FeedItem> {
+    let feed_title = feed
+        .title
+        .map(|t| t.content)
+        .unwrap_or_else(|| source.to_string());
+
+    feed.entries
+        .into_iter()
+        .map(|entry| FeedItem {
+            feed: feed_title.clone(),
+            id: entry.id,
+            title: entry.title.map(|t| t.content),
+            link: entry.links.into_iter().next().map(|l| l.href),
+            published: entry.published.or(entry.updated),
+            summary: entry
+                .summary
+                .map(|s| s.content)
+                .or_else(|| entry.content.and_then(|c| c.body)),
+        })
+        .collect()
+}
+
+// Newest first, filtered by `since` and truncated to `limit`
+fn select_items(mut items: Vec<FeedItem>, request: &FeedRequest) -> Vec<FeedItem> {
+    if let Some(since) = request.since {
+        let before = items.len();
+        items.retain(|item| item.published.is_some_and(|p| p > since));
+        if items.len() < before {
+            info!(
+                "Dropped {} items older than {}",
+                before - items.len(),
+                since
+            );
+        }
+    }
+    items.sort_by_key(|item| std::cmp::Reverse(item.published));
+    if let Some(limit) = request.limit {
+        if items.len() > limit {
+            warn!("Truncating {} items to limit {}", items.len(), limit);
+            items.truncate(limit);
+        }
+    }
+    items
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example Blog</title>
+  <id>urn:example</id>
+  <updated>2024-03-02T00:00:00Z</updated>
+  <entry>
+    <title>Older post</title>
+    <id>urn:example:1</id>
+    <link href="https://example.com/1"/>
+    <updated>2024-03-01T00:00:00Z</updated>
+    <summary>First</summary>
+  </entry>
+  <entry>
+    <title>Newer post</title>
+    <id>urn:example:2</id>
+    <link href="https://example.com/2"/>
+    <updated>2024-03-02T00:00:00Z</updated>
+    <summary>Second</summary>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "FeedPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("https://a.example/rss https://b.example/atom").unwrap();
+        assert_eq!(req.sources().len(), 2);
+
+        let req =
+            parse_request("url: https://a.example/rss\nlimit: 5\nsince: 2024-01-01T00:00:00Z")
+                .unwrap();
+        assert_eq!(req.sources(), vec!["https://a.example/rss"]);
+        assert_eq!(req.limit, Some(5));
+        assert!(req.since.is_some());
+
+        assert!(parse_request("  ").is_err());
+    }
+
+    #[test]
+    fn test_feed_items_are_filtered_and_sorted() {
+        let feed = feed_rs::parser::parse(ATOM.as_bytes()).unwrap();
+        let items = feed_items("urn:example", feed);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].feed, "Example Blog");
+
+        let request =
+            parse_request("url: feed.xml\nsince: 2024-03-01T12:00:00Z\nlimit: 10").unwrap();
+        let selected = select_items(items.clone(), &request);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].title.as_deref(), Some("Newer post"));
+        assert_eq!(selected[0].link.as_deref(), Some("https://example.com/2"));
+
+        let request = parse_request("url: feed.xml\nlimit: 1").unwrap();
+        let selected = select_items(items, &request);
+        assert_eq!(selected[0].id, "urn:example:2");
+    }
+}
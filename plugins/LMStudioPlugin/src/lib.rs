@@ -1,5 +1,4 @@
-use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr};
-use std::ffi::{CStr, CString};
+use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr, ABI_VERSION};
 use std::os::raw::c_char;
 
 unsafe extern "C" fn name() -> *const c_char {
@@ -8,21 +7,14 @@ unsafe extern "C" fn name() -> *const c_char {
 
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
-        return PluginOutput {
-            text: std::ptr::null_mut(),
-        };
+        return PluginOutput::null();
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let prompt = c_str.to_string_lossy();
-    let out = format!("[LM Studio output for prompt: {}]", prompt);
-    let text = CString::new(out).unwrap().into_raw();
-    PluginOutput { text }
+    let prompt = (*input).to_string_lossy();
+    PluginOutput::new(format!("[LM Studio output for prompt: {}]", prompt))
 }
 
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 unsafe extern "C" fn run_with_buffer(
@@ -59,8 +51,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
     if input.is_null() {
         return false;
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let text = c_str.to_string_lossy();
+    let text = (*input).to_string_lossy();
     !text.trim().is_empty()
 }
 
@@ -71,7 +62,7 @@ unsafe extern "C" fn get_capabilities() -> *const c_char {
 
 #[no_mangle]
 pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -85,3 +76,11 @@ pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginV
 pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
@@ -0,0 +1,442 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexWriter, SnippetGenerator, TantivyDocument};
+
+const MANIFEST_FILE: &str = "lao-manifest.json";
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+
+// Search request, accepted either as YAML params or as a bare query string
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SearchRequest {
+    #[serde(default = "default_action")]
+    pub action: String,
+    /// Directory to index; text queries fall back to LAO_SEARCH_PATH or "."
+    #[serde(default = "default_path")]
+    pub path: String,
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    /// Where to keep the index; defaults to a per-directory folder under the LAO cache
+    #[serde(default)]
+    pub index_dir: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub path: String,
+    pub score: f32,
+    pub snippet: String,
+}
+
+fn default_action() -> String {
+    "search".to_string()
+}
+
+fn default_path() -> String {
+    std::env::var("LAO_SEARCH_PATH").unwrap_or_else(|_| ".".to_string())
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+fn default_extensions() -> Vec<String> {
+    ["md", "markdown", "txt", "org", "rst"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+struct Fields {
+    path: Field,
+    title: Field,
+    body: Field,
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        path: builder.add_text_field("path", STRING | STORED),
+        title: builder.add_text_field("title", TEXT | STORED),
+        body: builder.add_text_field("body", TEXT | STORED),
+    };
+    (builder.build(), fields)
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"LocalSearchPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result.replace('\0', ""))
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for LocalSearchPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"LocalSearchPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] =
+        b"Indexes local text files and answers keyword queries with ranked snippets\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"search\", \"retrieval\", \"notes\", \"files\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"search\",\"description\":\"Search an indexed directory and return ranked snippets\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"index\",\"description\":\"Rebuild the index for a directory\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"search\",\"description\":\"Search an indexed directory and return ranked snippets\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"index\",\"description\":\"Rebuild the index for a directory\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<SearchRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => SearchRequest {
+            action: default_action(),
+            path: default_path(),
+            query: Some(trimmed.to_string()),
+            limit: default_limit(),
+            extensions: default_extensions(),
+            index_dir: None,
+        },
+    };
+
+    match request.action.as_str() {
+        "index" => Ok(request),
+        "search"
+            if request
+                .query
+                .as_deref()
+                .is_some_and(|q| !q.trim().is_empty()) =>
+        {
+            Ok(request)
+        }
+        "search" => Err(anyhow!("'search' needs a non-empty query")),
+        other => Err(anyhow!(
+            "unsupported action '{}', expected 'search' or 'index'",
+            other
+        )),
+    }
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let root = Path::new(&request.path);
+    if !root.is_dir() {
+        return Err(anyhow!("{} is not a directory", root.display()));
+    }
+    let index_dir = match &request.index_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => default_index_dir(root)?,
+    };
+
+    let files = scan_files(root, &index_dir, &request.extensions);
+    let index = open_or_rebuild(&index_dir, &files, request.action == "index")?;
+
+    if request.action == "index" {
+        return Ok(serde_json::to_string_pretty(&serde_json::json!({
+            "path": request.path,
+            "index_dir": index_dir.to_string_lossy(),
+            "files": files.len(),
+        }))?);
+    }
+
+    let hits = search(
+        &index,
+        request.query.as_deref().unwrap_or_default(),
+        request.limit,
+    )?;
+    Ok(serde_json::to_string_pretty(&hits)?)
+}
+
+// One index per directory under the shared LAO cache
+fn default_index_dir(root: &Path) -> Result<PathBuf> {
+    let canonical = root.canonicalize()?;
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in canonical.to_string_lossy().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    let cache_dir = std::env::var("LAO_CACHE_DIR").unwrap_or_else(|_| "cache".to_string());
+    Ok(Path::new(&cache_dir)
+        .join("search")
+        .join(format!("{:016x}", hash)))
+}
+
+// Indexable files mapped to their modification time, skipping hidden paths
+fn scan_files(root: &Path, index_dir: &Path, extensions: &[String]) -> BTreeMap<String, u64> {
+    let index_dir = index_dir.canonicalize().ok();
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| {
+            let hidden = e.depth() > 0 && e.file_name().to_string_lossy().starts_with('.');
+            let is_index = index_dir
+                .as_deref()
+                .is_some_and(|dir| e.path().canonicalize().is_ok_and(|p| p == dir));
+            !hidden && !is_index
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.iter().any(|x| x.eq_ignore_ascii_case(ext)))
+        })
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            if meta.len() > MAX_FILE_BYTES {
+                warn!("Skipping large file {}", e.path().display());
+                return None;
+            }
+            let modified = meta
+                .modified()
+                .ok()?
+                .duration_since(UNIX_EPOCH)
+                .ok()?
+                .as_secs();
+            Some((e.path().to_string_lossy().to_string(), modified))
+        })
+        .collect()
+}
+
+// Reuse the index while the file manifest is unchanged, otherwise rebuild from scratch
+fn open_or_rebuild(index_dir: &Path, files: &BTreeMap<String, u64>, force: bool) -> Result<Index> {
+    let manifest_path = index_dir.join(MANIFEST_FILE);
+    let unchanged = std::fs::read_to_string(&manifest_path)
+        .ok()
+        .and_then(|m| serde_json::from_str::<BTreeMap<String, u64>>(&m).ok())
+        .is_some_and(|previous| &previous == files);
+    if unchanged && !force {
+        if let Ok(index) = Index::open_in_dir(index_dir) {
+            return Ok(index);
+        }
+    }
+
+    info!(
+        "Indexing {} files into {}",
+        files.len(),
+        index_dir.display()
+    );
+    if index_dir.exists() {
+        std::fs::remove_dir_all(index_dir)?;
+    }
+    std::fs::create_dir_all(index_dir)?;
+
+    let (schema, fields) = schema();
+    let index = Index::create_in_dir(index_dir, schema)?;
+    let mut writer: IndexWriter = index.writer_with_num_threads(1, 50_000_000)?;
+    for path in files.keys() {
+        let Ok(body) = std::fs::read_to_string(path) else {
+            warn!("Skipping unreadable file {}", path);
+            continue;
+        };
+        let title = Path::new(path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        writer.add_document(doc!(
+            fields.path => path.as_str(),
+            fields.title => title,
+            fields.body => body,
+        ))?;
+    }
+    writer.commit()?;
+
+    std::fs::write(&manifest_path, serde_json::to_string(files)?)?;
+    Ok(index)
+}
+
+fn search(index: &Index, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+    let (_, fields) = schema();
+    let searcher = index.reader()?.searcher();
+    let parser = QueryParser::for_index(index, vec![fields.title, fields.body]);
+    // Lenient parsing keeps natural-language questions from failing on stray syntax
+    let (query, _) = parser.parse_query_lenient(query);
+
+    let mut snippets = SnippetGenerator::create(&searcher, &*query, fields.body)?;
+    snippets.set_max_num_chars(240);
+
+    let mut hits = Vec::new();
+    for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+        let document: TantivyDocument = searcher.doc(address)?;
+        let path = document
+            .get_first(fields.path)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let snippet = snippets.snippet_from_doc(&document);
+        hits.push(SearchHit {
+            path,
+            score,
+            snippet: snippet.fragment().trim().to_string(),
+        });
+    }
+    Ok(hits)
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn notes_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("garden.md"),
+            "# Garden\nPlant tomatoes after the last frost. Water the basil daily.",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("rust.txt"),
+            "Lifetimes and borrowing are the core of the Rust ownership model.",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join(".git").join("notes.md"), "tomatoes").unwrap();
+        std::fs::write(dir.path().join("image.png"), "tomatoes").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "LocalSearchPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("how do lifetimes work?").unwrap();
+        assert_eq!(req.action, "search");
+        assert_eq!(req.query.as_deref(), Some("how do lifetimes work?"));
+
+        let req = parse_request("action: index\npath: ~/notes").unwrap();
+        assert_eq!(req.action, "index");
+        assert_eq!(req.extensions, default_extensions());
+
+        assert!(parse_request("path: ~/notes").is_err());
+        assert!(parse_request("action: delete\nquery: x").is_err());
+    }
+
+    #[test]
+    fn test_index_and_search() {
+        let notes = notes_dir();
+        let index_dir = notes.path().join(".index");
+        let files = scan_files(notes.path(), &index_dir, &default_extensions());
+        assert_eq!(files.len(), 2);
+
+        let index = open_or_rebuild(&index_dir, &files, false).unwrap();
+        let hits = search(&index, "when to plant tomatoes?", 5).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.ends_with("garden.md"));
+        assert!(hits[0].snippet.contains("tomatoes"));
+
+        // Unchanged manifest reuses the index; a new file triggers a rebuild
+        std::fs::write(notes.path().join("salsa.md"), "Tomatoes, onion, lime.").unwrap();
+        let files = scan_files(notes.path(), &index_dir, &default_extensions());
+        let index = open_or_rebuild(&index_dir, &files, false).unwrap();
+        assert_eq!(search(&index, "tomatoes", 5).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_process_input_with_params() {
+        let notes = notes_dir();
+        let input = format!(
+            "path: {}\nquery: ownership\nindex_dir: {}\n",
+            notes.path().display(),
+            notes.path().join(".index").display()
+        );
+        let hits: Vec<SearchHit> = serde_json::from_str(&process_input(&input).unwrap()).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.ends_with("rust.txt"));
+    }
+}
@@ -0,0 +1,188 @@
+// Minimal IMAP4rev1 client covering the read-only commands MailPlugin needs
+use anyhow::{anyhow, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+pub trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+// A response line plus the literal (`{N}` bytes) that followed it, if any
+#[derive(Debug)]
+pub struct ResponseLine {
+    pub text: String,
+    pub literal: Option<Vec<u8>>,
+}
+
+pub struct ImapSession {
+    reader: BufReader<Box<dyn Stream>>,
+    next_tag: u32,
+}
+
+impl ImapSession {
+    pub fn connect(host: &str, port: u16, tls: bool) -> Result<Self> {
+        let tcp = TcpStream::connect((host, port))
+            .map_err(|e| anyhow!("failed to connect to {}:{}: {}", host, port, e))?;
+        tcp.set_read_timeout(Some(Duration::from_secs(60)))?;
+
+        let stream: Box<dyn Stream> = if tls {
+            let connector = native_tls::TlsConnector::new()?;
+            let tls_stream = connector
+                .connect(host, tcp)
+                .map_err(|e| anyhow!("TLS handshake with {} failed: {}", host, e))?;
+            Box::new(tls_stream)
+        } else {
+            Box::new(tcp)
+        };
+        Self::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: Box<dyn Stream>) -> Result<Self> {
+        let mut session = ImapSession {
+            reader: BufReader::new(stream),
+            next_tag: 0,
+        };
+        let greeting = session.read_line()?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            return Err(anyhow!("unexpected IMAP greeting: {}", greeting));
+        }
+        Ok(session)
+    }
+
+    pub fn login(&mut self, username: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(username), quote(password)))
+            .map(|_| ())
+            .map_err(|e| anyhow!("IMAP login failed: {}", e))
+    }
+
+    // EXAMINE opens the mailbox read-only so fetching never changes flags
+    pub fn examine(&mut self, mailbox: &str) -> Result<()> {
+        self.command(&format!("EXAMINE {}", quote(mailbox)))
+            .map(|_| ())
+    }
+
+    pub fn uid_search(&mut self, criteria: &str) -> Result<Vec<u32>> {
+        let lines = self.command(&format!("UID SEARCH {}", criteria))?;
+        Ok(lines
+            .iter()
+            .filter_map(|l| l.text.strip_prefix("* SEARCH"))
+            .flat_map(|ids| ids.split_whitespace().filter_map(|id| id.parse().ok()))
+            .collect())
+    }
+
+    // Returns the raw RFC 822 message and whether it carries the \Seen flag
+    pub fn uid_fetch(&mut self, uid: u32) -> Result<Option<(Vec<u8>, bool)>> {
+        let lines = self.command(&format!("UID FETCH {} (FLAGS BODY.PEEK[])", uid))?;
+        Ok(lines.into_iter().find_map(|line| {
+            let seen = line.text.contains("\\Seen");
+            line.literal.map(|raw| (raw, seen))
+        }))
+    }
+
+    pub fn logout(&mut self) -> Result<()> {
+        self.command("LOGOUT").map(|_| ())
+    }
+
+    fn command(&mut self, command: &str) -> Result<Vec<ResponseLine>> {
+        self.next_tag += 1;
+        let tag = format!("a{}", self.next_tag);
+        let stream = self.reader.get_mut();
+        stream.write_all(format!("{} {}\r\n", tag, command).as_bytes())?;
+        stream.flush()?;
+
+        let mut lines = Vec::new();
+        loop {
+            let text = self.read_line()?;
+            if let Some(status) = text.strip_prefix(&tag) {
+                let status = status.trim();
+                if status.starts_with("OK") {
+                    return Ok(lines);
+                }
+                return Err(anyhow!("{}", status));
+            }
+            let literal = match literal_size(&text) {
+                Some(size) => {
+                    let mut buf = vec![0; size];
+                    self.reader.read_exact(&mut buf)?;
+                    Some(buf)
+                }
+                None => None,
+            };
+            lines.push(ResponseLine { text, literal });
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut buf = Vec::new();
+        if self.reader.read_until(b'\n', &mut buf)? == 0 {
+            return Err(anyhow!("IMAP server closed the connection"));
+        }
+        Ok(String::from_utf8_lossy(&buf).trim_end().to_string())
+    }
+}
+
+fn literal_size(line: &str) -> Option<usize> {
+    let rest = line.strip_suffix('}')?;
+    let start = rest.rfind('{')?;
+    rest[start + 1..].parse().ok()
+}
+
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Replays canned server output and discards whatever the client writes
+    struct Replay(Cursor<Vec<u8>>);
+
+    impl Read for Replay {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for Replay {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_session_search_and_fetch() {
+        let raw = "Subject: hi\r\n\r\nbody\r\n";
+        let script = format!(
+            "* OK ready\r\n\
+             a1 OK logged in\r\n\
+             * 3 EXISTS\r\na2 OK [READ-ONLY] done\r\n\
+             * SEARCH 4 9\r\na3 OK done\r\n\
+             * 2 FETCH (UID 9 FLAGS (\\Seen) BODY[] {{{}}}\r\n{})\r\na4 OK done\r\n\
+             a5 NO [AUTHENTICATIONFAILED] nope\r\n",
+            raw.len(),
+            raw
+        );
+        let stream = Box::new(Replay(Cursor::new(script.into_bytes())));
+        let mut session = ImapSession::from_stream(stream).unwrap();
+
+        session.login("me", "secret").unwrap();
+        session.examine("INBOX").unwrap();
+        assert_eq!(session.uid_search("ALL").unwrap(), vec![4, 9]);
+        let (body, seen) = session.uid_fetch(9).unwrap().unwrap();
+        assert_eq!(body, raw.as_bytes());
+        assert!(seen);
+        assert!(session.logout().is_err());
+    }
+
+    #[test]
+    fn test_quote_and_literal_size() {
+        assert_eq!(quote(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(literal_size("* 1 FETCH (BODY[] {42}"), Some(42));
+        assert_eq!(literal_size("* 1 FETCH (FLAGS ())"), None);
+    }
+}
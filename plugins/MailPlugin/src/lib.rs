@@ -0,0 +1,479 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info, warn};
+use mail_parser::{Address, MessageParser};
+use serde::{Deserialize, Serialize};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+mod imap;
+
+// Mail request, accepted either as YAML params or as a bare maildir path
+#[derive(Debug, Clone, Deserialize)]
+pub struct MailRequest {
+    #[serde(default)]
+    pub maildir: Option<String>,
+    #[serde(default)]
+    pub imap: Option<ImapConfig>,
+    #[serde(default)]
+    pub query: MailQuery,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    #[serde(default = "default_max_body_chars")]
+    pub max_body_chars: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImapConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub username: String,
+    /// Environment variable holding the password, so it never appears in workflow files
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MailQuery {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub since: Option<NaiveDate>,
+    #[serde(default)]
+    pub unseen: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailMessage {
+    pub id: String,
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub subject: Option<String>,
+    pub date: Option<String>,
+    pub seen: bool,
+    pub body: String,
+    #[serde(skip)]
+    timestamp: Option<i64>,
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+fn default_max_body_chars() -> usize {
+    4000
+}
+
+fn default_port() -> u16 {
+    993
+}
+
+fn default_password_env() -> String {
+    "LAO_IMAP_PASSWORD".to_string()
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"MailPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing mail request");
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result.replace('\0', ""))
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for MailPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"MailPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Fetches email from IMAP or a local maildir as structured JSON\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"mail\", \"email\", \"imap\", \"maildir\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"fetch-mail\",\"description\":\"Fetch messages matching a query\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"fetch-mail\",\"description\":\"Fetch messages matching a query\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<MailRequest> {
+    let trimmed = input.trim();
+
+    let request: MailRequest = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ if !trimmed.is_empty() => MailRequest {
+            maildir: Some(trimmed.to_string()),
+            imap: None,
+            query: MailQuery::default(),
+            limit: default_limit(),
+            max_body_chars: default_max_body_chars(),
+        },
+        _ => return Err(anyhow!("expected a maildir path or mail parameters")),
+    };
+
+    match (&request.maildir, &request.imap) {
+        (Some(_), Some(_)) => Err(anyhow!("set either 'maildir' or 'imap', not both")),
+        (None, None) => Err(anyhow!("one of 'maildir' or 'imap' is required")),
+        _ => Ok(request),
+    }
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+
+    let mut messages = match (&request.maildir, &request.imap) {
+        (Some(dir), _) => read_maildir(&expand_home(dir), &request)?,
+        (_, Some(config)) => fetch_imap(config, &request)?,
+        _ => unreachable!("validated by parse_request"),
+    };
+
+    messages.retain(|m| matches_query(m, &request.query));
+    messages.sort_by_key(|m| std::cmp::Reverse(m.timestamp));
+    messages.truncate(request.limit);
+    Ok(serde_json::to_string_pretty(&messages)?)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn read_maildir(dir: &Path, request: &MailRequest) -> Result<Vec<MailMessage>> {
+    if !dir.join("cur").is_dir() && !dir.join("new").is_dir() {
+        return Err(anyhow!("{} is not a maildir", dir.display()));
+    }
+
+    let mut messages = Vec::new();
+    for sub in ["new", "cur"] {
+        let Ok(entries) = std::fs::read_dir(dir.join(sub)) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // Maildir flags follow ":2," in the file name; S marks a message as seen
+            let seen = sub == "cur"
+                && file_name
+                    .split_once(":2,")
+                    .is_some_and(|(_, flags)| flags.contains('S'));
+            let id = file_name
+                .split_once(':')
+                .map_or(file_name.as_str(), |(id, _)| id)
+                .to_string();
+
+            match std::fs::read(entry.path()) {
+                Ok(raw) => messages.extend(parse_message(id, &raw, seen, request.max_body_chars)),
+                Err(e) => warn!("Skipping {}: {}", entry.path().display(), e),
+            }
+        }
+    }
+    Ok(messages)
+}
+
+fn fetch_imap(config: &ImapConfig, request: &MailRequest) -> Result<Vec<MailMessage>> {
+    let password = std::env::var(&config.password_env)
+        .map_err(|_| anyhow!("password variable {} is not set", config.password_env))?;
+
+    let mut session = imap::ImapSession::connect(&config.host, config.port, config.tls)?;
+    session.login(&config.username, &password)?;
+    session.examine(&config.mailbox)?;
+
+    let mut uids = session.uid_search(&search_criteria(&request.query))?;
+    uids.sort_unstable();
+    // UIDs grow with arrival order, so the tail holds the newest messages
+    let newest = &uids[uids.len().saturating_sub(request.limit)..];
+
+    let mut messages = Vec::new();
+    for uid in newest {
+        if let Some((raw, seen)) = session.uid_fetch(*uid)? {
+            messages.extend(parse_message(
+                uid.to_string(),
+                &raw,
+                seen,
+                request.max_body_chars,
+            ));
+        }
+    }
+    if let Err(e) = session.logout() {
+        warn!("IMAP logout failed: {}", e);
+    }
+    Ok(messages)
+}
+
+fn search_criteria(query: &MailQuery) -> String {
+    let mut criteria = Vec::new();
+    if let Some(from) = &query.from {
+        criteria.push(format!("FROM {}", imap::quote(from)));
+    }
+    if let Some(subject) = &query.subject {
+        criteria.push(format!("SUBJECT {}", imap::quote(subject)));
+    }
+    if let Some(since) = query.since {
+        criteria.push(format!("SINCE {}", since.format("%-d-%b-%Y")));
+    }
+    if query.unseen {
+        criteria.push("UNSEEN".to_string());
+    }
+    if criteria.is_empty() {
+        "ALL".to_string()
+    } else {
+        criteria.join(" ")
+    }
+}
+
+fn parse_message(id: String, raw: &[u8], seen: bool, max_body_chars: usize) -> Option<MailMessage> {
+    let message = MessageParser::default().parse(raw)?;
+    let body = message
+        .body_text(0)
+        .map(|b| b.trim().chars().take(max_body_chars).collect())
+        .unwrap_or_default();
+
+    Some(MailMessage {
+        id,
+        from: message.from().and_then(Address::first).map(format_addr),
+        to: message
+            .to()
+            .map(|to| to.clone().into_list().iter().map(format_addr).collect())
+            .unwrap_or_default(),
+        subject: message.subject().map(str::to_string),
+        date: message.date().map(|d| d.to_rfc3339()),
+        seen,
+        body,
+        timestamp: message.date().map(|d| d.to_timestamp()),
+    })
+}
+
+fn format_addr(addr: &mail_parser::Addr) -> String {
+    match (addr.name(), addr.address()) {
+        (Some(name), Some(address)) => format!("{} <{}>", name, address),
+        (None, Some(address)) => address.to_string(),
+        (Some(name), None) => name.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+// IMAP already filters server-side; this keeps maildir results consistent with it
+fn matches_query(message: &MailMessage, query: &MailQuery) -> bool {
+    let contains = |field: &Option<String>, needle: &str| {
+        field
+            .as_deref()
+            .is_some_and(|f| f.to_lowercase().contains(&needle.to_lowercase()))
+    };
+
+    query
+        .from
+        .as_ref()
+        .is_none_or(|f| contains(&message.from, f))
+        && query
+            .subject
+            .as_ref()
+            .is_none_or(|s| contains(&message.subject, s))
+        && query.since.is_none_or(|since| {
+            let start = since.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+            message.timestamp.is_some_and(|t| t >= start)
+        })
+        && !(query.unseen && message.seen)
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn write_message(dir: &Path, sub: &str, file: &str, from: &str, subject: &str, date: &str) {
+        let raw = format!(
+            "From: {}\r\nTo: me@example.com\r\nSubject: {}\r\nDate: {}\r\n\r\nHello there\r\n",
+            from, subject, date
+        );
+        std::fs::write(dir.join(sub).join(file), raw).unwrap();
+    }
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "MailPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("~/Mail/INBOX").unwrap();
+        assert_eq!(req.maildir.as_deref(), Some("~/Mail/INBOX"));
+        assert_eq!(req.limit, 20);
+
+        let req = parse_request(
+            "imap:\n  host: imap.example.com\n  username: me\nquery:\n  unseen: true\nlimit: 5",
+        )
+        .unwrap();
+        let imap = req.imap.unwrap();
+        assert_eq!(imap.port, 993);
+        assert_eq!(imap.mailbox, "INBOX");
+        assert!(req.query.unseen);
+
+        assert!(parse_request("limit: 5").is_err());
+        assert!(parse_request("").is_err());
+    }
+
+    #[test]
+    fn test_search_criteria() {
+        assert_eq!(search_criteria(&MailQuery::default()), "ALL");
+        let query = MailQuery {
+            from: Some("boss@example.com".to_string()),
+            subject: None,
+            since: NaiveDate::from_ymd_opt(2024, 3, 5),
+            unseen: true,
+        };
+        assert_eq!(
+            search_criteria(&query),
+            "FROM \"boss@example.com\" SINCE 5-Mar-2024 UNSEEN"
+        );
+    }
+
+    /// A maildir with a read message from Alice and a new one from Bob.
+    fn maildir() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["cur", "new", "tmp"] {
+            std::fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        write_message(
+            dir.path(),
+            "cur",
+            "1.host:2,S",
+            "Alice <alice@example.com>",
+            "Weekly report",
+            "Mon, 4 Mar 2024 09:00:00 +0000",
+        );
+        write_message(
+            dir.path(),
+            "new",
+            "2.host",
+            "Bob <bob@example.com>",
+            "Invoice",
+            "Tue, 5 Mar 2024 09:00:00 +0000",
+        );
+        let input = format!("maildir: {}\n", dir.path().display());
+        (dir, input)
+    }
+
+    fn read(input: &str) -> Vec<MailMessage> {
+        serde_json::from_str(&process_input(input).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_read_maildir_newest_first() {
+        let (_dir, input) = maildir();
+        let all = read(&input);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].subject.as_deref(), Some("Invoice"));
+        assert_eq!(all[0].from.as_deref(), Some("Bob <bob@example.com>"));
+        assert_eq!(all[1].id, "1.host");
+        assert!(all[1].seen);
+        assert_eq!(all[1].body, "Hello there");
+    }
+
+    #[test]
+    fn test_read_maildir_unseen_only() {
+        let (_dir, input) = maildir();
+        let unseen = read(&format!("{}query:\n  unseen: true\n", input));
+        assert_eq!(unseen.len(), 1);
+        assert_eq!(unseen[0].id, "2.host");
+    }
+
+    #[test]
+    fn test_read_maildir_from_ignores_case() {
+        let (_dir, input) = maildir();
+        let from_alice = read(&format!("{}query:\n  from: ALICE\n", input));
+        assert_eq!(from_alice.len(), 1);
+    }
+}
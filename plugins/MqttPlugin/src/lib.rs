@@ -0,0 +1,363 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::raw::c_char;
+use std::time::Duration;
+
+// Publish request, accepted either as YAML params or as "<topic> <payload>" text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MqttRequest {
+    /// `host:port`; LAO_MQTT_BROKER, then 127.0.0.1:1883, when unset
+    #[serde(default)]
+    pub broker: Option<String>,
+    pub topic: String,
+    #[serde(default)]
+    pub payload: String,
+    /// Keep the message for subscribers that connect later, like dashboards
+    #[serde(default)]
+    pub retain: bool,
+    /// 0 (at most once) or 1 (wait for the broker's acknowledgement)
+    #[serde(default)]
+    pub qos: u8,
+    /// LAO_MQTT_USERNAME when unset
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Environment variable holding the password, so it never appears in workflow files
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttReport {
+    pub broker: String,
+    pub topic: String,
+    pub bytes: usize,
+    pub retain: bool,
+    pub qos: u8,
+}
+
+fn default_password_env() -> String {
+    "LAO_MQTT_PASSWORD".to_string()
+}
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"MqttPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for MqttPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"MqttPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Publishes messages to an MQTT broker such as Home Assistant's\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"mqtt\", \"home-assistant\", \"smart-home\", \"network\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"publish\",\"description\":\"Publish a message to an MQTT topic\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"publish\",\"description\":\"Publish a message to an MQTT topic\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<MqttRequest> {
+    let trimmed = input.trim();
+
+    let request: MqttRequest = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => {
+            let (topic, payload) = trimmed
+                .split_once(char::is_whitespace)
+                .unwrap_or((trimmed, ""));
+            let mut request: MqttRequest = serde_yaml::from_str("topic: ''")?;
+            request.topic = topic.to_string();
+            request.payload = payload.trim().to_string();
+            request
+        }
+    };
+
+    if request.topic.is_empty() {
+        return Err(anyhow!(
+            "expected '<topic> <payload>' or a 'topic' parameter"
+        ));
+    }
+    if request.topic.contains(['+', '#']) {
+        return Err(anyhow!(
+            "cannot publish to a wildcard topic '{}'",
+            request.topic
+        ));
+    }
+    if request.qos > 1 {
+        return Err(anyhow!("qos must be 0 or 1"));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let broker = request
+        .broker
+        .clone()
+        .or_else(|| std::env::var("LAO_MQTT_BROKER").ok())
+        .unwrap_or_else(|| "127.0.0.1:1883".to_string());
+    let username = request
+        .username
+        .clone()
+        .or_else(|| std::env::var("LAO_MQTT_USERNAME").ok());
+    let password = std::env::var(&request.password_env).ok();
+    publish(&broker, username.as_deref(), password.as_deref(), &request)?;
+
+    let report = MqttReport {
+        broker,
+        topic: request.topic,
+        bytes: request.payload.len(),
+        retain: request.retain,
+        qos: request.qos,
+    };
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+// CONNECT, PUBLISH (and wait for PUBACK at QoS 1), DISCONNECT
+fn publish(
+    broker: &str,
+    username: Option<&str>,
+    password: Option<&str>,
+    request: &MqttRequest,
+) -> Result<()> {
+    let mut stream =
+        TcpStream::connect(broker).map_err(|e| anyhow!("cannot connect to {}: {}", broker, e))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+
+    let mut flags = 0x02; // clean session
+    if username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+    let mut connect = Vec::new();
+    push_string(&mut connect, "MQTT");
+    connect.extend_from_slice(&[4, flags, 0, 60]);
+    // Unique, so concurrent steps don't disconnect each other
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    push_string(
+        &mut connect,
+        &format!("lao-publish-{}-{}", std::process::id(), nanos),
+    );
+    for value in [username, password].into_iter().flatten() {
+        push_string(&mut connect, value);
+    }
+    write_packet(&mut stream, 0x10, &connect)?;
+    match read_packet(&mut stream)? {
+        (0x20, body) if body.get(1) == Some(&0) => {}
+        (0x20, body) => {
+            return Err(anyhow!(
+                "{} refused the connection (code {})",
+                broker,
+                body.get(1).copied().unwrap_or_default()
+            ))
+        }
+        (kind, _) => return Err(anyhow!("expected CONNACK, got packet type {}", kind >> 4)),
+    }
+
+    let mut body = Vec::new();
+    push_string(&mut body, &request.topic);
+    if request.qos == 1 {
+        body.extend_from_slice(&1u16.to_be_bytes());
+    }
+    body.extend_from_slice(request.payload.as_bytes());
+    let kind = 0x30 | (request.qos << 1) | request.retain as u8;
+    write_packet(&mut stream, kind, &body)?;
+    if request.qos == 1 {
+        match read_packet(&mut stream)? {
+            (0x40, _) => {}
+            (kind, _) => return Err(anyhow!("expected PUBACK, got packet type {}", kind >> 4)),
+        }
+    }
+    write_packet(&mut stream, 0xE0, &[])?;
+    Ok(())
+}
+
+fn push_string(buf: &mut Vec<u8>, text: &str) {
+    buf.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    buf.extend_from_slice(text.as_bytes());
+}
+
+fn write_packet(stream: &mut TcpStream, kind: u8, body: &[u8]) -> Result<()> {
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    stream.write_all(&packet)?;
+    Ok(())
+}
+
+fn read_packet(stream: &mut TcpStream) -> Result<(u8, Vec<u8>)> {
+    let mut kind = [0u8];
+    stream.read_exact(&mut kind)?;
+    let mut len = 0usize;
+    for shift in (0..4).map(|i| 7 * i) {
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7F) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    Ok((kind[0], body))
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "MqttPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("lao/summary Release moves to Friday").unwrap();
+        assert_eq!(req.topic, "lao/summary");
+        assert_eq!(req.payload, "Release moves to Friday");
+        assert_eq!(req.password_env, "LAO_MQTT_PASSWORD");
+
+        let req = parse_request("topic: lao/state\npayload: 'on'\nretain: true\nqos: 1").unwrap();
+        assert!(req.retain);
+        assert_eq!(req.qos, 1);
+
+        assert!(parse_request("topic: lao/+/state").is_err());
+        assert!(parse_request("topic: lao/state\nqos: 2").is_err());
+        assert!(parse_request("").is_err());
+    }
+
+    #[test]
+    fn test_publish_to_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let broker = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(read_packet(&mut stream).unwrap().0, 0x10);
+            write_packet(&mut stream, 0x20, &[0, 0]).unwrap();
+            let (kind, body) = read_packet(&mut stream).unwrap();
+            write_packet(&mut stream, 0x40, &body[13..15]).unwrap();
+            assert_eq!(read_packet(&mut stream).unwrap().0, 0xE0);
+            (kind, body)
+        });
+
+        let input = format!(
+            "broker: {}\ntopic: lao/summary\npayload: shipped\nretain: true\nqos: 1",
+            addr
+        );
+        let report: MqttReport = serde_json::from_str(&process_input(&input).unwrap()).unwrap();
+        assert_eq!(report.bytes, 7);
+
+        let (kind, body) = broker.join().unwrap();
+        assert_eq!(kind, 0x33);
+        assert_eq!(&body[2..13], b"lao/summary");
+        assert_eq!(&body[15..], b"shipped");
+    }
+}
@@ -1,8 +1,8 @@
 use anyhow::Result;
-use lao_plugin_api::{PluginInput, PluginOutput, PluginVTablePtr};
+use lao_plugin_api::{PluginInput, PluginOutput, PluginVTablePtr, ABI_VERSION};
 use log::{error, info};
 use serde::{Deserialize, Serialize};
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::os::raw::c_char;
 
 // Plugin configuration
@@ -52,21 +52,14 @@ unsafe extern "C" fn name() -> *const c_char {
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
         error!("Received null input");
-        let error_msg = CString::new("error: null input").unwrap();
-        return PluginOutput {
-            text: error_msg.into_raw(),
-        };
+        return PluginOutput::new("error: null input");
     }
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {
         Ok(s) => s,
         Err(_) => {
             error!("Invalid UTF-8 in input");
-            let error_msg = CString::new("error: invalid UTF-8 input").unwrap();
-            return PluginOutput {
-                text: error_msg.into_raw(),
-            };
+            return PluginOutput::new("error: invalid UTF-8 input");
         }
     };
 
@@ -74,10 +67,7 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
 
     // Validate input
     if !validate_input_internal(input_text) {
-        let error_msg = CString::new("error: invalid input format").unwrap();
-        return PluginOutput {
-            text: error_msg.into_raw(),
-        };
+        return PluginOutput::new("error: invalid input format");
     }
 
     // Process input
@@ -90,17 +80,12 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     };
 
     info!("Returning output: {}", result);
-    let output_cstring = CString::new(result).unwrap();
-    PluginOutput {
-        text: output_cstring.into_raw(),
-    }
+    PluginOutput::new(result)
 }
 
 // Free output function
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 // Run with buffer function
@@ -113,8 +98,7 @@ unsafe extern "C" fn run_with_buffer(
         return 0;
     }
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {
         Ok(s) => s,
         Err(_) => return 0,
     };
@@ -164,8 +148,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
         return false;
     }
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {
         Ok(s) => s,
         Err(_) => return false,
     };
@@ -186,25 +169,32 @@ fn validate_input_internal(input: &str) -> bool {
 
 // Internal processing function
 fn process_input(input: &str) -> Result<String> {
-    // Call Ollama API
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .post("http://localhost:11434/api/generate")
-        .json(&serde_json::json!({
+    // Call Ollama through the host's shared client
+    let result = lao_plugin_api::ollama_post(
+        "/api/generate",
+        &serde_json::json!({
             "model": "llama2",
             "prompt": input,
-            "stream": false
-        }))
-        .send()?;
-
-    let result: serde_json::Value = response.json()?;
+            "stream": true
+        }),
+    )
+    .map_err(anyhow::Error::msg)?;
+    lao_plugin_api::report_usage(ollama_usage(&result));
     Ok(result["response"].as_str().unwrap_or("").to_string())
 }
 
+// Ollama reports prompt and generated token counts as prompt_eval_count and eval_count
+fn ollama_usage(response: &serde_json::Value) -> lao_plugin_api::PluginUsage {
+    lao_plugin_api::PluginUsage {
+        prompt_tokens: response["prompt_eval_count"].as_u64().unwrap_or(0),
+        completion_tokens: response["eval_count"].as_u64().unwrap_or(0),
+    }
+}
+
 // Plugin vtable
 #[no_mangle]
 pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -218,3 +208,33 @@ pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginV
 pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `services` must be null or point to a valid HostServices.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_services(
+    services: *const lao_plugin_api::HostServices,
+) -> bool {
+    lao_plugin_api::accept_host_services(services)
+}
+
+// Token counts for the last run, read by the host after each call
+#[no_mangle]
+pub extern "C" fn plugin_last_usage() -> *const c_char {
+    lao_plugin_api::take_usage_ptr()
+}
+
+// Requests don't set num_ctx, so the model gets Ollama's default window
+#[no_mangle]
+pub extern "C" fn plugin_context_window() -> u64 {
+    lao_plugin_api::OLLAMA_DEFAULT_CONTEXT
+}
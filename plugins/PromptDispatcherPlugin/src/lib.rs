@@ -1,6 +1,7 @@
-use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr};
+use lao_plugin_api::{
+    run_process, PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr, ABI_VERSION,
+};
 use serde_json::Value;
-use std::ffi::CString;
 use std::os::raw::c_char;
 use std::process::Command;
 
@@ -47,30 +48,21 @@ fn find_matching_workflow(input: &str, library: &[(String, String)]) -> Option<S
 
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
-        return PluginOutput {
-            text: std::ptr::null_mut(),
-        };
+        return PluginOutput::null();
     }
 
-    let c_str = std::ffi::CStr::from_ptr((*input).text);
-    let input_str = c_str.to_string_lossy();
+    let input_str = (*input).to_string_lossy();
 
     // Check for nonsense input first
     if input_str.contains("nonsense") || input_str.len() < 5 {
         let error_msg = "error: could not generate workflow for invalid input";
-        let cstr = CString::new(error_msg).unwrap();
-        return PluginOutput {
-            text: cstr.into_raw(),
-        };
+        return PluginOutput::new(error_msg);
     }
 
     // Try to match against prompt library first
     if let Some(library) = load_prompt_library() {
         if let Some(workflow) = find_matching_workflow(&input_str, &library) {
-            let cstr = CString::new(workflow).unwrap();
-            return PluginOutput {
-                text: cstr.into_raw(),
-            };
+            return PluginOutput::new(workflow);
         }
     }
 
@@ -92,18 +84,12 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     cmd.arg("run").arg("llama2").arg(&prompt);
     println!("[PromptDispatcherPlugin] Running command: ollama run llama2 <prompt>");
 
-    match cmd.output() {
+    match run_process(&mut cmd) {
         Ok(output) => {
-            println!(
-                "[PromptDispatcherPlugin] ollama stdout: {}",
-                String::from_utf8_lossy(&output.stdout)
-            );
-            println!(
-                "[PromptDispatcherPlugin] ollama stderr: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
+            println!("[PromptDispatcherPlugin] ollama stdout: {}", output.stdout);
+            println!("[PromptDispatcherPlugin] ollama stderr: {}", output.stderr);
             if output.status.success() {
-                let out = String::from_utf8_lossy(&output.stdout).to_string();
+                let out = output.stdout;
                 // Clean up the output - remove markdown fences and extra text
                 let cleaned = out
                     .lines()
@@ -114,10 +100,7 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
                     .to_string();
 
                 if cleaned.contains("workflow:") && cleaned.contains("steps:") {
-                    let cstr = CString::new(cleaned).unwrap();
-                    return PluginOutput {
-                        text: cstr.into_raw(),
-                    };
+                    return PluginOutput::new(cleaned);
                 }
             } else {
                 println!(
@@ -133,16 +116,11 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
 
     // Final fallback - return error for unmatched prompts
     let error_msg = "error: could not generate workflow for this input";
-    let cstr = CString::new(error_msg).unwrap();
-    PluginOutput {
-        text: cstr.into_raw(),
-    }
+    PluginOutput::new(error_msg)
 }
 
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 unsafe extern "C" fn run_with_buffer(
@@ -154,8 +132,7 @@ unsafe extern "C" fn run_with_buffer(
         return 0;
     }
 
-    let c_str = std::ffi::CStr::from_ptr((*input).text);
-    let input_str = c_str.to_string_lossy();
+    let input_str = (*input).to_string_lossy();
 
     // Check for nonsense input
     if input_str.contains("nonsense") || input_str.len() < 5 {
@@ -211,8 +188,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
     if input.is_null() {
         return false;
     }
-    let c_str = std::ffi::CStr::from_ptr((*input).text);
-    let text = c_str.to_string_lossy();
+    let text = (*input).to_string_lossy();
     !text.trim().is_empty()
 }
 
@@ -223,7 +199,7 @@ unsafe extern "C" fn get_capabilities() -> *const c_char {
 
 #[no_mangle]
 pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -238,11 +214,26 @@ pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
 
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `command` must be null or point to a valid HostCommand.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_command(command: *const lao_plugin_api::HostCommand) -> bool {
+    lao_plugin_api::accept_host_command(command)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use lao_plugin_api::*;
-    use std::ffi::CString;
 
     #[test]
     fn test_plugin_name() {
@@ -257,16 +248,12 @@ mod tests {
     #[test]
     fn test_validate_input() {
         unsafe {
-            let valid_input = CString::new("Create a workflow to summarize text").unwrap();
-            let input = PluginInput {
-                text: valid_input.into_raw(),
-            };
+            let valid_input = PluginInputBuf::new("Create a workflow to summarize text");
+            let input = valid_input.as_input();
             assert!(validate_input(&input));
 
-            let empty_input = CString::new("   ").unwrap();
-            let input = PluginInput {
-                text: empty_input.into_raw(),
-            };
+            let empty_input = PluginInputBuf::new("   ");
+            let input = empty_input.as_input();
             assert!(!validate_input(&input));
         }
     }
@@ -0,0 +1,357 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info};
+use serde::Deserialize;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "native")]
+mod native;
+mod resample;
+
+/// Sample rate and channel count whisper.cpp expects
+pub const OUTPUT_SAMPLE_RATE: u32 = 16_000;
+
+// Recording request, accepted either as YAML params or as "<seconds> [output]" text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RecordRequest {
+    #[serde(default)]
+    pub duration_secs: Option<f64>,
+    /// Stop as soon as this file exists (e.g. touched from a hotkey binding)
+    #[serde(default)]
+    pub stop_file: Option<String>,
+    #[serde(default = "default_max_duration_secs")]
+    pub max_duration_secs: f64,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub source: AudioSource,
+    /// Backend-specific device name; defaults to the system default device
+    #[serde(default)]
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioSource {
+    #[default]
+    Microphone,
+    System,
+}
+
+fn default_max_duration_secs() -> f64 {
+    3600.0
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"RecordPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for RecordPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"RecordPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Records microphone or system audio to a WAV file\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"audio\", \"recording\", \"microphone\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"record\",\"description\":\"Record audio for a duration or until stopped and return the WAV path\",\"input_type\":\"Text\",\"output_type\":\"Audio\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"record\",\"description\":\"Record audio for a duration or until stopped and return the WAV path\",\"input_type\":\"Text\",\"output_type\":\"Audio\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<RecordRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => {
+            let mut parts = trimmed.split_whitespace();
+            let duration = parts
+                .next()
+                .ok_or_else(|| anyhow!("expected '<seconds> [output]'"))?;
+            RecordRequest {
+                duration_secs: Some(
+                    duration
+                        .parse()
+                        .map_err(|_| anyhow!("invalid duration '{}'", duration))?,
+                ),
+                stop_file: None,
+                max_duration_secs: default_max_duration_secs(),
+                output: parts.next().map(str::to_string),
+                source: AudioSource::default(),
+                device: None,
+            }
+        }
+    };
+
+    if request.duration_secs.is_none() && request.stop_file.is_none() {
+        return Err(anyhow!("set 'duration_secs', 'stop_file' or both"));
+    }
+    if request.duration_secs.is_some_and(|d| d <= 0.0) {
+        return Err(anyhow!("'duration_secs' must be positive"));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let output = output_path(&request);
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    // A stale stop file from a previous run would end this one immediately
+    if let Some(stop_file) = &request.stop_file {
+        let _ = std::fs::remove_file(stop_file);
+    }
+
+    info!(
+        "Recording {:?} audio to {}",
+        request.source,
+        output.display()
+    );
+    #[cfg(feature = "native")]
+    native::record(&request, &output)?;
+    #[cfg(not(feature = "native"))]
+    record_with_ffmpeg(&request, &output)?;
+
+    Ok(output.to_string_lossy().to_string())
+}
+
+fn output_path(request: &RecordRequest) -> PathBuf {
+    match &request.output {
+        Some(path) => PathBuf::from(path),
+        None => Path::new("recordings").join(format!(
+            "recording-{}.wav",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        )),
+    }
+}
+
+// The duration asked for, within the safety cap
+fn limit(request: &RecordRequest) -> Duration {
+    let limit = request
+        .duration_secs
+        .unwrap_or(request.max_duration_secs)
+        .min(request.max_duration_secs);
+    Duration::from_secs_f64(limit)
+}
+
+// Blocks until the duration elapses, the stop file appears or the safety cap is hit
+#[cfg_attr(not(feature = "native"), allow(dead_code))]
+fn wait_for_stop(request: &RecordRequest, started: Instant) {
+    let limit = limit(request);
+    while started.elapsed() < limit {
+        if let Some(stop_file) = &request.stop_file {
+            if Path::new(stop_file).exists() {
+                let _ = std::fs::remove_file(stop_file);
+                info!("Stop file found, ending recording");
+                return;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// ffmpeg input format and device for the current platform
+#[cfg_attr(feature = "native", allow(dead_code))]
+fn ffmpeg_input(request: &RecordRequest) -> Result<(&'static str, String)> {
+    let device = request.device.clone();
+    if cfg!(target_os = "linux") {
+        let default = match request.source {
+            AudioSource::Microphone => "default",
+            AudioSource::System => "@DEFAULT_MONITOR@",
+        };
+        Ok(("pulse", device.unwrap_or_else(|| default.to_string())))
+    } else if cfg!(target_os = "macos") {
+        match (request.source, device) {
+            (_, Some(device)) => Ok(("avfoundation", format!(":{}", device))),
+            (AudioSource::Microphone, None) => Ok(("avfoundation", ":0".to_string())),
+            (AudioSource::System, None) => Err(anyhow!(
+                "system audio on macOS needs a loopback 'device' such as BlackHole"
+            )),
+        }
+    } else if cfg!(target_os = "windows") {
+        let device = device.ok_or_else(|| anyhow!("'device' is required on Windows"))?;
+        Ok(("dshow", format!("audio={}", device)))
+    } else {
+        Err(anyhow!("audio capture is not supported on this platform"))
+    }
+}
+
+#[cfg(not(feature = "native"))]
+fn record_with_ffmpeg(request: &RecordRequest, output: &Path) -> Result<()> {
+    let (format, device) = ffmpeg_input(request)?;
+    let mut command = std::process::Command::new("ffmpeg");
+    command
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-y",
+            "-f",
+            format,
+            "-i",
+        ])
+        .arg(&device)
+        .args(["-ac", "1", "-ar", &OUTPUT_SAMPLE_RATE.to_string()])
+        .args(["-t", &limit(request).as_secs_f64().to_string()])
+        .arg(output);
+    // "q" asks ffmpeg to stop and finalize the WAV header
+    let stop_file = request
+        .stop_file
+        .as_ref()
+        .map(|path| lao_plugin_api::StopFile {
+            path: path.clone(),
+            input: "q".to_string(),
+        });
+    let result = lao_plugin_api::run_process_until(&mut command, stop_file)
+        .map_err(|e| anyhow!("failed to run ffmpeg: {}", e))?;
+    if !output.exists() {
+        return Err(anyhow!(
+            "ffmpeg produced no recording: {}",
+            result.stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `command` must be null or point to a valid HostCommand.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_command(command: *const lao_plugin_api::HostCommand) -> bool {
+    lao_plugin_api::accept_host_command(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "RecordPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("30 meeting.wav").unwrap();
+        assert_eq!(req.duration_secs, Some(30.0));
+        assert_eq!(req.output.as_deref(), Some("meeting.wav"));
+
+        let req = parse_request("stop_file: /tmp/lao-record.stop\nsource: system").unwrap();
+        assert_eq!(req.source, AudioSource::System);
+        assert_eq!(req.max_duration_secs, 3600.0);
+
+        assert!(parse_request("source: microphone").is_err());
+        assert!(parse_request("-5").is_err());
+        assert!(parse_request("soon").is_err());
+    }
+
+    #[test]
+    fn test_wait_for_stop_honours_stop_file() {
+        let dir = std::env::temp_dir().join(format!("lao-record-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let stop_file = dir.join("stop");
+        std::fs::write(&stop_file, "").unwrap();
+
+        let request = RecordRequest {
+            duration_secs: Some(30.0),
+            stop_file: Some(stop_file.to_string_lossy().to_string()),
+            max_duration_secs: 60.0,
+            output: None,
+            source: AudioSource::Microphone,
+            device: None,
+        };
+        let started = Instant::now();
+        wait_for_stop(&request, started);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!stop_file.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
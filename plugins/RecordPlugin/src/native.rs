@@ -0,0 +1,123 @@
+// In-process capture through cpal, enabled with the `native` feature
+use crate::resample::MonoResampler;
+use crate::{wait_for_stop, AudioSource, RecordRequest, OUTPUT_SAMPLE_RATE};
+use anyhow::{anyhow, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+type SharedWriter = Arc<
+    Mutex<(
+        MonoResampler,
+        hound::WavWriter<std::io::BufWriter<std::fs::File>>,
+    )>,
+>;
+
+pub fn record(request: &RecordRequest, output: &Path) -> Result<()> {
+    let host = cpal::default_host();
+    let device = match (&request.device, request.source) {
+        (Some(name), _) => host
+            .devices()?
+            .find(|d| d.name().is_ok_and(|n| &n == name))
+            .ok_or_else(|| anyhow!("audio device '{}' not found", name))?,
+        // WASAPI records the output mix when an input stream is opened on an output device
+        (None, AudioSource::System) => host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("no default output device"))?,
+        (None, AudioSource::Microphone) => host
+            .default_input_device()
+            .ok_or_else(|| anyhow!("no default input device"))?,
+    };
+
+    let config = match request.source {
+        AudioSource::System if request.device.is_none() => device.default_output_config()?,
+        _ => device.default_input_config()?,
+    };
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: OUTPUT_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let resampler = MonoResampler::new(
+        config.channels(),
+        config.sample_rate().0,
+        OUTPUT_SAMPLE_RATE,
+    );
+    let writer: SharedWriter = Arc::new(Mutex::new((
+        resampler,
+        hound::WavWriter::create(output, spec)?,
+    )));
+
+    let err_fn = |e| error!("Audio stream error: {}", e);
+    let stream_config = config.config();
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => {
+            let writer = writer.clone();
+            device.build_input_stream(
+                &stream_config,
+                move |data: &[f32], _: &_| write_samples(&writer, data),
+                err_fn,
+                None,
+            )?
+        }
+        cpal::SampleFormat::I16 => {
+            let writer = writer.clone();
+            device.build_input_stream(
+                &stream_config,
+                move |data: &[i16], _: &_| {
+                    let samples: Vec<f32> =
+                        data.iter().map(|&s| s as f32 / i16::MAX as f32).collect();
+                    write_samples(&writer, &samples)
+                },
+                err_fn,
+                None,
+            )?
+        }
+        cpal::SampleFormat::U16 => {
+            let writer = writer.clone();
+            device.build_input_stream(
+                &stream_config,
+                move |data: &[u16], _: &_| {
+                    let samples: Vec<f32> = data
+                        .iter()
+                        .map(|&s| (s as f32 - 32768.0) / 32768.0)
+                        .collect();
+                    write_samples(&writer, &samples)
+                },
+                err_fn,
+                None,
+            )?
+        }
+        other => return Err(anyhow!("unsupported sample format {:?}", other)),
+    };
+
+    stream.play()?;
+    wait_for_stop(request, Instant::now());
+    drop(stream);
+
+    let (_, wav) = Arc::try_unwrap(writer)
+        .map_err(|_| anyhow!("audio stream still holds the writer"))?
+        .into_inner()
+        .map_err(|_| anyhow!("audio writer lock poisoned"))?;
+    wav.finalize()?;
+    Ok(())
+}
+
+fn write_samples(writer: &SharedWriter, samples: &[f32]) {
+    let Ok(mut guard) = writer.lock() else {
+        return;
+    };
+    let (resampler, wav) = &mut *guard;
+    let mut mono = Vec::with_capacity(samples.len());
+    resampler.process(samples, &mut mono);
+    for sample in mono {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        if let Err(e) = wav.write_sample(value) {
+            error!("Failed to write audio sample: {}", e);
+            return;
+        }
+    }
+}
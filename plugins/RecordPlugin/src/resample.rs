@@ -0,0 +1,63 @@
+// Streaming downmix/resample used by the native backend to match whisper.cpp's input format
+#![cfg_attr(not(feature = "native"), allow(dead_code))]
+
+/// Converts interleaved frames at `in_rate` to mono samples at `out_rate`
+/// using linear interpolation, carrying state across callback buffers.
+pub struct MonoResampler {
+    channels: usize,
+    step: f64,
+    pos: f64,
+    last: Option<f32>,
+}
+
+impl MonoResampler {
+    pub fn new(channels: u16, in_rate: u32, out_rate: u32) -> Self {
+        MonoResampler {
+            channels: channels.max(1) as usize,
+            step: in_rate as f64 / out_rate as f64,
+            pos: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn process(&mut self, interleaved: &[f32], out: &mut Vec<f32>) {
+        let mono = interleaved
+            .chunks(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32);
+        // Index 0 holds the previous buffer's final sample so interpolation spans buffers
+        let buf: Vec<f32> = self.last.into_iter().chain(mono).collect();
+        if buf.is_empty() {
+            return;
+        }
+
+        while self.pos + 1.0 < buf.len() as f64 {
+            let i = self.pos.floor() as usize;
+            let frac = (self.pos - i as f64) as f32;
+            out.push(buf[i] * (1.0 - frac) + buf[i + 1] * frac);
+            self.pos += self.step;
+        }
+        self.pos -= (buf.len() - 1) as f64;
+        self.last = buf.last().copied();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downmix_and_downsample_across_buffers() {
+        // Stereo 48 kHz ramp, fed in uneven chunks, down to 16 kHz mono
+        let frames: Vec<f32> = (0..480).flat_map(|i| [i as f32, i as f32 + 2.0]).collect();
+        let mut resampler = MonoResampler::new(2, 48_000, 16_000);
+        let mut out = Vec::new();
+        for chunk in frames.chunks(142) {
+            resampler.process(chunk, &mut out);
+        }
+
+        assert_eq!(out.len(), 160);
+        for (n, sample) in out.iter().enumerate() {
+            assert!((sample - (n as f32 * 3.0 + 1.0)).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info};
+use serde::Deserialize;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(feature = "native")]
+mod native;
+
+// Capture request, accepted either as YAML params or as "screen" / "window <title>" text
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ScreenshotRequest {
+    /// Capture the first window whose title contains this text instead of a monitor
+    #[serde(default)]
+    pub window: Option<String>,
+    /// Monitor index; defaults to the primary monitor
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Wait before capturing, e.g. to let a hotkey-triggered menu close
+    #[serde(default)]
+    pub delay_secs: f64,
+}
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"ScreenshotPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for ScreenshotPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"ScreenshotPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Captures the screen or a window to a PNG image\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"screenshot\", \"screen\", \"image\", \"desktop\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"capture\",\"description\":\"Capture a monitor or window and return the PNG path\",\"input_type\":\"Text\",\"output_type\":\"Image\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"capture\",\"description\":\"Capture a monitor or window and return the PNG path\",\"input_type\":\"Text\",\"output_type\":\"Image\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<ScreenshotRequest> {
+    let trimmed = input.trim();
+
+    let request = match serde_yaml::from_str::<serde_yaml::Value>(trimmed) {
+        Ok(value @ serde_yaml::Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("screen") => {
+            ScreenshotRequest::default()
+        }
+        _ => match trimmed.split_once(char::is_whitespace) {
+            Some(("window", title)) => ScreenshotRequest {
+                window: Some(title.trim().to_string()),
+                ..Default::default()
+            },
+            _ => return Err(anyhow!("expected 'screen' or 'window <title>'")),
+        },
+    };
+
+    if request.window.is_some() && request.monitor.is_some() {
+        return Err(anyhow!("set either 'window' or 'monitor', not both"));
+    }
+    if request.delay_secs < 0.0 {
+        return Err(anyhow!("'delay_secs' must not be negative"));
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let output = output_path(&request);
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    if request.delay_secs > 0.0 {
+        std::thread::sleep(Duration::from_secs_f64(request.delay_secs));
+    }
+
+    #[cfg(feature = "native")]
+    native::capture(&request, &output)?;
+    #[cfg(not(feature = "native"))]
+    capture_with_tools(&request, &output)?;
+
+    info!("Saved screenshot to {}", output.display());
+    Ok(output.to_string_lossy().to_string())
+}
+
+fn output_path(request: &ScreenshotRequest) -> PathBuf {
+    match &request.output {
+        Some(path) => PathBuf::from(path),
+        None => Path::new("screenshots").join(format!(
+            "screenshot-{}.png",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        )),
+    }
+}
+
+// Candidate (program, args) invocations for the platform, tried in order
+#[cfg_attr(feature = "native", allow(dead_code))]
+fn capture_commands(
+    request: &ScreenshotRequest,
+    output: &Path,
+    wayland: bool,
+) -> Result<Vec<(&'static str, Vec<String>)>> {
+    let out = output.to_string_lossy().to_string();
+
+    if cfg!(target_os = "macos") {
+        if request.window.is_some() {
+            return Err(anyhow!(
+                "window capture on macOS needs the 'native' feature"
+            ));
+        }
+        let mut args = vec!["-x".to_string()];
+        if let Some(monitor) = request.monitor {
+            args.extend(["-D".to_string(), (monitor + 1).to_string()]);
+        }
+        args.push(out);
+        return Ok(vec![("screencapture", args)]);
+    }
+
+    if cfg!(target_os = "windows") {
+        if request.window.is_some() {
+            return Err(anyhow!(
+                "window capture on Windows needs the 'native' feature"
+            ));
+        }
+        let screen = match request.monitor {
+            Some(i) => format!("[System.Windows.Forms.Screen]::AllScreens[{}]", i),
+            None => "[System.Windows.Forms.Screen]::PrimaryScreen".to_string(),
+        };
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+             $b = {}.Bounds; \
+             $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+             [System.Drawing.Graphics]::FromImage($bmp).CopyFromScreen($b.Location, [System.Drawing.Point]::Empty, $b.Size); \
+             $bmp.Save('{}', [System.Drawing.Imaging.ImageFormat]::Png)",
+            screen,
+            out.replace('\'', "''")
+        );
+        return Ok(vec![(
+            "powershell",
+            vec!["-NoProfile".to_string(), "-Command".to_string(), script],
+        )]);
+    }
+
+    if request.monitor.is_some() {
+        return Err(anyhow!(
+            "selecting a monitor on Linux needs the 'native' feature"
+        ));
+    }
+    if let Some(title) = &request.window {
+        if wayland {
+            return Err(anyhow!(
+                "window capture on Wayland needs the 'native' feature"
+            ));
+        }
+        // ImageMagick's import resolves a window by id or by exact name
+        return Ok(vec![(
+            "import",
+            vec!["-window".to_string(), title.clone(), out],
+        )]);
+    }
+
+    let grim = ("grim", vec![out.clone()]);
+    let gnome = ("gnome-screenshot", vec!["-f".to_string(), out.clone()]);
+    let spectacle = (
+        "spectacle",
+        vec!["-b", "-n", "-f", "-o", &out]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+    );
+    let import = (
+        "import",
+        vec!["-window".to_string(), "root".to_string(), out.clone()],
+    );
+    let scrot = ("scrot", vec!["-o".to_string(), out]);
+    Ok(if wayland {
+        vec![grim, gnome, spectacle]
+    } else {
+        vec![import, scrot, gnome, spectacle]
+    })
+}
+
+#[cfg(not(feature = "native"))]
+fn capture_with_tools(request: &ScreenshotRequest, output: &Path) -> Result<()> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let mut failures = Vec::new();
+
+    for (program, args) in capture_commands(request, output, wayland)? {
+        let mut command = std::process::Command::new(program);
+        match lao_plugin_api::run_process(command.args(&args)) {
+            Ok(result) if result.status.success() && output.exists() => return Ok(()),
+            Ok(result) => failures.push(format!("{}: {}", program, result.stderr.trim())),
+            Err(e) => failures.push(format!("{}: {}", program, e)),
+        }
+    }
+    Err(anyhow!(
+        "no screenshot tool succeeded ({})",
+        failures.join("; ")
+    ))
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `command` must be null or point to a valid HostCommand.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_command(command: *const lao_plugin_api::HostCommand) -> bool {
+    lao_plugin_api::accept_host_command(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "ScreenshotPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(parse_request("").unwrap(), ScreenshotRequest::default());
+        assert_eq!(
+            parse_request("screen").unwrap(),
+            ScreenshotRequest::default()
+        );
+
+        let req = parse_request("window  Untitled - Notepad ").unwrap();
+        assert_eq!(req.window.as_deref(), Some("Untitled - Notepad"));
+
+        let req = parse_request("monitor: 1\ndelay_secs: 0.5\noutput: shot.png").unwrap();
+        assert_eq!(req.monitor, Some(1));
+        assert_eq!(req.output.as_deref(), Some("shot.png"));
+
+        assert!(parse_request("window: a\nmonitor: 0").is_err());
+        assert!(parse_request("everything").is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_linux_capture_commands() {
+        let output = Path::new("shot.png");
+        let screen = ScreenshotRequest::default();
+
+        let x11 = capture_commands(&screen, output, false).unwrap();
+        assert_eq!(x11[0].0, "import");
+        let wayland = capture_commands(&screen, output, true).unwrap();
+        assert_eq!(wayland[0], ("grim", vec!["shot.png".to_string()]));
+
+        let window = parse_request("window Terminal").unwrap();
+        assert_eq!(
+            capture_commands(&window, output, false).unwrap()[0].1,
+            vec!["-window", "Terminal", "shot.png"]
+        );
+        assert!(capture_commands(&window, output, true).is_err());
+    }
+}
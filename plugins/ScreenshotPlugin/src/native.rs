@@ -0,0 +1,42 @@
+// In-process capture through xcap, enabled with the `native` feature
+use crate::ScreenshotRequest;
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use xcap::{Monitor, Window};
+
+pub fn capture(request: &ScreenshotRequest, output: &Path) -> Result<()> {
+    let image = match &request.window {
+        Some(title) => {
+            let needle = title.to_lowercase();
+            let window = Window::all()?
+                .into_iter()
+                .filter(|w| !w.is_minimized().unwrap_or(false))
+                .find(|w| w.title().is_ok_and(|t| t.to_lowercase().contains(&needle)))
+                .ok_or_else(|| anyhow!("no visible window matching '{}'", title))?;
+            window.capture_image()?
+        }
+        None => {
+            let monitors = Monitor::all()?;
+            let monitor = match request.monitor {
+                Some(index) => monitors
+                    .into_iter()
+                    .nth(index)
+                    .ok_or_else(|| anyhow!("monitor {} not found", index))?,
+                None => {
+                    let primary = monitors
+                        .iter()
+                        .position(|m| m.is_primary().unwrap_or(false))
+                        .unwrap_or(0);
+                    monitors
+                        .into_iter()
+                        .nth(primary)
+                        .ok_or_else(|| anyhow!("no monitors found"))?
+                }
+            };
+            monitor.capture_image()?
+        }
+    };
+
+    image.save(output)?;
+    Ok(())
+}
@@ -1,5 +1,4 @@
-use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr};
-use std::ffi::{CStr, CString};
+use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr, ABI_VERSION};
 use std::os::raw::c_char;
 
 unsafe extern "C" fn name() -> *const c_char {
@@ -8,36 +7,32 @@ unsafe extern "C" fn name() -> *const c_char {
 
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
-        return PluginOutput {
-            text: std::ptr::null_mut(),
-        };
+        return PluginOutput::null();
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let text = c_str.to_string_lossy();
-    let client = reqwest::blocking::Client::new();
-    let res = client
-        .post("http://localhost:11434/api/generate")
-        .json(&serde_json::json!({
+    let text = (*input).to_string_lossy();
+    let res = lao_plugin_api::ollama_post(
+        "/api/generate",
+        &serde_json::json!({
             "model": "mistral",
             "prompt": format!("Summarize this:\n\n{}", text),
-            "stream": false
-        }))
-        .send();
+            "stream": true
+        }),
+    );
     let summary = match res {
-        Ok(resp) => {
-            let json: serde_json::Value = resp.json().unwrap_or_default();
+        Ok(json) => {
+            lao_plugin_api::report_usage(lao_plugin_api::PluginUsage {
+                prompt_tokens: json["prompt_eval_count"].as_u64().unwrap_or(0),
+                completion_tokens: json["eval_count"].as_u64().unwrap_or(0),
+            });
             json["response"].as_str().unwrap_or("").to_string()
         }
-        Err(e) => format!("Summarizer error: {}", e),
+        Err(e) => format!("error: summarizer request failed: {}", e),
     };
-    let out = CString::new(summary).unwrap().into_raw();
-    PluginOutput { text: out }
+    PluginOutput::new(summary)
 }
 
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 unsafe extern "C" fn run_with_buffer(
@@ -74,8 +69,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
     if input.is_null() {
         return false;
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let text = c_str.to_string_lossy();
+    let text = (*input).to_string_lossy();
     !text.trim().is_empty()
 }
 
@@ -86,7 +80,7 @@ unsafe extern "C" fn get_capabilities() -> *const c_char {
 
 #[no_mangle]
 pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -101,11 +95,41 @@ pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
 
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `services` must be null or point to a valid HostServices.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_services(
+    services: *const lao_plugin_api::HostServices,
+) -> bool {
+    lao_plugin_api::accept_host_services(services)
+}
+
+// Token counts for the last run, read by the host after each call
+#[no_mangle]
+pub extern "C" fn plugin_last_usage() -> *const c_char {
+    lao_plugin_api::take_usage_ptr()
+}
+
+// Requests don't set num_ctx, so the model gets Ollama's default window
+#[no_mangle]
+pub extern "C" fn plugin_context_window() -> u64 {
+    lao_plugin_api::OLLAMA_DEFAULT_CONTEXT
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use lao_plugin_api::*;
-    use std::ffi::CString;
+    use std::ffi::CStr;
 
     #[test]
     fn test_plugin_name() {
@@ -120,16 +144,12 @@ mod tests {
     #[test]
     fn test_validate_input() {
         unsafe {
-            let valid_input = CString::new("Some text to summarize").unwrap();
-            let input = PluginInput {
-                text: valid_input.into_raw(),
-            };
+            let valid_input = PluginInputBuf::new("Some text to summarize");
+            let input = valid_input.as_input();
             assert!(validate_input(&input));
 
-            let empty_input = CString::new("   ").unwrap();
-            let input = PluginInput {
-                text: empty_input.into_raw(),
-            };
+            let empty_input = PluginInputBuf::new("   ");
+            let input = empty_input.as_input();
             assert!(!validate_input(&input));
         }
     }
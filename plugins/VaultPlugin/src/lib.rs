@@ -0,0 +1,662 @@
+use anyhow::{anyhow, Result};
+use lao_plugin_api::{
+    PluginInput, PluginMetadata, PluginOutput, PluginVTable, PluginVTablePtr, ABI_VERSION,
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::os::raw::c_char;
+use std::path::{Component, Path, PathBuf};
+
+// Vault request, accepted either as YAML params or as "read <note>" / "search <text>" text
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct VaultRequest {
+    #[serde(default = "default_action")]
+    pub action: String,
+    /// Vault directory; LAO_VAULT_DIR when unset
+    #[serde(default)]
+    pub vault: Option<String>,
+    /// Note path inside the vault; `.md` is added when it has no extension
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Markdown written or appended
+    #[serde(default)]
+    pub content: String,
+    /// Fields set in the note's frontmatter
+    #[serde(default)]
+    pub frontmatter: Mapping,
+    /// What `write` does when the note exists
+    #[serde(default = "default_on_exists")]
+    pub on_exists: String,
+    #[serde(default)]
+    pub query: NoteQuery,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct NoteQuery {
+    /// Case-insensitive text in the title or body
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Tags the note must all have, in its frontmatter or as #tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Folder inside the vault
+    #[serde(default)]
+    pub folder: Option<String>,
+    /// Frontmatter fields that must have these values
+    #[serde(default, rename = "where")]
+    pub fields: Mapping,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    /// Path inside the vault, with `/` separators
+    pub note: String,
+    pub title: String,
+    pub frontmatter: Mapping,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultReport {
+    pub action: String,
+    pub note: String,
+    /// File written, or kept when `written` is false
+    pub path: String,
+    pub written: bool,
+}
+
+fn default_action() -> String {
+    "write".to_string()
+}
+
+fn default_on_exists() -> String {
+    "overwrite".to_string()
+}
+
+fn default_limit() -> usize {
+    20
+}
+
+const ACTIONS: [&str; 4] = ["write", "append", "read", "search"];
+const ON_EXISTS: [&str; 3] = ["overwrite", "version", "skip"];
+
+unsafe extern "C" fn name() -> *const c_char {
+    c"VaultPlugin".as_ptr()
+}
+
+unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
+    if input.is_null() {
+        error!("Received null input");
+        return PluginOutput::new("error: null input");
+    }
+
+    let input_text = (*input).to_string_lossy();
+    info!("Processing input: {}", input_text);
+
+    let result = match process_input(&input_text) {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Processing error: {}", e);
+            format!("error: {}", e)
+        }
+    };
+
+    PluginOutput::new(result)
+}
+
+unsafe extern "C" fn free_output(output: PluginOutput) {
+    output.free();
+}
+
+unsafe extern "C" fn run_with_buffer(
+    _input: *const PluginInput,
+    _buffer: *mut c_char,
+    _buffer_len: usize,
+) -> usize {
+    0 // Not implemented for VaultPlugin
+}
+
+unsafe extern "C" fn get_metadata() -> PluginMetadata {
+    // Use static byte arrays to ensure proper memory management
+    static NAME: &[u8] = b"VaultPlugin\0";
+    static VERSION: &[u8] = b"1.0.0\0";
+    static DESCRIPTION: &[u8] = b"Writes, appends, reads and searches notes in a markdown vault\0";
+    static AUTHOR: &[u8] = b"LAO Team\0";
+    static TAGS: &[u8] = b"[\"notes\", \"markdown\", \"obsidian\", \"files\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"write\",\"description\":\"Write a note with frontmatter\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"append\",\"description\":\"Append to a note and merge its frontmatter\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"read\",\"description\":\"Read a note and its frontmatter\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"search\",\"description\":\"Find notes by text, tags, folder and frontmatter fields\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+
+    PluginMetadata {
+        name: NAME.as_ptr() as *const c_char,
+        version: VERSION.as_ptr() as *const c_char,
+        description: DESCRIPTION.as_ptr() as *const c_char,
+        author: AUTHOR.as_ptr() as *const c_char,
+        dependencies: std::ptr::null(),
+        tags: TAGS.as_ptr() as *const c_char,
+        input_schema: std::ptr::null(),
+        output_schema: std::ptr::null(),
+        capabilities: CAPABILITIES.as_ptr() as *const c_char,
+    }
+}
+
+unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
+    if input.is_null() {
+        return false;
+    }
+    parse_request(&(*input).to_string_lossy()).is_ok()
+}
+
+unsafe extern "C" fn get_capabilities() -> *const c_char {
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"write\",\"description\":\"Write a note with frontmatter\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"append\",\"description\":\"Append to a note and merge its frontmatter\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"read\",\"description\":\"Read a note and its frontmatter\",\"input_type\":\"Text\",\"output_type\":\"Json\"},{\"name\":\"search\",\"description\":\"Find notes by text, tags, folder and frontmatter fields\",\"input_type\":\"Text\",\"output_type\":\"Json\"}]\0";
+    CAPABILITIES.as_ptr() as *const c_char
+}
+
+fn parse_request(input: &str) -> Result<VaultRequest> {
+    let trimmed = input.trim();
+
+    let mut request: VaultRequest = match serde_yaml::from_str::<Value>(trimmed) {
+        Ok(value @ Value::Mapping(_)) => serde_yaml::from_value(value)?,
+        _ => {
+            let (action, rest) = trimmed
+                .split_once(char::is_whitespace)
+                .unwrap_or((trimmed, ""));
+            let rest = rest.trim().to_string();
+            let mut request: VaultRequest = serde_yaml::from_str("{}")?;
+            request.action = action.to_string();
+            match action {
+                "read" => request.note = Some(rest),
+                "search" => request.query.text = Some(rest).filter(|t| !t.is_empty()),
+                _ => {
+                    return Err(anyhow!(
+                        "expected 'read <note>', 'search <text>' or parameters"
+                    ))
+                }
+            }
+            request
+        }
+    };
+
+    if !ACTIONS.contains(&request.action.as_str()) {
+        return Err(anyhow!(
+            "unsupported action '{}', expected one of: {}",
+            request.action,
+            ACTIONS.join(", ")
+        ));
+    }
+    if !ON_EXISTS.contains(&request.on_exists.as_str()) {
+        return Err(anyhow!(
+            "unsupported on_exists '{}', expected one of: {}",
+            request.on_exists,
+            ON_EXISTS.join(", ")
+        ));
+    }
+    if request.action != "search" {
+        let note = request
+            .note
+            .take()
+            .ok_or_else(|| anyhow!("'{}' needs a note", request.action))?;
+        request.note = Some(note_path(&note)?);
+    }
+    Ok(request)
+}
+
+fn process_input(input: &str) -> Result<String> {
+    let request = parse_request(input)?;
+    let vault = vault_dir(&request)?;
+    let note = request.note.clone().unwrap_or_default();
+
+    match request.action.as_str() {
+        "write" | "append" => {
+            let report = write_note(&vault, &note, &request)?;
+            Ok(serde_json::to_string_pretty(&report)?)
+        }
+        "read" => {
+            let path = vault.join(&note);
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+            Ok(serde_json::to_string_pretty(&parse_note(&note, &text)?)?)
+        }
+        _ => Ok(serde_json::to_string_pretty(&search(
+            &vault,
+            &request.query,
+            request.limit,
+        )?)?),
+    }
+}
+
+fn vault_dir(request: &VaultRequest) -> Result<PathBuf> {
+    let dir = request
+        .vault
+        .clone()
+        .or_else(|| std::env::var("LAO_VAULT_DIR").ok())
+        .ok_or_else(|| anyhow!("set 'vault' or LAO_VAULT_DIR"))?;
+    let dir = expand_home(&dir);
+    if !dir.is_dir() {
+        return Err(anyhow!("vault not found: {}", dir.display()));
+    }
+    Ok(dir)
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+// The note's path inside the vault, refusing paths that would leave it
+fn note_path(note: &str) -> Result<String> {
+    let note = note.trim().trim_start_matches('/');
+    let path = Path::new(note);
+    if note.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow!("invalid note path '{}'", note));
+    }
+    Ok(match path.extension() {
+        Some(_) => note.to_string(),
+        None => format!("{}.md", note),
+    })
+}
+
+// Frontmatter between `---` lines at the top of the note, and the body after it
+fn split_frontmatter(text: &str) -> Result<(Mapping, &str)> {
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return Ok((Mapping::new(), text));
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            let yaml = &rest[..offset];
+            let frontmatter = if yaml.trim().is_empty() {
+                Mapping::new()
+            } else {
+                serde_yaml::from_str(yaml)?
+            };
+            return Ok((frontmatter, &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    Ok((Mapping::new(), text))
+}
+
+fn render(frontmatter: &Mapping, body: &str) -> Result<String> {
+    let mut text = String::new();
+    if !frontmatter.is_empty() {
+        text.push_str("---\n");
+        text.push_str(&serde_yaml::to_string(frontmatter)?);
+        text.push_str("---\n");
+    }
+    text.push_str(body);
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+fn parse_note(note: &str, text: &str) -> Result<Note> {
+    let (frontmatter, body) =
+        split_frontmatter(text).map_err(|e| anyhow!("{}: invalid frontmatter: {}", note, e))?;
+    let title = Path::new(note)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(Note {
+        note: note.to_string(),
+        title,
+        frontmatter,
+        body: body.to_string(),
+    })
+}
+
+// Lists gain the new items they lack; other fields are replaced
+fn merge_frontmatter(existing: &mut Mapping, fields: &Mapping) {
+    for (key, value) in fields {
+        match (existing.get_mut(key), value) {
+            (Some(Value::Sequence(items)), Value::Sequence(new)) => {
+                for item in new {
+                    if !items.contains(item) {
+                        items.push(item.clone());
+                    }
+                }
+            }
+            _ => {
+                existing.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+// `path`, or the first of `stem-2.md`, `stem-3.md`, ... that is free
+fn next_version(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    std::iter::once(path.to_path_buf())
+        .chain((2..).map(|n| path.with_file_name(format!("{}-{}{}", stem, n, ext))))
+        .find(|candidate| !candidate.exists())
+        .expect("some version number is free")
+}
+
+fn write_note(vault: &Path, note: &str, request: &VaultRequest) -> Result<VaultReport> {
+    let mut path = vault.join(note);
+    let existing = if path.exists() {
+        Some(std::fs::read_to_string(&path)?)
+    } else {
+        None
+    };
+
+    let text = match (request.action.as_str(), existing) {
+        ("append", Some(existing)) => {
+            let current = parse_note(note, &existing)?;
+            let mut frontmatter = current.frontmatter;
+            merge_frontmatter(&mut frontmatter, &request.frontmatter);
+            let body = current.body.trim_end();
+            let body = if body.is_empty() {
+                request.content.clone()
+            } else {
+                format!("{}\n\n{}", body, request.content.trim_start_matches('\n'))
+            };
+            render(&frontmatter, &body)?
+        }
+        ("write", Some(_)) if request.on_exists == "skip" => {
+            return Ok(VaultReport {
+                action: request.action.clone(),
+                note: note.to_string(),
+                path: path.to_string_lossy().to_string(),
+                written: false,
+            });
+        }
+        (_, existing) => {
+            if existing.is_some() && request.on_exists == "version" {
+                path = next_version(&path);
+            }
+            render(&request.frontmatter, &request.content)?
+        }
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, text)?;
+    let note = path
+        .strip_prefix(vault)
+        .map(slash_path)
+        .unwrap_or_else(|_| note.to_string());
+    Ok(VaultReport {
+        action: request.action.clone(),
+        note,
+        path: path.to_string_lossy().to_string(),
+        written: true,
+    })
+}
+
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Tags from the frontmatter `tags` field and #tags in the body, lowercased
+fn note_tags(note: &Note) -> Vec<String> {
+    let listed: Vec<String> = match note.frontmatter.get("tags") {
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(text)) => text
+            .split([',', ' '])
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+    let inline = note.body.split_whitespace().filter_map(|word| {
+        let tag = word.strip_prefix('#')?;
+        let tag = tag.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let valid = tag.chars().next().is_some_and(char::is_alphanumeric)
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'));
+        valid.then(|| tag.to_string())
+    });
+    listed
+        .into_iter()
+        .chain(inline)
+        .map(|tag| tag.trim_start_matches('#').to_lowercase())
+        .collect()
+}
+
+fn matches_query(note: &Note, query: &NoteQuery) -> bool {
+    if let Some(folder) = &query.folder {
+        let folder = format!("{}/", folder.trim_matches('/'));
+        if !note.note.starts_with(&folder) {
+            return false;
+        }
+    }
+    if let Some(text) = &query.text {
+        let text = text.to_lowercase();
+        if !note.title.to_lowercase().contains(&text) && !note.body.to_lowercase().contains(&text) {
+            return false;
+        }
+    }
+    if !query.tags.is_empty() {
+        let tags = note_tags(note);
+        if !query
+            .tags
+            .iter()
+            .all(|tag| tags.contains(&tag.trim_start_matches('#').to_lowercase()))
+        {
+            return false;
+        }
+    }
+    query
+        .fields
+        .iter()
+        .all(|(key, wanted)| match note.frontmatter.get(key) {
+            Some(Value::Sequence(items)) => items.contains(wanted),
+            Some(value) => value == wanted,
+            None => false,
+        })
+}
+
+// Matching notes, most recently modified first; hidden folders like .obsidian are skipped
+fn search(vault: &Path, query: &NoteQuery, limit: usize) -> Result<Vec<Note>> {
+    let mut found = Vec::new();
+    let walker = walkdir::WalkDir::new(vault)
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'));
+    for entry in walker {
+        let entry = entry?;
+        let is_markdown = entry
+            .path()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
+        if !entry.file_type().is_file() || !is_markdown {
+            continue;
+        }
+        let relative = slash_path(entry.path().strip_prefix(vault).unwrap_or(entry.path()));
+        let note = match std::fs::read_to_string(entry.path())
+            .map_err(anyhow::Error::from)
+            .and_then(|text| parse_note(&relative, &text))
+        {
+            Ok(note) => note,
+            Err(e) => {
+                warn!("Skipping {}: {}", relative, e);
+                continue;
+            }
+        };
+        if matches_query(&note, query) {
+            let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+            found.push((modified, note));
+        }
+    }
+    found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.note.cmp(&b.1.note)));
+    Ok(found
+        .into_iter()
+        .take(limit)
+        .map(|(_, note)| note)
+        .collect())
+}
+
+#[no_mangle]
+pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    version: ABI_VERSION,
+    name,
+    run,
+    free_output,
+    run_with_buffer,
+    get_metadata,
+    validate_input,
+    get_capabilities,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
+    &PLUGIN_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn request(vault: &Path, yaml: &str) -> String {
+        format!("vault: {}\n{}", vault.display(), yaml)
+    }
+
+    #[test]
+    fn test_plugin_name() {
+        unsafe {
+            let name_str = CStr::from_ptr(name()).to_str().unwrap();
+            assert_eq!(name_str, "VaultPlugin");
+        }
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let req = parse_request("read Meetings/standup").unwrap();
+        assert_eq!(req.note.as_deref(), Some("Meetings/standup.md"));
+
+        let req = parse_request("search release date").unwrap();
+        assert_eq!(req.query.text.as_deref(), Some("release date"));
+
+        let req = parse_request("note: ideas.txt\ncontent: hi").unwrap();
+        assert_eq!(req.action, "write");
+        assert_eq!(req.note.as_deref(), Some("ideas.txt"));
+
+        assert!(parse_request("action: write\ncontent: hi").is_err());
+        assert!(parse_request("note: ../outside\ncontent: hi").is_err());
+        assert!(parse_request("note: a\non_exists: replace").is_err());
+        assert!(parse_request("delete a").is_err());
+    }
+
+    #[test]
+    fn test_frontmatter_round_trip() {
+        let (frontmatter, body) = split_frontmatter("---\ntags: [a]\n---\n# Title\n").unwrap();
+        assert_eq!(
+            frontmatter.get("tags"),
+            Some(&serde_yaml::from_str("[a]").unwrap())
+        );
+        assert_eq!(body, "# Title\n");
+
+        let text = render(&frontmatter, body).unwrap();
+        assert_eq!(split_frontmatter(&text).unwrap().1, "# Title\n");
+        assert_eq!(
+            split_frontmatter("no frontmatter").unwrap().1,
+            "no frontmatter"
+        );
+        assert!(split_frontmatter("---\n: [\n---\nbody").is_err());
+    }
+
+    const STANDUP: &str = "note: Meetings/standup\nfrontmatter:\n  tags: [meeting]\n  project: lao\ncontent: Release moves to Friday.";
+
+    /// A vault holding Meetings/standup.md, written and then appended to.
+    fn standup_vault() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path();
+        std::fs::create_dir_all(vault.join(".obsidian")).unwrap();
+        std::fs::write(vault.join(".obsidian/hidden.md"), "#meeting").unwrap();
+        let report: VaultReport =
+            serde_json::from_str(&process_input(&request(vault, STANDUP)).unwrap()).unwrap();
+        assert_eq!(report.note, "Meetings/standup.md");
+        assert!(report.written);
+        let append = "action: append\nnote: Meetings/standup\nfrontmatter:\n  tags: [followup]\ncontent: \"Action: ship #release\"";
+        process_input(&request(vault, append)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_append_merges_body_and_tags() {
+        let dir = standup_vault();
+        let note: Note = serde_json::from_str(
+            &process_input(&request(dir.path(), "action: read\nnote: Meetings/standup")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            note.body,
+            "Release moves to Friday.\n\nAction: ship #release\n"
+        );
+        assert_eq!(note_tags(&note), vec!["meeting", "followup", "release"]);
+    }
+
+    #[test]
+    fn test_write_to_existing_note_skips_or_versions() {
+        let dir = standup_vault();
+        let vault = dir.path();
+        let skip = format!("{}\non_exists: skip", STANDUP);
+        let report: VaultReport =
+            serde_json::from_str(&process_input(&request(vault, &skip)).unwrap()).unwrap();
+        assert!(!report.written);
+        let version = format!("{}\non_exists: version", STANDUP);
+        let report: VaultReport =
+            serde_json::from_str(&process_input(&request(vault, &version)).unwrap()).unwrap();
+        assert_eq!(report.note, "Meetings/standup-2.md");
+    }
+
+    #[test]
+    fn test_search_by_tags_text_and_fields() {
+        let dir = standup_vault();
+        let vault = dir.path();
+        let version = format!("{}\non_exists: version", STANDUP);
+        process_input(&request(vault, &version)).unwrap();
+        std::fs::write(vault.join("ideas.md"), "Release notes draft").unwrap();
+        let search = |query: &str| -> Vec<String> {
+            let output = process_input(&request(vault, query)).unwrap();
+            let notes: Vec<Note> = serde_json::from_str(&output).unwrap();
+            let mut names: Vec<String> = notes.into_iter().map(|n| n.note).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            search("action: search\nquery:\n  tags: [release]"),
+            ["Meetings/standup.md"]
+        );
+        assert_eq!(
+            search("action: search\nquery:\n  text: draft"),
+            ["ideas.md"]
+        );
+        assert_eq!(
+            search("action: search\nquery:\n  folder: Meetings\n  where:\n    project: lao"),
+            ["Meetings/standup-2.md", "Meetings/standup.md"]
+        );
+        assert!(search("action: search\nquery:\n  tags: [meeting]\n  text: nothing").is_empty());
+    }
+}
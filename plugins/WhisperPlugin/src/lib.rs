@@ -1,7 +1,36 @@
-use lao_plugin_api::{PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr};
-use std::ffi::{CStr, CString};
+use lao_plugin_api::typed::{self, Field, Payload, RawPayload, Signature, TypedVTable, Value};
+use lao_plugin_api::{
+    run_process, PluginInput, PluginMetadata, PluginOutput, PluginVTablePtr, ABI_VERSION,
+};
 use std::os::raw::c_char;
 use std::process::Command;
+use std::sync::OnceLock;
+
+static SIGNATURE: &std::ffi::CStr = c"{\"inputs\":[{\"name\":\"audio\",\"type\":\"file\",\"content_type\":\"audio/*\"},{\"name\":\"language\",\"type\":\"text\",\"optional\":true,\"description\":\"Spoken language, e.g. en; detected when unset\"}],\"outputs\":[{\"name\":\"transcript\",\"type\":\"text\"}]}";
+
+fn signature() -> &'static Signature {
+    static PARSED: OnceLock<Signature> = OnceLock::new();
+    PARSED.get_or_init(|| serde_json::from_str(SIGNATURE.to_str().unwrap()).unwrap())
+}
+
+fn transcribe(input: Payload) -> Result<Payload, String> {
+    let Some(Value::File(audio)) = input.value("audio") else {
+        return Err("audio must be a file".to_string());
+    };
+    let mut command = Command::new("./whisper.cpp");
+    command.arg(audio);
+    if let Some(Value::Text(language)) = input.value("language") {
+        command.args(["-l", language]);
+    }
+    // Progress and errors reach the run's logs as whisper.cpp writes them
+    match run_process(&mut command) {
+        Ok(out) if out.status.success() => {
+            Ok(Payload::new().with(Field::text("transcript", out.stdout)))
+        }
+        Ok(out) => Err(format!("whisper.cpp failed: {}", out.stderr)),
+        Err(e) => Err(format!("Failed to run whisper.cpp: {}", e)),
+    }
+}
 
 unsafe extern "C" fn name() -> *const c_char {
     c"WhisperPlugin".as_ptr()
@@ -9,36 +38,21 @@ unsafe extern "C" fn name() -> *const c_char {
 
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
-        return PluginOutput {
-            text: std::ptr::null_mut(),
-        };
+        return PluginOutput::null();
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let audio_path = c_str.to_string_lossy();
-    let output = Command::new("./whisper.cpp").arg(&*audio_path).output();
-    let text = match output {
-        Ok(out) if out.status.success() => {
-            CString::new(String::from_utf8_lossy(&out.stdout).to_string())
-                .unwrap()
-                .into_raw()
-        }
-        Ok(out) => CString::new(format!(
-            "whisper.cpp failed: {}",
-            String::from_utf8_lossy(&out.stderr)
-        ))
-        .unwrap()
-        .into_raw(),
-        Err(e) => CString::new(format!("Failed to run whisper.cpp: {}", e))
-            .unwrap()
-            .into_raw(),
-    };
-    PluginOutput { text }
+    typed::serve_text(input, signature(), transcribe)
+}
+
+unsafe extern "C" fn typed_signature() -> *const c_char {
+    SIGNATURE.as_ptr()
+}
+
+unsafe extern "C" fn typed_run(input: *const RawPayload) -> RawPayload {
+    typed::serve(input, transcribe)
 }
 
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 unsafe extern "C" fn run_with_buffer(
@@ -55,8 +69,8 @@ unsafe extern "C" fn get_metadata() -> PluginMetadata {
     static VERSION: &[u8] = b"1.0.0\0";
     static DESCRIPTION: &[u8] = b"Whisper speech-to-text plugin for LAO\0";
     static AUTHOR: &[u8] = b"LAO Team\0";
-    static TAGS: &[u8] = b"[\"speech\", \"whisper\", \"audio\", \"transcription\"]\0";
-    static CAPABILITIES: &[u8] = b"[{\"name\":\"speech-to-text\",\"description\":\"Convert speech to text using Whisper\",\"input_type\":\"Text\",\"output_type\":\"Text\"}]\0";
+    static TAGS: &[u8] = b"[\"speech\", \"whisper\", \"audio\", \"transcription\", \"deterministic\"]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"speech-to-text\",\"description\":\"Convert speech to text using Whisper\",\"input_type\":\"Audio\",\"output_type\":\"Text\"}]\0";
 
     PluginMetadata {
         name: NAME.as_ptr() as *const c_char,
@@ -75,19 +89,18 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
     if input.is_null() {
         return false;
     }
-    let c_str = CStr::from_ptr((*input).text);
-    let text = c_str.to_string_lossy();
+    let text = (*input).to_string_lossy();
     !text.trim().is_empty()
 }
 
 unsafe extern "C" fn get_capabilities() -> *const c_char {
-    static CAPABILITIES: &[u8] = b"[{\"name\":\"speech-to-text\",\"description\":\"Convert speech to text using Whisper\",\"input_type\":\"Text\",\"output_type\":\"Text\"}]\0";
+    static CAPABILITIES: &[u8] = b"[{\"name\":\"speech-to-text\",\"description\":\"Convert speech to text using Whisper\",\"input_type\":\"Audio\",\"output_type\":\"Text\"}]\0";
     CAPABILITIES.as_ptr() as *const c_char
 }
 
 #[no_mangle]
 pub static PLUGIN_VTABLE: lao_plugin_api::PluginVTable = lao_plugin_api::PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -102,11 +115,56 @@ pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
 
+pub static TYPED_VTABLE: TypedVTable = TypedVTable {
+    version: typed::TYPED_VERSION,
+    signature: typed_signature,
+    run: typed_run,
+    free_output: typed::free_output,
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_typed_vtable() -> *const TypedVTable {
+    &TYPED_VTABLE
+}
+
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
+/// # Safety
+///
+/// `output` must be null or point to a valid HostProcessOutput.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_process_output(
+    output: *const lao_plugin_api::HostProcessOutput,
+) -> bool {
+    lao_plugin_api::accept_host_process_output(output)
+}
+
+/// # Safety
+///
+/// `stop` must be null or point to a valid HostStop.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_stop(stop: *const lao_plugin_api::HostStop) -> bool {
+    lao_plugin_api::accept_host_stop(stop)
+}
+
+/// # Safety
+///
+/// `command` must be null or point to a valid HostCommand.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_host_command(command: *const lao_plugin_api::HostCommand) -> bool {
+    lao_plugin_api::accept_host_command(command)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use lao_plugin_api::*;
-    use std::ffi::CString;
 
     #[test]
     fn test_plugin_name() {
@@ -121,17 +179,28 @@ mod tests {
     #[test]
     fn test_validate_input() {
         unsafe {
-            let valid_input = CString::new("path/to/audio.wav").unwrap();
-            let input = PluginInput {
-                text: valid_input.into_raw(),
-            };
+            let valid_input = PluginInputBuf::new("path/to/audio.wav");
+            let input = valid_input.as_input();
             assert!(validate_input(&input));
 
-            let empty_input = CString::new("   ").unwrap();
-            let input = PluginInput {
-                text: empty_input.into_raw(),
-            };
+            let empty_input = PluginInputBuf::new("   ");
+            let input = empty_input.as_input();
             assert!(!validate_input(&input));
         }
     }
+
+    #[test]
+    fn test_typed_run_checks_its_fields() {
+        let declared = signature();
+        assert_eq!(declared.inputs[0].content_type.as_deref(), Some("audio/*"));
+        assert!(declared.inputs[1].optional);
+        unsafe {
+            let input = Payload::new().with(Field::text("audio", "talk.wav"));
+            let buffer = input.to_raw();
+            let output = typed_run(&buffer.as_raw());
+            let result = Payload::from_raw(&output).unwrap().into_result();
+            typed::free_output(output);
+            assert_eq!(result.unwrap_err(), "audio must be a file");
+        }
+    }
 }
@@ -2,7 +2,7 @@ use anyhow::Result;
 use lao_plugin_api::*;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::os::raw::c_char;
 
 // Plugin configuration
@@ -52,21 +52,14 @@ unsafe extern "C" fn name() -> *const c_char {
 unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     if input.is_null() {
         error!("Received null input");
-        let error_msg = CString::new("error: null input").unwrap();
-        return PluginOutput {
-            text: error_msg.into_raw(),
-        };
+        return PluginOutput::new("error: null input");
     }
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {
         Ok(s) => s,
         Err(_) => {
             error!("Invalid UTF-8 in input");
-            let error_msg = CString::new("error: invalid UTF-8 input").unwrap();
-            return PluginOutput {
-                text: error_msg.into_raw(),
-            };
+            return PluginOutput::new("error: invalid UTF-8 input");
         }
     };
 
@@ -74,10 +67,7 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
 
     // Validate input
     if !validate_input_internal(input_text) {
-        let error_msg = CString::new("error: invalid input format").unwrap();
-        return PluginOutput {
-            text: error_msg.into_raw(),
-        };
+        return PluginOutput::new("error: invalid input format");
     }
 
     // Process input
@@ -90,17 +80,12 @@ unsafe extern "C" fn run(input: *const PluginInput) -> PluginOutput {
     };
 
     info!("Returning output: {}", result);
-    let output_cstring = CString::new(result).unwrap();
-    PluginOutput {
-        text: output_cstring.into_raw(),
-    }
+    PluginOutput::new(result)
 }
 
 // Free output function
 unsafe extern "C" fn free_output(output: PluginOutput) {
-    if !output.text.is_null() {
-        let _ = CString::from_raw(output.text);
-    }
+    output.free();
 }
 
 // Run with buffer function
@@ -113,8 +98,7 @@ unsafe extern "C" fn run_with_buffer(
         return 0;
     }
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {
         Ok(s) => s,
         Err(_) => return 0,
     };
@@ -164,8 +148,7 @@ unsafe extern "C" fn validate_input(input: *const PluginInput) -> bool {
         return false;
     }
 
-    let c_str = CStr::from_ptr((*input).text);
-    let input_text = match c_str.to_str() {
+    let input_text = match std::str::from_utf8((*input).as_bytes()) {
         Ok(s) => s,
         Err(_) => return false,
     };
@@ -194,7 +177,7 @@ fn process_input(input: &str) -> Result<String> {
 // Plugin vtable
 #[no_mangle]
 pub static PLUGIN_VTABLE: PluginVTable = PluginVTable {
-    version: 1,
+    version: ABI_VERSION,
     name,
     run,
     free_output,
@@ -209,12 +192,19 @@ pub extern "C" fn plugin_vtable() -> PluginVTablePtr {
     &PLUGIN_VTABLE
 }
 
+/// # Safety
+///
+/// `allocator` must be null or point to a valid HostAllocator.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_init(allocator: *const lao_plugin_api::HostAllocator) -> bool {
+    lao_plugin_api::accept_host_allocator(allocator)
+}
+
 // Test module
 #[cfg(test)]
 mod tests {
     use super::*;
-    use lao_plugin_api::*;
-    use std::ffi::CString;
+    use std::ffi::CStr;
 
     #[test]
     fn test_plugin_name() {
@@ -229,16 +219,12 @@ mod tests {
     #[test]
     fn test_validate_input() {
         unsafe {
-            let valid_input = CString::new("valid input").unwrap();
-            let input = PluginInput {
-                text: valid_input.into_raw(),
-            };
+            let valid_input = PluginInputBuf::new("valid input");
+            let input = valid_input.as_input();
             assert!(validate_input(&input));
 
-            let invalid_input = CString::new("").unwrap();
-            let input = PluginInput {
-                text: invalid_input.into_raw(),
-            };
+            let invalid_input = PluginInputBuf::new("");
+            let input = invalid_input.as_input();
             assert!(!validate_input(&input));
         }
     }
@@ -252,14 +238,11 @@ mod tests {
     #[test]
     fn test_plugin_run() {
         unsafe {
-            let input_text = CString::new("test input").unwrap();
-            let input = PluginInput {
-                text: input_text.into_raw(),
-            };
+            let input_text = PluginInputBuf::new("test input");
+            let input = input_text.as_input();
 
             let output = run(&input);
-            let output_cstr = CStr::from_ptr(output.text);
-            let output_str = output_cstr.to_str().unwrap();
+            let output_str = std::str::from_utf8(output.as_bytes()).unwrap();
 
             assert_eq!(output_str, "Processed: test input");
 
@@ -1,20 +1,28 @@
 use eframe::egui::{self, Color32, RichText};
+use lao_orchestrator_core::node::NodeId;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use crate::backend::{list_plugins_for_ui, BackendState};
-use crate::components::{graph, inspector, logs, toolbar};
+use crate::backend::{apply_events, list_plugins_for_ui, BackendState};
+use crate::components::{compare, gallery, graph, inspector, logs, queue, toolbar, usage};
+use crate::session::Replay;
 
 pub struct LaoApp {
     state: Arc<Mutex<BackendState>>,
 
     // UI Logic states
     graph_state: graph::GraphEditorState,
-    pipe_source_for_node: HashMap<String, String>,
+    pipe_source_for_node: HashMap<NodeId, NodeId>,
+    usage_panel: usage::UsagePanelState,
+    gallery_panel: gallery::GalleryPanelState,
+    compare_panel: compare::ComparePanelState,
+    queue_panel: queue::QueuePanelState,
+    /// Recording played back instead of a real backend (`--replay`)
+    replay: Option<Replay>,
 }
 
 impl LaoApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(_cc: &eframe::CreationContext<'_>, replay: Option<Replay>) -> Self {
         let mut state = BackendState::default();
 
         // Try to load plugins on startup
@@ -26,6 +34,11 @@ impl LaoApp {
             state: Arc::new(Mutex::new(state)),
             graph_state: graph::GraphEditorState::default(),
             pipe_source_for_node: HashMap::new(),
+            usage_panel: usage::UsagePanelState::default(),
+            gallery_panel: gallery::GalleryPanelState::default(),
+            compare_panel: compare::ComparePanelState::default(),
+            queue_panel: queue::QueuePanelState::default(),
+            replay,
         }
     }
 }
@@ -35,6 +48,21 @@ impl eframe::App for LaoApp {
         // Set a more professional theme
         ctx.set_visuals(egui::Visuals::dark());
 
+        // Take whatever the running workflow queued since the last frame
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(replay) = &mut self.replay {
+                replay.frame(&mut state);
+                if !replay.is_done() {
+                    ctx.request_repaint_after(std::time::Duration::from_millis(33));
+                }
+            }
+            apply_events(&mut state);
+            if state.events.is_some() {
+                ctx.request_repaint_after(std::time::Duration::from_millis(33));
+            }
+        }
+
         // Handle keyboard shortcuts
         if ctx.input(|i| i.key_pressed(egui::Key::Delete)) {
             let mut state = self.state.lock().unwrap();
@@ -68,11 +96,33 @@ impl eframe::App for LaoApp {
                 },
             );
 
+            if let Some(replay) = &self.replay {
+                let (played, total) = replay.progress();
+                let header = &replay.header;
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!(
+                        "▶ Replaying {} ({}/{}), recorded {} on {} with LAO {}",
+                        replay.path.display(),
+                        played,
+                        total,
+                        header.started.format("%Y-%m-%d %H:%M"),
+                        header.os,
+                        header.lao_version
+                    ),
+                );
+            }
+
             ui.add_space(10.0);
 
             // 1. Top Bar / Workflow Management
             toolbar::show(ui, &self.state);
 
+            ui.add_space(10.0);
+
+            // Workflows in workflows/, by title and description
+            gallery::show(ui, &mut self.gallery_panel, &self.state);
+
             ui.add_space(15.0);
 
             // 2. Main Workspace (Graph + Inspector)
@@ -85,8 +135,27 @@ impl eframe::App for LaoApp {
             let workflow_result = state.workflow_result.clone();
             // Clone plugins so we can use them while graph is borrowed mutably
             let plugins = state.plugins.clone();
+            let paused_slot = state.paused.clone();
+            let mut paused = paused_slot.lock().unwrap();
+
+            // A run waiting at a breakpoint is resumed from its node's inspector
+            if let Some(run) = paused.as_ref() {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        Color32::YELLOW,
+                        format!("⏸ Paused before {} ({})", run.step_id, run.plugin),
+                    );
+                    if ui.button("Edit input").clicked() {
+                        self.graph_state.selected_node = Some(run.step_id.clone());
+                    }
+                });
+                ui.add_space(10.0);
+            }
 
             if let Some(ref mut graph) = state.graph {
+                if let Some(metadata) = &graph.metadata {
+                    gallery::header(ui, metadata);
+                }
                 // Split view: Left = Graph (bigger), Right = Inspector (if selected)
                 if self.graph_state.selected_node.is_some() {
                     ui.columns(2, |columns| {
@@ -107,6 +176,7 @@ impl eframe::App for LaoApp {
                                     &mut graph.edges,
                                     &mut self.pipe_source_for_node,
                                     &mut self.graph_state.connecting_from,
+                                    &mut paused,
                                 );
 
                                 match action {
@@ -136,6 +206,8 @@ impl eframe::App for LaoApp {
                         state.graph = Some(crate::backend::WorkflowGraph {
                             nodes: Vec::new(),
                             edges: Vec::new(),
+                            workflow: None,
+                            metadata: None,
                         });
                     }
                 });
@@ -143,14 +215,40 @@ impl eframe::App for LaoApp {
 
             ui.add_space(15.0);
 
-            // 3. Bottom: Logs
+            // 3. Bottom: Logs, next to the graph's streaming steps
+            let state = &mut *state;
             logs::show(
                 ui,
                 &mut state.live_logs,
                 is_running,
                 execution_progress,
                 &workflow_result,
+                state.graph.as_ref(),
             );
+
+            ui.add_space(10.0);
+
+            // 4. Usage history
+            usage::show(ui, &mut self.usage_panel);
+
+            ui.add_space(10.0);
+
+            // 5. Run comparison
+            compare::show(ui, &mut self.compare_panel);
+
+            ui.add_space(10.0);
+
+            // 6. Queued runs
+            queue::show(ui, &mut self.queue_panel, &state.workflow_path);
+            if self.queue_panel.is_busy() {
+                ctx.request_repaint_after(std::time::Duration::from_millis(250));
+            }
+
+            // Edits and finished runs of the frame, when recording
+            if let Some(mut recorder) = state.recorder.take() {
+                recorder.frame(state);
+                state.recorder = Some(recorder);
+            }
         });
     }
 }
@@ -1,35 +1,90 @@
+use crate::events::EventQueue;
+use crate::session::Recorder;
 use lao_orchestrator_core::{
-    load_workflow_yaml, run_workflow_yaml_parallel_with_callback, run_workflow_yaml_with_callback,
-    StepEvent,
+    breakpoints::{self, Breakpoints, PauseHandler, PausedStep, Resume},
+    cancel::{self, CancelToken},
+    checkpoint::{self, Checkpoint},
+    cross_platform::{PathUtils, Platform},
+    display::StepDisplay,
+    foreach::Instances,
+    logging::{self, LogEvent, LogLevel},
+    metadata::WorkflowMetadata,
+    node::{NodeId, NodeStatus},
+    plan::ExecutionPlan,
+    plugins, run_diff, run_labels, run_workflow_yaml_parallel_with_callback,
+    run_workflow_yaml_with_callback, StepEvent,
 };
+use lao_plugin_api::ArtifactRef;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkflowGraph {
     pub nodes: Vec<GraphNode>,
     pub edges: Vec<GraphEdge>,
+    /// Name of the loaded workflow, to find its past runs
+    #[serde(default)]
+    pub workflow: Option<String>,
+    /// Its `metadata:` block, shown above the canvas and kept on save
+    #[serde(default)]
+    pub metadata: Option<WorkflowMetadata>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphNode {
-    pub id: String,
+    pub id: NodeId,
     pub run: String,
     pub input_type: Option<String>,
     pub output_type: Option<String>,
-    pub status: String,
+    pub status: NodeStatus,
     pub x: f32,
     pub y: f32,
     pub message: Option<String>,
     pub output: Option<String>,
     pub error: Option<String>,
+    #[serde(default)]
+    pub artifact: Option<ArtifactRef>,
     pub attempt: u32,
+    /// How far a fan-out step (`foreach`) got through its items
+    #[serde(default)]
+    pub instances: Option<Instances>,
+    /// Left out of runs (`enabled: false`) until toggled back on
+    #[serde(default)]
+    pub disabled: bool,
+    /// Runs pause before the step so its input can be edited
+    #[serde(default)]
+    pub breakpoint: bool,
+    /// Label, icon and color in the graph
+    #[serde(default)]
+    pub display: StepDisplay,
+    /// The step's params, searched by the node finder
+    #[serde(default)]
+    pub params: serde_yaml::Value,
+    /// Preset the step is filled from when loaded
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Wraps the input, `{input}` standing for it; from the preset when it has one
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Prompt file the `prompt` param is read from when loaded, `path@version`
+    #[serde(default)]
+    pub prompt_file: Option<String>,
+    /// Backend the step's LLM requests go to, e.g. `ollama@gpu-box`
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Times a failed attempt is retried; the default when unset
+    #[serde(default)]
+    pub retries: Option<u32>,
+    /// When the failed attempt is retried, counted down in the inspector
+    #[serde(skip)]
+    pub retry_at: Option<std::time::Instant>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphEdge {
-    pub from: String,
-    pub to: String,
+    pub from: NodeId,
+    pub to: NodeId,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,7 +101,9 @@ pub struct BackendState {
     pub graph: Option<WorkflowGraph>,
     pub error: String,
     pub plugins: Vec<UiPluginInfo>,
-    pub live_logs: Vec<String>,
+    pub live_logs: Vec<LogEvent>,
+    /// Events of the current run not yet applied, see `apply_events`
+    pub events: Option<Arc<EventQueue>>,
     #[allow(dead_code)]
     pub selected_node: Option<String>,
     pub is_running: bool,
@@ -54,6 +111,61 @@ pub struct BackendState {
     pub workflow_result: Option<WorkflowResult>,
     #[allow(dead_code)]
     pub multimodal_files: Vec<UploadedFile>,
+    /// The step the running workflow waits at, see `UiPause`
+    pub paused: Arc<Mutex<Option<PausedRun>>>,
+    /// Stops the running workflow (the toolbar's Stop button)
+    pub cancel: Option<CancelToken>,
+    /// Set while the session is recorded (the toolbar's Record button)
+    pub recorder: Option<Recorder>,
+    /// The last unfinished run of the open workflow (the toolbar's Resume
+    /// button), refreshed on load and after each run
+    pub resumable: Option<Checkpoint>,
+    /// Params of runs started from the toolbar, one `key=value` per line
+    pub run_params: String,
+}
+
+/// A run waiting at a breakpoint for the user to resume it
+pub struct PausedRun {
+    pub step_id: NodeId,
+    pub plugin: String,
+    /// The step's input, edited in the inspector
+    pub input: String,
+    original: String,
+    reply: mpsc::Sender<Resume>,
+}
+
+impl PausedRun {
+    /// Go on with the (possibly edited) input
+    pub fn resume(self) {
+        let resume = if self.input == self.original {
+            Resume::Continue
+        } else {
+            Resume::Replace(self.input)
+        };
+        self.reply.send(resume).ok();
+    }
+
+    pub fn abort(self) {
+        self.reply.send(Resume::Abort).ok();
+    }
+}
+
+// Hands a paused step to the UI and waits on the run's thread for the answer
+struct UiPause(Arc<Mutex<Option<PausedRun>>>);
+
+impl PauseHandler for UiPause {
+    fn paused(&self, step: &PausedStep) -> Resume {
+        let (reply, answer) = mpsc::channel();
+        *self.0.lock().unwrap() = Some(PausedRun {
+            step_id: NodeId::from(&step.step_id),
+            plugin: step.plugin.clone(),
+            input: step.input.clone(),
+            original: step.input.clone(),
+            reply,
+        });
+        // Dropped unanswered (the slot was replaced): stop the run
+        answer.recv().unwrap_or(Resume::Abort)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,11 +195,17 @@ impl Default for BackendState {
             error: String::new(),
             plugins: Vec::new(),
             live_logs: Vec::new(),
+            events: None,
             selected_node: None,
             is_running: false,
             execution_progress: 0.0,
             workflow_result: None,
             multimodal_files: Vec::new(),
+            paused: Arc::new(Mutex::new(None)),
+            cancel: None,
+            recorder: None,
+            resumable: None,
+            run_params: String::new(),
         }
     }
 }
@@ -98,44 +216,88 @@ pub fn greet(name: &str) -> String {
 }
 
 pub fn get_workflow_graph(path: &str) -> Result<WorkflowGraph, String> {
-    let workflow = load_workflow_yaml(path)?;
+    let plan = ExecutionPlan::load(path, &plugins::shared().snapshot())?;
     let mut nodes = Vec::new();
     let mut edges = Vec::new();
 
-    for (_i, step) in workflow.steps.iter().enumerate() {
-        let id = step.run.clone();
+    // One column per parallel group; a cyclic plan has no groups, so fall
+    // back to a single row in declaration order
+    let mut position = vec![(0usize, 0usize); plan.steps.len()];
+    if plan.groups.is_empty() {
+        for (i, pos) in position.iter_mut().enumerate() {
+            *pos = (i, 0);
+        }
+    }
+    for (column, group) in plan.groups.iter().enumerate() {
+        for (row, &i) in group.iter().enumerate() {
+            position[i] = (column, row);
+        }
+    }
+
+    for step in &plan.steps {
+        let (column, row) = position[step.index];
         nodes.push(GraphNode {
-            id: id.clone(),
-            run: step.run.clone(),
-            input_type: None,
-            output_type: None,
-            status: "pending".to_string(),
-            x: 100.0 + (_i as f32 * 150.0),
-            y: 100.0,
+            id: step.id.clone(),
+            run: step.step.run.clone(),
+            input_type: step.io_types.as_ref().map(|(i, _)| format!("{:?}", i)),
+            output_type: step.io_types.as_ref().map(|(_, o)| format!("{:?}", o)),
+            status: NodeStatus::Pending,
+            x: 100.0 + (column as f32 * 150.0),
+            y: 100.0 + (row as f32 * 100.0),
             message: None,
             output: None,
             error: None,
+            artifact: None,
             attempt: 0,
+            instances: None,
+            disabled: !step.step.is_enabled(),
+            breakpoint: step.step.breakpoint == Some(true),
+            display: step.step.display.clone().unwrap_or_default(),
+            params: step.step.params.clone(),
+            preset: step.step.preset.clone(),
+            // Left to the preset, so edits to it still apply after a save
+            template: step
+                .step
+                .template
+                .clone()
+                .filter(|_| step.step.preset.is_none()),
+            prompt_file: step.step.prompt_file.clone(),
+            backend: step.step.backend.clone(),
+            retries: step.step.retries,
+            retry_at: None,
         });
-
-        if let Some(ref from) = step.input_from {
+        for parent in &step.parents {
             edges.push(GraphEdge {
-                from: from.clone(),
-                to: id.clone(),
+                from: parent.clone(),
+                to: step.id.clone(),
             });
         }
+    }
 
-        if let Some(ref deps) = step.depends_on {
-            for d in deps {
-                edges.push(GraphEdge {
-                    from: d.clone(),
-                    to: id.clone(),
-                });
-            }
+    Ok(WorkflowGraph {
+        nodes,
+        edges,
+        workflow: Some(plan.workflow.workflow.clone()),
+        metadata: plan.workflow.metadata.clone(),
+    })
+}
+
+/// Load `state.workflow_path` into the graph editor
+pub fn load_workflow(state: &mut BackendState) {
+    match get_workflow_graph(&state.workflow_path) {
+        Ok(graph) => {
+            state.graph = Some(graph);
+            state.error.clear();
+        }
+        Err(e) => {
+            state.error = e;
+            state.graph = None;
         }
     }
-
-    Ok(WorkflowGraph { nodes, edges })
+    state.resumable = checkpoint::latest(&run_diff::default_dir(), &state.workflow_path);
+    if let Some(recorder) = &mut state.recorder {
+        recorder.loaded(&state.workflow_path, state.graph.as_ref(), &state.error);
+    }
 }
 
 pub fn list_plugins_for_ui() -> Result<Vec<UiPluginInfo>, String> {
@@ -201,20 +363,15 @@ pub fn list_plugins_for_ui() -> Result<Vec<UiPluginInfo>, String> {
         if let Ok(files) = std::fs::read_dir(&plugins_dir) {
             for f in files.flatten() {
                 let path = f.path();
-                if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                    if matches!(ext, "so" | "dll" | "dylib") {
-                        if let Some(fname) = path.file_stem().and_then(|s| s.to_str()) {
-                            let base = fname.strip_prefix("lib").unwrap_or(fname);
-                            if !out.iter().any(|i| i.name.eq_ignore_ascii_case(base)) {
-                                out.push(UiPluginInfo {
-                                    name: base.to_string(),
-                                    version: String::new(),
-                                    description: String::new(),
-                                    author: String::new(),
-                                    tags: Vec::new(),
-                                });
-                            }
-                        }
+                if let Some(base) = Platform::shared_lib_stem(&path) {
+                    if !out.iter().any(|i| i.name.eq_ignore_ascii_case(base)) {
+                        out.push(UiPluginInfo {
+                            name: base.to_string(),
+                            version: String::new(),
+                            description: String::new(),
+                            author: String::new(),
+                            tags: Vec::new(),
+                        });
                     }
                 }
             }
@@ -231,22 +388,53 @@ fn resolve_plugins_dir() -> String {
         }
     }
 
-    let candidates = ["plugins/", "./plugins/", "../plugins/", "../../plugins/"];
-
-    for candidate in &candidates {
-        if std::path::Path::new(candidate).exists() {
-            return candidate.to_string();
+    let dir = PathUtils::plugin_dir();
+    if !dir.exists() {
+        let grandparent = std::path::Path::new("..").join("..").join("plugins");
+        if grandparent.exists() {
+            return grandparent.to_string_lossy().into_owned();
         }
     }
+    dir.to_string_lossy().into_owned()
+}
 
-    "plugins/".to_string()
+/// Params given one `key=value` per line, as in the toolbar and the queue
+/// panel
+pub fn parse_params(text: &str) -> Result<HashMap<String, String>, String> {
+    let mut params = HashMap::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                params.insert(key.trim().to_string(), value.trim().to_string());
+            }
+            _ => return Err(format!("Invalid param '{}', expected key=value", line)),
+        }
+    }
+    Ok(params)
 }
 
+/// Run the workflow at `path` on a thread with the toolbar's params, reusing
+/// the steps `resume` completed if set (resumed runs go step by step, with
+/// the params of the run they resume)
 pub fn run_workflow_stream(
     path: String,
     parallel: bool,
+    resume: Option<Checkpoint>,
     state: Arc<Mutex<BackendState>>,
 ) -> Result<(), String> {
+    let inputs = match &resume {
+        Some(checkpoint) => checkpoint.inputs.clone(),
+        None => {
+            let mut state_guard = state.lock().unwrap();
+            match parse_params(&state_guard.run_params) {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    state_guard.error = e.clone();
+                    return Err(e);
+                }
+            }
+        }
+    };
     std::thread::spawn(move || {
         let start_time = std::time::Instant::now();
         let mut total_steps = 0;
@@ -254,72 +442,67 @@ pub fn run_workflow_stream(
         let mut failed_steps = 0;
 
         // Initialize execution state
-        {
+        let token = CancelToken::new();
+        let breakpoints = {
             let mut state_guard = state.lock().unwrap();
             state_guard.is_running = true;
+            state_guard.cancel = Some(token.clone());
             state_guard.execution_progress = 0.0;
             state_guard.workflow_result = None;
             state_guard.error.clear();
+            if let Some(recorder) = &mut state_guard.recorder {
+                recorder.run(&path, parallel);
+            }
 
             // Count total steps for progress tracking
             if let Some(ref graph) = state_guard.graph {
                 total_steps = graph.nodes.len();
             }
-        }
 
-        let emit = |event: StepEvent| {
-            if let Ok(mut state_guard) = state.lock() {
-                // Update node status in graph
-                if let Some(ref mut graph) = state_guard.graph {
-                    if let Some(node) = graph.nodes.iter_mut().find(|n| n.id == event.step_id) {
-                        node.status = event.status.clone();
-                        node.message = event.message.clone();
-                        node.output = event.output.clone();
-                        node.error = event.error.clone();
-                        node.attempt = event.attempt;
-                    }
-                }
+            // Steps marked in the graph pause even before the workflow is saved
+            let handler = Breakpoints::new(UiPause(state_guard.paused.clone()));
+            let nodes = state_guard.graph.iter().flat_map(|g| &g.nodes);
+            nodes
+                .filter(|node| node.breakpoint)
+                .fold(handler, |b, node| b.at(node.id.as_str()))
+        };
 
-                // Add to live logs
-                let log_message = format!(
-                    "[{}] {}: {} (attempt {}){}",
-                    event.step_id,
-                    event.runner,
-                    event.status,
-                    event.attempt,
-                    event
-                        .message
-                        .map(|m| format!(" - {}", m))
-                        .unwrap_or_default()
-                );
-                state_guard.live_logs.push(log_message);
-
-                // Limit log size
-                if state_guard.live_logs.len() > 200 {
-                    state_guard.live_logs.remove(0);
-                }
+        // Engine log events and step updates are applied by the UI once per frame
+        let queue = EventQueue::subscribe();
+        if let Ok(mut state_guard) = state.lock() {
+            state_guard.events = Some(queue.clone());
+        }
 
-                // Update progress
-                if event.status == "success" || event.status == "cache" {
-                    completed_steps += 1;
-                    state_guard.execution_progress = completed_steps as f32 / total_steps as f32;
-                } else if event.status == "error" {
-                    failed_steps += 1;
-                }
+        let emit = |event: StepEvent| {
+            if event.status.is_complete() {
+                completed_steps += 1;
+                queue.set_progress(completed_steps as f32 / total_steps as f32);
+            } else if event.status == NodeStatus::Error {
+                failed_steps += 1;
             }
+            queue.push_step(event);
         };
 
-        let result = if parallel {
-            run_workflow_yaml_parallel_with_callback(&path, emit)
-        } else {
-            run_workflow_yaml_with_callback(&path, emit)
-        };
+        let result = run_labels::with_trigger("ui", || {
+            cancel::with_token(token, || {
+                breakpoints::with_breakpoints(breakpoints, || match resume {
+                    Some(checkpoint) => checkpoint::with_resume(checkpoint, || {
+                        run_workflow_yaml_with_callback(&path, inputs, emit)
+                    }),
+                    None if parallel => {
+                        run_workflow_yaml_parallel_with_callback(&path, inputs, emit)
+                    }
+                    None => run_workflow_yaml_with_callback(&path, inputs, emit),
+                })
+            })
+        });
 
         let execution_time = start_time.elapsed().as_secs_f32();
 
         // Update final state
         if let Ok(mut state_guard) = state.lock() {
             state_guard.is_running = false;
+            state_guard.cancel = None;
             state_guard.execution_progress = 1.0;
 
             let workflow_result = match result {
@@ -329,9 +512,9 @@ pub fn run_workflow_stream(
                         logs.len(),
                         execution_time
                     );
-                    state_guard
-                        .live_logs
-                        .push(format!("✓ DONE: {}", final_message));
+                    logging::info(final_message.clone())
+                        .field("status", "done")
+                        .emit();
                     WorkflowResult {
                         success: true,
                         total_steps,
@@ -341,11 +524,25 @@ pub fn run_workflow_stream(
                         final_message,
                     }
                 }
+                Err(err) if err == cancel::CANCELLED => {
+                    let final_message = format!("Workflow stopped after {:.2}s", execution_time);
+                    logging::warn(final_message.clone())
+                        .field("status", "cancelled")
+                        .emit();
+                    WorkflowResult {
+                        success: false,
+                        total_steps,
+                        completed_steps,
+                        failed_steps,
+                        execution_time,
+                        final_message,
+                    }
+                }
                 Err(err) => {
                     let final_message = format!("Workflow failed: {}", err);
-                    state_guard
-                        .live_logs
-                        .push(format!("✗ ERROR: {}", final_message));
+                    logging::error(final_message.clone())
+                        .field("status", "failed")
+                        .emit();
                     state_guard.error = err;
                     WorkflowResult {
                         success: false,
@@ -359,15 +556,74 @@ pub fn run_workflow_stream(
             };
 
             state_guard.workflow_result = Some(workflow_result);
+            state_guard.resumable = checkpoint::latest(&run_diff::default_dir(), &path);
         }
+        queue.close();
     });
 
     Ok(())
 }
 
+const MAX_LIVE_LOGS: usize = 200;
+
+/// Apply the step updates and logs the current run queued since the last frame
+pub fn apply_events(state: &mut BackendState) {
+    let Some(queue) = state.events.clone() else {
+        return;
+    };
+    // Closed before taking means this batch is the run's last
+    let closed = queue.is_closed();
+    let mut batch = queue.take();
+    if let Some(recorder) = &mut state.recorder {
+        let logs = batch.logs.make_contiguous();
+        recorder.events(&batch.steps, logs, batch.progress, closed);
+    }
+
+    if let Some(ref mut graph) = state.graph {
+        for event in batch.steps {
+            if let Some(node) = graph.nodes.iter_mut().find(|n| n.id == event.step_id) {
+                node.status = event.status;
+                node.message = event.message;
+                node.output = event.output;
+                node.error = event.error;
+                node.artifact = event.artifact;
+                node.attempt = event.attempt;
+                node.retry_at = event
+                    .retry_in_ms
+                    .map(|ms| std::time::Instant::now() + std::time::Duration::from_millis(ms));
+                if event.instances.is_some() {
+                    node.instances = event.instances;
+                }
+            }
+        }
+    }
+    if let Some(progress) = batch.progress {
+        if state.is_running {
+            state.execution_progress = progress;
+        }
+    }
+    if batch.dropped_logs > 0 {
+        state.live_logs.push(LogEvent::new(
+            LogLevel::Warn,
+            format!(
+                "{} log lines skipped to keep up with the run",
+                batch.dropped_logs
+            ),
+        ));
+    }
+    state.live_logs.extend(batch.logs);
+    if state.live_logs.len() > MAX_LIVE_LOGS {
+        let excess = state.live_logs.len() - MAX_LIVE_LOGS;
+        state.live_logs.drain(..excess);
+    }
+    if closed {
+        state.events = None;
+    }
+}
+
 pub fn save_workflow_yaml(graph: &WorkflowGraph, filename: &str) -> Result<(), String> {
     // Build dependency info from edges
-    let mut incoming: std::collections::HashMap<String, Vec<String>> =
+    let mut incoming: std::collections::HashMap<NodeId, Vec<NodeId>> =
         std::collections::HashMap::new();
     for e in &graph.edges {
         incoming
@@ -378,16 +634,17 @@ pub fn save_workflow_yaml(graph: &WorkflowGraph, filename: &str) -> Result<(), S
 
     let workflow = lao_orchestrator_core::Workflow {
         workflow: filename.trim_end_matches(".yaml").to_string(),
+        metadata: graph.metadata.clone(),
         steps: graph
             .nodes
             .iter()
             .map(|node| {
                 let deps = incoming.get(&node.id).cloned().unwrap_or_default();
                 // input_from = first predecessor if any
-                let input_from = deps.first().cloned();
+                let input_from = deps.first().map(|d| d.to_string());
                 // remaining predecessors are depends_on
                 let depends_on = if deps.len() > 1 {
-                    Some(deps[1..].to_vec())
+                    Some(deps[1..].iter().map(|d| d.to_string()).collect())
                 } else {
                     None
                 };
@@ -395,17 +652,21 @@ pub fn save_workflow_yaml(graph: &WorkflowGraph, filename: &str) -> Result<(), S
                 lao_orchestrator_core::WorkflowStep {
                     run: node.run.clone(),
                     params: serde_yaml::Value::Null, // Could be enhanced to support parameters
-                    retries: None,
-                    retry_delay: None,
-                    cache_key: None,
+                    retries: node.retries,
                     input_from,
                     depends_on,
-                    condition: None,
-                    on_success: None,
-                    on_failure: None,
+                    enabled: node.disabled.then_some(false),
+                    breakpoint: node.breakpoint.then_some(true),
+                    display: (!node.display.is_empty()).then(|| node.display.clone()),
+                    template: node.template.clone(),
+                    preset: node.preset.clone(),
+                    prompt_file: node.prompt_file.clone(),
+                    backend: node.backend.clone(),
+                    ..Default::default()
                 }
             })
             .collect(),
+        ..Default::default()
     };
 
     let yaml_content = serde_yaml::to_string(&workflow).map_err(|e| e.to_string())?;
@@ -420,7 +681,7 @@ pub fn export_workflow_yaml(graph: &WorkflowGraph) -> Result<String, String> {
     yaml.push_str("steps:\n");
 
     // Create a map of node incoming edges (predecessors)
-    let mut incoming: std::collections::HashMap<String, Vec<String>> =
+    let mut incoming: std::collections::HashMap<NodeId, Vec<NodeId>> =
         std::collections::HashMap::new();
     for edge in &graph.edges {
         incoming
@@ -430,7 +691,7 @@ pub fn export_workflow_yaml(graph: &WorkflowGraph) -> Result<String, String> {
     }
 
     // Create a map of node ID to step index for proper step naming
-    let mut node_to_step: std::collections::HashMap<String, usize> =
+    let mut node_to_step: std::collections::HashMap<NodeId, usize> =
         std::collections::HashMap::new();
     for (index, node) in graph.nodes.iter().enumerate() {
         node_to_step.insert(node.id.clone(), index);
@@ -468,7 +729,34 @@ pub fn export_workflow_yaml(graph: &WorkflowGraph) -> Result<String, String> {
         if let Some(ref output_type) = node.output_type {
             yaml.push_str(&format!("  output_type: {}\n", output_type));
         }
-        if node.status != "pending" {
+        if node.disabled {
+            yaml.push_str("  enabled: false\n");
+        }
+        if node.breakpoint {
+            yaml.push_str("  breakpoint: true\n");
+        }
+        if let Some(preset) = &node.preset {
+            yaml.push_str(&format!("  preset: {}\n", preset));
+        }
+        if let Some(prompt_file) = &node.prompt_file {
+            yaml.push_str(&format!("  prompt_file: {}\n", prompt_file));
+        }
+        if let Some(backend) = &node.backend {
+            yaml.push_str(&format!("  backend: {}\n", backend));
+        }
+        if let Some(template) = &node.template {
+            // A JSON string is a valid YAML scalar, escapes and all
+            let quoted = serde_json::to_string(template).map_err(|e| e.to_string())?;
+            yaml.push_str(&format!("  template: {}\n", quoted));
+        }
+        if !node.display.is_empty() {
+            yaml.push_str("  display:\n");
+            let display = serde_yaml::to_string(&node.display).map_err(|e| e.to_string())?;
+            for line in display.lines() {
+                yaml.push_str(&format!("    {}\n", line));
+            }
+        }
+        if node.status != NodeStatus::Pending {
             yaml.push_str(&format!("  status: {}\n", node.status));
         }
     }
@@ -0,0 +1,182 @@
+use eframe::egui::{self, Color32, RichText, Ui};
+use lao_orchestrator_core::run_diff::{self, DiffLine, RunDiff, RunSnapshot, StepChange};
+use lao_orchestrator_core::run_labels::RunQuery;
+
+#[derive(Default)]
+pub struct ComparePanelState {
+    pub runs: Vec<RunSnapshot>,
+    pub a: Option<String>,
+    pub b: Option<String>,
+    pub diff: Option<RunDiff>,
+    pub error: Option<String>,
+    /// Narrows the run lists to runs whose id, workflow, labels or tags contain it
+    pub search: String,
+    loaded: bool,
+}
+
+impl ComparePanelState {
+    fn reload(&mut self) {
+        self.runs = run_diff::list(&run_diff::default_dir());
+        self.loaded = true;
+        // Default to the two most recent runs, older one first
+        if self.a.is_none() && self.b.is_none() && self.runs.len() >= 2 {
+            self.a = Some(self.runs[1].run_id.clone());
+            self.b = Some(self.runs[0].run_id.clone());
+        }
+        self.compare();
+    }
+
+    fn compare(&mut self) {
+        self.diff = None;
+        self.error = None;
+        if let (Some(a), Some(b)) = (&self.a, &self.b) {
+            match run_diff::diff_runs(&run_diff::default_dir(), a, b) {
+                Ok(diff) => self.diff = Some(diff),
+                Err(e) => self.error = Some(e),
+            }
+        }
+    }
+}
+
+pub fn show(ui: &mut Ui, state: &mut ComparePanelState) {
+    egui::CollapsingHeader::new(RichText::new("🔀 Compare Runs").size(14.0))
+        .default_open(false)
+        .show(ui, |ui| {
+            if !state.loaded {
+                state.reload();
+            }
+            ui.horizontal(|ui| {
+                ui.label("🔎");
+                ui.add(
+                    egui::TextEdit::singleline(&mut state.search)
+                        .hint_text("workflow, project=thesis, #schedule…")
+                        .desired_width(220.0),
+                );
+            });
+            let query = RunQuery {
+                text: Some(state.search.trim().to_string()).filter(|s| !s.is_empty()),
+                ..Default::default()
+            };
+            let visible: Vec<&RunSnapshot> =
+                state.runs.iter().filter(|r| query.matches(r)).collect();
+            let (changed, refresh) = ui
+                .horizontal(|ui| {
+                    let mut changed =
+                        run_picker(ui, "compare_a", &visible, &state.runs, &mut state.a);
+                    ui.label("→");
+                    changed |= run_picker(ui, "compare_b", &visible, &state.runs, &mut state.b);
+                    (changed, ui.button("🔄 Refresh").clicked())
+                })
+                .inner;
+            if refresh {
+                state.reload();
+            } else if changed {
+                state.compare();
+            }
+
+            if let Some(error) = &state.error {
+                ui.colored_label(Color32::from_rgb(244, 67, 54), error);
+                return;
+            }
+            if state.runs.len() < 2 {
+                ui.colored_label(Color32::GRAY, "Run a workflow twice to compare runs.");
+                return;
+            }
+            let Some(diff) = &state.diff else {
+                return;
+            };
+
+            ui.label(format!(
+                "{} → {} · duration {:+} ms",
+                diff.a.status, diff.b.status, diff.duration_delta_ms
+            ));
+            ui.add_space(6.0);
+            for step in &diff.steps {
+                let (symbol, color) = match step.change {
+                    StepChange::Added => ("+", Color32::from_rgb(76, 175, 80)),
+                    StepChange::Removed => ("−", Color32::from_rgb(244, 67, 54)),
+                    StepChange::Changed => ("~", Color32::from_rgb(255, 152, 0)),
+                    StepChange::Unchanged => ("=", Color32::GRAY),
+                };
+                let status = match &step.status {
+                    (Some(a), Some(b)) if a != b => format!("{} → {}", a, b),
+                    (_, Some(status)) | (Some(status), None) => status.clone(),
+                    (None, None) => String::new(),
+                };
+                let header = RichText::new(format!(
+                    "{} {} ({}) · {} · {:+} ms",
+                    symbol, step.step_id, step.runner, status, step.duration_delta_ms
+                ))
+                .color(color);
+                if step.output.is_empty() && step.error.is_none() {
+                    ui.label(header);
+                    continue;
+                }
+                egui::CollapsingHeader::new(header)
+                    .id_salt(("compare_step", &step.step_id))
+                    .show(ui, |ui| {
+                        if let Some((error_a, error_b)) = &step.error {
+                            ui.label(format!(
+                                "error: {} → {}",
+                                error_a.as_deref().unwrap_or("none"),
+                                error_b.as_deref().unwrap_or("none")
+                            ));
+                        }
+                        for line in &step.output {
+                            let (prefix, text, color) = match line {
+                                DiffLine::Same(text) => (" ", text, Color32::GRAY),
+                                DiffLine::Added(text) => {
+                                    ("+", text, Color32::from_rgb(76, 175, 80))
+                                }
+                                DiffLine::Removed(text) => {
+                                    ("-", text, Color32::from_rgb(244, 67, 54))
+                                }
+                            };
+                            ui.label(
+                                RichText::new(format!("{}{}", prefix, text))
+                                    .monospace()
+                                    .color(color),
+                            );
+                        }
+                    });
+            }
+        });
+}
+
+// Combo box of the `visible` saved runs; true when the selection changed
+fn run_picker(
+    ui: &mut Ui,
+    id: &str,
+    visible: &[&RunSnapshot],
+    runs: &[RunSnapshot],
+    selected: &mut Option<String>,
+) -> bool {
+    let label = |run: &RunSnapshot| {
+        let mut label = format!(
+            "{} · {} · {}",
+            run.finished.format("%m-%d %H:%M"),
+            run.workflow,
+            run.status
+        );
+        if !run.labels.is_empty() {
+            label.push_str(&format!(" · {}", run.labels.render()));
+        }
+        label
+    };
+    let current = selected
+        .as_ref()
+        .and_then(|id| runs.iter().find(|r| &r.run_id == id))
+        .map(label)
+        .unwrap_or_else(|| "Select run".to_string());
+    let mut changed = false;
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(current)
+        .width(260.0)
+        .show_ui(ui, |ui| {
+            for run in visible {
+                let value = Some(run.run_id.clone());
+                changed |= ui.selectable_value(selected, value, label(run)).changed();
+            }
+        });
+    changed
+}
@@ -0,0 +1,48 @@
+// Checking an edge before it is drawn.
+use crate::backend::WorkflowGraph;
+use lao_orchestrator_core::{edge_problems, node::NodeId, plugins::PluginRegistry};
+use std::collections::{HashMap, HashSet};
+
+/// Why each node of `graph` can't take an edge from `from`; `None` when it can
+pub fn targets(
+    graph: &WorkflowGraph,
+    from: &NodeId,
+    registry: &PluginRegistry,
+) -> HashMap<NodeId, Option<String>> {
+    let Some(source) = graph.nodes.iter().find(|n| &n.id == from) else {
+        return HashMap::new();
+    };
+    graph
+        .nodes
+        .iter()
+        .filter(|node| &node.id != from)
+        .map(|node| {
+            let problem = if reaches(graph, &node.id, from) {
+                Some(format!(
+                    "'{}' already depends on '{}'; this edge would create a cycle",
+                    from, node.id
+                ))
+            } else {
+                edge_problems(registry, &source.run, &node.run)
+                    .into_iter()
+                    .next()
+            };
+            (node.id.clone(), problem)
+        })
+        .collect()
+}
+
+/// Whether a path of edges leads from `start` to `goal`
+fn reaches(graph: &WorkflowGraph, start: &NodeId, goal: &NodeId) -> bool {
+    let mut seen = HashSet::new();
+    let mut pending = vec![start];
+    while let Some(id) = pending.pop() {
+        if id == goal {
+            return true;
+        }
+        if seen.insert(id) {
+            pending.extend(graph.edges.iter().filter(|e| &e.from == id).map(|e| &e.to));
+        }
+    }
+    false
+}
@@ -0,0 +1,90 @@
+// Previewing the data that crossed an edge.
+use crate::backend::GraphNode;
+use eframe::egui::{self, Color32, Id, LayerId, RichText, Ui};
+use lao_orchestrator_core::run_diff::{self, RunSnapshot};
+
+const PREVIEW_CHARS: usize = 600;
+const PREVIEW_LINES: usize = 12;
+
+/// The latest run of the loaded workflow, read once per workflow
+#[derive(Default)]
+pub struct EdgeHistory {
+    workflow: Option<String>,
+    run: Option<RunSnapshot>,
+}
+
+impl EdgeHistory {
+    fn latest(&mut self, workflow: &str) -> Option<&RunSnapshot> {
+        if self.workflow.as_deref() != Some(workflow) {
+            self.workflow = Some(workflow.to_string());
+            self.run = run_diff::list(&run_diff::default_dir())
+                .into_iter()
+                .find(|run| run.workflow == workflow);
+        }
+        self.run.as_ref()
+    }
+
+    /// Read the history again on the next preview, for runs finished since
+    pub fn reload(&mut self) {
+        self.workflow = None;
+        self.run = None;
+    }
+}
+
+/// The first lines and characters of `text`, and how many characters are left out
+fn truncate(text: &str) -> (String, usize) {
+    let mut preview: String = text
+        .lines()
+        .take(PREVIEW_LINES)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Some((end, _)) = preview.char_indices().nth(PREVIEW_CHARS) {
+        preview.truncate(end);
+    }
+    let total = text.chars().count();
+    let shown = preview.chars().count();
+    (preview, total.saturating_sub(shown))
+}
+
+/// Tooltip at the pointer for the edge from `from` to `to`
+pub fn show(
+    ctx: &egui::Context,
+    layer: LayerId,
+    from: &GraphNode,
+    to: &GraphNode,
+    workflow: Option<&str>,
+    history: &mut EdgeHistory,
+) {
+    let id = Id::new(("edge_preview", from.id.as_str(), to.id.as_str()));
+    egui::show_tooltip_at_pointer(ctx, layer, id, |ui: &mut Ui| {
+        ui.label(RichText::new(format!("{} → {}", from.id, to.id)).strong());
+        let (data, source) = match (&from.output, &from.error) {
+            (Some(output), _) => (Some(output.clone()), "this session's run".to_string()),
+            (None, Some(error)) => {
+                ui.colored_label(Color32::RED, format!("{} failed: {}", from.id, error));
+                return;
+            }
+            (None, None) => {
+                let recorded = workflow.and_then(|w| history.latest(w)).and_then(|run| {
+                    let step = run.steps.iter().find(|s| s.step_id == from.id.as_str())?;
+                    let finished = run.finished.format("%Y-%m-%d %H:%M");
+                    Some((
+                        step.output.clone(),
+                        format!("run {} ({})", run.run_id, finished),
+                    ))
+                });
+                recorded.unwrap_or_default()
+            }
+        };
+        let Some(data) = data else {
+            ui.colored_label(Color32::GRAY, "No data has crossed this edge yet");
+            return;
+        };
+        ui.colored_label(Color32::GRAY, format!("From {}", source));
+        let (preview, left_out) = truncate(&data);
+        ui.label(RichText::new(preview).monospace());
+        if left_out > 0 {
+            ui.colored_label(Color32::GRAY, format!("… {} more characters", left_out));
+        }
+    });
+}
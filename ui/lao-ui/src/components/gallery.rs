@@ -0,0 +1,73 @@
+use crate::backend::{load_workflow, BackendState};
+use eframe::egui::{self, Color32, RichText, Ui};
+use lao_orchestrator_core::metadata::{self, GalleryEntry, WorkflowMetadata};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+pub struct GalleryPanelState {
+    pub entries: Option<Vec<GalleryEntry>>,
+}
+
+pub fn show(ui: &mut Ui, state: &mut GalleryPanelState, backend: &Arc<Mutex<BackendState>>) {
+    egui::CollapsingHeader::new(RichText::new("📚 Workflow Gallery").size(14.0))
+        .default_open(false)
+        .show(ui, |ui| {
+            if ui.button("🔄 Refresh").clicked() || state.entries.is_none() {
+                state.entries = Some(metadata::gallery(Path::new("workflows")));
+            }
+            let entries = state.entries.as_deref().unwrap_or_default();
+            if entries.is_empty() {
+                ui.colored_label(Color32::GRAY, "No workflows in workflows/.");
+            }
+            for entry in entries {
+                ui.horizontal(|ui| {
+                    if ui.button("📂 Open").clicked() {
+                        let mut backend = backend.lock().unwrap();
+                        backend.workflow_path = entry.path.to_string_lossy().to_string();
+                        load_workflow(&mut backend);
+                    }
+                    ui.label(RichText::new(&entry.title).strong());
+                    if let Some(author) = &entry.author {
+                        ui.label(RichText::new(format!("by {}", author)).color(Color32::GRAY));
+                    }
+                });
+                match (&entry.error, &entry.description) {
+                    (Some(error), _) => {
+                        ui.colored_label(Color32::from_rgb(244, 67, 54), error);
+                    }
+                    (None, Some(description)) => {
+                        ui.label(RichText::new(description).size(12.0));
+                    }
+                    (None, None) => {}
+                }
+                ui.add_space(4.0);
+            }
+        });
+}
+
+/// The loaded workflow's metadata, above the canvas
+pub fn header(ui: &mut Ui, metadata: &WorkflowMetadata) {
+    ui.horizontal(|ui| {
+        if let Some(title) = &metadata.title {
+            ui.label(RichText::new(title).size(16.0).strong());
+        }
+        if let Some(author) = &metadata.author {
+            ui.label(RichText::new(format!("by {}", author)).color(Color32::GRAY));
+        }
+    });
+    if let Some(description) = &metadata.description {
+        ui.label(description);
+    }
+    if !metadata.requires.is_empty() {
+        ui.label(
+            RichText::new(format!("Requires: {}", metadata.requires.join(", ")))
+                .size(12.0)
+                .color(Color32::GRAY),
+        );
+    }
+    for example in &metadata.examples {
+        ui.label(RichText::new(example).monospace().size(12.0));
+    }
+    ui.add_space(6.0);
+}
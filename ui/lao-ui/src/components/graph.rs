@@ -1,21 +1,56 @@
 use crate::backend::{
     export_workflow_yaml, save_workflow_yaml, GraphEdge, GraphNode, UiPluginInfo, WorkflowGraph,
 };
+use crate::components::connect_check;
+use crate::components::edge_preview::{self, EdgeHistory};
+use crate::components::graph_image::{self, ImageFormat, ImageOptions};
+use crate::components::node_finder::NodeFinder;
 use eframe::egui::{self, Color32, Id, Pos2, Rect, Stroke, Ui, Vec2};
+use lao_orchestrator_core::node::{NodeId, NodeStatus};
+use lao_orchestrator_core::plugins;
+use lao_orchestrator_core::presets::{self, Preset};
+
+/// Background of a node in `status`
+pub fn status_color(status: NodeStatus) -> Color32 {
+    match status {
+        NodeStatus::Running => Color32::from_rgb(33, 150, 243),
+        NodeStatus::Success => Color32::from_rgb(76, 175, 80),
+        NodeStatus::Error => Color32::from_rgb(244, 67, 54),
+        NodeStatus::Cache => Color32::from_rgb(156, 39, 176),
+        NodeStatus::Pending => Color32::from_rgb(96, 125, 139),
+        NodeStatus::Skipped => Color32::from_rgb(34, 34, 34),
+        NodeStatus::Cancelled => Color32::from_rgb(255, 152, 0),
+    }
+}
 
 pub struct GraphEditorState {
     pub pan_offset: Vec2,
-    pub connecting_from: Option<String>,
-    pub selected_node: Option<String>,
+    pub connecting_from: Option<NodeId>,
+    pub selected_node: Option<NodeId>,
 
     // Editor UI state
     pub new_node_name: String,
     pub new_node_type: String,
+    /// Preset the next node is added from, instead of a bare plugin
+    pub new_node_preset: Option<Preset>,
 
     // Dialog state
     pub show_save_dialog: bool,
     pub show_export_dialog: bool,
     pub new_workflow_filename: String,
+    pub show_image_dialog: bool,
+    pub image_options: ImageOptions,
+    pub image_path: String,
+    /// Where the last image went, or why it didn't
+    pub image_result: Option<Result<String, String>>,
+
+    /// Ctrl+F search, and the node to center once the canvas is laid out
+    pub finder: NodeFinder,
+    pub jump_to: Option<NodeId>,
+
+    /// Edge under the pointer, and the run history its preview comes from
+    pub hovered_edge: Option<(NodeId, NodeId)>,
+    pub edge_history: EdgeHistory,
 }
 
 impl Default for GraphEditorState {
@@ -26,9 +61,18 @@ impl Default for GraphEditorState {
             selected_node: None,
             new_node_name: String::new(),
             new_node_type: "EchoPlugin".to_string(), // Default safe value
+            new_node_preset: None,
             show_save_dialog: false,
             show_export_dialog: false,
             new_workflow_filename: "new_workflow.yaml".to_string(),
+            show_image_dialog: false,
+            image_options: ImageOptions::default(),
+            image_path: "workflow.png".to_string(),
+            image_result: None,
+            finder: NodeFinder::default(),
+            jump_to: None,
+            hovered_edge: None,
+            edge_history: EdgeHistory::default(),
         }
     }
 }
@@ -57,6 +101,11 @@ pub fn show(
                 state.show_export_dialog = true;
             }
 
+            if ui.add(egui::Button::new("🖼️ Export Image")).clicked() {
+                state.show_image_dialog = true;
+                state.image_result = None;
+            }
+
             ui.add_space(10.0);
 
             // Add delete all nodes button
@@ -75,7 +124,8 @@ pub fn show(
             if state.connecting_from.is_some() {
                 ui.colored_label(
                     Color32::from_rgb(255, 193, 7),
-                    egui::RichText::new("🔗 Connection mode: Click target node").size(12.0),
+                    egui::RichText::new("🔗 Connection mode: Click a node outlined in green")
+                        .size(12.0),
                 );
                 if ui.add(egui::Button::new("❌ Cancel")).clicked() {
                     state.connecting_from = None;
@@ -83,8 +133,10 @@ pub fn show(
             } else {
                 ui.colored_label(
                     Color32::GRAY,
-                    egui::RichText::new("💡 Tip: Right-click nodes for options, drag to move")
-                        .size(12.0),
+                    egui::RichText::new(
+                        "💡 Tip: Right-click nodes for options, drag to move, Ctrl+F to find",
+                    )
+                    .size(12.0),
                 );
             }
         });
@@ -153,30 +205,113 @@ pub fn show(
             }
         }
 
+        // Image export dialog
+        if state.show_image_dialog {
+            let mut close_dialog = false;
+            egui::Window::new("Export Image")
+                .open(&mut state.show_image_dialog)
+                .show(ui.ctx(), |ui| {
+                    let options = &mut state.image_options;
+                    ui.horizontal(|ui| {
+                        ui.label("Format:");
+                        for format in [ImageFormat::Png, ImageFormat::Svg] {
+                            let name = format.extension().to_uppercase();
+                            if ui.radio_value(&mut options.format, format, name).changed() {
+                                // Keep the file name's extension in step
+                                let path = std::path::Path::new(&state.image_path);
+                                state.image_path = path
+                                    .with_extension(format.extension())
+                                    .to_string_lossy()
+                                    .into_owned();
+                            }
+                        }
+                    });
+                    ui.checkbox(&mut options.auto_layout, "Auto-layout")
+                        .on_hover_text("Arrange nodes in columns; off keeps the canvas positions");
+                    ui.checkbox(&mut options.statuses, "Include statuses")
+                        .on_hover_text("Color nodes by the status of the last run");
+                    ui.horizontal(|ui| {
+                        ui.label("File:");
+                        ui.text_edit_singleline(&mut state.image_path);
+                    });
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Export").clicked() {
+                            state.image_result = Some(
+                                graph_image::export(ui.ctx(), graph, options, &state.image_path)
+                                    .map(|_| state.image_path.clone()),
+                            );
+                        }
+                        if ui.button("Close").clicked() {
+                            close_dialog = true;
+                        }
+                    });
+
+                    match &state.image_result {
+                        Some(Ok(path)) => {
+                            ui.colored_label(Color32::GREEN, format!("Saved {}", path));
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(Color32::RED, format!("Export error: {}", e));
+                        }
+                        None => {}
+                    }
+                });
+
+            if close_dialog {
+                state.show_image_dialog = false;
+            }
+        }
+
         // Add node controls
         ui.horizontal(|ui| {
             ui.label("Add Node:");
             ui.text_edit_singleline(&mut state.new_node_name);
 
+            let selected = match &state.new_node_preset {
+                Some(preset) => format!("⭐ {}", preset.name),
+                None => state.new_node_type.clone(),
+            };
             egui::ComboBox::from_id_salt("plugin_type_combo")
-                .selected_text(&state.new_node_type)
+                .selected_text(selected)
                 .show_ui(ui, |ui| {
                     for (i, plugin) in plugins.iter().enumerate() {
                         ui.push_id(format!("plugin_option_{}", i), |ui| {
-                            ui.selectable_value(
-                                &mut state.new_node_type,
-                                plugin.name.clone(),
-                                &plugin.name,
-                            );
+                            let chosen = state.new_node_preset.is_none()
+                                && state.new_node_type == plugin.name;
+                            if ui.selectable_label(chosen, &plugin.name).clicked() {
+                                state.new_node_type = plugin.name.clone();
+                                state.new_node_preset = None;
+                            }
                         });
                     }
+                    // Read while the list is open, so new presets show up
+                    let presets = presets::list(&presets::default_dir());
+                    if !presets.is_empty() {
+                        ui.separator();
+                    }
+                    for preset in presets {
+                        let chosen = state
+                            .new_node_preset
+                            .as_ref()
+                            .is_some_and(|p| p.name == preset.name);
+                        let label = ui.selectable_label(chosen, format!("⭐ {}", preset.name));
+                        let label = match &preset.description {
+                            Some(description) => label.on_hover_text(description),
+                            None => label,
+                        };
+                        if label.clicked() {
+                            state.new_node_type = preset.run().unwrap_or_default().to_string();
+                            state.new_node_preset = Some(preset);
+                        }
+                    }
                 });
 
             if ui.button("Add Node").clicked() {
                 let node_id = if state.new_node_name.is_empty() {
-                    format!("node_{}", graph.nodes.len() + 1)
+                    NodeId::from(format!("node_{}", graph.nodes.len() + 1))
                 } else {
-                    state.new_node_name.clone()
+                    NodeId::new(&state.new_node_name)
                 };
 
                 // Calculate better initial position
@@ -192,19 +327,37 @@ pub fn show(
                     run: state.new_node_type.clone(),
                     input_type: None,
                     output_type: None,
-                    status: "pending".to_string(),
+                    status: NodeStatus::Pending,
                     x: 50.0 + (col as f32 * spacing_x),
                     y: 50.0 + (row as f32 * spacing_y),
                     message: None,
                     output: None,
                     error: None,
+                    artifact: None,
                     attempt: 0,
+                    instances: None,
+                    disabled: false,
+                    breakpoint: false,
+                    display: Default::default(),
+                    params: serde_yaml::Value::Null,
+                    preset: state.new_node_preset.as_ref().map(|p| p.name.clone()),
+                    template: None,
+                    prompt_file: None,
+                    backend: None,
+                    retries: None,
+                    retry_at: None,
                 });
 
                 state.new_node_name.clear();
             }
         });
 
+        if let Some(node_id) = state.finder.show(ui, graph) {
+            state.jump_to = Some(node_id);
+        }
+        let found = state.finder.matches(graph);
+        let current = state.finder.current(&found);
+
         // Visual graph area
         let available_rect = ui.available_rect_before_wrap();
         // Reserve at least some height
@@ -215,6 +368,15 @@ pub fn show(
 
         let response = ui.allocate_rect(graph_rect, egui::Sense::click_and_drag());
 
+        // Center the node found with Ctrl+F
+        if let Some(node_id) = state.jump_to.take() {
+            if let Some(node) = graph.nodes.iter().find(|n| n.id == node_id) {
+                state.pan_offset =
+                    graph_rect.size() / 2.0 - egui::vec2(node.x + 60.0, node.y + 30.0);
+                state.selected_node = Some(node_id);
+            }
+        }
+
         if ui.is_rect_visible(graph_rect) {
             let painter = ui.painter();
 
@@ -252,6 +414,11 @@ pub fn show(
 
             // Draw edges
             let mut edge_to_delete: Option<usize> = None;
+            let pointer = response
+                .hovered()
+                .then(|| ui.input(|i| i.pointer.hover_pos()))
+                .flatten();
+            let mut hovered_edge = None;
             for (i, edge) in graph.edges.iter().enumerate() {
                 if let (Some(from_node), Some(to_node)) = (
                     graph.nodes.iter().find(|n| n.id == edge.from),
@@ -266,11 +433,21 @@ pub fn show(
                         graph_rect.min.y + state.pan_offset.y + to_node.y + 30.0,
                     );
 
+                    // An edge under the pointer is drawn darker and previewed
+                    let hovered = pointer.is_some_and(|p| {
+                        let segment = to_pos - from_pos;
+                        let t = ((p - from_pos).dot(segment) / segment.length_sq()).clamp(0.0, 1.0);
+                        (from_pos + segment * t).distance(p) < 6.0
+                    });
+                    let edge_stroke = if hovered {
+                        hovered_edge = Some((from_node, to_node));
+                        Stroke::new(3.0, Color32::from_gray(60))
+                    } else {
+                        Stroke::new(2.0, Color32::from_gray(136))
+                    };
+
                     // Draw arrow line
-                    painter.line_segment(
-                        [from_pos, to_pos],
-                        Stroke::new(2.0, Color32::from_gray(136)),
-                    );
+                    painter.line_segment([from_pos, to_pos], edge_stroke);
 
                     // Draw arrowhead
                     let direction = (to_pos - from_pos).normalized();
@@ -283,28 +460,35 @@ pub fn show(
                     let arrow_p2 =
                         arrow_tip - direction * arrow_size - perpendicular * arrow_size * 0.5;
 
-                    painter.line_segment(
-                        [arrow_tip, arrow_p1],
-                        Stroke::new(2.0, Color32::from_gray(136)),
-                    );
-                    painter.line_segment(
-                        [arrow_tip, arrow_p2],
-                        Stroke::new(2.0, Color32::from_gray(136)),
-                    );
+                    painter.line_segment([arrow_tip, arrow_p1], edge_stroke);
+                    painter.line_segment([arrow_tip, arrow_p2], edge_stroke);
 
                     // Check for edge click to delete
                     let edge_center = (from_pos + to_pos.to_vec2()) * 0.5;
                     let edge_rect = Rect::from_center_size(edge_center, Vec2::splat(20.0));
-                    let edge_response = ui.interact(
-                        edge_rect,
-                        Id::new(format!("edge_{}", i)),
-                        egui::Sense::click(),
-                    );
+                    let edge_response =
+                        ui.interact(edge_rect, Id::new(("edge", i)), egui::Sense::click());
                     if edge_response.secondary_clicked() {
                         edge_to_delete = Some(i);
                     }
                 }
             }
+            let hovered_ids = hovered_edge.map(|(from, to)| (from.id.clone(), to.id.clone()));
+            if hovered_ids != state.hovered_edge {
+                // Runs may have finished since the last preview
+                state.edge_history.reload();
+                state.hovered_edge = hovered_ids;
+            }
+            if let Some((from, to)) = hovered_edge {
+                edge_preview::show(
+                    ui.ctx(),
+                    ui.layer_id(),
+                    from,
+                    to,
+                    graph.workflow.as_deref(),
+                    &mut state.edge_history,
+                );
+            }
             if let Some(idx) = edge_to_delete {
                 if idx < graph.edges.len() {
                     graph.edges.remove(idx);
@@ -313,6 +497,14 @@ pub fn show(
 
             // Draw nodes
             let mut node_clicked = None;
+            // Valid targets are outlined green, invalid ones red with the reason
+            let targets = state
+                .connecting_from
+                .as_ref()
+                .map(|from| connect_check::targets(graph, from, &plugins::shared().snapshot()))
+                .unwrap_or_default();
+            let valid_target = Color32::from_rgb(76, 175, 80);
+            let invalid_target = Color32::from_rgb(244, 67, 54);
             for node in &mut graph.nodes {
                 let node_pos = Pos2::new(
                     graph_rect.min.x + state.pan_offset.x + node.x,
@@ -320,48 +512,110 @@ pub fn show(
                 );
                 let node_rect = Rect::from_min_size(node_pos, egui::vec2(120.0, 60.0));
 
-                // Node background color based on status
-                let node_color = match node.status.as_str() {
-                    "running" => Color32::from_rgb(33, 150, 243),
-                    "success" => Color32::from_rgb(76, 175, 80),
-                    "error" => Color32::from_rgb(244, 67, 54),
-                    "cache" => Color32::from_rgb(156, 39, 176),
-                    "pending" => Color32::from_rgb(96, 125, 139),
-                    _ => Color32::from_rgb(34, 34, 34),
+                let status_color = status_color(node.status);
+                // A node with its own color keeps it, and shows its status
+                // as a dot in the corner
+                let custom_color = node
+                    .display
+                    .rgb()
+                    .map(|[r, g, b]| Color32::from_rgb(r, g, b));
+                let node_color = custom_color.unwrap_or(status_color);
+                // Disabled nodes are grayed out whatever their status
+                let node_color = if node.disabled {
+                    node_color.gamma_multiply(0.35)
+                } else {
+                    node_color
+                };
+                let text_color = if node.disabled {
+                    Color32::from_gray(140)
+                } else {
+                    Color32::WHITE
                 };
 
                 painter.rect_filled(node_rect, 12.0, node_color);
 
                 // Highlight/Stroke
+                let search_hit = Color32::from_rgb(255, 152, 0);
                 if state.connecting_from.as_ref() == Some(&node.id) {
                     painter.rect_stroke(node_rect, 12.0, Stroke::new(3.0, Color32::YELLOW));
+                } else if let Some(problem) = targets.get(&node.id) {
+                    let color = if problem.is_some() {
+                        invalid_target
+                    } else {
+                        valid_target
+                    };
+                    painter.rect_stroke(node_rect, 12.0, Stroke::new(3.0, color));
+                } else if current.as_ref() == Some(&node.id) {
+                    painter.rect_stroke(node_rect, 12.0, Stroke::new(4.0, search_hit));
                 } else if state.selected_node.as_ref() == Some(&node.id) {
                     painter.rect_stroke(node_rect, 12.0, Stroke::new(2.0, Color32::WHITE));
+                } else if found.contains(&node.id) {
+                    painter.rect_stroke(node_rect, 12.0, Stroke::new(2.0, search_hit));
                 } else {
                     painter.rect_stroke(node_rect, 12.0, Stroke::new(2.0, Color32::from_gray(68)));
                 }
 
+                if custom_color.is_some() {
+                    let dot = node_rect.right_top() + egui::vec2(-10.0, 10.0);
+                    painter.circle_filled(dot, 5.0, status_color);
+                    painter.circle_stroke(dot, 5.0, Stroke::new(1.0, Color32::WHITE));
+                }
+
+                // Fan-out steps show their items: done of all while running
+                if let Some(instances) = node.instances {
+                    let count = if node.status == NodeStatus::Running {
+                        format!("{}/{}", instances.done, instances.total)
+                    } else {
+                        format!("×{}", instances.total)
+                    };
+                    let badge = Rect::from_center_size(
+                        node_rect.left_top() + egui::vec2(16.0, 0.0),
+                        egui::vec2(40.0, 16.0),
+                    );
+                    painter.rect_filled(badge, 8.0, Color32::from_gray(40));
+                    painter.text(
+                        badge.center(),
+                        egui::Align2::CENTER_CENTER,
+                        count,
+                        egui::FontId::proportional(10.0),
+                        Color32::WHITE,
+                    );
+                }
+
                 painter.text(
                     node_rect.center() - egui::vec2(0.0, 8.0),
                     egui::Align2::CENTER_CENTER,
-                    &node.id,
+                    node.display.title(node.id.as_str()),
                     egui::FontId::default(),
-                    Color32::WHITE,
+                    text_color,
                 );
 
+                let label = if node.disabled {
+                    format!("{} (disabled)", node.run)
+                } else if node.breakpoint {
+                    format!("⏸ {} ({})", node.run, node.status)
+                } else {
+                    format!("{} ({})", node.run, node.status)
+                };
                 painter.text(
                     node_rect.center() + egui::vec2(0.0, 8.0),
                     egui::Align2::CENTER_CENTER,
-                    format!("{} ({})", node.run, node.status),
+                    label,
                     egui::FontId::proportional(10.0),
-                    Color32::from_gray(221),
+                    text_color.gamma_multiply(0.87),
                 );
 
-                let node_response =
+                let mut node_response =
                     ui.interact(node_rect, Id::new(&node.id), egui::Sense::click_and_drag());
+                let problem = targets.get(&node.id).cloned().flatten();
+                if let Some(problem) = &problem {
+                    node_response = node_response.on_hover_text(problem);
+                }
 
                 if node_response.clicked() || node_response.secondary_clicked() {
-                    if let Some(ref from_id) = state.connecting_from {
+                    if problem.is_some() {
+                        // Stay in connection mode so another target can be picked
+                    } else if let Some(ref from_id) = state.connecting_from {
                         if from_id != &node.id {
                             let edge = GraphEdge {
                                 from: from_id.clone(),
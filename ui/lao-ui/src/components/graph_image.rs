@@ -0,0 +1,459 @@
+// Exporting the graph as an image for documentation and sharing.
+use crate::backend::{GraphNode, WorkflowGraph};
+use crate::components::graph::status_color;
+use eframe::egui::{self, Color32, Pos2, Rect, Vec2};
+use lao_orchestrator_core::node::{NodeId, NodeStatus};
+use std::collections::HashMap;
+
+const NODE_SIZE: Vec2 = Vec2::new(120.0, 60.0);
+const COLUMN_WIDTH: f32 = 180.0;
+const ROW_HEIGHT: f32 = 90.0;
+const MARGIN: f32 = 30.0;
+const BACKGROUND: Color32 = Color32::from_gray(248);
+const EDGE: Color32 = Color32::from_gray(136);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageOptions {
+    pub format: ImageFormat,
+    pub auto_layout: bool,
+    pub statuses: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::Png,
+            auto_layout: true,
+            statuses: false,
+        }
+    }
+}
+
+/// A node as drawn, in points from the image's top left
+struct NodeBox {
+    rect: Rect,
+    fill: Color32,
+    text: Color32,
+    title: String,
+    subtitle: String,
+    /// Status dot of a node drawn in its own color
+    dot: Option<Color32>,
+}
+
+struct Scene {
+    size: Vec2,
+    nodes: Vec<NodeBox>,
+    /// Start, end and the two ends of the arrowhead
+    edges: Vec<[Pos2; 4]>,
+}
+
+/// Render `graph` and write it to `path`
+pub fn export(
+    ctx: &egui::Context,
+    graph: &WorkflowGraph,
+    options: &ImageOptions,
+    path: &str,
+) -> Result<(), String> {
+    if graph.nodes.is_empty() {
+        return Err("The graph has no nodes to export".to_string());
+    }
+    let scene = scene(graph, options);
+    match options.format {
+        ImageFormat::Svg => std::fs::write(path, svg(&scene)).map_err(|e| e.to_string()),
+        ImageFormat::Png => png(ctx, &scene)
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Top left corner of each node before the margin is added
+fn positions(graph: &WorkflowGraph, auto_layout: bool) -> HashMap<NodeId, Pos2> {
+    if !auto_layout {
+        let min_x = graph
+            .nodes
+            .iter()
+            .map(|n| n.x)
+            .fold(f32::INFINITY, f32::min);
+        let min_y = graph
+            .nodes
+            .iter()
+            .map(|n| n.y)
+            .fold(f32::INFINITY, f32::min);
+        return graph
+            .nodes
+            .iter()
+            .map(|n| (n.id.clone(), Pos2::new(n.x - min_x, n.y - min_y)))
+            .collect();
+    }
+    // Longest path from a node without parents; a cycle stops growing once
+    // every node has had its turn
+    let mut depth: HashMap<&NodeId, usize> = graph.nodes.iter().map(|n| (&n.id, 0)).collect();
+    for _ in 0..graph.nodes.len() {
+        let mut changed = false;
+        for edge in &graph.edges {
+            let (Some(&from), Some(&to)) = (depth.get(&edge.from), depth.get(&edge.to)) else {
+                continue;
+            };
+            if to <= from && from + 1 < graph.nodes.len() {
+                depth.insert(&edge.to, from + 1);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut rows: HashMap<usize, usize> = HashMap::new();
+    graph
+        .nodes
+        .iter()
+        .map(|n| {
+            let column = depth[&n.id];
+            let row = rows.entry(column).or_default();
+            let pos = Pos2::new(column as f32 * COLUMN_WIDTH, *row as f32 * ROW_HEIGHT);
+            *row += 1;
+            (n.id.clone(), pos)
+        })
+        .collect()
+}
+
+fn node_box(node: &GraphNode, min: Pos2, statuses: bool) -> NodeBox {
+    let status = if statuses {
+        node.status
+    } else {
+        NodeStatus::Pending
+    };
+    let custom = node
+        .display
+        .rgb()
+        .map(|[r, g, b]| Color32::from_rgb(r, g, b));
+    let fill = custom.unwrap_or_else(|| status_color(status));
+    let subtitle = if node.disabled {
+        format!("{} (disabled)", node.run)
+    } else if statuses {
+        format!("{} ({})", node.run, node.status)
+    } else {
+        node.run.clone()
+    };
+    NodeBox {
+        rect: Rect::from_min_size(min, NODE_SIZE),
+        fill: if node.disabled {
+            fill.gamma_multiply(0.35)
+        } else {
+            fill
+        },
+        text: if node.disabled {
+            Color32::from_gray(140)
+        } else {
+            Color32::WHITE
+        },
+        title: node.display.title(node.id.as_str()),
+        subtitle,
+        dot: custom.filter(|_| statuses).map(|_| status_color(status)),
+    }
+}
+
+fn scene(graph: &WorkflowGraph, options: &ImageOptions) -> Scene {
+    let positions = positions(graph, options.auto_layout);
+    let offset = Vec2::splat(MARGIN);
+    let nodes: Vec<NodeBox> = graph
+        .nodes
+        .iter()
+        .map(|n| node_box(n, positions[&n.id] + offset, options.statuses))
+        .collect();
+    let edges = graph
+        .edges
+        .iter()
+        .filter_map(|edge| {
+            let from = *positions.get(&edge.from)? + offset;
+            let to = *positions.get(&edge.to)? + offset;
+            let start = from + Vec2::new(NODE_SIZE.x, NODE_SIZE.y / 2.0);
+            let end = to + Vec2::new(0.0, NODE_SIZE.y / 2.0);
+            let direction = (end - start).normalized();
+            let perpendicular = Vec2::new(-direction.y, direction.x);
+            let tip = end - direction * 5.0;
+            let back = tip - direction * 8.0;
+            Some([
+                start,
+                tip,
+                back + perpendicular * 4.0,
+                back - perpendicular * 4.0,
+            ])
+        })
+        .collect();
+    let max = nodes.iter().fold(Pos2::ZERO, |max, n| max.max(n.rect.max));
+    Scene {
+        size: max.to_vec2() + offset,
+        nodes,
+        edges,
+    }
+}
+
+/// `attribute` and its opacity set to `color`
+fn svg_paint(attribute: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = format!("{}=\"#{:02x}{:02x}{:02x}\"", attribute, r, g, b);
+    if a < 255 {
+        paint.push_str(&format!(
+            " {}-opacity=\"{:.2}\"",
+            attribute,
+            a as f32 / 255.0
+        ));
+    }
+    paint
+}
+
+fn svg_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn svg(scene: &Scene) -> String {
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\">\n",
+        w = scene.size.x.ceil(),
+        h = scene.size.y.ceil()
+    );
+    out.push_str(&format!(
+        "  <rect width=\"100%\" height=\"100%\" {}/>\n",
+        svg_paint("fill", BACKGROUND)
+    ));
+    let edge = svg_paint("stroke", EDGE);
+    for [start, tip, left, right] in &scene.edges {
+        out.push_str(&format!(
+            "  <path d=\"M{} {} L{} {} M{} {} L{} {} L{} {}\" {} stroke-width=\"2\" fill=\"none\"/>\n",
+            start.x, start.y, tip.x, tip.y, left.x, left.y, tip.x, tip.y, right.x, right.y, edge
+        ));
+    }
+    for node in &scene.nodes {
+        let rect = node.rect;
+        let center = rect.center();
+        out.push_str(&format!(
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"12\" {} stroke=\"#444444\" stroke-width=\"2\"/>\n",
+            rect.min.x,
+            rect.min.y,
+            rect.width(),
+            rect.height(),
+            svg_paint("fill", node.fill)
+        ));
+        if let Some(dot) = node.dot {
+            out.push_str(&format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"5\" {} stroke=\"#ffffff\"/>\n",
+                rect.max.x - 10.0,
+                rect.min.y + 10.0,
+                svg_paint("fill", dot)
+            ));
+        }
+        let text = svg_paint("fill", node.text);
+        let subtitle = svg_paint("fill", node.text.gamma_multiply(0.87));
+        out.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\" font-size=\"14\" {}>{}</text>\n",
+            center.x,
+            center.y - 8.0,
+            text,
+            svg_escape(&node.title)
+        ));
+        out.push_str(&format!(
+            "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\" font-size=\"10\" {}>{}</text>\n",
+            center.x,
+            center.y + 8.0,
+            subtitle,
+            svg_escape(&node.subtitle)
+        ));
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Pixels drawn at `scale` pixels per point
+struct Canvas {
+    image: image::RgbaImage,
+    scale: f32,
+}
+
+impl Canvas {
+    /// Blend `color` into the pixel at `x`, `y` by `coverage`
+    fn blend(&mut self, x: u32, y: u32, color: Color32, coverage: f32) {
+        if x >= self.image.width() || y >= self.image.height() || coverage <= 0.0 {
+            return;
+        }
+        // Color32 is premultiplied
+        let coverage = coverage.min(1.0);
+        let alpha = coverage * color.a() as f32 / 255.0;
+        let pixel = self.image.get_pixel_mut(x, y);
+        for (channel, value) in pixel.0.iter_mut().zip([color.r(), color.g(), color.b()]) {
+            *channel = (*channel as f32 * (1.0 - alpha) + value as f32 * coverage).round() as u8;
+        }
+    }
+
+    /// Paint every pixel of `bounds` (in points) by the coverage that
+    /// `distance` gives its center, as a signed distance in pixels
+    fn shade(&mut self, bounds: Rect, color: Color32, distance: impl Fn(Pos2) -> f32) {
+        let bounds = bounds.expand(2.0);
+        let (x0, y0) = (
+            (bounds.min.x * self.scale).max(0.0) as u32,
+            (bounds.min.y * self.scale).max(0.0) as u32,
+        );
+        let (x1, y1) = (
+            (bounds.max.x * self.scale).ceil() as u32,
+            (bounds.max.y * self.scale).ceil() as u32,
+        );
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+                self.blend(x, y, color, 0.5 - distance(center));
+            }
+        }
+    }
+
+    fn rounded_rect(&mut self, rect: Rect, radius: f32, fill: Color32, stroke: (f32, Color32)) {
+        let scale = self.scale;
+        let inner = rect.shrink(radius);
+        let distance = move |p: Pos2| {
+            let p = Pos2::new(p.x / scale, p.y / scale);
+            let outside = Vec2::new(
+                (inner.min.x - p.x).max(p.x - inner.max.x),
+                (inner.min.y - p.y).max(p.y - inner.max.y),
+            );
+            let d = outside.max(Vec2::ZERO).length() + outside.x.max(outside.y).min(0.0) - radius;
+            d * scale
+        };
+        self.shade(rect, fill, distance);
+        let half_width = stroke.0 * scale / 2.0;
+        self.shade(rect, stroke.1, move |p| distance(p).abs() - half_width);
+    }
+
+    fn circle(&mut self, center: Pos2, radius: f32, fill: Color32) {
+        let scale = self.scale;
+        let bounds = Rect::from_center_size(center, Vec2::splat(radius * 2.0));
+        self.shade(bounds, fill, move |p| {
+            (p.to_vec2() / scale - center.to_vec2()).length() * scale - radius * scale
+        });
+    }
+
+    fn line(&mut self, from: Pos2, to: Pos2, width: f32, color: Color32) {
+        let scale = self.scale;
+        let bounds = Rect::from_two_pos(from, to);
+        self.shade(bounds, color, move |p| {
+            let p = p.to_vec2() / scale;
+            let segment = to - from;
+            let t = ((p - from.to_vec2()).dot(segment) / segment.length_sq()).clamp(0.0, 1.0);
+            let nearest = from.to_vec2() + segment * t;
+            ((p - nearest).length() - width / 2.0) * scale
+        });
+    }
+
+    /// Draw `galley` centered on `center`, with glyphs sampled from `atlas`
+    fn text(
+        &mut self,
+        galley: &egui::Galley,
+        center: Pos2,
+        atlas: &egui::FontImage,
+        color: Color32,
+    ) {
+        let origin = center - galley.size() / 2.0;
+        for row in &galley.rows {
+            for glyph in &row.glyphs {
+                let uv = glyph.uv_rect;
+                if uv.is_nothing() {
+                    continue;
+                }
+                let min = (origin + glyph.pos.to_vec2() + uv.offset) * self.scale;
+                let size = uv.size * self.scale;
+                let texels = Vec2::new(
+                    (uv.max[0] - uv.min[0]) as f32,
+                    (uv.max[1] - uv.min[1]) as f32,
+                );
+                for y in 0..size.y.ceil() as u32 {
+                    for x in 0..size.x.ceil() as u32 {
+                        let u = uv.min[0] as usize + (x as f32 / size.x * texels.x) as usize;
+                        let v = uv.min[1] as usize + (y as f32 / size.y * texels.y) as usize;
+                        let coverage =
+                            atlas.pixels[(v * atlas.width() + u).min(atlas.pixels.len() - 1)];
+                        self.blend(
+                            (min.x + x as f32).round().max(0.0) as u32,
+                            (min.y + y as f32).round().max(0.0) as u32,
+                            color,
+                            coverage.powf(0.55),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn png(ctx: &egui::Context, scene: &Scene) -> image::RgbaImage {
+    let scale = ctx.pixels_per_point();
+    let size = scene.size * scale;
+    let mut canvas = Canvas {
+        image: image::RgbaImage::from_pixel(
+            size.x.ceil() as u32,
+            size.y.ceil() as u32,
+            image::Rgba([BACKGROUND.r(), BACKGROUND.g(), BACKGROUND.b(), 255]),
+        ),
+        scale,
+    };
+    for &[start, tip, left, right] in &scene.edges {
+        canvas.line(start, tip, 2.0, EDGE);
+        canvas.line(left, tip, 2.0, EDGE);
+        canvas.line(right, tip, 2.0, EDGE);
+    }
+    // Lay out all text first: the atlas only holds the glyphs once they're
+    // laid out
+    let galleys: Vec<_> = ctx.fonts(|fonts| {
+        scene
+            .nodes
+            .iter()
+            .map(|node| {
+                (
+                    fonts.layout_no_wrap(
+                        node.title.clone(),
+                        egui::FontId::proportional(14.0),
+                        node.text,
+                    ),
+                    fonts.layout_no_wrap(
+                        node.subtitle.clone(),
+                        egui::FontId::proportional(10.0),
+                        node.text,
+                    ),
+                )
+            })
+            .collect()
+    });
+    let atlas = ctx.fonts(|fonts| fonts.image());
+    for (node, (title, subtitle)) in scene.nodes.iter().zip(&galleys) {
+        canvas.rounded_rect(node.rect, 12.0, node.fill, (2.0, Color32::from_gray(68)));
+        if let Some(dot) = node.dot {
+            let center = node.rect.right_top() + Vec2::new(-10.0, 10.0);
+            canvas.circle(center, 6.0, Color32::WHITE);
+            canvas.circle(center, 5.0, dot);
+        }
+        let center = node.rect.center();
+        canvas.text(title, center - Vec2::new(0.0, 8.0), &atlas, node.text);
+        canvas.text(
+            subtitle,
+            center + Vec2::new(0.0, 8.0),
+            &atlas,
+            node.text.gamma_multiply(0.87),
+        );
+    }
+    canvas.image
+}
@@ -1,5 +1,7 @@
-use crate::backend::{GraphEdge, GraphNode, UiPluginInfo};
+use crate::backend::{GraphEdge, GraphNode, PausedRun, UiPluginInfo};
 use eframe::egui::{self, Color32, Ui};
+use lao_orchestrator_core::node::{NodeId, NodeStatus};
+use lao_orchestrator_core::{display, presets, prompts, retry};
 use std::collections::HashMap;
 
 pub enum InspectorAction {
@@ -12,8 +14,9 @@ pub fn show(
     node: &mut GraphNode,
     plugins: &[UiPluginInfo],
     edges: &mut Vec<GraphEdge>,
-    pipe_source_for_node: &mut HashMap<String, String>,
-    connecting_from: &mut Option<String>,
+    pipe_source_for_node: &mut HashMap<NodeId, NodeId>,
+    connecting_from: &mut Option<NodeId>,
+    paused: &mut Option<PausedRun>,
 ) -> InspectorAction {
     let mut action = InspectorAction::None;
 
@@ -22,7 +25,7 @@ pub fn show(
 
     ui.horizontal(|ui| {
         ui.label("ID:");
-        ui.label(&node.id);
+        ui.label(node.id.as_str());
     });
 
     ui.horizontal(|ui| {
@@ -38,18 +41,144 @@ pub fn show(
             });
     });
 
+    ui.horizontal(|ui| {
+        let mut enabled = !node.disabled;
+        ui.checkbox(&mut enabled, "Enabled")
+            .on_hover_text("Disabled steps are skipped by runs until turned back on");
+        node.disabled = !enabled;
+    });
+
+    ui.checkbox(&mut node.breakpoint, "Pause before this step")
+        .on_hover_text("Runs started here wait before the step so its input can be edited");
+
+    ui.collapsing("Appearance", |ui| {
+        optional_text(ui, "Label:", &mut node.display.label);
+        optional_text(ui, "Icon:", &mut node.display.icon);
+        ui.horizontal(|ui| {
+            ui.label("Color:");
+            egui::ComboBox::from_id_salt("node_color_combo")
+                .selected_text(node.display.color.as_deref().unwrap_or("status"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut node.display.color, None, "status");
+                    for (role, _) in display::ROLES {
+                        ui.selectable_value(&mut node.display.color, Some(role.to_string()), *role);
+                    }
+                });
+            let mut rgb = node.display.rgb().unwrap_or([96, 125, 139]);
+            if ui.color_edit_button_srgb(&mut rgb).changed() {
+                node.display.color = Some(display::hex(rgb));
+            }
+        });
+    });
+
+    ui.collapsing("Template & preset", |ui| {
+        match &node.preset {
+            Some(preset) => {
+                ui.label(format!("Filled from preset ⭐ {}", preset));
+            }
+            None => {
+                ui.label("Template ({input} stands for the input):");
+                let mut template = node.template.clone().unwrap_or_default();
+                if ui
+                    .add(egui::TextEdit::multiline(&mut template).desired_rows(3))
+                    .changed()
+                {
+                    node.template = (!template.trim().is_empty()).then_some(template);
+                }
+            }
+        }
+        optional_text(ui, "Prompt file:", &mut node.prompt_file);
+        optional_text(ui, "Backend:", &mut node.backend);
+        if let Some(prompt_file) = &node.prompt_file {
+            match prompts::resolve(prompt_file) {
+                Ok(text) => ui.label(egui::RichText::new(text).monospace()),
+                Err(e) => ui.colored_label(Color32::RED, e),
+            };
+        }
+        let name_id = egui::Id::new(("preset_name", node.id.as_str()));
+        let result_id = egui::Id::new(("preset_saved", node.id.as_str()));
+        let mut name: String = ui.data_mut(|d| d.get_temp(name_id).unwrap_or_default());
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut name);
+            if ui.button("Save as preset").clicked() {
+                let result = save_preset(node, &name);
+                ui.data_mut(|d| d.insert_temp(result_id, result));
+            }
+        });
+        ui.data_mut(|d| d.insert_temp(name_id, name));
+        match ui.data(|d| d.get_temp::<Result<String, String>>(result_id)) {
+            Some(Ok(path)) => {
+                ui.colored_label(Color32::GREEN, format!("Saved {}", path));
+            }
+            Some(Err(e)) => {
+                ui.colored_label(Color32::RED, e);
+            }
+            None => {}
+        }
+    });
+
+    if paused.as_ref().is_some_and(|p| p.step_id == node.id) {
+        ui.separator();
+        ui.colored_label(Color32::YELLOW, "⏸ Paused: edit the input, then resume");
+        if let Some(run) = paused.as_mut() {
+            egui::ScrollArea::vertical()
+                .id_salt("paused_input")
+                .max_height(160.0)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut run.input)
+                            .desired_width(f32::INFINITY)
+                            .code_editor(),
+                    );
+                });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("▶ Resume").clicked() {
+                if let Some(run) = paused.take() {
+                    run.resume();
+                }
+            }
+            if ui.button("⏹ Abort run").clicked() {
+                if let Some(run) = paused.take() {
+                    run.abort();
+                }
+            }
+        });
+        ui.separator();
+    }
+
     ui.horizontal(|ui| {
         ui.label("Status:");
-        let status_color = match node.status.as_str() {
-            "running" => Color32::BLUE,
-            "success" => Color32::GREEN,
-            "error" => Color32::RED,
-            "cache" => Color32::BROWN,
-            _ => Color32::GRAY,
+        let status_color = match node.status {
+            NodeStatus::Running => Color32::BLUE,
+            NodeStatus::Success => Color32::GREEN,
+            NodeStatus::Error => Color32::RED,
+            NodeStatus::Cache => Color32::BROWN,
+            NodeStatus::Cancelled => Color32::from_rgb(255, 152, 0),
+            NodeStatus::Pending | NodeStatus::Skipped => Color32::GRAY,
         };
-        ui.colored_label(status_color, &node.status);
+        ui.colored_label(status_color, node.status.as_str());
     });
 
+    if node.attempt > 0 {
+        ui.horizontal(|ui| {
+            ui.label("Attempt:");
+            let attempts = node.retries.unwrap_or(retry::DEFAULT_RETRIES) + 1;
+            ui.label(format!("{} of {}", node.attempt, attempts));
+            // Counts down while the run waits to retry the failed attempt
+            let left = node
+                .retry_at
+                .map(|at| at.saturating_duration_since(std::time::Instant::now()))
+                .filter(|left| node.status == NodeStatus::Error && !left.is_zero());
+            if let Some(left) = left {
+                ui.colored_label(
+                    Color32::from_rgb(255, 152, 0),
+                    format!("⟳ retrying in {:.1}s", left.as_secs_f32()),
+                );
+            }
+        });
+    }
+
     if let Some(ref msg) = node.message {
         ui.horizontal(|ui| {
             ui.label("Message:");
@@ -58,15 +187,48 @@ pub fn show(
     }
 
     if let Some(ref output) = node.output {
+        // A running step's output is what it has streamed so far
+        let streaming = node.status == NodeStatus::Running;
         ui.collapsing("node_output", |ui| {
+            if streaming {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("streaming…");
+                });
+            }
             egui::ScrollArea::vertical()
                 .max_height(100.0)
+                .stick_to_bottom(streaming)
                 .show(ui, |ui| {
                     ui.text_edit_multiline(&mut output.clone());
                 });
         });
     }
 
+    if let Some(ref artifact) = node.artifact {
+        ui.collapsing("node_artifact", |ui| {
+            ui.label(format!(
+                "{} · {} · {:.1} KB",
+                artifact.mime,
+                &artifact.sha256[..artifact.sha256.len().min(12)],
+                artifact.size as f64 / 1024.0
+            ));
+            if artifact.mime.starts_with("image/") {
+                ui.add(
+                    egui::Image::new(format!("file://{}", artifact.path))
+                        .max_height(160.0)
+                        .maintain_aspect_ratio(true),
+                );
+            } else {
+                ui.label(&artifact.path);
+            }
+            if ui.button("📂 Open").clicked() {
+                ui.ctx()
+                    .open_url(egui::OpenUrl::new_tab(format!("file://{}", artifact.path)));
+            }
+        });
+    }
+
     if let Some(ref error) = node.error {
         ui.collapsing("node_error", |ui| {
             ui.colored_label(Color32::RED, error);
@@ -91,7 +253,7 @@ pub fn show(
     ui.separator();
     ui.heading("Piping");
     // Let user pick which predecessor provides input (input_from)
-    let incoming: Vec<String> = edges
+    let incoming: Vec<NodeId> = edges
         .iter()
         .filter(|e| e.to == node.id)
         .map(|e| e.from.clone())
@@ -102,10 +264,10 @@ pub fn show(
             .cloned()
             .unwrap_or_else(|| incoming[0].clone());
         egui::ComboBox::from_id_salt("node_pipe_from")
-            .selected_text(&chosen)
+            .selected_text(chosen.as_str())
             .show_ui(ui, |ui| {
                 for pred in &incoming {
-                    ui.selectable_value(&mut chosen, pred.clone(), pred);
+                    ui.selectable_value(&mut chosen, pred.clone(), pred.as_str());
                 }
             });
         // Apply choice by reordering edges so chosen is first among incoming
@@ -132,3 +294,47 @@ pub fn show(
 
     action
 }
+
+/// A text field where an empty value means unset
+fn optional_text(ui: &mut Ui, label: &str, value: &mut Option<String>) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut text = value.clone().unwrap_or_default();
+        if ui.text_edit_singleline(&mut text).changed() {
+            *value = (!text.trim().is_empty()).then_some(text);
+        }
+    });
+}
+
+/// Save the node's plugin, template, prompt file and params (but not its
+/// input) as a preset
+fn save_preset(node: &GraphNode, name: &str) -> Result<String, String> {
+    let dir = presets::default_dir();
+    let mut fields = serde_yaml::Mapping::new();
+    fields.insert("run".into(), node.run.as_str().into());
+    let template = match &node.preset {
+        Some(preset) => presets::load(&dir, preset)?.template().map(str::to_string),
+        None => node.template.clone(),
+    };
+    if let Some(template) = template {
+        fields.insert("template".into(), template.into());
+    }
+    if let Some(prompt_file) = &node.prompt_file {
+        fields.insert("prompt_file".into(), prompt_file.as_str().into());
+    }
+    if let Some(params) = node.params.as_mapping() {
+        for (key, value) in params {
+            // The prompt param is read from the prompt file when there is one
+            let from_file = node.prompt_file.is_some() && key.as_str() == Some("prompt");
+            if key.as_str() != Some("input") && !from_file {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    let preset = presets::Preset {
+        name: name.trim().to_string(),
+        description: None,
+        fields,
+    };
+    presets::save(&dir, &preset).map(|path| path.display().to_string())
+}
@@ -1,12 +1,18 @@
-use crate::backend::WorkflowResult;
+use crate::backend::{WorkflowGraph, WorkflowResult};
 use eframe::egui::{self, Color32, RichText, Ui};
+use lao_orchestrator_core::logging::{LogEvent, LogLevel};
+use lao_orchestrator_core::node::NodeStatus;
+
+// Characters of a step's streamed output shown on its live line
+const STREAM_TAIL_CHARS: usize = 120;
 
 pub fn show(
     ui: &mut Ui,
-    logs: &mut Vec<String>,
+    logs: &mut Vec<LogEvent>,
     is_running: bool,
     execution_progress: f32,
     workflow_result: &Option<WorkflowResult>,
+    graph: Option<&WorkflowGraph>,
 ) {
     ui.group(|ui| {
         ui.heading("📊 Live Logs & Execution Status");
@@ -57,25 +63,30 @@ pub fn show(
             .auto_shrink([false, true])
             .stick_to_bottom(true)
             .show(ui, |ui| {
-                for log in logs.iter() {
-                    // Color code based on log content with better colors
-                    let (color, icon) = if log.contains("✓ DONE") {
-                        (Color32::from_rgb(76, 175, 80), "✅")
-                    } else if log.contains("✗ ERROR") {
-                        (Color32::from_rgb(244, 67, 54), "❌")
-                    } else if log.contains("running") {
-                        (Color32::from_rgb(33, 150, 243), "🔄")
-                    } else if log.contains("success") || log.contains("cache") {
-                        (Color32::from_rgb(76, 175, 80), "✅")
-                    } else if log.contains("error") || log.contains("failed") {
-                        (Color32::from_rgb(244, 67, 54), "❌")
-                    } else {
-                        (Color32::WHITE, "ℹ️")
-                    };
-
+                for event in logs.iter() {
+                    let (color, icon) = style_for(event);
                     ui.horizontal(|ui| {
                         ui.label(icon);
-                        ui.colored_label(color, log);
+                        ui.colored_label(color, event_line(event));
+                    });
+                }
+
+                // Running steps that stream their output get a line that follows it
+                let streaming =
+                    graph
+                        .into_iter()
+                        .flat_map(|graph| &graph.nodes)
+                        .filter_map(|node| match (node.status, &node.output) {
+                            (NodeStatus::Running, Some(output)) => Some((node, output)),
+                            _ => None,
+                        });
+                for (node, output) in streaming {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.colored_label(
+                            Color32::from_rgb(33, 150, 243),
+                            format!("[{}] {} ({})", node.id, stream_tail(output), node.run),
+                        );
                     });
                 }
 
@@ -93,3 +104,52 @@ pub fn show(
             });
     });
 }
+
+// Warnings and errors are coloured by level; info lines by their step status
+fn style_for(event: &LogEvent) -> (Color32, &'static str) {
+    match event.level {
+        LogLevel::Error => return (Color32::from_rgb(244, 67, 54), "❌"),
+        LogLevel::Warn => return (Color32::from_rgb(255, 152, 0), "⚠️"),
+        LogLevel::Debug | LogLevel::Trace => return (Color32::GRAY, "🔍"),
+        LogLevel::Info => {}
+    }
+    match event.field_str("status") {
+        Some("running") => (Color32::from_rgb(33, 150, 243), "🔄"),
+        Some("success") | Some("cache") | Some("done") => (Color32::from_rgb(76, 175, 80), "✅"),
+        Some("skipped") => (Color32::GRAY, "⏭️"),
+        _ => (Color32::WHITE, "ℹ️"),
+    }
+}
+
+// The end of `output` on one line, "…" marking what was cut
+fn stream_tail(output: &str) -> String {
+    let line = output.split_whitespace().collect::<Vec<_>>().join(" ");
+    let count = line.chars().count();
+    if count <= STREAM_TAIL_CHARS {
+        return line;
+    }
+    let tail: String = line.chars().skip(count - STREAM_TAIL_CHARS).collect();
+    format!("…{}", tail)
+}
+
+fn event_line(event: &LogEvent) -> String {
+    let mut line = format!("{} ", event.timestamp.format("%H:%M:%S"));
+    if let Some(step) = &event.step_id {
+        line.push_str(&format!("[{}] ", step));
+    }
+    line.push_str(&event.message);
+    if let Some(runner) = event.field_str("runner") {
+        line.push_str(&format!(" ({})", runner));
+    }
+    if let Some(attempt) = event.fields.get("attempt").and_then(|v| v.as_u64()) {
+        if attempt > 1 {
+            line.push_str(&format!(" attempt {}", attempt));
+        }
+    }
+    for key in ["detail", "error"] {
+        if let Some(text) = event.field_str(key) {
+            line.push_str(&format!(" - {}", text));
+        }
+    }
+    line
+}
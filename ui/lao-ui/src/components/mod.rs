@@ -1,4 +1,12 @@
+pub mod compare;
+pub mod connect_check;
+pub mod edge_preview;
+pub mod gallery;
 pub mod graph;
+pub mod graph_image;
 pub mod inspector;
 pub mod logs;
+pub mod node_finder;
+pub mod queue;
 pub mod toolbar;
+pub mod usage;
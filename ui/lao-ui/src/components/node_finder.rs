@@ -0,0 +1,112 @@
+// Finding a node in a graph larger than the canvas.
+use crate::backend::{GraphNode, WorkflowGraph};
+use eframe::egui::{self, Color32, Key, Modifiers, Ui};
+use lao_orchestrator_core::node::NodeId;
+
+#[derive(Default)]
+pub struct NodeFinder {
+    pub open: bool,
+    pub query: String,
+    /// Index of the selected match
+    current: usize,
+    focus: bool,
+}
+
+fn matches_node(node: &GraphNode, query: &str) -> bool {
+    let params = match &node.params {
+        serde_yaml::Value::Null => String::new(),
+        params => serde_yaml::to_string(params).unwrap_or_default(),
+    };
+    [
+        node.id.as_str(),
+        node.run.as_str(),
+        node.display.label.as_deref().unwrap_or_default(),
+        params.as_str(),
+    ]
+    .iter()
+    .any(|field| field.to_lowercase().contains(query))
+}
+
+impl NodeFinder {
+    /// Nodes matching the query, in graph order
+    pub fn matches(&self, graph: &WorkflowGraph) -> Vec<NodeId> {
+        let query = self.query.trim().to_lowercase();
+        if !self.open || query.is_empty() {
+            return Vec::new();
+        }
+        graph
+            .nodes
+            .iter()
+            .filter(|node| matches_node(node, &query))
+            .map(|node| node.id.clone())
+            .collect()
+    }
+
+    /// The selected match, if any
+    pub fn current(&self, matches: &[NodeId]) -> Option<NodeId> {
+        matches.get(self.current).cloned()
+    }
+
+    /// Open the bar on Ctrl+F and show it; the node to jump to, when the
+    /// selected match changes
+    pub fn show(&mut self, ui: &mut Ui, graph: &WorkflowGraph) -> Option<NodeId> {
+        if ui.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::F)) {
+            self.open = true;
+            self.focus = true;
+        }
+        if !self.open {
+            return None;
+        }
+        let mut jump = None;
+        ui.horizontal(|ui| {
+            ui.label("🔍 Find:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.query)
+                    .hint_text("id, plugin or param")
+                    .desired_width(220.0),
+            );
+            if std::mem::take(&mut self.focus) {
+                response.request_focus();
+            }
+            let matches = self.matches(graph);
+            if response.changed() {
+                self.current = 0;
+                jump = matches.first().cloned();
+            }
+            let mut step = 0isize;
+            // Enter moves the focus off the field, so it's checked on lost focus
+            if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                step = 1;
+                response.request_focus();
+            }
+            if ui.input(|i| i.key_pressed(Key::Escape)) {
+                self.open = false;
+            }
+
+            if matches.is_empty() {
+                if !self.query.trim().is_empty() {
+                    ui.colored_label(Color32::GRAY, "No matches");
+                }
+            } else {
+                ui.label(format!("{} of {}", self.current + 1, matches.len()));
+            }
+            if ui.small_button("▲").clicked() {
+                step = -1;
+            }
+            if ui.small_button("▼").clicked() {
+                step = 1;
+            }
+            if ui.small_button("✖").clicked() {
+                self.open = false;
+            }
+
+            if step != 0 && !matches.is_empty() {
+                let count = matches.len() as isize;
+                self.current = (self.current as isize + step).rem_euclid(count) as usize;
+                jump = matches.get(self.current).cloned();
+            }
+            self.current = self.current.min(matches.len().saturating_sub(1));
+        });
+        jump
+    }
+}
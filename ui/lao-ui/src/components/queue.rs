@@ -0,0 +1,186 @@
+use crate::backend::parse_params;
+use eframe::egui::{self, Color32, RichText, Ui};
+use lao_orchestrator_core::pipeline::Lanes;
+use lao_orchestrator_core::plugins;
+use lao_orchestrator_core::run_labels;
+use lao_orchestrator_core::run_queue::{QueueStatus, QueuedRun, RunQueue};
+
+#[derive(Default)]
+pub struct QueuePanelState {
+    /// Started with the first run queued
+    queue: Option<RunQueue>,
+    pub path: String,
+    /// One `key=value` per line
+    pub params: String,
+    pub error: Option<String>,
+}
+
+impl QueuePanelState {
+    /// Whether runs are queued or running, so the panel needs repainting
+    pub fn is_busy(&self) -> bool {
+        self.queue.as_ref().is_some_and(RunQueue::is_busy)
+    }
+
+    fn enqueue(&mut self) {
+        let params = match parse_params(&self.params) {
+            Ok(params) => params,
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
+        if let Err(e) = lao_orchestrator_core::load_workflow_yaml(&self.path) {
+            self.error = Some(e);
+            return;
+        }
+        self.error = None;
+        let queue = self.queue.get_or_insert_with(|| {
+            run_labels::with_trigger("ui", || {
+                RunQueue::new(plugins::shared().snapshot(), Lanes::from_env(), 0)
+            })
+        });
+        queue.push(&self.path, params);
+    }
+}
+
+fn status_color(status: QueueStatus) -> Color32 {
+    match status {
+        QueueStatus::Queued => Color32::GRAY,
+        QueueStatus::Running => Color32::from_rgb(33, 150, 243),
+        QueueStatus::Success => Color32::from_rgb(76, 175, 80),
+        QueueStatus::Error => Color32::from_rgb(244, 67, 54),
+        QueueStatus::Cancelled => Color32::from_rgb(255, 152, 0),
+    }
+}
+
+pub fn show(ui: &mut Ui, state: &mut QueuePanelState, current_path: &str) {
+    egui::CollapsingHeader::new(RichText::new("📥 Run Queue").size(14.0))
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Workflow:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut state.path)
+                        .hint_text("path/to/workflow.yaml")
+                        .desired_width(260.0),
+                );
+                if ui
+                    .add_enabled(!current_path.is_empty(), egui::Button::new("Use open"))
+                    .on_hover_text("Queue the workflow open in the editor")
+                    .clicked()
+                {
+                    state.path = current_path.to_string();
+                }
+            });
+            ui.add(
+                egui::TextEdit::multiline(&mut state.params)
+                    .hint_text("params, one key=value per line")
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        !state.path.trim().is_empty(),
+                        egui::Button::new("➕ Enqueue"),
+                    )
+                    .clicked()
+                {
+                    state.enqueue();
+                }
+                if let Some(queue) = &state.queue {
+                    if ui.button("🧹 Clear finished").clicked() {
+                        queue.clear_finished();
+                    }
+                    let [cpu, gpu] = queue.slots();
+                    ui.colored_label(
+                        Color32::GRAY,
+                        format!(
+                            "{} runs at once · {} CPU / {} GPU lanes",
+                            queue.depth(),
+                            cpu,
+                            gpu
+                        ),
+                    );
+                }
+            });
+            if let Some(error) = &state.error {
+                ui.colored_label(Color32::from_rgb(244, 67, 54), error);
+            }
+
+            let Some(queue) = &state.queue else {
+                return;
+            };
+            let runs = queue.runs();
+            if runs.is_empty() {
+                ui.colored_label(Color32::GRAY, "Nothing queued.");
+                return;
+            }
+            ui.add_space(6.0);
+            for run in &runs {
+                ui.horizontal(|ui| run_row(ui, queue, run));
+            }
+        });
+}
+
+fn run_row(ui: &mut Ui, queue: &RunQueue, run: &QueuedRun) {
+    let queued = run.status == QueueStatus::Queued;
+    if ui
+        .add_enabled(queued, egui::Button::new("↑").small())
+        .clicked()
+    {
+        queue.move_run(run.id, true);
+    }
+    if ui
+        .add_enabled(queued, egui::Button::new("↓").small())
+        .clicked()
+    {
+        queue.move_run(run.id, false);
+    }
+    match run.status {
+        QueueStatus::Queued | QueueStatus::Running => {
+            if ui.small_button("⏹").on_hover_text("Cancel").clicked() {
+                queue.cancel(run.id);
+            }
+        }
+        _ => {
+            if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                queue.remove(run.id);
+            }
+        }
+    }
+
+    let status = format!("{:?}", run.status).to_lowercase();
+    ui.colored_label(status_color(run.status), status);
+    let name = std::path::Path::new(&run.path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| run.path.clone());
+    let mut params: Vec<String> = run
+        .params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    params.sort();
+    ui.label(RichText::new(name).strong())
+        .on_hover_text(&run.path);
+    if !params.is_empty() {
+        ui.colored_label(Color32::GRAY, params.join(" "));
+    }
+
+    let detail = match (&run.status, &run.step) {
+        (QueueStatus::Running, Some((step, resource))) => {
+            format!("{} on {}", step, resource.as_str())
+        }
+        (QueueStatus::Running, None) => "waiting for a lane".to_string(),
+        (QueueStatus::Error, _) => run.error.clone().unwrap_or_default(),
+        (QueueStatus::Success, _) => format!("{} steps", run.steps),
+        _ => String::new(),
+    };
+    if !detail.is_empty() {
+        ui.colored_label(Color32::LIGHT_GRAY, detail);
+    }
+    if let Some(ms) = run.duration_ms {
+        ui.colored_label(Color32::GRAY, format!("{:.1}s", ms as f64 / 1000.0));
+    }
+}
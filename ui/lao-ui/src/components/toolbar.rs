@@ -1,5 +1,8 @@
-use crate::backend::{get_workflow_graph, run_workflow_stream, BackendState};
+use crate::backend::{load_workflow, run_workflow_stream, BackendState};
+use crate::session::Recorder;
 use eframe::egui::{self, Color32, RichText, Ui};
+use lao_orchestrator_core::logging::{LogEvent, LogLevel};
+use lao_orchestrator_core::node::NodeStatus;
 use std::sync::{Arc, Mutex};
 
 pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
@@ -22,16 +25,7 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
 
             // Action buttons with icons and better styling
             if ui.add(egui::Button::new("📁 Load")).clicked() {
-                match get_workflow_graph(&state.workflow_path) {
-                    Ok(graph) => {
-                        state.graph = Some(graph);
-                        state.error.clear();
-                    }
-                    Err(e) => {
-                        state.error = e;
-                        state.graph = None;
-                    }
-                }
+                load_workflow(&mut state);
             }
 
             ui.add_space(5.0);
@@ -44,11 +38,12 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
                     // Reset node statuses before execution
                     let mut graph_clone = graph.clone();
                     for node in &mut graph_clone.nodes {
-                        node.status = "pending".to_string();
+                        node.status = NodeStatus::Pending;
                         node.message = None;
                         node.output = None;
                         node.error = None;
                         node.attempt = 0;
+                        node.instances = None;
                     }
                     state.graph = Some(graph_clone);
                 }
@@ -60,7 +55,7 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
                 let state_ref = Arc::clone(state_arc);
                 // Drop lock before running
                 drop(state);
-                let _ = run_workflow_stream(path, false, state_ref);
+                let _ = run_workflow_stream(path, false, None, state_ref);
             }
 
             // Re-acquire lock if needed or just continue if we didn't drop
@@ -83,6 +78,7 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
 
         let mut should_run = false;
         let mut should_run_parallel = false;
+        let mut should_resume = None;
         // We need to read state to draw UI, then maybe run commands
 
         // Scope for lock
@@ -102,16 +98,7 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
 
                 // Action buttons with icons and better styling
                 if ui.add(egui::Button::new("📁 Load")).clicked() {
-                    match get_workflow_graph(&state.workflow_path) {
-                        Ok(graph) => {
-                            state.graph = Some(graph);
-                            state.error.clear();
-                        }
-                        Err(e) => {
-                            state.error = e;
-                            state.graph = None;
-                        }
-                    }
+                    load_workflow(&mut state);
                 }
 
                 ui.add_space(5.0);
@@ -124,11 +111,12 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
                         // Reset node statuses before execution
                         let mut graph_clone = graph.clone();
                         for node in &mut graph_clone.nodes {
-                            node.status = "pending".to_string();
+                            node.status = NodeStatus::Pending;
                             node.message = None;
                             node.output = None;
                             node.error = None;
                             node.attempt = 0;
+                            node.instances = None;
                         }
                         state.graph = Some(graph_clone);
                     }
@@ -143,16 +131,90 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
                         // Reset node statuses before execution
                         let mut graph_clone = graph.clone();
                         for node in &mut graph_clone.nodes {
-                            node.status = "pending".to_string();
+                            node.status = NodeStatus::Pending;
                             node.message = None;
                             node.output = None;
                             node.error = None;
                             node.attempt = 0;
+                            node.instances = None;
                         }
                         state.graph = Some(graph_clone);
                     }
                     should_run_parallel = true;
                 }
+
+                // Runs the last unfinished run again, reusing the steps that succeeded
+                if let Some(checkpoint) = &state.resumable {
+                    let hover = format!(
+                        "Resume run {}: {} steps succeeded",
+                        checkpoint.run_id,
+                        checkpoint.succeeded()
+                    );
+                    let resume = ui
+                        .add_enabled(!state.is_running, egui::Button::new("⏯ Resume"))
+                        .on_hover_text(&hover)
+                        .on_disabled_hover_text(&hover);
+                    if resume.clicked() {
+                        should_resume = Some(checkpoint.clone());
+                    }
+                }
+
+                // Cancels the run; nodes that won't finish are marked cancelled
+                let stop = ui.add_enabled(
+                    state.cancel.is_some(),
+                    egui::Button::new("⏹ Stop").fill(Color32::from_rgb(244, 67, 54)),
+                );
+                if stop.clicked() {
+                    if let Some(recorder) = &mut state.recorder {
+                        recorder.stop();
+                    }
+                    if let Some(token) = &state.cancel {
+                        token.cancel();
+                    }
+                    // A run waiting at a breakpoint stops there
+                    if let Some(run) = state.paused.lock().unwrap().take() {
+                        run.abort();
+                    }
+                }
+
+                // Writes the session to a file to attach to bug reports
+                let state = &mut *state;
+                let recording = state.recorder.is_some();
+                let label = if recording {
+                    "⏺ Recording…"
+                } else {
+                    "⏺ Record"
+                };
+                if ui
+                    .selectable_label(recording, label)
+                    .on_hover_text(
+                        "Record loads, edits and runs to replay them with lao-ui --replay",
+                    )
+                    .clicked()
+                {
+                    match state.recorder.take() {
+                        Some(recorder) => state.live_logs.push(LogEvent::new(
+                            LogLevel::Info,
+                            format!("Session recorded to {}", recorder.path.display()),
+                        )),
+                        None => match Recorder::start(&state.plugins, state.graph.as_ref()) {
+                            Ok(recorder) => state.recorder = Some(recorder),
+                            Err(e) => state.error = e,
+                        },
+                    }
+                }
+            });
+
+            // Fill `${{ params.NAME }}` of runs started here; resumed runs keep theirs
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Params:").size(14.0));
+                ui.add(
+                    egui::TextEdit::multiline(&mut state.run_params)
+                        .hint_text("one key=value per line")
+                        .desired_rows(1)
+                        .desired_width(ui.available_width() * 0.6)
+                        .id_source("run_params_input"),
+                );
             });
 
             // Error display with better styling
@@ -176,12 +238,13 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
                     ui.separator();
 
                     for node in &graph.nodes {
-                        let status_color = match node.status.as_str() {
-                            "running" => Color32::from_rgb(33, 150, 243),
-                            "success" => Color32::from_rgb(76, 175, 80),
-                            "error" => Color32::from_rgb(244, 67, 54),
-                            "cache" => Color32::from_rgb(156, 39, 176),
-                            _ => Color32::GRAY,
+                        let status_color = match node.status {
+                            NodeStatus::Running => Color32::from_rgb(33, 150, 243),
+                            NodeStatus::Success => Color32::from_rgb(76, 175, 80),
+                            NodeStatus::Error => Color32::from_rgb(244, 67, 54),
+                            NodeStatus::Cache => Color32::from_rgb(156, 39, 176),
+                            NodeStatus::Cancelled => Color32::from_rgb(255, 152, 0),
+                            NodeStatus::Pending | NodeStatus::Skipped => Color32::GRAY,
                         };
 
                         ui.horizontal(|ui| {
@@ -206,14 +269,31 @@ pub fn show(ui: &mut Ui, state_arc: &Arc<Mutex<BackendState>>) {
             let state = state_arc.lock().unwrap();
             let path = state.workflow_path.clone();
             drop(state); // Drop lock before async call
-            let _ = run_workflow_stream(path, false, Arc::clone(state_arc));
+            let _ = run_workflow_stream(path, false, None, Arc::clone(state_arc));
         }
 
         if should_run_parallel {
             let state = state_arc.lock().unwrap();
             let path = state.workflow_path.clone();
             drop(state);
-            let _ = run_workflow_stream(path, true, Arc::clone(state_arc));
+            let _ = run_workflow_stream(path, true, None, Arc::clone(state_arc));
+        }
+
+        if let Some(checkpoint) = should_resume {
+            let mut state = state_arc.lock().unwrap();
+            if let Some(graph) = &mut state.graph {
+                for node in &mut graph.nodes {
+                    node.status = NodeStatus::Pending;
+                    node.message = None;
+                    node.output = None;
+                    node.error = None;
+                    node.attempt = 0;
+                    node.instances = None;
+                }
+            }
+            let path = state.workflow_path.clone();
+            drop(state);
+            let _ = run_workflow_stream(path, false, Some(checkpoint), Arc::clone(state_arc));
         }
     });
 }
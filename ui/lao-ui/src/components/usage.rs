@@ -0,0 +1,119 @@
+use eframe::egui::{self, Color32, RichText, Ui};
+use lao_orchestrator_core::usage::{self, UsageSummary};
+
+pub struct UsagePanelState {
+    pub days: i64,
+    pub summary: Option<UsageSummary>,
+    pub error: Option<String>,
+}
+
+impl Default for UsagePanelState {
+    fn default() -> Self {
+        Self {
+            days: 7,
+            summary: None,
+            error: None,
+        }
+    }
+}
+
+impl UsagePanelState {
+    fn reload(&mut self) {
+        match usage::read_history(&usage::default_path()) {
+            Ok(history) => {
+                let since = chrono::Utc::now() - chrono::Duration::days(self.days);
+                self.summary = Some(usage::summarize(&history, Some(since)));
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+}
+
+pub fn show(ui: &mut Ui, state: &mut UsagePanelState) {
+    egui::CollapsingHeader::new(RichText::new("💰 Token & Compute Usage").size(14.0))
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Last");
+                let mut changed = false;
+                for days in [1, 7, 30] {
+                    changed |= ui
+                        .selectable_value(&mut state.days, days, format!("{}d", days))
+                        .changed();
+                }
+                if ui.button("🔄 Refresh").clicked() || changed || state.summary.is_none() {
+                    state.reload();
+                }
+            });
+
+            if let Some(error) = &state.error {
+                ui.colored_label(Color32::from_rgb(244, 67, 54), error);
+                return;
+            }
+            let Some(summary) = &state.summary else {
+                return;
+            };
+            if summary.runs == 0 {
+                ui.colored_label(Color32::GRAY, "No runs recorded in this period.");
+                return;
+            }
+
+            ui.label(format!(
+                "{} runs · {} tokens · {:.1} min plugin time · ~{:.1} Wh",
+                summary.runs,
+                summary.total_tokens(),
+                summary.plugin_seconds() / 60.0,
+                summary.energy_wh
+            ));
+            ui.add_space(6.0);
+
+            ui.label(RichText::new("Plugin time by workflow").strong());
+            let workflows = summary.workflows_by_time();
+            let max_seconds = workflows
+                .first()
+                .map(|(_, totals)| totals.seconds)
+                .unwrap_or(0.0);
+            for (name, totals) in workflows {
+                bar(
+                    ui,
+                    name,
+                    totals.seconds / max_seconds.max(f64::EPSILON),
+                    &format!("{:.1} min · {} tok", totals.seconds / 60.0, totals.tokens),
+                    Color32::from_rgb(255, 152, 0),
+                );
+            }
+
+            ui.add_space(6.0);
+            ui.label(RichText::new("Tokens by plugin").strong());
+            let max_tokens = summary
+                .plugins
+                .values()
+                .map(|totals| totals.total_tokens())
+                .max()
+                .unwrap_or(0);
+            for (name, totals) in summary.plugins.iter().filter(|(_, t)| t.total_tokens() > 0) {
+                bar(
+                    ui,
+                    name,
+                    totals.total_tokens() as f64 / max_tokens.max(1) as f64,
+                    &format!("{} tok in {} calls", totals.total_tokens(), totals.calls),
+                    Color32::from_rgb(33, 150, 243),
+                );
+            }
+        });
+}
+
+// Label, a bar scaled to `fraction` of the available width, then the value text
+fn bar(ui: &mut Ui, label: &str, fraction: f64, value: &str, color: Color32) {
+    ui.horizontal(|ui| {
+        ui.add_sized([160.0, 18.0], egui::Label::new(label).truncate());
+        let width = (ui.available_width() - 180.0).max(40.0);
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(width, 14.0), egui::Sense::hover());
+        ui.painter().rect_filled(rect, 3.0, Color32::from_gray(60));
+        let mut filled = rect;
+        filled.set_width(rect.width() * fraction.clamp(0.0, 1.0) as f32);
+        ui.painter().rect_filled(filled, 3.0, color);
+        ui.label(RichText::new(value).size(12.0));
+    });
+}
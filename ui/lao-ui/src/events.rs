@@ -0,0 +1,92 @@
+// Coalescing between a running workflow and the egui frame loop.
+use lao_orchestrator_core::logging::{self, LogEvent, LogSink};
+use lao_orchestrator_core::StepEvent;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Log events held between two frames
+pub const MAX_QUEUED_LOGS: usize = 500;
+
+#[derive(Default)]
+pub struct EventBatch {
+    /// Latest event of each step, in the order steps first reported
+    pub steps: Vec<StepEvent>,
+    pub logs: VecDeque<LogEvent>,
+    /// Logs dropped since the previous batch because the queue was full
+    pub dropped_logs: usize,
+    pub progress: Option<f32>,
+}
+
+#[derive(Default)]
+pub struct EventQueue {
+    pending: Mutex<EventBatch>,
+    closed: AtomicBool,
+}
+
+impl EventQueue {
+    /// A queue that receives every log event until it is closed or dropped
+    pub fn subscribe() -> Arc<Self> {
+        let queue = Arc::new(Self::default());
+        logging::add_sink(QueueSink(Arc::downgrade(&queue)));
+        queue
+    }
+
+    pub fn push_step(&self, event: StepEvent) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        match pending
+            .steps
+            .iter_mut()
+            .find(|e| e.step_id == event.step_id)
+        {
+            Some(existing) => *existing = event,
+            None => pending.steps.push(event),
+        }
+    }
+
+    pub fn push_log(&self, event: LogEvent) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if pending.logs.len() >= MAX_QUEUED_LOGS {
+            pending.logs.pop_front();
+            pending.dropped_logs += 1;
+        }
+        pending.logs.push_back(event);
+    }
+
+    pub fn set_progress(&self, progress: f32) {
+        self.pending
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .progress = Some(progress);
+    }
+
+    /// Mark the run as finished; later log events are no longer queued
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Everything queued since the last call
+    pub fn take(&self) -> EventBatch {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+struct QueueSink(Weak<EventQueue>);
+
+impl LogSink for QueueSink {
+    fn log(&self, event: &LogEvent) {
+        if let Some(queue) = self.0.upgrade() {
+            if !queue.is_closed() {
+                queue.push_log(event.clone());
+            }
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.0.upgrade().is_some_and(|q| !q.is_closed())
+    }
+}
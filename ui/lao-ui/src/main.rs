@@ -1,8 +1,21 @@
 use eframe::egui;
+use lao_orchestrator_core::{
+    artifacts, audit, checkpoint, crash, data_dir, encryption,
+    env_vars::{self, TemplateEnv},
+    evaluation, logging,
+    plugin_policy::{self, PluginPolicy},
+    plugins,
+    redaction::{self, RedactionConfig},
+    run_class, run_diff,
+    run_logs::{RunLogConfig, RunLogSink},
+    usage,
+};
 
 mod app;
 mod backend;
 mod components;
+mod events;
+mod session;
 // mod ui_old; // Not compiling ui_old to avoid duplicate symbol errors or unused code warnings if possible, but user asked to keep it.
 // Actually, if I include `mod ui_old;`, it will try to compile it.
 // `ui_old.rs` has `LaoApp` struct which might conflict if I import it, but I am not importing it.
@@ -21,6 +34,59 @@ use app::LaoApp;
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    if let Err(e) = data_dir::prepare() {
+        eprintln!("Failed to set up the data directory: {}", e);
+        std::process::exit(1);
+    }
+    crash::install(crash::default_dir());
+    let redaction_path = redaction::default_path();
+    if let Err(e) = RedactionConfig::load(&redaction_path).and_then(redaction::init) {
+        eprintln!("Failed to load redaction rules: {}", e);
+        std::process::exit(1);
+    }
+    logging::add_sink(RunLogSink::new(RunLogConfig::from_env()));
+    audit::init(audit::default_path());
+    usage::init(usage::default_path());
+    evaluation::init(evaluation::default_path());
+    artifacts::init(artifacts::default_dir());
+    run_diff::init(run_diff::default_dir());
+    checkpoint::init(run_diff::default_dir());
+    if let Err(e) = PluginPolicy::load(&plugin_policy::default_path()).and_then(plugin_policy::init)
+    {
+        eprintln!("Failed to load plugin policy: {}", e);
+        std::process::exit(1);
+    }
+    let dotenv = [env_vars::default_path(), env_vars::user_path()];
+    if let Err(e) = TemplateEnv::load_all(&dotenv).and_then(env_vars::init) {
+        eprintln!("Failed to load .env: {}", e);
+        std::process::exit(1);
+    }
+    // UI runs are interactive; a daemon's scheduled runs yield to them
+    run_class::init(run_class::default_lease_dir());
+    if let Err(e) = encryption::init_from_env() {
+        eprintln!("Failed to set up encryption: {}", e);
+        std::process::exit(1);
+    }
+    // `--replay <file>` plays a recorded session back (see `session`)
+    let args: Vec<String> = std::env::args().collect();
+    let replay = match args.iter().position(|a| a == "--replay") {
+        Some(i) => match args.get(i + 1).map(|p| session::Replay::open(p.as_ref())) {
+            Some(Ok(replay)) => Some(replay),
+            Some(Err(e)) => {
+                eprintln!("Failed to open the recording: {}", e);
+                std::process::exit(1);
+            }
+            None => {
+                eprintln!("--replay needs a recorded session file");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // Build the plugin registry off the UI thread so the window appears right away
+    std::thread::spawn(|| {
+        plugins::shared();
+    });
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -36,7 +102,7 @@ fn main() -> Result<(), eframe::Error> {
             // This gives us image support:
             egui_extras::install_image_loaders(&cc.egui_ctx);
 
-            Ok(Box::new(LaoApp::new(cc)))
+            Ok(Box::new(LaoApp::new(cc, replay)))
         }),
     )
 }
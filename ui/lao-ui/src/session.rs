@@ -0,0 +1,384 @@
+// Recording a UI session to replay it, for bug reports.
+use crate::backend::{
+    get_workflow_graph, BackendState, UiPluginInfo, WorkflowGraph, WorkflowResult,
+};
+use crate::events::EventQueue;
+use chrono::{DateTime, Utc};
+use lao_orchestrator_core::{
+    data_dir,
+    logging::{LogEvent, LogLevel},
+    node::NodeStatus,
+    redaction, StepEvent,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// First line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    pub lao_version: String,
+    pub os: String,
+    pub started: DateTime<Utc>,
+    /// `name@version` of each plugin the UI listed
+    pub plugins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// A workflow file was loaded; `yaml` is its text, unset if unreadable
+    Load {
+        path: String,
+        yaml: Option<String>,
+        error: Option<String>,
+    },
+    /// The graph after an edit, without run state
+    Edit {
+        graph: WorkflowGraph,
+    },
+    Run {
+        path: String,
+        parallel: bool,
+    },
+    Stop,
+    /// What one frame took from the running workflow's event queue
+    Events {
+        steps: Vec<StepEvent>,
+        logs: Vec<LogEvent>,
+        progress: Option<f32>,
+        /// The run's last batch
+        closed: bool,
+    },
+    Finished {
+        result: Option<WorkflowResult>,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Since recording started
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// sessions/ in the data directory
+pub fn default_dir() -> PathBuf {
+    data_dir::path("sessions")
+}
+
+// The graph as edits change it: run state is left out
+fn edit_view(graph: &WorkflowGraph) -> WorkflowGraph {
+    let mut graph = graph.clone();
+    for node in &mut graph.nodes {
+        node.status = NodeStatus::Pending;
+        node.message = None;
+        node.output = None;
+        node.error = None;
+        node.artifact = None;
+        node.attempt = 0;
+        node.instances = None;
+    }
+    graph
+}
+
+pub struct Recorder {
+    pub path: PathBuf,
+    file: File,
+    start: Instant,
+    last_graph: Option<serde_json::Value>,
+    running: bool,
+}
+
+impl Recorder {
+    pub fn start(plugins: &[UiPluginInfo], graph: Option<&WorkflowGraph>) -> Result<Self, String> {
+        let dir = default_dir();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        let started = Utc::now();
+        let path = dir.join(format!("session-{}.jsonl", started.format("%Y%m%d-%H%M%S")));
+        let mut file =
+            File::create(&path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+        let header = Header {
+            lao_version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            started,
+            plugins: plugins
+                .iter()
+                .map(|p| format!("{}@{}", p.name, p.version))
+                .collect(),
+        };
+        let line = serde_json::to_string(&header).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+        let mut recorder = Self {
+            path,
+            file,
+            start: Instant::now(),
+            last_graph: None,
+            running: false,
+        };
+        // A graph already open is where the replay starts from
+        if let Some(graph) = graph {
+            recorder.edited(graph);
+        }
+        Ok(recorder)
+    }
+
+    fn record(&mut self, action: Action) {
+        let entry = Entry {
+            at_ms: self.start.elapsed().as_millis() as u64,
+            action,
+        };
+        if let Ok(line) = serde_json::to_string(&entry) {
+            writeln!(self.file, "{}", line).ok();
+        }
+    }
+
+    // Records the graph if it differs from the last one recorded
+    fn edited(&mut self, graph: &WorkflowGraph) {
+        let graph = edit_view(graph);
+        let value = serde_json::to_value(&graph).ok();
+        if value != self.last_graph {
+            self.last_graph = value;
+            self.record(Action::Edit { graph });
+        }
+    }
+
+    pub fn loaded(&mut self, path: &str, graph: Option<&WorkflowGraph>, error: &str) {
+        self.record(Action::Load {
+            path: path.to_string(),
+            yaml: std::fs::read_to_string(path).ok(),
+            error: (!error.is_empty()).then(|| error.to_string()),
+        });
+        // The loaded graph isn't an edit
+        self.last_graph = graph.and_then(|g| serde_json::to_value(edit_view(g)).ok());
+    }
+
+    pub fn run(&mut self, path: &str, parallel: bool) {
+        self.running = true;
+        self.record(Action::Run {
+            path: path.to_string(),
+            parallel,
+        });
+    }
+
+    pub fn stop(&mut self) {
+        self.record(Action::Stop);
+    }
+
+    pub fn events(
+        &mut self,
+        steps: &[StepEvent],
+        logs: &[LogEvent],
+        progress: Option<f32>,
+        closed: bool,
+    ) {
+        if steps.is_empty() && logs.is_empty() && progress.is_none() && !closed {
+            return;
+        }
+        let redact = |text: &Option<String>| text.as_deref().map(|t| redaction::redact(t, None));
+        let steps = steps
+            .iter()
+            .map(|event| StepEvent {
+                message: redact(&event.message),
+                output: redact(&event.output),
+                error: redact(&event.error),
+                ..event.clone()
+            })
+            .collect();
+        self.record(Action::Events {
+            steps,
+            logs: logs.to_vec(),
+            progress,
+            closed,
+        });
+    }
+
+    /// Record what changed in `state` over the frame
+    pub fn frame(&mut self, state: &BackendState) {
+        if let Some(graph) = &state.graph {
+            self.edited(graph);
+        }
+        if self.running && !state.is_running {
+            self.running = false;
+            self.record(Action::Finished {
+                result: state.workflow_result.clone(),
+                error: state.error.clone(),
+            });
+        }
+    }
+}
+
+/// A recording being played back
+pub struct Replay {
+    pub path: PathBuf,
+    pub header: Header,
+    entries: Vec<Entry>,
+    next: usize,
+    start: Instant,
+    // Recorded workflow files are written here to be loaded
+    dir: PathBuf,
+    queue: Option<Arc<EventQueue>>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let mut lines = BufReader::new(file).lines();
+        let line = |n: usize, line: Option<std::io::Result<String>>| match line {
+            Some(Ok(line)) => Ok(line),
+            Some(Err(e)) => Err(format!("{}:{}: {}", path.display(), n, e)),
+            None => Err(format!("{}: empty recording", path.display())),
+        };
+        let header = serde_json::from_str(&line(1, lines.next())?)
+            .map_err(|e| format!("{}:1: {}", path.display(), e))?;
+        let mut entries = Vec::new();
+        for (n, text) in lines.enumerate() {
+            let text = line(n + 2, Some(text))?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            // A session cut short by a crash ends in a partial line
+            match serde_json::from_str(&text) {
+                Ok(entry) => entries.push(entry),
+                Err(e) if e.is_eof() => break,
+                Err(e) => return Err(format!("{}:{}: {}", path.display(), n + 2, e)),
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            header,
+            entries,
+            next: 0,
+            start: Instant::now(),
+            dir: std::env::temp_dir().join(format!("lao-replay-{}", std::process::id())),
+            queue: None,
+        })
+    }
+
+    /// Entries played so far, and in all
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.entries.len())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.entries.len()
+    }
+
+    /// Apply the entries due by now to `state`
+    pub fn frame(&mut self, state: &mut BackendState) {
+        let now = self.start.elapsed().as_millis() as u64;
+        while let Some(entry) = self.entries.get(self.next).filter(|e| e.at_ms <= now) {
+            let action = entry.action.clone();
+            self.next += 1;
+            self.apply(action, state);
+        }
+    }
+
+    fn apply(&mut self, action: Action, state: &mut BackendState) {
+        match action {
+            Action::Load { path, yaml, error } => {
+                let loaded = match yaml {
+                    Some(yaml) => {
+                        let name = Path::new(&path)
+                            .file_name()
+                            .unwrap_or("workflow.yaml".as_ref());
+                        let file = self.dir.join(name);
+                        std::fs::create_dir_all(&self.dir)
+                            .and_then(|_| std::fs::write(&file, yaml))
+                            .map_err(|e| e.to_string())
+                            .and_then(|_| {
+                                state.workflow_path = file.to_string_lossy().into_owned();
+                                get_workflow_graph(&state.workflow_path)
+                            })
+                    }
+                    None => {
+                        state.workflow_path = path;
+                        Err(error.unwrap_or_default())
+                    }
+                };
+                match loaded {
+                    Ok(graph) => {
+                        state.graph = Some(graph);
+                        state.error.clear();
+                    }
+                    Err(e) => {
+                        state.graph = None;
+                        state.error = e;
+                    }
+                }
+            }
+            Action::Edit { mut graph } => {
+                // Edits keep the run state nodes have
+                if let Some(current) = &state.graph {
+                    for node in &mut graph.nodes {
+                        if let Some(old) = current.nodes.iter().find(|n| n.id == node.id) {
+                            node.status = old.status;
+                            node.message = old.message.clone();
+                            node.output = old.output.clone();
+                            node.error = old.error.clone();
+                            node.artifact = old.artifact.clone();
+                            node.attempt = old.attempt;
+                            node.instances = old.instances;
+                        }
+                    }
+                }
+                state.graph = Some(graph);
+            }
+            Action::Run { .. } => {
+                if let Some(graph) = &mut state.graph {
+                    *graph = edit_view(graph);
+                }
+                let queue = Arc::new(EventQueue::default());
+                state.events = Some(queue.clone());
+                self.queue = Some(queue);
+                state.is_running = true;
+                state.execution_progress = 0.0;
+                state.workflow_result = None;
+                state.error.clear();
+            }
+            Action::Stop => {
+                state
+                    .live_logs
+                    .push(LogEvent::new(LogLevel::Warn, "⏹ Stop pressed"));
+            }
+            Action::Events {
+                steps,
+                logs,
+                progress,
+                closed,
+            } => {
+                let Some(queue) = &self.queue else {
+                    return;
+                };
+                steps.into_iter().for_each(|e| queue.push_step(e));
+                logs.into_iter().for_each(|e| queue.push_log(e));
+                if let Some(progress) = progress {
+                    queue.set_progress(progress);
+                }
+                if closed {
+                    queue.close();
+                    self.queue = None;
+                }
+            }
+            Action::Finished { result, error } => {
+                state.is_running = false;
+                state.execution_progress = 1.0;
+                state.workflow_result = result;
+                state.error = error;
+            }
+        }
+    }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}